@@ -0,0 +1,102 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Catch-up throughput of SQL storage when inserting leaves one at a time or in batches.
+//!
+//! This simulates a node catching up on a backlog of decided leaves, committing one transaction per
+//! batch. For each batch size, it compares calling `insert_leaf` once per leaf with a single call to
+//! `insert_leaves` for the whole batch, and prints the throughput of each in leaves per second.
+//!
+//! Run with `cargo bench --bench insert_leaves --features testing`. Like the SQL tests, this uses a
+//! temporary Postgres container, or a temporary SQLite database with the `embedded-db` feature.
+
+use committable::Committable;
+use hotshot_example_types::{
+    node_types::TestVersions,
+    state_types::{TestInstanceState, TestValidatedState},
+};
+use hotshot_query_service::{
+    availability::LeafQueryData,
+    data_source::{
+        storage::{sql::testing::TmpDb, SqlStorage, UpdateAvailabilityStorage},
+        Transaction, VersionedDataSource,
+    },
+    testing::mocks::MockTypes,
+};
+use hotshot_types::data::Leaf;
+use std::time::{Duration, Instant};
+
+/// The number of leaves to insert in each run.
+const LEAVES: usize = 2000;
+
+/// The number of leaves to insert in each transaction.
+const BATCH_SIZES: [usize; 4] = [1, 10, 100, 1000];
+
+/// Generate a chain of `n` leaves, starting at height 0.
+async fn leaves(n: usize) -> Vec<LeafQueryData<MockTypes>> {
+    let genesis = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+        &TestValidatedState::default(),
+        &TestInstanceState::default(),
+    )
+    .await;
+    let mut leaf = genesis.leaf().clone();
+    let mut qc = genesis.qc().clone();
+    (0..n)
+        .map(|i| {
+            leaf.block_header_mut().block_number = i as u64;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            LeafQueryData::new(leaf.clone(), qc.clone()).unwrap()
+        })
+        .collect()
+}
+
+/// Insert `leaves` into a fresh database, committing every `batch_size` leaves.
+///
+/// Returns the time taken to insert and commit all the leaves.
+async fn run(leaves: &[LeafQueryData<MockTypes>], batch_size: usize, batched: bool) -> Duration {
+    let db = TmpDb::init().await;
+    let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+    let start = Instant::now();
+    for batch in leaves.chunks(batch_size) {
+        let mut tx = storage.write().await.unwrap();
+        if batched {
+            tx.insert_leaves(batch.to_vec()).await.unwrap();
+        } else {
+            for leaf in batch {
+                tx.insert_leaf(leaf.clone()).await.unwrap();
+            }
+        }
+        tx.commit().await.unwrap();
+    }
+    start.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    let leaves = leaves(LEAVES).await;
+    println!("inserting {LEAVES} leaves");
+    println!(
+        "{:>10} {:>20} {:>20}",
+        "batch", "insert_leaf (/s)", "insert_leaves (/s)"
+    );
+    for batch_size in BATCH_SIZES {
+        let per_leaf = run(&leaves, batch_size, false).await;
+        let batched = run(&leaves, batch_size, true).await;
+        let throughput = |elapsed: Duration| LEAVES as f64 / elapsed.as_secs_f64();
+        println!(
+            "{batch_size:>10} {:>20.0} {:>20.0}",
+            throughput(per_leaf),
+            throughput(batched)
+        );
+    }
+}
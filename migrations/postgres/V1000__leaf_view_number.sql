@@ -0,0 +1,5 @@
+-- The consensus view in which each leaf was proposed, so that leaves can be looked up by view.
+-- Leaves which were stored before this column existed are backfilled from the leaf JSON.
+ALTER TABLE leaf ADD COLUMN view_number BIGINT;
+UPDATE leaf SET view_number = (leaf->>'view_number')::BIGINT;
+CREATE INDEX leaf_view_number_idx ON leaf (view_number);
@@ -0,0 +1,6 @@
+-- The height of the last block whose payload data was pruned, when the pruner is configured to
+-- discard payloads while retaining leaves and headers.
+CREATE TABLE pruned_payload_height (
+    id SERIAL PRIMARY KEY,
+    last_height BIGINT NOT NULL
+);
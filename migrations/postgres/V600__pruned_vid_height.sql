@@ -0,0 +1,6 @@
+-- The height of the last block whose VID data was pruned, when the pruner is configured to discard
+-- VID data while retaining leaves and headers.
+CREATE TABLE pruned_vid_height (
+    id SERIAL PRIMARY KEY,
+    last_height BIGINT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- VID shares indexed by the storage node they belong to, so that a node can retrieve its own share
+-- (or any other share we happen to have) by index. This is separate from `vid.share`, which holds
+-- the share belonging to this node, without recording its index.
+CREATE TABLE vid_share (
+    height     BIGINT NOT NULL REFERENCES header (height) ON DELETE CASCADE,
+    node_index BIGINT NOT NULL,
+    data       BYTEA  NOT NULL,
+    PRIMARY KEY (height, node_index)
+);
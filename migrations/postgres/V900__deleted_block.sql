@@ -0,0 +1,5 @@
+-- Blocks whose payload and VID data were deleted on demand, independent of the pruner. The leaves
+-- and headers of these blocks are retained.
+CREATE TABLE deleted_block (
+    height BIGINT PRIMARY KEY REFERENCES header (height) ON DELETE CASCADE
+);
@@ -0,0 +1,6 @@
+-- Indexes which are built in the background after startup, rather than in a migration, and whether
+-- each one has finished building.
+CREATE TABLE background_index (
+    name  TEXT PRIMARY KEY,
+    ready BOOLEAN NOT NULL DEFAULT false
+);
@@ -10,9 +10,18 @@
 // You should have received a copy of the GNU General Public License along with this program. If not,
 // see <https://www.gnu.org/licenses/>.
 
+use derivative::Derivative;
+use futures::future::{BoxFuture, FutureExt};
+use hotshot_types::traits::metrics::{HistogramFamily, Metrics};
 use std::fs;
+use std::marker::PhantomData;
 use std::path::Path;
-use tide_disco::api::{Api, ApiError};
+use std::sync::Arc;
+use std::time::Instant;
+use tide_disco::{
+    api::{Api, ApiError},
+    RequestParams,
+};
 use toml::{map::Entry, Value};
 use vbs::version::StaticVersionType;
 
@@ -57,3 +66,74 @@ fn load_toml(path: &Path) -> Result<Value, ApiError> {
         reason: err.to_string(),
     })
 }
+
+/// Latency histograms for the request handlers of an API module.
+///
+/// If enabled, each instrumented handler records the duration of every request in a
+/// `request_duration` histogram, labeled by the name of the endpoint. `State` and `Error` are the
+/// state and error types of the handlers being instrumented.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Default(bound = ""))]
+pub(crate) struct RequestMetrics<State, Error> {
+    durations: Option<Arc<dyn HistogramFamily>>,
+    _phantom: PhantomData<fn(&State) -> Error>,
+}
+
+impl<State, Error> RequestMetrics<State, Error>
+where
+    State: 'static,
+    Error: 'static,
+{
+    /// Register request latency histograms for the module `module` in `metrics`, if given.
+    ///
+    /// Each module gets its own subgroup of `metrics`, so the modules of a single application can
+    /// share a registry. The histograms can only be registered once per module and registry.
+    pub(crate) fn new(metrics: Option<&dyn Metrics>, module: &str) -> Self {
+        Self {
+            durations: metrics.map(|metrics| {
+                metrics
+                    .subgroup(module.into())
+                    .histogram_family("request_duration".into(), vec!["endpoint".into()])
+                    .into()
+            }),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Use the same histograms for handlers with a different state type.
+    pub(crate) fn with_state<S>(&self) -> RequestMetrics<S, Error> {
+        RequestMetrics {
+            durations: self.durations.clone(),
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Wrap a request handler so that it records the duration of each request to `endpoint`.
+    pub(crate) fn instrument<T, F>(
+        &self,
+        endpoint: &str,
+        handler: F,
+    ) -> impl 'static + Send + Sync + Fn(RequestParams, &State) -> BoxFuture<'_, Result<T, Error>>
+    where
+        T: 'static,
+        F: 'static + Send + Sync + Fn(RequestParams, &State) -> BoxFuture<'_, Result<T, Error>>,
+    {
+        let histogram = self
+            .durations
+            .as_ref()
+            .map(|durations| durations.create(vec![endpoint.into()]));
+        move |req, state| {
+            let fut = handler(req, state);
+            let Some(histogram) = histogram.clone() else {
+                return fut;
+            };
+            async move {
+                let start = Instant::now();
+                let res = fut.await;
+                histogram.add_point(start.elapsed().as_secs_f64());
+                res
+            }
+            .boxed()
+        }
+    }
+}
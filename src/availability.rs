@@ -26,22 +26,43 @@
 //! chain which is tabulated by this specific node and not subject to full consensus agreement, try
 //! the [node](crate::node) API.
 
-use crate::{api::load_api, Payload};
+use crate::{
+    api::{load_api, RequestMetrics},
+    types::HeightIndexed,
+    Payload, QueryError,
+};
 use derive_more::From;
-use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt};
-use hotshot_types::traits::node_implementation::NodeType;
+use futures::{
+    future,
+    stream::{BoxStream, Stream},
+    FutureExt, StreamExt, TryFutureExt, TryStreamExt,
+};
+use hotshot_types::traits::{
+    block_contents::BlockHeader,
+    metrics::Metrics,
+    node_implementation::{ConsensusTime, NodeType},
+};
 use serde::{Deserialize, Serialize};
-use snafu::{OptionExt, Snafu};
-use std::{fmt::Display, path::PathBuf, time::Duration};
-use tide_disco::{api::ApiError, method::ReadState, Api, RequestError, StatusCode};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{
+    fmt::Display,
+    io,
+    ops::{Bound, Range},
+    path::PathBuf,
+    time::Duration,
+};
+use tide_disco::{api::ApiError, method::ReadState, Api, RequestError, RequestParams, StatusCode};
 use vbs::version::StaticVersionType;
 
 pub(crate) mod data_source;
 mod fetch;
+pub mod grpc;
 pub(crate) mod query_data;
+mod verify;
 pub use data_source::*;
 pub use fetch::Fetch;
 pub use query_data::*;
+pub use verify::{verify_leaf, VerifyLeafError};
 
 #[derive(Debug)]
 pub struct Options {
@@ -77,6 +98,24 @@ pub struct Options {
     /// belongs to a class which might contain a large payload, the large object limit always
     /// applies.
     pub large_object_range_limit: usize,
+
+    /// The maximum size, in bytes, of the response to a single range query.
+    ///
+    /// The range limits bound the number of objects in a response, but not their size, and a single
+    /// payload can be arbitrarily large. If this is set, range queries measure the size of each
+    /// object as it is loaded, in the JSON encoding, and fail with status 413 as soon as the total
+    /// exceeds this limit, rather than loading the rest of the range into memory. The default is no
+    /// limit.
+    pub max_response_bytes: Option<usize>,
+
+    /// Metrics registry in which to record the duration of each request.
+    ///
+    /// If set, every request handler in this module records its latency in a `request_duration`
+    /// histogram, labeled by endpoint, in the `availability` subgroup of this registry. This is
+    /// disabled by default, since it adds a time series for every endpoint. To export these
+    /// histograms along with the other status metrics, use the registry of the data source, e.g.
+    /// `Some(Box::new(data_source.metrics().clone()))`.
+    pub request_metrics: Option<Box<dyn Metrics>>,
 }
 
 impl Default for Options {
@@ -87,6 +126,8 @@ impl Default for Options {
             extensions: vec![],
             large_object_range_limit: 100,
             small_object_range_limit: 500,
+            max_response_bytes: None,
+            request_metrics: None,
         }
     }
 }
@@ -118,6 +159,30 @@ pub enum Error {
         height: u64,
         index: u64,
     },
+    #[snafu(display("error fetching VID share {index} for block {block}: {source}"))]
+    #[from(ignore)]
+    QueryVidShare {
+        source: QueryError,
+        block: String,
+        index: usize,
+    },
+    #[snafu(display("block {block} is not available: {source}"))]
+    #[from(ignore)]
+    QueryBlock {
+        source: QueryError,
+        block: String,
+    },
+    #[snafu(display("error reading bytes of payload {block}: {source}"))]
+    #[from(ignore)]
+    QueryPayloadBytes {
+        source: QueryError,
+        block: String,
+    },
+    #[snafu(display("cannot satisfy byte range {range}"))]
+    #[from(ignore)]
+    InvalidByteRange {
+        range: String,
+    },
     #[snafu(display("request for range {from}..{until} exceeds limit {limit}"))]
     #[from(ignore)]
     RangeLimit {
@@ -125,6 +190,17 @@ pub enum Error {
         until: usize,
         limit: usize,
     },
+    #[snafu(display("response exceeds maximum size of {limit} bytes"))]
+    #[from(ignore)]
+    ResponseTooLarge {
+        limit: usize,
+    },
+    #[snafu(display("page cursor issued by {issuer} cannot be used with {endpoint}"))]
+    #[from(ignore)]
+    CursorMismatch {
+        issuer: String,
+        endpoint: String,
+    },
     Custom {
         message: String,
         status: StatusCode,
@@ -141,20 +217,255 @@ impl Error {
 
     pub fn status(&self) -> StatusCode {
         match self {
-            Self::Request { .. } | Self::RangeLimit { .. } => StatusCode::BAD_REQUEST,
+            Self::Request { .. } | Self::RangeLimit { .. } | Self::CursorMismatch { .. } => {
+                StatusCode::BAD_REQUEST
+            }
             Self::FetchLeaf { .. } | Self::FetchBlock { .. } | Self::FetchTransaction { .. } => {
                 StatusCode::NOT_FOUND
             }
             Self::InvalidTransactionIndex { .. } => StatusCode::NOT_FOUND,
+            Self::ResponseTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidByteRange { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            Self::QueryBlock { source, .. }
+            | Self::QueryVidShare { source, .. }
+            | Self::QueryPayloadBytes { source, .. } => source.status(),
             Self::Custom { status, .. } => *status,
         }
     }
 }
 
+/// Wait up to `timeout` for a block, or part of a block, to become available.
+///
+/// Fails with [`Error::FetchBlock`] if the data does not become available in time, or with
+/// [`Error::QueryBlock`] if it is known that the data will never become available, for example
+/// because its payload has been pruned.
+async fn fetch_block<T>(
+    fetch: Fetch<T>,
+    timeout: Duration,
+    resource: impl Display,
+) -> Result<T, Error>
+where
+    T: Send + 'static,
+{
+    match fetch.try_with_timeout(timeout).await {
+        Ok(obj) => obj.context(FetchBlockSnafu {
+            resource: resource.to_string(),
+        }),
+        Err(source) => Err(Error::QueryBlock {
+            source,
+            block: resource.to_string(),
+        }),
+    }
+}
+
+/// Parse the `:height` parameter of a request, resolving the `latest` alias.
+///
+/// Routes which accept a height also accept the literal `latest` in its place, which this resolves
+/// to the height of the most recent leaf. The alias is resolved separately for each request, so two
+/// requests for `latest` may refer to different heights. If there are no leaves yet, this fails
+/// with `404 Not Found`.
+async fn height_param<State, Types>(
+    req: &RequestParams,
+    state: &State,
+    timeout: Duration,
+) -> Result<usize, Error>
+where
+    State: ReadState,
+    <State as ReadState>::State: Send + Sync + AvailabilityDataSource<Types>,
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    if let Some(height) = req.opt_integer_param("height")? {
+        return Ok(height);
+    }
+    let latest = state
+        .read(|state| {
+            async move {
+                let mut leaves = state.get_leaf_range_rev(Bound::Unbounded, usize::MAX).await;
+                match leaves.next().await {
+                    Some(fetch) => fetch.with_timeout(timeout).await,
+                    None => None,
+                }
+            }
+            .boxed()
+        })
+        .await;
+    let leaf = latest.context(FetchLeafSnafu { resource: "latest" })?;
+    Ok(leaf.height() as usize)
+}
+
+/// Parse the `Range` header of a request for part of a payload.
+///
+/// Only a single range of bytes is supported, either closed (`bytes=start-end`, where `end` is
+/// inclusive) or open-ended (`bytes=start-`). If there is no `Range` header, the whole payload is
+/// requested.
+fn byte_range_header(req: &RequestParams) -> Result<Range<usize>, Error> {
+    let Some(header) = req.headers().get("Range") else {
+        return Ok(0..usize::MAX);
+    };
+    let header = header.last().as_str();
+    let invalid = || Error::InvalidByteRange {
+        range: header.to_string(),
+    };
+    let (start, end) = header
+        .strip_prefix("bytes=")
+        .and_then(|range| range.split_once('-'))
+        .ok_or_else(invalid)?;
+    let start = start.trim().parse::<usize>().map_err(|_| invalid())?;
+    let end = match end.trim() {
+        "" => usize::MAX,
+        end => end
+            .parse::<usize>()
+            .ok()
+            .filter(|end| *end >= start)
+            .ok_or_else(invalid)?
+            .saturating_add(1),
+    };
+    Ok(start..end)
+}
+
+/// Parse a namespace ID from its JSON representation in a URL parameter.
+///
+/// For convenience, namespace IDs which are represented as JSON strings may be given without
+/// quotes.
+fn parse_namespace<Types: NodeType>(param: &str) -> Result<NamespaceId<Types>, Error>
+where
+    Payload<Types>: NamespacedPayload<Types>,
+{
+    serde_json::from_str(param)
+        .or_else(|_| serde_json::from_value(serde_json::Value::String(param.into())))
+        .map_err(|err| Error::Custom {
+            message: format!("invalid namespace {param}: {err}"),
+            status: StatusCode::BAD_REQUEST,
+        })
+}
+
+/// Define the availability API.
+///
+/// The endpoints which depend on the namespaces of a block (`get_namespace`,
+/// `get_block_transaction` and the `stream_namespace_transactions` streams) are only served if the
+/// payload type implements [`NamespacedPayload`]; use [`define_api_with_namespaces`] to include
+/// them.
 pub fn define_api<State, Types: NodeType, Ver: StaticVersionType + 'static>(
     options: &Options,
     _: Ver,
 ) -> Result<Api<State, Error, Ver>, ApiError>
+where
+    State: 'static + Send + Sync + ReadState,
+    <State as ReadState>::State: Send + Sync + AvailabilityDataSource<Types>,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    let request_metrics =
+        RequestMetrics::<State, Error>::new(options.request_metrics.as_deref(), "availability");
+    define_api_with_metrics(options, &request_metrics)
+}
+
+/// Define the availability API, including the endpoints for querying namespaces.
+///
+/// This is the same as [`define_api`], but also serves the endpoints which depend on the
+/// namespaces of a block, for payload types which implement [`NamespacedPayload`].
+pub fn define_api_with_namespaces<State, Types: NodeType, Ver: StaticVersionType + 'static>(
+    options: &Options,
+    _: Ver,
+) -> Result<Api<State, Error, Ver>, ApiError>
+where
+    State: 'static + Send + Sync + ReadState,
+    <State as ReadState>::State: Send + Sync + AvailabilityDataSource<Types>,
+    Payload<Types>: NamespacedPayload<Types>,
+{
+    let request_metrics =
+        RequestMetrics::<State, Error>::new(options.request_metrics.as_deref(), "availability");
+    let mut api = define_api_with_metrics(options, &request_metrics)?;
+    let timeout = options.fetch_timeout;
+
+    api.stream("stream_namespace_transactions", move |req, state| {
+        async move {
+            let height = req.integer_param("height")?;
+            let namespace = parse_namespace::<Types>(req.string_param("namespace")?)?;
+            state
+                .read(|state| {
+                    async move {
+                        Ok(state
+                            .subscribe_namespace_transactions(namespace, height)
+                            .await
+                            .map(Ok))
+                    }
+                    .boxed()
+                })
+                .await
+        }
+        .try_flatten_stream()
+        .boxed()
+    })?
+    .stream(
+        "stream_namespace_transactions_with_cursor",
+        move |req, state| {
+            async move {
+                let endpoint = "stream_namespace_transactions_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                let namespace = parse_namespace::<Types>(req.string_param("namespace")?)?;
+                state
+                    .read(|state| {
+                        async move {
+                            let txs = state
+                                .subscribe_namespace_transactions(namespace, height)
+                                .await;
+                            Ok(with_cursors(txs, endpoint, resume, |tx| {
+                                (tx.block_height(), tx.index())
+                            }))
+                        }
+                        .boxed()
+                    })
+                    .await
+            }
+            .try_flatten_stream()
+            .boxed()
+        },
+    )?
+    .at(
+        "get_block_transaction",
+        request_metrics.instrument("get_block_transaction", move |req, state| {
+            async move {
+                let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                    BlockId::Hash(hash)
+                } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                    BlockId::PayloadHash(hash)
+                } else {
+                    BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                };
+                let index: u64 = req.integer_param("index")?;
+                let fetch = state.read(|state| state.get_block(id).boxed()).await;
+                let block = fetch_block(fetch, timeout, id).await?;
+                block
+                    .transaction_at(index as usize)
+                    .context(InvalidTransactionIndexSnafu {
+                        height: block.height(),
+                        index,
+                    })
+            }
+            .boxed()
+        }),
+    )?
+    .at(
+        "get_namespace",
+        request_metrics.instrument("get_namespace", move |req, state| {
+            async move {
+                let height = height_param::<_, Types>(&req, state, timeout).await?;
+                let namespace = parse_namespace::<Types>(req.string_param("namespace")?)?;
+                let fetch = state.read(|state| state.get_block(height).boxed()).await;
+                let block = fetch_block(fetch, timeout, height).await?;
+                Ok(NamespaceQueryData::new(&block, namespace))
+            }
+            .boxed()
+        }),
+    )?;
+    Ok(api)
+}
+
+fn define_api_with_metrics<State, Types: NodeType, Ver: StaticVersionType + 'static>(
+    options: &Options,
+    request_metrics: &RequestMetrics<State, Error>,
+) -> Result<Api<State, Error, Ver>, ApiError>
 where
     State: 'static + Send + Sync + ReadState,
     <State as ReadState>::State: Send + Sync + AvailabilityDataSource<Types>,
@@ -168,42 +479,175 @@ where
     let timeout = options.fetch_timeout;
     let small_object_range_limit = options.small_object_range_limit;
     let large_object_range_limit = options.large_object_range_limit;
+    let max_response_bytes = options.max_response_bytes;
 
     api.with_version("0.0.1".parse().unwrap())
-        .at("get_leaf", move |req, state| {
-            async move {
-                let id = match req.opt_integer_param("height")? {
-                    Some(height) => LeafId::Number(height),
-                    None => LeafId::Hash(req.blob_param("hash")?),
-                };
-                let fetch = state.read(|state| state.get_leaf(id).boxed()).await;
-                fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
-                    resource: id.to_string(),
-                })
-            }
-            .boxed()
-        })?
-        .at("get_leaf_range", move |req, state| {
-            async move {
-                let from = req.integer_param::<_, usize>("from")?;
-                let until = req.integer_param("until")?;
-                enforce_range_limit(from, until, small_object_range_limit)?;
-
-                let leaves = state
-                    .read(|state| state.get_leaf_range(from..until).boxed())
-                    .await;
-                leaves
-                    .enumerate()
-                    .then(|(index, fetch)| async move {
-                        fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
-                            resource: (index + from).to_string(),
-                        })
+        .at(
+            "get_leaf",
+            request_metrics.instrument("get_leaf", move |req, state| {
+                async move {
+                    let id = match req.opt_blob_param("hash")? {
+                        Some(hash) => LeafId::Hash(hash),
+                        None => {
+                            LeafId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                        }
+                    };
+                    let fetch = state.read(|state| state.get_leaf(id).boxed()).await;
+                    fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                        resource: id.to_string(),
                     })
-                    .try_collect::<Vec<_>>()
-                    .await
-            }
-            .boxed()
-        })?
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_leaf_by_view",
+            request_metrics.instrument("get_leaf_by_view", move |req, state| {
+                async move {
+                    let view = req.integer_param::<_, u64>("view")?;
+                    let fetch = state
+                        .read(|state| state.get_leaf_by_view(Types::View::new(view)).boxed())
+                        .await;
+                    fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                        resource: format!("view {view}"),
+                    })
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_qc",
+            request_metrics.instrument("get_qc", move |req, state| {
+                async move {
+                    let id = match req.opt_blob_param("hash")? {
+                        Some(hash) => LeafId::Hash(hash),
+                        None => {
+                            LeafId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                        }
+                    };
+                    let fetch = state.read(|state| state.get_qc(id).boxed()).await;
+                    fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                        resource: id.to_string(),
+                    })
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_first_leaf",
+            request_metrics.instrument("get_first_leaf", move |_req, state| {
+                async move {
+                    let fetch = state
+                        .read(|state| state.first_available_leaf().boxed())
+                        .await;
+                    fetch
+                        .with_timeout(timeout)
+                        .await
+                        .context(FetchLeafSnafu { resource: "first" })
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_earliest_height",
+            request_metrics.instrument("get_earliest_height", move |_req, state| {
+                async move { Ok(state.read(|state| state.earliest_height().boxed()).await as u64) }
+                    .boxed()
+            }),
+        )?
+        .at(
+            "get_earliest_block_height",
+            request_metrics.instrument("get_earliest_block_height", move |_req, state| {
+                async move {
+                    Ok(state
+                        .read(|state| state.earliest_block_height().boxed())
+                        .await as u64)
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_leaf_range",
+            request_metrics.instrument("get_leaf_range", move |req, state| {
+                async move {
+                    let from = req.integer_param::<_, usize>("from")?;
+                    let until = req.integer_param("until")?;
+                    enforce_range_limit(from, until, small_object_range_limit)?;
+
+                    let leaves = state
+                        .read(|state| state.get_leaf_range(from..until).boxed())
+                        .await;
+                    leaves
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                                resource: (index + from).to_string(),
+                            })
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_leaf_page",
+            request_metrics.instrument("get_leaf_page", move |req, state| {
+                async move {
+                    let (cursor, range) =
+                        page_params(&req, "get_leaf_page", small_object_range_limit)?;
+                    let from = range.start;
+
+                    let leaves = state
+                        .read(|state| state.get_leaf_range(range).boxed())
+                        .await;
+                    let items = leaves
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                                resource: (index + from).to_string(),
+                            })
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    Ok(cursor.page(items))
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_leaf_range_rev",
+            request_metrics.instrument("get_leaf_range_rev", move |req, state| {
+                async move {
+                    let limit = req.integer_param::<_, usize>("limit")?;
+                    enforce_range_limit(0, limit, small_object_range_limit)?;
+                    // `until` is exclusive; without it, start from the most recent object.
+                    let end = match req.opt_integer_param::<_, usize>("until")? {
+                        Some(0) => return Ok(vec![]),
+                        Some(until) => until - 1,
+                        None => usize::MAX,
+                    };
+
+                    let objects = state
+                        .read(|state| state.get_leaf_range_rev(Bound::Unbounded, end).boxed())
+                        .await;
+                    objects
+                        .take(limit)
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch.with_timeout(timeout).await.context(FetchLeafSnafu {
+                                resource: format!("{index} in reverse range ending at {end}"),
+                            })
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
         .stream("stream_leaves", move |req, state| {
             async move {
                 let height = req.integer_param("height")?;
@@ -216,49 +660,91 @@ where
             .try_flatten_stream()
             .boxed()
         })?
-        .at("get_header", move |req, state| {
-            async move {
-                let id = if let Some(height) = req.opt_integer_param("height")? {
-                    BlockId::Number(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    BlockId::Hash(hash)
-                } else {
-                    BlockId::PayloadHash(req.blob_param("payload-hash")?)
-                };
-                let fetch = state.read(|state| state.get_block(id).boxed()).await;
-                Ok(fetch
-                    .with_timeout(timeout)
-                    .await
-                    .context(FetchBlockSnafu {
-                        resource: id.to_string(),
-                    })?
-                    .header()
-                    .clone())
-            }
-            .boxed()
-        })?
-        .at("get_header_range", move |req, state| {
+        .stream("stream_leaves_with_cursor", move |req, state| {
             async move {
-                let from = req.integer_param::<_, usize>("from")?;
-                let until = req.integer_param::<_, usize>("until")?;
-                enforce_range_limit(from, until, large_object_range_limit)?;
-
-                let headers = state
-                    .read(|state| state.get_block_range(from..until).boxed())
-                    .await;
-                headers
-                    .enumerate()
-                    .then(|(index, fetch)| async move {
-                        fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                            resource: (index + from).to_string(),
-                        })
+                let endpoint = "stream_leaves_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                state
+                    .read(|state| {
+                        async move {
+                            let leaves = state.subscribe_leaves(height).await;
+                            Ok(with_cursors(leaves, endpoint, resume, |leaf| {
+                                (leaf.height(), 0)
+                            }))
+                        }
+                        .boxed()
                     })
-                    .map(|r| r.map(|block| block.header().clone()))
-                    .try_collect::<Vec<_>>()
                     .await
             }
+            .try_flatten_stream()
             .boxed()
         })?
+        .at(
+            "get_header",
+            request_metrics.instrument("get_header", move |req, state| {
+                async move {
+                    let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::Hash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else {
+                        BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                    };
+                    let fetch = state.read(|state| state.get_header(id).boxed()).await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_header_range",
+            request_metrics.instrument("get_header_range", move |req, state| {
+                async move {
+                    let from = req.integer_param::<_, usize>("from")?;
+                    let until = req.integer_param::<_, usize>("until")?;
+                    enforce_range_limit(from, until, large_object_range_limit)?;
+
+                    let headers = state
+                        .read(|state| state.get_block_range(from..until).boxed())
+                        .await;
+                    headers
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(|r| r.map(|block| block.header().clone()))
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_header_page",
+            request_metrics.instrument("get_header_page", move |req, state| {
+                async move {
+                    let (cursor, range) =
+                        page_params(&req, "get_header_page", large_object_range_limit)?;
+                    let from = range.start;
+
+                    let headers = state
+                        .read(|state| state.get_block_range(range).boxed())
+                        .await;
+                    let items = headers
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(|r| r.map(|block| block.header().clone()))
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    Ok(cursor.page(items))
+                }
+                .boxed()
+            }),
+        )?
         .stream("stream_headers", move |req, state| {
             async move {
                 let height = req.integer_param("height")?;
@@ -277,43 +763,138 @@ where
             .try_flatten_stream()
             .boxed()
         })?
-        .at("get_block", move |req, state| {
-            async move {
-                let id = if let Some(height) = req.opt_integer_param("height")? {
-                    BlockId::Number(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    BlockId::Hash(hash)
-                } else {
-                    BlockId::PayloadHash(req.blob_param("payload-hash")?)
-                };
-                let fetch = state.read(|state| state.get_block(id).boxed()).await;
-                fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                    resource: id.to_string(),
-                })
-            }
-            .boxed()
-        })?
-        .at("get_block_range", move |req, state| {
+        .stream("stream_headers_with_cursor", move |req, state| {
             async move {
-                let from = req.integer_param::<_, usize>("from")?;
-                let until = req.integer_param("until")?;
-                enforce_range_limit(from, until, large_object_range_limit)?;
-
-                let blocks = state
-                    .read(|state| state.get_block_range(from..until).boxed())
-                    .await;
-                blocks
-                    .enumerate()
-                    .then(|(index, fetch)| async move {
-                        fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                            resource: (index + from).to_string(),
-                        })
+                let endpoint = "stream_headers_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                Ok(state
+                    .read(|state| {
+                        async move {
+                            let headers = state
+                                .subscribe_blocks(height)
+                                .await
+                                .map(|block| block.header);
+                            with_cursors(headers, endpoint, resume, |header| {
+                                (header.block_number(), 0)
+                            })
+                        }
+                        .boxed()
                     })
-                    .try_collect::<Vec<_>>()
-                    .await
+                    .await)
             }
+            .try_flatten_stream()
             .boxed()
         })?
+        .at(
+            "get_block",
+            request_metrics.instrument("get_block", move |req, state| {
+                async move {
+                    let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::Hash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else {
+                        BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                    };
+                    let fetch = state.read(|state| state.get_block(id).boxed()).await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_first_block",
+            request_metrics.instrument("get_first_block", move |_req, state| {
+                async move {
+                    let fetch = state
+                        .read(|state| state.first_available_block().boxed())
+                        .await;
+                    fetch_block(fetch, timeout, "first").await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_range",
+            request_metrics.instrument("get_block_range", move |req, state| {
+                async move {
+                    let from = req.integer_param::<_, usize>("from")?;
+                    let until = req.integer_param("until")?;
+                    enforce_range_limit(from, until, large_object_range_limit)?;
+
+                    let blocks = state
+                        .read(|state| state.get_block_range(from..until).boxed())
+                        .await;
+                    blocks
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_page",
+            request_metrics.instrument("get_block_page", move |req, state| {
+                async move {
+                    let (cursor, range) =
+                        page_params(&req, "get_block_page", large_object_range_limit)?;
+                    let from = range.start;
+
+                    let blocks = state
+                        .read(|state| state.get_block_range(range).boxed())
+                        .await;
+                    let items = blocks
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    Ok(cursor.page(items))
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_range_rev",
+            request_metrics.instrument("get_block_range_rev", move |req, state| {
+                async move {
+                    let limit = req.integer_param::<_, usize>("limit")?;
+                    enforce_range_limit(0, limit, large_object_range_limit)?;
+                    // `until` is exclusive; without it, start from the most recent object.
+                    let end = match req.opt_integer_param::<_, usize>("until")? {
+                        Some(0) => return Ok(vec![]),
+                        Some(until) => until - 1,
+                        None => usize::MAX,
+                    };
+
+                    let objects = state
+                        .read(|state| state.get_block_range_rev(Bound::Unbounded, end).boxed())
+                        .await;
+                    objects
+                        .take(limit)
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(
+                                fetch,
+                                timeout,
+                                format!("{index} in reverse range ending at {end}"),
+                            )
+                            .await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
         .stream("stream_blocks", move |req, state| {
             async move {
                 let height = req.integer_param("height")?;
@@ -326,43 +907,141 @@ where
             .try_flatten_stream()
             .boxed()
         })?
-        .at("get_payload", move |req, state| {
+        .stream("stream_blocks_with_cursor", move |req, state| {
             async move {
-                let id = if let Some(height) = req.opt_integer_param("height")? {
-                    BlockId::Number(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    BlockId::PayloadHash(hash)
-                } else {
-                    BlockId::Hash(req.blob_param("block-hash")?)
-                };
-                let fetch = state.read(|state| state.get_payload(id).boxed()).await;
-                fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                    resource: id.to_string(),
-                })
-            }
-            .boxed()
-        })?
-        .at("get_payload_range", move |req, state| {
-            async move {
-                let from = req.integer_param::<_, usize>("from")?;
-                let until = req.integer_param("until")?;
-                enforce_range_limit(from, until, large_object_range_limit)?;
-
-                let payloads = state
-                    .read(|state| state.get_payload_range(from..until).boxed())
-                    .await;
-                payloads
-                    .enumerate()
-                    .then(|(index, fetch)| async move {
-                        fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                            resource: (index + from).to_string(),
-                        })
+                let endpoint = "stream_blocks_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                Ok(state
+                    .read(|state| {
+                        async move {
+                            let blocks = state.subscribe_blocks(height).await;
+                            with_cursors(blocks, endpoint, resume, |block| (block.height(), 0))
+                        }
+                        .boxed()
                     })
-                    .try_collect::<Vec<_>>()
-                    .await
+                    .await)
             }
+            .try_flatten_stream()
             .boxed()
         })?
+        .at(
+            "get_payload",
+            request_metrics.instrument("get_payload", move |req, state| {
+                async move {
+                    let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("block-hash")? {
+                        BlockId::Hash(hash)
+                    } else {
+                        BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                    };
+                    let fetch = state.read(|state| state.get_payload(id).boxed()).await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_raw_payload",
+            request_metrics.instrument("get_raw_payload", move |req, state| {
+                async move {
+                    let id = BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?);
+                    let fetch = state.read(|state| state.get_raw_payload(id).boxed()).await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_payload_bytes",
+            request_metrics.instrument("get_payload_bytes", move |req, state| {
+                async move {
+                    let id = BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?);
+                    let range = byte_range_header(&req)?;
+                    let (start, end) = (range.start, range.end);
+
+                    // If the payload is stored locally, read only the requested bytes. Otherwise,
+                    // fetch the whole payload and slice it.
+                    let bytes = match state
+                        .read(|state| state.get_payload_bytes(id, start..end).boxed())
+                        .await
+                    {
+                        Ok(bytes) => bytes,
+                        Err(QueryError::NotFound | QueryError::Missing) => {
+                            let fetch = state.read(|state| state.get_raw_payload(id).boxed()).await;
+                            let payload = fetch_block(fetch, timeout, id).await?;
+                            PayloadByteRange::new(payload, start..end)
+                        }
+                        Err(source) => {
+                            return Err(Error::QueryPayloadBytes {
+                                source,
+                                block: id.to_string(),
+                            })
+                        }
+                    };
+                    // A range must start within the payload, except that an empty payload can
+                    // still be requested from the start.
+                    ensure!(
+                        start < bytes.total_len() || start == 0,
+                        InvalidByteRangeSnafu {
+                            range: format!(
+                                "starting at {start} of a {}-byte payload",
+                                bytes.total_len()
+                            ),
+                        }
+                    );
+                    Ok(bytes)
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_payload_range",
+            request_metrics.instrument("get_payload_range", move |req, state| {
+                async move {
+                    let from = req.integer_param::<_, usize>("from")?;
+                    let until = req.integer_param("until")?;
+                    enforce_range_limit(from, until, large_object_range_limit)?;
+
+                    let payloads = state
+                        .read(|state| state.get_payload_range(from..until).boxed())
+                        .await;
+                    payloads
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_payload_page",
+            request_metrics.instrument("get_payload_page", move |req, state| {
+                async move {
+                    let (cursor, range) =
+                        page_params(&req, "get_payload_page", large_object_range_limit)?;
+                    let from = range.start;
+
+                    let payloads = state
+                        .read(|state| state.get_payload_range(range).boxed())
+                        .await;
+                    let items = payloads
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    Ok(cursor.page(items))
+                }
+                .boxed()
+            }),
+        )?
         .stream("stream_payloads", move |req, state| {
             async move {
                 let height = req.integer_param("height")?;
@@ -375,22 +1054,65 @@ where
             .try_flatten_stream()
             .boxed()
         })?
-        .at("get_vid_common", move |req, state| {
+        .stream("stream_payloads_with_cursor", move |req, state| {
             async move {
-                let id = if let Some(height) = req.opt_integer_param("height")? {
-                    BlockId::Number(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    BlockId::Hash(hash)
-                } else {
-                    BlockId::PayloadHash(req.blob_param("payload-hash")?)
-                };
-                let fetch = state.read(|state| state.get_vid_common(id).boxed()).await;
-                fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                    resource: id.to_string(),
-                })
+                let endpoint = "stream_payloads_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                Ok(state
+                    .read(|state| {
+                        async move {
+                            let payloads = state.subscribe_payloads(height).await;
+                            with_cursors(payloads, endpoint, resume, |payload| {
+                                (payload.height(), 0)
+                            })
+                        }
+                        .boxed()
+                    })
+                    .await)
             }
+            .try_flatten_stream()
             .boxed()
         })?
+        .at(
+            "get_vid_common",
+            request_metrics.instrument("get_vid_common", move |req, state| {
+                async move {
+                    let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::Hash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else {
+                        BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                    };
+                    let fetch = state.read(|state| state.get_vid_common(id).boxed()).await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_vid_share",
+            request_metrics.instrument("get_vid_share", move |req, state| {
+                async move {
+                    let id = if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::Hash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else {
+                        BlockId::Number(height_param::<_, Types>(&req, state, timeout).await?)
+                    };
+                    let index = req.integer_param("index")?;
+                    state
+                        .read(|state| state.get_vid_share(id, index).boxed())
+                        .await
+                        .context(QueryVidShareSnafu {
+                            block: id.to_string(),
+                            index,
+                        })
+                }
+                .boxed()
+            }),
+        )?
         .stream("stream_vid_common", move |req, state| {
             async move {
                 let height = req.integer_param("height")?;
@@ -403,88 +1125,170 @@ where
             .try_flatten_stream()
             .boxed()
         })?
-        .at("get_transaction", move |req, state| {
-            async move {
-                match req.opt_blob_param("hash")? {
-                    Some(hash) => {
-                        let fetch = state
-                            .read(|state| state.get_transaction(hash).boxed())
-                            .await;
-                        fetch
-                            .with_timeout(timeout)
-                            .await
-                            .context(FetchTransactionSnafu {
-                                resource: hash.to_string(),
-                            })
-                    }
-                    None => {
-                        let height: u64 = req.integer_param("height")?;
-                        let fetch = state
-                            .read(|state| state.get_block(height as usize).boxed())
-                            .await;
-                        let block = fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                            resource: height.to_string(),
-                        })?;
-                        let i: u64 = req.integer_param("index")?;
-                        let index = block
-                            .payload()
-                            .nth(block.metadata(), i as usize)
-                            .context(InvalidTransactionIndexSnafu { height, index: i })?;
-                        TransactionQueryData::new(&block, index, i)
-                            .context(InvalidTransactionIndexSnafu { height, index: i })
-                    }
-                }
-            }
-            .boxed()
-        })?
-        .at("get_block_summary", move |req, state| {
+        .stream("stream_vid_common_with_cursor", move |req, state| {
             async move {
-                let id: usize = req.integer_param("height")?;
-
-                let fetch = state.read(|state| state.get_block(id).boxed()).await;
-                fetch
-                    .with_timeout(timeout)
-                    .await
-                    .context(FetchBlockSnafu {
-                        resource: id.to_string(),
+                let endpoint = "stream_vid_common_with_cursor";
+                let (height, resume) = stream_start(&req, endpoint)?;
+                Ok(state
+                    .read(|state| {
+                        async move {
+                            let common = state.subscribe_vid_common(height).await;
+                            with_cursors(common, endpoint, resume, |common| (common.height(), 0))
+                        }
+                        .boxed()
                     })
-                    .map(BlockSummaryQueryData::from)
+                    .await)
             }
+            .try_flatten_stream()
             .boxed()
         })?
-        .at("get_block_summary_range", move |req, state| {
-            async move {
-                let from: usize = req.integer_param("from")?;
-                let until: usize = req.integer_param("until")?;
-                enforce_range_limit(from, until, large_object_range_limit)?;
-
-                let blocks = state
-                    .read(|state| state.get_block_range(from..until).boxed())
-                    .await;
-                let result: Vec<BlockSummaryQueryData<Types>> = blocks
-                    .enumerate()
-                    .then(|(index, fetch)| async move {
-                        fetch.with_timeout(timeout).await.context(FetchBlockSnafu {
-                            resource: (index + from).to_string(),
+        .at(
+            "get_transaction",
+            request_metrics.instrument("get_transaction", move |req, state| {
+                async move {
+                    match req.opt_blob_param("hash")? {
+                        Some(hash) => {
+                            let fetch = state
+                                .read(|state| state.get_transaction(hash).boxed())
+                                .await;
+                            fetch
+                                .with_timeout(timeout)
+                                .await
+                                .context(FetchTransactionSnafu {
+                                    resource: hash.to_string(),
+                                })
+                        }
+                        None => {
+                            let height =
+                                height_param::<_, Types>(&req, state, timeout).await? as u64;
+                            let fetch = state
+                                .read(|state| state.get_block(height as usize).boxed())
+                                .await;
+                            let block = fetch_block(fetch, timeout, height).await?;
+                            let i: u64 = req.integer_param("index")?;
+                            TransactionQueryData::new(&block, i)
+                                .context(InvalidTransactionIndexSnafu { height, index: i })
+                        }
+                    }
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_summary",
+            request_metrics.instrument("get_block_summary", move |req, state| {
+                async move {
+                    let id = height_param::<_, Types>(&req, state, timeout).await?;
+
+                    let fetch = state
+                        .read(|state| state.get_block_summary(id).boxed())
+                        .await;
+                    fetch_block(fetch, timeout, id).await
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_summary_range",
+            request_metrics.instrument("get_block_summary_range", move |req, state| {
+                async move {
+                    let from: usize = req.integer_param("from")?;
+                    let until: usize = req.integer_param("until")?;
+                    enforce_range_limit(from, until, large_object_range_limit)?;
+
+                    let summaries = state
+                        .read(|state| state.get_block_summary_range(from..until).boxed())
+                        .await;
+                    let result: Vec<BlockSummaryQueryData<Types>> = summaries
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
                         })
-                    })
-                    .map(|result| result.map(BlockSummaryQueryData::from))
-                    .try_collect()
-                    .await?;
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect()
+                        .await?;
 
-                Ok(result)
-            }
-            .boxed()
-        })?
-        .at("get_limits", move |_req, _state| {
-            async move {
-                Ok(Limits {
-                    small_object_range_limit,
-                    large_object_range_limit,
-                })
-            }
-            .boxed()
-        })?;
+                    Ok(result)
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_blocks_in_time_range",
+            request_metrics.instrument("get_blocks_in_time_range", move |req, state| {
+                async move {
+                    let start: u64 = req.integer_param("from")?;
+                    let end: u64 = req.integer_param("to")?;
+
+                    let summaries = state
+                        .read(|state| state.get_blocks_in_time_range(start, end).boxed())
+                        .await
+                        .map_err(|err| Error::Custom {
+                            message: err.to_string(),
+                            status: err.status(),
+                        })?;
+                    // The number of blocks in a time range is not known until we look, so the
+                    // range limit is enforced on the result instead of the request.
+                    let result: Vec<BlockSummaryQueryData<Types>> = summaries
+                        .take(large_object_range_limit + 1)
+                        .then(|fetch| async move {
+                            fetch_block(fetch, timeout, format!("in time range {start}..{end}"))
+                                .await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect()
+                        .await?;
+                    if result.len() > large_object_range_limit {
+                        return Err(Error::Custom {
+                            message: format!(
+                                "time range {start}..{end} contains more than \
+                                 {large_object_range_limit} blocks"
+                            ),
+                            status: StatusCode::BAD_REQUEST,
+                        });
+                    }
+
+                    Ok(result)
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_block_summary_page",
+            request_metrics.instrument("get_block_summary_page", move |req, state| {
+                async move {
+                    let (cursor, range) =
+                        page_params(&req, "get_block_summary_page", large_object_range_limit)?;
+                    let from = range.start;
+
+                    let summaries = state
+                        .read(|state| state.get_block_summary_range(range).boxed())
+                        .await;
+                    let items: Vec<BlockSummaryQueryData<Types>> = summaries
+                        .enumerate()
+                        .then(|(index, fetch)| async move {
+                            fetch_block(fetch, timeout, index + from).await
+                        })
+                        .map(enforce_response_size(max_response_bytes))
+                        .try_collect()
+                        .await?;
+                    Ok(cursor.page(items))
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_limits",
+            request_metrics.instrument("get_limits", move |_req, _state| {
+                async move {
+                    Ok(Limits {
+                        small_object_range_limit,
+                        large_object_range_limit,
+                    })
+                }
+                .boxed()
+            }),
+        )?;
     Ok(api)
 }
 
@@ -495,11 +1299,135 @@ fn enforce_range_limit(from: usize, until: usize, limit: usize) -> Result<(), Er
     Ok(())
 }
 
+/// Fail a range query once the objects loaded so far exceed `limit` bytes in total.
+///
+/// The result is meant to be mapped over the stream of objects in a range, before it is collected.
+/// Since collecting stops at the first error, this keeps the rest of the range from being loaded.
+fn enforce_response_size<T: Serialize>(
+    limit: Option<usize>,
+) -> impl FnMut(Result<T, Error>) -> Result<T, Error> {
+    let mut size = 0;
+    move |obj| {
+        let obj = obj?;
+        if let Some(limit) = limit {
+            size += json_size(&obj)?;
+            ensure!(size <= limit, ResponseTooLargeSnafu { limit });
+        }
+        Ok(obj)
+    }
+}
+
+/// The size of the JSON encoding of `obj`, computed without allocating the encoding.
+fn json_size<T: Serialize>(obj: &T) -> Result<usize, Error> {
+    struct Counter(usize);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, obj).map_err(Error::internal)?;
+    Ok(counter.0)
+}
+
+/// Parse the parameters of a paginated range query to `endpoint`.
+///
+/// Returns the cursor for the requested page, which is either given explicitly or starts a new
+/// query of the range `[:from, :until)`, along with the range of heights to load for this page.
+fn page_params(
+    req: &RequestParams,
+    endpoint: &str,
+    limit: usize,
+) -> Result<(PageCursor, Range<usize>), Error> {
+    let page_size = req.integer_param::<_, usize>("limit")?;
+    enforce_range_limit(0, page_size, limit)?;
+    if page_size == 0 {
+        return Err(Error::Custom {
+            message: "page size must be positive".into(),
+            status: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    let cursor = match req.opt_blob_param::<_, PageCursor>("cursor")? {
+        Some(cursor) if cursor.endpoint() != endpoint => {
+            return Err(Error::CursorMismatch {
+                issuer: cursor.endpoint().into(),
+                endpoint: endpoint.into(),
+            });
+        }
+        Some(cursor) => cursor,
+        None => PageCursor::new(
+            endpoint,
+            req.integer_param("from")?,
+            req.integer_param("until")?,
+        ),
+    };
+    let range = cursor.next_page(page_size);
+    Ok((cursor, range))
+}
+
+/// The height at which to start a cursor stream, and the cursor to resume after, if any.
+///
+/// If the request includes a cursor, the stream starts at the block containing the cursor, since
+/// that block may contain further items after the cursor, which must not be skipped.
+fn stream_start(
+    req: &RequestParams,
+    endpoint: &str,
+) -> Result<(usize, Option<StreamCursor>), Error> {
+    match req.opt_blob_param::<_, StreamCursor>("cursor")? {
+        Some(cursor) if cursor.endpoint() != endpoint => Err(Error::CursorMismatch {
+            issuer: cursor.endpoint().into(),
+            endpoint: endpoint.into(),
+        }),
+        Some(cursor) => Ok((cursor.height() as usize, Some(cursor))),
+        None => Ok((req.integer_param("height")?, None)),
+    }
+}
+
+/// Attach a cursor to each item in `stream`, skipping items up to and including `resume`.
+///
+/// `position` gives the height and index within its block of each item.
+fn with_cursors<T>(
+    stream: impl Stream<Item = T> + Send + 'static,
+    endpoint: &'static str,
+    resume: Option<StreamCursor>,
+    position: impl Fn(&T) -> (u64, u64) + Send + 'static,
+) -> BoxStream<'static, Result<StreamFrame<T>, Error>>
+where
+    T: Send + 'static,
+{
+    stream
+        .map(move |item| {
+            let (height, index) = position(&item);
+            StreamFrame {
+                cursor: StreamCursor::new(endpoint, height, index),
+                item,
+            }
+        })
+        .filter(move |frame| {
+            future::ready(
+                resume
+                    .as_ref()
+                    .map_or(true, |resume| resume.precedes(&frame.cursor)),
+            )
+        })
+        .map(Ok)
+        .boxed()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
         data_source::{storage::no_storage, ExtensibleDataSource},
+        explorer::ExplorerTransaction,
         status::StatusDataSource,
         task::BackgroundTask,
         testing::{
@@ -513,7 +1441,7 @@ mod test {
     use async_lock::RwLock;
     use committable::Committable;
     use futures::future::FutureExt;
-    use hotshot_types::{data::Leaf, simple_certificate::QuorumCertificate};
+    use hotshot_types::{data::Leaf, simple_certificate::QuorumCertificate, traits::EncodeBytes};
     use portpicker::pick_unused_port;
     use serde::de::DeserializeOwned;
     use std::{fmt::Debug, time::Duration};
@@ -580,6 +1508,31 @@ mod test {
                     .unwrap()
             );
 
+            // Check that the QC can be looked up without the rest of the leaf.
+            let qc: QuorumCertificate<MockTypes> =
+                client.get(&format!("qc/{i}")).send().await.unwrap();
+            assert_eq!(qc, *leaf.qc());
+            assert_eq!(qc.view_number, leaf.leaf().view_number());
+            assert_eq!(
+                Some(leaf.clone()),
+                client
+                    .get::<Option<LeafQueryData<MockTypes>>>(&format!(
+                        "leaf/view/{}",
+                        leaf.leaf().view_number().u64()
+                    ))
+                    .send()
+                    .await
+                    .unwrap()
+            );
+            assert_eq!(
+                qc,
+                client
+                    .get::<QuorumCertificate<MockTypes>>(&format!("qc/hash/{}", leaf.hash()))
+                    .send()
+                    .await
+                    .unwrap()
+            );
+
             // Check that looking up the block various ways returns the correct block.
             let block: BlockQueryData<MockTypes> =
                 client.get(&format!("block/{}", i)).send().await.unwrap();
@@ -618,6 +1571,65 @@ mod test {
                     .await
                     .unwrap(),
             );
+            // The raw payload is exactly the encoding the payload commitment commits to.
+            let raw: RawPayloadQueryData<MockTypes> = client
+                .get(&format!("payload/{i}/raw"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(raw.height(), block.height());
+            assert_eq!(raw.block_hash(), block.hash());
+            assert_eq!(raw.hash(), block.payload_hash());
+            assert_eq!(raw.data(), &*block.payload().encode());
+            // Download the raw payload in pieces.
+            let encoded = block.payload().encode();
+            let mut downloaded = vec![];
+            loop {
+                let bytes: PayloadByteRange<MockTypes> = client
+                    .get(&format!("payload/{i}/bytes"))
+                    .header(
+                        "Range",
+                        format!("bytes={}-{}", downloaded.len(), downloaded.len() + 1),
+                    )
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(bytes.height(), block.height());
+                assert_eq!(bytes.hash(), block.payload_hash());
+                assert_eq!(bytes.start(), downloaded.len());
+                assert_eq!(bytes.total_len(), encoded.len());
+                downloaded.extend_from_slice(bytes.data());
+                // Every request makes progress, except for the single request for an empty payload,
+                // which is served from the start.
+                if downloaded.len() == encoded.len() {
+                    break;
+                }
+                assert!(!bytes.data().is_empty());
+            }
+            assert_eq!(downloaded, &*encoded);
+            let bytes: PayloadByteRange<MockTypes> = client
+                .get(&format!("payload/{i}/bytes"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(bytes.data(), &*encoded);
+            let err = client
+                .get::<PayloadByteRange<MockTypes>>(&format!("payload/{i}/bytes"))
+                .header("Range", format!("bytes={}-", encoded.len() + 1))
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+            // A range starting exactly at the end of a non-empty payload is also unsatisfiable.
+            if !encoded.is_empty() {
+                let err = client
+                    .get::<PayloadByteRange<MockTypes>>(&format!("payload/{i}/bytes"))
+                    .header("Range", format!("bytes={}-", encoded.len()))
+                    .send()
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+            }
             // Look up the common VID data.
             let common: VidCommonQueryData<MockTypes> = client
                 .get(&format!("vid/common/{}", block.height()))
@@ -723,6 +1735,12 @@ mod test {
                 assert_eq!(txn.index(), j as u64);
                 assert_eq!(txn.hash(), txn_from_block.commit());
                 assert_eq!(txn.transaction(), &txn_from_block);
+                assert!(txn.verify(block.header()));
+                assert!(verify_tx_inclusion(
+                    block.header(),
+                    txn.transaction(),
+                    txn.proof()
+                ));
                 // We should be able to look up the transaction by hash. Note that for duplicate
                 // transactions, this endpoint may return a different transaction with the same
                 // hash, which is acceptable. Therefore, we don't check equivalence of the entire
@@ -739,7 +1757,45 @@ mod test {
                         .unwrap()
                         .hash()
                 );
+                // We should be able to look up the transaction by its position in the block.
+                let (ns, txn_at): (NamespaceId<MockTypes>, TransactionQueryData<MockTypes>) =
+                    client
+                        .get(&format!("block/{i}/transaction/{j}"))
+                        .send()
+                        .await
+                        .unwrap();
+                assert_eq!(ns, txn_from_block.namespace_id());
+                assert_eq!(txn_at, txn);
             }
+            let err = client
+                .get::<(NamespaceId<MockTypes>, TransactionQueryData<MockTypes>)>(&format!(
+                    "block/{i}/transaction/{}",
+                    block.len()
+                ))
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::NOT_FOUND);
+
+            // All mock transactions are in namespace 0.
+            let ns: NamespaceQueryData<MockTypes> = client
+                .get(&format!("block/{i}/namespace/0"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(ns.block_height(), i);
+            assert_eq!(ns.block_hash(), block.hash());
+            assert_eq!(
+                ns.transactions(),
+                block.enumerate().map(|(_, tx)| tx).collect::<Vec<_>>()
+            );
+            assert!(ns.verify(block.header()));
+            let ns: NamespaceQueryData<MockTypes> = client
+                .get(&format!("block/{i}/namespace/1"))
+                .send()
+                .await
+                .unwrap();
+            assert!(ns.transactions().is_empty());
 
             let block_range: Vec<BlockQueryData<MockTypes>> = client
                 .get(&format!("block/{}/{}", 0, i))
@@ -812,7 +1868,7 @@ mod test {
         let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
         app.register_module(
             "availability",
-            define_api(
+            define_api_with_namespaces(
                 &Options {
                     fetch_timeout,
                     ..Default::default()
@@ -893,7 +1949,103 @@ mod test {
                 client.get(&format!("vid/common/{i}")).send().await.unwrap()
             );
 
-            validate(&client, (i + 1) as u64).await;
+            validate(&client, (i + 1) as u64).await;
+        }
+
+        // `latest` is resolved when each request is made. The chain keeps growing, so later
+        // requests may see later blocks, but never earlier ones.
+        let latest: LeafQueryData<MockTypes> = client.get("leaf/latest").send().await.unwrap();
+        assert_eq!(
+            latest,
+            client
+                .get(&format!("leaf/{}", latest.height()))
+                .send()
+                .await
+                .unwrap()
+        );
+        let header: Header<MockTypes> = client.get("header/latest").send().await.unwrap();
+        assert!(header.block_number() >= latest.height());
+        let summary: BlockSummaryQueryData<MockTypes> =
+            client.get("block/summary/latest").send().await.unwrap();
+        assert!(summary.height() >= header.block_number());
+
+        // All of the transactions we submitted are in the mock namespace, and should be streamed
+        // in order, skipping all the empty blocks.
+        let txs = client
+            .socket("stream/transactions/namespace/0/0")
+            .subscribe::<TransactionQueryData<MockTypes>>()
+            .await
+            .unwrap()
+            .take(3)
+            .collect::<Vec<_>>()
+            .await;
+        for (nonce, tx) in txs.into_iter().enumerate() {
+            let tx = tx.unwrap();
+            assert_eq!(*tx.transaction(), mock_transaction(vec![nonce as u8]));
+            let block: BlockQueryData<MockTypes> = client
+                .get(&format!("block/{}", tx.block_height()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(tx.block_hash(), block.hash());
+        }
+
+        // Streams with cursors can be resumed exactly after the last item received.
+        let leaves = client
+            .socket("stream/leaves/cursor/0")
+            .subscribe::<StreamFrame<LeafQueryData<MockTypes>>>()
+            .await
+            .unwrap()
+            .take(2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(leaves[0].item.height(), 0);
+        let resumed = client
+            .socket(&format!("stream/leaves/resume/{}", leaves[0].cursor))
+            .subscribe::<StreamFrame<LeafQueryData<MockTypes>>>()
+            .await
+            .unwrap()
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resumed, leaves[1]);
+
+        // This also works for transactions, which may share a block.
+        let txs = client
+            .socket("stream/transactions/namespace/0/cursor/0")
+            .subscribe::<StreamFrame<TransactionQueryData<MockTypes>>>()
+            .await
+            .unwrap()
+            .take(2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(txs[0].cursor.height(), txs[0].item.block_height());
+        assert_eq!(txs[0].cursor.index(), txs[0].item.index());
+        let resumed = client
+            .socket(&format!(
+                "stream/transactions/namespace/0/resume/{}",
+                txs[0].cursor
+            ))
+            .subscribe::<StreamFrame<TransactionQueryData<MockTypes>>>()
+            .await
+            .unwrap()
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resumed, txs[1]);
+
+        // A cursor from one stream cannot be used to resume another.
+        let res = client
+            .socket(&format!("stream/blocks/resume/{}", txs[0].cursor))
+            .subscribe::<StreamFrame<BlockQueryData<MockTypes>>>()
+            .await;
+        if let Ok(mut stream) = res {
+            let next = stream.next().await;
+            assert!(!matches!(next, Some(Ok(_))), "{next:?}");
         }
 
         network.shut_down().await;
@@ -1093,4 +2245,407 @@ mod test {
 
         network.shut_down().await;
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_response_bytes() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        network.start().await;
+
+        // Wait for a few leaves to be produced, and measure them.
+        let leaves = network
+            .data_source()
+            .subscribe_leaves(0)
+            .await
+            .take(3)
+            .collect::<Vec<_>>()
+            .await;
+        let max_response_bytes = json_size(&leaves[0]).unwrap() + json_size(&leaves[1]).unwrap();
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(
+                &Options {
+                    max_response_bytes: Some(max_response_bytes),
+                    ..Default::default()
+                },
+                MockBase::instance(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+
+        // Start a client.
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{}/availability", port)
+                .parse()
+                .unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        // A range which fits in the limit succeeds.
+        let range: Vec<LeafQueryData<MockTypes>> = client.get("leaf/0/2").send().await.unwrap();
+        assert_eq!(range, leaves[..2]);
+
+        // A range which is within the range limit but exceeds the size limit is rejected.
+        let err = client
+            .get::<Vec<LeafQueryData<MockTypes>>>("leaf/0/3")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let err = client
+            .get::<Page<LeafQueryData<MockTypes>>>("leaf/page/0/3/3")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        network.shut_down().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocks_in_time_range() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        network.start().await;
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+
+        // Start a client.
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{}/availability", port)
+                .parse()
+                .unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        // Wait for some blocks to be produced.
+        let blocks = client
+            .socket("stream/blocks/0")
+            .subscribe::<BlockQueryData<MockTypes>>()
+            .await
+            .unwrap()
+            .take(5)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let end = blocks.last().unwrap().header().timestamp() + 1;
+
+        // Every block produced so far is in the range ending after the last one, and every block
+        // returned is actually in the range.
+        let summaries: Vec<BlockSummaryQueryData<MockTypes>> = client
+            .get(&format!("block/time-range/0/{end}"))
+            .send()
+            .await
+            .unwrap();
+        for block in &blocks {
+            assert!(
+                summaries
+                    .iter()
+                    .any(|summary| summary.hash() == block.hash()),
+                "missing block {}",
+                block.height()
+            );
+        }
+        for summary in &summaries {
+            assert!(summary.header().timestamp() < end);
+        }
+        assert!(summaries
+            .iter()
+            .zip(summaries.iter().skip(1))
+            .all(|(prev, next)| prev.height() < next.height()));
+
+        // A range containing only one block's timestamp contains that block.
+        let block = &blocks[2];
+        let start = block.header().timestamp();
+        let summaries: Vec<BlockSummaryQueryData<MockTypes>> = client
+            .get(&format!("block/time-range/{start}/{}", start + 1))
+            .send()
+            .await
+            .unwrap();
+        assert!(summaries
+            .iter()
+            .any(|summary| summary.hash() == block.hash()));
+        for summary in &summaries {
+            assert_eq!(summary.header().timestamp(), start);
+        }
+
+        // An empty range contains no blocks.
+        let summaries: Vec<BlockSummaryQueryData<MockTypes>> = client
+            .get(&format!("block/time-range/{end}/{start}"))
+            .send()
+            .await
+            .unwrap();
+        assert!(summaries.is_empty());
+
+        network.shut_down().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pagination() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        network.start().await;
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(
+                &Options {
+                    large_object_range_limit: 2,
+                    small_object_range_limit: 3,
+                    ..Default::default()
+                },
+                MockBase::instance(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+
+        // Start a client.
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{}/availability", port)
+                .parse()
+                .unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        // Wait for enough blocks to be produced.
+        let until = 7;
+        client
+            .socket("stream/blocks/0")
+            .subscribe::<BlockQueryData<MockTypes>>()
+            .await
+            .unwrap()
+            .take(until)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // Page through the whole range, checking that we get every object exactly once, in order,
+        // even though more blocks are being produced concurrently.
+        async fn paginate<T: DeserializeOwned + HeightIndexed>(
+            client: &Client<Error, MockBase>,
+            req: &str,
+            until: usize,
+            limit: usize,
+        ) -> PageCursor {
+            let mut page: Page<T> = client
+                .get(&format!("{req}/page/0/{until}/{limit}"))
+                .send()
+                .await
+                .unwrap();
+            let mut heights = vec![];
+            let mut last_cursor = None;
+            loop {
+                assert!(!page.items.is_empty() && page.items.len() <= limit);
+                heights.extend(page.items.iter().map(|item| item.height() as usize));
+                let Some(cursor) = page.next_cursor else {
+                    break;
+                };
+                page = client
+                    .get(&format!("{req}/page/{cursor}/{limit}"))
+                    .send()
+                    .await
+                    .unwrap();
+                last_cursor = Some(cursor);
+            }
+            assert_eq!(heights, (0..until).collect::<Vec<_>>());
+            last_cursor.unwrap()
+        }
+
+        let cursor = paginate::<LeafQueryData<MockTypes>>(&client, "leaf", until, 3).await;
+        paginate::<BlockQueryData<MockTypes>>(&client, "block", until, 2).await;
+        paginate::<PayloadQueryData<MockTypes>>(&client, "payload", until, 2).await;
+        paginate::<BlockSummaryQueryData<MockTypes>>(&client, "block/summaries", until, 2).await;
+
+        // Headers don't know their own height, so check them separately.
+        let page: Page<Header<MockTypes>> = client
+            .get(&format!("header/page/0/{until}/2"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.items[1].block_number, 1);
+        let page: Page<Header<MockTypes>> = client
+            .get(&format!("header/page/{}/2", page.next_cursor.unwrap()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].block_number, 2);
+
+        // A page may not exceed the range limit.
+        let err = client
+            .get::<Page<LeafQueryData<MockTypes>>>(&format!("leaf/page/0/{until}/4"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        // A cursor is only valid for the endpoint which issued it.
+        let err = client
+            .get::<Page<BlockQueryData<MockTypes>>>(&format!("block/page/{cursor}/2"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        network.shut_down().await;
+    }
+
+    // This test runs the `postgres` Docker image, which doesn't work on Windows.
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pruned_payload_gone() {
+        use crate::{
+            data_source::{
+                sql::testing::TmpDb,
+                storage::{
+                    pruning::{HeightWindowPolicy, PrunedHeightStorage, PrunerCfg},
+                    UpdateAvailabilityStorage,
+                },
+                Transaction as _, VersionedDataSource,
+            },
+            fetching::provider::NoFetching,
+        };
+        use hotshot_example_types::node_types::TestVersions;
+        use tokio::time::sleep;
+
+        setup_test();
+
+        // Start a data source which keeps only the payload of the latest block.
+        let db = TmpDb::init().await;
+        let data_source = db
+            .config()
+            .pruner_cfg(
+                PrunerCfg::new()
+                    .with_prune_policy(HeightWindowPolicy::new().with_payload_window(1))
+                    .with_minimum_retention(Duration::ZERO)
+                    .with_interval(Duration::from_millis(100)),
+            )
+            .unwrap()
+            .connect(NoFetching)
+            .await
+            .unwrap();
+
+        // Mock up a short chain.
+        let mut leaf = Leaf::<MockTypes>::genesis(&Default::default(), &Default::default()).await;
+        let mut qc =
+            QuorumCertificate::genesis::<TestVersions>(&Default::default(), &Default::default())
+                .await;
+        let mut tx = data_source.write().await.unwrap();
+        for i in 0..3 {
+            leaf.block_header_mut().block_number = i;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let leaf = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(BlockQueryData::new(
+                leaf.header().clone(),
+                MockPayload::genesis(),
+            ))
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        // Wait for the pruner to discard the older payloads.
+        while data_source
+            .read()
+            .await
+            .unwrap()
+            .load_payload_pruned_height()
+            .await
+            .unwrap()
+            != Some(1)
+        {
+            tracing::info!("waiting for pruner");
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(RwLock::new(data_source));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        let _server = BackgroundTask::spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{port}/availability")
+                .parse()
+                .unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        // Pruned payloads are gone for good, rather than not found.
+        for i in 0..2 {
+            let err = client
+                .get::<BlockQueryData<MockTypes>>(&format!("block/{i}"))
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::GONE, "{err:#}");
+            let err = client
+                .get::<PayloadQueryData<MockTypes>>(&format!("payload/{i}"))
+                .send()
+                .await
+                .unwrap_err();
+            assert_eq!(err.status(), StatusCode::GONE, "{err:#}");
+
+            // The leaves and headers are still available.
+            let leaf: LeafQueryData<MockTypes> =
+                client.get(&format!("leaf/{i}")).send().await.unwrap();
+            assert_eq!(leaf.height(), i);
+            client
+                .get::<MockHeader>(&format!("header/{i}"))
+                .send()
+                .await
+                .unwrap();
+        }
+        let block: BlockQueryData<MockTypes> = client.get("block/2").send().await.unwrap();
+        assert_eq!(block.height(), 2);
+
+        // A block which does not exist yet is still reported as not found.
+        let err = client
+            .get::<BlockQueryData<MockTypes>>("block/3")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND, "{err:#}");
+    }
 }
@@ -13,21 +13,28 @@
 use super::{
     fetch::Fetch,
     query_data::{
-        BlockHash, BlockQueryData, LeafHash, LeafQueryData, PayloadMetadata, PayloadQueryData,
-        QueryablePayload, TransactionHash, TransactionQueryData, VidCommonMetadata,
-        VidCommonQueryData,
+        BlockHash, BlockQueryData, BlockSummaryQueryData, DecideNotification, LeafHash,
+        LeafQueryData, NamespaceId, NamespacedPayload, PayloadByteRange, PayloadMetadata,
+        PayloadQueryData, QueryableHeader, QueryablePayload, RawPayloadQueryData, TransactionHash,
+        TransactionQueryData, VidCommonMetadata, VidCommonQueryData,
     },
 };
-use crate::{types::HeightIndexed, Payload, VidCommitment, VidShare};
+use crate::{
+    types::HeightIndexed, Header, Payload, QueryError, QueryResult, VidCommitment, VidShare,
+};
 use async_trait::async_trait;
 use derivative::Derivative;
 use derive_more::{Display, From};
 use futures::{
     future::Future,
-    stream::{BoxStream, Stream, StreamExt},
+    stream::{self, BoxStream, Stream, StreamExt},
+};
+use hotshot_types::{simple_certificate::QuorumCertificate, traits::node_implementation::NodeType};
+use std::{
+    cmp::Ordering,
+    ops::{Bound, Range, RangeBounds},
+    time::Duration,
 };
-use hotshot_types::traits::node_implementation::NodeType;
-use std::{cmp::Ordering, ops::RangeBounds};
 
 #[derive(Derivative, From, Display)]
 #[derivative(Ord = "feature_allow_slow_enum")]
@@ -73,6 +80,11 @@ pub enum BlockId<Types: NodeType> {
     Number(usize),
     #[display(fmt = "{_0}")]
     Hash(BlockHash<Types>),
+    /// A block identified by the commitment to its payload.
+    ///
+    /// Payload commitments are not necessarily unique: for example, every empty block has the same
+    /// payload commitment. When several blocks share a payload commitment, lookups by this ID
+    /// resolve to the block with the lowest height.
     #[display(fmt = "{_0}")]
     #[from(ignore)]
     PayloadHash(VidCommitment),
@@ -151,6 +163,18 @@ where
     where
         ID: Into<LeafId<Types>> + Send + Sync;
 
+    /// Get the quorum certificate signing a leaf.
+    ///
+    /// This is the [`qc`](LeafQueryData::qc) of the result of [`get_leaf`](Self::get_leaf), which
+    /// is what the default implementation returns. It lets clients which only verify signatures
+    /// avoid downloading the rest of the leaf.
+    async fn get_qc<ID>(&self, id: ID) -> Fetch<QuorumCertificate<Types>>
+    where
+        ID: Into<LeafId<Types>> + Send + Sync,
+    {
+        self.get_leaf(id).await.map(|leaf| leaf.qc().clone())
+    }
+
     async fn get_block<ID>(&self, id: ID) -> Fetch<BlockQueryData<Types>>
     where
         ID: Into<BlockId<Types>> + Send + Sync;
@@ -163,6 +187,93 @@ where
     where
         ID: Into<BlockId<Types>> + Send + Sync;
 
+    /// Get the encoded bytes of a block payload.
+    ///
+    /// This is equivalent to encoding the result of [`get_payload`](Self::get_payload), which is
+    /// what the default implementation does. Data sources which store payloads in encoded form
+    /// should override it to return the stored bytes without decoding them.
+    async fn get_raw_payload<ID>(&self, id: ID) -> Fetch<RawPayloadQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.get_payload(id).await.map(RawPayloadQueryData::from)
+    }
+
+    /// Get a range of the encoded bytes of a block payload, if the payload is available locally.
+    ///
+    /// The range is clamped to the length of the encoded payload. This lets clients download a
+    /// large payload in pieces without the data source loading all of it for each piece. Unlike
+    /// [`get_raw_payload`](Self::get_raw_payload), this does not fetch a missing payload; if the
+    /// payload is not stored locally, it fails with [`QueryError::NotFound`] or
+    /// [`QueryError::Missing`], and the caller can fall back to fetching the whole payload. The
+    /// default implementation always fails with [`QueryError::Missing`].
+    async fn get_payload_bytes<ID>(
+        &self,
+        id: ID,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        let _ = (id, range);
+        Err(QueryError::Missing)
+    }
+
+    /// Get the header of a block along with the size and transaction count of its payload.
+    ///
+    /// This is equivalent to converting the result of [`get_block`](Self::get_block) into a
+    /// [`BlockSummaryQueryData`], which is what the default implementation does. Data sources
+    /// which store the payload size and transaction count separately from the payload should
+    /// override it to avoid loading and decoding the whole payload.
+    async fn get_block_summary<ID>(&self, id: ID) -> Fetch<BlockSummaryQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.get_block(id).await.map(BlockSummaryQueryData::from)
+    }
+
+    /// Get the header of a block.
+    ///
+    /// This is equivalent to taking the header from the result of [`get_block`](Self::get_block),
+    /// which is what the default implementation does. A header is available as soon as the
+    /// corresponding leaf is, so data sources should override this to load only the header, without
+    /// the payload, and without waiting for the payload to be fetched.
+    async fn get_header<ID>(&self, id: ID) -> Fetch<Header<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.get_block(id).await.map(|block| block.header().clone())
+    }
+
+    /// The height of the earliest leaf which has not been pruned.
+    ///
+    /// Leaves below this height have been deleted and will never be available from this data
+    /// source. This is cheap to call: data sources which prune track this watermark as the pruner
+    /// runs, rather than searching storage for the lowest height. The default implementation, for
+    /// data sources which never prune, returns 0.
+    async fn earliest_height(&self) -> usize {
+        0
+    }
+
+    /// The height of the earliest block whose payload has not been pruned.
+    ///
+    /// This is at least [`earliest_height`](Self::earliest_height), and may be greater if payloads
+    /// are pruned more aggressively than leaves. The default implementation returns
+    /// [`earliest_height`](Self::earliest_height).
+    async fn earliest_block_height(&self) -> usize {
+        self.earliest_height().await
+    }
+
+    /// Get the earliest leaf which has not been pruned.
+    async fn first_available_leaf(&self) -> Fetch<LeafQueryData<Types>> {
+        self.get_leaf(self.earliest_height().await).await
+    }
+
+    /// Get the earliest block whose payload has not been pruned.
+    async fn first_available_block(&self) -> Fetch<BlockQueryData<Types>> {
+        self.get_block(self.earliest_block_height().await).await
+    }
+
     async fn get_vid_common<ID>(&self, id: ID) -> Fetch<VidCommonQueryData<Types>>
     where
         ID: Into<BlockId<Types>> + Send + Sync;
@@ -171,6 +282,46 @@ where
     where
         ID: Into<BlockId<Types>> + Send + Sync;
 
+    /// Get the VID share belonging to the storage node with the given `index`.
+    ///
+    /// Unlike most objects, VID shares are not fetched from peers if they are missing: each share
+    /// is only ever sent to the node it belongs to, and only some shares are persisted. If the
+    /// requested share was not stored, this returns [`QueryError::Missing`]. The default
+    /// implementation always does so.
+    async fn get_vid_share<ID>(&self, id: ID, index: usize) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        let _ = (id, index);
+        Err(QueryError::Missing)
+    }
+
+    /// Get the leaf proposed in the given consensus view.
+    ///
+    /// The QC stored with each leaf certifies that leaf in the view in which it was proposed, so
+    /// this is also the leaf directly certified by a QC for `view`. Not every view produces a
+    /// leaf: if `view` failed or was skipped, this resolves to `None` once a later view has been
+    /// decided. If no view at or after `view` has been decided yet, the fetch stays pending until
+    /// one is.
+    ///
+    /// Only leaves which are stored locally are found. While this node is missing some leaves, for
+    /// example because it is still syncing, a view whose leaf has not been fetched yet also
+    /// resolves to `None`. The default implementation does not index leaves by view, and always
+    /// resolves to `None`.
+    async fn get_leaf_by_view(&self, view: Types::View) -> Fetch<Option<LeafQueryData<Types>>> {
+        let _ = view;
+        Fetch::Ready(None)
+    }
+
+    /// Get a range of leaves in ascending order of height.
+    ///
+    /// The range is not loaded all at once. Instead, the returned stream pages through storage in
+    /// chunks, loading the next chunk only once the consumer has polled every item of the previous
+    /// one, so it is safe to request very large (or unbounded) ranges. Leaves which are not
+    /// available locally are yielded as pending [`Fetch`]es, and trigger a fetch from a provider
+    /// just like [`get_leaf`](Self::get_leaf).
+    ///
+    /// The other range getters behave the same way for their respective objects.
     async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
     where
         R: RangeBounds<usize> + Send + 'static;
@@ -187,6 +338,24 @@ where
     where
         R: RangeBounds<usize> + Send + 'static;
 
+    /// Get a range of block summaries in ascending order of height.
+    ///
+    /// Like [`get_block_summary`](Self::get_block_summary), the default implementation is based
+    /// on [`get_block_range`](Self::get_block_range), and may be overridden with a more efficient
+    /// one.
+    async fn get_block_summary_range<R>(
+        &self,
+        range: R,
+    ) -> BoxStream<'static, Fetch<BlockSummaryQueryData<Types>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.get_block_range(range)
+            .await
+            .map(|fetch| fetch.map(BlockSummaryQueryData::from))
+            .boxed()
+    }
+
     async fn get_vid_common_range<R>(&self, range: R) -> Self::VidCommonRange<R>
     where
         R: RangeBounds<usize> + Send + 'static;
@@ -195,12 +364,88 @@ where
     where
         R: RangeBounds<usize> + Send + 'static;
 
+    /// Get a range of leaves in descending order of height.
+    ///
+    /// The stream starts with the leaf at height `end` (inclusive) and ends with the leaf at
+    /// `start`. If `end` is at or beyond the current block height, the stream starts with the most
+    /// recent leaf instead, so that `get_leaf_range_rev(Bound::Unbounded, usize::MAX)` combined
+    /// with [`take`](StreamExt::take) can be used to load the latest `n` leaves without knowing
+    /// the block height in advance. If `start` is after `end`, the stream is empty.
+    async fn get_leaf_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<LeafQueryData<Types>>>;
+
+    /// Get a range of blocks in descending order of height.
+    ///
+    /// This has the same semantics as [`get_leaf_range_rev`](Self::get_leaf_range_rev).
+    async fn get_block_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<BlockQueryData<Types>>>;
+
+    /// Get all leaves whose header timestamp is in the range `[start, end)`, in order of height.
+    ///
+    /// Timestamps are in seconds since the Unix epoch. Each leaf is returned alongside its height,
+    /// so the caller can fetch the corresponding block or other data.
+    ///
+    /// The result is filtered by the timestamp field of each header. HotShot timestamps are not
+    /// strictly monotonic (they can occasionally go backwards slightly), so the result is not
+    /// guaranteed to be a contiguous range of heights. Unlike the range getters, this only returns
+    /// leaves which are available locally; it does not trigger fetches for missing data.
+    ///
+    /// The default implementation always fails, since this requires storage indexed by timestamp.
+    async fn get_leaf_range_by_time(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        let _ = (start, end);
+        Err(QueryError::Error {
+            message: "this data source does not support querying leaves by time".into(),
+        })
+    }
+
+    /// Get summaries of all blocks whose header timestamp is in the range `[start, end)`.
+    ///
+    /// Timestamps are in seconds since the Unix epoch. The blocks are found with
+    /// [`get_leaf_range_by_time`](Self::get_leaf_range_by_time), so, like that method, this
+    /// returns every block whose own timestamp falls in the range, in order of height, even when
+    /// timestamps regress and the matching heights are not contiguous. Payloads which are missing
+    /// are fetched like those of any other block summary.
+    async fn get_blocks_in_time_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<BoxStream<'static, Fetch<BlockSummaryQueryData<Types>>>> {
+        let leaves = self.get_leaf_range_by_time(start, end).await?;
+        let mut summaries = Vec::with_capacity(leaves.len());
+        for (height, _) in leaves {
+            summaries.push(self.get_block_summary(height as usize).await);
+        }
+        Ok(stream::iter(summaries).boxed())
+    }
+
     /// Returns the transaction with the given `hash`.
     async fn get_transaction(
         &self,
         hash: TransactionHash<Types>,
     ) -> Fetch<TransactionQueryData<Types>>;
 
+    /// Check whether a transaction with the given `hash` might be known to this data source.
+    ///
+    /// This is a cheap check which never yields a false negative: if it returns `false`, no block
+    /// stored by this data source contains the transaction, and a call to
+    /// [`get_transaction`](Self::get_transaction) would not find it locally. A `true` result may be
+    /// a false positive, which can be resolved with [`get_transaction`](Self::get_transaction).
+    ///
+    /// The default implementation always returns `true`.
+    async fn transaction_maybe_exists(&self, _hash: TransactionHash<Types>) -> bool {
+        true
+    }
+
     async fn subscribe_blocks(&self, from: usize) -> BoxStream<'static, BlockQueryData<Types>> {
         self.get_block_range(from..)
             .await
@@ -251,6 +496,52 @@ where
             .then(Fetch::resolve)
             .boxed()
     }
+
+    /// Subscribe to notifications of decided blocks, starting with the block at `from`.
+    ///
+    /// Blocks which have already been stored are replayed first, in order, after which the stream
+    /// follows the chain, yielding each block once it has been stored by the same path which
+    /// updates the data source. Thus, a client which reconnects at the height after the last
+    /// notification it received will not miss any blocks.
+    async fn subscribe_decides(&self, from: usize) -> BoxStream<'static, DecideNotification<Types>>
+    where
+        Header<Types>: QueryableHeader<Types>,
+    {
+        self.subscribe_blocks(from)
+            .await
+            .map(DecideNotification::from)
+            .boxed()
+    }
+
+    /// Subscribe to the transactions in a given namespace, starting with the block at `from`.
+    ///
+    /// The stream yields transactions in the order they are sequenced. Blocks which contain no
+    /// transactions in `namespace` are skipped entirely.
+    async fn subscribe_namespace_transactions(
+        &self,
+        namespace: NamespaceId<Types>,
+        from: usize,
+    ) -> BoxStream<'static, TransactionQueryData<Types>>
+    where
+        Payload<Types>: NamespacedPayload<Types>,
+    {
+        self.subscribe_blocks(from)
+            .await
+            .flat_map(move |block| {
+                let indices = block.payload().iter(block.metadata()).collect::<Vec<_>>();
+                let transactions = block
+                    .payload()
+                    .namespaces(block.metadata())
+                    .filter(|(ns, _)| *ns == namespace)
+                    .flat_map(|(_, range)| range)
+                    .filter_map(|i| {
+                        TransactionQueryData::with_index(&block, indices.get(i)?.clone(), i as u64)
+                    })
+                    .collect::<Vec<_>>();
+                stream::iter(transactions)
+            })
+            .boxed()
+    }
 }
 
 /// Information about a block.
@@ -300,4 +591,85 @@ impl<Types: NodeType> BlockInfo<Types> {
 pub trait UpdateAvailabilityData<Types: NodeType> {
     /// Append information about a new block to the database.
     fn append(&self, info: BlockInfo<Types>) -> impl Send + Future<Output = anyhow::Result<()>>;
+
+    /// Append information about a chain of consecutive blocks to the database.
+    ///
+    /// This is equivalent to calling [`append`](Self::append) for each block in order, but data
+    /// sources may override it to store the whole chain atomically and with less overhead, which
+    /// is useful when replaying a long history of blocks, e.g. from an archive. If this fails, none
+    /// of the chain should be considered stored.
+    fn append_chain<I>(&self, chain: I) -> impl Send + Future<Output = anyhow::Result<()>>
+    where
+        Self: Sync,
+        I: IntoIterator<Item = BlockInfo<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        async move {
+            for info in chain {
+                self.append(info).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// The number of storage nodes used for VID dispersal of the genesis block, if known.
+    ///
+    /// HotShot does not run VID for the genesis block, so
+    /// [`UpdateDataSource`](crate::data_source::UpdateDataSource) computes the genesis VID data
+    /// itself, which requires the VID parameters the network was configured with. If this returns
+    /// `None`,
+    /// [`GENESIS_VID_NUM_STORAGE_NODES`](hotshot_types::traits::block_contents::GENESIS_VID_NUM_STORAGE_NODES)
+    /// is assumed.
+    fn genesis_vid_num_storage_nodes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Record how long it took to process a consensus event and store its decided blocks.
+    ///
+    /// [`UpdateDataSource`](crate::data_source::UpdateDataSource) calls this after each successful
+    /// update, with the time elapsed between receiving the event and committing the blocks to
+    /// storage. The default implementation does nothing.
+    fn record_update_latency(&self, latency: Duration) {
+        let _ = latency;
+    }
+
+    /// Record decided blocks which were stored without VID data.
+    ///
+    /// [`UpdateDataSource`](crate::data_source::UpdateDataSource) calls this after each successful
+    /// update, with the number of non-genesis blocks whose VID data was not available at decide.
+    /// Blocks appended by other means, for example after being fetched, are not counted. The
+    /// default implementation does nothing.
+    fn record_missing_vid_at_decide(&self, count: usize) {
+        let _ = count;
+    }
+
+    /// Check a decided block payload before it is stored.
+    ///
+    /// [`UpdateDataSource`](crate::data_source::UpdateDataSource) calls this for each decided
+    /// block whose payload is available. If it fails, the payload is not stored, while the leaf
+    /// still is, and the height is reported in
+    /// [`UpdateSummary::rejected_payloads`](crate::data_source::UpdateSummary::rejected_payloads).
+    /// The default implementation accepts every payload.
+    fn validate_payload(
+        &self,
+        header: &Header<Types>,
+        payload: &Payload<Types>,
+    ) -> anyhow::Result<()> {
+        let _ = (header, payload);
+        Ok(())
+    }
+
+    /// Record that the payloads at `heights` were rejected by
+    /// [`validate_payload`](Self::validate_payload).
+    ///
+    /// [`UpdateDataSource`](crate::data_source::UpdateDataSource) calls this after storing the
+    /// leaves of blocks whose payloads it rejected, so that data sources which fetch missing
+    /// payloads do not fetch the rejected ones again. The default implementation does nothing.
+    fn reject_payloads(
+        &self,
+        heights: Vec<u64>,
+    ) -> impl Send + Future<Output = anyhow::Result<()>> {
+        let _ = heights;
+        async move { Ok(()) }
+    }
 }
@@ -10,7 +10,8 @@
 // You should have received a copy of the GNU General Public License along with this program. If not,
 // see <https://www.gnu.org/licenses/>.
 
-use futures::future::{BoxFuture, FutureExt};
+use crate::QueryError;
+use futures::future::{self, BoxFuture, Either, FutureExt};
 use snafu::{Error, ErrorCompat, IntoError, NoneError, OptionExt};
 use std::{future::IntoFuture, time::Duration};
 use tokio::time::timeout;
@@ -23,7 +24,10 @@ use tokio::time::timeout;
 ///
 /// For data that is already available locally, a request for that data may return [`Fetch::Ready`]
 /// with the data itself. Otherwise, the request will return [`Fetch::Pending`] with a future that
-/// will resolve once the data is available.
+/// will resolve once the data is available. If it is known that the data will never be available,
+/// for example because it has been pruned, the request will return [`Fetch::Failed`] with the reason.
+/// If the data source is configured to give up on retrieving data from a provider, the request may
+/// instead return [`Fetch::Abandonable`], which is like [`Fetch::Pending`] but may fail later.
 ///
 /// Depending on the context, [`Fetch`] can behave a bit like a [`Future`](futures::Future) or a bit
 /// like a [`Result`]. Therefore, it implements [`IntoFuture`], so it can be awaited (this is the
@@ -34,6 +38,18 @@ use tokio::time::timeout;
 pub enum Fetch<T> {
     Ready(T),
     Pending(BoxFuture<'static, T>),
+    /// The requested data is not available yet, and we may give up on retrieving it.
+    ///
+    /// The first future resolves once the data is available. The second resolves if we give up on
+    /// retrieving the data, with the reason. Awaiting this fetch only waits for the data, which may
+    /// still arrive after we have given up, for example from consensus. Use
+    /// [`try_with_timeout`](Self::try_with_timeout) to observe the error.
+    Abandonable(BoxFuture<'static, T>, BoxFuture<'static, QueryError>),
+    /// The requested data will never be available.
+    ///
+    /// Awaiting a failed fetch never completes, just like awaiting a fetch for data which does not
+    /// exist. Use [`try_with_timeout`](Self::try_with_timeout) to observe the error.
+    Failed(QueryError),
 }
 
 impl<T> Fetch<T> {
@@ -44,7 +60,7 @@ impl<T> Fetch<T> {
     pub fn try_resolve(self) -> Result<T, Self> {
         match self {
             Self::Ready(obj) => Ok(obj),
-            Self::Pending(fut) => Err(Self::Pending(fut)),
+            fetch => Err(fetch),
         }
     }
 
@@ -75,7 +91,15 @@ impl<T> Fetch<T> {
 
     /// Does this fetch represent an unresolved query?
     pub fn is_pending(&self) -> bool {
-        matches!(self, Self::Pending(_))
+        matches!(self, Self::Pending(_) | Self::Abandonable(..))
+    }
+
+    /// The reason the requested data will never be available, if this fetch has failed.
+    pub fn error(&self) -> Option<&QueryError> {
+        match self {
+            Self::Failed(err) => Some(err),
+            _ => None,
+        }
     }
 }
 
@@ -88,9 +112,34 @@ impl<T: Send + 'static> Fetch<T> {
     /// Wait for the requested data to become available, but only for up to `timeout`.
     ///
     /// This function is similar to [`resolve`](Self::resolve), but if the future does not resolve
-    /// within `timeout`, then [`with_timeout`](Self::with_timeout) will resolve with [`None`].
+    /// within `timeout`, then [`with_timeout`](Self::with_timeout) will resolve with [`None`]. If the
+    /// fetch has [failed](Self::Failed), this resolves with [`None`] immediately.
     pub async fn with_timeout(self, timeout_duration: Duration) -> Option<T> {
-        timeout(timeout_duration, self.into_future()).await.ok()
+        self.try_with_timeout(timeout_duration).await.ok().flatten()
+    }
+
+    /// Wait for the requested data to become available, but only for up to `timeout`.
+    ///
+    /// This function is similar to [`with_timeout`](Self::with_timeout), but if the fetch has
+    /// [failed](Self::Failed), it fails immediately with the reason the data will never be
+    /// available. Likewise, if we give up on an [abandonable](Self::Abandonable) fetch within
+    /// `timeout`, this fails with the reason.
+    pub async fn try_with_timeout(
+        self,
+        timeout_duration: Duration,
+    ) -> Result<Option<T>, QueryError> {
+        match self {
+            Self::Ready(obj) => Ok(Some(obj)),
+            Self::Pending(fut) => Ok(timeout(timeout_duration, fut).await.ok()),
+            Self::Abandonable(fut, failed) => {
+                match timeout(timeout_duration, future::select(fut, failed)).await {
+                    Ok(Either::Left((obj, _))) => Ok(Some(obj)),
+                    Ok(Either::Right((err, _))) => Err(err),
+                    Err(_) => Ok(None),
+                }
+            }
+            Self::Failed(err) => Err(err),
+        }
     }
 }
 
@@ -106,6 +155,8 @@ impl<T: 'static> Fetch<T> {
         match self {
             Self::Ready(obj) => Fetch::Ready(f(obj)),
             Self::Pending(fut) => Fetch::Pending(fut.map(f).boxed()),
+            Self::Abandonable(fut, failed) => Fetch::Abandonable(fut.map(f).boxed(), failed),
+            Self::Failed(err) => Fetch::Failed(err),
         }
     }
 }
@@ -118,7 +169,8 @@ impl<T: Send + 'static> IntoFuture for Fetch<T> {
         async move {
             match self {
                 Self::Ready(obj) => obj,
-                Self::Pending(fut) => fut.await,
+                Self::Pending(fut) | Self::Abandonable(fut, _) => fut.await,
+                Self::Failed(_) => future::pending::<T>().await,
             }
         }
         .boxed()
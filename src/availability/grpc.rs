@@ -0,0 +1,391 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! gRPC transport for the availability API.
+//!
+//! This module exposes a subset of the availability API over a [tonic] service, for clients which
+//! prefer gRPC to HTTP. The service is backed by the same [`AvailabilityDataSource`] as the HTTP
+//! API, and it is constructed from the same [`ReadState`] handle (such as
+//! [`ApiState`](crate::ApiState)), so that both servers can run concurrently against one store.
+//!
+//! Query data is returned as bincode-encoded bytes rather than native protobuf messages, so that
+//! the consensus types do not have to be re-specified in protobuf. See `proto/availability.proto`
+//! for the service definition.
+//!
+//! # Example
+//!
+//! ```
+//! # use hotshot_query_service::{
+//! #   availability::{grpc::AvailabilityService, AvailabilityDataSource},
+//! #   testing::mocks::MockTypes,
+//! #   ApiState,
+//! # };
+//! # async fn doc<D>(data_source: D) -> anyhow::Result<()>
+//! # where
+//! #   D: AvailabilityDataSource<MockTypes> + Send + Sync + 'static,
+//! # {
+//! let state = ApiState::from(data_source);
+//!
+//! // The same `state` can be passed to `App::with_state` to serve the HTTP API.
+//! tonic::transport::Server::builder()
+//!     .add_service(AvailabilityService::<_, MockTypes>::new(state.clone()).into_server())
+//!     .serve("0.0.0.0:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![cfg(feature = "grpc")]
+
+use super::{
+    AvailabilityDataSource, BlockId, BlockQueryData, LeafId, LeafQueryData, QueryablePayload,
+    VidCommonQueryData,
+};
+use crate::{types::HeightIndexed, Payload};
+use futures::{
+    future::FutureExt,
+    stream::{BoxStream, StreamExt},
+};
+use hotshot_types::traits::node_implementation::NodeType;
+use serde::Serialize;
+use std::{fmt::Display, marker::PhantomData, time::Duration};
+use tagged_base64::TaggedBase64;
+use tide_disco::method::ReadState;
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/availability.proto`.
+pub mod proto {
+    tonic::include_proto!("hotshot_query_service.availability");
+}
+
+use proto::availability_server::{Availability, AvailabilityServer};
+
+/// gRPC implementation of the availability API.
+#[derive(Clone, Debug)]
+pub struct AvailabilityService<State, Types> {
+    state: State,
+    fetch_timeout: Duration,
+    _types: PhantomData<fn() -> Types>,
+}
+
+impl<State, Types> AvailabilityService<State, Types> {
+    /// Serve the availability API from `state`.
+    pub fn new(state: State) -> Self {
+        Self {
+            state,
+            fetch_timeout: super::Options::default().fetch_timeout,
+            _types: Default::default(),
+        }
+    }
+
+    /// Set the timeout for failing requests due to missing data.
+    ///
+    /// This is analogous to [`Options::fetch_timeout`](super::Options::fetch_timeout) for the HTTP
+    /// API.
+    pub fn with_fetch_timeout(mut self, timeout: Duration) -> Self {
+        self.fetch_timeout = timeout;
+        self
+    }
+
+    /// Wrap this service in a server which can be added to a [`tonic`] router.
+    pub fn into_server(self) -> AvailabilityServer<Self>
+    where
+        Self: Availability,
+    {
+        AvailabilityServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<State, Types> Availability for AvailabilityService<State, Types>
+where
+    Types: NodeType,
+    State: 'static + Send + Sync + ReadState,
+    <State as ReadState>::State: Send + Sync + AvailabilityDataSource<Types>,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    type SubscribeLeavesStream = BoxStream<'static, Result<proto::Leaf, Status>>;
+    type SubscribeBlocksStream = BoxStream<'static, Result<proto::Block, Status>>;
+
+    async fn get_leaf(&self, req: Request<proto::LeafId>) -> Result<Response<proto::Leaf>, Status> {
+        let id = match req.into_inner().id {
+            Some(proto::leaf_id::Id::Height(height)) => LeafId::Number(height as usize),
+            Some(proto::leaf_id::Id::Hash(hash)) => LeafId::Hash(parse_hash(&hash)?),
+            None => return Err(Status::invalid_argument("missing leaf ID")),
+        };
+        let fetch = self.state.read(|state| state.get_leaf(id).boxed()).await;
+        let leaf = fetch
+            .with_timeout(self.fetch_timeout)
+            .await
+            .ok_or_else(|| not_found("leaf", id))?;
+        Ok(Response::new(encode_leaf(&leaf)?))
+    }
+
+    async fn get_block(
+        &self,
+        req: Request<proto::BlockId>,
+    ) -> Result<Response<proto::Block>, Status> {
+        let id = block_id(req.into_inner())?;
+        let fetch = self.state.read(|state| state.get_block(id).boxed()).await;
+        let block = fetch
+            .with_timeout(self.fetch_timeout)
+            .await
+            .ok_or_else(|| not_found("block", id))?;
+        Ok(Response::new(encode_block(&block)?))
+    }
+
+    async fn get_vid_common(
+        &self,
+        req: Request<proto::BlockId>,
+    ) -> Result<Response<proto::VidCommon>, Status> {
+        let id = block_id(req.into_inner())?;
+        let fetch = self
+            .state
+            .read(|state| state.get_vid_common(id).boxed())
+            .await;
+        let common = fetch
+            .with_timeout(self.fetch_timeout)
+            .await
+            .ok_or_else(|| not_found("VID common", id))?;
+        Ok(Response::new(encode_vid_common(&common)?))
+    }
+
+    async fn subscribe_leaves(
+        &self,
+        req: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeLeavesStream>, Status> {
+        let from = req.into_inner().from as usize;
+        let leaves = self
+            .state
+            .read(|state| state.subscribe_leaves(from).boxed())
+            .await;
+        Ok(Response::new(leaves.map(|leaf| encode_leaf(&leaf)).boxed()))
+    }
+
+    async fn subscribe_blocks(
+        &self,
+        req: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let from = req.into_inner().from as usize;
+        let blocks = self
+            .state
+            .read(|state| state.subscribe_blocks(from).boxed())
+            .await;
+        Ok(Response::new(
+            blocks.map(|block| encode_block(&block)).boxed(),
+        ))
+    }
+}
+
+fn block_id<Types: NodeType>(id: proto::BlockId) -> Result<BlockId<Types>, Status> {
+    match id.id {
+        Some(proto::block_id::Id::Height(height)) => Ok(BlockId::Number(height as usize)),
+        Some(proto::block_id::Id::Hash(hash)) => Ok(BlockId::Hash(parse_hash(&hash)?)),
+        Some(proto::block_id::Id::PayloadHash(hash)) => {
+            Ok(BlockId::PayloadHash(parse_hash(&hash)?))
+        }
+        None => Err(Status::invalid_argument("missing block ID")),
+    }
+}
+
+fn parse_hash<T>(hash: &str) -> Result<T, Status>
+where
+    T: TryFrom<TaggedBase64>,
+    <T as TryFrom<TaggedBase64>>::Error: Display,
+{
+    TaggedBase64::parse(hash)
+        .map_err(|err| Status::invalid_argument(format!("malformed hash {hash}: {err}")))?
+        .try_into()
+        .map_err(|err| Status::invalid_argument(format!("invalid hash {hash}: {err}")))
+}
+
+fn not_found(resource: &str, id: impl Display) -> Status {
+    Status::not_found(format!("{resource} {id} not available"))
+}
+
+fn encode<T: Serialize>(obj: &T) -> Result<Vec<u8>, Status> {
+    bincode::serialize(obj).map_err(|err| Status::internal(format!("encoding response: {err}")))
+}
+
+fn encode_leaf<Types: NodeType>(leaf: &LeafQueryData<Types>) -> Result<proto::Leaf, Status> {
+    Ok(proto::Leaf {
+        height: leaf.height(),
+        hash: leaf.hash().to_string(),
+        data: encode(leaf)?,
+    })
+}
+
+fn encode_block<Types>(block: &BlockQueryData<Types>) -> Result<proto::Block, Status>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    Ok(proto::Block {
+        height: block.height(),
+        hash: block.hash().to_string(),
+        payload_hash: block.payload_hash().to_string(),
+        data: encode(block)?,
+    })
+}
+
+fn encode_vid_common<Types: NodeType>(
+    common: &VidCommonQueryData<Types>,
+) -> Result<proto::VidCommon, Status> {
+    Ok(proto::VidCommon {
+        height: common.height(),
+        block_hash: common.block_hash().to_string(),
+        payload_hash: common.payload_hash().to_string(),
+        data: encode(common)?,
+    })
+}
+
+// These tests run the `postgres` Docker image, which doesn't work on Windows.
+#[cfg(all(test, not(target_os = "windows")))]
+mod test {
+    use super::{
+        proto::{availability_client::AvailabilityClient, block_id, leaf_id},
+        *,
+    };
+    use crate::{
+        availability::define_api,
+        task::BackgroundTask,
+        testing::{
+            consensus::{MockDataSource, MockNetwork},
+            mocks::{MockBase, MockTypes},
+            setup_test, sleep,
+        },
+        ApiState, Error,
+    };
+    use portpicker::pick_unused_port;
+    use tide_disco::App;
+    use vbs::version::StaticVersionType;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_grpc_alongside_http() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        let state = ApiState::from(network.data_source());
+
+        // Serve both the HTTP and gRPC APIs from the same data source.
+        let http_port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(state.clone());
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "HTTP server",
+            app.serve(format!("0.0.0.0:{http_port}"), MockBase::instance()),
+        );
+        let grpc_port = pick_unused_port().unwrap();
+        let _grpc = BackgroundTask::spawn(
+            "gRPC server",
+            tonic::transport::Server::builder()
+                .add_service(AvailabilityService::<_, MockTypes>::new(state).into_server())
+                .serve(format!("0.0.0.0:{grpc_port}").parse().unwrap()),
+        );
+        network.start().await;
+
+        // Wait for the gRPC server to come up.
+        let mut client = loop {
+            match AvailabilityClient::connect(format!("http://localhost:{grpc_port}")).await {
+                Ok(client) => break client,
+                Err(err) => {
+                    tracing::info!("waiting for gRPC server: {err}");
+                    sleep(Duration::from_millis(100)).await;
+                }
+            }
+        };
+
+        // Stream a few leaves and blocks, and check that they match what we get from the individual
+        // endpoints, both by height and by hash.
+        let mut leaves = client
+            .subscribe_leaves(proto::SubscribeRequest { from: 0 })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut blocks = client
+            .subscribe_blocks(proto::SubscribeRequest { from: 0 })
+            .await
+            .unwrap()
+            .into_inner();
+        for i in 0..3 {
+            let leaf = leaves.next().await.unwrap().unwrap();
+            let block = blocks.next().await.unwrap().unwrap();
+            assert_eq!(leaf.height, i);
+            assert_eq!(block.height, i);
+
+            let decoded: LeafQueryData<MockTypes> = bincode::deserialize(&leaf.data).unwrap();
+            assert_eq!(decoded.height(), i);
+            assert_eq!(decoded.hash().to_string(), leaf.hash);
+            let decoded: BlockQueryData<MockTypes> = bincode::deserialize(&block.data).unwrap();
+            assert_eq!(decoded.hash().to_string(), block.hash);
+
+            let by_height = client
+                .get_leaf(proto::LeafId {
+                    id: Some(leaf_id::Id::Height(i)),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(by_height, leaf);
+            let by_hash = client
+                .get_leaf(proto::LeafId {
+                    id: Some(leaf_id::Id::Hash(leaf.hash.clone())),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(by_hash, leaf);
+
+            for id in [
+                block_id::Id::Height(i),
+                block_id::Id::Hash(block.hash.clone()),
+            ] {
+                let by_id = client
+                    .get_block(proto::BlockId { id: Some(id) })
+                    .await
+                    .unwrap()
+                    .into_inner();
+                assert_eq!(by_id, block);
+            }
+
+            let common = client
+                .get_vid_common(proto::BlockId {
+                    id: Some(block_id::Id::Height(i)),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(common.height, i);
+            assert_eq!(common.block_hash, block.hash);
+            assert_eq!(common.payload_hash, block.payload_hash);
+        }
+
+        // Malformed requests are rejected.
+        let err = client
+            .get_block(proto::BlockId { id: None })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        let err = client
+            .get_leaf(proto::LeafId {
+                id: Some(leaf_id::Id::Hash("not a hash".into())),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
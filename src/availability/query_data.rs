@@ -11,6 +11,7 @@
 // see <https://www.gnu.org/licenses/>.
 
 use crate::{types::HeightIndexed, Header, Metadata, Payload, Transaction, VidCommon, VidShare};
+use anyhow::Context;
 use committable::{Commitment, Committable};
 use hotshot_types::{
     data::Leaf,
@@ -24,9 +25,17 @@ use hotshot_types::{
     vid::{vid_scheme, VidCommitment},
 };
 use jf_vid::VidScheme;
+use once_cell::sync::Lazy;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use snafu::{ensure, Snafu};
-use std::fmt::Debug;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display, Formatter},
+    iter,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+use tagged_base64::TaggedBase64;
 
 pub type LeafHash<Types> = Commitment<Leaf<Types>>;
 /// A block hash is the hash of the block header.
@@ -38,6 +47,7 @@ pub type TransactionHash<Types> = Commitment<Transaction<Types>>;
 pub type TransactionIndex<Types> = <Payload<Types> as QueryablePayload<Types>>::TransactionIndex;
 pub type TransactionInclusionProof<Types> =
     <Payload<Types> as QueryablePayload<Types>>::InclusionProof;
+pub type NamespaceId<Types> = <Payload<Types> as NamespacedPayload<Types>>::Namespace;
 
 pub type Timestamp = time::OffsetDateTime;
 
@@ -94,6 +104,13 @@ pub trait QueryablePayload<Types: NodeType>: traits::BlockPayload<Types> {
     }
 
     /// List the transaction indices in the block.
+    ///
+    /// The order of this enumeration defines the position of each transaction within the block, as
+    /// reported by [`TransactionQueryData::index`] and used by positional queries like
+    /// [`nth`](Self::nth). It must therefore be stable, yielding the same sequence every time it is
+    /// called on the same payload, and it should match the order in which transactions are
+    /// committed to by the payload commitment, so that positions are meaningful to clients
+    /// reconstructing execution order.
     fn iter<'a>(&'a self, meta: &'a Self::Metadata) -> Self::Iter<'a>;
 
     /// Enumerate the transactions in the block with their indices.
@@ -187,6 +204,136 @@ pub trait QueryablePayload<Types: NodeType>: traits::BlockPayload<Types> {
     }
 }
 
+/// A block payload whose transactions are partitioned into namespaces.
+///
+/// The transactions in a block may be partitioned into namespaces, for example one for each rollup
+/// sharing a sequencer. Implementing this trait enables the namespace queries of the availability
+/// API (see [`NamespaceQueryData`] and
+/// [`subscribe_namespace_transactions`](crate::availability::AvailabilityDataSource::subscribe_namespace_transactions)).
+/// Payload formats which do not have namespaces need not implement it.
+///
+/// Note to implementors: the only required method is
+/// [`transaction_namespace`](Self::transaction_namespace). As with [`QueryablePayload`], the
+/// provided methods may be inefficient, and payload formats with their own namespace table should
+/// override them.
+pub trait NamespacedPayload<Types: NodeType>: QueryablePayload<Types> {
+    /// An identifier for a namespace within the block.
+    type Namespace: Clone + Debug + PartialEq + Eq + Serialize + DeserializeOwned + Send + Sync;
+
+    /// Get the namespace of a transaction by its block-specific index.
+    fn transaction_namespace(
+        &self,
+        meta: &Self::Metadata,
+        index: &Self::TransactionIndex,
+    ) -> Option<Self::Namespace>;
+
+    /// List the namespaces in the block, with the transactions belonging to each.
+    ///
+    /// Each item is a namespace and a range of positions, in the order of [`iter`](Self::iter),
+    /// of transactions belonging to that namespace. Items are yielded in block order, and a
+    /// namespace may occur more than once if its transactions are not contiguous.
+    ///
+    /// The default implementation looks up the namespace of every transaction in the block with
+    /// [`transaction_namespace`](Self::transaction_namespace) and groups runs of consecutive
+    /// transactions in the same namespace. Payload formats with their own namespace table should
+    /// override this to read the table directly.
+    /// The namespace queries of the availability API are implemented in terms of this method, so
+    /// they work for any payload format that implements it.
+    fn namespaces<'a>(
+        &'a self,
+        meta: &'a Self::Metadata,
+    ) -> Box<dyn 'a + Iterator<Item = (Self::Namespace, Range<usize>)>> {
+        let mut namespaces = self
+            .iter(meta)
+            .enumerate()
+            .filter_map(|(pos, ix)| Some((pos, self.transaction_namespace(meta, &ix)?)))
+            .peekable();
+        Box::new(iter::from_fn(move || {
+            let (start, namespace) = namespaces.next()?;
+            let mut end = start + 1;
+            while namespaces
+                .next_if(|(pos, next)| *pos == end && *next == namespace)
+                .is_some()
+            {
+                end += 1;
+            }
+            Some((namespace, start..end))
+        }))
+    }
+
+    /// Get all the transactions in a given namespace, each with an inclusion proof.
+    ///
+    /// Transactions are returned in the order they appear in the block. The default implementation
+    /// finds the transactions using [`namespaces`](Self::namespaces) and computes an inclusion
+    /// proof for each one.
+    fn namespace_with_proof(
+        &self,
+        meta: &Self::Metadata,
+        namespace: &Self::Namespace,
+    ) -> Vec<(Self::Transaction, Self::InclusionProof)> {
+        let indices = self.iter(meta).collect::<Vec<_>>();
+        self.namespaces(meta)
+            .filter(|(ns, _)| ns == namespace)
+            .flat_map(|(_, range)| range)
+            .filter_map(|pos| self.transaction_with_proof(meta, indices.get(pos)?))
+            .collect()
+    }
+}
+
+/// A block payload whose transaction inclusion proofs can be checked against a block header.
+///
+/// Implementing this trait allows a client which only has the header of a block (for example, a
+/// light client) to check that a transaction returned by an untrusted query service really belongs
+/// to that block, without downloading the whole payload. See [`TransactionQueryData::verify`].
+pub trait VerifiablePayload<Types: NodeType>: QueryablePayload<Types> {
+    /// Check that `transaction` belongs to the payload committed to by `header`.
+    fn verify_inclusion(
+        header: &Header<Types>,
+        transaction: &Self::Transaction,
+        proof: &Self::InclusionProof,
+    ) -> bool;
+}
+
+/// A namespaced payload whose inclusion proofs can be checked against the namespace of a
+/// transaction.
+///
+/// This allows a client to check that a [`NamespaceQueryData`] returned by an untrusted query
+/// service contains only transactions from the namespace it asked for. See
+/// [`NamespaceQueryData::verify`].
+pub trait VerifiableNamespacedPayload<Types: NodeType>:
+    VerifiablePayload<Types> + NamespacedPayload<Types>
+{
+    /// Check that `transaction` belongs to `namespace` in the payload committed to by `header`.
+    ///
+    /// Implementations must check the namespace as well as inclusion. A payload whose inclusion
+    /// proofs do not commit to the namespace of a transaction must determine it some other way, such
+    /// as from the transaction itself.
+    fn verify_namespace_inclusion(
+        header: &Header<Types>,
+        namespace: &Self::Namespace,
+        transaction: &Self::Transaction,
+        proof: &Self::InclusionProof,
+    ) -> bool;
+}
+
+/// Check that `transaction` belongs to the payload committed to by `header`.
+///
+/// This is the same as [`VerifiablePayload::verify_inclusion`], but as a free function, so that a
+/// light client holding only a block header and a transaction with its proof (for example, from a
+/// [`TransactionQueryData`] returned by an untrusted query service) can check inclusion without
+/// naming the payload type or depending on a data source.
+pub fn verify_tx_inclusion<Types>(
+    header: &Header<Types>,
+    transaction: &Transaction<Types>,
+    proof: &TransactionInclusionProof<Types>,
+) -> bool
+where
+    Types: NodeType,
+    Payload<Types>: VerifiablePayload<Types>,
+{
+    Payload::<Types>::verify_inclusion(header, transaction, proof)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(bound = "")]
 pub struct LeafQueryData<Types: NodeType> {
@@ -368,6 +515,22 @@ where
     ) -> impl '_ + Iterator<Item = (TransactionIndex<Types>, Transaction<Types>)> {
         self.payload.enumerate(self.metadata())
     }
+
+    /// Get the transaction at position `index` in this block, along with its namespace.
+    ///
+    /// Returns [`None`] if the block has no more than `index` transactions.
+    pub fn transaction_at(
+        &self,
+        index: usize,
+    ) -> Option<(NamespaceId<Types>, TransactionQueryData<Types>)>
+    where
+        Payload<Types>: NamespacedPayload<Types>,
+    {
+        let ix = self.payload.nth(self.metadata(), index)?;
+        let namespace = self.payload.transaction_namespace(self.metadata(), &ix)?;
+        let txn = TransactionQueryData::with_index(self, ix, index as u64)?;
+        Some((namespace, txn))
+    }
 }
 
 impl<Types: NodeType> HeightIndexed for BlockQueryData<Types> {
@@ -434,6 +597,133 @@ impl<Types: NodeType> HeightIndexed for PayloadQueryData<Types> {
     }
 }
 
+/// The encoded bytes of a block payload.
+///
+/// `data` is exactly the output of [`EncodeBytes::encode`] for the payload, which is what the
+/// payload commitment `hash` commits to, so clients can verify and decode the payload themselves.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct RawPayloadQueryData<Types: NodeType> {
+    pub(crate) height: u64,
+    pub(crate) block_hash: BlockHash<Types>,
+    pub(crate) hash: VidCommitment,
+    pub(crate) data: Vec<u8>,
+}
+
+impl<Types: NodeType> From<PayloadQueryData<Types>> for RawPayloadQueryData<Types> {
+    fn from(payload: PayloadQueryData<Types>) -> Self {
+        Self {
+            height: payload.height,
+            block_hash: payload.block_hash,
+            hash: payload.hash,
+            data: payload.data.encode().to_vec(),
+        }
+    }
+}
+
+impl<Types: NodeType> From<BlockQueryData<Types>> for RawPayloadQueryData<Types> {
+    fn from(block: BlockQueryData<Types>) -> Self {
+        PayloadQueryData::from(block).into()
+    }
+}
+
+impl<Types: NodeType> RawPayloadQueryData<Types> {
+    pub fn hash(&self) -> VidCommitment {
+        self.hash
+    }
+
+    pub fn block_hash(&self) -> BlockHash<Types> {
+        self.block_hash
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl<Types: NodeType> HeightIndexed for RawPayloadQueryData<Types> {
+    fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+/// A contiguous range of the encoded bytes of a block payload.
+///
+/// `data` holds the bytes of the [encoded payload](RawPayloadQueryData) from offset `start` up to
+/// [`end`](Self::end), exclusive. `total_len` is the length of the whole encoded payload, so a client
+/// downloading a large payload in pieces knows when it has received all of it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct PayloadByteRange<Types: NodeType> {
+    pub(crate) height: u64,
+    pub(crate) block_hash: BlockHash<Types>,
+    pub(crate) hash: VidCommitment,
+    pub(crate) start: usize,
+    pub(crate) total_len: usize,
+    pub(crate) data: Vec<u8>,
+}
+
+impl<Types: NodeType> PayloadByteRange<Types> {
+    /// Extract the bytes in `range` from an encoded payload.
+    ///
+    /// The range is clamped to the length of the payload, so the result may be shorter than
+    /// requested, or empty.
+    pub fn new(payload: RawPayloadQueryData<Types>, range: Range<usize>) -> Self {
+        let total_len = payload.data.len();
+        let start = range.start.min(total_len);
+        let end = range.end.clamp(start, total_len);
+        Self {
+            height: payload.height,
+            block_hash: payload.block_hash,
+            hash: payload.hash,
+            start,
+            total_len,
+            data: payload.data[start..end].to_vec(),
+        }
+    }
+
+    pub fn hash(&self) -> VidCommitment {
+        self.hash
+    }
+
+    pub fn block_hash(&self) -> BlockHash<Types> {
+        self.block_hash
+    }
+
+    /// The offset in the encoded payload of the first byte in this range.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The offset in the encoded payload just past the last byte in this range.
+    pub fn end(&self) -> usize {
+        self.start + self.data.len()
+    }
+
+    /// The length of the whole encoded payload.
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl<Types: NodeType> HeightIndexed for PayloadByteRange<Types> {
+    fn height(&self) -> u64 {
+        self.height
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(bound = "")]
 pub struct VidCommonQueryData<Types: NodeType> {
@@ -459,12 +749,10 @@ impl<Types: NodeType> VidCommonQueryData<Types> {
     ) -> Self {
         let leaf = Leaf::<Types>::genesis(validated_state, instance_state).await;
         let payload = leaf.block_payload().unwrap();
-        let bytes = payload.encode();
-        let disperse = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES)
-            .disperse(bytes)
-            .unwrap();
+        let disperse =
+            genesis_vid_disperse(GENESIS_VID_NUM_STORAGE_NODES, &payload.encode()).unwrap();
 
-        Self::new(leaf.block_header().clone(), disperse.common)
+        Self::new(leaf.block_header().clone(), disperse.common.clone())
     }
 
     pub fn block_hash(&self) -> BlockHash<Types> {
@@ -480,6 +768,46 @@ impl<Types: NodeType> VidCommonQueryData<Types> {
     }
 }
 
+/// The VID dispersal of a genesis payload, as needed to store VID data for the genesis block.
+#[derive(Debug)]
+pub(crate) struct GenesisVid {
+    pub(crate) commit: VidCommitment,
+    pub(crate) common: VidCommon,
+    /// The first share, which is the one we store as our own.
+    pub(crate) share: VidShare,
+}
+
+/// Cache of genesis VID dispersals, keyed by number of storage nodes and encoded payload.
+///
+/// The genesis payload is always the same for a given application, and dispersal is deterministic,
+/// so there is no need to recompute it each time a genesis leaf is processed. The payload is part
+/// of the key because it depends on the application's types, which a static cannot be generic over.
+static GENESIS_VID: Lazy<Mutex<HashMap<(usize, Vec<u8>), Arc<GenesisVid>>>> =
+    Lazy::new(Default::default);
+
+/// Compute (or look up) the VID dispersal of a genesis payload.
+pub(crate) fn genesis_vid_disperse(
+    num_storage_nodes: usize,
+    payload: &[u8],
+) -> anyhow::Result<Arc<GenesisVid>> {
+    // Hold the lock while computing the dispersal, so that concurrent callers (for example, many
+    // nodes starting at once in a test) wait for one computation rather than all doing it.
+    let mut cache = GENESIS_VID.lock().unwrap();
+    if let Some(vid) = cache.get(&(num_storage_nodes, payload.to_vec())) {
+        return Ok(vid.clone());
+    }
+    let mut disperse = vid_scheme(num_storage_nodes)
+        .disperse(payload)
+        .context("unable to compute VID dispersal for genesis block")?;
+    let vid = Arc::new(GenesisVid {
+        commit: disperse.commit,
+        common: disperse.common,
+        share: disperse.shares.remove(0),
+    });
+    cache.insert((num_storage_nodes, payload.to_vec()), vid.clone());
+    Ok(vid)
+}
+
 impl<Types: NodeType> HeightIndexed for VidCommonQueryData<Types> {
     fn height(&self) -> u64 {
         self.height
@@ -510,7 +838,23 @@ impl<Types: NodeType> TransactionQueryData<Types>
 where
     Payload<Types>: QueryablePayload<Types>,
 {
-    pub(crate) fn new(
+    /// The transaction at position `index` in `block`.
+    ///
+    /// The block-specific transaction index, and thus the inclusion proof, is derived from the
+    /// position, so the proof always refers to the transaction at that position.
+    pub(crate) fn new(block: &BlockQueryData<Types>, index: u64) -> Option<Self> {
+        let i = block
+            .payload()
+            .nth(block.metadata(), index.try_into().ok()?)?;
+        Self::with_index(block, i, index)
+    }
+
+    /// The transaction with block-specific index `i`, which is at position `index` in `block`.
+    ///
+    /// The caller must ensure that `i` is the `index`th index yielded by
+    /// [`QueryablePayload::iter`]. This is useful when enumerating a block, to avoid a linear
+    /// search for each transaction.
+    pub(crate) fn with_index(
         block: &BlockQueryData<Types>,
         i: TransactionIndex<Types>,
         index: u64,
@@ -537,7 +881,7 @@ where
             .enumerate()
             .find_map(|(i, (index, tx))| {
                 if tx.commit() == hash {
-                    Some(Self::new(block, index, i as u64))
+                    Some(Self::with_index(block, index, i as u64))
                 } else {
                     None
                 }
@@ -556,6 +900,10 @@ where
     }
 
     /// The (0-based) position of this transaction within its block.
+    ///
+    /// This is the position of the transaction in the enumeration order of
+    /// [`QueryablePayload::iter`], which matches the order of transactions in the payload
+    /// commitment.
     pub fn index(&self) -> u64 {
         self.index
     }
@@ -574,6 +922,110 @@ where
     pub fn block_hash(&self) -> BlockHash<Types> {
         self.block_hash
     }
+
+    /// Check that this transaction belongs to the block with the given `header`.
+    ///
+    /// This checks that `header` is the header of the block this transaction claims to belong to,
+    /// and that the inclusion proof is valid with respect to that header's payload commitment.
+    pub fn verify(&self, header: &Header<Types>) -> bool
+    where
+        Payload<Types>: VerifiablePayload<Types>,
+    {
+        header.block_number() == self.block_height
+            && header.commit() == self.block_hash
+            && self.transaction.commit() == self.hash
+            && verify_tx_inclusion(header, &self.transaction, &self.proof)
+    }
+}
+
+/// The transactions from a single namespace of a block, with proofs.
+///
+/// This allows a client interested in only one namespace to download and check the relevant part
+/// of a block without downloading the whole payload. The proof consists of one
+/// [`InclusionProof`](QueryablePayload::InclusionProof) per transaction, in the same order as the
+/// transactions, each proving that the corresponding transaction belongs to the payload committed
+/// to by the block header. Whether the proofs additionally show that _no_ transactions from the
+/// namespace have been omitted depends on the payload type: a payload whose inclusion proofs commit
+/// to the transaction's position within its namespace provides this, while a trivial proof (like
+/// `()`) does not.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct NamespaceQueryData<Types: NodeType>
+where
+    Payload<Types>: NamespacedPayload<Types>,
+{
+    pub(crate) block_height: u64,
+    pub(crate) block_hash: BlockHash<Types>,
+    pub(crate) namespace: NamespaceId<Types>,
+    pub(crate) transactions: Vec<Transaction<Types>>,
+    pub(crate) proofs: Vec<TransactionInclusionProof<Types>>,
+}
+
+impl<Types: NodeType> NamespaceQueryData<Types>
+where
+    Payload<Types>: NamespacedPayload<Types>,
+{
+    pub fn new(block: &BlockQueryData<Types>, namespace: NamespaceId<Types>) -> Self {
+        let (transactions, proofs) = block
+            .payload()
+            .namespace_with_proof(block.metadata(), &namespace)
+            .into_iter()
+            .unzip();
+        Self {
+            block_height: block.height(),
+            block_hash: block.hash(),
+            namespace,
+            transactions,
+            proofs,
+        }
+    }
+
+    /// The height of the block containing these transactions.
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    /// The hash of the block containing these transactions.
+    pub fn block_hash(&self) -> BlockHash<Types> {
+        self.block_hash
+    }
+
+    /// The namespace which these transactions belong to.
+    pub fn namespace(&self) -> &NamespaceId<Types> {
+        &self.namespace
+    }
+
+    /// The transactions in this namespace, in the order they appear in the block.
+    pub fn transactions(&self) -> &[Transaction<Types>] {
+        &self.transactions
+    }
+
+    /// Inclusion proofs for each transaction, in the same order as
+    /// [`transactions`](Self::transactions).
+    pub fn proofs(&self) -> &[TransactionInclusionProof<Types>] {
+        &self.proofs
+    }
+
+    /// Check that these transactions belong to the given namespace of the block with `header`.
+    ///
+    /// This checks that `header` is the header of the block these transactions claim to belong
+    /// to, and that every inclusion proof is valid with respect to the header's payload commitment
+    /// and the claimed namespace (see [`VerifiableNamespacedPayload::verify_namespace_inclusion`]).
+    pub fn verify(&self, header: &Header<Types>) -> bool
+    where
+        Payload<Types>: VerifiableNamespacedPayload<Types>,
+    {
+        header.block_number() == self.block_height
+            && header.commit() == self.block_hash
+            && self.transactions.len() == self.proofs.len()
+            && self
+                .transactions
+                .iter()
+                .zip(&self.proofs)
+                .all(|(tx, proof)| {
+                    Payload::<Types>::verify_namespace_inclusion(header, &self.namespace, tx, proof)
+                })
+    }
 }
 
 pub(crate) fn payload_size<Types: NodeType>(payload: &Payload<Types>) -> u64 {
@@ -642,6 +1094,56 @@ where
     }
 }
 
+/// A notification that a block has been decided and stored.
+///
+/// This is a compact description of a decided block, for downstream indexers which need to know
+/// which transactions have been committed without downloading every block. See
+/// [`subscribe_decides`](super::AvailabilityDataSource::subscribe_decides).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct DecideNotification<Types: NodeType> {
+    pub(crate) height: u64,
+    pub(crate) block_hash: BlockHash<Types>,
+    pub(crate) timestamp: u64,
+    pub(crate) transactions: Vec<TransactionHash<Types>>,
+}
+
+impl<Types: NodeType> DecideNotification<Types> {
+    pub fn block_hash(&self) -> BlockHash<Types> {
+        self.block_hash
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The hashes of the transactions committed in this block, in the order they were sequenced.
+    pub fn transactions(&self) -> &[TransactionHash<Types>] {
+        &self.transactions
+    }
+}
+
+impl<Types: NodeType> HeightIndexed for DecideNotification<Types> {
+    fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+impl<Types: NodeType> From<BlockQueryData<Types>> for DecideNotification<Types>
+where
+    Header<Types>: QueryableHeader<Types>,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    fn from(block: BlockQueryData<Types>) -> Self {
+        Self {
+            height: block.height(),
+            block_hash: block.hash(),
+            timestamp: block.header().timestamp(),
+            transactions: block.enumerate().map(|(_, tx)| tx.commit()).collect(),
+        }
+    }
+}
+
 /// A summary of a payload without all the data.
 ///
 /// This type is useful when you only want information about a payload, such as its size or
@@ -723,3 +1225,437 @@ pub struct Limits {
     pub small_object_range_limit: usize,
     pub large_object_range_limit: usize,
 }
+
+/// The tag of the [`TaggedBase64`] encoding of a [`PageCursor`].
+const PAGE_CURSOR_TAG: &str = "CURSOR";
+
+/// An opaque position in a paginated range query.
+///
+/// A cursor records the endpoint which issued it and the end of the range originally requested, as
+/// well as the height of the next object to return. Since each page is loaded from the range fixed
+/// by the first request, a sequence of pages covers exactly that range, with no gaps or duplicates,
+/// no matter how many blocks are appended between requests. A cursor is only valid for the endpoint
+/// which issued it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "TaggedBase64", into = "TaggedBase64")]
+pub struct PageCursor {
+    endpoint: String,
+    next: usize,
+    until: usize,
+}
+
+impl PageCursor {
+    /// A cursor for the first page of the range `[from, until)` of `endpoint`.
+    pub(crate) fn new(endpoint: impl Into<String>, from: usize, until: usize) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            next: from,
+            until,
+        }
+    }
+
+    /// The endpoint which issued this cursor.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The heights of the next page of at most `limit` objects.
+    pub fn next_page(&self, limit: usize) -> Range<usize> {
+        self.next..self.until.min(self.next.saturating_add(limit))
+    }
+
+    /// Build a page of `items`, starting from this cursor.
+    ///
+    /// The page includes a cursor for the following page, unless `items` reaches the end of the
+    /// range.
+    pub(crate) fn page<T>(self, items: Vec<T>) -> Page<T> {
+        let next = self.next + items.len();
+        let next_cursor = (next < self.until).then_some(Self { next, ..self });
+        Page { items, next_cursor }
+    }
+}
+
+impl From<PageCursor> for TaggedBase64 {
+    fn from(cursor: PageCursor) -> Self {
+        let mut bytes = (cursor.next as u64).to_le_bytes().to_vec();
+        bytes.extend((cursor.until as u64).to_le_bytes());
+        bytes.extend(cursor.endpoint.as_bytes());
+        TaggedBase64::new(PAGE_CURSOR_TAG, &bytes).unwrap()
+    }
+}
+
+impl TryFrom<&TaggedBase64> for PageCursor {
+    type Error = InvalidPageCursor;
+
+    fn try_from(tb64: &TaggedBase64) -> Result<Self, Self::Error> {
+        let bytes = tb64.value();
+        if tb64.tag() != PAGE_CURSOR_TAG || bytes.len() < 16 {
+            return Err(InvalidPageCursor);
+        }
+        let next = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+        let until = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let endpoint = String::from_utf8(bytes[16..].to_vec()).map_err(|_| InvalidPageCursor)?;
+        Ok(Self {
+            endpoint,
+            next,
+            until,
+        })
+    }
+}
+
+impl TryFrom<TaggedBase64> for PageCursor {
+    type Error = InvalidPageCursor;
+
+    fn try_from(tb64: TaggedBase64) -> Result<Self, Self::Error> {
+        Self::try_from(&tb64)
+    }
+}
+
+impl Display for PageCursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", TaggedBase64::from(self.clone()))
+    }
+}
+
+/// A string which is not a valid [`PageCursor`].
+#[derive(Clone, Copy, Debug, Snafu)]
+#[snafu(display("invalid page cursor"))]
+pub struct InvalidPageCursor;
+
+/// A page of results from a paginated range query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+pub struct Page<T> {
+    /// The objects in this page, in order of height.
+    pub items: Vec<T>,
+    /// A cursor to request the next page, or [`None`] if this is the last page.
+    pub next_cursor: Option<PageCursor>,
+}
+
+/// The tag of the [`TaggedBase64`] encoding of a [`StreamCursor`].
+const STREAM_CURSOR_TAG: &str = "STREAM";
+
+/// An opaque position in a subscription stream.
+///
+/// A cursor records the endpoint which issued it and the position of an item in the stream: the
+/// height of the block it belongs to, and its index within that block (always 0 for streams with
+/// one item per block). Positions increase monotonically along a stream, so a client can resume a
+/// stream exactly after the last item it received, even if several items share a height. A cursor
+/// is only valid for the endpoint which issued it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "TaggedBase64", into = "TaggedBase64")]
+pub struct StreamCursor {
+    endpoint: String,
+    height: u64,
+    index: u64,
+}
+
+impl StreamCursor {
+    /// A cursor for the item at `index` within block `height` in a stream from `endpoint`.
+    pub(crate) fn new(endpoint: impl Into<String>, height: u64, index: u64) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            height,
+            index,
+        }
+    }
+
+    /// The endpoint which issued this cursor.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The height of the block containing the item at this cursor.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// The index of the item at this cursor within its block.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Whether `other` is strictly later in the stream than this cursor.
+    pub fn precedes(&self, other: &Self) -> bool {
+        (self.height, self.index) < (other.height, other.index)
+    }
+}
+
+impl From<StreamCursor> for TaggedBase64 {
+    fn from(cursor: StreamCursor) -> Self {
+        let mut bytes = cursor.height.to_le_bytes().to_vec();
+        bytes.extend(cursor.index.to_le_bytes());
+        bytes.extend(cursor.endpoint.as_bytes());
+        TaggedBase64::new(STREAM_CURSOR_TAG, &bytes).unwrap()
+    }
+}
+
+impl TryFrom<&TaggedBase64> for StreamCursor {
+    type Error = InvalidStreamCursor;
+
+    fn try_from(tb64: &TaggedBase64) -> Result<Self, Self::Error> {
+        let bytes = tb64.value();
+        if tb64.tag() != STREAM_CURSOR_TAG || bytes.len() < 16 {
+            return Err(InvalidStreamCursor);
+        }
+        let height = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let index = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let endpoint = String::from_utf8(bytes[16..].to_vec()).map_err(|_| InvalidStreamCursor)?;
+        Ok(Self {
+            endpoint,
+            height,
+            index,
+        })
+    }
+}
+
+impl TryFrom<TaggedBase64> for StreamCursor {
+    type Error = InvalidStreamCursor;
+
+    fn try_from(tb64: TaggedBase64) -> Result<Self, Self::Error> {
+        Self::try_from(&tb64)
+    }
+}
+
+impl Display for StreamCursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", TaggedBase64::from(self.clone()))
+    }
+}
+
+/// A string which is not a valid [`StreamCursor`].
+#[derive(Clone, Copy, Debug, Snafu)]
+#[snafu(display("invalid stream cursor"))]
+pub struct InvalidStreamCursor;
+
+/// An item in a subscription stream, along with a cursor to resume the stream after it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "T: Serialize + DeserializeOwned")]
+pub struct StreamFrame<T> {
+    pub item: T,
+    pub cursor: StreamCursor,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{
+        mocks::{mock_transaction, MockPayload, MockTypes},
+        setup_test,
+    };
+    use hotshot_example_types::state_types::{TestInstanceState, TestValidatedState};
+    use hotshot_types::traits::BlockPayload;
+
+    #[test]
+    fn test_default_namespaces() {
+        setup_test();
+
+        // An empty payload has no namespaces.
+        let (payload, meta) = <MockPayload as BlockPayload<MockTypes>>::empty();
+        assert_eq!(
+            NamespacedPayload::<MockTypes>::namespaces(&payload, &meta).count(),
+            0
+        );
+
+        // All mock transactions belong to namespace 0, so they form a single run.
+        let payload = MockPayload {
+            transactions: (0..3).map(|i| mock_transaction(vec![i])).collect(),
+        };
+        assert_eq!(
+            NamespacedPayload::<MockTypes>::namespaces(&payload, &meta).collect::<Vec<_>>(),
+            [(0, 0..3)]
+        );
+        assert_eq!(
+            NamespacedPayload::<MockTypes>::namespace_with_proof(&payload, &meta, &0).len(),
+            3
+        );
+        assert!(
+            NamespacedPayload::<MockTypes>::namespace_with_proof(&payload, &meta, &1).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_page_cursor() {
+        setup_test();
+
+        // A cursor survives encoding.
+        let cursor = PageCursor::new("get_leaf_page", 3, 10);
+        let tb64 = TaggedBase64::from(cursor.clone());
+        assert_eq!(PageCursor::try_from(&tb64).unwrap(), cursor);
+        assert_eq!(cursor.to_string(), tb64.to_string());
+
+        // Each page advances the cursor, until the end of the range.
+        assert_eq!(cursor.next_page(4), 3..7);
+        let page = cursor.page(vec![3, 4, 5, 6]);
+        let cursor = page.next_cursor.unwrap();
+        assert_eq!(cursor.endpoint(), "get_leaf_page");
+        assert_eq!(cursor.next_page(4), 7..10);
+        assert_eq!(cursor.page(vec![7, 8, 9]).next_cursor, None);
+
+        // Other tagged strings are not cursors.
+        let other = TaggedBase64::new("OTHER", &[0; 16]).unwrap();
+        PageCursor::try_from(&other).unwrap_err();
+        let short = TaggedBase64::new(PAGE_CURSOR_TAG, &[0; 8]).unwrap();
+        PageCursor::try_from(&short).unwrap_err();
+    }
+
+    #[test]
+    fn test_stream_cursor() {
+        setup_test();
+
+        // A cursor survives encoding.
+        let cursor = StreamCursor::new("stream_leaves_with_cursor", 3, 1);
+        let tb64 = TaggedBase64::from(cursor.clone());
+        assert_eq!(StreamCursor::try_from(&tb64).unwrap(), cursor);
+        assert_eq!(cursor.to_string(), tb64.to_string());
+
+        // Cursors are ordered by height, then by index within a block.
+        assert!(cursor.precedes(&StreamCursor::new("", 3, 2)));
+        assert!(cursor.precedes(&StreamCursor::new("", 4, 0)));
+        assert!(!cursor.precedes(&StreamCursor::new("", 3, 1)));
+        assert!(!cursor.precedes(&StreamCursor::new("", 2, 5)));
+
+        // Page cursors and other tagged strings are not stream cursors.
+        let page = TaggedBase64::from(PageCursor::new("get_leaf_page", 0, 1));
+        StreamCursor::try_from(&page).unwrap_err();
+        let short = TaggedBase64::new(STREAM_CURSOR_TAG, &[0; 8]).unwrap();
+        StreamCursor::try_from(&short).unwrap_err();
+    }
+
+    #[test]
+    fn test_genesis_vid_cached() {
+        setup_test();
+
+        let payload = Payload::<MockTypes>::empty().0.encode();
+        let cached = genesis_vid_disperse(GENESIS_VID_NUM_STORAGE_NODES, &payload).unwrap();
+
+        // The cached dispersal matches a fresh one.
+        let fresh = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES)
+            .disperse(&payload)
+            .unwrap();
+        assert_eq!(cached.commit, fresh.commit);
+        assert_eq!(cached.common, fresh.common);
+        assert_eq!(cached.share, fresh.shares[0]);
+
+        // Subsequent calls return the same cached object rather than recomputing it.
+        let again = genesis_vid_disperse(GENESIS_VID_NUM_STORAGE_NODES, &payload).unwrap();
+        assert!(Arc::ptr_eq(&cached, &again));
+
+        // A different number of storage nodes gives a different dispersal.
+        let other = genesis_vid_disperse(GENESIS_VID_NUM_STORAGE_NODES + 1, &payload).unwrap();
+        assert!(!Arc::ptr_eq(&cached, &other));
+        assert_eq!(
+            other.common,
+            vid_scheme(GENESIS_VID_NUM_STORAGE_NODES + 1)
+                .disperse(&payload)
+                .unwrap()
+                .common
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transaction_index() {
+        setup_test();
+
+        let txns = (0..5u8)
+            .map(|i| mock_transaction(vec![i]))
+            .collect::<Vec<_>>();
+        let genesis = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::new(
+            genesis.header().clone(),
+            MockPayload {
+                transactions: txns.clone(),
+            },
+        );
+
+        for (i, txn) in txns.iter().enumerate() {
+            // Looking up a transaction by hash or by position gives the same position, and a proof
+            // for the transaction at that position.
+            let by_hash = TransactionQueryData::with_hash(&block, txn.commit()).unwrap();
+            let (_, by_position) = block.transaction_at(i).unwrap();
+            assert_eq!(by_hash, by_position);
+            assert_eq!(by_hash.index(), i as u64);
+            assert_eq!(by_hash.block_height(), block.height());
+            assert_eq!(by_hash.transaction(), txn);
+            assert_eq!(
+                *by_hash.proof(),
+                block
+                    .payload()
+                    .nth_transaction_with_proof(block.metadata(), i)
+                    .unwrap()
+                    .1
+            );
+
+            // The position survives serialization.
+            let json = serde_json::to_string(&by_hash).unwrap();
+            let decoded: TransactionQueryData<MockTypes> = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.index(), i as u64);
+            assert_eq!(decoded, by_hash);
+        }
+        assert!(block.transaction_at(txns.len()).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify() {
+        setup_test();
+
+        let genesis = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let payload = MockPayload {
+            transactions: (0..3u8).map(|i| mock_transaction(vec![i])).collect(),
+        };
+        let block = BlockQueryData::new(genesis.header().clone(), payload.clone());
+        let mut header = block.header().clone();
+        header.block_number += 1;
+        let other = BlockQueryData::new(header, payload);
+
+        // A transaction verifies against the header of its own block.
+        let txn = TransactionQueryData::new(&block, 1).unwrap();
+        assert!(txn.verify(block.header()));
+
+        // It does not verify against the header of a different block, even one with the same
+        // payload, nor if it claims to be at a different height.
+        assert!(!txn.verify(other.header()));
+        let mut wrong_height = txn.clone();
+        wrong_height.block_height += 1;
+        assert!(!wrong_height.verify(block.header()));
+
+        // Tampering with the transaction or its hash is detected.
+        let mut tampered = txn.clone();
+        tampered.transaction = mock_transaction(vec![42]);
+        assert!(!tampered.verify(block.header()));
+        let mut tampered = txn.clone();
+        tampered.hash = mock_transaction(vec![42]).commit();
+        assert!(!tampered.verify(block.header()));
+
+        // The same goes for a namespace, which must also match the transactions.
+        let ns = NamespaceQueryData::new(&block, 0);
+        assert_eq!(ns.transactions().len(), 3);
+        assert!(ns.verify(block.header()));
+        assert!(!ns.verify(other.header()));
+        let mut wrong_namespace = ns.clone();
+        wrong_namespace.namespace = 1;
+        assert!(!wrong_namespace.verify(block.header()));
+        let mut tampered = ns.clone();
+        tampered.proofs.pop();
+        assert!(!tampered.verify(block.header()));
+
+        // A response padded with transactions from another namespace is rejected, even though
+        // each transaction is included in the block.
+        let mut foreign = NamespaceQueryData::new(&block, 1);
+        assert!(foreign.transactions().is_empty());
+        assert!(foreign.verify(block.header()));
+        foreign
+            .transactions
+            .extend(ns.transactions().iter().cloned());
+        foreign.proofs.extend(ns.proofs().iter().cloned());
+        assert!(!foreign.verify(block.header()));
+    }
+}
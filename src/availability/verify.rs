@@ -0,0 +1,157 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Authentication of leaves obtained from untrusted sources.
+
+use super::query_data::LeafHash;
+use crate::Leaf;
+use committable::Committable;
+use hotshot_types::{
+    message::UpgradeLock,
+    simple_certificate::QuorumCertificate,
+    traits::node_implementation::{ConsensusTime, NodeType, Versions},
+    vote::Certificate,
+};
+use snafu::{ensure, Snafu};
+
+/// A reason why a quorum certificate does not authenticate a leaf.
+#[derive(Clone, Debug, Snafu)]
+pub enum VerifyLeafError<Types: NodeType> {
+    /// The QC signs a different leaf.
+    #[snafu(display("QC references leaf {qc_leaf}, but expected {leaf}"))]
+    WrongLeaf {
+        leaf: LeafHash<Types>,
+        qc_leaf: LeafHash<Types>,
+    },
+    /// The QC is from a different view than the leaf.
+    #[snafu(display("QC is from view {qc_view}, but leaf is from view {leaf_view}"))]
+    WrongView { leaf_view: u64, qc_view: u64 },
+    /// The aggregate signature of the QC is invalid, or is not backed by enough stake.
+    #[snafu(display("QC signature is not valid for the given stake table"))]
+    InvalidSignature,
+}
+
+/// Check that `qc` is a valid quorum certificate for `leaf`.
+///
+/// The query service stores leaves and QCs as they are received from consensus, without checking
+/// the QCs, since it trusts the HotShot node it is attached to. A client which gets a leaf from an
+/// untrusted query service, or a query service which fetches leaves from untrusted peers, should
+/// authenticate the leaf with this function before relying on it.
+///
+/// This checks that `qc` references `leaf` and was formed in the same view, and that its aggregate
+/// signature is valid and carries enough stake according to the stake table of `membership` in
+/// `epoch`. `upgrade_lock` determines the protocol version, and thus the format of the vote data
+/// which the signature covers; it should reflect any upgrade decided before the view of `leaf`.
+pub async fn verify_leaf<Types, V>(
+    leaf: &Leaf<Types>,
+    qc: &QuorumCertificate<Types>,
+    membership: &Types::Membership,
+    epoch: Types::Epoch,
+    upgrade_lock: &UpgradeLock<Types, V>,
+) -> Result<(), VerifyLeafError<Types>>
+where
+    Types: NodeType,
+    V: Versions,
+{
+    let leaf_commit = <Leaf<Types> as Committable>::commit(leaf);
+    ensure!(
+        qc.data.leaf_commit == leaf_commit,
+        WrongLeafSnafu {
+            leaf: leaf_commit,
+            qc_leaf: qc.data.leaf_commit,
+        }
+    );
+    ensure!(
+        qc.view_number == leaf.view_number(),
+        WrongViewSnafu {
+            leaf_view: leaf.view_number().u64(),
+            qc_view: qc.view_number.u64(),
+        }
+    );
+    ensure!(
+        qc.is_valid_cert(membership, epoch, upgrade_lock).await,
+        InvalidSignatureSnafu
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        availability::AvailabilityDataSource,
+        testing::{
+            consensus::{MockDataSource, MockNetwork},
+            mocks::{MockTypes, MockVersions},
+            setup_test,
+        },
+    };
+    use futures::stream::StreamExt;
+    use hotshot_types::data::ViewNumber;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify_leaf() {
+        setup_test();
+
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        network.start().await;
+
+        // Skip the genesis leaf, whose QC is trivially valid.
+        let leaves = network
+            .data_source()
+            .subscribe_leaves(1)
+            .await
+            .take(2)
+            .collect::<Vec<_>>()
+            .await;
+        let membership = network.membership();
+        let epoch = ViewNumber::genesis();
+        let upgrade_lock = UpgradeLock::<MockTypes, MockVersions>::new();
+
+        // QCs from consensus authenticate their leaves.
+        for leaf in &leaves {
+            verify_leaf(leaf.leaf(), leaf.qc(), membership, epoch, &upgrade_lock)
+                .await
+                .unwrap();
+        }
+
+        // A valid QC does not authenticate a different leaf.
+        let err = verify_leaf(
+            leaves[0].leaf(),
+            leaves[1].qc(),
+            membership,
+            epoch,
+            &upgrade_lock,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, VerifyLeafError::WrongLeaf { .. }), "{err}");
+
+        // A QC whose view has been changed does not match the leaf.
+        let mut qc = leaves[0].qc().clone();
+        qc.view_number = ViewNumber::new(qc.view_number.u64() + 1);
+        let err = verify_leaf(leaves[0].leaf(), &qc, membership, epoch, &upgrade_lock)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VerifyLeafError::WrongView { .. }), "{err}");
+
+        // A QC which has been tampered with to reference a different leaf, keeping its original
+        // signature, fails signature verification.
+        let mut qc = leaves[1].qc().clone();
+        qc.data.leaf_commit = leaves[0].hash();
+        qc.view_number = leaves[0].leaf().view_number();
+        let err = verify_leaf(leaves[0].leaf(), &qc, membership, epoch, &upgrade_lock)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VerifyLeafError::InvalidSignature), "{err}");
+    }
+}
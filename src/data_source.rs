@@ -16,6 +16,7 @@
 //! retrieves data in answer to queries. We call this implementation a _data source_. This module
 //! defines a data source and provides several pre-built implementations:
 //! * [`FileSystemDataSource`]
+//! * [`RocksDbDataSource`]
 //! * [`SqlDataSource`]
 //! * [`FetchingDataSource`], a generalization of the above
 //! * [`MetricsDataSource`]
@@ -24,26 +25,40 @@
 //!
 //! We also provide combinators for modularly adding functionality to existing data sources:
 //! * [`ExtensibleDataSource`]
+//! * [`CachingDataSource`]
 //!
 
+mod caching;
 mod extension;
 pub mod fetching;
 pub mod fs;
+pub mod memory;
 mod metrics;
 mod notifier;
+pub mod rocksdb;
+mod snapshot;
 pub mod sql;
 pub mod storage;
 mod update;
 
+#[cfg(feature = "rocksdb-data-source")]
+pub use self::rocksdb::RocksDbDataSource;
+pub use caching::CachingDataSource;
 pub use extension::ExtensibleDataSource;
 pub use fetching::{AvailabilityProvider, FetchingDataSource};
 #[cfg(feature = "file-system-data-source")]
 pub use fs::FileSystemDataSource;
+#[cfg(feature = "memory-data-source")]
+pub use memory::MemoryDataSource;
 #[cfg(feature = "metrics-data-source")]
 pub use metrics::MetricsDataSource;
+pub use snapshot::{ExportSummary, SnapshotDataSource, SNAPSHOT_MAGIC};
 #[cfg(feature = "sql-data-source")]
 pub use sql::SqlDataSource;
-pub use update::{Transaction, UpdateDataSource, VersionedDataSource};
+pub use update::{
+    CommitMarker, LogMissingData, MissingData, MissingDataHandler, MissingVidPolicy,
+    PayloadValidator, Transaction, UpdateDataSource, UpdateSummary, VersionedDataSource,
+};
 
 #[cfg(any(test, feature = "testing"))]
 mod test_helpers {
@@ -121,7 +136,7 @@ mod test_helpers {
 pub mod availability_tests {
     use super::test_helpers::*;
     use crate::{
-        availability::{payload_size, BlockId},
+        availability::{payload_size, BlockId, BlockSummaryQueryData, QueryableHeader},
         data_source::storage::NodeStorage,
         node::NodeDataSource,
         testing::{
@@ -133,7 +148,10 @@ pub mod availability_tests {
     };
     use committable::Committable;
     use futures::stream::StreamExt;
-    use hotshot_types::data::Leaf;
+    use hotshot_types::{
+        data::{Leaf, ViewNumber},
+        traits::node_implementation::ConsensusTime,
+    };
     use std::collections::HashMap;
     use std::fmt::Debug;
     use std::ops::{Bound, RangeBounds};
@@ -143,6 +161,7 @@ pub mod availability_tests {
         // we've seen so we can detect duplicates.
         let mut seen_payloads = HashMap::new();
         let mut seen_transactions = HashMap::new();
+        let mut prev_view = None;
         let mut leaves = leaf_range(ds, ..).await.enumerate();
         while let Some((i, leaf)) = leaves.next().await {
             assert_eq!(leaf.height(), i as u64);
@@ -156,6 +175,21 @@ pub mod availability_tests {
             assert_eq!(leaf, ds.get_leaf(i).await.await);
             assert_eq!(leaf, ds.get_leaf(leaf.hash()).await.await);
 
+            // Check the view index. Any views between this leaf and the previous one failed, and
+            // have no leaf.
+            let view = leaf.leaf().view_number();
+            assert_eq!(Some(leaf.clone()), ds.get_leaf_by_view(view).await.await);
+            if let Some(prev_view) = prev_view {
+                for skipped in prev_view + 1..view.u64() {
+                    tracing::info!(skipped, "looking up leaf for failed view");
+                    assert_eq!(
+                        None,
+                        ds.get_leaf_by_view(ViewNumber::new(skipped)).await.await
+                    );
+                }
+            }
+            prev_view = Some(view.u64());
+
             tracing::info!("looking up block {i} various ways");
             let block = ds.get_block(i).await.await;
             assert_eq!(leaf.block_hash(), block.hash());
@@ -221,6 +255,20 @@ pub mod availability_tests {
                     .await;
             }
 
+            // Check block summary lookup.
+            tracing::info!("looking up block summary {i}");
+            let expected_summary = BlockSummaryQueryData::from(block.clone());
+            assert_eq!(ds.get_block_summary(i).await.await, expected_summary);
+            assert_eq!(
+                ds.get_block_summary(block.hash()).await.await,
+                expected_summary
+            );
+
+            // Check header lookup.
+            tracing::info!("looking up header {i} various ways");
+            assert_eq!(ds.get_header(i).await.await, *block.header());
+            assert_eq!(ds.get_header(block.hash()).await.await, *block.header());
+
             // Look up the common VID data.
             tracing::info!("looking up VID common {i} various ways");
             let common = ds.get_vid_common(block.height() as usize).await.await;
@@ -350,6 +398,49 @@ pub mod availability_tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_subscribe_decides<D: TestableDataSource>() {
+        setup_test();
+
+        let mut network = MockNetwork::<D>::init().await;
+        let ds = network.data_source();
+        network.start().await;
+
+        // Submit a transaction and wait for the notification of the block which commits it.
+        let txn = mock_transaction(vec![42]);
+        network.submit_transaction(txn.clone()).await;
+        let mut decides = ds.subscribe_decides(0).await.enumerate();
+        let decide = loop {
+            let (i, decide) = decides.next().await.unwrap();
+            assert_eq!(decide.height(), i as u64);
+            if decide.transactions().contains(&txn.commit()) {
+                break decide;
+            }
+        };
+
+        // The notification describes the stored block.
+        let block = ds.get_block(decide.height() as usize).await.await;
+        assert_eq!(decide.block_hash(), block.hash());
+        assert_eq!(decide.timestamp(), block.header().timestamp());
+        assert_eq!(
+            decide.transactions(),
+            block
+                .enumerate()
+                .map(|(_, txn)| txn.commit())
+                .collect::<Vec<_>>()
+        );
+
+        // Resubscribing from the same height replays the same notification from storage.
+        assert_eq!(
+            ds.subscribe_decides(decide.height() as usize)
+                .await
+                .next()
+                .await
+                .unwrap(),
+            decide
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     pub async fn test_range<D: TestableDataSource>()
     where
@@ -448,6 +539,78 @@ pub mod availability_tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_range_rev<D: TestableDataSource>()
+    where
+        for<'a> D::ReadOnly<'a>: NodeStorage<MockTypes>,
+    {
+        setup_test();
+
+        let mut network = MockNetwork::<D>::init().await;
+        let ds = network.data_source();
+        network.start().await;
+
+        // Wait for there to be at least 3 blocks.
+        loop {
+            let mut tx = ds.read().await.unwrap();
+            let block_height = tx.block_height().await.unwrap();
+            if block_height >= 3 {
+                break;
+            }
+        }
+
+        // A bounded range is yielded in descending order.
+        do_range_rev_test(&ds, Bound::Included(1), 2, [2, 1]).await;
+        do_range_rev_test(&ds, Bound::Excluded(0), 2, [2, 1]).await;
+        do_range_rev_test(&ds, Bound::Unbounded, 2, [2, 1, 0]).await;
+
+        // An empty range yields nothing.
+        do_range_rev_test(&ds, Bound::Included(2), 1, []).await;
+        do_range_rev_test(&ds, Bound::Excluded(2), 2, []).await;
+
+        // An end beyond the current head starts from the most recent object. Since blocks are
+        // still being produced, we only check that the stream starts at or after the block height
+        // we observe first, and is strictly descending from there.
+        let block_height = {
+            let mut tx = ds.read().await.unwrap();
+            tx.block_height().await.unwrap() as u64
+        };
+        let leaves = ds
+            .get_leaf_range_rev(Bound::Unbounded, usize::MAX)
+            .await
+            .take(3)
+            .then(|fetch| fetch.resolve())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(leaves.len(), 3);
+        assert!(leaves[0].height() + 1 >= block_height);
+        for (prev, leaf) in leaves.iter().zip(&leaves[1..]) {
+            assert_eq!(leaf.height() + 1, prev.height());
+        }
+    }
+
+    async fn do_range_rev_test<D, I>(ds: &D, start: Bound<usize>, end: usize, expected_indices: I)
+    where
+        D: TestableDataSource,
+        I: IntoIterator<Item = u64>,
+    {
+        tracing::info!(?start, end, "testing reverse range");
+
+        let mut leaves = ds.get_leaf_range_rev(start, end).await;
+        let mut blocks = ds.get_block_range_rev(start, end).await;
+
+        for i in expected_indices {
+            tracing::info!(i, "check entries");
+            let leaf = leaves.next().await.unwrap().await;
+            let block = blocks.next().await.unwrap().await;
+            assert_eq!(leaf.height(), i);
+            assert_eq!(block.height(), i);
+            assert_eq!(block, ds.get_block(i as usize).await.await);
+        }
+        assert!(leaves.next().await.is_none());
+        assert!(blocks.next().await.is_none());
+    }
+
     // A wrapper around a range that turns the lower bound from inclusive to exclusive.
     #[derive(Clone, Copy, Debug)]
     struct ExRange<R>(R);
@@ -472,15 +635,18 @@ pub mod availability_tests {
 #[espresso_macros::generic_tests]
 pub mod persistence_tests {
     use crate::{
-        availability::{BlockQueryData, LeafQueryData},
+        availability::{BlockInfo, BlockQueryData, LeafQueryData, VidCommonQueryData},
         data_source::{
-            storage::{AvailabilityStorage, NodeStorage, UpdateAvailabilityStorage},
-            Transaction,
+            storage::{
+                AvailabilityStorage, InconsistentData, NodeStorage, Reorg,
+                UpdateAvailabilityStorage,
+            },
+            SnapshotDataSource, Transaction,
         },
         node::NodeDataSource,
         testing::{
             consensus::TestableDataSource,
-            mocks::{MockPayload, MockTypes},
+            mocks::{mock_transaction, MockPayload, MockTypes},
             setup_test,
         },
         types::HeightIndexed,
@@ -488,7 +654,12 @@ pub mod persistence_tests {
     };
     use committable::Committable;
     use hotshot_example_types::state_types::{TestInstanceState, TestValidatedState};
-    use hotshot_types::simple_certificate::QuorumCertificate;
+    use hotshot_types::{
+        simple_certificate::QuorumCertificate,
+        traits::block_contents::{EncodeBytes, GENESIS_VID_NUM_STORAGE_NODES},
+        vid::vid_scheme,
+    };
+    use jf_vid::VidScheme;
 
     #[tokio::test(flavor = "multi_thread")]
     pub async fn test_revert<D: TestableDataSource>()
@@ -544,6 +715,551 @@ pub mod persistence_tests {
         ds.get_block(1).await.try_resolve().unwrap_err();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_read_at_least<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>,
+        for<'a> D::ReadOnly<'a>: AvailabilityStorage<MockTypes> + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+
+        // Each commit gets a strictly greater marker, and a read at least at that marker sees the
+        // committed data.
+        let mut prev = None;
+        for height in 1..3 {
+            leaf.block_header_mut().block_number = height;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let leaf = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+
+            let mut tx = ds.write().await.unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            let marker = tx.commit_with_version().await.unwrap();
+            if let Some(prev) = prev {
+                assert!(marker > prev);
+            }
+            prev = Some(marker);
+
+            let mut tx = ds.read_at_least(marker).await.unwrap();
+            assert_eq!(tx.block_height().await.unwrap(), height as usize + 1);
+            assert_eq!(leaf, tx.get_leaf((height as usize).into()).await.unwrap());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_insert_leaves<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>
+            + AvailabilityStorage<MockTypes>
+            + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Mock up a short chain of leaves, not including the genesis leaf.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaves = vec![];
+        for _ in 0..3 {
+            leaf.block_header_mut().block_number += 1;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            leaves.push(LeafQueryData::new(leaf.clone(), qc.clone()).unwrap());
+        }
+
+        // Insert the whole batch and check that we can read it back.
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaves(leaves.clone()).await.unwrap();
+        assert_eq!(tx.block_height().await.unwrap(), 4);
+        for leaf in &leaves {
+            assert_eq!(
+                *leaf,
+                tx.get_leaf((leaf.height() as usize).into()).await.unwrap()
+            );
+        }
+
+        // Revert the changes; none of the batch should be stored.
+        tx.revert().await;
+        assert_eq!(
+            NodeDataSource::<MockTypes>::block_height(&ds)
+                .await
+                .unwrap(),
+            0
+        );
+        for leaf in &leaves {
+            ds.get_leaf(leaf.height() as usize)
+                .await
+                .try_resolve()
+                .unwrap_err();
+        }
+
+        // Insert the batch again and commit it.
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaves(leaves.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_append_chain<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>
+            + AvailabilityStorage<MockTypes>
+            + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Mock up a short chain of complete blocks, not including the genesis block.
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let payload = MockPayload::genesis();
+        let disperse = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES)
+            .disperse(payload.encode())
+            .unwrap();
+        let mut chain = vec![];
+        for i in 1..=3 {
+            let mut leaf = leaf.clone();
+            leaf.leaf.block_header_mut().block_number = i;
+            leaf.qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf.leaf);
+            let block = BlockQueryData::new(leaf.header().clone(), payload.clone());
+            let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common.clone());
+            chain.push(BlockInfo::new(
+                leaf,
+                Some(block),
+                Some(common),
+                Some(disperse.shares[0].clone()),
+            ));
+        }
+
+        // Append the whole chain at once, and check that every part of every block was stored.
+        ds.append_chain(chain.clone()).await.unwrap();
+        assert_eq!(
+            NodeDataSource::<MockTypes>::block_height(&ds)
+                .await
+                .unwrap(),
+            4
+        );
+        for info in chain.clone() {
+            let height = info.height() as usize;
+            assert_eq!(info.leaf, ds.get_leaf(height).await.await);
+            assert_eq!(info.block.unwrap(), ds.get_block(height).await.await);
+            assert_eq!(
+                info.vid_common.unwrap(),
+                ds.get_vid_common(height).await.await
+            );
+        }
+
+        // Re-delivering blocks which are already stored, in any order, is a no-op.
+        ds.append_chain(chain.clone()).await.unwrap();
+        ds.append_chain(vec![chain[1].clone(), chain[0].clone()])
+            .await
+            .unwrap();
+
+        // A chain which conflicts with the stored one is rejected, and none of it is stored.
+        let mut fork = chain[2].leaf.clone();
+        fork.leaf.block_header_mut().timestamp += 1;
+        fork.qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&fork.leaf);
+        let mut next = fork.clone();
+        next.leaf.block_header_mut().block_number = 4;
+        next.qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&next.leaf);
+        let err = ds
+            .append_chain(vec![
+                BlockInfo::new(fork.clone(), None, None, None),
+                BlockInfo::new(next, None, None, None),
+            ])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<Reorg<MockTypes>>().unwrap();
+        assert_eq!(err.height, 3);
+        assert_eq!(err.old_hash, chain[2].leaf.hash());
+        assert_eq!(err.new_hash, fork.hash());
+        assert_eq!(chain[2].leaf, ds.get_leaf(3).await.await);
+        assert_eq!(
+            NodeDataSource::<MockTypes>::block_height(&ds)
+                .await
+                .unwrap(),
+            4
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_reinsert_block<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>
+            + AvailabilityStorage<MockTypes>
+            + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Mock up a block, distinct from the genesis block.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        leaf.block_header_mut().block_number += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let block = BlockQueryData::new(leaf.block_header().clone(), MockPayload::genesis());
+        let leaf = LeafQueryData::new(leaf, qc).unwrap();
+
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaf(leaf.clone()).await.unwrap();
+        tx.insert_block(block.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+
+        // Re-inserting the same block is a no-op.
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_block(block.clone()).await.unwrap();
+        assert_eq!(block, tx.get_block(1.into()).await.unwrap());
+        tx.commit().await.unwrap();
+
+        // Inserting a different payload at the same height fails.
+        let payload = MockPayload {
+            transactions: vec![mock_transaction(vec![1, 2, 3])],
+        };
+        let conflicting = BlockQueryData::new(leaf.header().clone(), payload);
+        let mut tx = ds.write().await.unwrap();
+        let err = tx.insert_block(conflicting).await.unwrap_err();
+        let err = err.downcast_ref::<InconsistentData>().unwrap();
+        assert_eq!(err.height, 1);
+        assert_eq!(block, tx.get_block(1.into()).await.unwrap());
+        tx.revert().await;
+
+        // So does inserting a block with a different header at the same height.
+        let mut header = leaf.header().clone();
+        header.timestamp += 1;
+        let conflicting = BlockQueryData::new(header, MockPayload::genesis());
+        let mut tx = ds.write().await.unwrap();
+        let err = tx.insert_block(conflicting).await.unwrap_err();
+        let err = err.downcast_ref::<InconsistentData>().unwrap();
+        assert_eq!(err.height, 1);
+        tx.revert().await;
+
+        assert_eq!(block, ds.get_block(1).await.await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_reinsert_leaf<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>
+            + AvailabilityStorage<MockTypes>
+            + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Mock up two different leaves at the same height.
+        let qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        leaf.block_header_mut().block_number += 1;
+        let mut fork = leaf.clone();
+        fork.block_header_mut().timestamp += 1;
+        let make_leaf = |leaf: Leaf<MockTypes>| {
+            let mut qc = qc.clone();
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            LeafQueryData::new(leaf, qc).unwrap()
+        };
+        let leaf = make_leaf(leaf);
+        let fork = make_leaf(fork);
+
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaf(leaf.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+
+        // Re-inserting the same leaf is a no-op.
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaf(leaf.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+
+        // Inserting a different leaf at the same height is detected as a reorg.
+        let mut tx = ds.write().await.unwrap();
+        let err = tx.insert_leaf(fork.clone()).await.unwrap_err();
+        let err = err.downcast_ref::<Reorg<MockTypes>>().unwrap();
+        assert_eq!(err.height, 1);
+        assert_eq!(err.old_hash, leaf.hash());
+        assert_eq!(err.new_hash, fork.hash());
+        assert_eq!(leaf, tx.get_leaf(1.into()).await.unwrap());
+        tx.revert().await;
+
+        assert_eq!(leaf, ds.get_leaf(1).await.await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_read_your_writes<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>
+            + AvailabilityStorage<MockTypes>
+            + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Mock up a short chain of leaves, not including the genesis leaf.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+
+        // Interleave writes with reads in a single transaction. Each read must observe every write
+        // which preceded it, even though none of them have been committed.
+        let mut tx = ds.write().await.unwrap();
+        let mut leaves = vec![];
+        for i in 1..=3 {
+            leaf.block_header_mut().block_number = i;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let leaf = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            leaves.push(leaf);
+
+            assert_eq!(tx.block_height().await.unwrap(), i as usize + 1);
+            for leaf in &leaves {
+                assert_eq!(
+                    *leaf,
+                    tx.get_leaf((leaf.height() as usize).into()).await.unwrap()
+                );
+                assert_eq!(*leaf, tx.get_leaf(leaf.hash().into()).await.unwrap());
+                assert_eq!(
+                    *leaf.header(),
+                    tx.get_header((leaf.height() as usize).into())
+                        .await
+                        .unwrap()
+                );
+            }
+        }
+
+        // Once committed, the writes are visible outside the transaction too.
+        tx.commit().await.unwrap();
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn test_snapshot_roundtrip<D: TestableDataSource>()
+    where
+        for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<MockTypes>,
+        for<'a> D::ReadOnly<'a>: AvailabilityStorage<MockTypes> + NodeStorage<MockTypes>,
+    {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = D::connect(&storage).await;
+
+        // Generate a short chain of leaves, blocks, and VID data.
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let disperse = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES)
+            .disperse(block.payload().encode())
+            .unwrap();
+        let mut leaves = vec![];
+        let mut tx = ds.write().await.unwrap();
+        for i in 1..=3 {
+            let mut leaf = leaf.clone();
+            leaf.leaf.block_header_mut().block_number = i;
+            leaf.qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf.leaf);
+            leaves.push(leaf.clone());
+            let mut block = block.clone();
+            block.header.block_number = i;
+            let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common.clone());
+
+            tx.insert_leaf(leaf).await.unwrap();
+            tx.insert_block(block).await.unwrap();
+            // Leave out the VID data for one block, to check that missing objects are skipped.
+            if i != 2 {
+                tx.insert_vid(common, Some(disperse.shares[0].clone()))
+                    .await
+                    .unwrap();
+            }
+        }
+        tx.commit().await.unwrap();
+
+        let mut archive = vec![];
+        let summary = ds.export_snapshot(&mut archive).await.unwrap();
+        assert_eq!(summary.leaves, 3);
+        assert_eq!(summary.blocks, 3);
+        assert_eq!(summary.vid, 2);
+        assert_eq!(summary.first_height, Some(1));
+        assert_eq!(summary.last_height, Some(3));
+
+        // Restore the snapshot into a fresh data source.
+        let storage2 = D::create(1).await;
+        let ds2 = D::connect(&storage2).await;
+        assert_eq!(
+            ds2.import_snapshot(archive.as_slice()).await.unwrap(),
+            summary
+        );
+
+        let mut tx = ds.read().await.unwrap();
+        let mut tx2 = ds2.read().await.unwrap();
+        assert_eq!(
+            NodeStorage::<MockTypes>::block_height(&mut tx)
+                .await
+                .unwrap(),
+            NodeStorage::<MockTypes>::block_height(&mut tx2)
+                .await
+                .unwrap(),
+        );
+        for i in 1..=3 {
+            assert_eq!(
+                tx.get_leaf(i.into()).await.unwrap(),
+                tx2.get_leaf(i.into()).await.unwrap()
+            );
+            assert_eq!(
+                tx.get_block(i.into()).await.unwrap(),
+                tx2.get_block(i.into()).await.unwrap()
+            );
+            if i == 2 {
+                tx2.get_vid_common(i.into()).await.unwrap_err();
+            } else {
+                assert_eq!(
+                    tx.get_vid_common(i.into()).await.unwrap(),
+                    tx2.get_vid_common(i.into()).await.unwrap()
+                );
+                assert_eq!(
+                    tx.vid_share(i).await.unwrap(),
+                    tx2.vid_share(i).await.unwrap()
+                );
+            }
+        }
+
+        // A truncated archive is rejected.
+        let mut archive = vec![];
+        ds.export_snapshot(&mut archive).await.unwrap();
+        archive.truncate(archive.len() - 1);
+        let storage3 = D::create(2).await;
+        let ds3 = D::connect(&storage3).await;
+        ds3.import_snapshot(archive.as_slice()).await.unwrap_err();
+
+        // Importing the same snapshot again is a no-op.
+        assert_eq!(
+            ds2.import_snapshot(archive_copy(&ds).await.as_slice())
+                .await
+                .unwrap(),
+            summary
+        );
+
+        // A partial import can be resumed.
+        let storage4 = D::create(3).await;
+        let ds4 = D::connect(&storage4).await;
+        let mut tx = ds4.write().await.unwrap();
+        tx.insert_leaf(leaves[0].clone()).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            ds4.import_snapshot(archive_copy(&ds).await.as_slice())
+                .await
+                .unwrap(),
+            summary
+        );
+        for i in 1..=3 {
+            assert_eq!(ds.get_leaf(i).await.await, ds4.get_leaf(i).await.await);
+            assert_eq!(ds.get_block(i).await.await, ds4.get_block(i).await.await);
+        }
+
+        // A leaf which is not certified by its QC is rejected.
+        let storage5 = D::create(4).await;
+        let ds5 = D::connect(&storage5).await;
+        let mut forged = leaves[2].clone();
+        forged.leaf.block_header_mut().block_number = 4;
+        let mut tx = ds5.write().await.unwrap();
+        tx.insert_leaf(forged).await.unwrap();
+        tx.commit().await.unwrap();
+        let storage6 = D::create(5).await;
+        let ds6 = D::connect(&storage6).await;
+        ds6.import_snapshot(archive_copy(&ds5).await.as_slice())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            NodeDataSource::<MockTypes>::block_height(&ds6)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    async fn archive_copy<D: SnapshotDataSource<MockTypes>>(ds: &D) -> Vec<u8> {
+        let mut archive = vec![];
+        ds.export_snapshot(&mut archive).await.unwrap();
+        archive
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     pub async fn test_reset<D: TestableDataSource>()
     where
@@ -698,7 +1414,10 @@ pub mod node_tests {
             storage::{NodeStorage, UpdateAvailabilityStorage},
             update::Transaction,
         },
-        node::{BlockId, NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
+        node::{
+            BlockId, Gap, Gaps, NodeDataSource, SyncGaps, SyncStatus, TimeWindowQueryData,
+            WindowStart,
+        },
         testing::{
             consensus::{MockNetwork, TestableDataSource},
             mocks::{mock_transaction, MockPayload, MockTypes},
@@ -779,6 +1498,14 @@ pub mod node_tests {
         // At first, the node is fully synced.
         assert!(ds.sync_status().await.unwrap().is_fully_synced());
 
+        let gaps = |ranges: &[(usize, usize)]| Gaps {
+            ranges: ranges
+                .iter()
+                .map(|&(start, end)| Gap { start, end })
+                .collect(),
+            has_more: false,
+        };
+
         // Insert a leaf without the corresponding block or VID info, make sure we detect that the
         // block and VID info are missing.
         ds.append(leaves[0].clone().into()).await.unwrap();
@@ -790,6 +1517,14 @@ pub mod node_tests {
                 missing_vid_shares: 1,
                 missing_leaves: 0,
                 pruned_height: None,
+                latest_stored_height: Some(0),
+                lowest_stored_height: Some(0),
+                gaps: SyncGaps {
+                    leaves: gaps(&[]),
+                    blocks: gaps(&[(0, 1)]),
+                    vid: gaps(&[(0, 1)]),
+                },
+                ..Default::default()
             }
         );
 
@@ -804,6 +1539,14 @@ pub mod node_tests {
                 missing_vid_shares: 3,
                 missing_leaves: 1,
                 pruned_height: None,
+                latest_stored_height: Some(2),
+                lowest_stored_height: Some(0),
+                gaps: SyncGaps {
+                    leaves: gaps(&[(1, 2)]),
+                    blocks: gaps(&[(0, 3)]),
+                    vid: gaps(&[(0, 3)]),
+                },
+                ..Default::default()
             }
         );
 
@@ -821,6 +1564,14 @@ pub mod node_tests {
                 missing_vid_shares: 3,
                 missing_leaves: 1,
                 pruned_height: None,
+                latest_stored_height: Some(2),
+                lowest_stored_height: Some(0),
+                gaps: SyncGaps {
+                    leaves: gaps(&[(1, 2)]),
+                    blocks: gaps(&[(0, 3)]),
+                    vid: gaps(&[(1, 3)]),
+                },
+                ..Default::default()
             }
         );
 
@@ -861,6 +1612,19 @@ pub mod node_tests {
             missing_vid_common: 0,
             missing_vid_shares: expected_missing,
             pruned_height: None,
+            latest_stored_height: Some(2),
+            latest_view: None,
+            fully_synced: expected_missing == 0,
+            lowest_stored_height: Some(0),
+            gaps: SyncGaps {
+                leaves: if expected_missing == 0 {
+                    gaps(&[])
+                } else {
+                    gaps(&[(1, 2)])
+                },
+                blocks: gaps(&[]),
+                vid: gaps(&[]),
+            },
         };
         assert_eq!(ds.sync_status().await.unwrap(), expected_sync_status);
 
@@ -0,0 +1,710 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+use super::{CommitMarker, VersionedDataSource};
+use crate::{
+    availability::{
+        AvailabilityDataSource, BlockHash, BlockId, BlockInfo, BlockQueryData,
+        BlockSummaryQueryData, Fetch, LeafHash, LeafId, LeafQueryData, PayloadByteRange,
+        PayloadMetadata, PayloadQueryData, QueryablePayload, RawPayloadQueryData, TransactionHash,
+        TransactionQueryData, UpdateAvailabilityData, VidCommonMetadata, VidCommonQueryData,
+    },
+    metrics::PrometheusMetrics,
+    node::{NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
+    status::{HasMetrics, StatusDataSource},
+    types::HeightIndexed,
+    Header, Payload, QueryResult, VidShare,
+};
+use async_trait::async_trait;
+use derivative::Derivative;
+use futures::stream::BoxStream;
+use hotshot_types::traits::{
+    metrics::{Counter, Metrics},
+    node_implementation::NodeType,
+};
+use lru::LruCache;
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    ops::{Bound, Range, RangeBounds},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Wrapper which caches recently used leaves and headers in memory.
+///
+/// [`CachingDataSource`] sits in front of any [`AvailabilityDataSource`] and keeps the most
+/// recently requested leaves in an LRU cache, indexed by height, leaf hash, and block hash. Lookups
+/// which hit the cache are served without touching the underlying storage. Leaves added via
+/// [`UpdateAvailabilityData`] (and thus via [`UpdateDataSource::update`]) replace any cached entry
+/// at the same height, so the cache never serves a leaf which has since been superseded.
+///
+/// Only leaves which are available are cached. Requests which return a pending [`Fetch`] are
+/// forwarded to the underlying data source, and the result is added to the cache once it resolves.
+///
+/// The number of cache hits and misses is tracked in the `cache` subgroup of the underlying data
+/// source's [metrics](HasMetrics), so it is reported alongside the rest of the status metrics.
+///
+/// Note that the cache is only aware of data written through this wrapper. Writing a different
+/// leaf at an already-cached height directly through a [`VersionedDataSource`] transaction will
+/// not invalidate the cached entry.
+///
+/// [`UpdateDataSource::update`]: super::UpdateDataSource::update
+#[derive(Derivative)]
+#[derivative(Clone(bound = "D: Clone"), Debug(bound = "D: Debug"))]
+pub struct CachingDataSource<Types: NodeType, D> {
+    data_source: D,
+    #[derivative(Debug = "ignore")]
+    cache: Arc<LeafCache<Types>>,
+}
+
+impl<Types, D> CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: HasMetrics,
+{
+    /// Wrap `data_source` with a cache holding up to `capacity` leaves.
+    pub fn new(data_source: D, capacity: NonZeroUsize) -> Self {
+        let cache = Arc::new(LeafCache::new(capacity, data_source.metrics()));
+        Self { data_source, cache }
+    }
+}
+
+impl<Types: NodeType, D> CachingDataSource<Types, D> {
+    /// Access the underlying data source.
+    pub fn inner(&self) -> &D {
+        &self.data_source
+    }
+
+    /// Mutably access the underlying data source.
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.data_source
+    }
+
+    /// Drop all cached entries.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+}
+
+/// In-memory LRU cache of leaves.
+///
+/// The cache is shared by all clones of a [`CachingDataSource`]. All state is protected by a single
+/// mutex which is never held across an `await`, so concurrent readers only contend for the
+/// duration of a hash map operation.
+struct LeafCache<Types: NodeType> {
+    state: Mutex<LeafCacheState<Types>>,
+    hits: Box<dyn Counter>,
+    misses: Box<dyn Counter>,
+}
+
+struct LeafCacheState<Types: NodeType> {
+    leaves: LruCache<u64, LeafQueryData<Types>>,
+    by_leaf_hash: HashMap<LeafHash<Types>, u64>,
+    by_block_hash: HashMap<BlockHash<Types>, u64>,
+}
+
+impl<Types: NodeType> LeafCache<Types> {
+    fn new(capacity: NonZeroUsize, metrics: &PrometheusMetrics) -> Self {
+        let group = metrics.subgroup("cache".into());
+        Self {
+            state: Mutex::new(LeafCacheState {
+                leaves: LruCache::new(capacity),
+                by_leaf_hash: Default::default(),
+                by_block_hash: Default::default(),
+            }),
+            hits: group.create_counter("hits".into(), None),
+            misses: group.create_counter("misses".into(), None),
+        }
+    }
+
+    fn get(&self, id: LeafId<Types>) -> Option<LeafQueryData<Types>> {
+        let mut state = self.state.lock().unwrap();
+        let leaf = match id {
+            LeafId::Number(n) => state.leaves.get(&(n as u64)).cloned(),
+            LeafId::Hash(hash) => state.get_by(|state| state.by_leaf_hash.get(&hash).copied()),
+        };
+        self.record(leaf)
+    }
+
+    fn get_by_block_hash(&self, hash: BlockHash<Types>) -> Option<LeafQueryData<Types>> {
+        let mut state = self.state.lock().unwrap();
+        let leaf = state.get_by(|state| state.by_block_hash.get(&hash).copied());
+        self.record(leaf)
+    }
+
+    fn insert(&self, leaf: LeafQueryData<Types>) {
+        let mut state = self.state.lock().unwrap();
+        let height = leaf.height();
+        state.by_leaf_hash.insert(leaf.hash(), height);
+        state.by_block_hash.insert(leaf.block_hash(), height);
+        if let Some((old_height, old)) = state.leaves.push(height, leaf) {
+            // Either we evicted the least recently used leaf, or we replaced a leaf at the same
+            // height. Either way, the old leaf's index entries are now stale.
+            state.unindex(old_height, &old);
+        }
+    }
+
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.leaves.clear();
+        state.by_leaf_hash.clear();
+        state.by_block_hash.clear();
+    }
+
+    fn record(&self, leaf: Option<LeafQueryData<Types>>) -> Option<LeafQueryData<Types>> {
+        if leaf.is_some() {
+            self.hits.add(1);
+        } else {
+            self.misses.add(1);
+        }
+        leaf
+    }
+}
+
+impl<Types: NodeType> LeafCacheState<Types> {
+    fn get_by(&mut self, index: impl FnOnce(&Self) -> Option<u64>) -> Option<LeafQueryData<Types>> {
+        let height = index(self)?;
+        self.leaves.get(&height).cloned()
+    }
+
+    fn unindex(&mut self, height: u64, leaf: &LeafQueryData<Types>) {
+        let current = self
+            .leaves
+            .peek(&height)
+            .map(|current| (current.hash(), current.block_hash()));
+        if current.map(|(hash, _)| hash) != Some(leaf.hash())
+            && self.by_leaf_hash.get(&leaf.hash()) == Some(&height)
+        {
+            self.by_leaf_hash.remove(&leaf.hash());
+        }
+        if current.map(|(_, hash)| hash) != Some(leaf.block_hash())
+            && self.by_block_hash.get(&leaf.block_hash()) == Some(&height)
+        {
+            self.by_block_hash.remove(&leaf.block_hash());
+        }
+    }
+}
+
+impl<Types, D> VersionedDataSource for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: VersionedDataSource + Send,
+{
+    type Transaction<'a>
+        = D::Transaction<'a>
+    where
+        Self: 'a;
+
+    type ReadOnly<'a>
+        = D::ReadOnly<'a>
+    where
+        Self: 'a;
+
+    async fn write(&self) -> anyhow::Result<Self::Transaction<'_>> {
+        self.data_source.write().await
+    }
+
+    async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.data_source.read().await
+    }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.data_source.read_at_least(marker).await
+    }
+}
+
+#[async_trait]
+impl<Types, D> AvailabilityDataSource<Types> for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    D: AvailabilityDataSource<Types> + Send + Sync,
+{
+    type LeafRange<R>
+        = D::LeafRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+    type BlockRange<R>
+        = D::BlockRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+    type PayloadRange<R>
+        = D::PayloadRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+    type PayloadMetadataRange<R>
+        = D::PayloadMetadataRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+    type VidCommonRange<R>
+        = D::VidCommonRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+    type VidCommonMetadataRange<R>
+        = D::VidCommonMetadataRange<R>
+    where
+        R: RangeBounds<usize> + Send;
+
+    async fn get_leaf<ID>(&self, id: ID) -> Fetch<LeafQueryData<Types>>
+    where
+        ID: Into<LeafId<Types>> + Send + Sync,
+    {
+        let id = id.into();
+        if let Some(leaf) = self.cache.get(id) {
+            return Fetch::Ready(leaf);
+        }
+        let cache = self.cache.clone();
+        self.data_source.get_leaf(id).await.map(move |leaf| {
+            cache.insert(leaf.clone());
+            leaf
+        })
+    }
+    async fn get_block<ID>(&self, id: ID) -> Fetch<BlockQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_block(id).await
+    }
+    async fn get_payload<ID>(&self, id: ID) -> Fetch<PayloadQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_payload(id).await
+    }
+    async fn get_payload_metadata<ID>(&self, id: ID) -> Fetch<PayloadMetadata<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_payload_metadata(id).await
+    }
+    async fn get_raw_payload<ID>(&self, id: ID) -> Fetch<RawPayloadQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_raw_payload(id).await
+    }
+    async fn get_payload_bytes<ID>(
+        &self,
+        id: ID,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_payload_bytes(id, range).await
+    }
+    async fn get_block_summary<ID>(&self, id: ID) -> Fetch<BlockSummaryQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_block_summary(id).await
+    }
+    async fn get_header<ID>(&self, id: ID) -> Fetch<Header<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        // Headers are cached as part of their leaves, so a header looked up by height or block
+        // hash is served from memory if the corresponding leaf is cached.
+        match id.into() {
+            BlockId::Number(n) => self.get_leaf(n).await.map(|leaf| leaf.header().clone()),
+            BlockId::Hash(hash) => {
+                if let Some(leaf) = self.cache.get_by_block_hash(hash) {
+                    return Fetch::Ready(leaf.header().clone());
+                }
+                self.data_source.get_header(hash).await
+            }
+            id => {
+                // Headers are not indexed by payload hash; always go to the underlying data
+                // source.
+                self.cache.misses.add(1);
+                self.data_source.get_header(id).await
+            }
+        }
+    }
+    async fn earliest_height(&self) -> usize {
+        self.data_source.earliest_height().await
+    }
+    async fn earliest_block_height(&self) -> usize {
+        self.data_source.earliest_block_height().await
+    }
+    async fn get_vid_common<ID>(&self, id: ID) -> Fetch<VidCommonQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_vid_common(id).await
+    }
+    async fn get_vid_common_metadata<ID>(&self, id: ID) -> Fetch<VidCommonMetadata<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_vid_common_metadata(id).await
+    }
+    async fn get_vid_share<ID>(&self, id: ID, index: usize) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_vid_share(id, index).await
+    }
+    async fn get_leaf_by_view(&self, view: Types::View) -> Fetch<Option<LeafQueryData<Types>>> {
+        self.data_source.get_leaf_by_view(view).await
+    }
+    async fn get_leaf_range_by_time(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        self.data_source.get_leaf_range_by_time(start, end).await
+    }
+    async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_leaf_range(range).await
+    }
+    async fn get_block_range<R>(&self, range: R) -> Self::BlockRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_block_range(range).await
+    }
+    async fn get_payload_range<R>(&self, range: R) -> Self::PayloadRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_payload_range(range).await
+    }
+    async fn get_payload_metadata_range<R>(&self, range: R) -> Self::PayloadMetadataRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_payload_metadata_range(range).await
+    }
+    async fn get_block_summary_range<R>(
+        &self,
+        range: R,
+    ) -> BoxStream<'static, Fetch<BlockSummaryQueryData<Types>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_block_summary_range(range).await
+    }
+    async fn get_vid_common_range<R>(&self, range: R) -> Self::VidCommonRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_vid_common_range(range).await
+    }
+    async fn get_vid_common_metadata_range<R>(&self, range: R) -> Self::VidCommonMetadataRange<R>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_vid_common_metadata_range(range).await
+    }
+    async fn get_leaf_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<LeafQueryData<Types>>> {
+        self.data_source.get_leaf_range_rev(start, end).await
+    }
+    async fn get_block_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<BlockQueryData<Types>>> {
+        self.data_source.get_block_range_rev(start, end).await
+    }
+    async fn get_transaction(
+        &self,
+        hash: TransactionHash<Types>,
+    ) -> Fetch<TransactionQueryData<Types>> {
+        self.data_source.get_transaction(hash).await
+    }
+
+    async fn transaction_maybe_exists(&self, hash: TransactionHash<Types>) -> bool {
+        self.data_source.transaction_maybe_exists(hash).await
+    }
+}
+
+impl<Types, D> UpdateAvailabilityData<Types> for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: UpdateAvailabilityData<Types> + Send + Sync,
+{
+    async fn append(&self, info: BlockInfo<Types>) -> anyhow::Result<()> {
+        let leaf = info.leaf.clone();
+        self.data_source.append(info).await?;
+        self.cache.insert(leaf);
+        Ok(())
+    }
+
+    async fn append_chain<I>(&self, chain: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = BlockInfo<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        let chain = chain.into_iter().collect::<Vec<_>>();
+        let leaves = chain
+            .iter()
+            .map(|info| info.leaf.clone())
+            .collect::<Vec<_>>();
+        self.data_source.append_chain(chain).await?;
+        for leaf in leaves {
+            self.cache.insert(leaf);
+        }
+        Ok(())
+    }
+
+    fn genesis_vid_num_storage_nodes(&self) -> Option<usize> {
+        self.data_source.genesis_vid_num_storage_nodes()
+    }
+
+    fn record_update_latency(&self, latency: Duration) {
+        self.data_source.record_update_latency(latency)
+    }
+
+    fn record_missing_vid_at_decide(&self, count: usize) {
+        self.data_source.record_missing_vid_at_decide(count)
+    }
+
+    fn validate_payload(
+        &self,
+        header: &Header<Types>,
+        payload: &Payload<Types>,
+    ) -> anyhow::Result<()> {
+        self.data_source.validate_payload(header, payload)
+    }
+
+    async fn reject_payloads(&self, heights: Vec<u64>) -> anyhow::Result<()> {
+        self.data_source.reject_payloads(heights).await
+    }
+}
+
+#[async_trait]
+impl<Types, D> NodeDataSource<Types> for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: NodeDataSource<Types> + Send + Sync,
+{
+    async fn block_height(&self) -> QueryResult<usize> {
+        self.data_source.block_height().await
+    }
+    async fn count_transactions_in_range(
+        &self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        self.data_source.count_transactions_in_range(range).await
+    }
+    async fn payload_size_in_range(
+        &self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        self.data_source.payload_size_in_range(range).await
+    }
+    async fn vid_share<ID>(&self, id: ID) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.vid_share(id).await
+    }
+    async fn sync_status(&self) -> QueryResult<SyncStatus> {
+        self.data_source.sync_status().await
+    }
+    async fn get_header_window(
+        &self,
+        start: impl Into<WindowStart<Types>> + Send + Sync,
+        end: u64,
+        limit: usize,
+    ) -> QueryResult<TimeWindowQueryData<Header<Types>>> {
+        self.data_source.get_header_window(start, end, limit).await
+    }
+}
+
+impl<Types, D> HasMetrics for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: HasMetrics,
+{
+    fn metrics(&self) -> &PrometheusMetrics {
+        self.data_source.metrics()
+    }
+}
+
+#[async_trait]
+impl<Types, D> StatusDataSource for CachingDataSource<Types, D>
+where
+    Types: NodeType,
+    D: StatusDataSource + Send + Sync,
+{
+    async fn block_height(&self) -> QueryResult<usize> {
+        self.data_source.block_height().await
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+mod impl_testable_data_source {
+    use super::*;
+    use crate::{
+        data_source::UpdateDataSource,
+        testing::{
+            consensus::{DataSourceLifeCycle, TestableDataSource},
+            mocks::MockTypes,
+        },
+    };
+    use hotshot::types::Event;
+
+    /// A small capacity, so that the tests exercise eviction.
+    const TEST_CAPACITY: NonZeroUsize = match NonZeroUsize::new(5) {
+        Some(capacity) => capacity,
+        None => unreachable!(),
+    };
+
+    #[async_trait]
+    impl<D> DataSourceLifeCycle for CachingDataSource<MockTypes, D>
+    where
+        D: TestableDataSource + UpdateDataSource<MockTypes> + HasMetrics,
+    {
+        type Storage = D::Storage;
+
+        async fn create(node_id: usize) -> Self::Storage {
+            D::create(node_id).await
+        }
+
+        async fn connect(storage: &Self::Storage) -> Self {
+            Self::new(D::connect(storage).await, TEST_CAPACITY)
+        }
+
+        async fn reset(storage: &Self::Storage) -> Self {
+            Self::new(D::reset(storage).await, TEST_CAPACITY)
+        }
+
+        async fn handle_event(&self, event: &Event<MockTypes>) {
+            self.update(event).await.unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        data_source::{storage::UpdateAvailabilityStorage, Transaction as _},
+        testing::{
+            consensus::{DataSourceLifeCycle, MockDataSource},
+            mocks::MockTypes,
+            setup_test,
+        },
+    };
+    use hotshot_example_types::node_types::TestVersions;
+
+    // For some reason this is the only way to import the macro defined in another module of this
+    // crate.
+    use crate::*;
+
+    instantiate_data_source_tests!(CachingDataSource<MockTypes, MockDataSource>);
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cache_hits_and_invalidation() {
+        setup_test();
+
+        let storage = MockDataSource::create(0).await;
+        let inner = MockDataSource::connect(&storage).await;
+        let ds = CachingDataSource::<MockTypes, _>::new(inner, NonZeroUsize::new(2).unwrap());
+        let metrics = ds.metrics().get_subgroup(["cache"]).unwrap();
+        let hits = || metrics.get_counter("hits").unwrap().get();
+        let misses = || metrics.get_counter("misses").unwrap().get();
+
+        // Insert a few leaves directly into storage, bypassing the cache.
+        let mut tx = ds.write().await.unwrap();
+        let mut chain = vec![];
+        for i in 0..3 {
+            let mut leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+                &Default::default(),
+                &Default::default(),
+            )
+            .await;
+            leaf.leaf.block_header_mut().block_number = i;
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            chain.push(leaf);
+        }
+        tx.commit().await.unwrap();
+
+        // The first lookup misses, the second hits.
+        assert_eq!(ds.get_leaf(0).await.await, chain[0]);
+        assert_eq!((hits(), misses()), (0, 1));
+        assert_eq!(ds.get_leaf(0).await.await, chain[0]);
+        assert_eq!(ds.get_leaf(chain[0].hash()).await.await, chain[0]);
+        assert_eq!(
+            ds.get_header(chain[0].block_hash()).await.await,
+            *chain[0].header()
+        );
+        assert_eq!((hits(), misses()), (3, 1));
+
+        // Filling the cache evicts the least recently used entry.
+        ds.get_leaf(1).await.await;
+        ds.get_leaf(2).await.await;
+        assert_eq!((hits(), misses()), (3, 3));
+        assert_eq!(ds.get_leaf(chain[0].hash()).await.await, chain[0]);
+        assert_eq!((hits(), misses()), (3, 4));
+
+        // Appending a different leaf at a cached height replaces the cached entry.
+        let mut corrected = chain[2].clone();
+        corrected.leaf.block_header_mut().timestamp += 1;
+        ds.append(corrected.clone().into()).await.unwrap();
+        assert_eq!(ds.get_leaf(2).await.await, corrected);
+        assert_eq!(ds.get_leaf(corrected.hash()).await.await, corrected);
+        assert_eq!((hits(), misses()), (5, 4));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_header_cache_hits() {
+        setup_test();
+
+        let storage = MockDataSource::create(0).await;
+        let inner = MockDataSource::connect(&storage).await;
+        let ds = CachingDataSource::<MockTypes, _>::new(inner, NonZeroUsize::new(2).unwrap());
+        let metrics = ds.metrics().get_subgroup(["cache"]).unwrap();
+        let hits = || metrics.get_counter("hits").unwrap().get();
+        let misses = || metrics.get_counter("misses").unwrap().get();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &Default::default(),
+            &Default::default(),
+        )
+        .await;
+        let block =
+            BlockQueryData::<MockTypes>::genesis(&Default::default(), &Default::default()).await;
+        let mut tx = ds.write().await.unwrap();
+        tx.insert_leaf(leaf.clone()).await.unwrap();
+        tx.insert_block(block.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+
+        // Headers requested through the trait, as the API does, are served from the cached leaf.
+        async fn header<D: AvailabilityDataSource<MockTypes>>(
+            ds: &D,
+            id: impl Into<BlockId<MockTypes>> + Send + Sync,
+        ) -> Header<MockTypes> {
+            ds.get_header(id).await.await
+        }
+        assert_eq!(header(&ds, 0).await, *leaf.header());
+        assert_eq!((hits(), misses()), (0, 1));
+        assert_eq!(header(&ds, 0).await, *leaf.header());
+        assert_eq!(header(&ds, leaf.block_hash()).await, *leaf.header());
+        assert_eq!((hits(), misses()), (2, 1));
+
+        // Headers are not indexed by payload hash, so these always miss.
+        let id = BlockId::PayloadHash(block.payload_hash());
+        assert_eq!(header(&ds, id).await, *leaf.header());
+        assert_eq!((hits(), misses()), (2, 2));
+    }
+}
@@ -10,17 +10,18 @@
 // You should have received a copy of the GNU General Public License along with this program. If not,
 // see <https://www.gnu.org/licenses/>.
 
-use super::VersionedDataSource;
+use super::{CommitMarker, VersionedDataSource};
 use crate::{
     availability::{
-        AvailabilityDataSource, BlockId, BlockInfo, BlockQueryData, Fetch, LeafId, LeafQueryData,
-        PayloadMetadata, PayloadQueryData, QueryableHeader, QueryablePayload, TransactionHash,
+        AvailabilityDataSource, BlockId, BlockInfo, BlockQueryData, BlockSummaryQueryData, Fetch,
+        LeafId, LeafQueryData, PayloadByteRange, PayloadMetadata, PayloadQueryData,
+        QueryableHeader, QueryablePayload, RawPayloadQueryData, TransactionHash,
         TransactionQueryData, UpdateAvailabilityData, VidCommonMetadata, VidCommonQueryData,
     },
     explorer::{self, ExplorerDataSource, ExplorerHeader, ExplorerTransaction},
     merklized_state::{
         MerklizedState, MerklizedStateDataSource, MerklizedStateHeightPersistence, Snapshot,
-        UpdateStateData,
+        StateDiff, UpdateStateData,
     },
     metrics::PrometheusMetrics,
     node::{NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
@@ -28,10 +29,13 @@ use crate::{
     Header, Payload, QueryResult, Transaction, VidShare,
 };
 use async_trait::async_trait;
-use hotshot_types::traits::node_implementation::NodeType;
+use futures::stream::BoxStream;
+use hotshot_types::{simple_certificate::QuorumCertificate, traits::node_implementation::NodeType};
 use jf_merkle_tree::prelude::MerkleProof;
-use std::ops::RangeBounds;
-use tagged_base64::TaggedBase64;
+use std::{
+    ops::{Bound, Range, RangeBounds},
+    time::Duration,
+};
 
 /// Wrapper to add extensibility to an existing data source.
 ///
@@ -136,6 +140,10 @@ where
     async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
         self.data_source.read().await
     }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.data_source.read_at_least(marker).await
+    }
 }
 
 #[async_trait]
@@ -177,6 +185,12 @@ where
     {
         self.data_source.get_leaf(id).await
     }
+    async fn get_qc<ID>(&self, id: ID) -> Fetch<QuorumCertificate<Types>>
+    where
+        ID: Into<LeafId<Types>> + Send + Sync,
+    {
+        self.data_source.get_qc(id).await
+    }
     async fn get_block<ID>(&self, id: ID) -> Fetch<BlockQueryData<Types>>
     where
         ID: Into<BlockId<Types>> + Send + Sync,
@@ -195,6 +209,40 @@ where
     {
         self.data_source.get_payload_metadata(id).await
     }
+    async fn get_raw_payload<ID>(&self, id: ID) -> Fetch<RawPayloadQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_raw_payload(id).await
+    }
+    async fn get_payload_bytes<ID>(
+        &self,
+        id: ID,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_payload_bytes(id, range).await
+    }
+    async fn get_block_summary<ID>(&self, id: ID) -> Fetch<BlockSummaryQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_block_summary(id).await
+    }
+    async fn get_header<ID>(&self, id: ID) -> Fetch<Header<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_header(id).await
+    }
+    async fn earliest_height(&self) -> usize {
+        self.data_source.earliest_height().await
+    }
+    async fn earliest_block_height(&self) -> usize {
+        self.data_source.earliest_block_height().await
+    }
     async fn get_vid_common<ID>(&self, id: ID) -> Fetch<VidCommonQueryData<Types>>
     where
         ID: Into<BlockId<Types>> + Send + Sync,
@@ -207,6 +255,22 @@ where
     {
         self.data_source.get_vid_common_metadata(id).await
     }
+    async fn get_vid_share<ID>(&self, id: ID, index: usize) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.data_source.get_vid_share(id, index).await
+    }
+    async fn get_leaf_by_view(&self, view: Types::View) -> Fetch<Option<LeafQueryData<Types>>> {
+        self.data_source.get_leaf_by_view(view).await
+    }
+    async fn get_leaf_range_by_time(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        self.data_source.get_leaf_range_by_time(start, end).await
+    }
     async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
     where
         R: RangeBounds<usize> + Send + 'static,
@@ -231,6 +295,15 @@ where
     {
         self.data_source.get_payload_metadata_range(range).await
     }
+    async fn get_block_summary_range<R>(
+        &self,
+        range: R,
+    ) -> BoxStream<'static, Fetch<BlockSummaryQueryData<Types>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.data_source.get_block_summary_range(range).await
+    }
     async fn get_vid_common_range<R>(&self, range: R) -> Self::VidCommonRange<R>
     where
         R: RangeBounds<usize> + Send + 'static,
@@ -243,12 +316,30 @@ where
     {
         self.data_source.get_vid_common_metadata_range(range).await
     }
+    async fn get_leaf_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<LeafQueryData<Types>>> {
+        self.data_source.get_leaf_range_rev(start, end).await
+    }
+    async fn get_block_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<BlockQueryData<Types>>> {
+        self.data_source.get_block_range_rev(start, end).await
+    }
     async fn get_transaction(
         &self,
         hash: TransactionHash<Types>,
     ) -> Fetch<TransactionQueryData<Types>> {
         self.data_source.get_transaction(hash).await
     }
+
+    async fn transaction_maybe_exists(&self, hash: TransactionHash<Types>) -> bool {
+        self.data_source.transaction_maybe_exists(hash).await
+    }
 }
 
 impl<D, U, Types> UpdateAvailabilityData<Types> for ExtensibleDataSource<D, U>
@@ -260,6 +351,38 @@ where
     async fn append(&self, info: BlockInfo<Types>) -> anyhow::Result<()> {
         self.data_source.append(info).await
     }
+
+    async fn append_chain<I>(&self, chain: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = BlockInfo<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        self.data_source.append_chain(chain).await
+    }
+
+    fn genesis_vid_num_storage_nodes(&self) -> Option<usize> {
+        self.data_source.genesis_vid_num_storage_nodes()
+    }
+
+    fn record_update_latency(&self, latency: Duration) {
+        self.data_source.record_update_latency(latency)
+    }
+
+    fn record_missing_vid_at_decide(&self, count: usize) {
+        self.data_source.record_missing_vid_at_decide(count)
+    }
+
+    fn validate_payload(
+        &self,
+        header: &Header<Types>,
+        payload: &Payload<Types>,
+    ) -> anyhow::Result<()> {
+        self.data_source.validate_payload(header, payload)
+    }
+
+    async fn reject_payloads(&self, heights: Vec<u64>) -> anyhow::Result<()> {
+        self.data_source.reject_payloads(heights).await
+    }
 }
 
 #[async_trait]
@@ -339,6 +462,28 @@ where
     ) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>> {
         self.data_source.get_path(snapshot, key).await
     }
+
+    async fn get_path_batch(
+        &self,
+        snapshot: Snapshot<Types, State, ARITY>,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>> {
+        self.data_source.get_path_batch(snapshot, keys).await
+    }
+
+    async fn snapshot_for_height(&self, height: u64) -> QueryResult<Snapshot<Types, State, ARITY>> {
+        self.data_source.snapshot_for_height(height).await
+    }
+
+    async fn get_diff(
+        &self,
+        from: Snapshot<Types, State, ARITY>,
+        to: Snapshot<Types, State, ARITY>,
+        offset: usize,
+        limit: usize,
+    ) -> QueryResult<StateDiff<State::Key, State::Entry>> {
+        self.data_source.get_diff(from, to, offset, limit).await
+    }
 }
 
 #[async_trait]
@@ -405,6 +550,16 @@ where
         self.data_source.get_block_summaries(request).await
     }
 
+    async fn get_block_summary_page(
+        &self,
+        request: explorer::query_data::GetBlockSummaryPageRequest,
+    ) -> Result<
+        explorer::query_data::BlockSummaryPage<Types>,
+        explorer::query_data::GetBlockSummariesError,
+    > {
+        self.data_source.get_block_summary_page(request).await
+    }
+
     async fn get_transaction_detail(
         &self,
         request: explorer::query_data::TransactionIdentifier<Types>,
@@ -434,15 +589,31 @@ where
         self.data_source.get_explorer_summary().await
     }
 
+    async fn get_block_statistics(
+        &self,
+        request: explorer::query_data::GetBlockStatisticsRequest,
+    ) -> Result<explorer::query_data::BlockStatistics, explorer::query_data::GetBlockStatisticsError>
+    {
+        self.data_source.get_block_statistics(request).await
+    }
+
     async fn get_search_results(
         &self,
-        query: TaggedBase64,
+        query: explorer::query_data::SearchQuery,
     ) -> Result<
         explorer::query_data::SearchResult<Types>,
         explorer::query_data::GetSearchResultsError,
     > {
         self.data_source.get_search_results(query).await
     }
+
+    async fn get_search_match(
+        &self,
+        query: explorer::query_data::SearchQuery,
+    ) -> Result<explorer::query_data::SearchMatch<Types>, explorer::query_data::GetSearchResultsError>
+    {
+        self.data_source.get_search_match(query).await
+    }
 }
 
 #[cfg(any(test, feature = "testing"))]
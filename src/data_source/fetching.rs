@@ -54,6 +54,17 @@
 //! because it is rather unlikely that a major scan will discover any missing blocks that the next
 //! minor scan would have missed, it is ok if major scans run very infrequently.
 //!
+//! ## Backfill
+//!
+//! As an alternative (or a complement) to scanning, the data source can be configured to [backfill
+//! gaps](Builder::with_backfill) on a schedule. Instead of visiting every block in a range, the
+//! backfill task periodically asks storage exactly which heights are missing, using the same gap
+//! detection that powers the node API's sync status report, and fetches a bounded batch of the
+//! missing objects with a [bounded number](Builder::with_backfill_concurrency) of simultaneous
+//! requests, leaving room for fetches triggered by clients. Only heights below the current block
+//! height are ever backfilled; objects above the chain tip do not exist yet, so they cannot be
+//! considered missing.
+//!
 //! # Active Fetching
 //!
 //! Active fetching means reaching out to a remote data availability provider to retrieve a missing
@@ -64,6 +75,15 @@
 //! is less than the current chain height. We can fetch a block when the corresponding header exists
 //! (corresponding based on height, hash, or payload hash) or can be actively fetched.
 //!
+//! Concurrent requests for the same missing resource share a single active fetch: while a leaf,
+//! payload, or VID common object is being fetched, further requests for it wait for the result of
+//! the fetch in progress instead of sending their own request to the provider. The number of
+//! requests coalesced this way is counted in the `coalesced_fetches` metric, labeled by the kind of
+//! resource. By default, an active fetch is retried until it succeeds. If a
+//! [fetch timeout](Builder::with_fetch_timeout) is set and the provider keeps failing until it
+//! elapses, the fetch is abandoned instead: every request waiting on it fails with the same error
+//! (see [`Fetch::try_with_timeout`]), and the next request for the resource starts a new fetch.
+//!
 //! # Passive Fetching
 //!
 //! For requests that cannot be actively fetched (for example, a block requested by hash, where we
@@ -77,25 +97,28 @@ use super::{
     notifier::Notifier,
     storage::{
         pruning::{PruneStorage, PrunedHeightStorage},
-        Aggregate, AggregatesStorage, AvailabilityStorage, ExplorerStorage,
-        MerklizedStateHeightStorage, MerklizedStateStorage, NodeStorage, UpdateAggregatesStorage,
-        UpdateAvailabilityStorage,
+        Aggregate, AggregatesStorage, AvailabilityStorage, DeleteBlockStorage, ExplorerStorage,
+        MerklizedStateHeightStorage, MerklizedStateStorage, NodeStorage, PayloadTooLarge, Reorg,
+        UpdateAggregatesStorage, UpdateAvailabilityStorage,
     },
-    Transaction, VersionedDataSource,
+    update::{verify_block_info, PayloadValidator},
+    CommitMarker, Transaction, VersionedDataSource,
 };
 use crate::{
     availability::{
-        AvailabilityDataSource, BlockId, BlockInfo, BlockQueryData, Fetch, LeafId, LeafQueryData,
-        PayloadMetadata, PayloadQueryData, QueryableHeader, QueryablePayload, TransactionHash,
+        AvailabilityDataSource, BlockId, BlockInfo, BlockQueryData, BlockSummaryQueryData, Fetch,
+        LeafId, LeafQueryData, PayloadByteRange, PayloadMetadata, PayloadQueryData,
+        QueryableHeader, QueryablePayload, RawPayloadQueryData, TransactionHash,
         TransactionQueryData, UpdateAvailabilityData, VidCommonMetadata, VidCommonQueryData,
     },
     explorer::{self, ExplorerDataSource},
     fetching::{self, request, Provider},
     merklized_state::{
         MerklizedState, MerklizedStateDataSource, MerklizedStateHeightPersistence, Snapshot,
+        StateDiff,
     },
     metrics::PrometheusMetrics,
-    node::{NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
+    node::{Gaps, NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
     status::{HasMetrics, StatusDataSource},
     task::BackgroundTask,
     types::HeightIndexed,
@@ -112,41 +135,59 @@ use futures::{
     stream::{self, BoxStream, Stream, StreamExt},
 };
 use hotshot_types::traits::{
-    metrics::{Gauge, Metrics},
-    node_implementation::NodeType,
+    metrics::{Counter, CounterFamily, Gauge, Histogram, Metrics},
+    node_implementation::{ConsensusTime, NodeType},
 };
 use jf_merkle_tree::{prelude::MerkleProof, MerkleTreeScheme};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, RwLock,
+};
 use std::{
-    cmp::min,
+    cmp::{max, min},
     fmt::{Debug, Display},
     iter::repeat_with,
     marker::PhantomData,
     ops::{Bound, Range, RangeBounds},
-    time::Duration,
+    time::{Duration, Instant},
+};
+use tokio::{
+    spawn,
+    sync::broadcast::{self, error::RecvError},
+    time::sleep,
 };
-use tagged_base64::TaggedBase64;
-use tokio::{spawn, time::sleep};
 use tracing::Instrument;
 
 mod block;
+mod breaker;
 mod header;
 mod leaf;
+mod tap;
 mod transaction;
+mod transaction_filter;
 mod vid;
 
+pub use tap::{AvailabilityMutation, AvailabilityTap, ChannelTap};
+
 use self::{
     block::PayloadFetcher,
+    breaker::CircuitBreaker,
+    header::HeaderOnly,
     leaf::LeafFetcher,
     transaction::TransactionRequest,
+    transaction_filter::TransactionFilter,
     vid::{VidCommonFetcher, VidCommonRequest},
 };
 
 /// Builder for [`FetchingDataSource`] with configuration.
-pub struct Builder<Types, S, P> {
+pub struct Builder<Types, S, P>
+where
+    Types: NodeType,
+{
     storage: S,
     provider: P,
     backoff: ExponentialBackoffBuilder,
+    fetch_timeout: Option<Duration>,
     rate_limit: usize,
     range_chunk_size: usize,
     minor_scan_interval: Duration,
@@ -158,10 +199,25 @@ pub struct Builder<Types, S, P> {
     proactive_fetching: bool,
     aggregator: bool,
     aggregator_chunk_size: Option<usize>,
+    unavailable: Vec<Range<u64>>,
+    genesis_vid_num_storage_nodes: Option<usize>,
+    max_sync_gaps: usize,
+    max_payload_bytes: Option<u64>,
+    verify_inserts: bool,
+    transaction_filter: Option<usize>,
+    local_vid_recovery: bool,
+    backfill: Option<(Duration, usize)>,
+    backfill_concurrency: usize,
+    circuit_breaker: Option<(usize, Duration, Duration)>,
+    tap: Option<Arc<dyn AvailabilityTap<Types>>>,
+    payload_validator: Option<Arc<dyn PayloadValidator<Types>>>,
     _types: PhantomData<Types>,
 }
 
-impl<Types, S, P> Builder<Types, S, P> {
+impl<Types, S, P> Builder<Types, S, P>
+where
+    Types: NodeType,
+{
     /// Construct a new builder with the given storage and fetcher and the default options.
     pub fn new(storage: S, provider: P) -> Self {
         let mut default_backoff = ExponentialBackoffBuilder::default();
@@ -175,6 +231,7 @@ impl<Types, S, P> Builder<Types, S, P> {
             storage,
             provider,
             backoff: default_backoff,
+            fetch_timeout: None,
             rate_limit: 32,
             range_chunk_size: 25,
             // By default, we run minor proactive scans fairly frequently: once every minute. These
@@ -194,6 +251,22 @@ impl<Types, S, P> Builder<Types, S, P> {
             proactive_fetching: true,
             aggregator: true,
             aggregator_chunk_size: None,
+            unavailable: vec![],
+            genesis_vid_num_storage_nodes: None,
+            // Enough to give a useful picture of a node's gaps, while keeping sync status reports
+            // small and cheap to compute even for a node with a very fragmented history.
+            max_sync_gaps: 100,
+            max_payload_bytes: None,
+            verify_inserts: false,
+            transaction_filter: None,
+            local_vid_recovery: false,
+            backfill: None,
+            // Keep backfill well below the default fetch rate limit, so that most of the capacity
+            // remains available for fetches triggered by clients.
+            backfill_concurrency: 4,
+            circuit_breaker: None,
+            tap: None,
+            payload_validator: None,
             _types: Default::default(),
         }
     }
@@ -228,6 +301,17 @@ impl<Types, S, P> Builder<Types, S, P> {
         self
     }
 
+    /// Give up on active fetches which are still failing after `timeout`.
+    ///
+    /// When an active fetch is abandoned, requests waiting on it fail with the same error, and the
+    /// next request for the same resource starts a new fetch. Requests which are simply awaited,
+    /// such as the items of a stream, keep waiting for the object, so they still get it once it is
+    /// fetched again. By default, active fetches are retried until they succeed.
+    pub fn with_fetch_timeout(mut self, timeout: Duration) -> Self {
+        self.fetch_timeout = Some(timeout);
+        self
+    }
+
     /// Set the maximum number of simultaneous fetches.
     pub fn with_rate_limit(mut self, with_rate_limit: usize) -> Self {
         self.rate_limit = with_rate_limit;
@@ -240,6 +324,8 @@ impl<Types, S, P> Builder<Types, S, P> {
     /// * The number of objects to load from storage in a single request
     /// * The number of objects to buffer in memory per request/stream
     /// * The number of concurrent notification subscriptions per request/stream
+    ///
+    /// Streams load at most one chunk ahead of what the consumer has polled. The default is 25.
     pub fn with_range_chunk_size(mut self, range_chunk_size: usize) -> Self {
         self.range_chunk_size = range_chunk_size;
         self
@@ -328,6 +414,23 @@ impl<Types, S, P> Builder<Types, S, P> {
         self
     }
 
+    /// Periodically backfill gaps in storage.
+    ///
+    /// Every `interval`, find the heights missing from storage and fetch up to `batch_size` missing
+    /// objects of each type (leaves, payloads and VID common data).
+    ///
+    /// See [backfill](self#backfill).
+    pub fn with_backfill(mut self, interval: Duration, batch_size: usize) -> Self {
+        self.backfill = Some((interval, batch_size));
+        self
+    }
+
+    /// Set the maximum number of simultaneous fetches made by [backfill](Self::with_backfill).
+    pub fn with_backfill_concurrency(mut self, limit: usize) -> Self {
+        self.backfill_concurrency = limit;
+        self
+    }
+
     /// Run without an aggregator.
     ///
     /// This can reduce load on the CPU and the database, but it will cause aggregate statistics
@@ -349,6 +452,136 @@ impl<Types, S, P> Builder<Types, S, P> {
         self.aggregator_chunk_size = Some(chunk_size);
         self
     }
+
+    /// Never actively fetch objects at the given heights.
+    ///
+    /// This can be called multiple times to add several ranges. See
+    /// [`FetchingDataSource::mark_unavailable`].
+    pub fn with_unavailable_heights(mut self, heights: Range<u64>) -> Self {
+        self.unavailable.push(heights);
+        self
+    }
+
+    /// Set the number of storage nodes used for VID dispersal of the genesis block.
+    ///
+    /// HotShot does not run VID for the genesis block, so the data source computes the genesis VID
+    /// data itself, which only matches the genesis header if it uses the same VID parameters as
+    /// the rest of the network. If this is not set,
+    /// [`GENESIS_VID_NUM_STORAGE_NODES`](hotshot_types::traits::block_contents::GENESIS_VID_NUM_STORAGE_NODES)
+    /// is used, and a warning is logged when the genesis block is stored.
+    pub fn with_genesis_vid_num_storage_nodes(mut self, num_storage_nodes: usize) -> Self {
+        self.genesis_vid_num_storage_nodes = Some(num_storage_nodes);
+        self
+    }
+
+    /// Set the maximum number of missing ranges of each type of object reported by
+    /// [`sync_status`](NodeDataSource::sync_status).
+    pub fn with_max_sync_gaps(mut self, max_gaps: usize) -> Self {
+        self.max_sync_gaps = max_gaps;
+        self
+    }
+
+    /// Set the maximum size, in bytes, of a payload which can be appended to the data source.
+    ///
+    /// Appending a block whose payload is larger than this fails with
+    /// [`PayloadTooLarge`](super::storage::PayloadTooLarge), and nothing is stored. This guards
+    /// against malformed events whose payloads are too large to be reasonably persisted. The genesis
+    /// payload is always accepted. By default, there is no limit.
+    pub fn with_max_payload_bytes(mut self, limit: u64) -> Self {
+        self.max_payload_bytes = Some(limit);
+        self
+    }
+
+    /// Verify the consistency of each block before it is appended to the data source.
+    ///
+    /// When enabled, appending a block fails with [`InvalidData`](super::storage::InvalidData),
+    /// and nothing is stored, if the leaf is not certified by its own QC, or if the payload does
+    /// not match the payload commitment in the header. Checking the payload means recomputing its
+    /// VID commitment, which is expensive for large payloads, so this is disabled by default. It is
+    /// useful for nodes which update the data source with events from untrusted peers.
+    ///
+    /// Since the VID commitment depends on the number of storage nodes, which is only known from
+    /// the VID common data, a non-genesis block appended without VID common data is rejected.
+    pub fn with_verify_inserts(mut self, verify: bool) -> Self {
+        self.verify_inserts = verify;
+        self
+    }
+
+    /// Maintain a bloom filter over the hashes of all known transactions.
+    ///
+    /// The filter allows
+    /// [`transaction_maybe_exists`](AvailabilityDataSource::transaction_maybe_exists) to rule out
+    /// unknown transactions without querying storage. It is sized for `capacity` transactions;
+    /// beyond that, the false positive rate grows. The filter is kept in memory, and is rebuilt
+    /// from storage in the background each time the data source starts.
+    pub fn with_transaction_filter(mut self, capacity: usize) -> Self {
+        self.transaction_filter = Some(capacity);
+        self
+    }
+
+    /// Recompute missing VID common data locally before fetching it from a peer.
+    ///
+    /// When enabled, a request for VID common data which is missing from storage first tries to
+    /// recompute it by re-dispersing the block payload, if the payload is stored locally. The
+    /// number of storage nodes is taken from the nearest earlier block with VID common data. The
+    /// result is only accepted if its commitment matches the payload commitment in the header, in
+    /// which case it is persisted like fetched data; otherwise, the data is fetched from the
+    /// provider as usual. Dispersal is CPU-intensive, so this is disabled by default.
+    pub fn with_local_vid_recovery(mut self, enable: bool) -> Self {
+        self.local_vid_recovery = enable;
+        self
+    }
+
+    /// Stop sending requests to storage while it is persistently failing.
+    ///
+    /// After `threshold` consecutive failures to open a storage transaction, all within `window`
+    /// of the first, requests fail immediately with [`QueryError::Unavailable`] for `cooldown`,
+    /// instead of waiting on storage which is unlikely to respond. After the cooldown, a single
+    /// request is let through to probe storage, and normal operation resumes once it succeeds. The
+    /// state of the breaker is reported in the `storage_circuit_breaker` metric: 0 while closed
+    /// (requests allowed), 1 while open (requests rejected), and 2 while probing. By default, there
+    /// is no circuit breaker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    pub fn with_circuit_breaker(
+        mut self,
+        threshold: usize,
+        window: Duration,
+        cooldown: Duration,
+    ) -> Self {
+        assert!(threshold > 0, "circuit breaker threshold must be positive");
+        self.circuit_breaker = Some((threshold, window, cooldown));
+        self
+    }
+
+    /// Mirror every committed availability write to `tap`.
+    ///
+    /// Each leaf, block, and VID object stored by the data source, whether appended from consensus
+    /// or fetched from a peer, is emitted to `tap` as an [`AvailabilityMutation`] after the
+    /// transaction storing it commits. See [`AvailabilityTap`] for the ordering guarantees.
+    pub fn with_tap(mut self, tap: impl AvailabilityTap<Types> + 'static) -> Self {
+        self.tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Check block payloads with `validator` before storing them.
+    ///
+    /// A payload which `validator` rejects is never stored. For blocks decided by consensus, the
+    /// leaf is stored without the payload, and the height is reported in
+    /// [`UpdateSummary::rejected_payloads`](crate::data_source::UpdateSummary::rejected_payloads).
+    /// Payloads fetched from a peer are checked in the same way. Either way, the rejection is
+    /// recorded like a [deleted block](FetchingDataSource::delete_block), so that the payload is
+    /// not fetched again, and requests for it fail with
+    /// [`QueryError::Deleted`](crate::QueryError::Deleted). By default, all payloads are accepted.
+    pub fn with_payload_validator(
+        mut self,
+        validator: impl PayloadValidator<Types> + 'static,
+    ) -> Self {
+        self.payload_validator = Some(Arc::new(validator));
+        self
+    }
 }
 
 impl<Types, S, P> Builder<Types, S, P>
@@ -399,7 +632,23 @@ where
     scanner: Option<BackgroundTask>,
     // The aggregator task, which derives aggregate statistics from a block stream.
     aggregator: Option<BackgroundTask>,
+    // The backfill task, if enabled. This is only saved here so that we can cancel it on drop.
+    backfill: Option<BackgroundTask>,
+    // The task populating the transaction filter with existing transactions, if enabled.
+    transaction_filter: Option<BackgroundTask>,
     pruner: Pruner<Types, S, P>,
+    // The number of non-genesis decided blocks stored without VID data.
+    missing_vid_at_decide: Box<dyn Counter>,
+    // The time taken to process consensus events, from receipt until the decided blocks are stored.
+    update_latency: Box<dyn Histogram>,
+    // The configured number of storage nodes for genesis VID dispersal, if any.
+    genesis_vid_num_storage_nodes: Option<usize>,
+    // The maximum number of gaps of each type of object to report in the sync status.
+    max_sync_gaps: usize,
+    // The maximum size of a payload which can be appended, if any.
+    max_payload_bytes: Option<u64>,
+    // Whether to verify the consistency of each block before appending it.
+    verify_inserts: bool,
 }
 
 #[derive(Derivative)]
@@ -416,10 +665,11 @@ impl<Types, S, P> Pruner<Types, S, P>
 where
     Types: NodeType,
     Payload<Types>: QueryablePayload<Types>,
-    S: PruneStorage + Send + Sync + 'static,
+    S: VersionedDataSource + PruneStorage + Send + Sync + 'static,
+    for<'a> S::ReadOnly<'a>: PrunedHeightStorage,
     P: AvailabilityProvider<Types>,
 {
-    async fn new(fetcher: Arc<Fetcher<Types, S, P>>) -> Self {
+    async fn new(fetcher: Arc<Fetcher<Types, S, P>>, metrics: PrunerMetrics) -> Self {
         let cfg = fetcher.storage.get_pruning_config();
         let Some(cfg) = cfg else {
             return Self {
@@ -431,7 +681,7 @@ where
         let future = async move {
             for i in 1.. {
                 tracing::warn!("starting pruner run {i} ");
-                fetcher.prune().await;
+                fetcher.prune(&metrics).await;
                 sleep(cfg.interval()).await;
             }
         };
@@ -475,8 +725,35 @@ where
             .unwrap_or(builder.range_chunk_size);
         let scanner_metrics = ScannerMetrics::new(builder.storage.metrics());
         let aggregator_metrics = AggregatorMetrics::new(builder.storage.metrics());
-
-        let fetcher = Arc::new(Fetcher::new(builder).await?);
+        let pruner_metrics = PrunerMetrics::new(builder.storage.metrics());
+        let backfill_metrics = BackfillMetrics::new(builder.storage.metrics());
+        let backfill = builder.backfill;
+        let backfill_concurrency = builder.backfill_concurrency;
+        let missing_vid_at_decide = builder
+            .storage
+            .metrics()
+            .create_counter("missing_vid_at_decide".into(), None);
+        let update_latency = builder
+            .storage
+            .metrics()
+            .create_histogram("update_latency".into(), Some("s".into()));
+        let genesis_vid_num_storage_nodes = builder.genesis_vid_num_storage_nodes;
+        let max_sync_gaps = builder.max_sync_gaps;
+        let max_payload_bytes = builder.max_payload_bytes;
+        let verify_inserts = builder.verify_inserts;
+        let range_chunk_size = builder.range_chunk_size;
+        let breaker = builder
+            .circuit_breaker
+            .map(|(threshold, window, cooldown)| {
+                CircuitBreaker::new(threshold, window, cooldown, builder.storage.metrics())
+            });
+
+        let coalesced = builder
+            .storage
+            .metrics()
+            .counter_family("coalesced_fetches".into(), vec!["kind".into()]);
+
+        let fetcher = Arc::new(Fetcher::new(builder, breaker, coalesced).await?);
         let scanner = if proactive_fetching {
             Some(BackgroundTask::spawn(
                 "proactive scanner",
@@ -503,18 +780,100 @@ where
             None
         };
 
-        let pruner = Pruner::new(fetcher.clone()).await;
+        let backfill = backfill.map(|(interval, batch_size)| {
+            BackgroundTask::spawn(
+                "backfill",
+                fetcher.clone().backfill(
+                    interval,
+                    batch_size,
+                    backfill_concurrency,
+                    backfill_metrics,
+                ),
+            )
+        });
+
+        let transaction_filter = if fetcher.notifiers.transactions.is_enabled() {
+            Some(BackgroundTask::spawn(
+                "transaction filter",
+                fetcher.clone().build_transaction_filter(range_chunk_size),
+            ))
+        } else {
+            None
+        };
+
+        let pruner = Pruner::new(fetcher.clone(), pruner_metrics).await;
         let ds = Self {
             fetcher,
             scanner,
             pruner,
             aggregator,
+            backfill,
+            transaction_filter,
+            missing_vid_at_decide,
+            update_latency,
+            genesis_vid_num_storage_nodes,
+            max_sync_gaps,
+            max_payload_bytes,
+            verify_inserts,
         };
 
         Ok(ds)
     }
 }
 
+impl<Types, S, P> FetchingDataSource<Types, S, P>
+where
+    Types: NodeType,
+{
+    /// Mark objects at the given heights as permanently unavailable.
+    ///
+    /// Requests for objects in this range which are missing from local storage will no longer
+    /// trigger an active fetch from the provider; they will only resolve if the object is added to
+    /// storage some other way. This is useful for known gaps which no peer can fill, such as blocks
+    /// from before a provider's retention window, to prevent every request for such a block from
+    /// causing a futile fetch.
+    ///
+    /// The set of unavailable heights is kept in memory, and is not persisted across restarts. Use
+    /// [`Builder::with_unavailable_heights`] to configure it on startup.
+    pub fn mark_unavailable(&self, heights: Range<u64>) {
+        self.fetcher.unavailable.write().unwrap().push(heights);
+    }
+
+    /// Allow active fetching of all objects which were previously marked unavailable.
+    pub fn clear_unavailable(&self) {
+        self.fetcher.unavailable.write().unwrap().clear();
+    }
+}
+
+impl<Types, S, P> FetchingDataSource<Types, S, P>
+where
+    Types: NodeType,
+    S: VersionedDataSource,
+    for<'a> S::Transaction<'a>: DeleteBlockStorage<Types>,
+{
+    /// Delete the payload and VID data of a block, keeping its leaf and header.
+    ///
+    /// The deletion is committed in its own transaction (see
+    /// [`DeleteBlockStorage::delete_block`]), and the block is then marked
+    /// [unavailable](Self::mark_unavailable), so that requests for it do not fetch the deleted
+    /// data again. Deleted blocks are marked unavailable again whenever the data source starts up.
+    ///
+    /// Note that [`clear_unavailable`](Self::clear_unavailable) also clears this mark, but even so,
+    /// storage refuses to restore the data of a deleted block.
+    pub async fn delete_block(&self, id: BlockId<Types>) -> anyhow::Result<()> {
+        let mut tx = self
+            .fetcher
+            .storage
+            .write()
+            .await
+            .context("opening transaction")?;
+        let height = tx.delete_block(id).await?;
+        tx.commit().await.context("committing transaction")?;
+        self.mark_unavailable(height..height + 1);
+        Ok(())
+    }
+}
+
 impl<Types, S, P> AsRef<S> for FetchingDataSource<Types, S, P>
 where
     Types: NodeType,
@@ -543,9 +902,7 @@ where
     P: Send + Sync,
 {
     async fn block_height(&self) -> QueryResult<usize> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.block_height().await
     }
 }
@@ -613,6 +970,50 @@ where
         self.fetcher.get(id.into()).await
     }
 
+    async fn get_raw_payload<ID>(&self, id: ID) -> Fetch<RawPayloadQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.fetcher.get(id.into()).await
+    }
+
+    async fn get_payload_bytes<ID>(
+        &self,
+        id: ID,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        let mut tx = self.read().await?;
+        tx.get_payload_bytes(id.into(), range).await
+    }
+
+    async fn get_block_summary<ID>(&self, id: ID) -> Fetch<BlockSummaryQueryData<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.fetcher.get(id.into()).await
+    }
+
+    async fn get_header<ID>(&self, id: ID) -> Fetch<Header<Types>>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.fetcher
+            .get::<HeaderOnly<Types>>(id.into())
+            .await
+            .map(|header| header.0)
+    }
+
+    async fn earliest_height(&self) -> usize {
+        self.fetcher.earliest_height.load(Ordering::Acquire) as usize
+    }
+
+    async fn earliest_block_height(&self) -> usize {
+        self.fetcher.earliest_block_height.load(Ordering::Acquire) as usize
+    }
+
     async fn get_vid_common<ID>(&self, id: ID) -> Fetch<VidCommonQueryData<Types>>
     where
         ID: Into<BlockId<Types>> + Send + Sync,
@@ -627,6 +1028,62 @@ where
         self.fetcher.get(VidCommonRequest::from(id.into())).await
     }
 
+    async fn get_vid_share<ID>(&self, id: ID, index: usize) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        let mut tx = self.read().await?;
+        tx.get_vid_share(id.into(), index).await
+    }
+
+    async fn get_leaf_range_by_time(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        let mut tx = self.read().await?;
+        tx.get_leaf_range_by_time(start, end).await
+    }
+
+    async fn get_leaf_by_view(&self, view: Types::View) -> Fetch<Option<LeafQueryData<Types>>> {
+        let view = view.u64();
+        let height = match self.read().await {
+            Ok(mut tx) => match tx.get_leaf_by_view(view).await {
+                Ok(leaf) => return Fetch::Ready(Some(leaf)),
+                Err(QueryError::NotFound) => tx.block_height().await.unwrap_or(0),
+                Err(err) => {
+                    tracing::warn!(view, "error looking up leaf by view: {err:#}");
+                    tx.block_height().await.unwrap_or(0)
+                }
+            },
+            Err(err) => {
+                tracing::warn!(
+                    view,
+                    "unable to open transaction to look up leaf by view: {err:#}"
+                );
+                0
+            }
+        };
+
+        // There is no leaf from `view` in storage. Follow the chain from the latest leaf we have
+        // until we see a leaf from `view`, or from a later view, meaning `view` produced no leaf.
+        let mut leaves = self.subscribe_leaves(height.saturating_sub(1)).await;
+        Fetch::Pending(
+            async move {
+                while let Some(leaf) = leaves.next().await {
+                    let leaf_view = leaf.leaf().view_number().u64();
+                    if leaf_view == view {
+                        return Some(leaf);
+                    } else if leaf_view > view {
+                        return None;
+                    }
+                }
+                None
+            }
+            .boxed(),
+        )
+    }
+
     async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
     where
         R: RangeBounds<usize> + Send + 'static,
@@ -655,6 +1112,16 @@ where
         self.fetcher.clone().get_range(range)
     }
 
+    async fn get_block_summary_range<R>(
+        &self,
+        range: R,
+    ) -> BoxStream<'static, Fetch<BlockSummaryQueryData<Types>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.fetcher.clone().get_range(range)
+    }
+
     async fn get_vid_common_range<R>(&self, range: R) -> Self::VidCommonRange<R>
     where
         R: RangeBounds<usize> + Send + 'static,
@@ -669,12 +1136,32 @@ where
         self.fetcher.clone().get_range(range)
     }
 
+    async fn get_leaf_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<LeafQueryData<Types>>> {
+        self.fetcher.clone().get_range_rev(start, end).await
+    }
+
+    async fn get_block_range_rev(
+        &self,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<BlockQueryData<Types>>> {
+        self.fetcher.clone().get_range_rev(start, end).await
+    }
+
     async fn get_transaction(
         &self,
         hash: TransactionHash<Types>,
     ) -> Fetch<TransactionQueryData<Types>> {
         self.fetcher.get(TransactionRequest::from(hash)).await
     }
+
+    async fn transaction_maybe_exists(&self, hash: TransactionHash<Types>) -> bool {
+        self.fetcher.notifiers.transactions.maybe_contains(hash)
+    }
 }
 
 impl<Types, S, P> UpdateAvailabilityData<Types> for FetchingDataSource<Types, S, P>
@@ -687,29 +1174,100 @@ where
     P: AvailabilityProvider<Types>,
 {
     async fn append(&self, info: BlockInfo<Types>) -> anyhow::Result<()> {
-        let height = info.height() as usize;
-        let fetch_block = info.block.is_none();
-        let fetch_vid = info.vid_common.is_none();
+        self.append_chain([info]).await
+    }
 
-        self.fetcher.store_and_notify(info).await;
+    fn genesis_vid_num_storage_nodes(&self) -> Option<usize> {
+        self.genesis_vid_num_storage_nodes
+    }
 
-        if fetch_block || fetch_vid {
-            // If data related to this block is missing, try and fetch it. Do this in an async task:
-            // we're triggering a fire-and-forget fetch; we don't need to block the caller on this.
-            let fetcher = self.fetcher.clone();
-            let span = tracing::info_span!("fetch missing data", height);
-            spawn(
-                async move {
-                    tracing::info!(fetch_block, fetch_vid, "fetching missing data");
-                    if fetch_block {
-                        fetcher.get::<PayloadMetadata<Types>>(height).await;
-                    }
-                    if fetch_vid {
-                        fetcher.get::<VidCommonMetadata<Types>>(height).await;
+    fn record_update_latency(&self, latency: Duration) {
+        self.update_latency.add_point(latency.as_secs_f64());
+    }
+
+    fn record_missing_vid_at_decide(&self, count: usize) {
+        if count > 0 {
+            self.missing_vid_at_decide.add(count);
+        }
+    }
+
+    fn validate_payload(
+        &self,
+        header: &Header<Types>,
+        payload: &Payload<Types>,
+    ) -> anyhow::Result<()> {
+        self.fetcher.validate_payload(header, payload)
+    }
+
+    async fn reject_payloads(&self, heights: Vec<u64>) -> anyhow::Result<()> {
+        for height in heights {
+            self.fetcher.reject_payload(height).await?;
+        }
+        Ok(())
+    }
+
+    async fn append_chain<I>(&self, chain: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = BlockInfo<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        let chain = chain.into_iter().collect::<Vec<_>>();
+
+        // Reject oversized payloads before storing any part of the chain. This uses the payload
+        // size recorded when the block was constructed, so we never need to re-encode the payload.
+        if let Some(limit) = self.max_payload_bytes {
+            for block in chain.iter().filter_map(|info| info.block.as_ref()) {
+                if block.height() != 0 && block.size() > limit {
+                    return Err(PayloadTooLarge {
+                        height: block.height(),
+                        size: block.size(),
+                        limit,
                     }
+                    .into());
                 }
-                .instrument(span),
-            );
+            }
+        }
+
+        if self.verify_inserts {
+            for info in &chain {
+                verify_block_info(info, self.genesis_vid_num_storage_nodes)?;
+            }
+        }
+
+        let missing = chain
+            .iter()
+            .map(|info| {
+                (
+                    info.height() as usize,
+                    info.block.is_none(),
+                    info.vid_common.is_none(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Store the whole chain in a single transaction.
+        self.fetcher.store_and_notify_all(chain).await?;
+
+        for (height, fetch_block, fetch_vid) in missing {
+            if fetch_block || fetch_vid {
+                // If data related to this block is missing, try and fetch it. Do this in an async
+                // task: we're triggering a fire-and-forget fetch; we don't need to block the caller
+                // on this.
+                let fetcher = self.fetcher.clone();
+                let span = tracing::info_span!("fetch missing data", height);
+                spawn(
+                    async move {
+                        tracing::info!(fetch_block, fetch_vid, "fetching missing data");
+                        if fetch_block {
+                            fetcher.get::<PayloadMetadata<Types>>(height).await;
+                        }
+                        if fetch_vid {
+                            fetcher.get::<VidCommonMetadata<Types>>(height).await;
+                        }
+                    }
+                    .instrument(span),
+                );
+            }
         }
         Ok(())
     }
@@ -737,10 +1295,15 @@ where
     async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
         self.fetcher.read().await
     }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.fetcher.read_at_least(marker).await
+    }
 }
 
 /// Asynchronous retrieval and storage of [`Fetchable`] resources.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug(bound = "S: Debug, P: Debug"))]
 struct Fetcher<Types, S, P>
 where
     Types: NodeType,
@@ -758,9 +1321,26 @@ where
     chunk_fetch_delay: Duration,
     // Exponential backoff when retrying failed oeprations.
     backoff: ExponentialBackoff,
+    // Time after which active fetches are abandoned, if any.
+    fetch_timeout: Option<Duration>,
     // Semaphore limiting the number of simultaneous DB accesses we can have from tasks spawned to
     // retry failed loads.
     retry_semaphore: Arc<Semaphore>,
+    // Heights for which we never trigger active fetches.
+    unavailable: RwLock<Vec<Range<u64>>>,
+    // Whether to try recomputing missing VID common data locally before fetching it.
+    local_vid_recovery: bool,
+    // The earliest heights for which leaves and payloads have not been pruned. These are loaded
+    // from storage on startup and updated after each pruner run.
+    earliest_height: AtomicU64,
+    earliest_block_height: AtomicU64,
+    // Circuit breaker for opening storage transactions, if enabled.
+    breaker: Option<CircuitBreaker>,
+    // Sink for committed availability writes, if any.
+    tap: Option<Arc<dyn AvailabilityTap<Types>>>,
+    // Policy check for payloads before they are stored, if any.
+    #[derivative(Debug = "ignore")]
+    payload_validator: Option<Arc<dyn PayloadValidator<Types>>>,
 }
 
 impl<Types, S, P> VersionedDataSource for Fetcher<Types, S, P>
@@ -779,11 +1359,24 @@ where
         Self: 'a;
 
     async fn write(&self) -> anyhow::Result<Self::Transaction<'_>> {
-        self.storage.write().await
+        match &self.breaker {
+            Some(breaker) => breaker.guard(self.storage.write()).await,
+            None => self.storage.write().await,
+        }
     }
 
     async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
-        self.storage.read().await
+        match &self.breaker {
+            Some(breaker) => breaker.guard(self.storage.read()).await,
+            None => self.storage.read().await,
+        }
+    }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        match &self.breaker {
+            Some(breaker) => breaker.guard(self.storage.read_at_least(marker)).await,
+            None => self.storage.read_at_least(marker).await,
+        }
     }
 }
 
@@ -793,17 +1386,59 @@ where
     S: VersionedDataSource + Sync,
     for<'a> S::ReadOnly<'a>: PrunedHeightStorage + NodeStorage<Types>,
 {
-    async fn new(builder: Builder<Types, S, P>) -> anyhow::Result<Self> {
+    async fn new(
+        builder: Builder<Types, S, P>,
+        breaker: Option<CircuitBreaker>,
+        coalesced: Box<dyn CounterFamily>,
+    ) -> anyhow::Result<Self> {
         let retry_semaphore = Arc::new(Semaphore::new(builder.rate_limit));
         let backoff = builder.backoff.build();
 
-        let payload_fetcher = fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone());
-        let leaf_fetcher = fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone());
-        let vid_common_fetcher = fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone());
+        // Concurrent requests for the same resource are coalesced into a single fetch. Count the
+        // requests which join a fetch already in progress, by kind of resource.
+        let mut payload_fetcher = fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone())
+            .with_coalesced_counter(coalesced.create(vec!["payload".into()]));
+        let mut leaf_fetcher = fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone())
+            .with_coalesced_counter(coalesced.create(vec!["leaf".into()]));
+        let mut vid_common_fetcher =
+            fetching::Fetcher::new(retry_semaphore.clone(), backoff.clone())
+                .with_coalesced_counter(coalesced.create(vec!["vid_common".into()]));
+        if let Some(timeout) = builder.fetch_timeout {
+            payload_fetcher = payload_fetcher.with_timeout(timeout);
+            leaf_fetcher = leaf_fetcher.with_timeout(timeout);
+            vid_common_fetcher = vid_common_fetcher.with_timeout(timeout);
+        }
+
+        let block_height = {
+            let mut tx = builder.storage.read().await?;
+            tx.block_height().await? as u64
+        };
+        let pruned = PrunedHeights::load(&builder.storage).await?;
+
+        // Blocks which were deleted on demand must never be fetched again.
+        let mut unavailable = builder.unavailable;
+        {
+            let mut tx = builder.storage.read().await?;
+            unavailable.extend(
+                tx.load_deleted_heights()
+                    .await
+                    .context("loading deleted heights")?
+                    .into_iter()
+                    .map(|h| h..h + 1),
+            );
+        }
+        let notifiers = Notifiers {
+            transactions: builder
+                .transaction_filter
+                .map(TransactionFilter::new)
+                .unwrap_or_default(),
+            block_height: AtomicU64::new(block_height),
+            ..Default::default()
+        };
 
         Ok(Self {
             storage: builder.storage,
-            notifiers: Default::default(),
+            notifiers,
             provider: Arc::new(builder.provider),
             payload_fetcher: Arc::new(payload_fetcher),
             leaf_fetcher: Arc::new(leaf_fetcher),
@@ -812,7 +1447,15 @@ where
             active_fetch_delay: builder.active_fetch_delay,
             chunk_fetch_delay: builder.chunk_fetch_delay,
             backoff,
+            fetch_timeout: builder.fetch_timeout,
             retry_semaphore,
+            unavailable: RwLock::new(unavailable),
+            local_vid_recovery: builder.local_vid_recovery,
+            earliest_height: AtomicU64::new(pruned.earliest_height()),
+            earliest_block_height: AtomicU64::new(pruned.earliest_block_height()),
+            breaker,
+            tap: builder.tap,
+            payload_validator: builder.payload_validator,
         })
     }
 }
@@ -826,6 +1469,18 @@ where
     for<'a> S::ReadOnly<'a>: AvailabilityStorage<Types> + NodeStorage<Types> + PrunedHeightStorage,
     P: AvailabilityProvider<Types>,
 {
+    /// Wait for the active fetch for the requested object to be abandoned, if it can be.
+    ///
+    /// Active fetches are only abandoned if a [fetch timeout](Builder::with_fetch_timeout) is set.
+    /// Otherwise, this returns [`None`] without subscribing to failures.
+    fn wait_for_failure<T>(&self, req: T::Request) -> Option<BoxFuture<'static, QueryError>>
+    where
+        T: Fetchable<Types>,
+    {
+        self.fetch_timeout?;
+        self.notifiers.wait_for_failure::<T>(req)
+    }
+
     async fn get<T>(self: &Arc<Self>, req: impl Into<T::Request> + Send) -> Fetch<T>
     where
         T: Fetchable<Types>,
@@ -841,11 +1496,16 @@ where
         // Note the "someone" who later fetches the object and adds it to storage may be an active
         // fetch triggered by this very requests, in cases where that is possible, but it need not
         // be.
+        //
+        // For the same reason, if active fetches can be abandoned, we subscribe to failures, so
+        // that if the fetch triggered by this request is abandoned, we find out about it.
         let passive_fetch = T::passive_fetch(&self.notifiers, req).await;
+        let failure = self.wait_for_failure::<T>(req);
 
         match self.try_get(req).await {
-            Ok(Some(obj)) => return Fetch::Ready(obj),
-            Ok(None) => return passive(req, passive_fetch),
+            Ok(Lookup::Ready(obj)) => return Fetch::Ready(obj),
+            Ok(Lookup::Pending) => return passive(req, passive_fetch, failure),
+            Ok(Lookup::Gone(err)) => return Fetch::Failed(err),
             Err(err) => {
                 tracing::warn!(
                     ?req,
@@ -872,7 +1532,7 @@ where
                         fetcher.try_get(req).await
                     };
                     match res {
-                        Ok(Some(obj)) => {
+                        Ok(Lookup::Ready(obj)) => {
                             // If the object was immediately available after all, signal the
                             // original fetch. We probably just temporarily couldn't access it due
                             // to database errors.
@@ -880,13 +1540,20 @@ where
                             send.send(obj).ok();
                             break;
                         }
-                        Ok(None) => {
+                        Ok(Lookup::Pending) => {
                             // The object was not immediately available after all, but we have
                             // successfully spawned a fetch for it if possible. The spawned fetch
                             // will notify the original request once it completes.
                             tracing::info!(?req, "spawned fetch after retries");
                             break;
                         }
+                        Ok(Lookup::Gone(err)) => {
+                            // The original fetch has already been returned as pending, so there is
+                            // no way to report this error to it. It will simply never resolve,
+                            // just like a fetch for an object that never existed.
+                            tracing::warn!(?req, "object is no longer available: {err:#}");
+                            break;
+                        }
                         Err(err) => {
                             tracing::warn!(
                                 ?req,
@@ -906,32 +1573,44 @@ where
 
         // Wait for the object to be fetched, either from the local database on retry or from
         // another provider eventually.
-        passive(req, select_some(passive_fetch, recv.map(Result::ok)))
+        passive(
+            req,
+            select_some(passive_fetch, recv.map(Result::ok)),
+            failure,
+        )
     }
 
     /// Try to get an object from local storage or initialize a fetch if it is missing.
     ///
-    /// There are three possible scenarios in this function, indicated by the return type:
-    /// * `Ok(Some(obj))`: the requested object was available locally and successfully retrieved
-    ///   from the database; no fetch was spawned
-    /// * `Ok(None)`: the requested object was not available locally, but a fetch was successfully
-    ///   spawned if possible (in other words, if a fetch was not spawned, it was determined that
-    ///   the requested object is not fetchable)
+    /// There are four possible scenarios in this function, indicated by the return type:
+    /// * `Ok(Lookup::Ready(obj))`: the requested object was available locally and successfully
+    ///   retrieved from the database; no fetch was spawned
+    /// * `Ok(Lookup::Pending)`: the requested object was not available locally, but a fetch was
+    ///   successfully spawned if possible (in other words, if a fetch was not spawned, it was
+    ///   determined that the requested object is not fetchable)
+    /// * `Ok(Lookup::Gone(err))`: the requested object was intentionally discarded, and will never
+    ///   be available; no fetch was spawned
     /// * `Err(_)`: it could not be determined whether the object was available locally or whether
     ///   it could be fetched; no fetch was spawned even though the object may be fetchable
-    async fn try_get<T>(self: &Arc<Self>, req: T::Request) -> anyhow::Result<Option<T>>
+    async fn try_get<T>(self: &Arc<Self>, req: T::Request) -> anyhow::Result<Lookup<T>>
     where
         T: Fetchable<Types>,
     {
         let mut tx = self.read().await.context("opening read transaction")?;
         match T::load(&mut tx, req).await {
-            Ok(t) => Ok(Some(t)),
+            Ok(t) => Ok(Lookup::Ready(t)),
             Err(QueryError::Missing | QueryError::NotFound) => {
                 // We successfully queried the database, but the object wasn't there. Try to
                 // fetch it.
                 tracing::debug!(?req, "object missing from local storage, will try to fetch");
-                self.fetch::<T>(&mut tx, req).await?;
-                Ok(None)
+                self.fetch::<T>(&mut tx, req).await
+            }
+            Err(
+                err @ (QueryError::PayloadPruned | QueryError::VidPruned | QueryError::Deleted),
+            ) => {
+                // The object was intentionally discarded, so there is no point fetching it again.
+                tracing::debug!(?req, "object was pruned from local storage, will not fetch");
+                Ok(Lookup::Gone(err))
             }
             Err(err) => {
                 // An error occurred while querying the database. We don't know if we need to fetch
@@ -996,14 +1675,70 @@ where
                     // Introduce a delay (`active_fetch_delay`) for active fetches to reduce load on
                     // the catchup provider. The delay applies between pending fetches, not between
                     // chunks.
-                    Fetch::Pending(_) => sleep(active_fetch_delay).await,
-                    Fetch::Ready(_) => (),
+                    Fetch::Pending(_) | Fetch::Abandonable(..) => sleep(active_fetch_delay).await,
+                    Fetch::Ready(_) | Fetch::Failed(_) => (),
                 };
                 f
             })
             .boxed()
     }
 
+    /// Get a range of objects in descending order, from `end` (inclusive) down to `start`.
+    ///
+    /// `end` is clamped to the current block height, so this never waits for objects which have
+    /// not been produced yet. Like [`get_range`](Self::get_range), objects are loaded in chunks,
+    /// and each chunk is only loaded when the stream is polled.
+    async fn get_range_rev<T>(
+        self: Arc<Self>,
+        start: Bound<usize>,
+        end: usize,
+    ) -> BoxStream<'static, Fetch<T>>
+    where
+        T: RangedFetchable<Types>,
+    {
+        let block_height = match self.storage.read().await {
+            Ok(mut tx) => tx.block_height().await,
+            Err(err) => Err(QueryError::Error {
+                message: err.to_string(),
+            }),
+        };
+        let block_height = match block_height {
+            Ok(height) => height,
+            Err(err) => {
+                tracing::warn!("unable to load block height for reverse range: {err:#}");
+                return stream::empty().boxed();
+            }
+        };
+        if block_height == 0 {
+            return stream::empty().boxed();
+        }
+        // Convert to an exclusive end bound, clamped to the block height.
+        let end = min(end, block_height - 1) + 1;
+
+        let chunk_size = self.range_chunk_size;
+        let chunk_fetch_delay = self.chunk_fetch_delay;
+        let active_fetch_delay = self.active_fetch_delay;
+
+        stream::iter(range_chunks_rev(start, end, chunk_size))
+            .then(move |chunk| {
+                let self_clone = self.clone();
+                async move {
+                    let chunk = self_clone.get_chunk(chunk).await.collect::<Vec<_>>().await;
+                    // As in `get_range_with_chunk_size`, delay between chunks to limit load.
+                    sleep(chunk_fetch_delay).await;
+                    stream::iter(chunk.into_iter().rev())
+                }
+            })
+            .flatten()
+            .then(move |f| async move {
+                if let Fetch::Pending(_) | Fetch::Abandonable(..) = f {
+                    sleep(active_fetch_delay).await;
+                }
+                f
+            })
+            .boxed()
+    }
+
     /// Get a range of objects from local storage or a provider.
     ///
     /// This method is similar to `get_range`, except that:
@@ -1023,17 +1758,28 @@ where
                 .map(|i| T::passive_fetch(&self.notifiers, i.into())),
         )
         .await;
+        let failures = chunk
+            .clone()
+            .map(|i| self.wait_for_failure::<T>(i.into()))
+            .collect::<Vec<_>>();
 
         match self.try_get_chunk(&chunk).await {
             Ok(objs) => {
-                // Convert to fetches. Objects which are not immediately available (`None` in the
+                // Convert to fetches. Objects which are not immediately available (`Pending` in the
                 // chunk) become passive fetches awaiting a notification of availability.
-                return stream::iter(objs.into_iter().zip(passive_fetches).enumerate().map(
-                    move |(i, (obj, passive_fetch))| match obj {
-                        Some(obj) => Fetch::Ready(obj),
-                        None => passive(T::Request::from(chunk.start + i), passive_fetch),
-                    },
-                ))
+                return stream::iter(
+                    objs.into_iter()
+                        .zip(passive_fetches)
+                        .zip(failures)
+                        .enumerate()
+                        .map(move |(i, ((obj, passive_fetch), failure))| match obj {
+                            Lookup::Ready(obj) => Fetch::Ready(obj),
+                            Lookup::Pending => {
+                                passive(T::Request::from(chunk.start + i), passive_fetch, failure)
+                            }
+                            Lookup::Gone(err) => Fetch::Failed(err),
+                        }),
+                )
                 .boxed();
             }
             Err(err) => {
@@ -1068,18 +1814,39 @@ where
                         match res {
                             Ok(objs) => {
                                 for (i, (obj, sender)) in objs.into_iter().zip(send).enumerate() {
-                                    if let Some(obj) = obj {
-                                        // If the object was immediately available after all, signal
-                                        // the original fetch. We probably just temporarily couldn't
-                                        // access it due to database errors.
-                                        tracing::info!(?chunk, i, "object was ready after retries");
-                                        sender.send(obj).ok();
-                                    } else {
-                                        // The object was not immediately available after all, but
-                                        // we have successfully spawned a fetch for it if possible.
-                                        // The spawned fetch will notify the original request once
-                                        // it completes.
-                                        tracing::info!(?chunk, i, "spawned fetch after retries");
+                                    match obj {
+                                        Lookup::Ready(obj) => {
+                                            // If the object was immediately available after all,
+                                            // signal the original fetch. We probably just
+                                            // temporarily couldn't access it due to database
+                                            // errors.
+                                            tracing::info!(
+                                                ?chunk,
+                                                i,
+                                                "object was ready after retries"
+                                            );
+                                            sender.send(obj).ok();
+                                        }
+                                        Lookup::Pending => {
+                                            // The object was not immediately available after all,
+                                            // but we have successfully spawned a fetch for it if
+                                            // possible. The spawned fetch will notify the original
+                                            // request once it completes.
+                                            tracing::info!(
+                                                ?chunk,
+                                                i,
+                                                "spawned fetch after retries"
+                                            );
+                                        }
+                                        Lookup::Gone(err) => {
+                                            // As in `get`, the original fetch cannot be failed
+                                            // after the fact.
+                                            tracing::warn!(
+                                                ?chunk,
+                                                i,
+                                                "object is no longer available: {err:#}"
+                                            );
+                                        }
                                     }
                                 }
                                 break;
@@ -1104,24 +1871,31 @@ where
 
         // Wait for the objects to be fetched, either from the local database on retry or from
         // another provider eventually.
-        stream::iter(passive_fetches.into_iter().zip(recv).enumerate().map(
-            move |(i, (passive_fetch, recv))| {
-                passive(
-                    T::Request::from(chunk.start + i),
-                    select_some(passive_fetch, recv.map(Result::ok)),
-                )
-            },
-        ))
+        stream::iter(
+            passive_fetches
+                .into_iter()
+                .zip(recv)
+                .zip(failures)
+                .enumerate()
+                .map(move |(i, ((passive_fetch, recv), failure))| {
+                    passive(
+                        T::Request::from(chunk.start + i),
+                        select_some(passive_fetch, recv.map(Result::ok)),
+                        failure,
+                    )
+                }),
+        )
         .boxed()
     }
 
     /// Try to get a range of objects from local storage, intializing fetches if any are missing.
     ///
     /// If this function succeeded, then for each object in the requested range, either:
-    /// * the object was available locally, and corresponds to `Some(_)` object in the result
-    /// * the object was not available locally (and corresponds to `None` in the result), but a
-    ///   fetch was successfully spawned if possible (in other words, if a fetch was not spawned, it
-    ///   was determined that the requested object is not fetchable)
+    /// * the object was available locally, and corresponds to [`Lookup::Ready`] in the result
+    /// * the object was not available locally (and corresponds to [`Lookup::Pending`] in the
+    ///   result), but a fetch was successfully spawned if possible (in other words, if a fetch was
+    ///   not spawned, it was determined that the requested object is not fetchable)
+    /// * the object was intentionally discarded, and corresponds to [`Lookup::Gone`] in the result
     ///
     /// This function will fail if it could not be determined which objects in the requested range
     /// are available locally, or if, for any missing object, it could not be determined whether
@@ -1130,7 +1904,7 @@ where
     async fn try_get_chunk<T>(
         self: &Arc<Self>,
         chunk: &Range<usize>,
-    ) -> anyhow::Result<Vec<Option<T>>>
+    ) -> anyhow::Result<Vec<Lookup<T>>>
     where
         T: RangedFetchable<Types>,
     {
@@ -1139,7 +1913,7 @@ where
             .await
             .context(format!("when fetching items in range {chunk:?}"))?;
 
-        // Log and discard error information; we want a list of Option where None indicates an
+        // Log and discard error information; we want a list of objects where gaps indicate an
         // object that needs to be fetched. Note that we don't use `FetchRequest::might_exist` to
         // silence the logs here when an object is missing that is not expected to exist at all.
         // When objects are not expected to exist, `load_range` should just return a truncated list
@@ -1156,18 +1930,20 @@ where
                     "item {} in chunk not available, will be fetched",
                     results.len()
                 );
-                self.fetch::<T>(&mut tx, (chunk.start + results.len()).into())
+                let lookup = self
+                    .fetch::<T>(&mut tx, (chunk.start + results.len()).into())
                     .await?;
-                results.push(None);
+                results.push(lookup);
             }
 
-            results.push(Some(t));
+            results.push(Lookup::Ready(t));
         }
         // Fetch missing objects from the end of the range.
         while results.len() < chunk.len() {
-            self.fetch::<T>(&mut tx, (chunk.start + results.len()).into())
+            let lookup = self
+                .fetch::<T>(&mut tx, (chunk.start + results.len()).into())
                 .await?;
-            results.push(None);
+            results.push(lookup);
         }
 
         Ok(results)
@@ -1176,13 +1952,14 @@ where
     /// Spawn an active fetch for the requested object, if possible.
     ///
     /// On success, either an active fetch for `req` has been spawned, or it has been determined
-    /// that `req` is not fetchable. Fails if it cannot be determined (e.g. due to errors in the
-    /// local database) whether `req` is fetchable or not.
+    /// that `req` is not fetchable. If `req` is not fetchable because it was pruned, the result is
+    /// [`Lookup::Gone`], otherwise it is [`Lookup::Pending`]. Fails if it cannot be determined (e.g.
+    /// due to errors in the local database) whether `req` is fetchable or not.
     async fn fetch<T>(
         self: &Arc<Self>,
         tx: &mut <Self as VersionedDataSource>::ReadOnly<'_>,
         req: T::Request,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<Lookup<T>>
     where
         T: Fetchable<Types>,
     {
@@ -1192,12 +1969,26 @@ where
         let heights = Heights::load(tx)
             .await
             .context("failed to load heights; cannot definitively say object might exist")?;
-        if req.might_exist(heights) {
-            T::active_fetch(tx, self.clone(), req).await?;
-        } else {
+        if !req.might_exist(heights) {
             tracing::debug!("not fetching object {req:?} that cannot exist at {heights:?}");
+        } else if let Some(err) = T::pruned(req, heights) {
+            tracing::debug!("not fetching object {req:?} that was pruned at {heights:?}");
+            return Ok(Lookup::Gone(err));
+        } else if req.height().is_some_and(|h| self.is_unavailable(h)) {
+            tracing::debug!("not fetching object {req:?} that was marked unavailable");
+        } else {
+            T::active_fetch(tx, self.clone(), req).await?;
         }
-        Ok(())
+        Ok(Lookup::Pending)
+    }
+
+    /// Has the object at height `h` been marked as permanently unavailable?
+    fn is_unavailable(&self, h: u64) -> bool {
+        self.unavailable
+            .read()
+            .unwrap()
+            .iter()
+            .any(|range| range.contains(&h))
     }
 
     /// Proactively search for and retrieve missing objects.
@@ -1318,12 +2109,17 @@ where
                 metrics.add_missing_blocks(major, missing_blocks);
 
                 // We have to trigger a separate fetch of the VID data, since this is fetched
-                // independently of the block payload.
+                // independently of the block payload. VID data which has been pruned is not fetched,
+                // so we skip over those blocks entirely.
+                let vid_start = match heights.vid_pruned_height {
+                    Some(h) => max(start, h as usize + 1),
+                    None => start,
+                };
                 let mut vid = self
                     .clone()
                     .get_range_with_chunk_size::<_, VidCommonMetadata<Types>>(
                         chunk_size,
-                        start..block_height,
+                        vid_start..block_height,
                     );
                 let mut missing_vid = 0;
                 while let Some(fetch) = vid.next().await {
@@ -1355,6 +2151,108 @@ where
             .await;
         }
     }
+
+    /// Periodically find gaps in storage and fetch the missing objects.
+    ///
+    /// This function will run until cancelled, thus, it is meant to be spawned as a background task
+    /// rather than called synchronously.
+    async fn backfill(
+        self: Arc<Self>,
+        interval: Duration,
+        batch_size: usize,
+        concurrency: usize,
+        metrics: BackfillMetrics,
+    ) {
+        for i in 0.. {
+            sleep(interval).await;
+            let span = tracing::info_span!("backfill", i);
+            async {
+                // Gap detection only considers heights below the current block height, so we never
+                // try to backfill objects above the chain tip.
+                let gaps = match self.read().await {
+                    Ok(mut tx) => tx.sync_gaps(batch_size).await,
+                    Err(err) => Err(QueryError::Error {
+                        message: format!("{err:#}"),
+                    }),
+                };
+                let gaps = match gaps {
+                    Ok(gaps) => gaps,
+                    Err(err) => {
+                        tracing::warn!("unable to find gaps to backfill: {err:#}");
+                        return;
+                    }
+                };
+
+                // Each gap contains at least one missing height, so we found enough gaps to fill a
+                // whole batch for each type of object.
+                let heights = |gaps: &Gaps| {
+                    gaps.ranges
+                        .iter()
+                        .flat_map(|gap| gap.start..gap.end)
+                        .take(batch_size)
+                        .collect::<Vec<_>>()
+                };
+                let leaves = heights(&gaps.leaves);
+                let blocks = heights(&gaps.blocks);
+                let vid = heights(&gaps.vid);
+                let missing = leaves.len() + blocks.len() + vid.len();
+                metrics.missing.set(missing);
+                if missing == 0 {
+                    return;
+                }
+                tracing::info!(
+                    leaves = leaves.len(),
+                    blocks = blocks.len(),
+                    vid = vid.len(),
+                    "backfilling missing objects"
+                );
+                metrics.found.add(missing);
+
+                // Objects which are not fetched within one interval are not counted as filled. The
+                // fetch continues in the background, and if it never succeeds, the object will be
+                // found missing again in the next round.
+                let fetches = leaves
+                    .into_iter()
+                    .map(|h| self.backfill_one::<LeafQueryData<Types>>(h, interval))
+                    .chain(
+                        blocks
+                            .into_iter()
+                            .map(|h| self.backfill_one::<PayloadMetadata<Types>>(h, interval)),
+                    )
+                    .chain(
+                        vid.into_iter()
+                            .map(|h| self.backfill_one::<VidCommonMetadata<Types>>(h, interval)),
+                    );
+                let metrics = &metrics;
+                stream::iter(fetches)
+                    .for_each_concurrent(concurrency, |fetch| async move {
+                        if fetch.await {
+                            metrics.filled.add(1);
+                        }
+                    })
+                    .await;
+            }
+            .instrument(span)
+            .await;
+        }
+    }
+
+    /// Fetch the object at `height`, waiting up to `timeout` for it to become available.
+    ///
+    /// Returns whether the object was available within the timeout.
+    fn backfill_one<T>(self: &Arc<Self>, height: usize, timeout: Duration) -> BoxFuture<'_, bool>
+    where
+        T: RangedFetchable<Types>,
+    {
+        async move {
+            self.get::<T>(height)
+                .await
+                .with_timeout(timeout)
+                .await
+                .is_some()
+        }
+        .boxed()
+    }
 }
 
 impl<Types, S, P> Fetcher<Types, S, P>
@@ -1368,6 +2266,52 @@ where
     P: AvailabilityProvider<Types>,
 {
     #[tracing::instrument(skip_all)]
+    /// Add the transactions of all blocks already in storage to the transaction filter.
+    ///
+    /// Blocks which become available while this is running are added to the filter as they are
+    /// stored, so once this completes, the filter covers every stored transaction.
+    async fn build_transaction_filter(self: Arc<Self>, chunk_size: usize) {
+        let height = loop {
+            let res = async {
+                let mut tx = self.read().await.context("opening transaction")?;
+                anyhow::Result::<_>::Ok(tx.block_height().await?)
+            }
+            .await;
+            match res {
+                Ok(height) => break height,
+                Err(err) => {
+                    tracing::error!("unable to load block height: {err:#}");
+                    sleep(Duration::from_secs(5)).await;
+                }
+            }
+        };
+
+        tracing::info!(height, "building transaction filter");
+        for chunk in range_chunks(0..height, chunk_size) {
+            let blocks = loop {
+                let res = async {
+                    let mut tx = self.read().await.context("opening transaction")?;
+                    anyhow::Result::<_>::Ok(tx.get_block_range(chunk.clone()).await?)
+                }
+                .await;
+                match res {
+                    Ok(blocks) => break blocks,
+                    Err(err) => {
+                        tracing::warn!(?chunk, "unable to load blocks: {err:#}");
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            };
+            // Blocks missing from storage are not known transactions; they will be added to the
+            // filter if and when they are fetched.
+            for block in blocks.into_iter().flatten() {
+                self.notifiers.transactions.insert_block(&block);
+            }
+        }
+        self.notifiers.transactions.set_ready();
+        tracing::info!(height, "transaction filter ready");
+    }
+
     async fn aggregate(self: Arc<Self>, chunk_size: usize, metrics: AggregatorMetrics) {
         loop {
             let prev_aggregate = loop {
@@ -1453,38 +2397,102 @@ where
     S: VersionedDataSource,
     for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
 {
+    /// Check a payload against the configured validator, if any.
+    fn validate_payload(
+        &self,
+        header: &Header<Types>,
+        payload: &Payload<Types>,
+    ) -> anyhow::Result<()> {
+        match &self.payload_validator {
+            Some(validator) => validator.validate(header, payload),
+            None => Ok(()),
+        }
+    }
+
+    /// Record that the payload at `height` was rejected by the validator.
+    ///
+    /// The height is marked unavailable, so that neither requests for the block nor the proactive
+    /// scanner fetch the payload again, and the rejection is persisted (see
+    /// [`UpdateAvailabilityStorage::reject_payload`]) so that this survives a restart.
+    async fn reject_payload(&self, height: u64) -> anyhow::Result<()> {
+        self.unavailable.write().unwrap().push(height..height + 1);
+        let mut tx = self.storage.write().await.context("opening transaction")?;
+        tx.reject_payload(height).await?;
+        tx.commit().await.context("committing transaction")
+    }
+
     /// Store an object and notify anyone waiting on this object that it is available.
     async fn store_and_notify<T>(&self, obj: T)
     where
         T: Storable<Types>,
     {
+        // A fetched object which conflicts with storage, or which could not be stored, has already
+        // been logged, and waiters have been notified with it where possible.
+        self.store_and_notify_all(vec![obj]).await.ok();
+    }
+
+    /// Store a batch of objects atomically and notify anyone waiting on them that they are
+    /// available.
+    ///
+    /// Transient storage errors are retried with backoff. If the retries are exhausted, waiters are
+    /// still notified with the objects we have in memory, but the last storage error is returned,
+    /// so that callers who need the objects to be durable can tell they are not. If any of `objs`
+    /// conflicts with a different leaf already in storage, nothing is stored or notified, and this
+    /// fails with [`Reorg`]: retrying cannot resolve a conflict, and notifying would hand waiters an
+    /// object from the wrong chain.
+    async fn store_and_notify_all<T>(&self, objs: Vec<T>) -> anyhow::Result<()>
+    where
+        T: Storable<Types>,
+    {
+        let (Some(first), Some(last)) = (objs.first(), objs.last()) else {
+            return Ok(());
+        };
+        let (first, last) = (first.height(), last.height());
+
         let try_store = || async {
             let mut tx = self.storage.write().await?;
-            obj.clone().store(&mut tx).await?;
+            T::store_all(objs.clone(), &mut tx).await?;
             tx.commit().await
         };
 
-        // Store the object in local storage, so we can avoid fetching it in the future.
+        // Store the objects in local storage, so we can avoid fetching them in the future.
         let mut backoff = self.backoff.clone();
         backoff.reset();
-        loop {
+        let res = loop {
             let Err(err) = try_store().await else {
-                break;
+                break Ok(());
             };
             // It is unfortunate if this fails, but we can still proceed by notifying with the
-            // object that we fetched, keeping it in memory. Log the error, retry a few times, and
-            // eventually move on.
+            // objects that we fetched, keeping them in memory. Log the error, retry a few times,
+            // and eventually give up, reporting the error to the caller.
             tracing::warn!(
-                "failed to store fetched {} {}: {err:#}",
+                first,
+                last,
+                "failed to store fetched {}: {err:#}",
                 T::name(),
-                obj.height()
             );
+            if err.downcast_ref::<Reorg<Types>>().is_some() {
+                return Err(err);
+            }
 
             let Some(delay) = backoff.next_backoff() else {
-                break;
+                break Err(err);
             };
             tracing::info!(?delay, "retrying failed operation");
             sleep(delay).await;
+        };
+
+        // Mirror the writes to the tap, now that they are durable.
+        if res.is_ok() {
+            if let Some(tap) = &self.tap {
+                let mut mutations = vec![];
+                for obj in &objs {
+                    obj.mutations(&mut mutations);
+                }
+                if let Err(err) = tap.emit(mutations).await {
+                    tracing::warn!(first, last, "failed to emit {} to tap: {err:#}", T::name());
+                }
+            }
         }
 
         // Send a notification about the newly received object. It is important that we do this
@@ -1511,16 +2519,23 @@ where
         // satisfies the invariant that we only wait on notifications for objects which are not in
         // storage, and eventually some other task will come along, find the object missing from
         // storage, and re-fetch it.
-        obj.notify(&self.notifiers).await;
+        for obj in &objs {
+            obj.notify(&self.notifiers).await;
+        }
+        res
     }
 }
 
 impl<Types, S, P> Fetcher<Types, S, P>
 where
     Types: NodeType,
-    S: PruneStorage + Sync,
+    S: VersionedDataSource + PruneStorage + Sync,
+    for<'a> S::ReadOnly<'a>: PrunedHeightStorage,
 {
-    async fn prune(&self) {
+    async fn prune(&self, metrics: &PrunerMetrics) {
+        let start = Instant::now();
+        let before = self.pruned_heights().await;
+
         // We loop until the whole run pruner run is complete
         let mut pruner = S::Pruner::default();
         loop {
@@ -1538,6 +2553,26 @@ where
                 }
             }
         }
+
+        // Record what this run deleted, even if it failed partway through, since any batches it
+        // completed were committed.
+        match (before, self.pruned_heights().await) {
+            (Ok(before), Ok(after)) => {
+                self.earliest_height
+                    .store(after.earliest_height(), Ordering::Release);
+                self.earliest_block_height
+                    .store(after.earliest_block_height(), Ordering::Release);
+                metrics.update(before, after);
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                tracing::warn!("unable to load pruned heights: {err:#}");
+            }
+        }
+        metrics.duration.add_point(start.elapsed().as_secs_f64());
+    }
+
+    async fn pruned_heights(&self) -> anyhow::Result<PrunedHeights> {
+        PrunedHeights::load(&self.storage).await
     }
 }
 
@@ -1549,6 +2584,16 @@ where
     block: Notifier<BlockQueryData<Types>>,
     leaf: Notifier<LeafQueryData<Types>>,
     vid_common: Notifier<VidCommonQueryData<Types>>,
+    // Active fetches which were abandoned after the fetch timeout. Unlike the notifiers
+    // above, subscriptions to this channel are cheap to drop, which matters because almost every
+    // subscriber is dropped without ever receiving a matching message.
+    failed: broadcast::Sender<FailedFetch>,
+    // Not a notifier as such, but kept up to date with the transactions of each new block as it
+    // becomes available.
+    transactions: TransactionFilter,
+    // Likewise, the number of blocks in the chain, kept up to date as each new leaf becomes
+    // available, so that it can be read without querying storage.
+    block_height: AtomicU64,
 }
 
 impl<Types> Default for Notifiers<Types>
@@ -1560,14 +2605,108 @@ where
             block: Notifier::new(),
             leaf: Notifier::new(),
             vid_common: Notifier::new(),
+            // Failures are rare, so this only needs to absorb a burst of them, such as a whole
+            // chunk of a range request failing at once. A subscriber which falls behind by more
+            // than this just keeps waiting, as if it had not subscribed to failures at all.
+            failed: broadcast::channel(1024).0,
+            transactions: TransactionFilter::disabled(),
+            block_height: AtomicU64::new(0),
         }
     }
 }
 
+impl<Types> Notifiers<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    /// Fail any requests waiting on an active fetch which has been abandoned.
+    fn fail(&self, resource: Resource, height: u64, err: QueryError) {
+        tracing::warn!(?resource, height, "abandoned fetch: {err:#}");
+        // This only fails if no one is waiting, in which case there is no one to tell.
+        self.failed
+            .send(FailedFetch {
+                resource,
+                height,
+                err,
+            })
+            .ok();
+    }
+
+    /// Wait for the active fetch for the requested object to be abandoned.
+    ///
+    /// This subscribes immediately, so that, as with [`Fetchable::passive_fetch`], a failure cannot
+    /// be missed between subscribing and triggering the fetch. The returned future resolves with
+    /// the reason the fetch was abandoned. Returns [`None`] if the object is not fetched by
+    /// height, in which case there is nothing to wait for.
+    fn wait_for_failure<T>(&self, req: T::Request) -> Option<BoxFuture<'static, QueryError>>
+    where
+        T: Fetchable<Types>,
+    {
+        let (Some(resource), Some(height)) = (T::RESOURCE, req.height()) else {
+            return None;
+        };
+        let mut failed = self.failed.subscribe();
+        Some(
+            async move {
+                loop {
+                    match failed.recv().await {
+                        Ok(failure) if failure.fails(resource, height) => break failure.err,
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        // If the failure channel is closed, we will never be told about a
+                        // failure, so just wait for the object.
+                        Err(RecvError::Closed) => future::pending().await,
+                    }
+                }
+            }
+            .boxed(),
+        )
+    }
+}
+
+/// A kind of object which is retrieved by an active fetch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Resource {
+    Leaf,
+    Payload,
+    VidCommon,
+}
+
+/// An active fetch which was abandoned after the fetch timeout.
+#[derive(Clone, Debug)]
+struct FailedFetch {
+    resource: Resource,
+    height: u64,
+    err: QueryError,
+}
+
+impl FailedFetch {
+    /// Does this failure abandon requests for `resource` at `height`?
+    ///
+    /// Payloads and VID common data are fetched by first fetching the leaf if we don't have the
+    /// header, so failing to fetch a leaf also fails requests for anything else at the same height.
+    fn fails(&self, resource: Resource, height: u64) -> bool {
+        self.height == height && (self.resource == resource || self.resource == Resource::Leaf)
+    }
+}
+
+/// The result of looking up an object in local storage.
+#[derive(Debug)]
+enum Lookup<T> {
+    /// The object was available locally.
+    Ready(T),
+    /// The object was not available locally, but a fetch was spawned if possible.
+    Pending,
+    /// The object was intentionally discarded, and will never be available.
+    Gone(QueryError),
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Heights {
     height: u64,
     pruned_height: Option<u64>,
+    payload_pruned_height: Option<u64>,
+    vid_pruned_height: Option<u64>,
 }
 
 impl Heights {
@@ -1581,15 +2720,33 @@ impl Heights {
             .load_pruned_height()
             .await
             .context("loading pruned height")?;
+        let payload_pruned_height = tx
+            .load_payload_pruned_height()
+            .await
+            .context("loading payload pruned height")?;
+        let vid_pruned_height = tx
+            .load_vid_pruned_height()
+            .await
+            .context("loading VID pruned height")?;
         Ok(Self {
             height,
             pruned_height,
+            payload_pruned_height,
+            vid_pruned_height,
         })
     }
 
     fn might_exist(self, h: u64) -> bool {
         h < self.height && self.pruned_height.map_or(true, |ph| h > ph)
     }
+
+    fn payload_pruned(self, h: u64) -> bool {
+        self.payload_pruned_height.map_or(false, |ph| h <= ph)
+    }
+
+    fn vid_pruned(self, h: u64) -> bool {
+        self.vid_pruned_height.map_or(false, |ph| h <= ph)
+    }
 }
 
 #[async_trait]
@@ -1608,11 +2765,35 @@ where
         snapshot: Snapshot<Types, State, ARITY>,
         key: State::Key,
     ) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.get_path(snapshot, key).await
     }
+
+    async fn get_path_batch(
+        &self,
+        snapshot: Snapshot<Types, State, ARITY>,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>> {
+        // Look up all the paths in a single transaction, so they are consistent with each other.
+        let mut tx = self.read().await?;
+        tx.get_path_batch(snapshot, keys).await
+    }
+
+    async fn snapshot_for_height(&self, height: u64) -> QueryResult<Snapshot<Types, State, ARITY>> {
+        let mut tx = self.read().await?;
+        tx.snapshot_for_height(height).await
+    }
+
+    async fn get_diff(
+        &self,
+        from: Snapshot<Types, State, ARITY>,
+        to: Snapshot<Types, State, ARITY>,
+        offset: usize,
+        limit: usize,
+    ) -> QueryResult<StateDiff<State::Key, State::Entry>> {
+        let mut tx = self.read().await?;
+        tx.get_diff(from, to, offset, limit).await
+    }
 }
 
 #[async_trait]
@@ -1625,9 +2806,7 @@ where
     P: Send + Sync,
 {
     async fn get_last_state_height(&self) -> QueryResult<usize> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.get_last_state_height().await
     }
 }
@@ -1636,14 +2815,12 @@ where
 impl<Types, S, P> NodeDataSource<Types> for FetchingDataSource<Types, S, P>
 where
     Types: NodeType,
-    S: VersionedDataSource + 'static,
+    S: VersionedDataSource + HasMetrics + 'static,
     for<'a> S::ReadOnly<'a>: NodeStorage<Types>,
     P: Send + Sync,
 {
     async fn block_height(&self) -> QueryResult<usize> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.block_height().await
     }
 
@@ -1651,9 +2828,7 @@ where
         &self,
         range: impl RangeBounds<usize> + Send,
     ) -> QueryResult<usize> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.count_transactions_in_range(range).await
     }
 
@@ -1661,9 +2836,7 @@ where
         &self,
         range: impl RangeBounds<usize> + Send,
     ) -> QueryResult<usize> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.payload_size_in_range(range).await
     }
 
@@ -1671,17 +2844,25 @@ where
     where
         ID: Into<BlockId<Types>> + Send + Sync,
     {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.vid_share(id).await
     }
 
     async fn sync_status(&self) -> QueryResult<SyncStatus> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
-        tx.sync_status().await
+        let mut tx = self.read().await?;
+        let mut status = tx.sync_status().await?;
+        status.gaps = tx.sync_gaps(self.max_sync_gaps).await?;
+        status.latest_stored_height = tx.block_height().await?.checked_sub(1);
+        // The latest view is only known if this node is running consensus and has populated the
+        // consensus metrics.
+        status.latest_view = self
+            .metrics()
+            .get_subgroup(["consensus"])
+            .and_then(|metrics| metrics.get_gauge("current_view"))
+            .ok()
+            .map(|view| view.get() as u64);
+        status.fully_synced = status.is_fully_synced();
+        Ok(status)
     }
 
     async fn get_header_window(
@@ -1690,9 +2871,7 @@ where
         end: u64,
         limit: usize,
     ) -> QueryResult<TimeWindowQueryData<Header<Types>>> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         tx.get_header_window(start, end, limit).await
     }
 }
@@ -1715,20 +2894,50 @@ where
         Vec<explorer::query_data::BlockSummary<Types>>,
         explorer::query_data::GetBlockSummariesError,
     > {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_block_summaries(request).await
     }
 
+    async fn get_block_summary_page(
+        &self,
+        request: explorer::query_data::GetBlockSummaryPageRequest,
+    ) -> Result<
+        explorer::query_data::BlockSummaryPage<Types>,
+        explorer::query_data::GetBlockSummariesError,
+    > {
+        // Use the cached block height rather than counting blocks in storage, so that this stays
+        // cheap no matter how often the UI polls it.
+        let total_count = self.fetcher.notifiers.block_height.load(Ordering::Acquire);
+        let Some(start) = total_count
+            .checked_sub(1)
+            .and_then(|latest| latest.checked_sub(request.offset as u64))
+        else {
+            return Ok(explorer::query_data::BlockSummaryPage {
+                block_summaries: vec![],
+                total_count,
+            });
+        };
+        let mut tx = self.read().await.map_err(QueryError::from)?;
+        let block_summaries = tx
+            .get_block_summaries(explorer::query_data::GetBlockSummariesRequest(
+                explorer::query_data::BlockRange {
+                    target: explorer::query_data::BlockIdentifier::Height(start as usize),
+                    num_blocks: request.limit,
+                },
+            ))
+            .await?;
+        Ok(explorer::query_data::BlockSummaryPage {
+            block_summaries,
+            total_count,
+        })
+    }
+
     async fn get_block_detail(
         &self,
         request: explorer::query_data::BlockIdentifier<Types>,
     ) -> Result<explorer::query_data::BlockDetail<Types>, explorer::query_data::GetBlockDetailError>
     {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_block_detail(request).await
     }
 
@@ -1739,9 +2948,7 @@ where
         Vec<explorer::query_data::TransactionSummary<Types>>,
         explorer::query_data::GetTransactionSummariesError,
     > {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_transaction_summaries(request).await
     }
 
@@ -1752,9 +2959,7 @@ where
         explorer::query_data::TransactionDetailResponse<Types>,
         explorer::query_data::GetTransactionDetailError,
     > {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_transaction_detail(request).await
     }
 
@@ -1764,24 +2969,38 @@ where
         explorer::query_data::ExplorerSummary<Types>,
         explorer::query_data::GetExplorerSummaryError,
     > {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_explorer_summary().await
     }
 
+    async fn get_block_statistics(
+        &self,
+        request: explorer::query_data::GetBlockStatisticsRequest,
+    ) -> Result<explorer::query_data::BlockStatistics, explorer::query_data::GetBlockStatisticsError>
+    {
+        let mut tx = self.read().await.map_err(QueryError::from)?;
+        tx.get_block_statistics(request).await
+    }
+
     async fn get_search_results(
         &self,
-        query: TaggedBase64,
+        query: explorer::query_data::SearchQuery,
     ) -> Result<
         explorer::query_data::SearchResult<Types>,
         explorer::query_data::GetSearchResultsError,
     > {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await.map_err(QueryError::from)?;
         tx.get_search_results(query).await
     }
+
+    async fn get_search_match(
+        &self,
+        query: explorer::query_data::SearchQuery,
+    ) -> Result<explorer::query_data::SearchMatch<Types>, explorer::query_data::GetSearchResultsError>
+    {
+        let mut tx = self.read().await.map_err(QueryError::from)?;
+        tx.get_search_match(query).await
+    }
 }
 
 /// A provider which can be used as a fetcher by the availability service.
@@ -1816,6 +3035,11 @@ trait FetchRequest: Copy + Debug + Send + Sync + 'static {
     fn might_exist(self, _heights: Heights) -> bool {
         true
     }
+
+    /// The height of the requested object, if it is known from the request alone.
+    fn height(self) -> Option<u64> {
+        None
+    }
 }
 
 /// Objects which can be fetched from a remote DA provider and cached in local storage.
@@ -1832,6 +3056,13 @@ where
     /// A succinct specification of the object to be fetched.
     type Request: FetchRequest;
 
+    /// The kind of active fetch which retrieves this object, if it is fetched by height.
+    ///
+    /// Requests for objects of this type fail if the corresponding fetch is abandoned. Objects
+    /// which are never actively fetched by height leave this as [`None`], and are simply waited
+    /// for.
+    const RESOURCE: Option<Resource> = None;
+
     /// Does this object satisfy the given request?
     fn satisfies(&self, req: Self::Request) -> bool;
 
@@ -1863,6 +3094,15 @@ where
         for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
         P: AvailabilityProvider<Types>;
 
+    /// Has the requested object been intentionally discarded by payload-only pruning?
+    ///
+    /// If so, this returns the error to report for it. Objects derived from a block payload or
+    /// from VID data override this so that we don't actively fetch data which the pruner will only
+    /// discard again. Leaves and headers are never pruned this way.
+    fn pruned(_req: Self::Request, _heights: Heights) -> Option<QueryError> {
+        None
+    }
+
     /// Wait for someone else to fetch the object.
     async fn passive_fetch(notifiers: &Notifiers<Types>, req: Self::Request) -> PassiveFetch<Self>;
 
@@ -1893,21 +3133,44 @@ where
 }
 
 /// An object which can be stored in the database.
-trait Storable<Types: NodeType>: HeightIndexed + Clone {
+trait Storable<Types: NodeType>: HeightIndexed + Clone + Send {
     /// The name of this type of object, for debugging purposes.
     fn name() -> &'static str;
 
     /// Notify anyone waiting for this object that it has become available.
     fn notify(&self, notifiers: &Notifiers<Types>) -> impl Send + Future<Output = ()>;
 
+    /// The storage writes performed by [`store`](Self::store), in order.
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>);
+
     /// Store the object in the local database.
     fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
     ) -> impl Send + Future<Output = anyhow::Result<()>>;
+
+    /// Store a batch of objects in the local database.
+    ///
+    /// The default implementation stores each object in turn, but implementations may override it
+    /// to store the whole batch more efficiently.
+    fn store_all(
+        objs: Vec<Self>,
+        storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
+    ) -> impl Send + Future<Output = anyhow::Result<()>> {
+        async move {
+            for obj in objs {
+                obj.store(storage).await?;
+            }
+            Ok(())
+        }
+    }
 }
 
-impl<Types: NodeType> Storable<Types> for BlockInfo<Types> {
+impl<Types> Storable<Types> for BlockInfo<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
     fn name() -> &'static str {
         "block info"
     }
@@ -1923,6 +3186,19 @@ impl<Types: NodeType> Storable<Types> for BlockInfo<Types> {
         }
     }
 
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>) {
+        self.leaf.mutations(mutations);
+        if let Some(block) = &self.block {
+            block.mutations(mutations);
+        }
+        if let Some(common) = &self.vid_common {
+            mutations.push(AvailabilityMutation::InsertVid {
+                common: common.clone(),
+                share: self.vid_share.clone(),
+            });
+        }
+    }
+
     async fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
@@ -1938,6 +3214,31 @@ impl<Types: NodeType> Storable<Types> for BlockInfo<Types> {
 
         Ok(())
     }
+
+    async fn store_all(
+        infos: Vec<Self>,
+        storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
+    ) -> anyhow::Result<()> {
+        // The blocks and VID data reference the headers, which are inserted along with the leaves,
+        // so the leaves must go first. Insert them all in one batch, which storage can do in far
+        // fewer round trips than one leaf at a time, then fill in the rest of each block.
+        let (leaves, rest): (Vec<_>, Vec<_>) = infos
+            .into_iter()
+            .map(|info| (info.leaf, (info.block, info.vid_common, info.vid_share)))
+            .unzip();
+        storage.insert_leaves(leaves).await?;
+
+        for (block, vid_common, vid_share) in rest {
+            if let Some(block) = block {
+                block.store(storage).await?;
+            }
+            if let Some(common) = vid_common {
+                (common, vid_share).store(storage).await?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Break a range into fixed-size chunks.
@@ -1968,6 +3269,32 @@ where
     })
 }
 
+/// Break a range into fixed-size chunks, in descending order.
+///
+/// The range is given by an arbitrary `start` bound and an exclusive `end`. Each chunk is itself an
+/// ascending range; only the order of the chunks is reversed.
+fn range_chunks_rev(
+    start: Bound<usize>,
+    end: usize,
+    chunk_size: usize,
+) -> impl Iterator<Item = Range<usize>> {
+    let start = match start {
+        Bound::Included(i) => i,
+        Bound::Excluded(i) => i.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let mut end = end;
+    std::iter::from_fn(move || {
+        if end <= start {
+            return None;
+        }
+        let chunk_start = max(start, end.saturating_sub(chunk_size));
+        let chunk = chunk_start..end;
+        end = chunk_start;
+        Some(chunk)
+    })
+}
+
 trait ResultExt<T, E> {
     fn ok_or_trace(self) -> Option<T>
     where
@@ -2060,6 +3387,27 @@ impl ScannerMetrics {
     }
 }
 
+#[derive(Debug)]
+struct BackfillMetrics {
+    /// The number of missing objects found in the latest round of backfill.
+    missing: Box<dyn Gauge>,
+    /// The total number of missing objects found by backfill.
+    found: Box<dyn Counter>,
+    /// The total number of missing objects successfully fetched by backfill.
+    filled: Box<dyn Counter>,
+}
+
+impl BackfillMetrics {
+    fn new(metrics: &PrometheusMetrics) -> Self {
+        let group = metrics.subgroup("backfill".into());
+        Self {
+            missing: group.create_gauge("missing".into(), None),
+            found: group.create_counter("found".into(), None),
+            filled: group.create_counter("filled".into(), None),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct AggregatorMetrics {
     /// The block height for which aggregate statistics are currently available.
@@ -2075,18 +3423,99 @@ impl AggregatorMetrics {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+struct PrunedHeights {
+    blocks: Option<u64>,
+    payloads: Option<u64>,
+    vid: Option<u64>,
+}
+
+impl PrunedHeights {
+    async fn load<S>(storage: &S) -> anyhow::Result<Self>
+    where
+        S: VersionedDataSource,
+        for<'a> S::ReadOnly<'a>: PrunedHeightStorage,
+    {
+        let mut tx = storage.read().await.context("opening read transaction")?;
+        let blocks = tx
+            .load_pruned_height()
+            .await
+            .context("loading pruned height")?;
+        let payloads = tx
+            .load_payload_pruned_height()
+            .await
+            .context("loading payload pruned height")?;
+        let vid = tx
+            .load_vid_pruned_height()
+            .await
+            .context("loading VID pruned height")?;
+        Ok(Self {
+            blocks,
+            // Payloads and VID data are also deleted when the whole block is pruned.
+            payloads: max(blocks, payloads),
+            vid: max(blocks, vid),
+        })
+    }
+
+    /// The height of the earliest leaf which has not been pruned.
+    fn earliest_height(&self) -> u64 {
+        self.blocks.map_or(0, |h| h + 1)
+    }
+
+    /// The height of the earliest payload which has not been pruned.
+    fn earliest_block_height(&self) -> u64 {
+        self.payloads.map_or(0, |h| h + 1)
+    }
+}
+
+#[derive(Debug)]
+struct PrunerMetrics {
+    /// Total number of blocks deleted entirely by the pruner.
+    pruned_blocks: Box<dyn Counter>,
+    /// Total number of VID entries deleted by the pruner.
+    pruned_vid: Box<dyn Counter>,
+    /// The height of the oldest block which has not been pruned.
+    oldest_retained_height: Box<dyn Gauge>,
+    /// Duration of each pruner run (s).
+    duration: Box<dyn Histogram>,
+}
+
+impl PrunerMetrics {
+    fn new(metrics: &PrometheusMetrics) -> Self {
+        let group = metrics.subgroup("pruner".into());
+        Self {
+            pruned_blocks: group.create_counter("pruned_blocks_total".into(), None),
+            pruned_vid: group.create_counter("pruned_vid_total".into(), None),
+            oldest_retained_height: group.create_gauge("oldest_retained_height".into(), None),
+            duration: group.create_histogram("duration".into(), Some("s".into())),
+        }
+    }
+
+    fn update(&self, before: PrunedHeights, after: PrunedHeights) {
+        // A pruned height `h` means all blocks up to and including `h` are gone, so the number of
+        // blocks deleted is the difference in the number of blocks below each threshold.
+        let count = |h: Option<u64>| h.map_or(0, |h| h as usize + 1);
+        self.pruned_blocks
+            .add(count(after.blocks).saturating_sub(count(before.blocks)));
+        self.pruned_vid
+            .add(count(after.vid).saturating_sub(count(before.vid)));
+        self.oldest_retained_height.set(count(after.blocks));
+    }
+}
+
 /// Turn a fallible passive fetch future into an infallible "fetch".
 ///
 /// Basically, we ignore failures due to a channel sender being dropped, which should never happen.
 fn passive<T>(
     req: impl Debug + Send + 'static,
     fut: impl Future<Output = Option<T>> + Send + 'static,
+    failure: Option<BoxFuture<'static, QueryError>>,
 ) -> Fetch<T>
 where
     T: Send + 'static,
 {
-    Fetch::Pending(
-        fut.then(move |opt| async move {
+    let fut = fut
+        .then(move |opt| async move {
             match opt {
                 Some(t) => t,
                 None => {
@@ -2108,8 +3537,11 @@ where
                 }
             }
         })
-        .boxed(),
-    )
+        .boxed();
+    match failure {
+        Some(failure) => Fetch::Abandonable(fut, failure),
+        None => Fetch::Pending(fut),
+    }
 }
 
 /// Get the result of the first future to return `Some`, if either do.
@@ -10,15 +10,19 @@
 // You should have received a copy of the GNU General Public License along with this program. If not,
 // see <https://www.gnu.org/licenses/>.
 
-//! [`Fetchable`] implementation for [`BlockQueryData`] and [`PayloadQueryData`].
+//! [`Fetchable`] implementation for [`BlockQueryData`], [`PayloadQueryData`] and
+//! [`RawPayloadQueryData`].
 
 use super::{
     header::{fetch_header_and_then, HeaderCallback},
-    AvailabilityProvider, FetchRequest, Fetchable, Fetcher, Heights, Notifiers, RangedFetchable,
-    Storable,
+    AvailabilityMutation, AvailabilityProvider, FetchRequest, Fetchable, Fetcher, Heights,
+    Notifiers, RangedFetchable, Resource, Storable,
 };
 use crate::{
-    availability::{BlockId, BlockQueryData, PayloadMetadata, PayloadQueryData, QueryablePayload},
+    availability::{
+        BlockId, BlockQueryData, BlockSummaryQueryData, PayloadMetadata, PayloadQueryData,
+        QueryablePayload, RawPayloadQueryData,
+    },
     data_source::{
         storage::{AvailabilityStorage, UpdateAvailabilityStorage},
         VersionedDataSource,
@@ -29,7 +33,7 @@ use crate::{
         Callback,
     },
     types::HeightIndexed,
-    Header, Payload, QueryResult,
+    Header, Payload, QueryError, QueryResult,
 };
 use async_trait::async_trait;
 use derivative::Derivative;
@@ -51,6 +55,26 @@ where
             true
         }
     }
+
+    fn height(self) -> Option<u64> {
+        if let BlockId::Number(n) = self {
+            Some(n as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Has the payload for the requested block been discarded by the pruner?
+pub(super) fn payload_pruned<Types>(id: BlockId<Types>, heights: Heights) -> bool
+where
+    Types: NodeType,
+{
+    if let BlockId::Number(n) = id {
+        heights.payload_pruned(n as u64)
+    } else {
+        false
+    }
 }
 
 #[async_trait]
@@ -60,6 +84,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Payload);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req {
@@ -69,6 +94,10 @@ where
         }
     }
 
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        payload_pruned(req, heights).then_some(QueryError::PayloadPruned)
+    }
+
     async fn passive_fetch(
         notifiers: &Notifiers<Types>,
         req: Self::Request,
@@ -129,15 +158,21 @@ where
 impl<Types> Storable<Types> for BlockQueryData<Types>
 where
     Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
 {
     fn name() -> &'static str {
         "block"
     }
 
     async fn notify(&self, notifiers: &Notifiers<Types>) {
+        notifiers.transactions.insert_block(self);
         notifiers.block.notify(self).await;
     }
 
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>) {
+        mutations.push(AvailabilityMutation::InsertBlock(self.clone()));
+    }
+
     async fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
@@ -179,6 +214,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Payload);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req {
@@ -188,6 +224,10 @@ where
         }
     }
 
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        payload_pruned(req, heights).then_some(QueryError::PayloadPruned)
+    }
+
     async fn passive_fetch(
         notifiers: &Notifiers<Types>,
         req: Self::Request,
@@ -225,6 +265,62 @@ where
     }
 }
 
+#[async_trait]
+impl<Types> Fetchable<Types> for RawPayloadQueryData<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Payload);
+
+    fn satisfies(&self, req: Self::Request) -> bool {
+        match req {
+            BlockId::Number(n) => self.height() == n as u64,
+            BlockId::Hash(h) => self.block_hash() == h,
+            BlockId::PayloadHash(h) => self.hash() == h,
+        }
+    }
+
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        payload_pruned(req, heights).then_some(QueryError::PayloadPruned)
+    }
+
+    async fn passive_fetch(
+        notifiers: &Notifiers<Types>,
+        req: Self::Request,
+    ) -> BoxFuture<'static, Option<Self>> {
+        notifiers
+            .block
+            .wait_for(move |block| block.satisfies(req))
+            .await
+            .into_future()
+            .map(|block| block.map(RawPayloadQueryData::from))
+            .boxed()
+    }
+
+    async fn active_fetch<S, P>(
+        tx: &mut impl AvailabilityStorage<Types>,
+        fetcher: Arc<Fetcher<Types, S, P>>,
+        req: Self::Request,
+    ) -> anyhow::Result<()>
+    where
+        S: VersionedDataSource + 'static,
+        for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+        P: AvailabilityProvider<Types>,
+    {
+        // Fetching the raw payload is the same as fetching the payload: fetch the whole block.
+        BlockQueryData::active_fetch(tx, fetcher, req).await
+    }
+
+    async fn load<S>(storage: &mut S, req: Self::Request) -> QueryResult<Self>
+    where
+        S: AvailabilityStorage<Types>,
+    {
+        storage.get_raw_payload(req).await
+    }
+}
+
 #[async_trait]
 impl<Types> RangedFetchable<Types> for PayloadQueryData<Types>
 where
@@ -279,9 +375,25 @@ where
 {
     async fn run(self, payload: Payload<Types>) {
         tracing::info!("fetched payload {:?}", self.header.payload_commitment());
+        if let Err(err) = self.fetcher.validate_payload(&self.header, &payload) {
+            let height = self.header.block_number();
+            tracing::warn!(height, "rejected invalid fetched payload: {err:#}");
+            // Remember the rejection, otherwise the next request for this block, or the next pass
+            // of the proactive scanner, would just fetch the same payload again.
+            if let Err(err) = self.fetcher.reject_payload(height).await {
+                tracing::warn!(height, "failed to record rejected payload: {err:#}");
+            }
+            return;
+        }
         let block = BlockQueryData::new(self.header, payload);
         self.fetcher.store_and_notify(block).await;
     }
+
+    async fn fail(self, err: QueryError) {
+        self.fetcher
+            .notifiers
+            .fail(Resource::Payload, self.header.block_number(), err);
+    }
 }
 
 #[async_trait]
@@ -291,6 +403,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Payload);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req {
@@ -352,3 +465,77 @@ where
         storage.get_payload_metadata_range(range).await
     }
 }
+
+#[async_trait]
+impl<Types> Fetchable<Types> for BlockSummaryQueryData<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Payload);
+
+    fn satisfies(&self, req: Self::Request) -> bool {
+        match req {
+            BlockId::Number(n) => self.height() == n as u64,
+            BlockId::Hash(h) => self.hash() == h,
+            BlockId::PayloadHash(h) => self.header().payload_commitment() == h,
+        }
+    }
+
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        payload_pruned(req, heights).then_some(QueryError::PayloadPruned)
+    }
+
+    async fn passive_fetch(
+        notifiers: &Notifiers<Types>,
+        req: Self::Request,
+    ) -> BoxFuture<'static, Option<Self>> {
+        notifiers
+            .block
+            .wait_for(move |block| block.satisfies(req))
+            .await
+            .into_future()
+            .map(|opt| opt.map(Self::from))
+            .boxed()
+    }
+
+    async fn active_fetch<S, P>(
+        tx: &mut impl AvailabilityStorage<Types>,
+        fetcher: Arc<Fetcher<Types, S, P>>,
+        req: Self::Request,
+    ) -> anyhow::Result<()>
+    where
+        S: VersionedDataSource + 'static,
+        for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+        P: AvailabilityProvider<Types>,
+    {
+        // Trigger the full block to be fetched. This will be enough to satisfy this request for the
+        // block summary.
+        BlockQueryData::active_fetch(tx, fetcher, req).await
+    }
+
+    async fn load<S>(storage: &mut S, req: Self::Request) -> QueryResult<Self>
+    where
+        S: AvailabilityStorage<Types>,
+    {
+        storage.get_block_summary(req).await
+    }
+}
+
+#[async_trait]
+impl<Types> RangedFetchable<Types> for BlockSummaryQueryData<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    type RangedRequest = BlockId<Types>;
+
+    async fn load_range<S, R>(storage: &mut S, range: R) -> QueryResult<Vec<QueryResult<Self>>>
+    where
+        S: AvailabilityStorage<Types>,
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        storage.get_block_summary_range(range).await
+    }
+}
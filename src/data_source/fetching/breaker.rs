@@ -0,0 +1,254 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! A circuit breaker guarding access to storage.
+
+use crate::QueryError;
+use futures::future::Future;
+use hotshot_types::traits::metrics::{Gauge, Metrics};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The value of the breaker state metric while requests are allowed through.
+const CLOSED: usize = 0;
+/// The value of the breaker state metric while requests are rejected.
+const OPEN: usize = 1;
+/// The value of the breaker state metric while a single probe request is allowed through.
+const HALF_OPEN: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Requests are allowed through.
+    ///
+    /// We track the number of consecutive failures, and when the first of them happened.
+    Closed {
+        failures: usize,
+        since: Option<Instant>,
+    },
+    /// Requests are rejected until the cooldown expires.
+    Open { until: Instant },
+    /// A single probe request has been allowed through.
+    ///
+    /// If the probe succeeds, the breaker closes; if it fails, the breaker opens again. If the
+    /// probe has not completed by `until` (for example, because it was cancelled), another probe is
+    /// allowed.
+    HalfOpen { until: Instant },
+}
+
+/// A circuit breaker which stops sending requests to storage while it is persistently failing.
+///
+/// When storage is down, every request opening a transaction fails, but often only after waiting
+/// for a connection. Letting every request through piles up load on the service while storage is
+/// down, and on storage as soon as it starts to recover. After `threshold` consecutive failures
+/// within `window`, the breaker opens, and requests fail immediately with
+/// [`QueryError::Unavailable`] for `cooldown`. After that, a single probe request is allowed
+/// through, and the breaker closes again once a request succeeds.
+#[derive(Debug)]
+pub(super) struct CircuitBreaker {
+    threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    state: Mutex<State>,
+    metric: Box<dyn Gauge>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(
+        threshold: usize,
+        window: Duration,
+        cooldown: Duration,
+        metrics: &(impl Metrics + ?Sized),
+    ) -> Self {
+        let metric = metrics.create_gauge("storage_circuit_breaker".into(), None);
+        metric.set(CLOSED);
+        Self {
+            threshold,
+            window,
+            cooldown,
+            state: Mutex::new(State::Closed {
+                failures: 0,
+                since: None,
+            }),
+            metric,
+        }
+    }
+
+    /// Run a storage operation, unless the breaker is open.
+    pub(super) async fn guard<T>(
+        &self,
+        op: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.admit()?;
+        let res = op.await;
+        self.record(res.is_ok());
+        res
+    }
+
+    /// Check whether a request may proceed.
+    fn admit(&self) -> Result<(), QueryError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } | State::HalfOpen { until } if now < until => {
+                Err(QueryError::Unavailable)
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                tracing::info!("storage circuit breaker half-open, probing storage");
+                self.set_state(
+                    &mut state,
+                    State::HalfOpen {
+                        until: now + self.cooldown,
+                    },
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Record the outcome of a request which was admitted.
+    fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let next = match (*state, success) {
+            (State::Closed { failures: 0, .. }, true) => return,
+            (State::Closed { .. }, true) => State::Closed {
+                failures: 0,
+                since: None,
+            },
+            (_, true) => {
+                tracing::info!("storage recovered, closing circuit breaker");
+                State::Closed {
+                    failures: 0,
+                    since: None,
+                }
+            }
+            (State::Closed { failures, since }, false) => {
+                // Only failures within `window` of the first one count as consecutive.
+                let (failures, since) = match since {
+                    Some(since) if now.duration_since(since) <= self.window => {
+                        (failures + 1, since)
+                    }
+                    _ => (1, now),
+                };
+                if failures >= self.threshold {
+                    tracing::warn!(
+                        failures,
+                        "storage failing persistently, opening circuit breaker"
+                    );
+                    State::Open {
+                        until: now + self.cooldown,
+                    }
+                } else {
+                    State::Closed {
+                        failures,
+                        since: Some(since),
+                    }
+                }
+            }
+            (State::Open { .. } | State::HalfOpen { .. }, false) => State::Open {
+                until: now + self.cooldown,
+            },
+        };
+        self.set_state(&mut state, next);
+    }
+
+    fn set_state(&self, state: &mut State, next: State) {
+        *state = next;
+        self.metric.set(match next {
+            State::Closed { .. } => CLOSED,
+            State::Open { .. } => OPEN,
+            State::HalfOpen { .. } => HALF_OPEN,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metrics::PrometheusMetrics;
+    use anyhow::bail;
+
+    async fn ok() -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn fail() -> anyhow::Result<()> {
+        bail!("storage down")
+    }
+
+    fn is_unavailable(err: &anyhow::Error) -> bool {
+        matches!(err.downcast_ref(), Some(QueryError::Unavailable))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_circuit_breaker() {
+        let metrics = PrometheusMetrics::default();
+        let cooldown = Duration::from_millis(100);
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60), cooldown, &metrics);
+        let state = || metrics.get_gauge("storage_circuit_breaker").unwrap().get();
+        assert_eq!(state(), CLOSED);
+
+        // Failures which are not consecutive do not open the breaker.
+        breaker.guard(fail()).await.unwrap_err();
+        breaker.guard(fail()).await.unwrap_err();
+        breaker.guard(ok()).await.unwrap();
+        breaker.guard(fail()).await.unwrap_err();
+        breaker.guard(fail()).await.unwrap_err();
+        assert_eq!(state(), CLOSED);
+
+        // The third consecutive failure opens the breaker, after which requests are rejected
+        // without reaching storage.
+        let err = breaker.guard(fail()).await.unwrap_err();
+        assert!(!is_unavailable(&err), "{err:#}");
+        assert_eq!(state(), OPEN);
+        let err = breaker.guard(ok()).await.unwrap_err();
+        assert!(is_unavailable(&err), "{err:#}");
+
+        // After the cooldown, a failed probe opens the breaker again.
+        tokio::time::sleep(cooldown).await;
+        let err = breaker.guard(fail()).await.unwrap_err();
+        assert!(!is_unavailable(&err), "{err:#}");
+        assert_eq!(state(), OPEN);
+        let err = breaker.guard(ok()).await.unwrap_err();
+        assert!(is_unavailable(&err), "{err:#}");
+
+        // A successful probe closes the breaker.
+        tokio::time::sleep(cooldown).await;
+        breaker.guard(ok()).await.unwrap();
+        assert_eq!(state(), CLOSED);
+        breaker.guard(ok()).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_circuit_breaker_single_probe() {
+        let metrics = PrometheusMetrics::default();
+        let cooldown = Duration::from_millis(100);
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60), cooldown, &metrics);
+        breaker.guard(fail()).await.unwrap_err();
+        tokio::time::sleep(cooldown).await;
+
+        // While a probe is in flight, other requests are rejected.
+        breaker.admit().unwrap();
+        assert_eq!(
+            metrics.get_gauge("storage_circuit_breaker").unwrap().get(),
+            HALF_OPEN
+        );
+        assert!(matches!(breaker.admit(), Err(QueryError::Unavailable)));
+
+        // If the probe never completes, another is allowed after the cooldown.
+        tokio::time::sleep(cooldown).await;
+        breaker.admit().unwrap();
+    }
+}
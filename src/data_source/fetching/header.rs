@@ -14,22 +14,96 @@
 
 use super::{
     block::fetch_block_with_header, leaf::fetch_leaf_with_callbacks,
-    vid::fetch_vid_common_with_header, AvailabilityProvider, Fetcher,
+    vid::fetch_vid_common_with_header, AvailabilityProvider, Fetchable, Fetcher, Notifiers,
+    Resource,
 };
 use crate::{
-    availability::{BlockId, QueryablePayload},
+    availability::{BlockId, LeafQueryData, QueryablePayload},
     data_source::{
         storage::{AvailabilityStorage, UpdateAvailabilityStorage},
         update::VersionedDataSource,
     },
-    Header, Payload, QueryError,
+    Header, Payload, QueryError, QueryResult,
 };
 use anyhow::bail;
+use async_trait::async_trait;
+use committable::Committable;
 use derivative::Derivative;
+use futures::future::{BoxFuture, FutureExt};
 use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
 use std::cmp::Ordering;
+use std::future::IntoFuture;
 use std::sync::Arc;
 
+/// A block header, fetched on its own.
+///
+/// A header is available exactly when the corresponding leaf is, so it is fetched by fetching the
+/// leaf. But it is loaded from storage on its own, without reading the leaf or the payload.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""))]
+pub(super) struct HeaderOnly<Types: NodeType>(pub(super) Header<Types>);
+
+#[async_trait]
+impl<Types> Fetchable<Types> for HeaderOnly<Types>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+{
+    type Request = BlockId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Leaf);
+
+    fn satisfies(&self, req: Self::Request) -> bool {
+        match req {
+            BlockId::Number(n) => self.0.block_number() == n as u64,
+            BlockId::Hash(h) => self.0.commit() == h,
+            BlockId::PayloadHash(h) => self.0.payload_commitment() == h,
+        }
+    }
+
+    async fn passive_fetch(
+        notifiers: &Notifiers<Types>,
+        req: Self::Request,
+    ) -> BoxFuture<'static, Option<Self>> {
+        notifiers
+            .leaf
+            .wait_for(move |leaf| Self(leaf.header().clone()).satisfies(req))
+            .await
+            .into_future()
+            .map(|opt| opt.map(|leaf| Self(leaf.header().clone())))
+            .boxed()
+    }
+
+    async fn active_fetch<S, P>(
+        tx: &mut impl AvailabilityStorage<Types>,
+        fetcher: Arc<Fetcher<Types, S, P>>,
+        req: Self::Request,
+    ) -> anyhow::Result<()>
+    where
+        S: VersionedDataSource + 'static,
+        for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+        P: AvailabilityProvider<Types>,
+    {
+        match req {
+            // Trigger the leaf to be fetched. This will be enough to satisfy this request for the
+            // header.
+            BlockId::Number(n) => LeafQueryData::active_fetch(tx, fetcher, n.into()).await,
+            // As with blocks, we don't fetch a header by hash unless we know it exists, and if we
+            // knew that, we would already have the header.
+            _ => {
+                tracing::debug!("not fetching unknown header {req}");
+                Ok(())
+            }
+        }
+    }
+
+    async fn load<S>(storage: &mut S, req: Self::Request) -> QueryResult<Self>
+    where
+        S: AvailabilityStorage<Types>,
+    {
+        storage.get_header(req).await.map(Self)
+    }
+}
+
 #[derive(Derivative)]
 #[derivative(Debug(bound = ""))]
 pub(super) enum HeaderCallback<Types, S, P>
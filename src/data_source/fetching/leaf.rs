@@ -13,8 +13,8 @@
 //! [`Fetchable`] implementation for [`LeafQueryData`].
 
 use super::{
-    header::HeaderCallback, AvailabilityProvider, FetchRequest, Fetchable, Fetcher, Heights,
-    Notifiers, RangedFetchable, Storable,
+    header::HeaderCallback, AvailabilityMutation, AvailabilityProvider, FetchRequest, Fetchable,
+    Fetcher, Heights, Notifiers, RangedFetchable, Resource, Storable,
 };
 use crate::{
     availability::{LeafId, LeafQueryData, QueryablePayload},
@@ -24,14 +24,14 @@ use crate::{
     },
     fetching::{self, request, Callback},
     types::HeightIndexed,
-    Payload, QueryResult,
+    Payload, QueryError, QueryResult,
 };
 use async_trait::async_trait;
 use derivative::Derivative;
 use derive_more::From;
 use futures::future::{BoxFuture, FutureExt};
 use hotshot_types::traits::node_implementation::NodeType;
-use std::sync::Arc;
+use std::sync::{atomic, Arc};
 use std::{cmp::Ordering, future::IntoFuture, iter::once, ops::RangeBounds};
 
 pub(super) type LeafFetcher<Types, S, P> =
@@ -48,6 +48,14 @@ where
             true
         }
     }
+
+    fn height(self) -> Option<u64> {
+        if let LeafId::Number(n) = self {
+            Some(n as u64)
+        } else {
+            None
+        }
+    }
 }
 
 #[async_trait]
@@ -57,6 +65,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = LeafId<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::Leaf);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req {
@@ -118,7 +127,11 @@ pub(super) fn fetch_leaf_with_callbacks<Types, S, P, I>(
             fetcher.leaf_fetcher.clone().spawn_fetch(
                 n.into(),
                 fetcher.provider.clone(),
-                once(LeafCallback::Leaf { fetcher }).chain(callbacks),
+                once(LeafCallback::Leaf {
+                    height: n as u64,
+                    fetcher,
+                })
+                .chain(callbacks),
             );
         }
         LeafId::Hash(h) => {
@@ -156,9 +169,16 @@ where
     }
 
     async fn notify(&self, notifiers: &Notifiers<Types>) {
+        notifiers
+            .block_height
+            .fetch_max(self.height() + 1, atomic::Ordering::AcqRel);
         notifiers.leaf.notify(self).await;
     }
 
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>) {
+        mutations.push(AvailabilityMutation::InsertLeaf(self.clone()));
+    }
+
     async fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
@@ -173,6 +193,7 @@ pub(super) enum LeafCallback<Types: NodeType, S, P> {
     /// Callback when fetching the leaf for its own sake.
     #[from(ignore)]
     Leaf {
+        height: u64,
         #[derivative(Debug = "ignore")]
         fetcher: Arc<Fetcher<Types, S, P>>,
     },
@@ -220,11 +241,20 @@ where
 {
     async fn run(self, leaf: LeafQueryData<Types>) {
         match self {
-            Self::Leaf { fetcher } => {
+            Self::Leaf { fetcher, .. } => {
                 tracing::info!("fetched leaf {}", leaf.height());
                 fetcher.store_and_notify(leaf).await;
             }
             Self::Continuation { callback } => callback.run(leaf.leaf.block_header().clone()),
         }
     }
+
+    async fn fail(self, err: QueryError) {
+        match self {
+            Self::Leaf { height, fetcher } => fetcher.notifiers.fail(Resource::Leaf, height, err),
+            // Every leaf fetch has a `Leaf` callback, and failing the leaf also fails requests for
+            // whatever we were going to fetch next at the same height.
+            Self::Continuation { .. } => {}
+        }
+    }
 }
@@ -0,0 +1,114 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Mirroring of committed availability writes to external sinks.
+
+use crate::{
+    availability::{BlockQueryData, LeafQueryData, VidCommonQueryData},
+    data_source::storage::UpdateAvailabilityStorage,
+    types::HeightIndexed,
+    VidShare,
+};
+use async_trait::async_trait;
+use hotshot_types::traits::node_implementation::NodeType;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+/// A write to availability storage which has been committed.
+///
+/// Mutations are serializable, so a sink can forward them to another system, and replayable: a
+/// consumer can reconstruct the availability data of this data source by [applying](Self::apply)
+/// them to its own storage. Applying a mutation more than once, or applying mutations out of order,
+/// is harmless, just like it is for the data source itself.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub enum AvailabilityMutation<Types: NodeType> {
+    InsertLeaf(LeafQueryData<Types>),
+    InsertBlock(BlockQueryData<Types>),
+    InsertVid {
+        common: VidCommonQueryData<Types>,
+        share: Option<VidShare>,
+    },
+}
+
+impl<Types: NodeType> HeightIndexed for AvailabilityMutation<Types> {
+    fn height(&self) -> u64 {
+        match self {
+            Self::InsertLeaf(leaf) => leaf.height(),
+            Self::InsertBlock(block) => block.height(),
+            Self::InsertVid { common, .. } => common.height(),
+        }
+    }
+}
+
+impl<Types: NodeType> AvailabilityMutation<Types> {
+    /// Replay this mutation in `storage`.
+    ///
+    /// The caller is responsible for committing the transaction.
+    pub async fn apply(
+        self,
+        storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::InsertLeaf(leaf) => storage.insert_leaf(leaf).await,
+            Self::InsertBlock(block) => storage.insert_block(block).await,
+            Self::InsertVid { common, share } => storage.insert_vid(common, share).await,
+        }
+    }
+}
+
+/// A sink which receives every availability write once it is durable.
+///
+/// A tap registered with [`with_tap`](super::Builder::with_tap) receives the mutations from each
+/// storage transaction only after that transaction has committed, with the leaf for each height
+/// preceding its block and VID data. Writes from different transactions, such as blocks appended
+/// from consensus and objects fetched from peers, may be committed concurrently, so consumers
+/// should not rely on mutations arriving in order of height.
+///
+/// Emitting is awaited on the path that stores the data, so a slow sink slows down storage of new
+/// data. Since the data is already durable, an error from the sink cannot be undone; it is logged
+/// and the mutations are dropped.
+#[async_trait]
+pub trait AvailabilityTap<Types: NodeType>: Debug + Send + Sync {
+    async fn emit(&self, mutations: Vec<AvailabilityMutation<Types>>) -> anyhow::Result<()>;
+}
+
+/// An [`AvailabilityTap`] which forwards mutations over a bounded channel.
+///
+/// When the channel is full, storing new data waits for the receiver to catch up. Once the receiver
+/// is dropped, emitting fails, and mutations are discarded.
+#[derive(Debug)]
+pub struct ChannelTap<Types: NodeType> {
+    sender: Sender<AvailabilityMutation<Types>>,
+}
+
+impl<Types: NodeType> ChannelTap<Types> {
+    /// Create a tap and the receiving end of its channel, which buffers up to `capacity` mutations.
+    pub fn new(capacity: usize) -> (Self, Receiver<AvailabilityMutation<Types>>) {
+        let (sender, receiver) = channel(capacity);
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl<Types: NodeType> AvailabilityTap<Types> for ChannelTap<Types> {
+    async fn emit(&self, mutations: Vec<AvailabilityMutation<Types>>) -> anyhow::Result<()> {
+        for mutation in mutations {
+            self.sender
+                .send(mutation)
+                .await
+                .map_err(|_| anyhow::anyhow!("availability tap receiver dropped"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! A probabilistic index of known transaction hashes.
+
+use crate::{
+    availability::{BlockQueryData, QueryablePayload, TransactionHash},
+    Payload,
+};
+use committable::Committable;
+use hotshot_types::traits::node_implementation::NodeType;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// The number of bits set in the filter for each transaction.
+const NUM_HASHES: u64 = 7;
+
+/// The number of bits in the filter per expected transaction.
+///
+/// Together with [`NUM_HASHES`], this gives a false positive rate of about 1% when the filter holds
+/// as many transactions as it was sized for.
+const BITS_PER_TRANSACTION: f64 = 9.6;
+
+/// A bloom filter over the hashes of all transactions in blocks known to this node.
+///
+/// The filter never yields false negatives: once it is [ready](Self::set_ready), a transaction
+/// which is not in the filter is definitely not in any block this node has stored. It may yield
+/// false positives, which must be resolved by an actual lookup.
+#[derive(Debug)]
+pub(super) struct TransactionFilter {
+    /// The bits of the filter, or [`None`] if the filter is disabled.
+    bits: Option<Box<[AtomicU64]>>,
+    /// Whether the filter has been populated with all transactions that were already in storage.
+    ready: AtomicBool,
+}
+
+impl Default for TransactionFilter {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl TransactionFilter {
+    /// A filter which stores nothing and treats every transaction as possibly existing.
+    pub(super) fn disabled() -> Self {
+        Self {
+            bits: None,
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// An empty filter sized for `capacity` transactions.
+    pub(super) fn new(capacity: usize) -> Self {
+        let num_bits = ((capacity.max(1) as f64) * BITS_PER_TRANSACTION).ceil() as usize;
+        let num_words = num_bits.div_ceil(64);
+        Self {
+            bits: Some((0..num_words).map(|_| AtomicU64::new(0)).collect()),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Is this filter enabled?
+    pub(super) fn is_enabled(&self) -> bool {
+        self.bits.is_some()
+    }
+
+    /// Mark the filter as populated with all transactions that were already in storage.
+    pub(super) fn set_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+
+    /// Add all the transactions in `block` to the filter.
+    pub(super) fn insert_block<Types>(&self, block: &BlockQueryData<Types>)
+    where
+        Types: NodeType,
+        Payload<Types>: QueryablePayload<Types>,
+    {
+        let Some(bits) = &self.bits else {
+            return;
+        };
+        for (_, txn) in block.enumerate() {
+            for bit in positions::<Types>(txn.commit(), bits.len()) {
+                bits[bit / 64].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Might a transaction with the given hash exist?
+    ///
+    /// This returns `true` if the filter is disabled or not yet ready.
+    pub(super) fn maybe_contains<Types>(&self, hash: TransactionHash<Types>) -> bool
+    where
+        Types: NodeType,
+    {
+        let Some(bits) = &self.bits else {
+            return true;
+        };
+        if !self.ready.load(Ordering::Acquire) {
+            return true;
+        }
+        positions::<Types>(hash, bits.len())
+            .all(|bit| bits[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// The bits in a filter of `num_words` words corresponding to `hash`.
+fn positions<Types>(hash: TransactionHash<Types>, num_words: usize) -> impl Iterator<Item = usize>
+where
+    Types: NodeType,
+{
+    // Transaction hashes are already uniformly distributed, so we can derive the bit positions
+    // directly from the hash, using double hashing.
+    let bytes: [u8; 32] = hash.into();
+    let h1 = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+    let num_bits = num_words as u64 * 64;
+    (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::mocks::{mock_transaction, MockPayload, MockTypes};
+    use hotshot_example_types::state_types::{TestInstanceState, TestValidatedState};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transaction_filter() {
+        let txns = (0..100u8)
+            .map(|i| mock_transaction(vec![i]))
+            .collect::<Vec<_>>();
+        let genesis = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::new(
+            genesis.header().clone(),
+            MockPayload {
+                transactions: txns.clone(),
+            },
+        );
+
+        // A disabled filter, or one which is not yet ready, treats every transaction as possibly
+        // existing.
+        let hash = mock_transaction(vec![0, 0]).commit();
+        assert!(TransactionFilter::disabled().maybe_contains::<MockTypes>(hash));
+        let filter = TransactionFilter::new(1000);
+        assert!(filter.maybe_contains::<MockTypes>(hash));
+
+        // Once ready, the filter contains every inserted transaction and (with these parameters)
+        // rejects most others.
+        filter.insert_block(&block);
+        filter.set_ready();
+        for txn in &txns {
+            assert!(filter.maybe_contains::<MockTypes>(txn.commit()));
+        }
+        let false_positives = (0..100u8)
+            .filter(|i| filter.maybe_contains::<MockTypes>(mock_transaction(vec![*i, 1]).commit()))
+            .count();
+        assert!(false_positives < 10, "{false_positives}");
+    }
+}
@@ -14,8 +14,8 @@
 
 use super::{
     header::{fetch_header_and_then, HeaderCallback},
-    AvailabilityProvider, FetchRequest, Fetchable, Fetcher, Heights, Notifiers, RangedFetchable,
-    Storable,
+    AvailabilityMutation, AvailabilityProvider, FetchRequest, Fetchable, Fetcher, Heights,
+    Notifiers, RangedFetchable, Resource, Storable,
 };
 use crate::{
     availability::{BlockId, QueryablePayload, VidCommonMetadata, VidCommonQueryData},
@@ -23,17 +23,28 @@ use crate::{
         storage::{AvailabilityStorage, UpdateAvailabilityStorage},
         VersionedDataSource,
     },
-    fetching::{self, request, Callback},
+    fetching::{self, request, Callback, Provider},
     types::HeightIndexed,
-    Header, Payload, QueryResult, VidCommon, VidShare,
+    Header, Payload, QueryError, QueryResult, VidCommon, VidShare,
 };
 use async_trait::async_trait;
 use derivative::Derivative;
 use derive_more::From;
 use futures::future::{BoxFuture, FutureExt};
-use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
-use std::sync::Arc;
+use hotshot_types::{
+    traits::{
+        block_contents::{BlockHeader, EncodeBytes, GENESIS_VID_NUM_STORAGE_NODES},
+        node_implementation::NodeType,
+    },
+    vid::{vid_scheme, VidSchemeType},
+};
+use jf_vid::VidScheme;
+use std::sync::{
+    atomic::{AtomicBool, Ordering as AtomicOrdering},
+    Arc,
+};
 use std::{cmp::Ordering, future::IntoFuture, iter::once, ops::RangeBounds};
+use tokio::task::spawn_blocking;
 
 pub(super) type VidCommonFetcher<Types, S, P> =
     fetching::Fetcher<request::VidCommonRequest, VidCommonCallback<Types, S, P>>;
@@ -54,6 +65,22 @@ where
     fn might_exist(self, heights: Heights) -> bool {
         self.0.might_exist(heights)
     }
+
+    fn height(self) -> Option<u64> {
+        self.0.height()
+    }
+}
+
+/// Has the VID data for the requested block been discarded by the pruner?
+fn vid_pruned<Types>(id: BlockId<Types>, heights: Heights) -> bool
+where
+    Types: NodeType,
+{
+    if let BlockId::Number(n) = id {
+        heights.vid_pruned(n as u64)
+    } else {
+        false
+    }
 }
 
 #[async_trait]
@@ -63,6 +90,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = VidCommonRequest<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::VidCommon);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req.0 {
@@ -72,6 +100,10 @@ where
         }
     }
 
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        vid_pruned(req.0, heights).then_some(QueryError::VidPruned)
+    }
+
     async fn passive_fetch(
         notifiers: &Notifiers<Types>,
         req: Self::Request,
@@ -94,6 +126,15 @@ where
         for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
         P: AvailabilityProvider<Types>,
     {
+        if fetcher.local_vid_recovery {
+            if let Some((header, provider)) =
+                LocalVidRecovery::load(tx, req.0, fetcher.range_chunk_size, &fetcher.provider).await
+            {
+                fetch_vid_common_with_provider(fetcher, header, provider);
+                return Ok(());
+            }
+        }
+
         fetch_header_and_then(
             tx,
             req.0,
@@ -141,6 +182,13 @@ where
         notifiers.vid_common.notify(self).await;
     }
 
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>) {
+        mutations.push(AvailabilityMutation::InsertVid {
+            common: self.clone(),
+            share: None,
+        });
+    }
+
     async fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
@@ -161,6 +209,13 @@ where
         notifiers.vid_common.notify(&self.0).await;
     }
 
+    fn mutations(&self, mutations: &mut Vec<AvailabilityMutation<Types>>) {
+        mutations.push(AvailabilityMutation::InsertVid {
+            common: self.0.clone(),
+            share: self.1.clone(),
+        });
+    }
+
     async fn store(
         self,
         storage: &mut (impl UpdateAvailabilityStorage<Types> + Send),
@@ -178,6 +233,21 @@ pub(super) fn fetch_vid_common_with_header<Types, S, P>(
     S: VersionedDataSource + 'static,
     for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
     P: AvailabilityProvider<Types>,
+{
+    let provider = fetcher.provider.clone();
+    fetch_vid_common_with_provider(fetcher, header, provider);
+}
+
+fn fetch_vid_common_with_provider<Types, S, P>(
+    fetcher: Arc<Fetcher<Types, S, P>>,
+    header: Header<Types>,
+    provider: impl Provider<Types, request::VidCommonRequest> + 'static,
+) where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    S: VersionedDataSource + 'static,
+    for<'a> S::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+    P: AvailabilityProvider<Types>,
 {
     // Now that we have the header, we only need to retrieve the VID common data.
     tracing::info!(
@@ -187,7 +257,7 @@ pub(super) fn fetch_vid_common_with_header<Types, S, P>(
     );
     fetcher.vid_common_fetcher.spawn_fetch(
         request::VidCommonRequest(header.payload_commitment()),
-        fetcher.provider.clone(),
+        provider,
         once(VidCommonCallback {
             header,
             fetcher: fetcher.clone(),
@@ -195,6 +265,97 @@ pub(super) fn fetch_vid_common_with_header<Types, S, P>(
     );
 }
 
+/// A provider which recomputes VID common data from a locally stored payload.
+///
+/// The VID common data for a block is a deterministic function of the payload and the number of
+/// storage nodes, so if we have the payload, we can recompute it by dispersing the payload again.
+/// The node's own VID share is not enough on its own, since recovering the payload from shares
+/// requires the common data we are missing. Recovery is attempted once; if it fails, or produces a
+/// commitment which does not match the header, requests are forwarded to the `fallback` provider.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+struct LocalVidRecovery<P> {
+    payload: Arc<[u8]>,
+    num_storage_nodes: usize,
+    attempted: AtomicBool,
+    #[derivative(Debug = "ignore")]
+    fallback: Arc<P>,
+}
+
+impl<P> LocalVidRecovery<P> {
+    /// Prepare to recover the VID common data for block `id`, if its payload is stored locally.
+    ///
+    /// The number of storage nodes is taken from the nearest earlier block in the preceding
+    /// `lookback` blocks which has VID common data.
+    async fn load<Types>(
+        tx: &mut impl AvailabilityStorage<Types>,
+        id: BlockId<Types>,
+        lookback: usize,
+        fallback: &Arc<P>,
+    ) -> Option<(Header<Types>, Self)>
+    where
+        Types: NodeType,
+        Payload<Types>: QueryablePayload<Types>,
+    {
+        let block = tx.get_block(id).await.ok()?;
+        let height = block.height() as usize;
+        let num_storage_nodes = if height == 0 {
+            GENESIS_VID_NUM_STORAGE_NODES
+        } else {
+            let prev = tx
+                .get_vid_common_range(height.saturating_sub(lookback)..height)
+                .await
+                .ok()?;
+            let common = prev.into_iter().rev().find_map(Result::ok)?;
+            VidSchemeType::get_num_storage_nodes(common.common()) as usize
+        };
+        tracing::debug!(height, num_storage_nodes, "attempting local VID recovery");
+        Some((
+            block.header().clone(),
+            Self {
+                payload: block.payload().encode(),
+                num_storage_nodes,
+                attempted: AtomicBool::new(false),
+                fallback: fallback.clone(),
+            },
+        ))
+    }
+}
+
+#[async_trait]
+impl<Types, P> Provider<Types, request::VidCommonRequest> for LocalVidRecovery<P>
+where
+    Types: NodeType,
+    P: Provider<Types, request::VidCommonRequest>,
+{
+    async fn fetch(&self, req: request::VidCommonRequest) -> Option<VidCommon> {
+        if !self.attempted.swap(true, AtomicOrdering::Relaxed) {
+            let payload = self.payload.clone();
+            let num_storage_nodes = self.num_storage_nodes;
+            match spawn_blocking(move || vid_scheme(num_storage_nodes).disperse(payload)).await {
+                Ok(Ok(disperse)) if disperse.commit == req.0 => {
+                    tracing::info!("recovered VID common {:?} locally", req.0);
+                    return Some(disperse.common);
+                }
+                Ok(Ok(disperse)) => {
+                    tracing::warn!(
+                        "locally recovered VID commitment {} does not match header {}",
+                        disperse.commit,
+                        req.0
+                    );
+                }
+                Ok(Err(err)) => {
+                    tracing::warn!("unable to recover VID common {:?} locally: {err}", req.0);
+                }
+                Err(err) => {
+                    tracing::warn!("unable to recover VID common {:?} locally: {err}", req.0);
+                }
+            }
+        }
+        self.fallback.fetch(req).await
+    }
+}
+
 #[derive(Derivative)]
 #[derivative(Debug(bound = ""))]
 pub(super) struct VidCommonCallback<Types: NodeType, S, P> {
@@ -235,6 +396,12 @@ where
         let common = VidCommonQueryData::new(self.header, common);
         self.fetcher.store_and_notify(common).await;
     }
+
+    async fn fail(self, err: QueryError) {
+        self.fetcher
+            .notifiers
+            .fail(Resource::VidCommon, self.header.block_number(), err);
+    }
 }
 
 #[async_trait]
@@ -244,6 +411,7 @@ where
     Payload<Types>: QueryablePayload<Types>,
 {
     type Request = VidCommonRequest<Types>;
+    const RESOURCE: Option<Resource> = Some(Resource::VidCommon);
 
     fn satisfies(&self, req: Self::Request) -> bool {
         match req.0 {
@@ -253,6 +421,10 @@ where
         }
     }
 
+    fn pruned(req: Self::Request, heights: Heights) -> Option<QueryError> {
+        vid_pruned(req.0, heights).then_some(QueryError::VidPruned)
+    }
+
     async fn passive_fetch(
         notifiers: &Notifiers<Types>,
         req: Self::Request,
@@ -21,7 +21,7 @@ use atomic_store::AtomicStoreLoader;
 use hotshot_types::traits::node_implementation::NodeType;
 use std::path::Path;
 
-pub use super::storage::fs::Transaction;
+pub use super::storage::fs::{CompactionSummary, Durability, Transaction};
 
 /// A data source for the APIs provided in this crate, backed by the local file system.
 ///
@@ -176,9 +176,32 @@ where
 
     /// Open an existing [FileSystemDataSource] from storage at `path`.
     ///
-    /// If there is no data at `path`, a new store will be created.
+    /// If there is no data at `path`, a new store will be created. If a previous
+    /// [compaction](Self::compact) was interrupted, it is rolled back or completed first.
     ///
-    /// The [FileSystemDataSource] will manage its own persistence synchronization.
+    /// The [FileSystemDataSource] will manage its own persistence synchronization. To choose how
+    /// eagerly committed data is synced to disk, build the data source from storage configured with
+    /// [`FileSystemStorage::with_durability`] instead:
+    ///
+    /// ```
+    /// # use hotshot_query_service::data_source::{
+    /// #   fs::Durability, storage::FileSystemStorage, FileSystemDataSource,
+    /// # };
+    /// # use hotshot_query_service::fetching::provider::NoFetching;
+    /// # use hotshot_query_service::testing::mocks::MockTypes as AppTypes;
+    /// # use std::{path::Path, time::Duration};
+    /// # async fn doc(storage_path: &Path) -> anyhow::Result<()> {
+    /// let storage = FileSystemStorage::open(storage_path).await?.with_durability(
+    ///     Durability::Batched {
+    ///         commits: 100,
+    ///         interval: Duration::from_secs(1),
+    ///     },
+    /// );
+    /// let data_source: FileSystemDataSource<AppTypes, NoFetching> =
+    ///     FileSystemDataSource::builder(storage, NoFetching).build().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn open(path: &Path, provider: P) -> anyhow::Result<Self> {
         FileSystemDataSource::builder(FileSystemStorage::open(path).await?, provider)
             .build()
@@ -235,6 +258,15 @@ where
         self.as_ref().skip_version().await?;
         Ok(())
     }
+
+    /// Rewrite the on-disk storage into a small number of large files.
+    ///
+    /// This can be run while the data source is serving reads; see
+    /// [`FileSystemStorage::compact`] for details. It is only supported for a data source created
+    /// with [create](Self::create) or [open](Self::open).
+    pub async fn compact(&self) -> anyhow::Result<CompactionSummary> {
+        self.as_ref().compact().await
+    }
 }
 
 #[cfg(any(test, feature = "testing"))]
@@ -278,12 +310,162 @@ mod impl_testable_data_source {
 
 #[cfg(test)]
 mod test {
-    use super::FileSystemDataSource;
-    use crate::{fetching::provider::NoFetching, testing::mocks::MockTypes};
+    use super::{Durability, FileSystemDataSource};
+    use crate::{
+        availability::{AvailabilityDataSource, LeafQueryData, UpdateAvailabilityData},
+        data_source::storage::FileSystemStorage,
+        fetching::provider::NoFetching,
+        testing::{mocks::MockTypes, setup_test},
+        Leaf,
+    };
+    use committable::Committable;
+    use futures::stream::{self, StreamExt};
+    use hotshot_example_types::state_types::{TestInstanceState, TestValidatedState};
+    use hotshot_types::simple_certificate::QuorumCertificate;
+    use std::{fs, mem, time::Duration};
+    use tempfile::TempDir;
 
     // For some reason this is the only way to import the macro defined in another module of this
     // crate.
     use crate::*;
 
     instantiate_data_source_tests!(FileSystemDataSource<MockTypes, NoFetching>);
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_compact() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let dir = TempDir::with_prefix("test_compact").unwrap();
+        let path = dir.path().join("store");
+        let ds = FileSystemDataSource::<MockTypes, _>::create(&path, NoFetching)
+            .await
+            .unwrap();
+
+        // Append a chain of leaves, one commit at a time.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaves = vec![];
+        for _ in 0..5 {
+            leaf.block_header_mut().block_number += 1;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let info = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+            ds.append(info.clone().into()).await.unwrap();
+            leaves.push(info);
+        }
+
+        // Compact while reading concurrently.
+        let (summary, read) = futures::join!(
+            ds.compact(),
+            stream::iter(&leaves)
+                .then(|leaf| async { ds.get_leaf(leaf.height() as usize).await.await })
+                .collect::<Vec<_>>()
+        );
+        summary.unwrap();
+        assert_eq!(read, leaves);
+
+        // The data is still available after compaction, both from the running data source and
+        // after reopening it.
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+        drop(ds);
+        let ds = FileSystemDataSource::<MockTypes, _>::open(&path, NoFetching)
+            .await
+            .unwrap();
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+        drop(ds);
+
+        // An interrupted compaction, which left a partial staging directory, is rolled back when
+        // the store is opened.
+        let staging = dir.path().join("store.compacting");
+        fs::create_dir_all(&staging).unwrap();
+        fs::write(staging.join("partial"), b"garbage").unwrap();
+        let ds = FileSystemDataSource::<MockTypes, _>::open(&path, NoFetching)
+            .await
+            .unwrap();
+        assert!(!staging.exists());
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_batched_durability_crash_recovery() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let dir = TempDir::with_prefix("test_batched_durability").unwrap();
+        let path = dir.path().join("store");
+        let storage = FileSystemStorage::create(&path)
+            .await
+            .unwrap()
+            .with_durability(Durability::Batched {
+                commits: 3,
+                interval: Duration::from_secs(3600),
+            });
+        let ds = FileSystemDataSource::<MockTypes, _>::builder(storage, NoFetching)
+            .build()
+            .await
+            .unwrap();
+
+        // Append enough leaves, one commit at a time, that some have been synced as part of a
+        // batch and some are still waiting for the next batch.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaves = vec![];
+        for _ in 0..5 {
+            leaf.block_header_mut().block_number += 1;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let info = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+            ds.append(info.clone().into()).await.unwrap();
+            leaves.push(info);
+        }
+
+        // Simulate a crash of the process by abandoning the data source without running any
+        // destructors. Every commit, synced or not, has been written to the store, so it survives
+        // and the store reopens in a consistent state.
+        mem::forget(ds);
+        let storage = FileSystemStorage::open(&path)
+            .await
+            .unwrap()
+            .with_durability(Durability::Batched {
+                commits: 3,
+                interval: Duration::from_secs(3600),
+            });
+        let ds = FileSystemDataSource::<MockTypes, _>::builder(storage, NoFetching)
+            .build()
+            .await
+            .unwrap();
+        for leaf in &leaves {
+            assert_eq!(*leaf, ds.get_leaf(leaf.height() as usize).await.await);
+        }
+
+        // The recovered store accepts new commits.
+        leaf.block_header_mut().block_number += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let info = LeafQueryData::new(leaf, qc).unwrap();
+        ds.append(info.clone().into()).await.unwrap();
+        assert_eq!(info, ds.get_leaf(info.height() as usize).await.await);
+    }
 }
@@ -0,0 +1,111 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+#![cfg(feature = "memory-data-source")]
+
+use super::{storage::MemoryStorage, AvailabilityProvider, FetchingDataSource};
+use crate::{
+    availability::{query_data::QueryablePayload, QueryableHeader},
+    Header, Payload,
+};
+use hotshot_types::traits::node_implementation::NodeType;
+
+pub use super::storage::memory::{Read, Transaction, Write};
+
+/// A data source for the APIs provided in this crate, which keeps all data in memory.
+///
+/// Nothing is persisted, so this data source is not suitable for production use. It is intended for
+/// unit testing application code which consumes or updates a data source, without the overhead of
+/// setting up a database or a temporary directory. Transactions have the same semantics as with the
+/// persistent backends: a write [`Transaction`] reads its own writes, and its changes only become
+/// visible to others, atomically, when it is [committed](super::Transaction::commit).
+///
+/// Like the file system backend, this data source does not support pruning or the aggregate
+/// statistics used by the node API for partial ranges of blocks.
+///
+/// ```
+/// # use hotshot_query_service::data_source::{ExtensibleDataSource, MemoryDataSource};
+/// # use hotshot_query_service::fetching::provider::NoFetching;
+/// # use hotshot_query_service::testing::mocks::MockTypes as AppTypes;
+/// # async fn doc() -> Result<(), anyhow::Error> {
+/// type AppState = &'static str;
+///
+/// let data_source: ExtensibleDataSource<MemoryDataSource<AppTypes, NoFetching>, AppState> =
+///     ExtensibleDataSource::new(MemoryDataSource::create(NoFetching).await?, "app state");
+/// # Ok(())
+/// # }
+/// ```
+pub type MemoryDataSource<Types, P> = FetchingDataSource<Types, MemoryStorage<Types>, P>;
+
+impl<Types: NodeType, P> MemoryDataSource<Types, P>
+where
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    P: AvailabilityProvider<Types>,
+{
+    /// Create a new, empty [MemoryDataSource].
+    pub async fn create(provider: P) -> anyhow::Result<Self> {
+        MemoryDataSource::builder(MemoryStorage::new(), provider)
+            .build()
+            .await
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+mod impl_testable_data_source {
+    use super::*;
+    use crate::{
+        data_source::UpdateDataSource,
+        testing::{consensus::DataSourceLifeCycle, mocks::MockTypes},
+    };
+    use async_trait::async_trait;
+    use hotshot::types::Event;
+
+    #[async_trait]
+    impl<P: AvailabilityProvider<MockTypes> + Default> DataSourceLifeCycle
+        for MemoryDataSource<MockTypes, P>
+    {
+        type Storage = MemoryStorage<MockTypes>;
+
+        async fn create(_node_id: usize) -> Self::Storage {
+            MemoryStorage::new()
+        }
+
+        async fn connect(storage: &Self::Storage) -> Self {
+            Self::builder(storage.reconnect(), Default::default())
+                .build()
+                .await
+                .unwrap()
+        }
+
+        async fn reset(storage: &Self::Storage) -> Self {
+            storage.clear().await;
+            Self::connect(storage).await
+        }
+
+        async fn handle_event(&self, event: &Event<MockTypes>) {
+            self.update(event).await.unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MemoryDataSource;
+    use crate::{fetching::provider::NoFetching, testing::mocks::MockTypes};
+
+    // For some reason this is the only way to import the macro defined in another module of this
+    // crate.
+    use crate::*;
+
+    instantiate_data_source_tests!(MemoryDataSource<MockTypes, NoFetching>);
+}
@@ -0,0 +1,126 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+#![cfg(feature = "rocksdb-data-source")]
+
+use super::{storage::RocksDbStorage, AvailabilityProvider, FetchingDataSource};
+use crate::{
+    availability::{query_data::QueryablePayload, QueryableHeader},
+    Header, Payload,
+};
+use hotshot_types::traits::node_implementation::NodeType;
+use std::path::Path;
+
+pub use super::storage::rocksdb::{Read, Transaction, Write};
+
+/// A data source for the APIs provided in this crate, backed by an embedded RocksDB database.
+///
+/// This is a lighter-weight alternative to [`SqlDataSource`](super::SqlDataSource) for nodes which
+/// do not want to run a separate database server. Unlike
+/// [`FileSystemDataSource`](super::FileSystemDataSource), a write [`Transaction`] does not block
+/// concurrent readers: each transaction reads from a consistent snapshot of the database, and
+/// changes made by a write transaction only become visible to others when it is
+/// [committed](super::Transaction::commit), atomically.
+///
+/// Like the file system backend, this data source does not support pruning or the aggregate
+/// statistics used by the node API for partial ranges of blocks.
+///
+/// ```
+/// # use hotshot_query_service::data_source::{ExtensibleDataSource, RocksDbDataSource};
+/// # use hotshot_query_service::fetching::provider::NoFetching;
+/// # use hotshot_query_service::testing::mocks::MockTypes as AppTypes;
+/// # use std::path::Path;
+/// # async fn doc(storage_path: &Path) -> Result<(), anyhow::Error> {
+/// type AppState = &'static str;
+///
+/// let data_source: ExtensibleDataSource<RocksDbDataSource<AppTypes, NoFetching>, AppState> =
+///     ExtensibleDataSource::new(RocksDbDataSource::create(storage_path, NoFetching).await?, "app state");
+/// # Ok(())
+/// # }
+/// ```
+pub type RocksDbDataSource<Types, P> = FetchingDataSource<Types, RocksDbStorage, P>;
+
+impl<Types: NodeType, P> RocksDbDataSource<Types, P>
+where
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    P: AvailabilityProvider<Types>,
+{
+    /// Create a new [RocksDbDataSource] with storage at `path`.
+    ///
+    /// If there is already a database at `path`, it will be destroyed.
+    pub async fn create(path: &Path, provider: P) -> anyhow::Result<Self> {
+        RocksDbDataSource::builder(RocksDbStorage::create(path).await?, provider)
+            .build()
+            .await
+    }
+
+    /// Open an existing [RocksDbDataSource] from storage at `path`.
+    ///
+    /// If there is no database at `path`, a new one will be created.
+    pub async fn open(path: &Path, provider: P) -> anyhow::Result<Self> {
+        RocksDbDataSource::builder(RocksDbStorage::open(path).await?, provider)
+            .build()
+            .await
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+mod impl_testable_data_source {
+    use super::*;
+    use crate::{
+        data_source::UpdateDataSource,
+        testing::{consensus::DataSourceLifeCycle, mocks::MockTypes},
+    };
+    use async_trait::async_trait;
+    use hotshot::types::Event;
+    use tempfile::TempDir;
+
+    #[async_trait]
+    impl<P: AvailabilityProvider<MockTypes> + Default> DataSourceLifeCycle
+        for RocksDbDataSource<MockTypes, P>
+    {
+        type Storage = TempDir;
+
+        async fn create(node_id: usize) -> Self::Storage {
+            TempDir::with_prefix(format!("rocksdb_data_source_{node_id}")).unwrap()
+        }
+
+        async fn connect(storage: &Self::Storage) -> Self {
+            Self::open(storage.path(), Default::default())
+                .await
+                .unwrap()
+        }
+
+        async fn reset(storage: &Self::Storage) -> Self {
+            Self::create(storage.path(), Default::default())
+                .await
+                .unwrap()
+        }
+
+        async fn handle_event(&self, event: &Event<MockTypes>) {
+            self.update(event).await.unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RocksDbDataSource;
+    use crate::{fetching::provider::NoFetching, testing::mocks::MockTypes};
+
+    // For some reason this is the only way to import the macro defined in another module of this
+    // crate.
+    use crate::*;
+
+    instantiate_data_source_tests!(RocksDbDataSource<MockTypes, NoFetching>);
+}
@@ -0,0 +1,379 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Point-in-time backups of a data source.
+//!
+//! A snapshot is a single archive containing every leaf, block and VID entry stored in a data
+//! source. The archive starts with [`SNAPSHOT_MAGIC`], followed by a sequence of records, each
+//! encoded as a little-endian `u64` length followed by that many bytes of a bincode-serialized
+//! record. A zero length marks the end of the archive, so a truncated snapshot is detected on
+//! import rather than silently restoring a partial chain.
+//!
+//! A snapshot is not trusted on import. Each leaf must be certified by its own QC, and each block
+//! and VID entry must match the header of the corresponding leaf, which must appear earlier in the
+//! snapshot or already be present in the data source. Records which are already present are
+//! skipped, so an interrupted import can be resumed by importing the same snapshot again.
+
+use super::{
+    storage::{AvailabilityStorage, NodeStorage, UpdateAvailabilityStorage},
+    update::verify_block_parts,
+    Transaction, VersionedDataSource,
+};
+use crate::{
+    availability::{
+        BlockId, BlockQueryData, LeafId, LeafQueryData, QueryablePayload, VidCommonQueryData,
+    },
+    types::HeightIndexed,
+    Payload, VidShare,
+};
+use anyhow::{ensure, Context};
+use async_trait::async_trait;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use hotshot_types::traits::node_implementation::NodeType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Identifies a snapshot archive, including the version of the archive format.
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"HSQSNAP1";
+
+/// Number of blocks read from storage at a time while exporting.
+const EXPORT_CHUNK_SIZE: usize = 100;
+
+/// Number of records inserted in each transaction while importing.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Upper bound on the size of a single record, to avoid allocating absurd buffers when importing
+/// a corrupt archive.
+const MAX_RECORD_SIZE: u64 = 1 << 30;
+
+/// Backup and restore of a complete data source.
+#[async_trait]
+pub trait SnapshotDataSource<Types: NodeType> {
+    /// Write a consistent snapshot of all stored data to `out`.
+    ///
+    /// All data is read from a single read-only [`Transaction`], so the snapshot reflects the state
+    /// of the data source at a single point in time, even if new data is concurrently being
+    /// written. Objects which are missing from the data source (for example, payloads which have
+    /// not been fetched yet or which have been pruned) are omitted from the snapshot.
+    async fn export_snapshot<W>(&self, out: W) -> anyhow::Result<ExportSummary>
+    where
+        W: AsyncWrite + Unpin + Send;
+
+    /// Restore data from a snapshot produced by [`export_snapshot`](Self::export_snapshot).
+    ///
+    /// Every record is validated before it is inserted: leaves against their QCs, and blocks and
+    /// VID entries against the headers of their leaves. The import fails at the first invalid
+    /// record.
+    ///
+    /// Records are inserted in batches, each batch in its own transaction. If the import fails
+    /// partway through, batches which were already committed remain in the data source. The import
+    /// can be resumed by importing the same snapshot again, in which case records which are
+    /// already present are skipped rather than validated and inserted a second time. The returned
+    /// summary describes the whole snapshot, including skipped records.
+    async fn import_snapshot<R>(&self, input: R) -> anyhow::Result<ExportSummary>
+    where
+        R: AsyncRead + Unpin + Send;
+}
+
+/// A report of the data contained in a snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    /// Number of leaves (and thus headers) in the snapshot.
+    pub leaves: usize,
+    /// Number of block payloads in the snapshot.
+    pub blocks: usize,
+    /// Number of VID entries in the snapshot.
+    pub vid: usize,
+    /// The lowest block height covered by the snapshot, if it is not empty.
+    pub first_height: Option<u64>,
+    /// The highest block height covered by the snapshot, if it is not empty.
+    pub last_height: Option<u64>,
+}
+
+impl ExportSummary {
+    fn record<Types: NodeType>(&mut self, record: &SnapshotRecord<Types>) {
+        let height = match record {
+            SnapshotRecord::Leaf(leaf) => {
+                self.leaves += 1;
+                leaf.height()
+            }
+            SnapshotRecord::Block(block) => {
+                self.blocks += 1;
+                block.height()
+            }
+            SnapshotRecord::Vid(common, _) => {
+                self.vid += 1;
+                common.height()
+            }
+        };
+        self.first_height = Some(self.first_height.map_or(height, |h| h.min(height)));
+        self.last_height = Some(self.last_height.map_or(height, |h| h.max(height)));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+enum SnapshotRecord<Types: NodeType> {
+    Leaf(LeafQueryData<Types>),
+    Block(BlockQueryData<Types>),
+    Vid(VidCommonQueryData<Types>, Option<VidShare>),
+}
+
+#[async_trait]
+impl<Types, D> SnapshotDataSource<Types> for D
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    D: VersionedDataSource,
+    for<'a> D::ReadOnly<'a>: AvailabilityStorage<Types> + NodeStorage<Types>,
+    for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+{
+    async fn export_snapshot<W>(&self, mut out: W) -> anyhow::Result<ExportSummary>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let mut tx = self.read().await.context("opening read transaction")?;
+        let block_height = NodeStorage::<Types>::block_height(&mut tx).await?;
+        let mut summary = ExportSummary::default();
+
+        out.write_all(SNAPSHOT_MAGIC).await?;
+        for start in (0..block_height).step_by(EXPORT_CHUNK_SIZE) {
+            let end = (start + EXPORT_CHUNK_SIZE).min(block_height);
+            let leaves = tx.get_leaf_range(start..end).await?;
+            let blocks = tx.get_block_range(start..end).await?;
+            let vid = tx.get_vid_common_range(start..end).await?;
+
+            let mut records = vec![];
+            records.extend(leaves.into_iter().flatten().map(SnapshotRecord::Leaf));
+            records.extend(blocks.into_iter().flatten().map(SnapshotRecord::Block));
+            for common in vid.into_iter().flatten() {
+                let share = tx.vid_share(common.height() as usize).await.ok();
+                records.push(SnapshotRecord::Vid(common, share));
+            }
+
+            for record in records {
+                summary.record(&record);
+                write_record(&mut out, &record).await?;
+            }
+        }
+        out.write_all(&0u64.to_le_bytes()).await?;
+        out.flush().await?;
+
+        tracing::info!(?summary, "exported snapshot");
+        Ok(summary)
+    }
+
+    async fn import_snapshot<R>(&self, mut input: R) -> anyhow::Result<ExportSummary>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut magic = [0; SNAPSHOT_MAGIC.len()];
+        input
+            .read_exact(&mut magic)
+            .await
+            .context("reading snapshot header")?;
+        ensure!(&magic == SNAPSHOT_MAGIC, "not a snapshot archive");
+
+        let mut summary = ExportSummary::default();
+        let mut skipped = 0;
+        let mut batch = vec![];
+        while let Some(record) = read_record::<Types, _>(&mut input).await? {
+            // Only start a new batch at a leaf. The export writes each chunk of leaves before the
+            // blocks and VID entries for that chunk, so this keeps each block in the same batch as
+            // its VID common data, which is needed to verify the block's payload.
+            if batch.len() >= IMPORT_BATCH_SIZE && matches!(record, SnapshotRecord::Leaf(_)) {
+                skipped += insert_batch(self, std::mem::take(&mut batch)).await?;
+            }
+            summary.record(&record);
+            batch.push(record);
+        }
+        skipped += insert_batch(self, batch).await?;
+
+        tracing::info!(?summary, skipped, "imported snapshot");
+        Ok(summary)
+    }
+}
+
+async fn write_record<Types, W>(out: &mut W, record: &SnapshotRecord<Types>) -> anyhow::Result<()>
+where
+    Types: NodeType,
+    W: AsyncWrite + Unpin + Send,
+{
+    let bytes = bincode::serialize(record)?;
+    out.write_all(&(bytes.len() as u64).to_le_bytes()).await?;
+    out.write_all(&bytes).await?;
+    Ok(())
+}
+
+async fn read_record<Types, R>(input: &mut R) -> anyhow::Result<Option<SnapshotRecord<Types>>>
+where
+    Types: NodeType,
+    R: AsyncRead + Unpin + Send,
+{
+    let mut len = [0; 8];
+    input
+        .read_exact(&mut len)
+        .await
+        .context("snapshot is truncated")?;
+    let len = u64::from_le_bytes(len);
+    if len == 0 {
+        return Ok(None);
+    }
+    ensure!(
+        len <= MAX_RECORD_SIZE,
+        "snapshot record too large ({len} bytes)"
+    );
+
+    let mut bytes = vec![0; len as usize];
+    input
+        .read_exact(&mut bytes)
+        .await
+        .context("snapshot is truncated")?;
+    Ok(Some(
+        bincode::deserialize(&bytes).context("malformed snapshot record")?,
+    ))
+}
+
+/// Validate and insert a batch of records, returning the number of records skipped because they
+/// were already present.
+async fn insert_batch<Types, D>(ds: &D, batch: Vec<SnapshotRecord<Types>>) -> anyhow::Result<usize>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    D: VersionedDataSource,
+    for<'a> D::ReadOnly<'a>: AvailabilityStorage<Types>,
+    for<'a> D::Transaction<'a>: UpdateAvailabilityStorage<Types>,
+{
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    // Leaves must be inserted before the blocks and VID data which reference them. Within a batch
+    // they may be out of order, so partition them first.
+    let mut leaves = vec![];
+    let mut blocks = vec![];
+    let mut vid = vec![];
+    let mut skipped = 0;
+    {
+        let mut tx = ds.read().await.context("opening read transaction")?;
+        for record in batch {
+            match record {
+                SnapshotRecord::Leaf(leaf) => {
+                    if tx
+                        .get_leaf(LeafId::Number(leaf.height() as usize))
+                        .await
+                        .is_ok()
+                    {
+                        skipped += 1;
+                    } else {
+                        verify_block_parts(&leaf, None, None, None)?;
+                        leaves.push(leaf);
+                    }
+                }
+                SnapshotRecord::Block(block) => {
+                    if tx
+                        .get_block(BlockId::Number(block.height() as usize))
+                        .await
+                        .is_ok()
+                    {
+                        skipped += 1;
+                    } else {
+                        blocks.push(block);
+                    }
+                }
+                SnapshotRecord::Vid(common, share) => {
+                    let height = common.height() as usize;
+                    let present = tx.get_vid_common(BlockId::Number(height)).await.is_ok()
+                        && (share.is_none() || tx.vid_share(height).await.is_ok());
+                    if present {
+                        skipped += 1;
+                    } else {
+                        vid.push((common, share));
+                    }
+                }
+            }
+        }
+
+        // Blocks and VID entries are checked against the leaf at the same height, which is either
+        // in this batch or was already in storage.
+        let batch_leaves = leaves
+            .iter()
+            .map(|leaf| (leaf.height(), leaf))
+            .collect::<HashMap<_, _>>();
+        let mut stored_leaves = HashMap::new();
+        for height in blocks
+            .iter()
+            .map(|block| block.height())
+            .chain(vid.iter().map(|(common, _)| common.height()))
+        {
+            if batch_leaves.contains_key(&height) || stored_leaves.contains_key(&height) {
+                continue;
+            }
+            let leaf = tx
+                .get_leaf(LeafId::Number(height as usize))
+                .await
+                .with_context(|| format!("snapshot is missing leaf {height}"))?;
+            stored_leaves.insert(height, leaf);
+        }
+        let leaf_at = |height| {
+            batch_leaves
+                .get(&height)
+                .copied()
+                .or(stored_leaves.get(&height))
+        };
+
+        for (common, _) in &vid {
+            let leaf = leaf_at(common.height()).unwrap();
+            ensure!(
+                common.block_hash() == leaf.block_hash(),
+                "VID common data at height {} does not match header",
+                common.height()
+            );
+        }
+        let batch_vid = vid
+            .iter()
+            .map(|(common, _)| (common.height(), common))
+            .collect::<HashMap<_, _>>();
+        for block in &blocks {
+            let height = block.height();
+            let leaf = leaf_at(height).unwrap();
+            let common = match batch_vid.get(&height) {
+                Some(common) => Some((*common).clone()),
+                None => tx
+                    .get_vid_common(BlockId::Number(height as usize))
+                    .await
+                    .ok(),
+            };
+            if common.is_some() || height == 0 {
+                verify_block_parts(leaf, Some(block), common.as_ref(), None)?;
+            } else {
+                // Without VID common data, the payload commitment cannot be recomputed, but the
+                // block must at least have the header certified by its leaf.
+                ensure!(
+                    block.hash() == leaf.block_hash(),
+                    "block {height} does not match header"
+                );
+            }
+        }
+    }
+
+    let mut tx = ds.write().await.context("opening write transaction")?;
+    tx.insert_leaves(leaves).await?;
+    for block in blocks {
+        tx.insert_block(block).await?;
+    }
+    for (common, share) in vid {
+        tx.insert_vid(common, share).await?;
+    }
+    tx.commit().await?;
+    Ok(skipped)
+}
@@ -26,7 +26,7 @@ pub use anyhow::Error;
 use hotshot_types::traits::node_implementation::NodeType;
 pub use refinery::Migration;
 
-pub use sql::Transaction;
+pub use sql::{SqlConfigBuilder, Transaction};
 
 pub type Builder<Types, Provider> = fetching::Builder<Types, SqlStorage, Provider>;
 
@@ -375,19 +375,36 @@ mod test {
     use super::*;
     use crate::{
         availability::{
-            AvailabilityDataSource, BlockInfo, LeafQueryData, UpdateAvailabilityData,
-            VidCommonQueryData,
+            AvailabilityDataSource, BlockInfo, BlockQueryData, LeafQueryData,
+            UpdateAvailabilityData, VidCommonQueryData,
         },
         data_source::{
-            storage::{NodeStorage, UpdateAvailabilityStorage},
-            Transaction, VersionedDataSource,
+            fetching::{AvailabilityMutation, ChannelTap},
+            storage::{
+                pruning::{HeightWindowPolicy, PrunedHeightStorage, PrunerCfg},
+                AvailabilityStorage, InvalidData, NodeStorage, PayloadTooLarge, Reorg,
+                UpdateAvailabilityStorage,
+            },
+            MissingVidPolicy, Transaction, UpdateDataSource, VersionedDataSource,
         },
         fetching::provider::NoFetching,
-        testing::{consensus::DataSourceLifeCycle, mocks::MockTypes, setup_test},
+        status::HasMetrics,
+        testing::{
+            consensus::{DataSourceLifeCycle, MockDataSource, MockNetwork},
+            mocks::{mock_transaction, MockPayload, MockTypes},
+            setup_test,
+        },
+        types::HeightIndexed,
+        Leaf, QueryError,
     };
+    use committable::Committable;
+    use futures::{future, stream::StreamExt};
+    use hotshot::types::EventType;
     use hotshot_example_types::state_types::{TestInstanceState, TestValidatedState};
-    use hotshot_types::vid::vid_scheme;
+    use hotshot_types::{simple_certificate::QuorumCertificate, vid::vid_scheme};
     use jf_vid::VidScheme;
+    use std::{sync::Arc, time::Duration};
+    use tokio::time::sleep;
 
     type D = SqlDataSource<MockTypes, NoFetching>;
 
@@ -435,4 +452,540 @@ mod test {
             disperse.shares[0]
         );
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_missing_vid_at_decide() {
+        setup_test();
+
+        // Get a decide event from consensus, and drop the VID share of its most recent leaf.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        let events = network.handle().event_stream();
+        network.start().await;
+        let mut event = events
+            .filter(|event| future::ready(matches!(event.event, EventType::Decide { .. })))
+            .take(1)
+            .collect::<Vec<_>>()
+            .await
+            .remove(0);
+        let EventType::Decide { leaf_chain, .. } = &mut event.event else {
+            unreachable!();
+        };
+        let mut info = leaf_chain[0].clone();
+        info.vid_share = None;
+        let height = info.leaf.block_header().block_number;
+        *leaf_chain = Arc::new(vec![info]);
+
+        let storage = D::create(0).await;
+        let ds = <D as DataSourceLifeCycle>::connect(&storage).await;
+        let missing_vid = || {
+            ds.metrics()
+                .get_counter("missing_vid_at_decide")
+                .unwrap()
+                .get()
+        };
+
+        // With `MissingVidPolicy::Error`, the update fails and nothing is stored.
+        ds.update_with_vid_policy(&event, MissingVidPolicy::Error)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            NodeStorage::<MockTypes>::block_height(&mut ds.read().await.unwrap())
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(missing_vid(), 0);
+
+        // With the default policy, the block is stored without VID data, which is counted.
+        let summary = ds
+            .update_with_vid_policy(&event, MissingVidPolicy::Store)
+            .await
+            .unwrap();
+        assert_eq!(summary.missing_vid, [height]);
+        let leaf = ds
+            .get_leaf(height as usize)
+            .await
+            .try_resolve()
+            .ok()
+            .unwrap();
+        assert_eq!(leaf.height(), height);
+        assert_eq!(missing_vid(), 1);
+
+        // Blocks appended outside of a decide, for example after being fetched, are not counted.
+        ds.append(BlockInfo::new(leaf, None, None, None))
+            .await
+            .unwrap();
+        assert_eq!(missing_vid(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_payload_bytes() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds: D = storage
+            .config()
+            .builder(NoFetching)
+            .await
+            .unwrap()
+            .with_max_payload_bytes(64)
+            .build()
+            .await
+            .unwrap();
+
+        // The genesis payload is always accepted.
+        let genesis = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let genesis_block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        ds.append(BlockInfo::new(genesis, Some(genesis_block), None, None))
+            .await
+            .unwrap();
+
+        // Mock up a block with a payload larger than the limit.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        leaf.block_header_mut().block_number += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let payload = MockPayload {
+            transactions: vec![mock_transaction(vec![0; 100])],
+        };
+        let block = BlockQueryData::new(leaf.block_header().clone(), payload);
+        let leaf = LeafQueryData::new(leaf, qc).unwrap();
+        assert!(block.size() > 64);
+
+        // Appending it fails, and nothing is stored.
+        let err = ds
+            .append(BlockInfo::new(leaf, Some(block.clone()), None, None))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<PayloadTooLarge>().unwrap();
+        assert_eq!(err.height, 1);
+        assert_eq!(err.size, block.size());
+        assert_eq!(err.limit, 64);
+        assert_eq!(
+            NodeStorage::<MockTypes>::block_height(&mut ds.read().await.unwrap())
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify_inserts() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds: D = storage
+            .config()
+            .builder(NoFetching)
+            .await
+            .unwrap()
+            .with_verify_inserts(true)
+            .build()
+            .await
+            .unwrap();
+
+        // A consistent genesis block is accepted.
+        let genesis = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let genesis_block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let genesis_hash = genesis.hash();
+        ds.append(BlockInfo::new(genesis, Some(genesis_block), None, None))
+            .await
+            .unwrap();
+
+        // Mock up a leaf at height 1.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        leaf.block_header_mut().block_number += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let header = leaf.block_header().clone();
+        let leaf = LeafQueryData::new(leaf, qc).unwrap();
+        let common =
+            VidCommonQueryData::new(header.clone(), vid_scheme(2).disperse([]).unwrap().common);
+
+        // A leaf which is not certified by its QC is rejected.
+        let mut bad_leaf = leaf.clone();
+        bad_leaf.qc.data.leaf_commit = genesis_hash;
+        let err = ds
+            .append(BlockInfo::new(bad_leaf, None, None, None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<InvalidData>().unwrap().height, 1);
+
+        // A payload which does not match the header is rejected.
+        let payload = MockPayload {
+            transactions: vec![mock_transaction(vec![0])],
+        };
+        let block = BlockQueryData::new(header, payload);
+        let err = ds
+            .append(BlockInfo::new(
+                leaf.clone(),
+                Some(block.clone()),
+                Some(common),
+                None,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<InvalidData>().unwrap().height, 1);
+
+        // A block without VID common data cannot be verified, and is rejected.
+        let err = ds
+            .append(BlockInfo::new(leaf, Some(block), None, None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<InvalidData>().unwrap().height, 1);
+
+        // Nothing was stored.
+        assert_eq!(
+            NodeStorage::<MockTypes>::block_height(&mut ds.read().await.unwrap())
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tap() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let (tap, mut mutations) = ChannelTap::new(16);
+        let ds: D = storage
+            .config()
+            .builder(NoFetching)
+            .await
+            .unwrap()
+            .with_tap(tap)
+            .build()
+            .await
+            .unwrap();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let disperse = vid_scheme(2).disperse([]).unwrap();
+        let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common);
+        let share = disperse.shares[0].clone();
+        ds.append(BlockInfo::new(
+            leaf.clone(),
+            Some(block.clone()),
+            Some(common.clone()),
+            Some(share.clone()),
+        ))
+        .await
+        .unwrap();
+
+        // Each write is emitted once the append has committed.
+        let mut emitted = vec![];
+        for _ in 0..3 {
+            emitted.push(mutations.recv().await.unwrap());
+        }
+        assert_eq!(
+            emitted,
+            [
+                AvailabilityMutation::InsertLeaf(leaf.clone()),
+                AvailabilityMutation::InsertBlock(block.clone()),
+                AvailabilityMutation::InsertVid {
+                    common: common.clone(),
+                    share: Some(share.clone()),
+                },
+            ]
+        );
+        assert!(mutations.try_recv().is_err());
+
+        // Replaying the mutations into another data source reproduces the data.
+        let replica_storage = D::create(1).await;
+        let replica = <D as DataSourceLifeCycle>::connect(&replica_storage).await;
+        let mut tx = replica.write().await.unwrap();
+        for mutation in emitted {
+            mutation.apply(&mut tx).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+        assert_eq!(replica.get_leaf(0).await.await, leaf);
+        assert_eq!(replica.get_block(0).await.await, block);
+        assert_eq!(replica.get_vid_common(0).await.await, common);
+        assert_eq!(
+            NodeStorage::<MockTypes>::vid_share(&mut replica.read().await.unwrap(), 0)
+                .await
+                .unwrap(),
+            share
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_local_vid_recovery() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds: D = storage
+            .config()
+            .builder(NoFetching)
+            .await
+            .unwrap()
+            .with_local_vid_recovery(true)
+            .build()
+            .await
+            .unwrap();
+
+        // Store a genesis block with VID common data.
+        let genesis = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let genesis_block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let genesis_common = VidCommonQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        ds.append(BlockInfo::new(
+            genesis,
+            Some(genesis_block.clone()),
+            Some(genesis_common.clone()),
+            None,
+        ))
+        .await
+        .unwrap();
+
+        // Store the next block with its payload, but without VID common data.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        leaf.block_header_mut().block_number += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let header = leaf.block_header().clone();
+        let leaf = LeafQueryData::new(leaf, qc).unwrap();
+        let block = BlockQueryData::new(header.clone(), genesis_block.payload().clone());
+        ds.append(BlockInfo::new(leaf, Some(block), None, None))
+            .await
+            .unwrap();
+
+        // There is no provider to fetch from, but the VID common data can be recovered from the
+        // payload, using the number of storage nodes from the previous block.
+        let expected = VidCommonQueryData::new(header, genesis_common.common().clone());
+        let common = tokio::time::timeout(Duration::from_secs(60), async {
+            ds.get_vid_common(1).await.await
+        })
+        .await
+        .unwrap();
+        assert_eq!(common, expected);
+
+        // The recovered data is persisted.
+        let mut tx = ds.read().await.unwrap();
+        assert_eq!(tx.get_vid_common(1.into()).await.unwrap(), expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_purge_from() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        let storage = D::create(0).await;
+        let ds = <D as DataSourceLifeCycle>::connect(&storage).await;
+
+        // Mock up a short chain, with a fork of the last block.
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut chain = vec![];
+        for i in 0..3 {
+            leaf.block_header_mut().block_number = i;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            chain.push(LeafQueryData::new(leaf.clone(), qc.clone()).unwrap());
+        }
+        leaf.block_header_mut().timestamp += 1;
+        qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+        let fork = LeafQueryData::new(leaf, qc).unwrap();
+
+        let mut tx = ds.write().await.unwrap();
+        for leaf in &chain {
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(BlockQueryData::new(
+                leaf.header().clone(),
+                MockPayload::genesis(),
+            ))
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        // The fork conflicts with the stored chain.
+        let mut tx = ds.write().await.unwrap();
+        let err = tx.insert_leaf(fork.clone()).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Reorg<MockTypes>>().unwrap().height, 2);
+        tx.revert().await;
+
+        // After purging the divergent suffix, the fork can be inserted.
+        let mut tx = ds.write().await.unwrap();
+        tx.purge_from(2).await.unwrap();
+        assert_eq!(
+            NodeStorage::<MockTypes>::block_height(&mut tx)
+                .await
+                .unwrap(),
+            2
+        );
+        tx.get_leaf(2.into()).await.unwrap_err();
+        tx.get_block(2.into()).await.unwrap_err();
+        tx.insert_leaf(fork.clone()).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = ds.read().await.unwrap();
+        assert_eq!(tx.get_leaf(1.into()).await.unwrap(), chain[1]);
+        assert_eq!(tx.get_leaf(2.into()).await.unwrap(), fork);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pruned_payload_fetch_fails() {
+        use hotshot_example_types::node_types::TestVersions;
+
+        setup_test();
+
+        // Keep only the payload of the latest block.
+        let storage = D::create(0).await;
+        let ds: D = storage
+            .config()
+            .pruner_cfg(
+                PrunerCfg::new()
+                    .with_prune_policy(HeightWindowPolicy::new().with_payload_window(1))
+                    .with_minimum_retention(Duration::ZERO)
+                    .with_interval(Duration::from_millis(100)),
+            )
+            .unwrap()
+            .connect(NoFetching)
+            .await
+            .unwrap();
+
+        let mut qc = QuorumCertificate::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut tx = ds.write().await.unwrap();
+        for i in 0..3 {
+            leaf.block_header_mut().block_number = i;
+            qc.data.leaf_commit = <Leaf<MockTypes> as Committable>::commit(&leaf);
+            let leaf = LeafQueryData::new(leaf.clone(), qc.clone()).unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(BlockQueryData::new(
+                leaf.header().clone(),
+                MockPayload::genesis(),
+            ))
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        // Wait for the pruner to discard the older payloads.
+        while ds
+            .read()
+            .await
+            .unwrap()
+            .load_payload_pruned_height()
+            .await
+            .unwrap()
+            != Some(1)
+        {
+            tracing::info!("waiting for pruner");
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        // Requests for the pruned payloads fail immediately, rather than waiting for a fetch which
+        // will never happen.
+        let timeout = Duration::from_secs(60);
+        for i in 0..2 {
+            let err = ds
+                .get_block(i)
+                .await
+                .try_with_timeout(timeout)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::PayloadPruned), "{err:#}");
+            let err = ds
+                .get_payload(i)
+                .await
+                .try_with_timeout(timeout)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::PayloadPruned), "{err:#}");
+            ds.get_leaf(i).await.try_resolve().ok().unwrap();
+        }
+        // The same goes for ranges containing pruned payloads.
+        let mut blocks = ds.get_block_range(0..3).await.collect::<Vec<_>>().await;
+        let latest = blocks.pop().unwrap().try_resolve().ok().unwrap();
+        assert_eq!(latest.height(), 2);
+        for block in blocks {
+            assert!(matches!(block.error(), Some(QueryError::PayloadPruned)));
+        }
+    }
 }
@@ -21,6 +21,7 @@
 //! This module also comes with a few pre-built persistence implementations:
 //! * [`SqlStorage`]
 //! * [`FileSystemStorage`]
+//! * [`RocksDbStorage`]
 //! * [`NoStorage`]
 //!
 //! # Storage Traits vs Data Source Traits
@@ -59,42 +60,51 @@
 
 use crate::{
     availability::{
-        BlockId, BlockQueryData, LeafId, LeafQueryData, PayloadMetadata, PayloadQueryData,
-        QueryableHeader, QueryablePayload, TransactionHash, TransactionQueryData,
-        VidCommonMetadata, VidCommonQueryData,
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafHash, LeafId, LeafQueryData,
+        PayloadByteRange, PayloadMetadata, PayloadQueryData, QueryableHeader, QueryablePayload,
+        RawPayloadQueryData, TransactionHash, TransactionQueryData, VidCommonMetadata,
+        VidCommonQueryData,
     },
     explorer::{
         query_data::{
-            BlockDetail, BlockIdentifier, BlockSummary, ExplorerSummary, GetBlockDetailError,
+            BlockDetail, BlockIdentifier, BlockStatistics, BlockSummary, ExplorerSummary,
+            GetBlockDetailError, GetBlockStatisticsError, GetBlockStatisticsRequest,
             GetBlockSummariesError, GetBlockSummariesRequest, GetExplorerSummaryError,
             GetSearchResultsError, GetTransactionDetailError, GetTransactionSummariesError,
-            GetTransactionSummariesRequest, SearchResult, TransactionDetailResponse,
-            TransactionIdentifier, TransactionSummary,
+            GetTransactionSummariesRequest, SearchMatch, SearchQuery, SearchResult,
+            TransactionDetailResponse, TransactionIdentifier, TransactionSummary,
         },
         traits::{ExplorerHeader, ExplorerTransaction},
     },
-    merklized_state::{MerklizedState, Snapshot},
-    node::{SyncStatus, TimeWindowQueryData, WindowStart},
-    Header, Payload, QueryResult, Transaction, VidShare,
+    merklized_state::{MerklizedState, Snapshot, StateDiff},
+    node::{SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
+    types::HeightIndexed,
+    Header, Payload, QueryError, QueryResult, Transaction, VidShare,
 };
 use async_trait::async_trait;
 use futures::future::Future;
 use hotshot_types::traits::node_implementation::NodeType;
 use jf_merkle_tree::prelude::MerkleProof;
-use std::ops::RangeBounds;
-use tagged_base64::TaggedBase64;
+use snafu::Snafu;
+use std::ops::{Range, RangeBounds};
 
 pub mod fail_storage;
 pub mod fs;
 mod ledger_log;
+pub mod memory;
 pub mod no_storage;
 pub mod pruning;
+pub mod rocksdb;
 pub mod sql;
 
+#[cfg(feature = "rocksdb-data-source")]
+pub use self::rocksdb::RocksDbStorage;
 #[cfg(any(test, feature = "testing"))]
 pub use fail_storage::FailStorage;
 #[cfg(feature = "file-system-data-source")]
 pub use fs::FileSystemStorage;
+#[cfg(feature = "memory-data-source")]
+pub use memory::MemoryStorage;
 #[cfg(feature = "no-storage")]
 pub use no_storage::NoStorage;
 #[cfg(feature = "sql-data-source")]
@@ -128,6 +138,36 @@ where
         &mut self,
         id: BlockId<Types>,
     ) -> QueryResult<PayloadMetadata<Types>>;
+
+    /// Get the encoded bytes of a block payload.
+    ///
+    /// The default implementation loads the decoded payload and encodes it again. Storage which
+    /// keeps payloads in their encoded form should override this to return the bytes directly.
+    async fn get_raw_payload(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<RawPayloadQueryData<Types>> {
+        self.get_payload(id).await.map(RawPayloadQueryData::from)
+    }
+
+    /// Get a range of the encoded bytes of a block payload.
+    ///
+    /// The range is clamped to the length of the encoded payload. The default implementation loads
+    /// the whole encoded payload and slices it. Storage which can read part of a stored payload
+    /// should override this to avoid loading the rest.
+    async fn get_payload_bytes(
+        &mut self,
+        id: BlockId<Types>,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>> {
+        self.get_raw_payload(id)
+            .await
+            .map(|payload| PayloadByteRange::new(payload, range))
+    }
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>>;
     async fn get_vid_common(
         &mut self,
         id: BlockId<Types>,
@@ -137,6 +177,38 @@ where
         id: BlockId<Types>,
     ) -> QueryResult<VidCommonMetadata<Types>>;
 
+    /// Get the VID share belonging to the storage node with the given `index`.
+    ///
+    /// Only shares inserted with
+    /// [`insert_vid_share`](UpdateAvailabilityStorage::insert_vid_share) can be retrieved this
+    /// way. Storage which does not index shares by node returns [`QueryError::Missing`].
+    async fn get_vid_share(&mut self, _id: BlockId<Types>, _index: usize) -> QueryResult<VidShare> {
+        Err(QueryError::Missing)
+    }
+
+    /// Get all leaves whose header timestamp is in the range `[start, end)`, with their heights.
+    ///
+    /// Storage which does not index headers by timestamp returns an error.
+    async fn get_leaf_range_by_time(
+        &mut self,
+        _start: u64,
+        _end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        Err(QueryError::Error {
+            message: "this storage does not support querying leaves by time".into(),
+        })
+    }
+
+    /// Get the leaf proposed in consensus view `view`.
+    ///
+    /// Fails with [`QueryError::NotFound`] if no leaf from `view` is stored. Storage which does not
+    /// index leaves by view returns an error.
+    async fn get_leaf_by_view(&mut self, _view: u64) -> QueryResult<LeafQueryData<Types>> {
+        Err(QueryError::Error {
+            message: "this storage does not support querying leaves by view".into(),
+        })
+    }
+
     async fn get_leaf_range<R>(
         &mut self,
         range: R,
@@ -159,6 +231,12 @@ where
         &mut self,
         range: R,
     ) -> QueryResult<Vec<QueryResult<PayloadMetadata<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static;
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
     where
         R: RangeBounds<usize> + Send + 'static;
     async fn get_vid_common_range<R>(
@@ -180,14 +258,116 @@ where
     ) -> QueryResult<TransactionQueryData<Types>>;
 }
 
+/// An attempt to insert an object which conflicts with data already in storage.
+///
+/// Storage returns this error, wrapped in [`anyhow::Error`], when asked to insert an object at a
+/// height where a different object is already stored. This indicates a fork or corrupt data, so
+/// the existing object is never overwritten. Callers can distinguish this case from other failures
+/// using [`anyhow::Error::downcast_ref`].
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("inconsistent data at height {height}: {reason}"))]
+pub struct InconsistentData {
+    pub height: u64,
+    pub reason: String,
+}
+
+/// An attempt to insert a leaf at a height where a different leaf is already stored.
+///
+/// Storage returns this error, wrapped in [`anyhow::Error`], instead of overwriting the stored
+/// leaf. Since a height can only ever be decided once, this means either that the caller was given
+/// an invalid event or that the stored chain has diverged from consensus. The caller can recover by
+/// [purging](UpdateAvailabilityStorage::purge_from) the divergent suffix of the chain, starting at
+/// `height`, and then inserting the new leaf.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("reorg at height {height}: stored leaf {old_hash}, new leaf {new_hash}"))]
+pub struct Reorg<Types: NodeType> {
+    pub height: u64,
+    pub old_hash: LeafHash<Types>,
+    pub new_hash: LeafHash<Types>,
+}
+
+impl<Types: NodeType> Reorg<Types> {
+    /// Check that `new` does not conflict with the leaf `old` stored at the same height.
+    pub(crate) fn check(old: LeafHash<Types>, new: &LeafQueryData<Types>) -> Result<(), Self> {
+        if old == new.hash() {
+            Ok(())
+        } else {
+            Err(Self {
+                height: new.height(),
+                old_hash: old,
+                new_hash: new.hash(),
+            })
+        }
+    }
+}
+
+/// An attempt to insert a block whose payload exceeds the configured maximum size.
+///
+/// This is returned, wrapped in [`anyhow::Error`], when appending a block whose payload is larger
+/// than the limit set with
+/// [`with_max_payload_bytes`](crate::data_source::fetching::Builder::with_max_payload_bytes). The
+/// block is rejected before anything is written to storage.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("payload at height {height} is {size} bytes, exceeding the limit of {limit}"))]
+pub struct PayloadTooLarge {
+    pub height: u64,
+    pub size: u64,
+    pub limit: u64,
+}
+
+/// An attempt to insert an object which fails verification.
+///
+/// This is returned, wrapped in [`anyhow::Error`], when appending a block to a data source built
+/// with [`with_verify_inserts`](crate::data_source::fetching::Builder::with_verify_inserts), if the
+/// leaf is not certified by its own QC, or the payload does not match the commitment in the header.
+/// The block is rejected before anything is written to storage.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("invalid data at height {height}: {reason}"))]
+pub struct InvalidData {
+    pub height: u64,
+    pub reason: String,
+}
+
 pub trait UpdateAvailabilityStorage<Types>
 where
     Types: NodeType,
 {
+    /// Insert a leaf.
+    ///
+    /// Re-inserting the same leaf (for example, with a different QC) succeeds. If a different leaf
+    /// is already stored at the same height, this fails with [`Reorg`] and leaves the stored data
+    /// unchanged.
     fn insert_leaf(
         &mut self,
         leaf: LeafQueryData<Types>,
     ) -> impl Send + Future<Output = anyhow::Result<()>>;
+
+    /// Insert a batch of leaves.
+    ///
+    /// This is equivalent to calling [`insert_leaf`](Self::insert_leaf) for each leaf in order,
+    /// but storage implementations may override it to insert the whole batch more efficiently. As
+    /// with all mutations, the batch only takes effect when the transaction is committed, so if any
+    /// insertion fails, the caller can revert the transaction and none of the batch will be
+    /// stored.
+    fn insert_leaves<I>(&mut self, leaves: I) -> impl Send + Future<Output = anyhow::Result<()>>
+    where
+        Self: Send,
+        I: IntoIterator<Item = LeafQueryData<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        async move {
+            for leaf in leaves {
+                self.insert_leaf(leaf).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Insert a block.
+    ///
+    /// This is idempotent: if the same block is already stored, this is a no-op which succeeds. If
+    /// the block conflicts with data already stored at the same height (the header or the payload
+    /// differs), this fails with [`InconsistentData`] and leaves the stored data unchanged.
     fn insert_block(
         &mut self,
         block: BlockQueryData<Types>,
@@ -197,6 +377,72 @@ where
         common: VidCommonQueryData<Types>,
         share: Option<VidShare>,
     ) -> impl Send + Future<Output = anyhow::Result<()>>;
+
+    /// Insert the VID share belonging to the storage node with the given `index`.
+    ///
+    /// The VID common data for the block at `height` should already have been inserted with
+    /// [`insert_vid`](Self::insert_vid). Shares inserted this way can be retrieved with
+    /// [`get_vid_share`](AvailabilityStorage::get_vid_share). The default implementation fails,
+    /// for storage which does not index shares by node.
+    fn insert_vid_share(
+        &mut self,
+        height: u64,
+        index: usize,
+        share: VidShare,
+    ) -> impl Send + Future<Output = anyhow::Result<()>> {
+        let _ = share;
+        async move { anyhow::bail!("storage does not support VID share {index} at height {height}") }
+    }
+
+    /// Delete all data at or above `height`.
+    ///
+    /// This discards a divergent suffix of the chain, for example after an insertion fails with
+    /// [`Reorg`], so that the correct chain can be inserted in its place. Afterwards, the block
+    /// height of the storage is at most `height`. State derived from the purged data outside of
+    /// storage, such as the caches and background tasks of a running data source, is not reset,
+    /// so the data source should be restarted after committing a purge. The default
+    /// implementation fails, for storage which does not support deleting data.
+    fn purge_from(&mut self, height: u64) -> impl Send + Future<Output = anyhow::Result<()>> {
+        async move { anyhow::bail!("storage does not support purging from height {height}") }
+    }
+
+    /// Record that the payload of the block at `height` was rejected by a payload validator.
+    ///
+    /// The leaf at `height` should already have been inserted. From then on, the payload is treated
+    /// as if it had been deleted with [`DeleteBlockStorage::delete_block`]: queries for it fail with
+    /// [`QueryError::Deleted`], and it is not inserted again, even after a restart. The default
+    /// implementation does nothing, for storage which cannot record deletions; the rejection then
+    /// only lasts as long as the running data source.
+    fn reject_payload(&mut self, height: u64) -> impl Send + Future<Output = anyhow::Result<()>> {
+        let _ = height;
+        async move { Ok(()) }
+    }
+}
+
+/// Removal of individual blocks on demand, independent of the pruner.
+///
+/// This is meant for administrative use, such as complying with a request to remove a particular
+/// payload. Unlike the pruner, which discards data by age, this deletes exactly the requested
+/// blocks, wherever they are in the chain.
+pub trait DeleteBlockStorage<Types>
+where
+    Types: NodeType,
+{
+    /// Delete the payload and VID data of a block, keeping its leaf and header.
+    ///
+    /// The leaf and header are retained so that the chain remains intact and verifiable. The
+    /// deletion is recorded, so that from then on the block is treated as pruned: queries for its
+    /// payload or VID data fail with [`QueryError::Deleted`], and the data is not inserted again,
+    /// even after a restart. Like any mutation, this only takes effect when the transaction is
+    /// committed.
+    ///
+    /// On success, this returns the height of the deleted block. Deleting a block which has
+    /// already been deleted succeeds. This fails with [`QueryError::NotFound`] if there is no
+    /// header for the requested block.
+    fn delete_block(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> impl Send + Future<Output = anyhow::Result<u64>>;
 }
 
 #[async_trait]
@@ -222,6 +468,12 @@ pub trait NodeStorage<Types: NodeType> {
 
     /// Search the database for missing objects and generate a report.
     async fn sync_status(&mut self) -> QueryResult<SyncStatus>;
+
+    /// Find the ranges of heights missing from storage for each type of object.
+    ///
+    /// Only heights below the block height which have not been pruned are considered. At most
+    /// `max_gaps` ranges are reported for each type of object.
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps>;
 }
 
 #[derive(Clone, Debug, Default)]
@@ -309,13 +561,29 @@ where
         &mut self,
     ) -> Result<ExplorerSummary<Types>, GetExplorerSummaryError>;
 
+    /// `get_block_statistics` is a method that retrieves rolling statistics
+    /// over the latest blocks in the blockchain.  The size of the window is
+    /// given by the [GetBlockStatisticsRequest].
+    async fn get_block_statistics(
+        &mut self,
+        request: GetBlockStatisticsRequest,
+    ) -> Result<BlockStatistics, GetBlockStatisticsError>;
+
     /// `get_search_results` is a method that retrieves the results of a search
     /// query against the blockchain.  The results are generated from the given
-    /// query string.
+    /// [SearchQuery].
     async fn get_search_results(
         &mut self,
-        query: TaggedBase64,
+        query: SearchQuery,
     ) -> Result<SearchResult<Types>, GetSearchResultsError>;
+
+    /// `get_search_match` is a method that resolves a search query to the
+    /// first entity it identifies.  Unlike `get_search_results`, it stops
+    /// looking as soon as one kind of entity matches the [SearchQuery].
+    async fn get_search_match(
+        &mut self,
+        query: SearchQuery,
+    ) -> Result<SearchMatch<Types>, GetSearchResultsError>;
 }
 
 /// This trait defines methods that a data source should implement
@@ -331,6 +599,28 @@ where
         snapshot: Snapshot<Types, State, ARITY>,
         key: State::Key,
     ) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>>;
+
+    /// Get Merkle paths for several keys, all anchored to the same snapshot.
+    async fn get_path_batch(
+        &mut self,
+        snapshot: Snapshot<Types, State, ARITY>,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>>;
+
+    /// Resolve a block height to the snapshot of the state as of that block.
+    async fn snapshot_for_height(
+        &mut self,
+        height: u64,
+    ) -> QueryResult<Snapshot<Types, State, ARITY>>;
+
+    /// Get a page of the entries which changed between two snapshots.
+    async fn get_diff(
+        &mut self,
+        from: Snapshot<Types, State, ARITY>,
+        to: Snapshot<Types, State, ARITY>,
+        offset: usize,
+        limit: usize,
+    ) -> QueryResult<StateDiff<State::Key, State::Entry>>;
 }
 
 #[async_trait]
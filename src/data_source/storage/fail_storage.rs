@@ -19,15 +19,17 @@ use super::{
 };
 use crate::{
     availability::{
-        BlockId, BlockQueryData, LeafId, LeafQueryData, PayloadQueryData, QueryablePayload,
-        TransactionHash, TransactionQueryData, VidCommonQueryData,
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafId, LeafQueryData, PayloadByteRange,
+        PayloadQueryData, QueryablePayload, RawPayloadQueryData, TransactionHash,
+        TransactionQueryData, VidCommonQueryData,
     },
     data_source::{
         storage::{PayloadMetadata, VidCommonMetadata},
-        update, VersionedDataSource,
+        update::{self, CommitMarker},
+        VersionedDataSource,
     },
     metrics::PrometheusMetrics,
-    node::{SyncStatus, TimeWindowQueryData, WindowStart},
+    node::{SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
     status::HasMetrics,
     Header, Payload, QueryError, QueryResult, VidShare,
 };
@@ -35,7 +37,7 @@ use async_lock::Mutex;
 use async_trait::async_trait;
 use futures::future::Future;
 use hotshot_types::traits::node_implementation::NodeType;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 use std::sync::Arc;
 
 /// A specific action that can be targetted to inject an error.
@@ -48,13 +50,20 @@ pub enum FailableAction {
     GetBlock,
     GetPayload,
     GetPayloadMetadata,
+    GetRawPayload,
+    GetPayloadBytes,
+    GetBlockSummary,
     GetVidCommon,
     GetVidCommonMetadata,
+    GetVidShare,
+    GetLeafRangeByTime,
+    GetLeafByView,
     GetHeaderRange,
     GetLeafRange,
     GetBlockRange,
     GetPayloadRange,
     GetPayloadMetadataRange,
+    GetBlockSummaryRange,
     GetVidCommonRange,
     GetVidCommonMetadataRange,
     GetTransaction,
@@ -237,6 +246,21 @@ where
             failer: self.failer.clone(),
         })
     }
+
+    async fn read_at_least(
+        &self,
+        marker: CommitMarker,
+    ) -> anyhow::Result<<Self as VersionedDataSource>::ReadOnly<'_>> {
+        self.failer
+            .lock()
+            .await
+            .on_begin_read_only
+            .maybe_fail(FailableAction::Any)?;
+        Ok(Transaction {
+            inner: self.inner.read_at_least(marker).await?,
+            failer: self.failer.clone(),
+        })
+    }
 }
 
 impl<S> PrunerConfig for FailStorage<S>
@@ -301,9 +325,9 @@ impl<T> update::Transaction for Transaction<T>
 where
     T: update::Transaction,
 {
-    async fn commit(self) -> anyhow::Result<()> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
         self.maybe_fail_commit(FailableAction::Any).await?;
-        self.inner.commit().await
+        self.inner.commit_with_version().await
     }
 
     fn revert(self) -> impl Future + Send {
@@ -347,6 +371,33 @@ where
         self.inner.get_payload_metadata(id).await
     }
 
+    async fn get_raw_payload(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<RawPayloadQueryData<Types>> {
+        self.maybe_fail_read(FailableAction::GetRawPayload).await?;
+        self.inner.get_raw_payload(id).await
+    }
+
+    async fn get_payload_bytes(
+        &mut self,
+        id: BlockId<Types>,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>> {
+        self.maybe_fail_read(FailableAction::GetPayloadBytes)
+            .await?;
+        self.inner.get_payload_bytes(id, range).await
+    }
+
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        self.maybe_fail_read(FailableAction::GetBlockSummary)
+            .await?;
+        self.inner.get_block_summary(id).await
+    }
+
     async fn get_vid_common(
         &mut self,
         id: BlockId<Types>,
@@ -364,6 +415,26 @@ where
         self.inner.get_vid_common_metadata(id).await
     }
 
+    async fn get_vid_share(&mut self, id: BlockId<Types>, index: usize) -> QueryResult<VidShare> {
+        self.maybe_fail_read(FailableAction::GetVidShare).await?;
+        self.inner.get_vid_share(id, index).await
+    }
+
+    async fn get_leaf_range_by_time(
+        &mut self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        self.maybe_fail_read(FailableAction::GetLeafRangeByTime)
+            .await?;
+        self.inner.get_leaf_range_by_time(start, end).await
+    }
+
+    async fn get_leaf_by_view(&mut self, view: u64) -> QueryResult<LeafQueryData<Types>> {
+        self.maybe_fail_read(FailableAction::GetLeafByView).await?;
+        self.inner.get_leaf_by_view(view).await
+    }
+
     async fn get_leaf_range<R>(
         &mut self,
         range: R,
@@ -410,6 +481,18 @@ where
         self.inner.get_payload_metadata_range(range).await
     }
 
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        self.maybe_fail_read(FailableAction::GetBlockSummaryRange)
+            .await?;
+        self.inner.get_block_summary_range(range).await
+    }
+
     async fn get_vid_common_range<R>(
         &mut self,
         range: R,
@@ -467,6 +550,26 @@ where
         self.maybe_fail_write(FailableAction::Any).await?;
         self.inner.insert_vid(common, share).await
     }
+
+    async fn insert_vid_share(
+        &mut self,
+        height: u64,
+        index: usize,
+        share: VidShare,
+    ) -> anyhow::Result<()> {
+        self.maybe_fail_write(FailableAction::Any).await?;
+        self.inner.insert_vid_share(height, index, share).await
+    }
+
+    async fn purge_from(&mut self, height: u64) -> anyhow::Result<()> {
+        self.maybe_fail_write(FailableAction::Any).await?;
+        self.inner.purge_from(height).await
+    }
+
+    async fn reject_payload(&mut self, height: u64) -> anyhow::Result<()> {
+        self.maybe_fail_write(FailableAction::Any).await?;
+        self.inner.reject_payload(height).await
+    }
 }
 
 #[async_trait]
@@ -478,6 +581,21 @@ where
         self.maybe_fail_read(FailableAction::Any).await?;
         self.inner.load_pruned_height().await
     }
+
+    async fn load_payload_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        self.maybe_fail_read(FailableAction::Any).await?;
+        self.inner.load_payload_pruned_height().await
+    }
+
+    async fn load_vid_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        self.maybe_fail_read(FailableAction::Any).await?;
+        self.inner.load_vid_pruned_height().await
+    }
+
+    async fn load_deleted_heights(&mut self) -> anyhow::Result<Vec<u64>> {
+        self.maybe_fail_read(FailableAction::Any).await?;
+        self.inner.load_deleted_heights().await
+    }
 }
 
 #[async_trait]
@@ -520,6 +638,11 @@ where
         self.inner.sync_status().await
     }
 
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+        self.maybe_fail_read(FailableAction::Any).await?;
+        self.inner.sync_gaps(max_gaps).await
+    }
+
     async fn get_header_window(
         &mut self,
         start: impl Into<WindowStart<Types>> + Send + Sync,
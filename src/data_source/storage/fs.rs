@@ -15,32 +15,40 @@
 use super::{
     ledger_log::{Iter, LedgerLog},
     pruning::{PruneStorage, PrunedHeightStorage, PrunerConfig},
-    Aggregate, AggregatesStorage, AvailabilityStorage, NodeStorage, PayloadMetadata,
-    UpdateAggregatesStorage, UpdateAvailabilityStorage, VidCommonMetadata,
+    Aggregate, AggregatesStorage, AvailabilityStorage, InconsistentData, NodeStorage,
+    PayloadMetadata, Reorg, UpdateAggregatesStorage, UpdateAvailabilityStorage, VidCommonMetadata,
 };
 
 use crate::{
     availability::{
         data_source::{BlockId, LeafId},
         query_data::{
-            BlockHash, BlockQueryData, LeafHash, LeafQueryData, PayloadQueryData, QueryableHeader,
-            QueryablePayload, TransactionHash, TransactionQueryData, VidCommonQueryData,
+            BlockHash, BlockQueryData, BlockSummaryQueryData, LeafHash, LeafQueryData,
+            PayloadQueryData, QueryableHeader, QueryablePayload, TransactionHash,
+            TransactionQueryData, VidCommonQueryData,
         },
     },
-    data_source::{update, VersionedDataSource},
+    data_source::{
+        update::{self, CommitMarker, CommitTracker},
+        VersionedDataSource,
+    },
     metrics::PrometheusMetrics,
-    node::{SyncStatus, TimeWindowQueryData, WindowStart},
+    node::{Gaps, SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
     status::HasMetrics,
     types::HeightIndexed,
     ErrorSnafu, Header, MissingSnafu, NotFoundSnafu, Payload, QueryError, QueryResult,
     VidCommitment, VidShare,
 };
-use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use anyhow::bail;
+use async_lock::{RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard};
 use async_trait::async_trait;
 use atomic_store::{AtomicStore, AtomicStoreLoader, PersistenceError};
 use committable::Committable;
 use futures::future::Future;
-use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
+use hotshot_types::traits::{
+    block_contents::BlockHeader,
+    node_implementation::{ConsensusTime, NodeType},
+};
 use serde::{de::DeserializeOwned, Serialize};
 use snafu::OptionExt;
 use std::collections::{
@@ -49,12 +57,90 @@ use std::collections::{
 };
 use std::hash::Hash;
 use std::ops::{Bound, Deref, RangeBounds};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+const STORE_NAME: &str = "hotshot_data_source";
 
 const CACHED_LEAVES_COUNT: usize = 100;
 const CACHED_BLOCKS_COUNT: usize = 100;
 const CACHED_VID_COMMON_COUNT: usize = 100;
 
+/// How eagerly [`FileSystemStorage`] forces committed data to disk.
+///
+/// A commit always writes its changes to the files of the store before returning, but unless those
+/// files are synced, the operating system may hold the writes in memory for a while. If the process
+/// exits, nothing is lost, but if the machine crashes or loses power, commits which were not synced
+/// may be lost. A node which loses recent commits this way simply fetches the missing data from its
+/// peers again, so it can trade some durability for throughput.
+///
+/// This only applies to storage which manages its own persistence, that is, storage created with
+/// [`create`](FileSystemStorage::create) or [`open`](FileSystemStorage::open). Otherwise, the
+/// caller manages the [`AtomicStore`] and is responsible for syncing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Sync the store after every commit.
+    ///
+    /// Once a commit returns, it survives a crash. This is the slowest mode.
+    Strict,
+    /// Sync the store once `commits` commits have accumulated, or on the first commit at least
+    /// `interval` after the last sync, whichever comes first.
+    ///
+    /// A crash can lose up to `commits - 1` commits, or the commits made in the last `interval`. The
+    /// check happens as part of committing, so commits made just before the node goes idle stay
+    /// unsynced until the next commit, or until the operating system writes them back on its own.
+    Batched { commits: usize, interval: Duration },
+    /// Never sync the store explicitly, leaving it to the operating system to write data back.
+    ///
+    /// A crash can lose any commits which the operating system has not yet written back, typically
+    /// the last several seconds' worth. This is the default.
+    #[default]
+    None,
+}
+
+/// Tracks commits which have not yet been synced to disk.
+#[derive(Debug)]
+struct DiskSync {
+    /// The directory containing the store, if this storage manages its own persistence.
+    path: Option<PathBuf>,
+    durability: Durability,
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+impl DiskSync {
+    fn new(durability: Durability) -> Self {
+        Self {
+            path: None,
+            durability,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    /// Record a commit, and sync the store if the durability mode calls for it.
+    fn commit(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.unsynced += 1;
+        let due = match self.durability {
+            Durability::Strict => true,
+            Durability::Batched { commits, interval } => {
+                self.unsynced >= commits || self.last_sync.elapsed() >= interval
+            }
+            Durability::None => false,
+        };
+        if due {
+            sync_dir(path)?;
+            self.unsynced = 0;
+            self.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+}
+
 #[derive(custom_debug::Debug)]
 pub struct FileSystemStorageInner<Types>
 where
@@ -66,6 +152,10 @@ where
     index_by_payload_hash: HashMap<VidCommitment, u64>,
     index_by_txn_hash: HashMap<TransactionHash<Types>, u64>,
     index_by_time: BTreeMap<u64, Vec<u64>>,
+    index_by_view: HashMap<u64, u64>,
+    /// Payload size and transaction count of each block, so that block summaries can be served
+    /// without reading the payload from disk.
+    summary_by_height: HashMap<u64, (u64, u64)>,
     num_transactions: usize,
     payload_size: usize,
     #[debug(skip)]
@@ -73,6 +163,7 @@ where
     leaf_storage: LedgerLog<LeafQueryData<Types>>,
     block_storage: LedgerLog<BlockQueryData<Types>>,
     vid_storage: LedgerLog<(VidCommonQueryData<Types>, Option<VidShare>)>,
+    disk_sync: DiskSync,
 }
 
 impl<Types> FileSystemStorageInner<Types>
@@ -104,6 +195,32 @@ where
         self.get_block(id).map(|block| block.header)
     }
 
+    fn block_summary(
+        &self,
+        leaf: LeafQueryData<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        let (size, num_transactions) = *self
+            .summary_by_height
+            .get(&leaf.height())
+            .context(MissingSnafu)?;
+        Ok(BlockSummaryQueryData {
+            hash: leaf.block_hash(),
+            header: leaf.header().clone(),
+            size,
+            num_transactions,
+        })
+    }
+
+    fn get_block_summary(&self, id: BlockId<Types>) -> QueryResult<BlockSummaryQueryData<Types>> {
+        let leaf = self
+            .leaf_storage
+            .iter()
+            .nth(self.get_block_index(id)?)
+            .context(NotFoundSnafu)?
+            .context(MissingSnafu)?;
+        self.block_summary(leaf)
+    }
+
     fn get_block_range<R>(&self, range: R) -> QueryResult<Vec<QueryResult<BlockQueryData<Types>>>>
     where
         R: RangeBounds<usize> + Send,
@@ -120,6 +237,25 @@ where
 {
     inner: RwLock<FileSystemStorageInner<Types>>,
     metrics: PrometheusMetrics,
+    commits: CommitTracker,
+    /// The directory containing the store, if this storage manages its own persistence.
+    path: Option<PathBuf>,
+}
+
+/// The result of [compacting](FileSystemStorage::compact) a [FileSystemStorage].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompactionSummary {
+    /// The size of the store, in bytes, before compaction.
+    pub bytes_before: u64,
+    /// The size of the store, in bytes, after compaction.
+    pub bytes_after: u64,
+}
+
+impl CompactionSummary {
+    /// The number of bytes freed by compaction.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
 }
 
 impl<Types: NodeType> PrunerConfig for FileSystemStorage<Types> where
@@ -144,23 +280,36 @@ where
     ///
     /// The [FileSystemStorage] will manage its own persistence synchronization.
     pub async fn create(path: &Path) -> Result<Self, PersistenceError> {
-        let mut loader = AtomicStoreLoader::create(path, "hotshot_data_source")?;
+        let mut loader = AtomicStoreLoader::create(path, STORE_NAME)?;
         loader.retain_archives(1);
-        let data_source = Self::create_with_store(&mut loader).await?;
-        data_source.inner.write().await.top_storage = Some(AtomicStore::open(loader)?);
+        let mut data_source = Self::create_with_store(&mut loader).await?;
+        {
+            let inner = data_source.inner.get_mut();
+            inner.top_storage = Some(AtomicStore::open(loader)?);
+            inner.disk_sync.path = Some(path.to_owned());
+        }
+        data_source.path = Some(path.to_owned());
         Ok(data_source)
     }
 
     /// Open an existing [FileSystemStorage] from storage at `path`.
     ///
-    /// If there is no data at `path`, a new store will be created.
+    /// If there is no data at `path`, a new store will be created. If a previous
+    /// [compaction](Self::compact) of this store was interrupted, it is either rolled back or
+    /// completed before the store is opened.
     ///
     /// The [FileSystemStorage] will manage its own persistence synchronization.
-    pub async fn open(path: &Path) -> Result<Self, PersistenceError> {
-        let mut loader = AtomicStoreLoader::load(path, "hotshot_data_source")?;
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        recover_compaction(path)?;
+        let mut loader = AtomicStoreLoader::load(path, STORE_NAME)?;
         loader.retain_archives(1);
-        let data_source = Self::open_with_store(&mut loader).await?;
-        data_source.inner.write().await.top_storage = Some(AtomicStore::open(loader)?);
+        let mut data_source = Self::open_with_store(&mut loader).await?;
+        {
+            let inner = data_source.inner.get_mut();
+            inner.top_storage = Some(AtomicStore::open(loader)?);
+            inner.disk_sync.path = Some(path.to_owned());
+        }
+        data_source.path = Some(path.to_owned());
         Ok(data_source)
     }
 
@@ -182,14 +331,19 @@ where
                 index_by_payload_hash: Default::default(),
                 index_by_txn_hash: Default::default(),
                 index_by_time: Default::default(),
+                index_by_view: Default::default(),
+                summary_by_height: Default::default(),
                 num_transactions: 0,
                 payload_size: 0,
                 top_storage: None,
                 leaf_storage: LedgerLog::create(loader, "leaves", CACHED_LEAVES_COUNT)?,
                 block_storage: LedgerLog::create(loader, "blocks", CACHED_BLOCKS_COUNT)?,
                 vid_storage: LedgerLog::create(loader, "vid_common", CACHED_VID_COMMON_COUNT)?,
+                disk_sync: DiskSync::new(Durability::default()),
             }),
             metrics: Default::default(),
+            commits: Default::default(),
+            path: None,
         })
     }
 
@@ -215,6 +369,7 @@ where
         let mut index_by_block_hash = HashMap::new();
         let mut index_by_payload_hash = HashMap::new();
         let mut index_by_time = BTreeMap::<u64, Vec<u64>>::new();
+        let mut index_by_view = HashMap::new();
         let index_by_leaf_hash = leaf_storage
             .iter()
             .flatten()
@@ -229,11 +384,13 @@ where
                     .entry(leaf.header().timestamp())
                     .or_default()
                     .push(leaf.height());
+                index_by_view.insert(leaf.leaf().view_number().u64(), leaf.height());
                 (leaf.hash(), leaf.height())
             })
             .collect();
 
         let mut index_by_txn_hash = HashMap::new();
+        let mut summary_by_height = HashMap::new();
         let mut num_transactions = 0;
         let mut payload_size = 0;
         for block in block_storage.iter().flatten() {
@@ -241,6 +398,7 @@ where
             payload_size += block.size() as usize;
 
             let height = block.height();
+            summary_by_height.insert(height, (block.size(), block.num_transactions()));
             for (_, txn) in block.enumerate() {
                 update_index_by_hash(&mut index_by_txn_hash, txn.commit(), height);
             }
@@ -253,17 +411,98 @@ where
                 index_by_payload_hash,
                 index_by_txn_hash,
                 index_by_time,
+                index_by_view,
+                summary_by_height,
                 num_transactions,
                 payload_size,
                 leaf_storage,
                 block_storage,
                 vid_storage,
                 top_storage: None,
+                disk_sync: DiskSync::new(Durability::default()),
             }),
             metrics: Default::default(),
+            commits: Default::default(),
+            path: None,
         })
     }
 
+    /// Rewrite the store on disk into a small number of large files.
+    ///
+    /// Over time, the store accumulates many small files. Compaction copies all stored objects, at
+    /// the same positions, into a fresh store written into large files, and then swaps it in
+    /// place of the old one. Readers are not blocked while the new store is written, only writers
+    /// are. The new store is built in a staging directory next to the store and swapped in by
+    /// renaming directories, so if the process dies during compaction, [open](Self::open) either
+    /// keeps the old store or completes the swap.
+    ///
+    /// This is only supported for storage which manages its own persistence synchronization, that
+    /// is, storage created with [create](Self::create) or [open](Self::open).
+    pub async fn compact(&self) -> anyhow::Result<CompactionSummary> {
+        let Some(path) = &self.path else {
+            bail!("compaction is not supported for storage with an externally managed store");
+        };
+        let staging = sibling_path(path, "compacting");
+        let old = sibling_path(path, "old");
+
+        // Concurrent readers may continue while we copy the store, but writers must wait, so that
+        // the copy is consistent with the store it replaces.
+        let inner = self.inner.upgradable_read().await;
+        let bytes_before = dir_size(path)?;
+
+        // Discard anything left behind by a previous, interrupted compaction.
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        {
+            let mut loader = AtomicStoreLoader::create(&staging, STORE_NAME)?;
+            loader.retain_archives(1);
+            let mut leaf_storage = inner.leaf_storage.compact_into(&mut loader, "leaves")?;
+            let mut block_storage = inner.block_storage.compact_into(&mut loader, "blocks")?;
+            let mut vid_storage = inner.vid_storage.compact_into(&mut loader, "vid_common")?;
+            let mut store = AtomicStore::open(loader)?;
+            leaf_storage.commit_version().await?;
+            block_storage.commit_version().await?;
+            vid_storage.commit_version().await?;
+            store.commit_version()?;
+        }
+
+        // Swap the compacted store into place. Readers must not access the store while we do
+        // this, so we need exclusive access from here on.
+        let mut inner = RwLockUpgradableReadGuard::upgrade(inner).await;
+        fs::rename(path, &old)?;
+        fs::rename(&staging, path)?;
+        let mut loader = AtomicStoreLoader::load(path, STORE_NAME)?;
+        loader.retain_archives(1);
+        inner.leaf_storage = LedgerLog::open(&mut loader, "leaves", CACHED_LEAVES_COUNT)?;
+        inner.block_storage = LedgerLog::open(&mut loader, "blocks", CACHED_BLOCKS_COUNT)?;
+        inner.vid_storage = LedgerLog::open(&mut loader, "vid_common", CACHED_VID_COMMON_COUNT)?;
+        inner.top_storage = Some(AtomicStore::open(loader)?);
+        drop(inner);
+        fs::remove_dir_all(&old)?;
+
+        let summary = CompactionSummary {
+            bytes_before,
+            bytes_after: dir_size(path)?,
+        };
+        tracing::info!(
+            bytes_reclaimed = summary.bytes_reclaimed(),
+            ?summary,
+            "compacted file system storage"
+        );
+        Ok(summary)
+    }
+
+    /// Set how eagerly committed data is synced to disk.
+    ///
+    /// See [`Durability`] for the data which can be lost in a crash under each mode. The default is
+    /// [`Durability::None`].
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.inner.get_mut().disk_sync.durability = durability;
+        self
+    }
+
     /// Advance the version of the persistent store without committing changes to persistent state.
     pub async fn skip_version(&self) -> Result<(), PersistenceError> {
         let mut inner = self.inner.write().await;
@@ -277,6 +516,68 @@ where
     }
 }
 
+/// Roll back or complete an interrupted compaction of the store at `path`.
+fn recover_compaction(path: &Path) -> io::Result<()> {
+    let staging = sibling_path(path, "compacting");
+    let old = sibling_path(path, "old");
+    if old.exists() {
+        if !path.exists() {
+            if staging.exists() {
+                // We were interrupted between moving the old store out of the way and moving the
+                // new one into place. The staging directory is only moved once it holds a complete
+                // store, so finish the swap.
+                fs::rename(&staging, path)?;
+            } else {
+                fs::rename(&old, path)?;
+                return Ok(());
+            }
+        }
+        fs::remove_dir_all(&old)?;
+    } else if staging.exists() {
+        // We were interrupted before the swap, so the store at `path` is intact and the
+        // partially written staging directory can be discarded.
+        fs::remove_dir_all(&staging)?;
+    }
+    Ok(())
+}
+
+/// A path next to `path`, with the same file name plus `suffix`.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Flush the files in `path` to disk, recursively.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            sync_dir(&entry.path())?;
+        } else {
+            fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+    // Sync the directory itself, so that newly created files are durable too.
+    fs::File::open(path)?.sync_all()
+}
+
+/// The total size of the files in `path`, recursively.
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
 pub trait Revert {
     fn revert(&mut self);
 }
@@ -306,6 +607,7 @@ where
 #[derive(Debug)]
 pub struct Transaction<T: Revert> {
     inner: T,
+    commits: CommitTracker,
 }
 
 impl<T: Revert> Drop for Transaction<T> {
@@ -320,14 +622,17 @@ where
     Types: NodeType,
     Payload<Types>: QueryablePayload<Types>,
 {
-    async fn commit(mut self) -> anyhow::Result<()> {
+    async fn commit_with_version(mut self) -> anyhow::Result<CommitMarker> {
         self.inner.leaf_storage.commit_version().await?;
         self.inner.block_storage.commit_version().await?;
         self.inner.vid_storage.commit_version().await?;
         if let Some(store) = &mut self.inner.top_storage {
             store.commit_version()?;
         }
-        Ok(())
+        self.inner.disk_sync.commit()?;
+        // Readers cannot observe the new version until we release the lock, but they also cannot
+        // acquire the lock before then, so it is safe to record the commit now.
+        Ok(self.commits.commit())
     }
 
     fn revert(self) -> impl Future + Send {
@@ -342,9 +647,9 @@ where
     Types: NodeType,
     Payload<Types>: QueryablePayload<Types>,
 {
-    async fn commit(self) -> anyhow::Result<()> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
         // Nothing to commit for a read-only transaction.
-        Ok(())
+        Ok(self.commits.current())
     }
 
     fn revert(self) -> impl Future + Send {
@@ -369,14 +674,21 @@ where
     async fn write(&self) -> anyhow::Result<Self::Transaction<'_>> {
         Ok(Transaction {
             inner: self.inner.write().await,
+            commits: self.commits.clone(),
         })
     }
 
     async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
         Ok(Transaction {
             inner: self.inner.read().await,
+            commits: self.commits.clone(),
         })
     }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.commits.wait(marker).await;
+        self.read().await
+    }
 }
 
 fn range_iter<T>(
@@ -464,10 +776,20 @@ where
         self.get_block(id).await.map(PayloadMetadata::from)
     }
 
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        self.inner.get_block_summary(id)
+    }
+
     async fn get_vid_common(
         &mut self,
         id: BlockId<Types>,
     ) -> QueryResult<VidCommonQueryData<Types>> {
+        // Unlike the SQL and RocksDB backends, this backend stores the common data and this node's
+        // share together in a single log entry, so the share is read and decoded along with the
+        // common data. Separating them would change the on-disk format.
         Ok(self
             .inner
             .vid_storage
@@ -485,6 +807,39 @@ where
         self.get_vid_common(id).await.map(VidCommonMetadata::from)
     }
 
+    async fn get_leaf_by_view(&mut self, view: u64) -> QueryResult<LeafQueryData<Types>> {
+        let n = *self.inner.index_by_view.get(&view).context(NotFoundSnafu)?;
+        self.get_leaf(LeafId::Number(n as usize)).await
+    }
+
+    async fn get_leaf_range_by_time(
+        &mut self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        if start >= end {
+            return Ok(vec![]);
+        }
+
+        // Timestamps are not strictly monotonic, so the matching heights need not be contiguous.
+        // Collect them from the time index and sort them into chain order.
+        let mut heights = self
+            .inner
+            .index_by_time
+            .range(start..end)
+            .flat_map(|(_, heights)| heights.iter().copied())
+            .collect::<Vec<_>>();
+        heights.sort_unstable();
+        heights.dedup();
+        Ok(heights
+            .into_iter()
+            .filter_map(|height| {
+                let leaf = self.inner.leaf_storage.iter().nth(height as usize)??;
+                Some((height, leaf))
+            })
+            .collect())
+    }
+
     async fn get_leaf_range<R>(
         &mut self,
         range: R,
@@ -529,6 +884,18 @@ where
             .collect())
     }
 
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        Ok(range_iter(self.inner.leaf_storage.iter(), range)
+            .map(|res| self.inner.block_summary(res?))
+            .collect())
+    }
+
     async fn get_vid_common_range<R>(
         &mut self,
         range: R,
@@ -578,6 +945,9 @@ where
     Header<Types>: QueryableHeader<Types>,
 {
     async fn insert_leaf(&mut self, leaf: LeafQueryData<Types>) -> anyhow::Result<()> {
+        if let Some(Some(existing)) = self.inner.leaf_storage.iter().nth(leaf.height() as usize) {
+            Reorg::check(existing.hash(), &leaf)?;
+        }
         self.inner
             .leaf_storage
             .insert(leaf.height() as usize, leaf.clone())?;
@@ -599,20 +969,47 @@ where
             .entry(leaf.header().timestamp())
             .or_default()
             .push(leaf.height());
+        self.inner
+            .index_by_view
+            .insert(leaf.leaf().view_number().u64(), leaf.height());
         Ok(())
     }
 
     async fn insert_block(&mut self, block: BlockQueryData<Types>) -> anyhow::Result<()> {
-        if !self
-            .inner
-            .block_storage
-            .insert(block.height() as usize, block.clone())?
-        {
-            // The block was already present.
+        // Check the block against whatever we already have at this height. Re-inserting the same
+        // block is a no-op, but a conflicting block must not overwrite what is stored.
+        let height = block.height() as usize;
+        if let Some(Some(leaf)) = self.inner.leaf_storage.iter().nth(height) {
+            if leaf.block_hash() != block.hash() {
+                return Err(InconsistentData {
+                    height: block.height(),
+                    reason: format!(
+                        "block {} does not match stored header {}",
+                        block.hash(),
+                        leaf.block_hash()
+                    ),
+                }
+                .into());
+            }
+        }
+        if let Some(Some(existing)) = self.inner.block_storage.iter().nth(height) {
+            if existing != block {
+                return Err(InconsistentData {
+                    height: block.height(),
+                    reason: "payload does not match stored payload".into(),
+                }
+                .into());
+            }
+            // The block is already stored.
             return Ok(());
         }
+
+        self.inner.block_storage.insert(height, block.clone())?;
         self.inner.num_transactions += block.len();
         self.inner.payload_size += block.size() as usize;
+        self.inner
+            .summary_by_height
+            .insert(block.height(), (block.size(), block.num_transactions()));
         for (_, txn) in block.enumerate() {
             update_index_by_hash(
                 &mut self.inner.index_by_txn_hash,
@@ -635,6 +1032,15 @@ where
     }
 }
 
+/// The positions of the objects in a ledger log which are present, in ascending order.
+fn present_heights<T>(iter: Iter<'_, T>) -> impl '_ + Iterator<Item = usize>
+where
+    T: Clone + Serialize + DeserializeOwned,
+{
+    iter.enumerate()
+        .filter_map(|(height, obj)| obj.is_some().then_some(height))
+}
+
 /// Update an index mapping hashes of objects to their positions in the ledger.
 ///
 /// This function will insert the mapping from `hash` to `pos` into `index`, _unless_ there is
@@ -729,6 +1135,33 @@ where
             missing_vid_common: missing_vid,
             missing_vid_shares: missing_vid + null_vid_shares,
             pruned_height: None,
+            lowest_stored_height: self
+                .inner
+                .leaf_storage
+                .iter()
+                .position(|leaf| leaf.is_some()),
+            ..Default::default()
+        })
+    }
+
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+        let height = self.inner.leaf_storage.iter().len();
+        Ok(SyncGaps {
+            leaves: Gaps::from_present(
+                present_heights(self.inner.leaf_storage.iter()),
+                0..height,
+                max_gaps,
+            ),
+            blocks: Gaps::from_present(
+                present_heights(self.inner.block_storage.iter()),
+                0..height,
+                max_gaps,
+            ),
+            vid: Gaps::from_present(
+                present_heights(self.inner.vid_storage.iter()),
+                0..height,
+                max_gaps,
+            ),
         })
     }
 
@@ -20,6 +20,9 @@ use std::collections::VecDeque;
 use std::fmt::Debug;
 use tracing::warn;
 
+/// The size of the files written by [`LedgerLog::compact_into`].
+const COMPACTED_FILE_SIZE: u64 = 256u64 << 20; // 256 MB
+
 /// A caching append log for ledger objects.
 #[derive(Debug)]
 pub(crate) struct LedgerLog<T: Serialize + DeserializeOwned> {
@@ -101,6 +104,35 @@ impl<T: Serialize + DeserializeOwned + Clone> LedgerLog<T> {
         })
     }
 
+    /// Copy the contents of this log into a new log registered with `loader`.
+    ///
+    /// The new log is written into large files, so that a log which has accumulated many small
+    /// files over time can be consolidated. Positions in the log, including those of missing
+    /// objects, are preserved. The caller is responsible for committing the new log.
+    pub(crate) fn compact_into(
+        &self,
+        loader: &mut AtomicStoreLoader,
+        file_pattern: &str,
+    ) -> Result<Self, PersistenceError> {
+        let mut log = Self {
+            cache_start: 0,
+            cache_size: self.cache_size,
+            cache: VecDeque::with_capacity(self.cache_size),
+            store: AppendLog::create(
+                loader,
+                Default::default(),
+                file_pattern,
+                COMPACTED_FILE_SIZE,
+            )?,
+            pending_inserts: 0,
+            missing: 0,
+        };
+        for obj in self.iter() {
+            log.store_resource(obj)?;
+        }
+        Ok(log)
+    }
+
     pub(crate) fn iter(&self) -> Iter<T> {
         Iter {
             index: 0,
@@ -0,0 +1,756 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Storage which keeps everything in memory.
+//!
+//! All objects are kept in a single ledger of in-memory maps, shared behind an [`Arc`]. A
+//! transaction takes a reference to the ledger as it was when the transaction was opened, and reads
+//! only from that snapshot. The first time a write [`Transaction`] modifies the ledger, it makes its
+//! own private copy, where its changes are visible to reads made through the same transaction but
+//! not to any other transaction. Committing replaces the shared ledger with this copy, atomically.
+//! Reverting (or dropping) the transaction simply discards the copy. Write transactions are
+//! serialized with a lock, but they do not block readers.
+//!
+//! Nothing is persisted: all data is lost when the storage is dropped. This makes the in-memory
+//! backend suitable only for tests and demos.
+
+#![cfg(feature = "memory-data-source")]
+
+use super::{
+    pruning::{PruneStorage, PrunedHeightStorage, PrunerConfig},
+    Aggregate, AggregatesStorage, AvailabilityStorage, InconsistentData, NodeStorage,
+    PayloadMetadata, Reorg, UpdateAggregatesStorage, UpdateAvailabilityStorage, VidCommonMetadata,
+};
+use crate::{
+    availability::{
+        BlockHash, BlockId, BlockQueryData, BlockSummaryQueryData, LeafHash, LeafId, LeafQueryData,
+        PayloadQueryData, QueryableHeader, QueryablePayload, TransactionHash, TransactionQueryData,
+        VidCommonQueryData,
+    },
+    data_source::{
+        update::{self, CommitMarker, CommitTracker},
+        VersionedDataSource,
+    },
+    metrics::PrometheusMetrics,
+    node::{Gaps, SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
+    status::HasMetrics,
+    types::HeightIndexed,
+    ErrorSnafu, Header, MissingSnafu, NotFoundSnafu, Payload, QueryError, QueryResult, VidShare,
+};
+use async_lock::{Mutex, MutexGuard};
+use async_trait::async_trait;
+use committable::Committable;
+use derivative::Derivative;
+use futures::future::Future;
+use hotshot_types::{
+    traits::{
+        block_contents::BlockHeader,
+        node_implementation::{ConsensusTime, NodeType},
+    },
+    vid::VidCommitment,
+};
+use snafu::OptionExt;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    sync::{Arc, RwLock},
+};
+
+/// The contents of a [`MemoryStorage`].
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""), Default(bound = ""))]
+struct Ledger<Types: NodeType> {
+    height: u64,
+    leaves: BTreeMap<u64, LeafQueryData<Types>>,
+    headers: BTreeMap<u64, Header<Types>>,
+    blocks: BTreeMap<u64, BlockQueryData<Types>>,
+    vid_common: BTreeMap<u64, VidCommonQueryData<Types>>,
+    own_vid_shares: BTreeMap<u64, VidShare>,
+    vid_shares: BTreeMap<(u64, usize), VidShare>,
+    leaf_hashes: HashMap<LeafHash<Types>, u64>,
+    block_hashes: HashMap<BlockHash<Types>, u64>,
+    payload_hashes: HashMap<VidCommitment, u64>,
+    transactions: HashMap<TransactionHash<Types>, u64>,
+    /// Pairs of (timestamp, height) for every header.
+    timestamps: BTreeSet<(u64, u64)>,
+    /// The height of the leaf proposed in each view.
+    views: HashMap<u64, u64>,
+    num_transactions: usize,
+    payload_size: usize,
+}
+
+impl<Types: NodeType> Ledger<Types> {
+    fn block_index(&self, id: BlockId<Types>) -> QueryResult<u64> {
+        match id {
+            BlockId::Number(n) => Ok(n as u64),
+            BlockId::Hash(h) => self.block_hashes.get(&h).copied().context(NotFoundSnafu),
+            BlockId::PayloadHash(h) => self.payload_hashes.get(&h).copied().context(NotFoundSnafu),
+        }
+    }
+
+    /// Get the object at `height` from a map indexed by height.
+    fn get_at<T: Clone>(&self, map: &BTreeMap<u64, T>, height: u64) -> QueryResult<T> {
+        if height >= self.height {
+            return Err(QueryError::NotFound);
+        }
+        map.get(&height).cloned().context(MissingSnafu)
+    }
+
+    /// Get all objects with heights in `range` from a map indexed by height.
+    fn get_range<T: Clone>(
+        &self,
+        map: &BTreeMap<u64, T>,
+        range: impl RangeBounds<usize>,
+    ) -> Vec<QueryResult<T>> {
+        let start = match range.start_bound() {
+            Bound::Included(n) => *n as u64,
+            Bound::Excluded(n) => *n as u64 + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(n) => (*n as u64 + 1).min(self.height),
+            Bound::Excluded(n) => (*n as u64).min(self.height),
+            Bound::Unbounded => self.height,
+        };
+        (start..end)
+            .map(|height| map.get(&height).cloned().context(MissingSnafu))
+            .collect()
+    }
+}
+
+/// Map `hash` to `height` in a hash index.
+///
+/// This will insert the mapping _unless_ there is already an entry for `hash` at an earlier
+/// position in the ledger.
+fn index_by_hash<H: Eq + std::hash::Hash>(index: &mut HashMap<H, u64>, hash: H, height: u64) {
+    let entry = index.entry(hash).or_insert(height);
+    *entry = (*entry).min(height);
+}
+
+/// Storage for the APIs provided in this crate, kept entirely in memory.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""), Default(bound = ""))]
+pub struct MemoryStorage<Types: NodeType> {
+    #[derivative(Debug = "ignore")]
+    ledger: Arc<RwLock<Arc<Ledger<Types>>>>,
+    write_lock: Arc<Mutex<()>>,
+    metrics: PrometheusMetrics,
+    commits: CommitTracker,
+}
+
+impl<Types: NodeType> MemoryStorage<Types> {
+    /// Create a new, empty [`MemoryStorage`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open another handle to the same underlying data, as if reconnecting to a database.
+    ///
+    /// The new handle has its own metrics, so that it can be used to build a new data source.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn reconnect(&self) -> Self {
+        Self {
+            ledger: self.ledger.clone(),
+            write_lock: self.write_lock.clone(),
+            metrics: Default::default(),
+            commits: self.commits.clone(),
+        }
+    }
+
+    /// Delete all data.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) async fn clear(&self) {
+        let _lock = self.write_lock.lock().await;
+        *self.ledger.write().unwrap() = Default::default();
+    }
+
+    fn snapshot(&self) -> Arc<Ledger<Types>> {
+        self.ledger.read().unwrap().clone()
+    }
+}
+
+impl<Types: NodeType> PrunerConfig for MemoryStorage<Types> {}
+impl<Types: NodeType> PruneStorage for MemoryStorage<Types> {
+    type Pruner = ();
+}
+
+impl<Types: NodeType> HasMetrics for MemoryStorage<Types> {
+    fn metrics(&self) -> &PrometheusMetrics {
+        &self.metrics
+    }
+}
+
+/// Marker type indicating a transaction with read-write access to the ledger.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Write;
+
+/// Marker type indicating a transaction with read-only access to the ledger.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Read;
+
+/// A transaction on a [`MemoryStorage`].
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct Transaction<'a, Types: NodeType, Mode> {
+    #[derivative(Debug = "ignore")]
+    storage: &'a MemoryStorage<Types>,
+    /// The state of the ledger as seen by this transaction.
+    ///
+    /// Until a write transaction makes its first change, this is shared with the storage. After
+    /// that, it is a private copy including all the changes made in this transaction.
+    #[derivative(Debug = "ignore")]
+    ledger: Arc<Ledger<Types>>,
+    /// Held by write transactions for their whole lifetime, to serialize writers.
+    #[derivative(Debug = "ignore")]
+    _lock: Option<MutexGuard<'a, ()>>,
+    _mode: PhantomData<Mode>,
+}
+
+impl<'a, Types: NodeType, Mode> Transaction<'a, Types, Mode> {
+    fn new(storage: &'a MemoryStorage<Types>, lock: Option<MutexGuard<'a, ()>>) -> Self {
+        Self {
+            storage,
+            ledger: storage.snapshot(),
+            _lock: lock,
+            _mode: PhantomData,
+        }
+    }
+}
+
+impl<Types: NodeType> Transaction<'_, Types, Write> {
+    /// Get a mutable reference to this transaction's private copy of the ledger.
+    fn ledger_mut(&mut self) -> &mut Ledger<Types> {
+        Arc::make_mut(&mut self.ledger)
+    }
+}
+
+impl<Types: NodeType> update::Transaction for Transaction<'_, Types, Write> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
+        // We hold the write lock, so no one else can have modified the shared ledger since this
+        // transaction was opened, and it is safe to simply replace it.
+        *self.storage.ledger.write().unwrap() = self.ledger;
+        Ok(self.storage.commits.commit())
+    }
+
+    fn revert(self) -> impl Future + Send {
+        // Changes are discarded when `self` is dropped.
+        async move {}
+    }
+}
+
+impl<Types: NodeType> update::Transaction for Transaction<'_, Types, Read> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
+        // Nothing to commit for a read-only transaction.
+        Ok(self.storage.commits.current())
+    }
+
+    fn revert(self) -> impl Future + Send {
+        // Nothing to revert for a read-only transaction.
+        async move {}
+    }
+}
+
+impl<Types: NodeType> VersionedDataSource for MemoryStorage<Types> {
+    type Transaction<'a>
+        = Transaction<'a, Types, Write>
+    where
+        Self: 'a;
+    type ReadOnly<'a>
+        = Transaction<'a, Types, Read>
+    where
+        Self: 'a;
+
+    async fn write(&self) -> anyhow::Result<Self::Transaction<'_>> {
+        let lock = self.write_lock.lock().await;
+        Ok(Transaction::new(self, Some(lock)))
+    }
+
+    async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
+        Ok(Transaction::new(self, None))
+    }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.commits.wait(marker).await;
+        self.read().await
+    }
+}
+
+#[async_trait]
+impl<Types, Mode> AvailabilityStorage<Types> for Transaction<'_, Types, Mode>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    Mode: Send + Sync,
+{
+    async fn get_leaf(&mut self, id: LeafId<Types>) -> QueryResult<LeafQueryData<Types>> {
+        let height = match id {
+            LeafId::Number(n) => n as u64,
+            LeafId::Hash(h) => *self.ledger.leaf_hashes.get(&h).context(NotFoundSnafu)?,
+        };
+        self.ledger.get_at(&self.ledger.leaves, height)
+    }
+
+    async fn get_block(&mut self, id: BlockId<Types>) -> QueryResult<BlockQueryData<Types>> {
+        let height = self.ledger.block_index(id)?;
+        self.ledger.get_at(&self.ledger.blocks, height)
+    }
+
+    async fn get_header(&mut self, id: BlockId<Types>) -> QueryResult<Header<Types>> {
+        let height = self.ledger.block_index(id)?;
+        self.ledger.get_at(&self.ledger.headers, height)
+    }
+
+    async fn get_payload(&mut self, id: BlockId<Types>) -> QueryResult<PayloadQueryData<Types>> {
+        self.get_block(id).await.map(PayloadQueryData::from)
+    }
+
+    async fn get_payload_metadata(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<PayloadMetadata<Types>> {
+        self.get_block(id).await.map(PayloadMetadata::from)
+    }
+
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        self.get_block(id).await.map(BlockSummaryQueryData::from)
+    }
+
+    async fn get_vid_common(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<VidCommonQueryData<Types>> {
+        let height = self.ledger.block_index(id)?;
+        self.ledger.get_at(&self.ledger.vid_common, height)
+    }
+
+    async fn get_vid_common_metadata(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<VidCommonMetadata<Types>> {
+        self.get_vid_common(id).await.map(VidCommonMetadata::from)
+    }
+
+    async fn get_vid_share(&mut self, id: BlockId<Types>, index: usize) -> QueryResult<VidShare> {
+        let height = self.ledger.block_index(id)?;
+        if height >= self.ledger.height {
+            return Err(QueryError::NotFound);
+        }
+        self.ledger
+            .vid_shares
+            .get(&(height, index))
+            .cloned()
+            .context(MissingSnafu)
+    }
+
+    async fn get_leaf_by_view(&mut self, view: u64) -> QueryResult<LeafQueryData<Types>> {
+        let height = *self.ledger.views.get(&view).context(NotFoundSnafu)?;
+        self.ledger.get_at(&self.ledger.leaves, height)
+    }
+
+    async fn get_leaf_range_by_time(
+        &mut self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        if start >= end {
+            return Ok(vec![]);
+        }
+
+        // Timestamps are not strictly monotonic, so the matching heights need not be contiguous.
+        // Collect them from the time index and sort them into chain order.
+        let heights = self
+            .ledger
+            .timestamps
+            .range((start, 0)..(end, 0))
+            .map(|(_, height)| *height)
+            .collect::<BTreeSet<_>>();
+        Ok(heights
+            .into_iter()
+            .filter_map(|height| {
+                let leaf = self.ledger.leaves.get(&height)?;
+                Some((height, leaf.clone()))
+            })
+            .collect())
+    }
+
+    async fn get_leaf_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<LeafQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self.ledger.get_range(&self.ledger.leaves, range))
+    }
+
+    async fn get_block_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self.ledger.get_range(&self.ledger.blocks, range))
+    }
+
+    async fn get_payload_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<PayloadQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(PayloadQueryData::from))
+            .collect())
+    }
+
+    async fn get_payload_metadata_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<PayloadMetadata<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(PayloadMetadata::from))
+            .collect())
+    }
+
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(BlockSummaryQueryData::from))
+            .collect())
+    }
+
+    async fn get_vid_common_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<VidCommonQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self.ledger.get_range(&self.ledger.vid_common, range))
+    }
+
+    async fn get_vid_common_metadata_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<VidCommonMetadata<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self
+            .get_vid_common_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(VidCommonMetadata::from))
+            .collect())
+    }
+
+    async fn get_transaction(
+        &mut self,
+        hash: TransactionHash<Types>,
+    ) -> QueryResult<TransactionQueryData<Types>> {
+        let height = *self.ledger.transactions.get(&hash).context(NotFoundSnafu)?;
+        let block = self.get_block((height as usize).into()).await?;
+        TransactionQueryData::with_hash(&block, hash).context(ErrorSnafu {
+            message: format!(
+                "transaction index inconsistent: block {height} contains no transaction {hash}"
+            ),
+        })
+    }
+}
+
+impl<Types> UpdateAvailabilityStorage<Types> for Transaction<'_, Types, Write>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+{
+    async fn insert_leaf(&mut self, leaf: LeafQueryData<Types>) -> anyhow::Result<()> {
+        let height = leaf.height();
+        if let Some(existing) = self.ledger.leaves.get(&height) {
+            Reorg::check(existing.hash(), &leaf)?;
+        }
+        let ledger = self.ledger_mut();
+        ledger.height = ledger.height.max(height + 1);
+        index_by_hash(&mut ledger.leaf_hashes, leaf.hash(), height);
+        index_by_hash(&mut ledger.block_hashes, leaf.block_hash(), height);
+        index_by_hash(&mut ledger.payload_hashes, leaf.payload_hash(), height);
+        ledger
+            .timestamps
+            .insert((leaf.header().timestamp(), height));
+        ledger.views.insert(leaf.leaf().view_number().u64(), height);
+        ledger.headers.insert(height, leaf.header().clone());
+        ledger.leaves.insert(height, leaf);
+        Ok(())
+    }
+
+    async fn insert_block(&mut self, block: BlockQueryData<Types>) -> anyhow::Result<()> {
+        // Check the block against whatever we already have at this height. Re-inserting the same
+        // block is a no-op, but a conflicting block must not overwrite what is stored.
+        let height = block.height();
+        if let Some(header) = self.ledger.headers.get(&height) {
+            if header.commit() != block.hash() {
+                return Err(InconsistentData {
+                    height,
+                    reason: format!(
+                        "block {} does not match stored header {}",
+                        block.hash(),
+                        header.commit()
+                    ),
+                }
+                .into());
+            }
+        }
+        if let Some(existing) = self.ledger.blocks.get(&height) {
+            if *existing != block {
+                return Err(InconsistentData {
+                    height,
+                    reason: "payload does not match stored payload".into(),
+                }
+                .into());
+            }
+            // The block is already stored.
+            return Ok(());
+        }
+
+        let ledger = self.ledger_mut();
+        ledger.num_transactions += block.len();
+        ledger.payload_size += block.size() as usize;
+        for (_, txn) in block.enumerate() {
+            index_by_hash(&mut ledger.transactions, txn.commit(), height);
+        }
+        ledger.blocks.insert(height, block);
+        Ok(())
+    }
+
+    async fn insert_vid(
+        &mut self,
+        common: VidCommonQueryData<Types>,
+        share: Option<VidShare>,
+    ) -> anyhow::Result<()> {
+        let height = common.height();
+        let ledger = self.ledger_mut();
+        ledger.vid_common.insert(height, common);
+        // Never replace a share we already have with a missing one.
+        if let Some(share) = share {
+            ledger.own_vid_shares.insert(height, share);
+        }
+        Ok(())
+    }
+
+    async fn insert_vid_share(
+        &mut self,
+        height: u64,
+        index: usize,
+        share: VidShare,
+    ) -> anyhow::Result<()> {
+        self.ledger_mut().vid_shares.insert((height, index), share);
+        Ok(())
+    }
+
+    async fn purge_from(&mut self, height: u64) -> anyhow::Result<()> {
+        if height >= self.ledger.height {
+            return Ok(());
+        }
+        let ledger = self.ledger_mut();
+        for block in ledger.blocks.split_off(&height).into_values() {
+            ledger.num_transactions -= block.len();
+            ledger.payload_size -= block.size() as usize;
+        }
+        ledger.leaves.split_off(&height);
+        ledger.headers.split_off(&height);
+        ledger.vid_common.split_off(&height);
+        ledger.own_vid_shares.split_off(&height);
+        ledger.vid_shares.split_off(&(height, 0));
+        ledger.leaf_hashes.retain(|_, h| *h < height);
+        ledger.block_hashes.retain(|_, h| *h < height);
+        ledger.payload_hashes.retain(|_, h| *h < height);
+        ledger.transactions.retain(|_, h| *h < height);
+        ledger.timestamps.retain(|(_, h)| *h < height);
+        ledger.views.retain(|_, h| *h < height);
+        ledger.height = ledger
+            .leaves
+            .last_key_value()
+            .map_or(0, |(last, _)| last + 1);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<Types, Mode> NodeStorage<Types> for Transaction<'_, Types, Mode>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    Mode: Send + Sync,
+{
+    async fn block_height(&mut self) -> QueryResult<usize> {
+        Ok(self.ledger.height as usize)
+    }
+
+    async fn count_transactions_in_range(
+        &mut self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        if !matches!(range.start_bound(), Bound::Unbounded | Bound::Included(0))
+            || !matches!(range.end_bound(), Bound::Unbounded)
+        {
+            return Err(QueryError::Error {
+                message: "partial aggregates are not supported with in-memory backend".into(),
+            });
+        }
+
+        Ok(self.ledger.num_transactions)
+    }
+
+    async fn payload_size_in_range(
+        &mut self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        if !matches!(range.start_bound(), Bound::Unbounded | Bound::Included(0))
+            || !matches!(range.end_bound(), Bound::Unbounded)
+        {
+            return Err(QueryError::Error {
+                message: "partial aggregates are not supported with in-memory backend".into(),
+            });
+        }
+
+        Ok(self.ledger.payload_size)
+    }
+
+    async fn vid_share<ID>(&mut self, id: ID) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        let height = self.ledger.block_index(id.into())?;
+        self.ledger.get_at(&self.ledger.own_vid_shares, height)
+    }
+
+    async fn sync_status(&mut self) -> QueryResult<SyncStatus> {
+        let height = self.ledger.height as usize;
+        Ok(SyncStatus {
+            missing_blocks: height - self.ledger.blocks.len(),
+            missing_leaves: height - self.ledger.leaves.len(),
+            missing_vid_common: height - self.ledger.vid_common.len(),
+            missing_vid_shares: height - self.ledger.own_vid_shares.len(),
+            pruned_height: None,
+            lowest_stored_height: self.ledger.leaves.keys().next().map(|h| *h as usize),
+            ..Default::default()
+        })
+    }
+
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+        let height = self.ledger.height as usize;
+        let gaps = |present: Vec<u64>| {
+            Gaps::from_present(present.into_iter().map(|h| h as usize), 0..height, max_gaps)
+        };
+        Ok(SyncGaps {
+            leaves: gaps(self.ledger.leaves.keys().copied().collect()),
+            blocks: gaps(self.ledger.blocks.keys().copied().collect()),
+            vid: gaps(self.ledger.vid_common.keys().copied().collect()),
+        })
+    }
+
+    async fn get_header_window(
+        &mut self,
+        start: impl Into<WindowStart<Types>> + Send + Sync,
+        end: u64,
+        limit: usize,
+    ) -> QueryResult<TimeWindowQueryData<Header<Types>>> {
+        let first_block = match start.into() {
+            WindowStart::Height(h) => h,
+            WindowStart::Hash(h) => self.ledger.block_index(h.into())?,
+            WindowStart::Time(t) => {
+                // The time index is ordered by timestamp and then height, so the first entry at or
+                // after `t` is the first block with the minimum timestamp which is at least `t`.
+                let (_, height) = self
+                    .ledger
+                    .timestamps
+                    .range((t, 0)..)
+                    .next()
+                    .context(NotFoundSnafu)?;
+                *height
+            }
+        };
+
+        let mut res = TimeWindowQueryData::default();
+
+        // Include the block just before the start of the window, if there is one.
+        if first_block > 0 {
+            res.prev = Some(self.ledger.get_at(&self.ledger.headers, first_block - 1)?);
+        }
+
+        // Add blocks to the window, starting from `first_block`, until we reach the end of the
+        // requested time window.
+        for height in first_block..self.ledger.height {
+            let header = self.ledger.get_at(&self.ledger.headers, height)?;
+            if header.timestamp() >= end {
+                res.next = Some(header);
+                break;
+            }
+            res.window.push(header);
+            if res.window.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl<Types: NodeType, Mode: Send + Sync> AggregatesStorage for Transaction<'_, Types, Mode> {
+    async fn aggregates_height(&mut self) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
+    async fn load_prev_aggregate(&mut self) -> anyhow::Result<Option<Aggregate>> {
+        Ok(None)
+    }
+}
+
+impl<Types, Mode: Send + Sync> UpdateAggregatesStorage<Types> for Transaction<'_, Types, Mode>
+where
+    Types: NodeType,
+{
+    async fn update_aggregates(
+        &mut self,
+        _prev: Aggregate,
+        _blocks: &[PayloadMetadata<Types>],
+    ) -> anyhow::Result<Aggregate> {
+        Ok(Aggregate::default())
+    }
+}
+
+impl<Types: NodeType, Mode> PrunedHeightStorage for Transaction<'_, Types, Mode> {}
@@ -19,12 +19,15 @@ use super::{
 };
 use crate::{
     availability::{
-        BlockId, BlockQueryData, LeafId, LeafQueryData, PayloadQueryData, QueryablePayload,
-        TransactionHash, TransactionQueryData, VidCommonQueryData,
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafId, LeafQueryData, PayloadQueryData,
+        QueryablePayload, TransactionHash, TransactionQueryData, VidCommonQueryData,
+    },
+    data_source::{
+        update::{self, CommitMarker, CommitTracker},
+        VersionedDataSource,
     },
-    data_source::{update, VersionedDataSource},
     metrics::PrometheusMetrics,
-    node::{SyncStatus, TimeWindowQueryData, WindowStart},
+    node::{SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
     status::HasMetrics,
     types::HeightIndexed,
     Header, Payload, QueryError, QueryResult, VidShare,
@@ -48,6 +51,7 @@ pub struct NoStorage {
     // functionality, such as fetching of missing data, stems from that.
     height: Arc<RwLock<u64>>,
     metrics: PrometheusMetrics,
+    commits: CommitTracker,
 }
 
 pub struct Transaction<'a> {
@@ -72,6 +76,11 @@ impl VersionedDataSource for NoStorage {
     async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
         Ok(Transaction::new(self).await)
     }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.commits.wait(marker).await;
+        self.read().await
+    }
 }
 
 impl<'a> Transaction<'a> {
@@ -84,10 +93,10 @@ impl<'a> Transaction<'a> {
 }
 
 impl<'a> update::Transaction for Transaction<'a> {
-    async fn commit(self) -> anyhow::Result<()> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
         let mut height = self.storage.height.write().await;
         *height = max(*height, self.height);
-        Ok(())
+        Ok(self.storage.commits.commit())
     }
 
     fn revert(self) -> impl Future + Send {
@@ -135,6 +144,13 @@ where
         Err(QueryError::Missing)
     }
 
+    async fn get_block_summary(
+        &mut self,
+        _id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        Err(QueryError::Missing)
+    }
+
     async fn get_vid_common(
         &mut self,
         _id: BlockId<Types>,
@@ -189,6 +205,16 @@ where
         Ok(vec![])
     }
 
+    async fn get_block_summary_range<R>(
+        &mut self,
+        _range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(vec![])
+    }
+
     async fn get_vid_common_range<R>(
         &mut self,
         _range: R,
@@ -275,6 +301,10 @@ where
         Err(QueryError::Missing)
     }
 
+    async fn sync_gaps(&mut self, _max_gaps: usize) -> QueryResult<SyncGaps> {
+        Err(QueryError::Missing)
+    }
+
     async fn get_header_window(
         &mut self,
         _start: impl Into<WindowStart<Types>> + Send + Sync,
@@ -333,7 +363,7 @@ pub mod testing {
     use futures::stream::{BoxStream, StreamExt};
     use hotshot::types::Event;
     use portpicker::pick_unused_port;
-    use std::time::Duration;
+    use std::{ops::Bound, time::Duration};
     use tide_disco::App;
     use vbs::version::StaticVersionType;
 
@@ -490,6 +520,17 @@ pub mod testing {
                 }
             }
         }
+
+        async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+            match self {
+                Self::Sql(data_source) => {
+                    Ok(Transaction::Sql(data_source.read_at_least(marker).await?))
+                }
+                Self::NoStorage(data_source) => Ok(Transaction::NoStorage(
+                    data_source.read_at_least(marker).await?,
+                )),
+            }
+        }
     }
 
     impl UpdateAvailabilityData<MockTypes> for DataSource {
@@ -505,10 +546,10 @@ pub mod testing {
     where
         T: update::Transaction,
     {
-        async fn commit(self) -> anyhow::Result<()> {
+        async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
             match self {
-                Self::Sql(tx) => tx.commit().await,
-                Self::NoStorage(tx) => tx.commit().await,
+                Self::Sql(tx) => tx.commit_with_version().await,
+                Self::NoStorage(tx) => tx.commit_with_version().await,
             }
         }
 
@@ -643,6 +684,16 @@ pub mod testing {
             }
         }
 
+        async fn get_leaf_by_view(
+            &self,
+            view: <MockTypes as NodeType>::View,
+        ) -> Fetch<Option<LeafQueryData<MockTypes>>> {
+            match self {
+                Self::Sql(data_source) => data_source.get_leaf_by_view(view).await,
+                Self::NoStorage(data_source) => data_source.get_leaf_by_view(view).await,
+            }
+        }
+
         async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
         where
             R: RangeBounds<usize> + Send + 'static,
@@ -718,6 +769,28 @@ pub mod testing {
             }
         }
 
+        async fn get_leaf_range_rev(
+            &self,
+            start: Bound<usize>,
+            end: usize,
+        ) -> BoxStream<'static, Fetch<LeafQueryData<MockTypes>>> {
+            match self {
+                Self::Sql(data_source) => data_source.get_leaf_range_rev(start, end).await,
+                Self::NoStorage(data_source) => data_source.get_leaf_range_rev(start, end).await,
+            }
+        }
+
+        async fn get_block_range_rev(
+            &self,
+            start: Bound<usize>,
+            end: usize,
+        ) -> BoxStream<'static, Fetch<BlockQueryData<MockTypes>>> {
+            match self {
+                Self::Sql(data_source) => data_source.get_block_range_rev(start, end).await,
+                Self::NoStorage(data_source) => data_source.get_block_range_rev(start, end).await,
+            }
+        }
+
         async fn get_transaction(
             &self,
             hash: TransactionHash<MockTypes>,
@@ -782,6 +855,15 @@ pub mod testing {
             }
         }
 
+        async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+            match self {
+                Transaction::Sql(tx) => NodeStorage::<MockTypes>::sync_gaps(tx, max_gaps).await,
+                Transaction::NoStorage(tx) => {
+                    NodeStorage::<MockTypes>::sync_gaps(tx, max_gaps).await
+                }
+            }
+        }
+
         async fn get_header_window(
             &mut self,
             start: impl Into<WindowStart<MockTypes>> + Send + Sync,
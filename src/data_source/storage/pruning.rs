@@ -12,13 +12,16 @@
 
 use anyhow::bail;
 use async_trait::async_trait;
-use std::{fmt::Debug, time::Duration};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 #[derive(Clone, Debug)]
 pub struct PrunerCfg {
     pruning_threshold: Option<u64>,
     minimum_retention: Duration,
     target_retention: Duration,
+    target_retention_payload_only: Option<Duration>,
+    prune_policy: Option<Arc<dyn PrunePolicy>>,
+    retain_vid_common: bool,
     batch_size: u64,
     max_usage: u16,
     interval: Duration,
@@ -42,6 +45,152 @@ pub trait PrunedHeightStorage: Sized {
     async fn load_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
         Ok(None)
     }
+
+    /// The height of the latest block whose payload data has been pruned.
+    ///
+    /// Blocks at or below this height may have been stripped of their payload by the configured
+    /// [`PrunePolicy`], even though their leaves and headers remain available.
+    async fn load_payload_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// The height of the latest block whose VID data has been pruned.
+    ///
+    /// Blocks at or below this height may have been stripped of their VID shares (and, unless
+    /// [`PrunerCfg::retain_vid_common`] is set, their VID common data) by the configured
+    /// [`PrunePolicy`], even though their leaves and headers remain available.
+    async fn load_vid_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// The heights of all blocks whose payload and VID data have been deleted on demand.
+    ///
+    /// See [`DeleteBlockStorage`](super::DeleteBlockStorage).
+    async fn load_deleted_heights(&mut self) -> anyhow::Result<Vec<u64>> {
+        Ok(vec![])
+    }
+}
+
+/// A block which is a candidate for pruning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PruneCandidate {
+    /// The height of the block.
+    pub height: u64,
+    /// The timestamp of the block, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// The state of the chain at the time a [`PrunePolicy`] is evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PruneContext {
+    /// The number of blocks in the chain.
+    pub block_height: u64,
+    /// The current time, in seconds since the Unix epoch.
+    pub now: u64,
+}
+
+/// A policy deciding which categories of data to prune for each block.
+///
+/// The pruner evaluates each category independently, and only ever deletes the selected
+/// categories: leaves and headers are never removed by a [`PrunePolicy`], only by
+/// [`PrunerCfg::target_retention`] and the disk usage threshold.
+///
+/// Policies must be monotonic in the age of a block: if a category is selected for pruning for some
+/// block, it must also be selected for every older block. The pruner proceeds in order of block
+/// height and stops at the first block for which a category is not selected, or which is younger
+/// than [`PrunerCfg::minimum_retention`], whatever the policy says.
+///
+/// Queries for pruned payloads fail with
+/// [`QueryError::PayloadPruned`](crate::QueryError::PayloadPruned), and queries for pruned VID data
+/// with [`QueryError::VidPruned`](crate::QueryError::VidPruned).
+pub trait PrunePolicy: Debug + Send + Sync {
+    /// Should the payload of `block` be pruned?
+    fn should_prune_payload(&self, block: PruneCandidate, ctx: PruneContext) -> bool;
+
+    /// Should the VID data of `block` be pruned?
+    fn should_prune_vid(&self, block: PruneCandidate, ctx: PruneContext) -> bool;
+}
+
+/// Prune data older than a fixed period of time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AgeBasedPolicy {
+    payload_retention: Option<Duration>,
+    vid_retention: Option<Duration>,
+}
+
+impl AgeBasedPolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Prune payloads older than `retention`.
+    pub fn with_payload_retention(mut self, retention: Duration) -> Self {
+        self.payload_retention = Some(retention);
+        self
+    }
+
+    /// Prune VID data older than `retention`.
+    pub fn with_vid_retention(mut self, retention: Duration) -> Self {
+        self.vid_retention = Some(retention);
+        self
+    }
+
+    fn expired(retention: Option<Duration>, block: PruneCandidate, ctx: PruneContext) -> bool {
+        retention.map_or(false, |retention| {
+            block.timestamp.saturating_add(retention.as_secs()) <= ctx.now
+        })
+    }
+}
+
+impl PrunePolicy for AgeBasedPolicy {
+    fn should_prune_payload(&self, block: PruneCandidate, ctx: PruneContext) -> bool {
+        Self::expired(self.payload_retention, block, ctx)
+    }
+
+    fn should_prune_vid(&self, block: PruneCandidate, ctx: PruneContext) -> bool {
+        Self::expired(self.vid_retention, block, ctx)
+    }
+}
+
+/// Retain data only for a fixed number of the most recent blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeightWindowPolicy {
+    payload_window: Option<u64>,
+    vid_window: Option<u64>,
+}
+
+impl HeightWindowPolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Retain payloads only for the latest `window` blocks.
+    pub fn with_payload_window(mut self, window: u64) -> Self {
+        self.payload_window = Some(window);
+        self
+    }
+
+    /// Retain VID data only for the latest `window` blocks.
+    pub fn with_vid_window(mut self, window: u64) -> Self {
+        self.vid_window = Some(window);
+        self
+    }
+
+    fn expired(window: Option<u64>, block: PruneCandidate, ctx: PruneContext) -> bool {
+        window.map_or(false, |window| {
+            block.height.saturating_add(window) < ctx.block_height
+        })
+    }
+}
+
+impl PrunePolicy for HeightWindowPolicy {
+    fn should_prune_payload(&self, block: PruneCandidate, ctx: PruneContext) -> bool {
+        Self::expired(self.payload_window, block, ctx)
+    }
+
+    fn should_prune_vid(&self, block: PruneCandidate, ctx: PruneContext) -> bool {
+        Self::expired(self.vid_window, block, ctx)
+    }
 }
 
 pub trait PrunerConfig {
@@ -67,6 +216,12 @@ impl PrunerCfg {
             bail!("max_usage must be less than or equal to 10000")
         }
 
+        if let Some(retention) = self.target_retention_payload_only {
+            if retention > self.target_retention {
+                bail!("target_retention_payload_only must not exceed target_retention")
+            }
+        }
+
         Ok(())
     }
 
@@ -85,6 +240,21 @@ impl PrunerCfg {
         self
     }
 
+    pub fn with_target_retention_payload_only(mut self, retention: Duration) -> Self {
+        self.target_retention_payload_only = Some(retention);
+        self
+    }
+
+    pub fn with_prune_policy(mut self, policy: impl PrunePolicy + 'static) -> Self {
+        self.prune_policy = Some(Arc::new(policy));
+        self
+    }
+
+    pub fn with_retain_vid_common(mut self, retain_vid_common: bool) -> Self {
+        self.retain_vid_common = retain_vid_common;
+        self
+    }
+
     pub fn with_batch_size(mut self, batch_size: u64) -> Self {
         self.batch_size = batch_size;
         self
@@ -111,7 +281,8 @@ impl PrunerCfg {
 
     /// Minimum data retention period
     ///
-    /// Data younger than this is never pruned, regardless of disk usage.
+    /// Data younger than this is never pruned, regardless of disk usage or
+    /// [`prune_policy`](Self::prune_policy).
     pub fn minimum_retention(&self) -> Duration {
         self.minimum_retention
     }
@@ -124,6 +295,49 @@ impl PrunerCfg {
         self.target_retention
     }
 
+    /// Target payload retention period
+    ///
+    /// If set, block payloads and VID shares older than this are pruned, while leaves and headers
+    /// are retained until they exceed `TARGET_RETENTION`. Queries for a pruned payload fail with
+    /// [`QueryError::PayloadPruned`](crate::QueryError::PayloadPruned).
+    ///
+    /// In SQL storage, pruned payloads are not deleted row by row. Instead, their data is cleared
+    /// (`payload.data = NULL`), which keeps the payload metadata (size and number of transactions)
+    /// available. Likewise, if [`retain_vid_common`](Self::retain_vid_common) is set, VID shares are
+    /// cleared (`vid.share = NULL`) and the VID common data is kept. Either way, the space freed by
+    /// clearing this data is only reclaimed once the database is vacuumed: PostgreSQL's autovacuum
+    /// eventually makes it available for reuse, and SQLite reclaims it with `VACUUM` (or an
+    /// incremental vacuum). Until then, the size of the database on disk does not shrink.
+    ///
+    /// This is shorthand for an [`AgeBasedPolicy`] applying the same retention to payloads and VID
+    /// data, and is ignored if a custom [`prune_policy`](Self::prune_policy) is set.
+    pub fn target_retention_payload_only(&self) -> Option<Duration> {
+        self.target_retention_payload_only
+    }
+
+    /// Policy for pruning payload and VID data while retaining leaves and headers
+    ///
+    /// If no policy was set explicitly, this is derived from `TARGET_RETENTION_PAYLOAD_ONLY`.
+    pub fn prune_policy(&self) -> Option<Arc<dyn PrunePolicy>> {
+        if let Some(policy) = &self.prune_policy {
+            return Some(policy.clone());
+        }
+        let retention = self.target_retention_payload_only?;
+        Some(Arc::new(
+            AgeBasedPolicy::new()
+                .with_payload_retention(retention)
+                .with_vid_retention(retention),
+        ))
+    }
+
+    /// Whether to retain VID common data when pruning payloads
+    ///
+    /// VID common data is small and is needed to re-derive a payload from VID shares, so it is
+    /// retained by default. This only has an effect if a prune policy selects VID data for pruning.
+    pub fn retain_vid_common(&self) -> bool {
+        self.retain_vid_common
+    }
+
     /// Number of blocks to remove in a single pruning operation.
     pub fn batch_size(&self) -> u64 {
         self.batch_size
@@ -153,6 +367,9 @@ impl Default for PrunerCfg {
             minimum_retention: Duration::from_secs(24 * 3600),
             // 7 days
             target_retention: Duration::from_secs(7 * 24 * 3600),
+            target_retention_payload_only: None,
+            prune_policy: None,
+            retain_vid_common: true,
             batch_size: 30000,
             // 80%
             max_usage: 8000,
@@ -0,0 +1,974 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Storage backed by an embedded RocksDB key-value store.
+//!
+//! Each kind of object is stored in its own column family. Objects which are indexed by block
+//! height (leaves, headers, payloads and VID data) are keyed by the big-endian encoding of the
+//! height, so that the natural key order of the column family is the order of the chain and range
+//! queries are simple forward scans. Secondary indices map hashes to heights.
+//!
+//! A write [`Transaction`] buffers all of its changes in memory, where they are visible to reads
+//! made through the same transaction, and writes them to the database in a single atomic
+//! [`WriteBatch`] when it is committed. Reverting (or dropping) the transaction simply discards the
+//! buffered changes. Write transactions are serialized with a lock, but they do not block readers:
+//! each transaction reads from a consistent RocksDB snapshot taken when it was opened.
+
+#![cfg(feature = "rocksdb-data-source")]
+
+use super::{
+    pruning::{PruneStorage, PrunedHeightStorage, PrunerConfig},
+    Aggregate, AggregatesStorage, AvailabilityStorage, InconsistentData, NodeStorage,
+    PayloadMetadata, Reorg, UpdateAggregatesStorage, UpdateAvailabilityStorage, VidCommonMetadata,
+};
+use crate::{
+    availability::{
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafId, LeafQueryData, PayloadQueryData,
+        QueryableHeader, QueryablePayload, TransactionHash, TransactionQueryData,
+        VidCommonQueryData,
+    },
+    data_source::{
+        update::{self, CommitMarker, CommitTracker},
+        VersionedDataSource,
+    },
+    metrics::PrometheusMetrics,
+    node::{Gaps, SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
+    status::HasMetrics,
+    types::HeightIndexed,
+    ErrorSnafu, Header, MissingSnafu, NotFoundSnafu, Payload, QueryError, QueryResult, VidShare,
+};
+use async_lock::{Mutex, MutexGuard};
+use async_trait::async_trait;
+use committable::Committable;
+use futures::future::Future;
+use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, Snapshot, WriteBatch, DB};
+use serde::{de::DeserializeOwned, Serialize};
+use snafu::OptionExt;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    path::Path,
+};
+
+const LEAF_CF: &str = "leaf";
+const HEADER_CF: &str = "header";
+const PAYLOAD_CF: &str = "payload";
+const VID_CF: &str = "vid";
+const VID_SHARE_CF: &str = "vid_share";
+const OWN_VID_SHARE_CF: &str = "own_vid_share";
+const LEAF_HASH_CF: &str = "leaf_hash";
+const BLOCK_HASH_CF: &str = "block_hash";
+const PAYLOAD_HASH_CF: &str = "payload_hash";
+const TXN_HASH_CF: &str = "txn_hash";
+const TIME_CF: &str = "time";
+const META_CF: &str = "meta";
+
+const COLUMN_FAMILIES: [&str; 12] = [
+    LEAF_CF,
+    HEADER_CF,
+    PAYLOAD_CF,
+    VID_CF,
+    VID_SHARE_CF,
+    OWN_VID_SHARE_CF,
+    LEAF_HASH_CF,
+    BLOCK_HASH_CF,
+    PAYLOAD_HASH_CF,
+    TXN_HASH_CF,
+    TIME_CF,
+    META_CF,
+];
+
+// Counters kept in the metadata column family, so that they can be read without scanning.
+const BLOCK_HEIGHT: &[u8] = b"block_height";
+const NUM_LEAVES: &[u8] = b"num_leaves";
+const NUM_BLOCKS: &[u8] = b"num_blocks";
+const NUM_VID: &[u8] = b"num_vid";
+const NUM_VID_SHARES: &[u8] = b"num_vid_shares";
+const NUM_TRANSACTIONS: &[u8] = b"num_transactions";
+const PAYLOAD_SIZE: &[u8] = b"payload_size";
+
+/// Storage for the APIs provided in this crate, backed by an embedded RocksDB database.
+#[derive(custom_debug::Debug)]
+pub struct RocksDbStorage {
+    #[debug(skip)]
+    db: DB,
+    write_lock: Mutex<()>,
+    metrics: PrometheusMetrics,
+    commits: CommitTracker,
+}
+
+impl RocksDbStorage {
+    /// Create a new [RocksDbStorage] with storage at `path`.
+    ///
+    /// If there is already a database at `path`, it will be destroyed.
+    pub async fn create(path: &Path) -> anyhow::Result<Self> {
+        DB::destroy(&Options::default(), path)?;
+        Self::open(path).await
+    }
+
+    /// Open an existing [RocksDbStorage] from storage at `path`.
+    ///
+    /// If there is no database at `path`, a new one will be created.
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        Ok(Self {
+            db: DB::open_cf(&opts, path, COLUMN_FAMILIES)?,
+            write_lock: Mutex::new(()),
+            metrics: Default::default(),
+            commits: Default::default(),
+        })
+    }
+}
+
+impl PrunerConfig for RocksDbStorage {}
+impl PruneStorage for RocksDbStorage {
+    type Pruner = ();
+}
+
+impl HasMetrics for RocksDbStorage {
+    fn metrics(&self) -> &PrometheusMetrics {
+        &self.metrics
+    }
+}
+
+/// Marker type indicating a transaction with read-write access to the database.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Write;
+
+/// Marker type indicating a transaction with read-only access to the database.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Read;
+
+/// A transaction on a [RocksDbStorage].
+#[derive(custom_debug::Debug)]
+pub struct Transaction<'a, Mode> {
+    #[debug(skip)]
+    db: &'a DB,
+    #[debug(skip)]
+    snapshot: Snapshot<'a>,
+    /// Changes made in this transaction which have not yet been written to the database, by column
+    /// family and key.
+    pending: HashMap<&'static str, BTreeMap<Vec<u8>, Vec<u8>>>,
+    commits: &'a CommitTracker,
+    /// Held by write transactions for their whole lifetime, to serialize writers.
+    #[debug(skip)]
+    _lock: Option<MutexGuard<'a, ()>>,
+    _mode: PhantomData<Mode>,
+}
+
+impl<'a, Mode> Transaction<'a, Mode> {
+    fn new(db: &'a DB, commits: &'a CommitTracker, lock: Option<MutexGuard<'a, ()>>) -> Self {
+        Self {
+            db,
+            snapshot: db.snapshot(),
+            pending: Default::default(),
+            commits,
+            _lock: lock,
+            _mode: PhantomData,
+        }
+    }
+
+    fn cf(&self, name: &str) -> QueryResult<&'a ColumnFamily> {
+        self.db.cf_handle(name).context(ErrorSnafu {
+            message: format!("missing column family {name}"),
+        })
+    }
+
+    fn get_raw(&self, cf: &'static str, key: &[u8]) -> QueryResult<Option<Vec<u8>>> {
+        match self.pending.get(cf).and_then(|pending| pending.get(key)) {
+            Some(bytes) => Ok(Some(bytes.clone())),
+            None => self.snapshot.get_cf(self.cf(cf)?, key).map_err(db_error),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, cf: &'static str, key: &[u8]) -> QueryResult<Option<T>> {
+        self.get_raw(cf, key)?
+            .map(|bytes| decode(&bytes))
+            .transpose()
+    }
+
+    /// Read all entries with keys in the range `[from, to)`, up to `limit` entries.
+    ///
+    /// The result reflects both committed data and the pending changes of this transaction.
+    fn scan(
+        &self,
+        cf: &'static str,
+        from: &[u8],
+        to: Option<&[u8]>,
+        limit: usize,
+    ) -> QueryResult<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut entries = BTreeMap::new();
+        let iter = self
+            .snapshot
+            .iterator_cf(self.cf(cf)?, IteratorMode::From(from, Direction::Forward));
+        for entry in iter.take(limit) {
+            let (key, value) = entry.map_err(db_error)?;
+            if to.is_some_and(|to| *key >= *to) {
+                break;
+            }
+            entries.insert(key.into(), value.into());
+        }
+
+        // Merge in pending changes. Since we took at most `limit` committed entries, the first
+        // `limit` entries of the merged set are exactly the ones we want.
+        if let Some(pending) = self.pending.get(cf) {
+            let end = to.map_or(Bound::Unbounded, Bound::Excluded);
+            for (key, value) in pending.range::<[u8], _>((Bound::Included(from), end)) {
+                entries.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(entries.into_iter().take(limit).collect())
+    }
+
+    fn meta(&self, key: &[u8]) -> QueryResult<u64> {
+        Ok(self.get(META_CF, key)?.unwrap_or(0))
+    }
+
+    fn chain_height(&self) -> QueryResult<u64> {
+        self.meta(BLOCK_HEIGHT)
+    }
+
+    /// Get the object stored at `height` in a column family indexed by height.
+    fn get_at<T: DeserializeOwned>(&self, cf: &'static str, height: u64) -> QueryResult<T> {
+        if height >= self.chain_height()? {
+            return Err(QueryError::NotFound);
+        }
+        self.get(cf, &height.to_be_bytes())?.context(MissingSnafu)
+    }
+
+    /// Get all objects with heights in `range` from a column family indexed by height.
+    fn get_range<T: DeserializeOwned>(
+        &self,
+        cf: &'static str,
+        range: impl RangeBounds<usize>,
+    ) -> QueryResult<Vec<QueryResult<T>>> {
+        let start = match range.start_bound() {
+            Bound::Included(n) => *n as u64,
+            Bound::Excluded(n) => *n as u64 + 1,
+            Bound::Unbounded => 0,
+        };
+        let height = self.chain_height()?;
+        let end = match range.end_bound() {
+            Bound::Included(n) => (*n as u64 + 1).min(height),
+            Bound::Excluded(n) => (*n as u64).min(height),
+            Bound::Unbounded => height,
+        };
+        if start >= end {
+            return Ok(vec![]);
+        }
+
+        let mut entries = self.scan(
+            cf,
+            &start.to_be_bytes(),
+            Some(&end.to_be_bytes()),
+            (end - start) as usize,
+        )?;
+        Ok((start..end)
+            .map(|height| {
+                let bytes = entries
+                    .remove(&height.to_be_bytes()[..])
+                    .context(MissingSnafu)?;
+                decode(&bytes)
+            })
+            .collect())
+    }
+
+    /// All heights below `end` with an entry in a column family indexed by height.
+    ///
+    /// Only keys are read, so this is much cheaper than a [`scan`](Self::scan) of the same range.
+    fn present_heights(&self, cf: &'static str, end: u64) -> QueryResult<BTreeSet<u64>> {
+        let mut heights = BTreeSet::new();
+        let mut iter = self.snapshot.raw_iterator_cf(self.cf(cf)?);
+        iter.seek_to_first();
+        while let Some(key) = iter.key() {
+            let height = height_key(key);
+            if height >= end {
+                break;
+            }
+            heights.insert(height);
+            iter.next();
+        }
+        iter.status().map_err(db_error)?;
+
+        if let Some(pending) = self.pending.get(cf) {
+            heights.extend(
+                pending
+                    .keys()
+                    .map(|key| height_key(key))
+                    .filter(|height| *height < end),
+            );
+        }
+        Ok(heights)
+    }
+
+    /// The lowest height with an entry in a column family indexed by height.
+    fn first_height(&self, cf: &'static str) -> QueryResult<Option<u64>> {
+        let mut iter = self.snapshot.raw_iterator_cf(self.cf(cf)?);
+        iter.seek_to_first();
+        let committed = iter.key().map(height_key);
+        iter.status().map_err(db_error)?;
+
+        let pending = self
+            .pending
+            .get(cf)
+            .and_then(|pending| pending.keys().next())
+            .map(|key| height_key(key));
+        Ok(committed.into_iter().chain(pending).min())
+    }
+
+    /// Look up the height of an object in a hash index.
+    fn height_by_hash<H: Serialize>(&self, cf: &'static str, hash: &H) -> QueryResult<u64> {
+        self.get(cf, &encode(hash)?)?.context(NotFoundSnafu)
+    }
+
+    fn block_index<Types: NodeType>(&self, id: BlockId<Types>) -> QueryResult<u64> {
+        match id {
+            BlockId::Number(n) => Ok(n as u64),
+            BlockId::Hash(h) => self.height_by_hash(BLOCK_HASH_CF, &h),
+            BlockId::PayloadHash(h) => self.height_by_hash(PAYLOAD_HASH_CF, &h),
+        }
+    }
+}
+
+impl Transaction<'_, Write> {
+    fn put<T: Serialize>(&mut self, cf: &'static str, key: Vec<u8>, value: &T) -> QueryResult<()> {
+        self.pending
+            .entry(cf)
+            .or_default()
+            .insert(key, encode(value)?);
+        Ok(())
+    }
+
+    fn put_meta(&mut self, key: &[u8], value: u64) -> QueryResult<()> {
+        self.put(META_CF, key.to_vec(), &value)
+    }
+
+    fn add_meta(&mut self, key: &[u8], delta: u64) -> QueryResult<()> {
+        let value = self.meta(key)? + delta;
+        self.put_meta(key, value)
+    }
+
+    /// Insert an object of a height-indexed column family.
+    ///
+    /// Returns `false` if there was already an object at `height`, in which case it is overwritten.
+    fn put_at<T: Serialize>(
+        &mut self,
+        cf: &'static str,
+        height: u64,
+        value: &T,
+    ) -> QueryResult<bool> {
+        let key = height.to_be_bytes().to_vec();
+        let is_new = self.get_raw(cf, &key)?.is_none();
+        self.put(cf, key, value)?;
+        Ok(is_new)
+    }
+
+    /// Map `hash` to `height` in a hash index.
+    ///
+    /// This will insert the mapping _unless_ there is already an entry for `hash` at an earlier
+    /// position in the ledger.
+    fn index_by_hash<H: Serialize>(
+        &mut self,
+        cf: &'static str,
+        hash: &H,
+        height: u64,
+    ) -> QueryResult<()> {
+        let key = encode(hash)?;
+        if self
+            .get::<u64>(cf, &key)?
+            .is_some_and(|existing| existing <= height)
+        {
+            return Ok(());
+        }
+        self.put(cf, key, &height)
+    }
+}
+
+impl update::Transaction for Transaction<'_, Write> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
+        let mut batch = WriteBatch::default();
+        for (cf, entries) in &self.pending {
+            let cf = self.cf(cf)?;
+            for (key, value) in entries {
+                batch.put_cf(cf, key, value);
+            }
+        }
+        self.db.write(batch)?;
+        Ok(self.commits.commit())
+    }
+
+    fn revert(self) -> impl Future + Send {
+        // Pending changes are discarded when `self` is dropped.
+        async move {}
+    }
+}
+
+impl update::Transaction for Transaction<'_, Read> {
+    async fn commit_with_version(self) -> anyhow::Result<CommitMarker> {
+        // Nothing to commit for a read-only transaction.
+        Ok(self.commits.current())
+    }
+
+    fn revert(self) -> impl Future + Send {
+        // Nothing to revert for a read-only transaction.
+        async move {}
+    }
+}
+
+impl VersionedDataSource for RocksDbStorage {
+    type Transaction<'a>
+        = Transaction<'a, Write>
+    where
+        Self: 'a;
+    type ReadOnly<'a>
+        = Transaction<'a, Read>
+    where
+        Self: 'a;
+
+    async fn write(&self) -> anyhow::Result<Self::Transaction<'_>> {
+        let lock = self.write_lock.lock().await;
+        Ok(Transaction::new(&self.db, &self.commits, Some(lock)))
+    }
+
+    async fn read(&self) -> anyhow::Result<Self::ReadOnly<'_>> {
+        Ok(Transaction::new(&self.db, &self.commits, None))
+    }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Self::ReadOnly<'_>> {
+        self.commits.wait(marker).await;
+        self.read().await
+    }
+}
+
+#[async_trait]
+impl<Types, Mode> AvailabilityStorage<Types> for Transaction<'_, Mode>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    Mode: Send + Sync,
+{
+    async fn get_leaf(&mut self, id: LeafId<Types>) -> QueryResult<LeafQueryData<Types>> {
+        let height = match id {
+            LeafId::Number(n) => n as u64,
+            LeafId::Hash(h) => self.height_by_hash(LEAF_HASH_CF, &h)?,
+        };
+        self.get_at(LEAF_CF, height)
+    }
+
+    async fn get_block(&mut self, id: BlockId<Types>) -> QueryResult<BlockQueryData<Types>> {
+        self.get_at(PAYLOAD_CF, self.block_index(id)?)
+    }
+
+    async fn get_header(&mut self, id: BlockId<Types>) -> QueryResult<Header<Types>> {
+        self.get_at(HEADER_CF, self.block_index(id)?)
+    }
+
+    async fn get_payload(&mut self, id: BlockId<Types>) -> QueryResult<PayloadQueryData<Types>> {
+        self.get_block(id).await.map(PayloadQueryData::from)
+    }
+
+    async fn get_payload_metadata(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<PayloadMetadata<Types>> {
+        self.get_block(id).await.map(PayloadMetadata::from)
+    }
+
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        self.get_block(id).await.map(BlockSummaryQueryData::from)
+    }
+
+    async fn get_vid_common(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<VidCommonQueryData<Types>> {
+        // VID common data is stored separately from this node's share, so this never reads the
+        // (potentially much larger) share.
+        self.get_at(VID_CF, self.block_index(id)?)
+    }
+
+    async fn get_vid_common_metadata(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<VidCommonMetadata<Types>> {
+        self.get_vid_common(id).await.map(VidCommonMetadata::from)
+    }
+
+    async fn get_vid_share(&mut self, id: BlockId<Types>, index: usize) -> QueryResult<VidShare> {
+        let height = self.block_index(id)?;
+        if height >= self.chain_height()? {
+            return Err(QueryError::NotFound);
+        }
+        self.get(VID_SHARE_CF, &vid_share_key(height, index))?
+            .context(MissingSnafu)
+    }
+
+    async fn get_leaf_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<LeafQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        self.get_range(LEAF_CF, range)
+    }
+
+    async fn get_block_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        self.get_range(PAYLOAD_CF, range)
+    }
+
+    async fn get_payload_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<PayloadQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(PayloadQueryData::from))
+            .collect())
+    }
+
+    async fn get_payload_metadata_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<PayloadMetadata<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(PayloadMetadata::from))
+            .collect())
+    }
+
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        Ok(self
+            .get_block_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(BlockSummaryQueryData::from))
+            .collect())
+    }
+
+    async fn get_vid_common_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<VidCommonQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        self.get_range(VID_CF, range)
+    }
+
+    async fn get_vid_common_metadata_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<VidCommonMetadata<Types>>>>
+    where
+        R: RangeBounds<usize> + Send,
+    {
+        Ok(self
+            .get_vid_common_range(range)
+            .await?
+            .into_iter()
+            .map(|res| res.map(VidCommonMetadata::from))
+            .collect())
+    }
+
+    async fn get_transaction(
+        &mut self,
+        hash: TransactionHash<Types>,
+    ) -> QueryResult<TransactionQueryData<Types>> {
+        let height = self.height_by_hash(TXN_HASH_CF, &hash)?;
+        let block = self.get_block((height as usize).into()).await?;
+        TransactionQueryData::with_hash(&block, hash).context(ErrorSnafu {
+            message: format!(
+                "transaction index inconsistent: block {height} contains no transaction {hash}"
+            ),
+        })
+    }
+}
+
+impl<Types> UpdateAvailabilityStorage<Types> for Transaction<'_, Write>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+{
+    async fn insert_leaf(&mut self, leaf: LeafQueryData<Types>) -> anyhow::Result<()> {
+        let height = leaf.height();
+        if let Some(existing) = self.get::<LeafQueryData<Types>>(LEAF_CF, &height.to_be_bytes())? {
+            Reorg::check(existing.hash(), &leaf)?;
+        }
+        if self.put_at(LEAF_CF, height, &leaf)? {
+            self.add_meta(NUM_LEAVES, 1)?;
+        }
+        self.put_at(HEADER_CF, height, leaf.header())?;
+        if height + 1 > self.chain_height()? {
+            self.put_meta(BLOCK_HEIGHT, height + 1)?;
+        }
+
+        self.index_by_hash(LEAF_HASH_CF, &leaf.hash(), height)?;
+        self.index_by_hash(BLOCK_HASH_CF, &leaf.block_hash(), height)?;
+        self.index_by_hash(PAYLOAD_HASH_CF, &leaf.payload_hash(), height)?;
+        let mut time_key = leaf.header().timestamp().to_be_bytes().to_vec();
+        time_key.extend(height.to_be_bytes());
+        self.put(TIME_CF, time_key, &())?;
+        Ok(())
+    }
+
+    async fn insert_block(&mut self, block: BlockQueryData<Types>) -> anyhow::Result<()> {
+        // Check the block against whatever we already have at this height. Re-inserting the same
+        // block is a no-op, but a conflicting block must not overwrite what is stored.
+        let height = block.height();
+        let key = height.to_be_bytes();
+        if let Some(header) = self.get::<Header<Types>>(HEADER_CF, &key)? {
+            if header.commit() != block.hash() {
+                return Err(InconsistentData {
+                    height,
+                    reason: format!(
+                        "block {} does not match stored header {}",
+                        block.hash(),
+                        header.commit()
+                    ),
+                }
+                .into());
+            }
+        }
+        if let Some(existing) = self.get::<BlockQueryData<Types>>(PAYLOAD_CF, &key)? {
+            if existing != block {
+                return Err(InconsistentData {
+                    height,
+                    reason: "payload does not match stored payload".into(),
+                }
+                .into());
+            }
+            // The block is already stored.
+            return Ok(());
+        }
+
+        self.put_at(PAYLOAD_CF, height, &block)?;
+        self.add_meta(NUM_BLOCKS, 1)?;
+        self.add_meta(NUM_TRANSACTIONS, block.len() as u64)?;
+        self.add_meta(PAYLOAD_SIZE, block.size())?;
+        for (_, txn) in block.enumerate() {
+            self.index_by_hash(TXN_HASH_CF, &txn.commit(), height)?;
+        }
+        Ok(())
+    }
+
+    async fn insert_vid(
+        &mut self,
+        common: VidCommonQueryData<Types>,
+        share: Option<VidShare>,
+    ) -> anyhow::Result<()> {
+        let height = common.height();
+        if self.put_at(VID_CF, height, &common)? {
+            self.add_meta(NUM_VID, 1)?;
+        }
+        // Never replace a share we already have with a missing one.
+        if let Some(share) = share {
+            if self.put_at(OWN_VID_SHARE_CF, height, &share)? {
+                self.add_meta(NUM_VID_SHARES, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_vid_share(
+        &mut self,
+        height: u64,
+        index: usize,
+        share: VidShare,
+    ) -> anyhow::Result<()> {
+        self.put(VID_SHARE_CF, vid_share_key(height, index), &share)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<Types, Mode> NodeStorage<Types> for Transaction<'_, Mode>
+where
+    Types: NodeType,
+    Payload<Types>: QueryablePayload<Types>,
+    Header<Types>: QueryableHeader<Types>,
+    Mode: Send + Sync,
+{
+    async fn block_height(&mut self) -> QueryResult<usize> {
+        Ok(self.chain_height()? as usize)
+    }
+
+    async fn count_transactions_in_range(
+        &mut self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        if !matches!(range.start_bound(), Bound::Unbounded | Bound::Included(0))
+            || !matches!(range.end_bound(), Bound::Unbounded)
+        {
+            return Err(QueryError::Error {
+                message: "partial aggregates are not supported with RocksDB backend".into(),
+            });
+        }
+
+        Ok(self.meta(NUM_TRANSACTIONS)? as usize)
+    }
+
+    async fn payload_size_in_range(
+        &mut self,
+        range: impl RangeBounds<usize> + Send,
+    ) -> QueryResult<usize> {
+        if !matches!(range.start_bound(), Bound::Unbounded | Bound::Included(0))
+            || !matches!(range.end_bound(), Bound::Unbounded)
+        {
+            return Err(QueryError::Error {
+                message: "partial aggregates are not supported with RocksDB backend".into(),
+            });
+        }
+
+        Ok(self.meta(PAYLOAD_SIZE)? as usize)
+    }
+
+    async fn vid_share<ID>(&mut self, id: ID) -> QueryResult<VidShare>
+    where
+        ID: Into<BlockId<Types>> + Send + Sync,
+    {
+        self.get_at(OWN_VID_SHARE_CF, self.block_index(id.into())?)
+    }
+
+    async fn sync_status(&mut self) -> QueryResult<SyncStatus> {
+        let height = self.chain_height()? as usize;
+        let missing_vid = height - self.meta(NUM_VID)? as usize;
+        Ok(SyncStatus {
+            missing_blocks: height - self.meta(NUM_BLOCKS)? as usize,
+            missing_leaves: height - self.meta(NUM_LEAVES)? as usize,
+            missing_vid_common: missing_vid,
+            missing_vid_shares: height - self.meta(NUM_VID_SHARES)? as usize,
+            pruned_height: None,
+            lowest_stored_height: self.first_height(LEAF_CF)?.map(|h| h as usize),
+            ..Default::default()
+        })
+    }
+
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+        let height = self.chain_height()?;
+        let gaps = |cf: &'static str| -> QueryResult<Gaps> {
+            let present = self.present_heights(cf, height)?;
+            Ok(Gaps::from_present(
+                present.into_iter().map(|h| h as usize),
+                0..height as usize,
+                max_gaps,
+            ))
+        };
+        Ok(SyncGaps {
+            leaves: gaps(LEAF_CF)?,
+            blocks: gaps(PAYLOAD_CF)?,
+            vid: gaps(VID_CF)?,
+        })
+    }
+
+    async fn get_header_window(
+        &mut self,
+        start: impl Into<WindowStart<Types>> + Send + Sync,
+        end: u64,
+        limit: usize,
+    ) -> QueryResult<TimeWindowQueryData<Header<Types>>> {
+        let first_block = match start.into() {
+            WindowStart::Height(h) => h,
+            WindowStart::Hash(h) => self.block_index(h.into())?,
+            WindowStart::Time(t) => {
+                // The time index is keyed by timestamp and then height, so the first entry at or
+                // after `t` is the first block with the minimum timestamp which is at least `t`.
+                let entries = self.scan(TIME_CF, &t.to_be_bytes(), None, 1)?;
+                let (key, _) = entries.first_key_value().context(NotFoundSnafu)?;
+                u64::from_be_bytes(key[8..16].try_into().unwrap())
+            }
+        };
+
+        let mut res = TimeWindowQueryData::default();
+
+        // Include the block just before the start of the window, if there is one.
+        if first_block > 0 {
+            res.prev = Some(self.get_at(HEADER_CF, first_block - 1)?);
+        }
+
+        // Add blocks to the window, starting from `first_block`, until we reach the end of the
+        // requested time window.
+        for height in first_block..self.chain_height()? {
+            let header: Header<Types> = self.get_at(HEADER_CF, height)?;
+            if header.timestamp() >= end {
+                res.next = Some(header);
+                break;
+            }
+            res.window.push(header);
+            if res.window.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl<Mode: Send + Sync> AggregatesStorage for Transaction<'_, Mode> {
+    async fn aggregates_height(&mut self) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
+    async fn load_prev_aggregate(&mut self) -> anyhow::Result<Option<Aggregate>> {
+        Ok(None)
+    }
+}
+
+impl<Types, Mode: Send + Sync> UpdateAggregatesStorage<Types> for Transaction<'_, Mode>
+where
+    Types: NodeType,
+{
+    async fn update_aggregates(
+        &mut self,
+        _prev: Aggregate,
+        _blocks: &[PayloadMetadata<Types>],
+    ) -> anyhow::Result<Aggregate> {
+        Ok(Aggregate::default())
+    }
+}
+
+impl<Mode> PrunedHeightStorage for Transaction<'_, Mode> {}
+
+fn vid_share_key(height: u64, index: usize) -> Vec<u8> {
+    let mut key = height.to_be_bytes().to_vec();
+    key.extend((index as u64).to_be_bytes());
+    key
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> QueryResult<Vec<u8>> {
+    bincode::serialize(value).map_err(|err| QueryError::Error {
+        message: format!("failed to serialize object: {err}"),
+    })
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> QueryResult<T> {
+    bincode::deserialize(bytes).map_err(|err| QueryError::Error {
+        message: format!("failed to deserialize stored object: {err}"),
+    })
+}
+
+/// Decode the height from a key in a column family indexed by height.
+fn height_key(key: &[u8]) -> u64 {
+    u64::from_be_bytes(key[..8].try_into().unwrap())
+}
+
+fn db_error(err: impl Display) -> QueryError {
+    QueryError::Error {
+        message: format!("database error: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        availability::{BlockQueryData, LeafQueryData, VidCommonQueryData},
+        data_source::Transaction as _,
+        testing::{mocks::MockTypes, setup_test},
+    };
+    use hotshot_example_types::{
+        node_types::TestVersions,
+        state_types::{TestInstanceState, TestValidatedState},
+    };
+    use hotshot_types::vid::vid_scheme;
+    use jf_vid::VidScheme;
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_vid_common_without_share() {
+        setup_test();
+
+        let dir = TempDir::with_prefix("test_vid_common_without_share").unwrap();
+        let mut storage = RocksDbStorage::create(dir.path()).await.unwrap();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let common = VidCommonQueryData::new(
+            leaf.header().clone(),
+            vid_scheme(2).disperse([]).unwrap().common,
+        );
+
+        // Store VID common data without a share.
+        let mut tx = storage.write().await.unwrap();
+        tx.insert_leaf(leaf).await.unwrap();
+        tx.insert_block(block).await.unwrap();
+        tx.insert_vid(common.clone(), None).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let mut tx = storage.read().await.unwrap();
+        assert_eq!(
+            AvailabilityStorage::<MockTypes>::get_vid_common(&mut tx, BlockId::Number(0))
+                .await
+                .unwrap(),
+            common
+        );
+        let err = NodeStorage::<MockTypes>::vid_share(&mut tx, BlockId::Number(0))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::Missing), "{err:#}");
+        drop(tx);
+
+        // Fetching the common data never touches the share column family, so it still works even
+        // when that column family is gone.
+        storage.db.drop_cf(OWN_VID_SHARE_CF).unwrap();
+        let mut tx = storage.read().await.unwrap();
+        assert_eq!(
+            AvailabilityStorage::<MockTypes>::get_vid_common(&mut tx, BlockId::Number(0))
+                .await
+                .unwrap(),
+            common
+        );
+        NodeStorage::<MockTypes>::vid_share(&mut tx, BlockId::Number(0))
+            .await
+            .unwrap_err();
+    }
+}
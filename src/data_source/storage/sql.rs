@@ -14,16 +14,22 @@
 
 use crate::{
     data_source::{
-        storage::pruning::{PruneStorage, PrunerCfg, PrunerConfig},
-        update::Transaction as _,
+        storage::pruning::{
+            PruneCandidate, PruneContext, PruneStorage, PrunedHeightStorage, PrunerCfg,
+            PrunerConfig,
+        },
+        update::{CommitMarker, CommitTracker, Transaction as _},
         VersionedDataSource,
     },
     metrics::PrometheusMetrics,
     status::HasMetrics,
+    task::BackgroundTask,
     QueryError, QueryResult,
 };
 use async_trait::async_trait;
+use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use chrono::Utc;
+use futures::future::BoxFuture;
 
 use hotshot_types::traits::metrics::Metrics;
 use itertools::Itertools;
@@ -39,13 +45,21 @@ use sqlx::{
     pool::{Pool, PoolOptions},
     ConnectOptions, Row,
 };
-use std::{cmp::min, fmt::Debug, str::FromStr, time::Duration};
+use std::{
+    cmp::{max, min},
+    fmt::Debug,
+    str::FromStr,
+    time::Duration,
+};
+use tokio::time::sleep;
 pub extern crate sqlx;
 pub use sqlx::{Database, Sqlite};
 
+mod builder;
 mod db;
 mod migrate;
 mod queries;
+mod reindex;
 mod transaction;
 
 pub use anyhow::Error;
@@ -53,13 +67,19 @@ pub use anyhow::Error;
 // in the expansion of `include_migrations`, even when `include_migrations` is invoked from another
 // crate which doesn't have `include_dir` as a dependency.
 pub use crate::include_migrations;
+pub use builder::SqlConfigBuilder;
 pub use db::*;
 pub use include_dir::include_dir;
 pub use queries::QueryBuilder;
 pub use refinery::Migration;
+pub use reindex::{default_background_indexes, BackgroundIndex};
 pub use transaction::*;
 
-use self::{migrate::Migrator, transaction::PoolMetrics};
+use self::{
+    migrate::Migrator,
+    reindex::{build_background_indexes, ReindexMetrics},
+    transaction::PoolMetrics,
+};
 
 /// Embed migrations from the given directory into the current binary for PostgreSQL or SQLite.
 ///
@@ -128,6 +148,12 @@ macro_rules! include_migrations {
     };
 }
 
+/// The interval between the versions of consecutive [`default_migrations`].
+///
+/// Every default migration has a version which is a multiple of this interval, leaving the
+/// versions in between for custom migrations (see [`Config::extra_migrations`]).
+pub const DEFAULT_MIGRATION_INTERVAL: i32 = 100;
+
 /// The migrations requied to build the default schema for this version of [`SqlStorage`].
 pub fn default_migrations() -> Vec<Migration> {
     #[cfg(not(feature = "embedded-db"))]
@@ -155,8 +181,8 @@ pub fn default_migrations() -> Vec<Migration> {
             );
         } else {
             assert!(
-                m.version() % 100 == 0,
-                "default migration version {} is not a multiple of 100",
+                m.version() % DEFAULT_MIGRATION_INTERVAL == 0,
+                "default migration version {} is not a multiple of {DEFAULT_MIGRATION_INTERVAL}",
                 m.version()
             );
         }
@@ -212,14 +238,22 @@ pub struct Config {
     pool_opt: PoolOptions<Db>,
     #[cfg(not(feature = "embedded-db"))]
     schema: String,
+    #[cfg(not(feature = "embedded-db"))]
+    statement_timeout: Option<Duration>,
     reset: bool,
     migrations: Vec<Migration>,
     no_migrations: bool,
+    background_indexes: Vec<BackgroundIndex>,
     pruner_cfg: Option<PrunerCfg>,
     archive: bool,
+    commit_retries: usize,
+    state_retention: Option<u64>,
     pool: Option<Pool<Db>>,
 }
 
+/// The default number of times [`SqlStorage::with_transaction`] retries a conflicting transaction.
+pub const DEFAULT_COMMIT_RETRIES: usize = 3;
+
 #[cfg(not(feature = "embedded-db"))]
 impl Default for Config {
     fn default() -> Self {
@@ -253,8 +287,11 @@ impl From<SqliteConnectOptions> for Config {
             reset: false,
             migrations: vec![],
             no_migrations: false,
+            background_indexes: default_background_indexes(),
             pruner_cfg: None,
             archive: false,
+            commit_retries: DEFAULT_COMMIT_RETRIES,
+            state_retention: None,
             pool: None,
         }
     }
@@ -267,11 +304,15 @@ impl From<PgConnectOptions> for Config {
             db_opt,
             pool_opt: PoolOptions::default(),
             schema: "hotshot".into(),
+            statement_timeout: None,
             reset: false,
             migrations: vec![],
             no_migrations: false,
+            background_indexes: default_background_indexes(),
             pruner_cfg: None,
             archive: false,
+            commit_retries: DEFAULT_COMMIT_RETRIES,
+            state_retention: None,
             pool: None,
         }
     }
@@ -387,17 +428,64 @@ impl Config {
     }
 
     /// Add custom migrations to run when connecting to the database.
+    ///
+    /// A custom migration with the same version as one of the [`default_migrations`] replaces it.
+    /// To extend the default schema without replacing any part of it, use
+    /// [`extra_migrations`](Self::extra_migrations) instead.
     pub fn migrations(mut self, migrations: impl IntoIterator<Item = Migration>) -> Self {
         self.migrations.extend(migrations);
         self
     }
 
+    /// Add migrations from a downstream crate which extend the default schema.
+    ///
+    /// Migration versions which are multiples of [`DEFAULT_MIGRATION_INTERVAL`] are reserved for
+    /// the [`default_migrations`] of this crate, including ones added in future releases, as are
+    /// the legacy versions 10, 20 and 30. Downstream crates own every other version. A migration
+    /// which builds on the default schema as of default migration `V{100 * n}` should use a
+    /// version from `100 * n + 1` to `100 * n + 99`. It then runs after that default migration and
+    /// all earlier ones, and before any default migration added later, so that the default
+    /// migrations can keep growing without ever colliding with a downstream one.
+    ///
+    /// This fails if any of `migrations` has a reserved version. Unlike
+    /// [`migrations`](Self::migrations), it therefore cannot be used to replace a default
+    /// migration by accident.
+    pub fn extra_migrations(
+        mut self,
+        migrations: impl IntoIterator<Item = Migration>,
+    ) -> Result<Self, Error> {
+        for m in migrations {
+            let version = m.version();
+            if version <= 0
+                || version % DEFAULT_MIGRATION_INTERVAL == 0
+                || (version <= 30 && version % 10 == 0)
+            {
+                return Err(Error::msg(format!(
+                    "migration {} uses version {version}, which is reserved for default migrations",
+                    m.name()
+                )));
+            }
+            self.migrations.push(m);
+        }
+        Ok(self)
+    }
+
     /// Skip all migrations when connecting to the database.
     pub fn no_migrations(mut self) -> Self {
         self.no_migrations = true;
         self
     }
 
+    /// Add a custom index to build in the background after connecting to the database.
+    ///
+    /// Unlike an index created in a [migration](Self::migrations), a [`BackgroundIndex`] does not
+    /// delay startup or block writes to the table while it is being built, which makes it suitable
+    /// for adding new indexes to large, existing databases.
+    pub fn background_index(mut self, index: BackgroundIndex) -> Self {
+        self.background_indexes.push(index);
+        self
+    }
+
     /// Enable pruning with a given configuration.
     ///
     /// If [`archive`](Self::archive) was previously specified, this will override it.
@@ -422,10 +510,31 @@ impl Config {
         self
     }
 
+    /// Set the number of times to retry a transaction which conflicts with a concurrent one.
+    ///
+    /// This applies to transactions run with [`SqlStorage::with_transaction`]. The default is
+    /// [`DEFAULT_COMMIT_RETRIES`]. Setting this to 0 disables retries.
+    pub fn commit_retries(mut self, retries: usize) -> Self {
+        self.commit_retries = retries;
+        self
+    }
+
+    /// Retain only a limited window of merklized state history.
+    ///
+    /// By default, every version of every merklized state node is kept, so that Merkle paths can be
+    /// served from any snapshot. With this option, only the snapshots from the latest `heights`
+    /// block heights before the most recent state are kept. Each time nodes are inserted, older
+    /// versions of those nodes which are not needed to serve any snapshot in the window are deleted,
+    /// and queries for older snapshots fail with [`QueryError::StatePruned`].
+    pub fn state_retention(mut self, heights: u64) -> Self {
+        self.state_retention = Some(heights);
+        self
+    }
+
     /// Set the maximum idle time of a connection.
     ///
     /// Any connection which has been open and unused longer than this duration will be
-    /// automatically closed to reduce load on the server.
+    /// automatically closed to reduce load on the server. The default is 10 minutes.
     pub fn idle_connection_timeout(mut self, timeout: Duration) -> Self {
         self.pool_opt = self.pool_opt.idle_timeout(Some(timeout));
         self
@@ -436,7 +545,7 @@ impl Config {
     /// Any connection which has been open longer than this duration will be automatically closed
     /// (and, if needed, replaced), even if it is otherwise healthy. It is good practice to refresh
     /// even healthy connections once in a while (e.g. daily) in case of resource leaks in the
-    /// server implementation.
+    /// server implementation. The default is 30 minutes.
     pub fn connection_timeout(mut self, timeout: Duration) -> Self {
         self.pool_opt = self.pool_opt.max_lifetime(Some(timeout));
         self
@@ -446,7 +555,7 @@ impl Config {
     ///
     /// The data source will, to the best of its ability, maintain at least `min` open connections
     /// at all times. This can be used to reduce the latency hit of opening new connections when at
-    /// least this many simultaneous connections are frequently needed.
+    /// least this many simultaneous connections are frequently needed. The default is 0.
     pub fn min_connections(mut self, min: u32) -> Self {
         self.pool_opt = self.pool_opt.min_connections(min);
         self
@@ -455,12 +564,40 @@ impl Config {
     /// Set the maximum number of connections to maintain at any time.
     ///
     /// Once `max` connections are in use simultaneously, further attempts to acquire a connection
-    /// (or begin a transaction) will block until one of the existing connections is released.
+    /// (or begin a transaction) will block until one of the existing connections is released, or
+    /// until the [`acquire_timeout`](Self::acquire_timeout) elapses. The default is 10.
+    ///
+    /// Each HTTP request holds a connection for the duration of its query, and the fetcher and
+    /// pruner hold more in the background, so a service under heavy load will usually want a
+    /// larger pool. The total across all services sharing a database must stay below the server's
+    /// own connection limit (`max_connections` in Postgres, which defaults to 100).
     pub fn max_connections(mut self, max: u32) -> Self {
         self.pool_opt = self.pool_opt.max_connections(max);
         self
     }
 
+    /// Set the maximum time to wait for a connection when opening a transaction.
+    ///
+    /// If all [`max_connections`](Self::max_connections) connections are in use and none is
+    /// released within `timeout`, [`read`](VersionedDataSource::read) and
+    /// [`write`](VersionedDataSource::write) fail with [`QueryError::PoolExhausted`], allowing
+    /// callers to shed load instead of queueing indefinitely. The default is 30s.
+    pub fn acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_opt = self.pool_opt.acquire_timeout(timeout);
+        self
+    }
+
+    /// Cancel any SQL statement which takes longer than `timeout`.
+    ///
+    /// This applies to every connection in the pool, and so to both read-only and write
+    /// transactions. A query which is cancelled fails with [`QueryError::Timeout`], which callers
+    /// may retry. By default, there is no timeout.
+    #[cfg(not(feature = "embedded-db"))]
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
     /// Log at WARN level any time a SQL statement takes longer than `threshold`.
     ///
     /// The default threshold is 1s.
@@ -479,6 +616,12 @@ pub struct SqlStorage {
     metrics: PrometheusMetrics,
     pool_metrics: PoolMetrics,
     pruner_cfg: Option<PrunerCfg>,
+    commit_retries: usize,
+    state_retention: Option<u64>,
+    commits: CommitTracker,
+    // The task building background indexes. This is only saved here so that we can cancel it on
+    // drop.
+    reindex: Option<BackgroundTask>,
 }
 
 #[derive(Debug, Default)]
@@ -486,6 +629,9 @@ pub struct Pruner {
     pruned_height: Option<u64>,
     target_height: Option<u64>,
     minimum_retention_height: Option<u64>,
+    prune_context: Option<PruneContext>,
+    payload_pruned_height: Option<u64>,
+    vid_pruned_height: Option<u64>,
 }
 
 impl SqlStorage {
@@ -498,26 +644,40 @@ impl SqlStorage {
         let pool_metrics = PoolMetrics::new(&*metrics.subgroup("sql".into()));
         let pool = config.pool_opt.clone();
         let pruner_cfg = config.pruner_cfg;
+        let commit_retries = config.commit_retries;
+        let state_retention = config.state_retention;
 
         // re-use the same pool if present and return early
         if let Some(pool) = config.pool {
+            pool_metrics.record_options(&pool);
             return Ok(Self {
                 metrics,
                 pool_metrics,
                 pool,
                 pruner_cfg,
+                commit_retries,
+                state_retention,
+                commits: Default::default(),
+                reindex: None,
             });
         }
 
         #[cfg(not(feature = "embedded-db"))]
         let schema = config.schema.clone();
         #[cfg(not(feature = "embedded-db"))]
+        let statement_timeout = config.statement_timeout;
+        #[cfg(not(feature = "embedded-db"))]
         let pool = pool.after_connect(move |conn, _| {
             let schema = config.schema.clone();
             async move {
                 query(&format!("SET search_path TO {schema}"))
-                    .execute(conn)
+                    .execute(&mut *conn)
                     .await?;
+                if let Some(timeout) = statement_timeout {
+                    query(&format!("SET statement_timeout = {}", timeout.as_millis()))
+                        .execute(&mut *conn)
+                        .await?;
+                }
                 Ok(())
             }
             .boxed()
@@ -529,6 +689,7 @@ impl SqlStorage {
         }
 
         let pool = pool.connect_with(config.db_opt).await?;
+        pool_metrics.record_options(&pool);
 
         // Create or connect to the schema for this query service.
         let mut conn = pool.acquire().await?;
@@ -585,19 +746,93 @@ impl SqlStorage {
             query("DELETE FROM pruned_height WHERE id = 1")
                 .execute(conn.as_mut())
                 .await?;
+            query("DELETE FROM pruned_payload_height WHERE id = 1")
+                .execute(conn.as_mut())
+                .await?;
+            query("DELETE FROM pruned_vid_height WHERE id = 1")
+                .execute(conn.as_mut())
+                .await?;
         }
 
         conn.close().await?;
 
+        // Now that the schema is up to date, build any indexes which are missing. This may take a
+        // long time, so we don't wait for it; the storage is usable in the meantime.
+        let reindex_metrics = ReindexMetrics::new(
+            &*metrics.subgroup("sql".into()),
+            config.background_indexes.len(),
+        );
+        let reindex = BackgroundTask::spawn(
+            "background indexes",
+            build_background_indexes(pool.clone(), config.background_indexes, reindex_metrics),
+        );
+
         Ok(Self {
             pool,
             pool_metrics,
             metrics,
             pruner_cfg,
+            commit_retries,
+            state_retention,
+            commits: Default::default(),
+            reindex: Some(reindex),
         })
     }
 }
 
+impl SqlStorage {
+    /// Run `f` in a write transaction and commit it, retrying if it conflicts with a concurrent
+    /// transaction.
+    ///
+    /// The database aborts a transaction which conflicts with a concurrent transaction, or which is
+    /// part of a deadlock. Such a transaction may succeed if it is run again from the beginning. So
+    /// if `f` or the commit fails for this reason, the transaction is rolled back and `f` is called
+    /// again with a new transaction, after a randomized exponential backoff, up to the configured
+    /// number of [retries](Config::commit_retries). Any other error is returned right away.
+    ///
+    /// Since `f` may be called more than once, it should not have side effects outside of the
+    /// transaction.
+    pub async fn with_transaction<T, F>(&self, mut f: F) -> anyhow::Result<T>
+    where
+        F: for<'a> FnMut(&'a mut Transaction<Write>) -> BoxFuture<'a, anyhow::Result<T>>,
+    {
+        let mut backoff = ExponentialBackoffBuilder::new()
+            .with_initial_interval(Duration::from_millis(10))
+            .with_max_elapsed_time(None)
+            .build();
+        let mut retries = 0;
+        loop {
+            let res = async {
+                let mut tx = self.write().await?;
+                match f(&mut tx).await {
+                    Ok(res) => {
+                        tx.commit().await?;
+                        Ok(res)
+                    }
+                    Err(err) => {
+                        tx.revert().await;
+                        Err(err)
+                    }
+                }
+            }
+            .await;
+            match res {
+                Err(err) if retries < self.commit_retries && is_serialization_failure(&err) => {
+                    retries += 1;
+                    let delay = backoff.next_backoff().unwrap_or_default();
+                    tracing::info!(
+                        retries,
+                        ?delay,
+                        "transaction conflicted with a concurrent transaction, retrying: {err:#}"
+                    );
+                    sleep(delay).await;
+                }
+                res => break res,
+            }
+        }
+    }
+}
+
 impl PrunerConfig for SqlStorage {
     fn set_pruning_config(&mut self, cfg: PrunerCfg) {
         self.pruner_cfg = Some(cfg);
@@ -616,9 +851,7 @@ impl HasMetrics for SqlStorage {
 
 impl SqlStorage {
     async fn get_minimum_height(&self) -> QueryResult<Option<u64>> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
         let (Some(height),) =
             query_as::<(Option<i64>,)>("SELECT MIN(height) as height FROM header")
                 .fetch_one(tx.as_mut())
@@ -630,9 +863,7 @@ impl SqlStorage {
     }
 
     async fn get_height_by_timestamp(&self, timestamp: i64) -> QueryResult<Option<u64>> {
-        let mut tx = self.read().await.map_err(|err| QueryError::Error {
-            message: err.to_string(),
-        })?;
+        let mut tx = self.read().await?;
 
         // We order by timestamp and then height, even though logically this is no different than
         // just ordering by height, since timestamps are monotonic. The reason is that this order
@@ -654,6 +885,49 @@ impl SqlStorage {
         };
         Ok(Some(height as u64))
     }
+
+    async fn get_prune_context(&self) -> QueryResult<PruneContext> {
+        let mut tx = self.read().await?;
+        let (height,) = query_as::<(Option<i64>,)>("SELECT MAX(height) as height FROM header")
+            .fetch_one(tx.as_mut())
+            .await?;
+        Ok(PruneContext {
+            block_height: height.map_or(0, |h| h as u64 + 1),
+            now: Utc::now().timestamp() as u64,
+        })
+    }
+
+    /// Get the end of the next batch of blocks after `after` which are selected for pruning.
+    ///
+    /// At most `batch_size` blocks are considered, in order of height, and the batch ends just
+    /// before the first block which is not selected.
+    async fn get_prune_batch(
+        &self,
+        after: Option<u64>,
+        batch_size: u64,
+        select: impl Fn(PruneCandidate) -> bool,
+    ) -> QueryResult<Option<u64>> {
+        let mut tx = self.read().await?;
+        let blocks = query_as::<(i64, i64)>(
+            "SELECT height, timestamp FROM header
+              WHERE height > $1
+              ORDER BY height
+              LIMIT $2",
+        )
+        .bind(after.map_or(-1, |h| h as i64))
+        .bind(batch_size as i64)
+        .fetch_all(tx.as_mut())
+        .await?;
+        Ok(blocks
+            .into_iter()
+            .map(|(height, timestamp)| PruneCandidate {
+                height: height as u64,
+                timestamp: timestamp as u64,
+            })
+            .take_while(|block| select(*block))
+            .last()
+            .map(|block| block.height))
+    }
 }
 
 #[async_trait]
@@ -730,6 +1004,79 @@ impl PruneStorage for SqlStorage {
             }
         }
 
+        // If a prune policy is set, prune the payload and VID data it selects in batches, keeping
+        // leaves and headers. Each category is pruned independently.
+        if let Some(policy) = cfg.prune_policy() {
+            let ctx = match pruner.prune_context {
+                Some(ctx) => ctx,
+                None => {
+                    let ctx = self.get_prune_context().await?;
+                    pruner.prune_context = Some(ctx);
+                    ctx
+                }
+            };
+            // Whatever the policy selects, data younger than the minimum retention period is kept.
+            let minimum_retention = cfg.minimum_retention().as_secs();
+            let retained = move |block: PruneCandidate| {
+                block.timestamp.saturating_add(minimum_retention) > ctx.now
+            };
+
+            // Resume from where the last run left off, but never below data which has already been
+            // pruned entirely.
+            let payload_height = match pruner.payload_pruned_height {
+                Some(h) => Some(h),
+                None => {
+                    let mut tx = self.read().await?;
+                    max(
+                        tx.load_pruned_height().await?,
+                        tx.load_payload_pruned_height().await?,
+                    )
+                }
+            };
+            if let Some(payload_height) = self
+                .get_prune_batch(payload_height, batch_size, |block| {
+                    !retained(block) && policy.should_prune_payload(block, ctx)
+                })
+                .await?
+            {
+                let mut tx = self.write().await?;
+                tx.delete_payload_batch(payload_height).await?;
+                tx.commit().await.map_err(|e| QueryError::Error {
+                    message: format!("failed to commit {e}"),
+                })?;
+
+                pruner.payload_pruned_height = Some(payload_height);
+                return Ok(Some(payload_height));
+            }
+
+            let vid_height = match pruner.vid_pruned_height {
+                Some(h) => Some(h),
+                None => {
+                    let mut tx = self.read().await?;
+                    max(
+                        tx.load_pruned_height().await?,
+                        tx.load_vid_pruned_height().await?,
+                    )
+                }
+            };
+            if let Some(vid_height) = self
+                .get_prune_batch(vid_height, batch_size, |block| {
+                    !retained(block) && policy.should_prune_vid(block, ctx)
+                })
+                .await?
+            {
+                let mut tx = self.write().await?;
+                tx.delete_vid_batch(vid_height, cfg.retain_vid_common())
+                    .await?;
+                tx.commit().await.map_err(|e| QueryError::Error {
+                    message: format!("failed to commit {e}"),
+                })?;
+
+                pruner.vid_pruned_height = Some(vid_height);
+                return Ok(Some(vid_height));
+            }
+        }
+
         // If threshold is set, prune data exceeding minimum retention in batches
         // This parameter is needed for SQL storage as there is no direct way to get free space.
         if let Some(threshold) = cfg.pruning_threshold() {
@@ -787,11 +1134,28 @@ impl VersionedDataSource for SqlStorage {
         Self: 'a;
 
     async fn write(&self) -> anyhow::Result<Transaction<Write>> {
-        Transaction::new(&self.pool, self.pool_metrics.clone()).await
+        Transaction::new(
+            &self.pool,
+            self.pool_metrics.clone(),
+            self.commits.clone(),
+            self.state_retention,
+        )
+        .await
     }
 
     async fn read(&self) -> anyhow::Result<Transaction<Read>> {
-        Transaction::new(&self.pool, self.pool_metrics.clone()).await
+        Transaction::new(
+            &self.pool,
+            self.pool_metrics.clone(),
+            self.commits.clone(),
+            self.state_retention,
+        )
+        .await
+    }
+
+    async fn read_at_least(&self, marker: CommitMarker) -> anyhow::Result<Transaction<Read>> {
+        self.commits.wait(marker).await;
+        self.read().await
     }
 }
 
@@ -928,11 +1292,18 @@ pub mod testing {
                 .host(self.host())
                 .port(self.port());
 
-            cfg = cfg.migrations(vec![Migration::unapplied(
-                "V101__create_test_merkle_tree_table.sql",
-                &TestMerkleTreeMigration::create("test_tree"),
-            )
-            .unwrap()]);
+            cfg = cfg.migrations(vec![
+                Migration::unapplied(
+                    "V101__create_test_merkle_tree_table.sql",
+                    &TestMerkleTreeMigration::create("test_tree", "test_merkle_tree_root", 8),
+                )
+                .unwrap(),
+                Migration::unapplied(
+                    "V102__create_test_merkle_tree_4_table.sql",
+                    &TestMerkleTreeMigration::create("test_tree_4", "test_merkle_tree_4_root", 4),
+                )
+                .unwrap(),
+            ]);
 
             cfg
         }
@@ -1053,20 +1424,22 @@ pub mod testing {
     pub struct TestMerkleTreeMigration;
 
     impl TestMerkleTreeMigration {
-        fn create(name: &str) -> String {
+        /// A migration creating the node table `name` for a tree of the given `arity`, whose root
+        /// is stored in the header field `root`.
+        fn create(name: &str, root: &str, arity: usize) -> String {
             let (bit_vec, binary, hash_pk, root_stored_column) = if cfg!(feature = "embedded-db") {
                 (
-                    "TEXT",
+                    "TEXT".to_string(),
                     "BLOB",
                     "INTEGER PRIMARY KEY AUTOINCREMENT",
-                    " (json_extract(data, '$.test_merkle_tree_root'))",
+                    format!(" (json_extract(data, '$.{root}'))"),
                 )
             } else {
                 (
-                    "BIT(8)",
+                    format!("BIT({arity})"),
                     "BYTEA",
                     "SERIAL PRIMARY KEY",
-                    "(data->>'test_merkle_tree_root')",
+                    format!("(data->>'{root}')"),
                 )
             };
 
@@ -1078,7 +1451,7 @@ pub mod testing {
             );
     
             ALTER TABLE header
-            ADD column test_merkle_tree_root text
+            ADD column {root} text
             GENERATED ALWAYS as {root_stored_column} STORED;
 
             CREATE TABLE {name}
@@ -1105,14 +1478,20 @@ mod test {
         node_types::TestVersions,
         state_types::{TestInstanceState, TestValidatedState},
     };
+    use hotshot_types::vid::vid_scheme;
+    use jf_vid::VidScheme;
     use std::time::Duration;
     use tokio::time::sleep;
 
     use super::{testing::TmpDb, *};
     use crate::{
-        availability::LeafQueryData,
-        data_source::storage::{pruning::PrunedHeightStorage, UpdateAvailabilityStorage},
+        availability::{BlockId, BlockQueryData, LeafQueryData, VidCommonQueryData},
+        data_source::storage::{
+            pruning::{HeightWindowPolicy, PrunePolicy},
+            AvailabilityStorage, DeleteBlockStorage, NodeStorage, UpdateAvailabilityStorage,
+        },
         testing::{mocks::MockTypes, setup_test},
+        types::HeightIndexed,
     };
 
     #[tokio::test(flavor = "multi_thread")]
@@ -1186,6 +1565,32 @@ mod test {
         assert_eq!(cfg.db_opt.get_filename().to_string_lossy(), "data.db");
     }
 
+    #[test]
+    fn test_extra_migrations() {
+        let migration = |version| {
+            Migration::unapplied(
+                &format!("V{version}__extra.sql"),
+                "CREATE TABLE extra (x bigint);",
+            )
+            .unwrap()
+        };
+
+        // Versions between default migrations are available to downstream crates.
+        let cfg = Config::default()
+            .extra_migrations([migration(901), migration(999)])
+            .unwrap();
+        assert_eq!(cfg.migrations.len(), 2);
+
+        // Versions which are, or may one day be, used by default migrations are reserved.
+        for version in [20, 900, 1000] {
+            let err = Config::default()
+                .extra_migrations([migration(version)])
+                .err()
+                .unwrap();
+            tracing::info!("version {version} rejected as expected: {err}");
+        }
+    }
+
     async fn vacuum(storage: &SqlStorage) {
         storage
             .pool
@@ -1365,6 +1770,543 @@ mod test {
         assert_eq!(header_rows, 0);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_payload_only_pruning() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let mut storage = SqlStorage::connect(db.config()).await.unwrap();
+        let mut leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        // insert some mock data
+        for i in 0..20 {
+            leaf.leaf.block_header_mut().block_number = i;
+            leaf.leaf.block_header_mut().timestamp = Utc::now().timestamp() as u64;
+            block.header = leaf.header().clone();
+            let mut tx = storage.write().await.unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(block.clone()).await.unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        // Prune payloads older than 1s, but keep everything else for the default retention period.
+        storage.set_pruning_config(
+            PrunerCfg::new()
+                .with_target_retention_payload_only(Duration::from_secs(1))
+                .with_minimum_retention(Duration::ZERO),
+        );
+        sleep(Duration::from_secs(2)).await;
+        let mut pruner = Default::default();
+        let mut pruned_height = None;
+        while let Some(height) = storage.prune(&mut pruner).await.unwrap() {
+            pruned_height = Some(height);
+        }
+        assert_eq!(pruned_height, Some(19));
+
+        // All the leaves and headers are still there.
+        let mut tx = storage.read().await.unwrap();
+        for table in ["header", "leaf", "payload"] {
+            let rows = tx
+                .fetch_one(format!("select count(*) as count from {table}").as_str())
+                .await
+                .unwrap()
+                .get::<i64, _>("count");
+            assert_eq!(rows, 20, "{table} rows were deleted");
+        }
+        assert_eq!(tx.load_pruned_height().await.unwrap(), None);
+        assert_eq!(tx.load_payload_pruned_height().await.unwrap(), Some(19));
+
+        // The payloads are gone, and are reported as pruned rather than missing, but the metadata
+        // and leaves are still available.
+        for i in 0..20usize {
+            AvailabilityStorage::<MockTypes>::get_leaf(&mut tx, i.into())
+                .await
+                .unwrap();
+            AvailabilityStorage::<MockTypes>::get_payload_metadata(&mut tx, i.into())
+                .await
+                .unwrap();
+            let err = AvailabilityStorage::<MockTypes>::get_block(&mut tx, i.into())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::PayloadPruned), "{err:#}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_delete_block() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+        let mut leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let disperse = vid_scheme(2).disperse([]).unwrap();
+        let mut blocks = vec![];
+        for i in 0..5 {
+            leaf.leaf.block_header_mut().block_number = i;
+            block.header = leaf.header().clone();
+            let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common.clone());
+            let mut tx = storage.write().await.unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(block.clone()).await.unwrap();
+            tx.insert_vid(common.clone(), Some(disperse.shares[0].clone()))
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+            blocks.push((block.clone(), common));
+        }
+
+        // Delete a block from the middle of the chain. Deleting it twice is harmless.
+        let mut tx = storage.write().await.unwrap();
+        assert_eq!(
+            DeleteBlockStorage::<MockTypes>::delete_block(&mut tx, BlockId::Number(2))
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            DeleteBlockStorage::<MockTypes>::delete_block(&mut tx, blocks[2].0.hash().into())
+                .await
+                .unwrap(),
+            2
+        );
+        tx.commit().await.unwrap();
+
+        // Deleting a block we don't have fails.
+        let mut tx = storage.write().await.unwrap();
+        DeleteBlockStorage::<MockTypes>::delete_block(&mut tx, BlockId::Number(5))
+            .await
+            .unwrap_err();
+        tx.revert().await;
+
+        // Trying to restore the deleted data has no effect.
+        let (block, common) = blocks[2].clone();
+        let mut tx = storage.write().await.unwrap();
+        tx.insert_block(block).await.unwrap();
+        tx.insert_vid(common, Some(disperse.shares[0].clone()))
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // The deletion survives a restart.
+        drop(storage);
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+        let mut tx = storage.read().await.unwrap();
+        assert_eq!(tx.load_deleted_heights().await.unwrap(), vec![2]);
+
+        // The deleted payload and VID data are reported as deleted, but the leaf, header and
+        // payload metadata are still available, as are the neighbouring blocks.
+        for i in 0..5usize {
+            AvailabilityStorage::<MockTypes>::get_leaf(&mut tx, i.into())
+                .await
+                .unwrap();
+            AvailabilityStorage::<MockTypes>::get_payload_metadata(&mut tx, i.into())
+                .await
+                .unwrap();
+            let block = AvailabilityStorage::<MockTypes>::get_block(&mut tx, i.into()).await;
+            let vid = AvailabilityStorage::<MockTypes>::get_vid_common(&mut tx, i.into()).await;
+            if i == 2 {
+                assert!(matches!(block, Err(QueryError::Deleted)), "{block:?}");
+                assert!(matches!(vid, Err(QueryError::Deleted)), "{vid:?}");
+            } else {
+                assert_eq!(block.unwrap(), blocks[i].0);
+                assert_eq!(vid.unwrap(), blocks[i].1);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_prune_policy() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let mut storage = SqlStorage::connect(db.config()).await.unwrap();
+        let mut leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let disperse = vid_scheme(2).disperse([]).unwrap();
+        // insert some mock data: 5 blocks from two hours ago, followed by 15 recent blocks
+        let now = Utc::now().timestamp() as u64;
+        for i in 0..20 {
+            leaf.leaf.block_header_mut().block_number = i;
+            leaf.leaf.block_header_mut().timestamp = if i < 5 { now - 2 * 3600 } else { now };
+            block.header = leaf.header().clone();
+            let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common.clone());
+            let mut tx = storage.write().await.unwrap();
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+            tx.insert_block(block.clone()).await.unwrap();
+            tx.insert_vid(common, Some(disperse.shares[0].clone()))
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        // Keep payloads for only the latest 2 blocks, but don't touch VID data. The policy selects
+        // blocks up to 17, but the recent blocks are protected by the minimum retention period.
+        storage.set_pruning_config(
+            PrunerCfg::new()
+                .with_prune_policy(HeightWindowPolicy::new().with_payload_window(2))
+                .with_minimum_retention(Duration::from_secs(3600)),
+        );
+        let mut pruner = Default::default();
+        while storage.prune(&mut pruner).await.unwrap().is_some() {}
+        {
+            let mut tx = storage.read().await.unwrap();
+            assert_eq!(tx.load_payload_pruned_height().await.unwrap(), Some(4));
+            assert_eq!(tx.load_vid_pruned_height().await.unwrap(), None);
+            for i in 0..20usize {
+                AvailabilityStorage::<MockTypes>::get_leaf(&mut tx, i.into())
+                    .await
+                    .unwrap();
+                AvailabilityStorage::<MockTypes>::get_vid_common(&mut tx, i.into())
+                    .await
+                    .unwrap();
+                NodeStorage::<MockTypes>::vid_share(&mut tx, i)
+                    .await
+                    .unwrap();
+                let res = AvailabilityStorage::<MockTypes>::get_payload(&mut tx, i.into()).await;
+                if i < 5 {
+                    assert!(matches!(res, Err(QueryError::PayloadPruned)), "{res:?}");
+                } else {
+                    res.unwrap();
+                }
+            }
+        }
+
+        // A user-supplied policy can prune VID data independently of payloads, here for more
+        // blocks than have had their payloads pruned.
+        #[derive(Debug)]
+        struct PruneVidBelow(u64);
+
+        impl PrunePolicy for PruneVidBelow {
+            fn should_prune_payload(&self, _block: PruneCandidate, _ctx: PruneContext) -> bool {
+                false
+            }
+
+            fn should_prune_vid(&self, block: PruneCandidate, _ctx: PruneContext) -> bool {
+                block.height < self.0
+            }
+        }
+
+        storage.set_pruning_config(
+            PrunerCfg::new()
+                .with_prune_policy(PruneVidBelow(10))
+                .with_minimum_retention(Duration::ZERO)
+                .with_retain_vid_common(false),
+        );
+        let mut pruner = Default::default();
+        while storage.prune(&mut pruner).await.unwrap().is_some() {}
+        let mut tx = storage.read().await.unwrap();
+        assert_eq!(tx.load_payload_pruned_height().await.unwrap(), Some(4));
+        assert_eq!(tx.load_vid_pruned_height().await.unwrap(), Some(9));
+        for i in 0..20usize {
+            AvailabilityStorage::<MockTypes>::get_leaf(&mut tx, i.into())
+                .await
+                .unwrap();
+
+            // Payloads are only pruned below height 5...
+            let res = AvailabilityStorage::<MockTypes>::get_payload(&mut tx, i.into()).await;
+            if i < 5 {
+                assert!(matches!(res, Err(QueryError::PayloadPruned)), "{res:?}");
+            } else {
+                res.unwrap();
+            }
+
+            // ...while VID data is pruned below height 10, and reported as such.
+            let res = AvailabilityStorage::<MockTypes>::get_vid_common(&mut tx, i.into()).await;
+            if i < 10 {
+                assert!(matches!(res, Err(QueryError::VidPruned)), "{res:?}");
+            } else {
+                res.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[cfg(not(feature = "embedded-db"))]
+    async fn test_statement_timeout() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let cfg = db.config().statement_timeout(Duration::from_millis(100));
+        let storage = SqlStorage::connect(cfg).await.unwrap();
+
+        // Fast queries are unaffected.
+        storage
+            .read()
+            .await
+            .unwrap()
+            .fetch_one("SELECT 1")
+            .await
+            .unwrap();
+
+        // Slow queries are cancelled and reported as timeouts.
+        let err = storage
+            .read()
+            .await
+            .unwrap()
+            .fetch_one("SELECT pg_sleep(1)")
+            .await
+            .unwrap_err();
+        let err = QueryError::from(err);
+        assert!(matches!(err, QueryError::Timeout), "{err:#}");
+        assert_eq!(err.status(), tide_disco::StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[cfg(not(feature = "embedded-db"))]
+    async fn test_with_transaction_retry() {
+        use crate::{
+            data_source::storage::MerklizedStateHeightStorage, merklized_state::UpdateStateData,
+            testing::mocks::MockMerkleTree,
+        };
+        use futures::FutureExt;
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+        // Increment the stored state height, but on the first attempt, commit a concurrent
+        // increment after reading the height and before writing it.
+        async fn increment(
+            storage: &SqlStorage,
+            attempts: &Arc<AtomicUsize>,
+        ) -> anyhow::Result<()> {
+            storage
+                .with_transaction(|tx| {
+                    let attempts = attempts.clone();
+                    let other = storage.clone();
+                    async move {
+                        let height = tx.get_last_state_height().await?;
+                        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                            let mut other = other.write().await?;
+                            UpdateStateData::<MockTypes, MockMerkleTree, 8>::set_last_state_height(
+                                &mut other,
+                                height + 1,
+                            )
+                            .await?;
+                            other.commit().await?;
+                        }
+                        UpdateStateData::<MockTypes, MockMerkleTree, 8>::set_last_state_height(
+                            tx,
+                            height + 1,
+                        )
+                        .await
+                    }
+                    .boxed()
+                })
+                .await
+        }
+
+        // The conflict aborts the first attempt, and the retry succeeds.
+        let attempts = Arc::new(AtomicUsize::new(0));
+        increment(&storage, &attempts).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            storage
+                .read()
+                .await
+                .unwrap()
+                .get_last_state_height()
+                .await
+                .unwrap(),
+            2
+        );
+
+        // Without retries, the conflict is reported to the caller.
+        let storage = SqlStorage::connect(db.config().commit_retries(0))
+            .await
+            .unwrap();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let err = increment(&storage, &attempts).await.unwrap_err();
+        assert!(is_serialization_failure(&err), "{err:#}");
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            storage
+                .read()
+                .await
+                .unwrap()
+                .get_last_state_height()
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_background_indexes() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let index = BackgroundIndex::new("test_leaf_block_hash_idx", "ON leaf (block_hash)");
+        let cfg = db.config().background_index(index);
+        let storage = SqlStorage::connect(cfg).await.unwrap();
+        let num_indexes = default_background_indexes().len() + 1;
+
+        // The storage is usable immediately, and the indexes are eventually built.
+        loop {
+            let mut tx = storage.read().await.unwrap();
+            let (ready,) = query_as::<(i64,)>("SELECT count(*) FROM background_index WHERE ready")
+                .fetch_one(tx.as_mut())
+                .await
+                .unwrap();
+            if ready as usize == num_indexes {
+                break;
+            }
+            tracing::info!(ready, num_indexes, "waiting for background indexes");
+            sleep(Duration::from_millis(100)).await;
+        }
+        // The progress metric eventually reflects this.
+        let pending = storage
+            .metrics()
+            .get_subgroup(["sql"])
+            .unwrap()
+            .get_gauge("background_indexes_pending")
+            .unwrap();
+        while pending.get() > 0 {
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        // The custom index exists.
+        let mut tx = storage.read().await.unwrap();
+        #[cfg(not(feature = "embedded-db"))]
+        let sql = "SELECT count(*) FROM pg_indexes WHERE indexname = 'test_leaf_block_hash_idx'";
+        #[cfg(feature = "embedded-db")]
+        let sql = "SELECT count(*) FROM sqlite_master
+                    WHERE type = 'index' AND name = 'test_leaf_block_hash_idx'";
+        let (count,) = query_as::<(i64,)>(sql)
+            .fetch_one(tx.as_mut())
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_acquire_timeout() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let cfg = db
+            .config()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(100));
+        let storage = SqlStorage::connect(cfg).await.unwrap();
+        let metrics = storage.metrics().get_subgroup(["sql"]).unwrap();
+
+        // The pool configuration is reported alongside the usage metrics.
+        assert_eq!(metrics.get_gauge("max_connections").unwrap().get(), 1);
+        assert_eq!(metrics.get_gauge("min_connections").unwrap().get(), 0);
+        assert_eq!(metrics.get_gauge("acquire_timeout").unwrap().get(), 100);
+
+        // Wait for the background index task to release its connection.
+        while metrics
+            .get_gauge("background_indexes_pending")
+            .unwrap()
+            .get()
+            > 0
+        {
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        // While the only connection is in use, opening another transaction fails fast.
+        let tx = storage.read().await.unwrap();
+        let err = QueryError::from(storage.write().await.unwrap_err());
+        assert!(matches!(err, QueryError::PoolExhausted), "{err:#}");
+        assert_eq!(err.status(), tide_disco::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(metrics.get_gauge("pool_size").unwrap().get(), 1);
+        assert_eq!(metrics.get_gauge("idle_connections").unwrap().get(), 0);
+        assert_eq!(metrics.get_gauge("connection_waiters").unwrap().get(), 0);
+
+        // Once the connection is released, transactions can be opened again.
+        tx.commit().await.unwrap();
+        storage.read().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_vid_share_by_index() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let disperse = vid_scheme(4).disperse([]).unwrap();
+        let common = VidCommonQueryData::new(leaf.header().clone(), disperse.common);
+
+        let mut tx = storage.write().await.unwrap();
+        tx.insert_leaf(leaf.clone()).await.unwrap();
+        tx.insert_vid(common, Some(disperse.shares[0].clone()))
+            .await
+            .unwrap();
+        for index in [1, 3] {
+            UpdateAvailabilityStorage::<MockTypes>::insert_vid_share(
+                &mut tx,
+                0,
+                index,
+                disperse.shares[index].clone(),
+            )
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let mut tx = storage.read().await.unwrap();
+        for index in [1, 3] {
+            assert_eq!(
+                tx.get_vid_share(BlockId::<MockTypes>::Number(0), index)
+                    .await
+                    .unwrap(),
+                disperse.shares[index]
+            );
+            assert_eq!(
+                tx.get_vid_share(BlockId::Hash(leaf.block_hash()), index)
+                    .await
+                    .unwrap(),
+                disperse.shares[index]
+            );
+        }
+        // Shares which were not stored by index are missing, even if they are stored as this
+        // node's own share.
+        for index in [0, 2] {
+            let err = tx
+                .get_vid_share(BlockId::Number(0), index)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::Missing), "{err:#}");
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_pruned_height_storage() {
         setup_test();
@@ -1397,4 +2339,51 @@ mod test {
             );
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_leaf_range_by_time() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+        // Insert leaves with timestamps that go backwards once.
+        let timestamps = [10, 20, 18, 25, 30];
+        let mut leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let mut tx = storage.write().await.unwrap();
+        for (i, timestamp) in timestamps.into_iter().enumerate() {
+            leaf.leaf.block_header_mut().block_number = i as u64;
+            leaf.leaf.block_header_mut().timestamp = timestamp;
+            tx.insert_leaf(leaf.clone()).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let heights = |start, end| {
+            let storage = &storage;
+            async move {
+                storage
+                    .read()
+                    .await
+                    .unwrap()
+                    .get_leaf_range_by_time(start, end)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(height, leaf)| {
+                        assert_eq!(height, leaf.height());
+                        height
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(heights(0, 100).await, [0, 1, 2, 3, 4]);
+        assert_eq!(heights(18, 25).await, [1, 2]);
+        assert_eq!(heights(19, 26).await, [1, 3]);
+        assert_eq!(heights(30, 31).await, [4]);
+        assert_eq!(heights(31, 100).await, Vec::<u64>::new());
+    }
 }
@@ -16,8 +16,8 @@
 use super::{Database, Db, Query, QueryAs, Transaction};
 use crate::{
     availability::{
-        BlockId, BlockQueryData, LeafQueryData, PayloadQueryData, QueryablePayload,
-        VidCommonQueryData,
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafQueryData, PayloadQueryData,
+        QueryablePayload, RawPayloadQueryData, VidCommonQueryData,
     },
     data_source::storage::{PayloadMetadata, VidCommonMetadata},
     Header, Leaf, Payload, QueryError, QueryResult,
@@ -230,6 +230,63 @@ where
     }
 }
 
+const BLOCK_SUMMARY_COLUMNS: &str = "h.hash AS hash, h.data AS header_data, p.size AS payload_size, p.num_transactions AS num_transactions";
+
+impl<'r, Types> FromRow<'r, <Db as Database>::Row> for BlockSummaryQueryData<Types>
+where
+    Types: NodeType,
+{
+    fn from_row(row: &'r <Db as Database>::Row) -> sqlx::Result<Self> {
+        // The size and transaction count are computed when the payload is inserted, so if they are
+        // missing, we don't have the payload for this block yet.
+        let size: Option<i32> = row.try_get("payload_size")?;
+        let num_transactions: Option<i32> = row.try_get("num_transactions")?;
+        let (size, num_transactions) =
+            size.zip(num_transactions).ok_or(sqlx::Error::RowNotFound)?;
+
+        let header_data = row.try_get("header_data")?;
+        let header: Header<Types> =
+            serde_json::from_value(header_data).decode_error("malformed header")?;
+        let hash: String = row.try_get("hash")?;
+        let hash = hash.parse().decode_error("malformed block hash")?;
+
+        Ok(Self {
+            header,
+            hash,
+            size: size as u64,
+            num_transactions: num_transactions as u64,
+        })
+    }
+}
+
+const RAW_PAYLOAD_COLUMNS: &str =
+    "h.height AS height, h.hash AS hash, h.payload_hash AS payload_hash, p.data AS payload_data";
+
+impl<'r, Types> FromRow<'r, <Db as Database>::Row> for RawPayloadQueryData<Types>
+where
+    Types: NodeType,
+{
+    fn from_row(row: &'r <Db as Database>::Row) -> sqlx::Result<Self> {
+        // The payload data is stored exactly as encoded, so we can return it without decoding it
+        // or loading the header it depends on.
+        let data: Option<Vec<u8>> = row.try_get("payload_data")?;
+        let data = data.ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(Self {
+            height: row.try_get::<i64, _>("height")? as u64,
+            block_hash: row
+                .try_get::<String, _>("hash")?
+                .parse()
+                .decode_error("malformed block hash")?,
+            hash: row
+                .try_get::<String, _>("payload_hash")?
+                .parse()
+                .decode_error("malformed payload hash")?,
+            data,
+        })
+    }
+}
+
 const PAYLOAD_METADATA_COLUMNS: &str =
     "h.height AS height, h.hash AS hash, h.payload_hash AS payload_hash, p.size AS payload_size, p.num_transactions AS num_transactions";
 
@@ -319,14 +376,20 @@ where
     serde_json::from_value(data).decode_error("malformed header")
 }
 
+/// The SQLSTATE code Postgres uses when a statement is cancelled, e.g. by `statement_timeout`.
+const QUERY_CANCELED: &str = "57014";
+
 impl From<sqlx::Error> for QueryError {
     fn from(err: sqlx::Error) -> Self {
-        if matches!(err, sqlx::Error::RowNotFound) {
-            Self::NotFound
-        } else {
-            Self::Error {
-                message: err.to_string(),
+        match err {
+            sqlx::Error::RowNotFound => Self::NotFound,
+            sqlx::Error::PoolTimedOut => Self::PoolExhausted,
+            sqlx::Error::Database(err) if err.code().as_deref() == Some(QUERY_CANCELED) => {
+                Self::Timeout
             }
+            err => Self::Error {
+                message: err.to_string(),
+            },
         }
     }
 }
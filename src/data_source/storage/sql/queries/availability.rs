@@ -14,24 +14,27 @@
 
 use super::{
     super::transaction::{Transaction, TransactionMode},
-    QueryBuilder, BLOCK_COLUMNS, LEAF_COLUMNS, PAYLOAD_COLUMNS, PAYLOAD_METADATA_COLUMNS,
-    VID_COMMON_COLUMNS, VID_COMMON_METADATA_COLUMNS,
+    DecodeError, QueryBuilder, BLOCK_COLUMNS, BLOCK_SUMMARY_COLUMNS, LEAF_COLUMNS, PAYLOAD_COLUMNS,
+    PAYLOAD_METADATA_COLUMNS, RAW_PAYLOAD_COLUMNS, VID_COMMON_COLUMNS, VID_COMMON_METADATA_COLUMNS,
 };
 use crate::{
     availability::{
-        BlockId, BlockQueryData, LeafId, LeafQueryData, PayloadQueryData, QueryableHeader,
-        QueryablePayload, TransactionHash, TransactionQueryData, VidCommonQueryData,
+        BlockId, BlockQueryData, BlockSummaryQueryData, LeafId, LeafQueryData, PayloadByteRange,
+        PayloadQueryData, QueryableHeader, QueryablePayload, RawPayloadQueryData, TransactionHash,
+        TransactionQueryData, VidCommonQueryData,
+    },
+    data_source::storage::{
+        pruning::PrunedHeightStorage, AvailabilityStorage, PayloadMetadata, VidCommonMetadata,
     },
-    data_source::storage::{AvailabilityStorage, PayloadMetadata, VidCommonMetadata},
     types::HeightIndexed,
-    ErrorSnafu, Header, MissingSnafu, Payload, QueryError, QueryResult,
+    ErrorSnafu, Header, MissingSnafu, Payload, QueryError, QueryResult, VidShare,
 };
 use async_trait::async_trait;
 use futures::stream::{StreamExt, TryStreamExt};
-use hotshot_types::traits::node_implementation::NodeType;
+use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
 use snafu::OptionExt;
-use sqlx::FromRow;
-use std::ops::RangeBounds;
+use sqlx::{FromRow, Row};
+use std::ops::{Range, RangeBounds};
 
 #[async_trait]
 impl<Mode, Types> AvailabilityStorage<Types> for Transaction<Mode>
@@ -57,6 +60,17 @@ where
         Ok(leaf)
     }
 
+    async fn get_leaf_by_view(&mut self, view: u64) -> QueryResult<LeafQueryData<Types>> {
+        let mut query = QueryBuilder::default();
+        let sql = format!(
+            "SELECT {LEAF_COLUMNS} FROM leaf WHERE view_number = {} LIMIT 1",
+            query.bind(view as i64)?
+        );
+        let row = query.query(&sql).fetch_one(self.as_mut()).await?;
+        let leaf = LeafQueryData::from_row(&row)?;
+        Ok(leaf)
+    }
+
     async fn get_block(&mut self, id: BlockId<Types>) -> QueryResult<BlockQueryData<Types>> {
         let mut query = QueryBuilder::default();
         let where_clause = query.header_where_clause(id)?;
@@ -70,9 +84,15 @@ where
               ORDER BY h.height
               LIMIT 1"
         );
-        let row = query.query(&sql).fetch_one(self.as_mut()).await?;
-        let block = BlockQueryData::from_row(&row)?;
-        Ok(block)
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| BlockQueryData::from_row(&row));
+        match res {
+            Ok(block) => Ok(block),
+            Err(err) => Err(self.payload_pruned_or(id, err.into()).await),
+        }
     }
 
     async fn get_header(&mut self, id: BlockId<Types>) -> QueryResult<Header<Types>> {
@@ -92,9 +112,93 @@ where
               ORDER BY h.height
               LIMIT 1"
         );
-        let row = query.query(&sql).fetch_one(self.as_mut()).await?;
-        let payload = PayloadQueryData::from_row(&row)?;
-        Ok(payload)
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| PayloadQueryData::from_row(&row));
+        match res {
+            Ok(payload) => Ok(payload),
+            Err(err) => Err(self.payload_pruned_or(id, err.into()).await),
+        }
+    }
+
+    async fn get_raw_payload(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<RawPayloadQueryData<Types>> {
+        let mut query = QueryBuilder::default();
+        let where_clause = query.header_where_clause(id)?;
+        // ORDER BY h.height ASC ensures that if there are duplicate blocks (this can happen when
+        // selecting by payload ID, as payloads are not unique), we return the first one.
+        let sql = format!(
+            "SELECT {RAW_PAYLOAD_COLUMNS}
+              FROM header AS h
+              JOIN payload AS p ON h.height = p.height
+              WHERE {where_clause}
+              ORDER BY h.height
+              LIMIT 1"
+        );
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| RawPayloadQueryData::from_row(&row));
+        match res {
+            Ok(payload) => Ok(payload),
+            Err(err) => Err(self.payload_pruned_or(id, err.into()).await),
+        }
+    }
+
+    async fn get_payload_bytes(
+        &mut self,
+        id: BlockId<Types>,
+        range: Range<usize>,
+    ) -> QueryResult<PayloadByteRange<Types>> {
+        let mut query = QueryBuilder::default();
+        let where_clause = query.header_where_clause(id)?;
+        // Slice the payload in the database, so that only the requested bytes are loaded. SQL
+        // string offsets are 1-based and limited to 32-bit integers, which is more than the length
+        // of any payload.
+        let offset = query.bind(i32::try_from(range.start).unwrap_or(i32::MAX - 1) + 1)?;
+        let count =
+            query.bind(i32::try_from(range.end.saturating_sub(range.start)).unwrap_or(i32::MAX))?;
+        let sql = format!(
+            "SELECT h.height AS height, h.hash AS hash, h.payload_hash AS payload_hash,
+                    CAST(length(p.data) AS BIGINT) AS total_len, substr(p.data, {offset}, {count}) AS data
+              FROM header AS h
+              JOIN payload AS p ON h.height = p.height
+              WHERE {where_clause}
+              ORDER BY h.height
+              LIMIT 1"
+        );
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| {
+                // A missing payload has no data, and so no length.
+                let total_len: Option<i64> = row.try_get("total_len")?;
+                let total_len = total_len.ok_or(sqlx::Error::RowNotFound)? as usize;
+                Ok(PayloadByteRange {
+                    height: row.try_get::<i64, _>("height")? as u64,
+                    block_hash: row
+                        .try_get::<String, _>("hash")?
+                        .parse()
+                        .decode_error("malformed block hash")?,
+                    hash: row
+                        .try_get::<String, _>("payload_hash")?
+                        .parse()
+                        .decode_error("malformed payload hash")?,
+                    start: range.start.min(total_len),
+                    total_len,
+                    data: row.try_get("data")?,
+                })
+            });
+        match res {
+            Ok(bytes) => Ok(bytes),
+            Err(err) => Err(self.payload_pruned_or(id, err.into()).await),
+        }
     }
 
     async fn get_payload_metadata(
@@ -122,6 +226,33 @@ where
         Ok(payload)
     }
 
+    async fn get_block_summary(
+        &mut self,
+        id: BlockId<Types>,
+    ) -> QueryResult<BlockSummaryQueryData<Types>> {
+        let mut query = QueryBuilder::default();
+        let where_clause = query.header_where_clause(id)?;
+        // The payload size and transaction count are stored alongside the payload, so we can
+        // answer this query without loading or decoding the payload itself.
+        let sql = format!(
+            "SELECT {BLOCK_SUMMARY_COLUMNS}
+              FROM header AS h
+              JOIN payload AS p ON h.height = p.height
+              WHERE {where_clause}
+              ORDER BY h.height
+              LIMIT 1"
+        );
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| BlockSummaryQueryData::from_row(&row));
+        match res {
+            Ok(summary) => Ok(summary),
+            Err(err) => Err(self.payload_pruned_or(id, err.into()).await),
+        }
+    }
+
     async fn get_vid_common(
         &mut self,
         id: BlockId<Types>,
@@ -138,9 +269,42 @@ where
               ORDER BY h.height
               LIMIT 1"
         );
-        let row = query.query(&sql).fetch_one(self.as_mut()).await?;
-        let common = VidCommonQueryData::from_row(&row)?;
-        Ok(common)
+        let res = query
+            .query(&sql)
+            .fetch_one(self.as_mut())
+            .await
+            .and_then(|row| VidCommonQueryData::from_row(&row));
+        match res {
+            Ok(common) => Ok(common),
+            Err(err) => Err(self.vid_pruned_or(id, err.into()).await),
+        }
+    }
+
+    async fn get_vid_share(&mut self, id: BlockId<Types>, index: usize) -> QueryResult<VidShare> {
+        let mut query = QueryBuilder::default();
+        let where_clause = query.header_where_clause(id)?;
+        let index = query.bind(index as i64)?;
+        // ORDER BY h.height ASC ensures that if there are duplicate blocks (this can happen when
+        // selecting by payload ID, as payloads are not unique), we return the first one.
+        let sql = format!(
+            "SELECT s.data
+              FROM header AS h
+              JOIN vid_share AS s ON h.height = s.height
+              WHERE {where_clause} AND s.node_index = {index}
+              ORDER BY h.height
+              LIMIT 1"
+        );
+        let res = query
+            .query_as::<(Vec<u8>,)>(&sql)
+            .fetch_optional(self.as_mut())
+            .await;
+        match res {
+            Ok(Some((data,))) => {
+                Ok(bincode::deserialize(&data).decode_error("malformed VID share")?)
+            }
+            Ok(None) => Err(self.vid_pruned_or(id, QueryError::Missing).await),
+            Err(err) => Err(self.vid_pruned_or(id, err.into()).await),
+        }
     }
 
     async fn get_vid_common_metadata(
@@ -164,6 +328,37 @@ where
         Ok(common)
     }
 
+    async fn get_leaf_range_by_time(
+        &mut self,
+        start: u64,
+        end: u64,
+    ) -> QueryResult<Vec<(u64, LeafQueryData<Types>)>> {
+        // Filter using the index on header timestamp. Since timestamps are not strictly monotonic,
+        // the matching heights need not be contiguous, so we can't just find the endpoints and load
+        // the range of heights in between.
+        let mut query = QueryBuilder::default();
+        let start = query.bind(start as i64)?;
+        let end = query.bind(end as i64)?;
+        let sql = format!(
+            "SELECT h.height, {LEAF_COLUMNS}
+              FROM header AS h
+              JOIN leaf AS l ON h.height = l.height
+              WHERE h.timestamp >= {start} AND h.timestamp < {end}
+              ORDER BY h.height"
+        );
+        query
+            .query(&sql)
+            .fetch(self.as_mut())
+            .map(|res| -> sqlx::Result<_> {
+                let row = res?;
+                let height: i64 = row.try_get("height")?;
+                Ok((height as u64, LeafQueryData::from_row(&row)?))
+            })
+            .map_err(QueryError::from)
+            .try_collect()
+            .await
+    }
+
     async fn get_leaf_range<R>(
         &mut self,
         range: R,
@@ -258,6 +453,31 @@ where
             .await)
     }
 
+    async fn get_block_summary_range<R>(
+        &mut self,
+        range: R,
+    ) -> QueryResult<Vec<QueryResult<BlockSummaryQueryData<Types>>>>
+    where
+        R: RangeBounds<usize> + Send + 'static,
+    {
+        let mut query = QueryBuilder::default();
+        let where_clause = query.bounds_to_where_clause(range, "h.height")?;
+        let sql = format!(
+            "SELECT {BLOCK_SUMMARY_COLUMNS}
+              FROM header AS h
+              JOIN payload AS p ON h.height = p.height
+              {where_clause}
+              ORDER BY h.height"
+        );
+        Ok(query
+            .query(&sql)
+            .fetch(self.as_mut())
+            .map(|res| BlockSummaryQueryData::from_row(&res?))
+            .map_err(QueryError::from)
+            .collect()
+            .await)
+    }
+
     async fn get_vid_common_range<R>(
         &mut self,
         range: R,
@@ -339,3 +559,61 @@ where
         })
     }
 }
+
+impl<Mode> Transaction<Mode>
+where
+    Mode: TransactionMode,
+{
+    /// Check whether payload data which could not be loaded was intentionally pruned or deleted.
+    ///
+    /// If `err` indicates that the payload data for block `id` is not in the database, and the block
+    /// was deleted on demand, this returns [`QueryError::Deleted`]. If instead the block is at or
+    /// below the payload pruned height, this returns [`QueryError::PayloadPruned`]. Otherwise `err`
+    /// is returned unchanged.
+    async fn payload_pruned_or<Types>(&mut self, id: BlockId<Types>, err: QueryError) -> QueryError
+    where
+        Types: NodeType,
+    {
+        let pruned_height = self.load_payload_pruned_height().await;
+        self.pruned_or(id, err, pruned_height, QueryError::PayloadPruned)
+            .await
+    }
+
+    /// Check whether VID data which could not be loaded was intentionally pruned or deleted.
+    ///
+    /// This is the same as [`payload_pruned_or`](Self::payload_pruned_or), but checks against the
+    /// VID pruned height, and reports pruned data as [`QueryError::VidPruned`].
+    async fn vid_pruned_or<Types>(&mut self, id: BlockId<Types>, err: QueryError) -> QueryError
+    where
+        Types: NodeType,
+    {
+        let pruned_height = self.load_vid_pruned_height().await;
+        self.pruned_or(id, err, pruned_height, QueryError::VidPruned)
+            .await
+    }
+
+    async fn pruned_or<Types>(
+        &mut self,
+        id: BlockId<Types>,
+        err: QueryError,
+        pruned_height: anyhow::Result<Option<u64>>,
+        pruned: QueryError,
+    ) -> QueryError
+    where
+        Types: NodeType,
+    {
+        if !matches!(err, QueryError::NotFound | QueryError::Missing) {
+            return err;
+        }
+        let Ok(header) = self.load_header::<Types>(id).await else {
+            return err;
+        };
+        if let Ok(true) = self.is_deleted(header.block_number()).await {
+            return QueryError::Deleted;
+        }
+        match pruned_height {
+            Ok(Some(pruned_height)) if header.block_number() <= pruned_height => pruned,
+            _ => err,
+        }
+    }
+}
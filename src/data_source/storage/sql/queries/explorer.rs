@@ -13,23 +13,24 @@
 //! Explorer storage implementation for a database query engine.
 
 use super::{
-    super::transaction::{query, Transaction, TransactionMode},
+    super::transaction::{query, query_as, Transaction, TransactionMode},
     Database, Db, DecodeError, QueryBuilder, BLOCK_COLUMNS,
 };
 use crate::{
-    availability::{BlockQueryData, QueryableHeader, QueryablePayload, TransactionIndex},
+    availability::{BlockQueryData, LeafHash, QueryableHeader, QueryablePayload, TransactionIndex},
     data_source::storage::{ExplorerStorage, NodeStorage},
     explorer::{
         self,
         errors::{self, NotFound},
         query_data::TransactionDetailResponse,
         traits::ExplorerHeader,
-        BalanceAmount, BlockDetail, BlockIdentifier, BlockRange, BlockSummary, ExplorerHistograms,
-        ExplorerSummary, GenesisOverview, GetBlockDetailError, GetBlockSummariesError,
+        BalanceAmount, BlockDetail, BlockIdentifier, BlockRange, BlockStatistics, BlockSummary,
+        ExplorerHistograms, ExplorerSummary, GenesisOverview, GetBlockDetailError,
+        GetBlockStatisticsError, GetBlockStatisticsRequest, GetBlockSummariesError,
         GetBlockSummariesRequest, GetExplorerSummaryError, GetSearchResultsError,
         GetTransactionDetailError, GetTransactionSummariesError, GetTransactionSummariesRequest,
-        MonetaryValue, SearchResult, TransactionIdentifier, TransactionRange, TransactionSummary,
-        TransactionSummaryFilter,
+        MetricStatistics, MonetaryValue, SearchMatch, SearchQuery, SearchResult,
+        TransactionIdentifier, TransactionRange, TransactionSummary, TransactionSummaryFilter,
     },
     Header, Payload, QueryError, QueryResult, Transaction as HotshotTransaction,
 };
@@ -72,12 +73,42 @@ impl From<sqlx::Error> for GetBlockSummariesError {
     }
 }
 
+impl From<sqlx::Error> for GetBlockStatisticsError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::from(QueryError::from(err))
+    }
+}
+
 impl From<sqlx::Error> for GetSearchResultsError {
     fn from(err: sqlx::Error) -> Self {
         Self::from(QueryError::from(err))
     }
 }
 
+/// A common table expression selecting the metrics of the latest `$1` blocks.
+///
+/// The block time of each block is the difference between its timestamp and that of its
+/// predecessor, so the window function runs over one more header than the window itself. The
+/// outer `LIMIT` then drops the oldest block, whose block time is unknown. `$1` is the window size
+/// and `$2` is one more than the window size.
+const BLOCK_STATISTICS_WINDOW: &str = "WITH blocks AS (
+    SELECT * FROM (
+        SELECT
+            h.height AS height,
+            h.timestamp - lag(h.timestamp) OVER (ORDER BY h.height) AS time,
+            p.size AS size,
+            p.num_transactions AS transactions
+        FROM header AS h
+        JOIN payload AS p ON p.height = h.height
+        WHERE h.height IN (SELECT height FROM header ORDER BY height DESC LIMIT $2)
+    ) AS b
+    ORDER BY height DESC
+    LIMIT $1
+)";
+
+/// The columns of [`BLOCK_STATISTICS_WINDOW`] for which statistics are computed.
+const BLOCK_STATISTICS_COLUMNS: [&str; 3] = ["time", "size", "transactions"];
+
 impl<'r, Types> FromRow<'r, <Db as Database>::Row> for BlockSummary<Types>
 where
     Types: NodeType,
@@ -500,77 +531,282 @@ where
         })
     }
 
+    async fn get_block_statistics(
+        &mut self,
+        request: GetBlockStatisticsRequest,
+    ) -> Result<BlockStatistics, GetBlockStatisticsError> {
+        let window = request.window.get() as i64;
+
+        // Counts, means and maximums are computed with a single aggregate query over the window.
+        let aggregates = BLOCK_STATISTICS_COLUMNS
+            .iter()
+            .map(|col| {
+                format!(
+                    "count({col}), CAST(avg({col}) AS DOUBLE PRECISION), CAST(max({col}) AS BIGINT)"
+                )
+            })
+            .join(", ");
+        let sql = format!("{BLOCK_STATISTICS_WINDOW} SELECT count(*), {aggregates} FROM blocks");
+        let row = query(&sql)
+            .bind(window)
+            .bind(window + 1)
+            .fetch_one(self.as_mut())
+            .await?;
+        let num_blocks: i64 = row.try_get(0)?;
+
+        let mut metrics = [MetricStatistics::default(); 3];
+        for (i, (col, metric)) in BLOCK_STATISTICS_COLUMNS
+            .iter()
+            .zip(&mut metrics)
+            .enumerate()
+        {
+            let count: i64 = row.try_get(3 * i + 1)?;
+            let mean: Option<f64> = row.try_get(3 * i + 2)?;
+            let max: Option<i64> = row.try_get(3 * i + 3)?;
+            *metric = MetricStatistics {
+                mean,
+                median: self.block_statistics_median(col, window, count).await?,
+                max: max.map(|max| max as u64),
+            };
+        }
+        let [block_time, block_size, block_transactions] = metrics;
+
+        Ok(BlockStatistics {
+            num_blocks: num_blocks as u64,
+            block_time,
+            block_size,
+            block_transactions,
+        })
+    }
+
     async fn get_search_results(
         &mut self,
-        search_query: TaggedBase64,
+        search_query: SearchQuery,
     ) -> Result<SearchResult<Types>, GetSearchResultsError> {
-        let search_tag = search_query.tag();
         let header_tag = Commitment::<Header<Types>>::tag();
+        let leaf_tag = LeafHash::<Types>::tag();
         let tx_tag = Commitment::<HotshotTransaction<Types>>::tag();
 
-        if search_tag != header_tag && search_tag != tx_tag {
-            return Err(GetSearchResultsError::InvalidQuery(errors::BadQuery {}));
+        let mut result = SearchResult {
+            blocks: Vec::new(),
+            leaves: Vec::new(),
+            transactions: Vec::new(),
+        };
+        match search_query {
+            SearchQuery::Height(height) => {
+                let sql = format!(
+                    "SELECT {BLOCK_COLUMNS}
+                        FROM header AS h
+                        JOIN payload AS p ON h.height = p.height
+                        WHERE h.height = $1"
+                );
+                result.blocks = query(&sql)
+                    .bind(height as i64)
+                    .fetch(self.as_mut())
+                    .map(|row| BlockSummary::from_row(&row?))
+                    .try_collect()
+                    .await?;
+            }
+            SearchQuery::Tagged(hash) => {
+                let tag = hash.tag();
+                let hash = hash.to_string();
+                if tag == header_tag {
+                    result.blocks = self.search_blocks("h.hash", &hash).await?;
+                } else if tag == leaf_tag {
+                    result.leaves = self.search_blocks("l.hash", &hash).await?;
+                } else if tag == tx_tag {
+                    result.transactions = self.search_transactions(&hash).await?;
+                } else {
+                    return Err(GetSearchResultsError::InvalidQuery(errors::BadQuery {}));
+                }
+            }
+            SearchQuery::Hash(bytes) => {
+                // An untagged hash could be any kind of hash, so try each in turn, interpreting the
+                // bytes with the appropriate tag.
+                let tagged = |tag: &str| {
+                    TaggedBase64::new(tag, &bytes)
+                        .map(|hash| hash.to_string())
+                        .map_err(|_| GetSearchResultsError::InvalidQuery(errors::BadQuery {}))
+                };
+                result.blocks = self.search_blocks("h.hash", &tagged(&header_tag)?).await?;
+                result.leaves = self.search_blocks("l.hash", &tagged(&leaf_tag)?).await?;
+                result.transactions = self.search_transactions(&tagged(&tx_tag)?).await?;
+            }
         }
 
-        let search_query_string = search_query.to_string();
-        if search_tag == header_tag {
-            let block_query = format!(
-                "SELECT {BLOCK_COLUMNS}
-                    FROM header AS h
-                    JOIN payload AS p ON h.height = p.height
-                    WHERE h.hash = $1
-                    ORDER BY h.height DESC
-                    LIMIT 1"
-            );
-            let row = query(block_query.as_str())
-                .bind(&search_query_string)
-                .fetch_one(self.as_mut())
-                .await?;
+        Ok(result)
+    }
 
-            let block = BlockSummary::from_row(&row)?;
+    async fn get_search_match(
+        &mut self,
+        search_query: SearchQuery,
+    ) -> Result<SearchMatch<Types>, GetSearchResultsError> {
+        let header_tag = Commitment::<Header<Types>>::tag();
+        let leaf_tag = LeafHash::<Types>::tag();
+        let tx_tag = Commitment::<HotshotTransaction<Types>>::tag();
 
-            Ok(SearchResult {
-                blocks: vec![block],
-                transactions: Vec::new(),
-            })
-        } else {
-            let transactions_query = format!(
-                "SELECT {BLOCK_COLUMNS}
-                    FROM header AS h
-                    JOIN payload AS p ON h.height = p.height
-                    JOIN transactions AS t ON h.height = t.block_height
-                    WHERE t.hash = $1
-                    ORDER BY h.height DESC
-                    LIMIT 5"
-            );
-            let transactions_query_rows = query(transactions_query.as_str())
-                .bind(&search_query_string)
-                .fetch(self.as_mut());
-            let transactions_query_result: Vec<TransactionSummary<Types>> = transactions_query_rows
-                .map(|row| -> Result<Vec<TransactionSummary<Types>>, QueryError>{
-                    let block = BlockQueryData::<Types>::from_row(&row?)?;
-                    let transactions = block
-                        .enumerate()
-                        .enumerate()
-                        .filter(|(_, (_, txn))| txn.commit().to_string() == search_query_string)
-                        .map(|(offset, (_, txn))| {
-                            Ok(TransactionSummary::try_from((
-                                &block, offset, txn,
-                            ))?)
-                        })
-                        .try_collect::<TransactionSummary<Types>, Vec<TransactionSummary<Types>>, QueryError>()?;
-                    Ok(transactions)
-                })
-                .try_collect::<Vec<Vec<TransactionSummary<Types>>>>()
-                .await?
+        // The hashes the query could be, each with the tag identifying its kind, in the order in
+        // which to look them up.
+        let candidates = match search_query {
+            SearchQuery::Height(height) => {
+                // A height can only identify a block, so there is exactly one lookup to do.
+                let sql = format!(
+                    "SELECT {BLOCK_COLUMNS}
+                        FROM header AS h
+                        JOIN payload AS p ON h.height = p.height
+                        WHERE h.height = $1"
+                );
+                let block = query(&sql)
+                    .bind(height as i64)
+                    .fetch_optional(self.as_mut())
+                    .await?;
+                return match block {
+                    Some(row) => Ok(SearchMatch::Block(BlockSummary::from_row(&row)?)),
+                    None => Ok(SearchMatch::NotFound),
+                };
+            }
+            SearchQuery::Tagged(hash) => vec![(hash.tag(), hash.to_string())],
+            SearchQuery::Hash(bytes) => [&header_tag, &leaf_tag, &tx_tag]
                 .into_iter()
-                .flatten()
-                .collect();
+                .map(|tag| {
+                    let hash = TaggedBase64::new(tag, &bytes)
+                        .map_err(|_| GetSearchResultsError::InvalidQuery(errors::BadQuery {}))?;
+                    Ok((tag.clone(), hash.to_string()))
+                })
+                .collect::<Result<_, GetSearchResultsError>>()?,
+        };
 
-            Ok(SearchResult {
-                blocks: Vec::new(),
-                transactions: transactions_query_result,
-            })
+        for (tag, hash) in candidates {
+            if tag == header_tag {
+                if let Some(block) = self.search_blocks("h.hash", &hash).await?.pop() {
+                    return Ok(SearchMatch::Block(block));
+                }
+            } else if tag == leaf_tag {
+                if let Some(block) = self.search_blocks("l.hash", &hash).await?.pop() {
+                    return Ok(SearchMatch::Leaf(block));
+                }
+            } else if tag == tx_tag {
+                let mut transactions = self.search_transactions(&hash).await?;
+                match transactions.len() {
+                    0 => {}
+                    1 => return Ok(SearchMatch::Transaction(transactions.remove(0))),
+                    _ => return Ok(SearchMatch::Ambiguous(transactions)),
+                }
+            } else {
+                return Err(GetSearchResultsError::InvalidQuery(errors::BadQuery {}));
+            }
         }
+
+        Ok(SearchMatch::NotFound)
+    }
+}
+
+impl<Mode: TransactionMode> Transaction<Mode> {
+    /// Summaries of the blocks where `column` is equal to `hash`.
+    ///
+    /// `column` may refer to the header as `h` or the leaf as `l`.
+    async fn search_blocks<Types>(
+        &mut self,
+        column: &str,
+        hash: &str,
+    ) -> Result<Vec<BlockSummary<Types>>, GetSearchResultsError>
+    where
+        Types: NodeType,
+        Header<Types>: QueryableHeader<Types> + ExplorerHeader<Types>,
+        Payload<Types>: QueryablePayload<Types>,
+    {
+        let sql = format!(
+            "SELECT {BLOCK_COLUMNS}
+                FROM header AS h
+                JOIN payload AS p ON h.height = p.height
+                JOIN leaf AS l ON h.height = l.height
+                WHERE {column} = $1
+                ORDER BY h.height DESC
+                LIMIT 1"
+        );
+        Ok(query(&sql)
+            .bind(hash)
+            .fetch(self.as_mut())
+            .map(|row| BlockSummary::from_row(&row?))
+            .try_collect()
+            .await?)
+    }
+
+    /// The median of the non-null values of `column` in the latest `window` blocks.
+    ///
+    /// `count` is the number of non-null values, as computed by the aggregate query. SQLite has no
+    /// median aggregate, so we instead sort the values and fetch the middle one or two.
+    async fn block_statistics_median(
+        &mut self,
+        column: &str,
+        window: i64,
+        count: i64,
+    ) -> sqlx::Result<Option<f64>> {
+        if count == 0 {
+            return Ok(None);
+        }
+        let sql = format!(
+            "{BLOCK_STATISTICS_WINDOW}
+                SELECT CAST({column} AS BIGINT) FROM blocks
+                WHERE {column} IS NOT NULL
+                ORDER BY {column}
+                LIMIT $3 OFFSET $4"
+        );
+        let middle = query_as::<(i64,)>(&sql)
+            .bind(window)
+            .bind(window + 1)
+            .bind(2 - count % 2)
+            .bind((count - 1) / 2)
+            .fetch_all(self.as_mut())
+            .await?;
+        if middle.is_empty() {
+            return Ok(None);
+        }
+        let sum: f64 = middle.iter().map(|(value,)| *value as f64).sum();
+        Ok(Some(sum / middle.len() as f64))
+    }
+
+    /// Summaries of the transactions with the given hash.
+    async fn search_transactions<Types>(
+        &mut self,
+        hash: &str,
+    ) -> Result<Vec<TransactionSummary<Types>>, GetSearchResultsError>
+    where
+        Types: NodeType,
+        Header<Types>: QueryableHeader<Types> + ExplorerHeader<Types>,
+        Payload<Types>: QueryablePayload<Types>,
+        HotshotTransaction<Types>: explorer::traits::ExplorerTransaction,
+    {
+        let transactions_query = format!(
+            "SELECT {BLOCK_COLUMNS}
+                FROM header AS h
+                JOIN payload AS p ON h.height = p.height
+                JOIN transactions AS t ON h.height = t.block_height
+                WHERE t.hash = $1
+                ORDER BY h.height DESC
+                LIMIT 5"
+        );
+        let transactions_query_rows = query(transactions_query.as_str())
+            .bind(hash)
+            .fetch(self.as_mut());
+        let transactions = transactions_query_rows
+            .map(|row| -> Result<Vec<TransactionSummary<Types>>, QueryError> {
+                let block = BlockQueryData::<Types>::from_row(&row?)?;
+                let transactions = block
+                    .enumerate()
+                    .enumerate()
+                    .filter(|(_, (_, txn))| txn.commit().to_string() == hash)
+                    .map(|(offset, (_, txn))| {
+                        Ok(TransactionSummary::try_from((&block, offset, txn))?)
+                    })
+                    .try_collect::<TransactionSummary<Types>, Vec<TransactionSummary<Types>>, QueryError>()?;
+                Ok(transactions)
+            })
+            .try_collect::<Vec<Vec<TransactionSummary<Types>>>>()
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(transactions)
     }
 }
@@ -18,9 +18,10 @@ use super::{
 };
 use crate::{
     data_source::storage::{
-        Aggregate, AggregatesStorage, NodeStorage, PayloadMetadata, UpdateAggregatesStorage,
+        pruning::PrunedHeightStorage, Aggregate, AggregatesStorage, NodeStorage, PayloadMetadata,
+        UpdateAggregatesStorage,
     },
-    node::{BlockId, SyncStatus, TimeWindowQueryData, WindowStart},
+    node::{BlockId, Gaps, SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
     types::HeightIndexed,
     Header, MissingSnafu, NotFoundSnafu, QueryError, QueryResult, VidShare,
 };
@@ -30,7 +31,10 @@ use futures::stream::{StreamExt, TryStreamExt};
 use hotshot_types::traits::{block_contents::BlockHeader, node_implementation::NodeType};
 use snafu::OptionExt;
 use sqlx::Row;
-use std::ops::{Bound, RangeBounds};
+use std::{
+    cmp::max,
+    ops::{Bound, Range, RangeBounds},
+};
 
 #[async_trait]
 impl<Mode, Types> NodeStorage<Types> for Transaction<Mode>
@@ -154,8 +158,11 @@ where
         // missing in that case _or_ if the row is present but share data is NULL. Thus, we also
         // need to select the total number of VID rows and the number of present VID rows with a
         // NULL share.
-        let sql = "SELECT l.max_height, l.total_leaves, p.null_payloads, v.total_vid, vn.null_vid, pruned_height FROM
-                (SELECT max(leaf.height) AS max_height, count(*) AS total_leaves FROM leaf) AS l,
+        //
+        // The `IS NULL` counts are served by partial indexes which are built in the background (see
+        // `default_background_indexes`). Until those are ready, these fall back to table scans.
+        let sql = "SELECT l.min_height, l.max_height, l.total_leaves, p.null_payloads, v.total_vid, vn.null_vid, pruned_height FROM
+                (SELECT min(leaf.height) AS min_height, max(leaf.height) AS max_height, count(*) AS total_leaves FROM leaf) AS l,
                 (SELECT count(*) AS null_payloads FROM payload WHERE data IS NULL) AS p,
                 (SELECT count(*) AS total_vid FROM vid) AS v,
                 (SELECT count(*) AS null_vid FROM vid WHERE share IS NULL) AS vn,
@@ -184,6 +191,7 @@ where
         let pruned_height = row
             .get::<Option<i64>, _>("pruned_height")
             .map(|h| h as usize);
+        let lowest_stored_height = row.get::<Option<i64>, _>("min_height").map(|h| h as usize);
 
         let missing_leaves = block_height.saturating_sub(total_leaves);
         let missing_blocks = missing_leaves + null_payloads;
@@ -196,6 +204,58 @@ where
             missing_vid_common,
             missing_vid_shares,
             pruned_height,
+            lowest_stored_height,
+            ..Default::default()
+        })
+    }
+
+    async fn sync_gaps(&mut self, max_gaps: usize) -> QueryResult<SyncGaps> {
+        let block_height = NodeStorage::<Types>::block_height(self).await?;
+
+        // Heights at or below the relevant pruned height are expected to be missing, so we only
+        // look for gaps above them.
+        let above = |height: Option<u64>| height.map_or(0, |h| h as usize + 1);
+        let leaf_start = above(self.load_pruned_height().await.map_err(pruned_height_err)?);
+        let block_start = max(
+            leaf_start,
+            above(
+                self.load_payload_pruned_height()
+                    .await
+                    .map_err(pruned_height_err)?,
+            ),
+        );
+        let vid_start = max(
+            leaf_start,
+            above(
+                self.load_vid_pruned_height()
+                    .await
+                    .map_err(pruned_height_err)?,
+            ),
+        );
+
+        // A leaf or VID common is missing exactly when its row is missing. A block is also missing
+        // if its row is present with `NULL` data. Blocks which were deleted on demand are not gaps,
+        // since they will never be filled.
+        Ok(SyncGaps {
+            leaves: self
+                .gaps("leaf", "TRUE", leaf_start..block_height, max_gaps)
+                .await?,
+            blocks: self
+                .gaps(
+                    "payload",
+                    "(data IS NOT NULL OR height IN (SELECT height FROM deleted_block))",
+                    block_start..block_height,
+                    max_gaps,
+                )
+                .await?,
+            vid: self
+                .gaps(
+                    "(SELECT height FROM vid UNION ALL SELECT height FROM deleted_block) AS v",
+                    "TRUE",
+                    vid_start..block_height,
+                    max_gaps,
+                )
+                .await?,
         })
     }
 
@@ -358,6 +418,72 @@ impl<Types: NodeType> UpdateAggregatesStorage<Types> for Transaction<Write> {
 }
 
 impl<Mode: TransactionMode> Transaction<Mode> {
+    /// Find up to `max_gaps` ranges of heights within `range` which have no row in `table`
+    /// matching `filter`.
+    ///
+    /// `table` may be any table expression with a `height` column, such as an aliased subquery.
+    async fn gaps(
+        &mut self,
+        table: &str,
+        filter: &str,
+        range: Range<usize>,
+        max_gaps: usize,
+    ) -> QueryResult<Gaps> {
+        let mut gaps = Gaps::default();
+        if range.is_empty() {
+            return Ok(gaps);
+        }
+
+        // The first and last present heights tell us if there are gaps at either end of the range.
+        let sql = format!(
+            "SELECT min(height), max(height) FROM {table}
+              WHERE {filter} AND height >= $1 AND height < $2"
+        );
+        let (first, last) = query_as::<(Option<i64>, Option<i64>)>(&sql)
+            .bind(range.start as i64)
+            .bind(range.end as i64)
+            .fetch_one(self.as_mut())
+            .await?;
+        let (Some(first), Some(last)) = (first, last) else {
+            // Nothing in the range is present.
+            gaps.push(range, max_gaps);
+            return Ok(gaps);
+        };
+        let (first, last) = (first as usize, last as usize);
+        if first > range.start && !gaps.push(range.start..first, max_gaps) {
+            return Ok(gaps);
+        }
+
+        // Every other gap starts just after a present height whose successor is not present. We
+        // only need to find one more gap than we can return to know whether there are more.
+        let sql = format!(
+            "SELECT height + 1, next_height FROM (
+                SELECT height, LEAD(height) OVER (ORDER BY height) AS next_height
+                  FROM {table}
+                 WHERE {filter} AND height >= $1 AND height < $2
+             ) AS t
+             WHERE next_height > height + 1
+             ORDER BY height
+             LIMIT $3"
+        );
+        let interior = query_as::<(i64, i64)>(&sql)
+            .bind(range.start as i64)
+            .bind(range.end as i64)
+            .bind((max_gaps + 1) as i64)
+            .fetch_all(self.as_mut())
+            .await?;
+        for (start, end) in interior {
+            if !gaps.push(start as usize..end as usize, max_gaps) {
+                return Ok(gaps);
+            }
+        }
+
+        if last + 1 < range.end {
+            gaps.push(last + 1..range.end, max_gaps);
+        }
+        Ok(gaps)
+    }
+
     async fn time_window<Types: NodeType>(
         &mut self,
         start: u64,
@@ -478,5 +604,16 @@ async fn aggregate_range_bounds(
             height - 1
         }
     };
+    if from > to {
+        // An empty range contains no blocks. Don't look up the aggregates at `from`, which may not
+        // exist yet.
+        return Ok(None);
+    }
     Ok(Some((from, to)))
 }
+
+fn pruned_height_err(err: anyhow::Error) -> QueryError {
+    QueryError::Error {
+        message: format!("failed to load pruned height: {err:#}"),
+    }
+}
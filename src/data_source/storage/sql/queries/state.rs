@@ -19,8 +19,10 @@ use super::{
 use crate::data_source::storage::sql::build_where_in;
 use crate::data_source::storage::sql::sqlx::Row;
 use crate::{
-    data_source::storage::{MerklizedStateHeightStorage, MerklizedStateStorage},
-    merklized_state::{MerklizedState, Snapshot},
+    data_source::storage::{
+        MerklizedStateHeightStorage, MerklizedStateStorage, PrunedHeightStorage,
+    },
+    merklized_state::{MerklizedState, Snapshot, StateDiff},
     QueryError, QueryResult,
 };
 use ark_serialize::CanonicalDeserialize;
@@ -33,7 +35,7 @@ use jf_merkle_tree::{
 };
 use sqlx::types::BitVec;
 use sqlx::types::JsonValue;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
 #[async_trait]
@@ -50,24 +52,153 @@ where
         snapshot: Snapshot<Types, State, ARITY>,
         key: State::Key,
     ) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>> {
+        let (created, merkle_commitment) = self.snapshot_info(snapshot).await?;
+        let mut paths = self
+            .get_paths_at::<Types, State, ARITY>(created, merkle_commitment, &[key])
+            .await?;
+        Ok(paths.remove(0))
+    }
+
+    /// Retreives Merkle paths for several keys from the database.
+    ///
+    /// The snapshot is resolved only once, so all of the paths are anchored to the same root, and
+    /// nodes shared by several of the paths are only loaded once.
+    async fn get_path_batch(
+        &mut self,
+        snapshot: Snapshot<Types, State, ARITY>,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>> {
+        let (created, merkle_commitment) = self.snapshot_info(snapshot).await?;
+        self.get_paths_at::<Types, State, ARITY>(created, merkle_commitment, keys)
+            .await
+    }
+
+    async fn snapshot_for_height(
+        &mut self,
+        height: u64,
+    ) -> QueryResult<Snapshot<Types, State, ARITY>> {
+        let (_, commit) = self
+            .snapshot_info::<Types, State, ARITY>(Snapshot::Index(height))
+            .await?;
+        Ok(Snapshot::Commit(commit))
+    }
+
+    async fn get_diff(
+        &mut self,
+        from: Snapshot<Types, State, ARITY>,
+        to: Snapshot<Types, State, ARITY>,
+        offset: usize,
+        limit: usize,
+    ) -> QueryResult<StateDiff<State::Key, State::Entry>> {
+        let (from, _) = self.snapshot_info(from).await?;
+        let (to, _) = self.snapshot_info(to).await?;
+        if from > to {
+            return Err(QueryError::Error {
+                message: format!("cannot diff snapshot {from} against earlier snapshot {to}"),
+            });
+        }
+
+        // Every change to an entry, including removing it, creates a new version of a node at the
+        // end of the path to that entry, recording the index of the entry. So the changed entries
+        // are exactly those whose most recent version as of `to` was created after `from`.
+        let table = State::state_type();
+        let rows = query_as::<(JsonValue, Option<JsonValue>)>(&format!(
+            "SELECT idx, entry FROM {table} AS n
+              WHERE idx IS NOT NULL AND created > $1 AND created <= $2
+                AND NOT EXISTS (
+                    SELECT 1 FROM {table} AS m
+                     WHERE m.path = n.path AND m.created > n.created AND m.created <= $2
+                )
+              ORDER BY path
+              LIMIT $3 OFFSET $4"
+        ))
+        .bind(from)
+        .bind(to)
+        // Load one extra row, to find out if there is another page.
+        .bind(limit as i64 + 1)
+        .bind(offset as i64)
+        .fetch_all(self.as_mut())
+        .await?;
+
+        let next = (rows.len() > limit).then_some(offset + limit);
+        let changes = rows
+            .into_iter()
+            .take(limit)
+            .map(|(idx, entry)| -> QueryResult<_> {
+                let key = serde_json::from_value(idx).decode_error("malformed merkle index")?;
+                let entry = entry
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .decode_error("malformed merkle element")?;
+                Ok((key, entry))
+            })
+            .collect::<QueryResult<_>>()?;
+        Ok(StateDiff { changes, next })
+    }
+}
+
+#[async_trait]
+impl<Mode: TransactionMode> MerklizedStateHeightStorage for Transaction<Mode> {
+    async fn get_last_state_height(&mut self) -> QueryResult<usize> {
+        let Some((height,)) = query_as::<(i64,)>("SELECT height from last_merklized_state_height")
+            .fetch_optional(self.as_mut())
+            .await?
+        else {
+            return Ok(0);
+        };
+        Ok(height as usize)
+    }
+}
+
+impl<Mode: TransactionMode> Transaction<Mode> {
+    /// Retreive Merkle paths for `keys` from the snapshot created at height `created` with root
+    /// `merkle_commitment`.
+    ///
+    /// Paths to different keys always share some nodes (at least the root), so rather than loading
+    /// each path separately, we load each distinct node only once, and then assemble all of the
+    /// paths from the same set of nodes.
+    async fn get_paths_at<Types, State, const ARITY: usize>(
+        &mut self,
+        created: i64,
+        merkle_commitment: State::Commit,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>>
+    where
+        Types: NodeType,
+        State: MerklizedState<Types, ARITY> + 'static,
+    {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
         let state_type = State::state_type();
         let tree_height = State::tree_height();
 
-        // Get the traversal path of the index
-        let traversal_path = State::Key::to_traversal_path(&key, tree_height);
-        let (created, merkle_commitment) = self.snapshot_info(snapshot).await?;
+        // Get the traversal path of each key, and the paths of all the nodes along it.
+        let traversal_paths = keys
+            .iter()
+            .map(|key| State::Key::to_traversal_path(key, tree_height))
+            .collect::<Vec<_>>();
+        let paths = traversal_paths
+            .iter()
+            .map(|traversal_path| node_paths(traversal_path))
+            .collect::<Vec<_>>();
 
-        // Get all the nodes in the path to the index.
-        // Order by pos DESC is to return nodes from the leaf to the root
-        let (query, sql) = build_get_path_query(state_type, traversal_path.clone(), created)?;
+        // Get the latest version of each distinct node on any of the paths.
+        let distinct_paths = paths.iter().flatten().cloned().collect::<BTreeSet<_>>();
+        let (query, sql) = build_get_nodes_query(state_type, distinct_paths, created)?;
         let rows = query.query(&sql).fetch_all(self.as_mut()).await?;
-
-        let nodes: Vec<Node> = rows.into_iter().map(|r| r.into()).collect();
+        let mut nodes = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let node: Node = row.into();
+            let path = serde_json::from_value::<Vec<i32>>(node.path.clone())
+                .decode_error("malformed merkle node path")?;
+            nodes.insert(path, node);
+        }
 
         // insert all the hash ids to a hashset which is used to query later
         // HashSet is used to avoid duplicates
         let mut hash_ids = HashSet::new();
-        for node in nodes.iter() {
+        for node in nodes.values() {
             hash_ids.insert(node.hash_id);
             if let Some(children) = &node.children {
                 let children: Vec<i32> =
@@ -91,174 +222,27 @@ where
             HashMap::new()
         };
 
-        let mut proof_path = VecDeque::with_capacity(State::tree_height());
-        for Node {
-            hash_id,
-            children,
-            children_bitvec,
-            idx,
-            entry,
-            ..
-        } in nodes.iter()
-        {
-            {
-                let value = hashes.get(hash_id).ok_or(QueryError::Error {
-                    message: format!("node's value references non-existent hash {hash_id}"),
-                })?;
-
-                match (children, children_bitvec, idx, entry) {
-                    // If the row has children then its a branch
-                    (Some(children), Some(children_bitvec), None, None) => {
-                        let children: Vec<i32> =
-                            serde_json::from_value(children.clone()).map_err(|e| {
-                                QueryError::Error {
-                                    message: format!(
-                                        "Error deserializing 'children' into Vec<i32>: {e}"
-                                    ),
-                                }
-                            })?;
-                        let mut children = children.iter();
-
-                        // Reconstruct the Children MerkleNodes from storage.
-                        // Children bit_vec is used to create forgotten  or empty node
-                        let child_nodes = children_bitvec
-                            .iter()
-                            .map(|bit| {
-                                if bit {
-                                    let hash_id = children.next().ok_or(QueryError::Error {
-                                        message: "node has fewer children than set bits".into(),
-                                    })?;
-                                    let value = hashes.get(hash_id).ok_or(QueryError::Error {
-                                        message: format!(
-                                            "node's child references non-existent hash {hash_id}"
-                                        ),
-                                    })?;
-                                    Ok(Arc::new(MerkleNode::ForgettenSubtree {
-                                        value: State::T::deserialize_compressed(value.as_slice())
-                                            .decode_error("malformed merkle node value")?,
-                                    }))
-                                } else {
-                                    Ok(Arc::new(MerkleNode::Empty))
-                                }
-                            })
-                            .collect::<QueryResult<Vec<_>>>()?;
-                        // Use the Children merkle nodes to reconstruct the branch node
-                        proof_path.push_back(MerkleNode::Branch {
-                            value: State::T::deserialize_compressed(value.as_slice())
-                                .decode_error("malformed merkle node value")?,
-                            children: child_nodes,
-                        });
-                    }
-                    // If it has an entry, it's a leaf
-                    (None, None, Some(index), Some(entry)) => {
-                        proof_path.push_back(MerkleNode::Leaf {
-                            value: State::T::deserialize_compressed(value.as_slice())
-                                .decode_error("malformed merkle node value")?,
-                            pos: serde_json::from_value(index.clone())
-                                .decode_error("malformed merkle node index")?,
-                            elem: serde_json::from_value(entry.clone())
-                                .decode_error("malformed merkle element")?,
-                        });
-                    }
-                    // Otherwise, it's empty.
-                    (None, None, Some(_), None) => {
-                        proof_path.push_back(MerkleNode::Empty);
-                    }
-                    _ => {
-                        return Err(QueryError::Error {
-                            message: "Invalid type of merkle node found".to_string(),
-                        });
-                    }
-                }
-            }
-        }
-
-        // Reconstruct the merkle commitment from the path
-        let init = if let Some(MerkleNode::Leaf { value, .. }) = proof_path.front() {
-            *value
-        } else {
-            // If the path ends in a branch (or, as a special case, if the path and thus the entire
-            // tree is empty), we are looking up an entry that is not present in the tree. We always
-            // store all the nodes on all the paths to all the entries in the tree, so the only
-            // nodes we could be missing are empty nodes from unseen entries. Thus, we can
-            // reconstruct what the path should be by prepending empty nodes.
-            while proof_path.len() <= State::tree_height() {
-                proof_path.push_front(MerkleNode::Empty);
-            }
-            State::T::default()
-        };
-        let commitment_from_path = traversal_path
-            .iter()
-            .zip(proof_path.iter().skip(1))
-            .try_fold(init, |val, (branch, node)| -> QueryResult<State::T> {
-                match node {
-                    MerkleNode::Branch { value: _, children } => {
-                        let data = children
-                            .iter()
-                            .map(|node| match node.as_ref() {
-                                MerkleNode::ForgettenSubtree { value } => Ok(*value),
-                                MerkleNode::Empty => Ok(State::T::default()),
-                                _ => Err(QueryError::Error {
-                                    message: "Invalid child node".to_string(),
-                                }),
-                            })
-                            .collect::<QueryResult<Vec<_>>>()?;
-
-                        if data[*branch] != val {
-                            // This can only happen if data is missing: we have an old version of
-                            // one of the nodes in the path, which is why it is not matching up with
-                            // its parent.
-                            tracing::warn!(
-                                ?key,
-                                parent = ?data[*branch],
-                                child = ?val,
-                                branch = %*branch,
-                                %created,
-                                %merkle_commitment,
-                                "missing data in merklized state; parent-child mismatch",
-                            );
-                            return Err(QueryError::Missing);
-                        }
-
-                        State::Digest::digest(&data).map_err(|err| QueryError::Error {
-                            message: format!("failed to update digest: {err:#}"),
-                        })
-                    }
-                    MerkleNode::Empty => Ok(init),
-                    _ => Err(QueryError::Error {
-                        message: "Invalid type of Node in the proof".to_string(),
-                    }),
-                }
-            })?;
-
-        if commitment_from_path != merkle_commitment.digest() {
-            return Err(QueryError::Error {
-                message:
-                    format!("Commitment calcuated from merkle path ({commitment_from_path:?}) does not match the commitment in the header ({:?})", merkle_commitment.digest()),
-            });
-        }
-
-        Ok(MerkleProof {
-            pos: key,
-            proof: proof_path.into(),
-        })
-    }
-}
-
-#[async_trait]
-impl<Mode: TransactionMode> MerklizedStateHeightStorage for Transaction<Mode> {
-    async fn get_last_state_height(&mut self) -> QueryResult<usize> {
-        let Some((height,)) = query_as::<(i64,)>("SELECT height from last_merklized_state_height")
-            .fetch_optional(self.as_mut())
-            .await?
-        else {
-            return Ok(0);
-        };
-        Ok(height as usize)
+        keys.iter()
+            .zip(traversal_paths)
+            .zip(paths)
+            .map(|((key, traversal_path), paths)| {
+                // The nodes we have on the path, from the leaf to the root.
+                let path_nodes = paths
+                    .iter()
+                    .filter_map(|path| nodes.get(path))
+                    .collect::<Vec<_>>();
+                build_proof::<Types, State, ARITY>(
+                    key.clone(),
+                    &traversal_path,
+                    &path_nodes,
+                    &hashes,
+                    created,
+                    &merkle_commitment,
+                )
+            })
+            .collect()
     }
-}
 
-impl<Mode: TransactionMode> Transaction<Mode> {
     /// Get information identifying a [`Snapshot`].
     ///
     /// If the given snapshot is known to the database, this function returns
@@ -294,6 +278,23 @@ impl<Mode: TransactionMode> Transaction<Mode> {
                 (height, commit)
             }
             Snapshot::Index(created) => {
+                // The mapping from height to state commitment is stored in the header, so if the
+                // header has been pruned, we can no longer resolve this snapshot.
+                let pruned_height =
+                    self.load_pruned_height()
+                        .await
+                        .map_err(|err| QueryError::Error {
+                            message: format!("failed to load pruned height: {err:#}"),
+                        })?;
+                if pruned_height.is_some_and(|pruned| created <= pruned) {
+                    tracing::info!(
+                        created,
+                        ?pruned_height,
+                        "merklized state snapshot was pruned"
+                    );
+                    return Err(QueryError::Missing);
+                }
+
                 let created = created as i64;
                 let (commit,) = query_as::<(String,)>(&format!(
                     "SELECT {header_state_commitment_field} AS root_commmitment
@@ -318,6 +319,20 @@ impl<Mode: TransactionMode> Transaction<Mode> {
             return Err(QueryError::NotFound);
         }
 
+        // If we only retain a limited window of history, nodes needed for older snapshots may have
+        // been deleted.
+        if let Some(retention) = self.state_retention {
+            if (created as u64) + retention < height as u64 {
+                tracing::info!(
+                    created,
+                    height,
+                    retention,
+                    "merklized state snapshot is outside the retention window"
+                );
+                return Err(QueryError::StatePruned);
+            }
+        }
+
         Ok((created, commit))
     }
 }
@@ -427,44 +442,204 @@ impl Node {
     }
 }
 
-fn build_get_path_query<'q>(
-    table: &'static str,
-    traversal_path: Vec<usize>,
-    created: i64,
-) -> QueryResult<(QueryBuilder<'q>, String)> {
-    let mut query = QueryBuilder::default();
-    let mut traversal_path = traversal_path.into_iter().map(|x| x as i32);
+/// The paths of all the nodes on the traversal path to an entry, from the leaf up to the root.
+fn node_paths(traversal_path: &[usize]) -> Vec<Vec<i32>> {
+    (0..=traversal_path.len())
+        .map(|i| {
+            traversal_path[i..]
+                .iter()
+                .rev()
+                .map(|n| *n as i32)
+                .collect()
+        })
+        .collect()
+}
 
-    // We iterate through the path vector skipping the first element after each iteration
-    let len = traversal_path.len();
-    let mut sub_queries = Vec::new();
+/// Assemble a Merkle path to `key` from the `nodes` on its traversal path, ordered from the leaf up
+/// to the root, and check it against `merkle_commitment`.
+fn build_proof<Types, State, const ARITY: usize>(
+    key: State::Key,
+    traversal_path: &[usize],
+    nodes: &[&Node],
+    hashes: &HashMap<i32, Vec<u8>>,
+    created: i64,
+    merkle_commitment: &State::Commit,
+) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>>
+where
+    Types: NodeType,
+    State: MerklizedState<Types, ARITY>,
+{
+    let mut proof_path = VecDeque::with_capacity(State::tree_height());
+    for Node {
+        hash_id,
+        children,
+        children_bitvec,
+        idx,
+        entry,
+        ..
+    } in nodes.iter().copied()
+    {
+        {
+            let value = hashes.get(hash_id).ok_or(QueryError::Error {
+                message: format!("node's value references non-existent hash {hash_id}"),
+            })?;
 
-    query.bind(created)?;
+            match (children, children_bitvec, idx, entry) {
+                // If the row has children then its a branch
+                (Some(children), Some(children_bitvec), None, None) => {
+                    let children: Vec<i32> =
+                        serde_json::from_value(children.clone()).map_err(|e| {
+                            QueryError::Error {
+                                message: format!(
+                                    "Error deserializing 'children' into Vec<i32>: {e}"
+                                ),
+                            }
+                        })?;
+                    let mut children = children.iter();
+
+                    // Reconstruct the Children MerkleNodes from storage.
+                    // Children bit_vec is used to create forgotten  or empty node
+                    let child_nodes = children_bitvec
+                        .iter()
+                        .map(|bit| {
+                            if bit {
+                                let hash_id = children.next().ok_or(QueryError::Error {
+                                    message: "node has fewer children than set bits".into(),
+                                })?;
+                                let value = hashes.get(hash_id).ok_or(QueryError::Error {
+                                    message: format!(
+                                        "node's child references non-existent hash {hash_id}"
+                                    ),
+                                })?;
+                                Ok(Arc::new(MerkleNode::ForgettenSubtree {
+                                    value: State::T::deserialize_compressed(value.as_slice())
+                                        .decode_error("malformed merkle node value")?,
+                                }))
+                            } else {
+                                Ok(Arc::new(MerkleNode::Empty))
+                            }
+                        })
+                        .collect::<QueryResult<Vec<_>>>()?;
+                    // Use the Children merkle nodes to reconstruct the branch node
+                    proof_path.push_back(MerkleNode::Branch {
+                        value: State::T::deserialize_compressed(value.as_slice())
+                            .decode_error("malformed merkle node value")?,
+                        children: child_nodes,
+                    });
+                }
+                // If it has an entry, it's a leaf
+                (None, None, Some(index), Some(entry)) => {
+                    proof_path.push_back(MerkleNode::Leaf {
+                        value: State::T::deserialize_compressed(value.as_slice())
+                            .decode_error("malformed merkle node value")?,
+                        pos: serde_json::from_value(index.clone())
+                            .decode_error("malformed merkle node index")?,
+                        elem: serde_json::from_value(entry.clone())
+                            .decode_error("malformed merkle element")?,
+                    });
+                }
+                // Otherwise, it's empty.
+                (None, None, Some(_), None) => {
+                    proof_path.push_back(MerkleNode::Empty);
+                }
+                _ => {
+                    return Err(QueryError::Error {
+                        message: "Invalid type of merkle node found".to_string(),
+                    });
+                }
+            }
+        }
+    }
 
-    for _ in 0..=len {
-        let path = traversal_path.clone().rev().collect::<Vec<_>>();
-        let path: serde_json::Value = path.into();
-        let node_path = query.bind(path)?;
+    // Reconstruct the merkle commitment from the path
+    let init = if let Some(MerkleNode::Leaf { value, .. }) = proof_path.front() {
+        *value
+    } else {
+        // If the path ends in a branch (or, as a special case, if the path and thus the entire
+        // tree is empty), we are looking up an entry that is not present in the tree. We always
+        // store all the nodes on all the paths to all the entries in the tree, so the only
+        // nodes we could be missing are empty nodes from unseen entries. Thus, we can
+        // reconstruct what the path should be by prepending empty nodes.
+        while proof_path.len() <= State::tree_height() {
+            proof_path.push_front(MerkleNode::Empty);
+        }
+        State::T::default()
+    };
+    let commitment_from_path = traversal_path
+        .iter()
+        .zip(proof_path.iter().skip(1))
+        .try_fold(init, |val, (branch, node)| -> QueryResult<State::T> {
+            match node {
+                MerkleNode::Branch { value: _, children } => {
+                    let data = children
+                        .iter()
+                        .map(|node| match node.as_ref() {
+                            MerkleNode::ForgettenSubtree { value } => Ok(*value),
+                            MerkleNode::Empty => Ok(State::T::default()),
+                            _ => Err(QueryError::Error {
+                                message: "Invalid child node".to_string(),
+                            }),
+                        })
+                        .collect::<QueryResult<Vec<_>>>()?;
+
+                    if data[*branch] != val {
+                        // This can only happen if data is missing: we have an old version of
+                        // one of the nodes in the path, which is why it is not matching up with
+                        // its parent.
+                        tracing::warn!(
+                            ?key,
+                            parent = ?data[*branch],
+                            child = ?val,
+                            branch = %*branch,
+                            %created,
+                            %merkle_commitment,
+                            "missing data in merklized state; parent-child mismatch",
+                        );
+                        return Err(QueryError::Missing);
+                    }
 
-        let sub_query = format!(
-            "SELECT * FROM (SELECT * FROM {table} WHERE path = {node_path} AND created <= $1 ORDER BY created DESC LIMIT 1)",
-        );
+                    State::Digest::digest(&data).map_err(|err| QueryError::Error {
+                        message: format!("failed to update digest: {err:#}"),
+                    })
+                }
+                MerkleNode::Empty => Ok(init),
+                _ => Err(QueryError::Error {
+                    message: "Invalid type of Node in the proof".to_string(),
+                }),
+            }
+        })?;
 
-        sub_queries.push(sub_query);
-        traversal_path.next();
+    if commitment_from_path != merkle_commitment.digest() {
+        return Err(QueryError::Error {
+            message:
+                format!("Commitment calcuated from merkle path ({commitment_from_path:?}) does not match the commitment in the header ({:?})", merkle_commitment.digest()),
+        });
     }
 
-    let mut sql: String = sub_queries.join(" UNION ");
+    Ok(MerkleProof {
+        pos: key,
+        proof: proof_path.into(),
+    })
+}
 
-    sql = format!("SELECT * FROM ({sql}) as t ");
+/// Build a query for the latest version, as of height `created`, of each of the nodes at `paths`.
+fn build_get_nodes_query<'q>(
+    table: &'static str,
+    paths: impl IntoIterator<Item = Vec<i32>>,
+    created: i64,
+) -> QueryResult<(QueryBuilder<'q>, String)> {
+    let mut query = QueryBuilder::default();
+    query.bind(created)?;
 
-    // PostgreSQL already orders JSON arrays by length, so no additional function is needed
-    // For SQLite, `length()` is used to sort by length.
-    if cfg!(feature = "embedded-db") {
-        sql.push_str("ORDER BY length(t.path) DESC");
-    } else {
-        sql.push_str("ORDER BY t.path DESC");
+    let mut sub_queries = Vec::new();
+    for path in paths {
+        let path: serde_json::Value = path.into();
+        let node_path = query.bind(path)?;
+        sub_queries.push(format!(
+            "SELECT * FROM (SELECT * FROM {table} WHERE path = {node_path} AND created <= $1 ORDER BY created DESC LIMIT 1)",
+        ));
     }
+    let sql = format!("SELECT * FROM ({}) as t", sub_queries.join(" UNION "));
 
     Ok((query, sql))
 }
@@ -486,7 +661,7 @@ mod test {
         },
         merklized_state::UpdateStateData,
         testing::{
-            mocks::{MockMerkleTree, MockTypes},
+            mocks::{MockMerkleTree, MockMerkleTree4, MockTypes},
             setup_test,
         },
     };
@@ -568,6 +743,46 @@ mod test {
             assert_eq!(merkle_path, proof.clone(), "merkle paths mismatch");
         }
 
+        // Get all the paths at once and check that they match as well.
+        let keys = (0..27).collect::<Vec<_>>();
+        let merkle_paths = storage
+            .read()
+            .await
+            .unwrap()
+            .get_path_batch(
+                Snapshot::<_, MockMerkleTree, 8>::Index(block_height as u64),
+                &keys,
+            )
+            .await
+            .unwrap();
+        assert_eq!(merkle_paths.len(), keys.len());
+        for (i, merkle_path) in keys.iter().zip(merkle_paths) {
+            let (_, proof) = test_tree.lookup(i).expect_ok().unwrap();
+            assert_eq!(merkle_path, proof, "merkle paths mismatch");
+        }
+
+        // A batch may repeat keys and mix present and absent entries.
+        let keys = [26, 1000, 3, 26];
+        let merkle_paths = storage
+            .read()
+            .await
+            .unwrap()
+            .get_path_batch(
+                Snapshot::<_, MockMerkleTree, 8>::Index(block_height as u64),
+                &keys,
+            )
+            .await
+            .unwrap();
+        assert_eq!(merkle_paths.len(), keys.len());
+        for (key, merkle_path) in keys.iter().zip(merkle_paths) {
+            let proof = match test_tree.universal_lookup(key) {
+                LookupResult::Ok(_, proof) => proof,
+                LookupResult::NotFound(proof) => proof,
+                LookupResult::NotInMemory => panic!("missing entry {key}"),
+            };
+            assert_eq!(merkle_path, proof, "merkle paths mismatch for {key}");
+        }
+
         // Get the proof of index 0 with bh = 1
         let (_, proof_bh_1) = test_tree.lookup(0).expect_ok().unwrap();
         // Inserting Index 0 again with created (bh) = 2
@@ -652,6 +867,16 @@ mod test {
             .await
             .unwrap();
         assert_eq!(path_with_bh_1, proof_bh_1);
+
+        // A batch query against the old snapshot is not affected by the newer version of index 0.
+        let paths_with_bh_1 = storage
+            .read()
+            .await
+            .unwrap()
+            .get_path_batch(Snapshot::<_, MockMerkleTree, 8>::Index(1), &[0, 1])
+            .await
+            .unwrap();
+        assert_eq!(paths_with_bh_1[0], proof_bh_1);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -924,6 +1149,319 @@ mod test {
 
         assert_eq!(merkle_proof, proof.clone(), "merkle paths mismatch");
     }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_merklized_state_snapshot_for_height() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+        // Insert two versions of the tree, at heights 1 and 2.
+        let mut test_tree = MockMerkleTree::new(MockMerkleTree::tree_height());
+        let mut commitments = vec![];
+        let mut tx = storage.write().await.unwrap();
+        for block_height in 1..=2u64 {
+            test_tree.update(0, block_height as usize).unwrap();
+            let commitment = test_tree.commitment();
+            commitments.push(commitment);
+
+            let test_data = serde_json::json!({ MockMerkleTree::header_state_commitment_field() : serde_json::to_value(commitment).unwrap()});
+            tx.upsert(
+                "header",
+                ["height", "hash", "payload_hash", "timestamp", "data"],
+                ["height"],
+                [(
+                    block_height as i64,
+                    format!("randomHash{block_height}"),
+                    "t".to_string(),
+                    0,
+                    test_data,
+                )],
+            )
+            .await
+            .unwrap();
+            let (_, proof) = test_tree.lookup(0).expect_ok().unwrap();
+            let traversal_path =
+                <usize as ToTraversalPath<8>>::to_traversal_path(&0, test_tree.height());
+            UpdateStateData::<_, MockMerkleTree, 8>::insert_merkle_nodes(
+                &mut tx,
+                proof,
+                traversal_path,
+                block_height,
+            )
+            .await
+            .unwrap();
+        }
+        UpdateStateData::<_, MockMerkleTree, 8>::set_last_state_height(&mut tx, 2)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Each height resolves to the commitment of the state as of that block.
+        for (i, commitment) in commitments.iter().enumerate() {
+            let snapshot = MerklizedStateStorage::<_, MockMerkleTree, 8>::snapshot_for_height(
+                &mut storage.read().await.unwrap(),
+                i as u64 + 1,
+            )
+            .await
+            .unwrap();
+            assert_eq!(snapshot, Snapshot::Commit(*commitment));
+
+            let mut tx = storage.read().await.unwrap();
+            assert_eq!(
+                tx.get_path(snapshot, 0).await.unwrap(),
+                tx.get_path(Snapshot::<_, MockMerkleTree, 8>::Index(i as u64 + 1), 0)
+                    .await
+                    .unwrap()
+            );
+        }
+
+        // A height beyond the latest state is not found.
+        let err = MerklizedStateStorage::<_, MockMerkleTree, 8>::snapshot_for_height(
+            &mut storage.read().await.unwrap(),
+            3,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, QueryError::NotFound), "{err:#}");
+
+        // A pruned height is reported as missing.
+        let mut tx = storage.write().await.unwrap();
+        tx.delete_batch(1).await.unwrap();
+        tx.commit().await.unwrap();
+        let err = MerklizedStateStorage::<_, MockMerkleTree, 8>::snapshot_for_height(
+            &mut storage.read().await.unwrap(),
+            1,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, QueryError::Missing), "{err:#}");
+        MerklizedStateStorage::<_, MockMerkleTree, 8>::snapshot_for_height(
+            &mut storage.read().await.unwrap(),
+            2,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_merklized_state_diff() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+        // Insert some entries at height 1. At height 2, update one of them and remove another. At
+        // height 3, insert a new entry.
+        let blocks: [&[(usize, Option<usize>)]; 3] = [
+            &[(0, Some(0)), (1, Some(1)), (2, Some(2))],
+            &[(0, Some(99)), (1, None)],
+            &[(10, Some(10))],
+        ];
+        let mut test_tree = MockMerkleTree::new(MockMerkleTree::tree_height());
+        let mut tx = storage.write().await.unwrap();
+        for (i, updates) in blocks.into_iter().enumerate() {
+            let block_height = i as u64 + 1;
+            for (key, entry) in updates {
+                match entry {
+                    Some(entry) => {
+                        test_tree.update(*key, *entry).unwrap();
+                    }
+                    None => {
+                        test_tree.remove(*key).unwrap();
+                    }
+                }
+            }
+
+            let test_data = serde_json::json!({ MockMerkleTree::header_state_commitment_field() : serde_json::to_value(test_tree.commitment()).unwrap()});
+            tx.upsert(
+                "header",
+                ["height", "hash", "payload_hash", "timestamp", "data"],
+                ["height"],
+                [(
+                    block_height as i64,
+                    format!("randomHash{block_height}"),
+                    "t".to_string(),
+                    0,
+                    test_data,
+                )],
+            )
+            .await
+            .unwrap();
+            for (key, _) in updates {
+                let proof = match test_tree.universal_lookup(key) {
+                    LookupResult::Ok(_, proof) => proof,
+                    LookupResult::NotFound(proof) => proof,
+                    LookupResult::NotInMemory => panic!("missing entry {key}"),
+                };
+                let traversal_path =
+                    <usize as ToTraversalPath<8>>::to_traversal_path(key, test_tree.height());
+                UpdateStateData::<_, MockMerkleTree, 8>::insert_merkle_nodes(
+                    &mut tx,
+                    proof,
+                    traversal_path,
+                    block_height,
+                )
+                .await
+                .unwrap();
+            }
+        }
+        UpdateStateData::<_, MockMerkleTree, 8>::set_last_state_height(&mut tx, 3)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let diff = |from, to, offset, limit| {
+            let storage = &storage;
+            async move {
+                storage
+                    .read()
+                    .await
+                    .unwrap()
+                    .get_diff(
+                        Snapshot::<_, MockMerkleTree, 8>::Index(from),
+                        Snapshot::Index(to),
+                        offset,
+                        limit,
+                    )
+                    .await
+            }
+        };
+
+        // Each diff includes exactly the entries changed in its range, with their latest values.
+        let changes = |diff: StateDiff<usize, usize>| {
+            assert_eq!(diff.next, None);
+            diff.changes.into_iter().collect::<HashMap<_, _>>()
+        };
+        assert_eq!(
+            changes(diff(1, 2, 0, 10).await.unwrap()),
+            [(0, Some(99)), (1, None)].into()
+        );
+        assert_eq!(
+            changes(diff(2, 3, 0, 10).await.unwrap()),
+            [(10, Some(10))].into()
+        );
+        assert_eq!(
+            changes(diff(1, 3, 0, 10).await.unwrap()),
+            [(0, Some(99)), (1, None), (10, Some(10))].into()
+        );
+        assert_eq!(changes(diff(3, 3, 0, 10).await.unwrap()), [].into());
+
+        // A large diff can be loaded in pages.
+        let first = diff(1, 3, 0, 2).await.unwrap();
+        assert_eq!(first.changes.len(), 2);
+        assert_eq!(first.next, Some(2));
+        let second = diff(1, 3, 2, 2).await.unwrap();
+        assert_eq!(second.changes.len(), 1);
+        assert_eq!(second.next, None);
+        assert_eq!(
+            changes(StateDiff {
+                changes: [first.changes, second.changes].concat(),
+                next: None,
+            }),
+            [(0, Some(99)), (1, None), (10, Some(10))].into()
+        );
+
+        // Diffs must go forward, and both snapshots must be available.
+        diff(2, 1, 0, 10).await.unwrap_err();
+        diff(1, 4, 0, 10).await.unwrap_err();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_merklized_state_retention() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config().state_retention(2))
+            .await
+            .unwrap();
+
+        // Update the same entry at each of several heights, saving the expected proof at each
+        // height.
+        let mut test_tree = MockMerkleTree::new(MockMerkleTree::tree_height());
+        let traversal_path =
+            <usize as ToTraversalPath<8>>::to_traversal_path(&0, test_tree.height());
+        let mut proofs = vec![];
+        for block_height in 1..=5u64 {
+            test_tree.update(0, block_height as usize).unwrap();
+            let (_, proof) = test_tree.lookup(0).expect_ok().unwrap();
+            proofs.push(proof.clone());
+
+            let mut tx = storage.write().await.unwrap();
+            let test_data = serde_json::json!({ MockMerkleTree::header_state_commitment_field() : serde_json::to_value(test_tree.commitment()).unwrap()});
+            tx.upsert(
+                "header",
+                ["height", "hash", "payload_hash", "timestamp", "data"],
+                ["height"],
+                [(
+                    block_height as i64,
+                    format!("randomHash{block_height}"),
+                    "t".to_string(),
+                    0,
+                    test_data,
+                )],
+            )
+            .await
+            .unwrap();
+            UpdateStateData::<_, MockMerkleTree, 8>::insert_merkle_nodes(
+                &mut tx,
+                proof,
+                traversal_path.clone(),
+                block_height,
+            )
+            .await
+            .unwrap();
+            UpdateStateData::<_, MockMerkleTree, 8>::set_last_state_height(
+                &mut tx,
+                block_height as usize,
+            )
+            .await
+            .unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        // Snapshots within the retention window are still available, and each path checks out
+        // against the state root stored in the header at that height.
+        for block_height in 3..=5u64 {
+            let path = storage
+                .read()
+                .await
+                .unwrap()
+                .get_path(Snapshot::<_, MockMerkleTree, 8>::Index(block_height), 0)
+                .await
+                .unwrap();
+            assert_eq!(path, proofs[block_height as usize - 1]);
+        }
+
+        // Older snapshots have been pruned.
+        for block_height in 1..=2u64 {
+            let err = storage
+                .read()
+                .await
+                .unwrap()
+                .get_path(Snapshot::<_, MockMerkleTree, 8>::Index(block_height), 0)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::StatePruned), "{err:#}");
+        }
+
+        // Versions of the leaf which were already outside the retention window when the leaf was
+        // last updated have been deleted. Since the state height is only updated after the nodes
+        // are inserted, the window at that time started at height 2, so only version 1 is gone.
+        let node_path = traversal_path
+            .into_iter()
+            .rev()
+            .map(|n| n as i32)
+            .collect::<Vec<_>>();
+        let mut tx = storage.read().await.unwrap();
+        let (versions,) = query_as::<(i64,)>("SELECT count(*) FROM test_tree WHERE path = $1")
+            .bind(serde_json::to_value(node_path).unwrap())
+            .fetch_one(tx.as_mut())
+            .await
+            .unwrap();
+        assert_eq!(versions, 4);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_merklized_state_missing_state() {
         // This test checks that header commitment matches the root hash.
@@ -1390,4 +1928,90 @@ mod test {
             assert!(matches!(err, QueryError::Missing));
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_merklized_state_multiple_trees() {
+        setup_test();
+
+        let db = TmpDb::init().await;
+        let storage = SqlStorage::connect(db.config()).await.unwrap();
+
+        // Populate two trees, with different arities, using the same keys but different entries,
+        // and record both of their roots in the same headers.
+        let mut tree8 = MockMerkleTree::new(MockMerkleTree::tree_height());
+        let mut tree4 = MockMerkleTree4::new(MockMerkleTree4::tree_height());
+        for block_height in 1..=2u64 {
+            let mut tx = storage.write().await.unwrap();
+            for i in 0..10 {
+                let key = (block_height as usize - 1) * 10 + i;
+                tree8.update(key, key).unwrap();
+                tree4.update(key, key + 1000).unwrap();
+
+                let (_, proof8) = tree8.lookup(key).expect_ok().unwrap();
+                UpdateStateData::<_, MockMerkleTree, 8>::insert_merkle_nodes(
+                    &mut tx,
+                    proof8,
+                    <usize as ToTraversalPath<8>>::to_traversal_path(&key, tree8.height()),
+                    block_height,
+                )
+                .await
+                .unwrap();
+                let (_, proof4) = tree4.lookup(key).expect_ok().unwrap();
+                UpdateStateData::<_, MockMerkleTree4, 4>::insert_merkle_nodes(
+                    &mut tx,
+                    proof4,
+                    <usize as ToTraversalPath<4>>::to_traversal_path(&key, tree4.height()),
+                    block_height,
+                )
+                .await
+                .unwrap();
+            }
+
+            let test_data = serde_json::json!({
+                MockMerkleTree::header_state_commitment_field():
+                    serde_json::to_value(tree8.commitment()).unwrap(),
+                MockMerkleTree4::header_state_commitment_field():
+                    serde_json::to_value(tree4.commitment()).unwrap(),
+            });
+            tx.upsert(
+                "header",
+                ["height", "hash", "payload_hash", "timestamp", "data"],
+                ["height"],
+                [(
+                    block_height as i64,
+                    format!("randomHash{block_height}"),
+                    "t".to_string(),
+                    0,
+                    test_data,
+                )],
+            )
+            .await
+            .unwrap();
+            UpdateStateData::<_, MockMerkleTree, 8>::set_last_state_height(
+                &mut tx,
+                block_height as usize,
+            )
+            .await
+            .unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        // Each tree is stored separately, and each path checks out against its own root.
+        for key in 0..20 {
+            let mut tx = storage.read().await.unwrap();
+            let path8 = tx
+                .get_path(Snapshot::<_, MockMerkleTree, 8>::Index(2), key)
+                .await
+                .unwrap();
+            assert_eq!(path8, tree8.lookup(key).expect_ok().unwrap().1);
+            assert_eq!(*path8.elem().unwrap(), key);
+
+            let path4 = tx
+                .get_path(Snapshot::<_, MockMerkleTree4, 4>::Index(2), key)
+                .await
+                .unwrap();
+            assert_eq!(path4, tree4.lookup(key).expect_ok().unwrap().1);
+            assert_eq!(*path4.elem().unwrap(), key + 1000);
+        }
+    }
 }
@@ -0,0 +1,250 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the HotShot Query Service library.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// General Public License for more details.
+// You should have received a copy of the GNU General Public License along with this program. If not,
+// see <https://www.gnu.org/licenses/>.
+
+//! Indexes which are built in the background, without blocking startup.
+//!
+//! Creating an index on a large, existing table can take a long time, and an ordinary `CREATE
+//! INDEX` statement in a migration locks the table against writes until it completes. Indexes
+//! which are only needed to speed up queries (not to enforce constraints) can instead be declared
+//! as [`BackgroundIndex`]es. These are built after [`SqlStorage`](super::SqlStorage) connects,
+//! while the service is already running. On Postgres, they are built `CONCURRENTLY`, so that
+//! reads and writes to the table can proceed during the build.
+//!
+//! Until an index is ready, Postgres simply does not consider it when planning queries, so queries
+//! which would benefit from the index fall back to a table scan. Which indexes have been built is
+//! tracked in the `background_index` table, so each index is only built once.
+
+use super::{query, query_as, Db, Executor};
+use hotshot_types::traits::metrics::{Gauge, Metrics};
+use sqlx::pool::Pool;
+#[cfg(not(feature = "embedded-db"))]
+use std::time::Duration;
+
+/// How often to update the progress metric while an index is being built.
+#[cfg(not(feature = "embedded-db"))]
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The key of the advisory lock which prevents two processes from building indexes at once.
+#[cfg(not(feature = "embedded-db"))]
+const REINDEX_LOCK: i64 = 0x5245_494e_4445_58; // "REINDEX"
+
+/// An index which is built in the background after startup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackgroundIndex {
+    name: String,
+    definition: String,
+}
+
+impl BackgroundIndex {
+    /// An index called `name`.
+    ///
+    /// `definition` is the rest of the `CREATE INDEX` statement following the name, such as
+    /// `ON payload (height) WHERE data IS NULL`.
+    pub fn new(name: impl Into<String>, definition: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            definition: definition.into(),
+        }
+    }
+
+    /// The name of this index.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The indexes built in the background for the default schema.
+pub fn default_background_indexes() -> Vec<BackgroundIndex> {
+    vec![
+        // Speed up counting missing payloads and VID shares for the sync status.
+        BackgroundIndex::new(
+            "payload_missing_idx",
+            "ON payload (height) WHERE data IS NULL",
+        ),
+        BackgroundIndex::new(
+            "vid_missing_share_idx",
+            "ON vid (height) WHERE share IS NULL",
+        ),
+    ]
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct ReindexMetrics {
+    /// The number of background indexes which have not been built yet.
+    pending: Box<dyn Gauge>,
+    /// The percentage of the index currently being built which is complete.
+    progress: Box<dyn Gauge>,
+}
+
+impl ReindexMetrics {
+    /// Metrics for building `num_indexes` background indexes.
+    pub(super) fn new(metrics: &(impl Metrics + ?Sized), num_indexes: usize) -> Self {
+        let pending = metrics.create_gauge("background_indexes_pending".into(), None);
+        pending.set(num_indexes);
+        Self {
+            pending,
+            progress: metrics.create_gauge("background_index_progress".into(), None),
+        }
+    }
+}
+
+/// Build each of `indexes` which is not already built.
+///
+/// Errors are logged rather than returned: an index which fails to build is retried the next time
+/// the storage is connected, and in the meantime queries continue to work, only more slowly.
+pub(super) async fn build_background_indexes(
+    pool: Pool<Db>,
+    indexes: Vec<BackgroundIndex>,
+    metrics: ReindexMetrics,
+) {
+    let mut conn = match pool.acquire().await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::warn!("unable to build background indexes: {err:#}");
+            return;
+        }
+    };
+
+    // Make sure only one process builds indexes at a time. This lock is held by the session, so if
+    // this task is cancelled, we must close the connection rather than return it to the pool with
+    // the lock still held.
+    #[cfg(not(feature = "embedded-db"))]
+    {
+        conn.close_on_drop();
+        match query_as::<(bool,)>("SELECT pg_try_advisory_lock($1)")
+            .bind(REINDEX_LOCK)
+            .fetch_one(conn.as_mut())
+            .await
+        {
+            Ok((true,)) => {}
+            Ok((false,)) => {
+                tracing::info!("another process is building background indexes");
+                return;
+            }
+            Err(err) => {
+                tracing::warn!("unable to build background indexes: {err:#}");
+                return;
+            }
+        }
+    }
+
+    for index in indexes {
+        match build_index(&pool, conn.as_mut(), &index, &metrics).await {
+            Ok(()) => {
+                tracing::info!(index = %index.name, "background index ready");
+                metrics.pending.update(-1);
+            }
+            Err(err) => {
+                tracing::warn!(index = %index.name, "failed to build background index: {err:#}");
+            }
+        }
+    }
+}
+
+async fn build_index(
+    pool: &Pool<Db>,
+    conn: &mut <Db as sqlx::Database>::Connection,
+    index: &BackgroundIndex,
+    metrics: &ReindexMetrics,
+) -> sqlx::Result<()> {
+    let ready = query_as::<(bool,)>("SELECT ready FROM background_index WHERE name = $1")
+        .bind(&index.name)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if matches!(ready, Some((true,))) {
+        return Ok(());
+    }
+    tracing::info!(index = %index.name, "building background index");
+    metrics.progress.set(0);
+
+    #[cfg(not(feature = "embedded-db"))]
+    {
+        // A concurrent build which was interrupted leaves behind an invalid index, which `IF NOT
+        // EXISTS` would not replace. Drop it so we can start over.
+        let valid = query_as::<(bool,)>(
+            "SELECT i.indisvalid FROM pg_index AS i
+               JOIN pg_class AS c ON c.oid = i.indexrelid
+               JOIN pg_namespace AS n ON n.oid = c.relnamespace
+              WHERE c.relname = $1 AND n.nspname = current_schema()",
+        )
+        .bind(&index.name)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if matches!(valid, Some((false,))) {
+            tracing::warn!(index = %index.name, "dropping invalid index");
+            let sql = format!("DROP INDEX CONCURRENTLY IF EXISTS {}", index.name);
+            Executor::execute(&mut *conn, sql.as_str()).await?;
+        }
+
+        let (pid,) = query_as::<(i32,)>("SELECT pg_backend_pid()")
+            .fetch_one(&mut *conn)
+            .await?;
+        let sql = format!(
+            "CREATE INDEX CONCURRENTLY IF NOT EXISTS {} {}",
+            index.name, index.definition
+        );
+        let build = Executor::execute(&mut *conn, sql.as_str());
+        tokio::pin!(build);
+        loop {
+            tokio::select! {
+                res = &mut build => {
+                    res?;
+                    break;
+                }
+                _ = tokio::time::sleep(PROGRESS_INTERVAL) => {
+                    report_progress(pool, pid, metrics).await;
+                }
+            }
+        }
+    }
+    #[cfg(feature = "embedded-db")]
+    {
+        // SQLite has no concurrent index builds, but the database is local and usually small, so
+        // building in the background still gets the service up sooner.
+        let _ = pool;
+        let sql = format!(
+            "CREATE INDEX IF NOT EXISTS {} {}",
+            index.name, index.definition
+        );
+        Executor::execute(&mut *conn, sql.as_str()).await?;
+    }
+
+    query(
+        "INSERT INTO background_index (name, ready) VALUES ($1, true)
+            ON CONFLICT (name) DO UPDATE SET ready = excluded.ready",
+    )
+    .bind(&index.name)
+    .execute(&mut *conn)
+    .await?;
+    metrics.progress.set(100);
+    Ok(())
+}
+
+/// Update the progress metric for the index being built by the session `pid`.
+#[cfg(not(feature = "embedded-db"))]
+async fn report_progress(pool: &Pool<Db>, pid: i32, metrics: &ReindexMetrics) {
+    let progress = query_as::<(i64, i64)>(
+        "SELECT blocks_done, blocks_total FROM pg_stat_progress_create_index WHERE pid = $1",
+    )
+    .bind(pid)
+    .fetch_optional(pool)
+    .await;
+    match progress {
+        Ok(Some((done, total))) if total > 0 => {
+            metrics.progress.set((done * 100 / total) as usize);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            tracing::debug!("unable to load index build progress: {err:#}");
+        }
+    }
+}
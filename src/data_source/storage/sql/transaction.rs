@@ -28,11 +28,15 @@ use super::{
 };
 use crate::{
     availability::{
-        BlockQueryData, LeafQueryData, QueryableHeader, QueryablePayload, VidCommonQueryData,
+        BlockId, BlockQueryData, LeafHash, LeafQueryData, QueryableHeader, QueryablePayload,
+        VidCommonQueryData,
     },
     data_source::{
-        storage::{pruning::PrunedHeightStorage, UpdateAvailabilityStorage},
-        update,
+        storage::{
+            pruning::PrunedHeightStorage, DeleteBlockStorage, InconsistentData,
+            MerklizedStateHeightStorage, Reorg, UpdateAvailabilityStorage,
+        },
+        update::{self, CommitMarker, CommitTracker},
     },
     merklized_state::{MerklizedState, UpdateStateData},
     types::HeightIndexed,
@@ -47,7 +51,7 @@ use futures::{future::Future, stream::TryStreamExt};
 use hotshot_types::traits::{
     block_contents::BlockHeader,
     metrics::{Counter, Gauge, Histogram, Metrics},
-    node_implementation::NodeType,
+    node_implementation::{ConsensusTime, NodeType},
     EncodeBytes,
 };
 use itertools::Itertools;
@@ -56,7 +60,7 @@ use sqlx::types::BitVec;
 pub use sqlx::Executor;
 use sqlx::{pool::Pool, query_builder::Separated, Encode, FromRow, QueryBuilder, Type};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     marker::PhantomData,
     time::Instant,
 };
@@ -172,28 +176,55 @@ impl<Mode> Drop for TransactionMetricsGuard<Mode> {
 }
 
 /// An atomic SQL transaction.
+///
+/// A transaction holds a single connection from the pool for its whole lifetime, and every query
+/// made through it, reads included, runs on that connection. Reads through a [`Write`] transaction
+/// therefore observe the transaction's own uncommitted writes.
 #[derive(Debug, Deref, DerefMut)]
 pub struct Transaction<Mode> {
     #[deref]
     #[deref_mut]
     inner: sqlx::Transaction<'static, Db>,
     metrics: TransactionMetricsGuard<Mode>,
+    commits: CommitTracker,
+    /// The number of block heights of merklized state history to retain, if limited.
+    pub(super) state_retention: Option<u64>,
 }
 
 impl<Mode: TransactionMode> Transaction<Mode> {
-    pub(super) async fn new(pool: &Pool<Db>, metrics: PoolMetrics) -> anyhow::Result<Self> {
-        let mut inner = pool.begin().await?;
+    pub(super) async fn new(
+        pool: &Pool<Db>,
+        metrics: PoolMetrics,
+        commits: CommitTracker,
+        state_retention: Option<u64>,
+    ) -> anyhow::Result<Self> {
+        let inner = {
+            let _waiter = metrics.wait_for_connection(pool);
+            pool.begin().await
+        };
+        metrics.update_pool(pool);
+        let mut inner = inner.map_err(|err| match err {
+            // Report a timeout waiting for a connection as a typed error, so it can be
+            // distinguished from other failures and turned into a `503` response.
+            sqlx::Error::PoolTimedOut => anyhow::Error::new(QueryError::PoolExhausted),
+            err => err.into(),
+        })?;
         let metrics = TransactionMetricsGuard::begin(metrics);
         Mode::begin(inner.as_mut()).await?;
-        Ok(Self { inner, metrics })
+        Ok(Self {
+            inner,
+            metrics,
+            commits,
+            state_retention,
+        })
     }
 }
 
 impl<Mode: TransactionMode> update::Transaction for Transaction<Mode> {
-    async fn commit(mut self) -> anyhow::Result<()> {
+    async fn commit_with_version(mut self) -> anyhow::Result<CommitMarker> {
         self.inner.commit().await?;
         self.metrics.set_closed(CloseType::Commit);
-        Ok(())
+        Ok(self.commits.commit())
     }
     fn revert(mut self) -> impl Future + Send {
         async move {
@@ -203,6 +234,30 @@ impl<Mode: TransactionMode> update::Transaction for Transaction<Mode> {
     }
 }
 
+/// The error codes the database uses when a transaction is aborted because it conflicts with a
+/// concurrent transaction.
+///
+/// For Postgres, these are the SQLSTATE codes `serialization_failure` and `deadlock_detected`. For
+/// SQLite, they are the extended result codes `SQLITE_BUSY` and `SQLITE_BUSY_SNAPSHOT`.
+#[cfg(not(feature = "embedded-db"))]
+const CONFLICT_CODES: [&str; 2] = ["40001", "40P01"];
+#[cfg(feature = "embedded-db")]
+const CONFLICT_CODES: [&str; 2] = ["5", "517"];
+
+/// Did `err` occur because a transaction conflicted with a concurrent transaction?
+///
+/// A transaction which fails for this reason may succeed if it is rolled back and run again from
+/// the beginning. See [`SqlStorage::with_transaction`](super::SqlStorage::with_transaction).
+pub fn is_serialization_failure(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|err| match err.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Database(err)) => err
+                .code()
+                .is_some_and(|code| CONFLICT_CODES.contains(&code.as_ref())),
+            _ => false,
+        })
+}
+
 /// A collection of parameters which can be bound to a SQL query.
 ///
 /// This trait allows us to carry around hetergenous lists of parameters (e.g. tuples) and bind them
@@ -386,6 +441,77 @@ impl Transaction<Write> {
         )
         .await
     }
+
+    /// Delete payload data for all blocks up to and including `height`, keeping leaves and headers.
+    ///
+    /// Payload rows are kept with their data cleared, so that payload metadata (size and number of
+    /// transactions) remains available.
+    pub(super) async fn delete_payload_batch(&mut self, height: u64) -> anyhow::Result<()> {
+        self.execute(
+            query("UPDATE payload SET data = NULL WHERE height <= $1 AND data IS NOT NULL")
+                .bind(height as i64),
+        )
+        .await?;
+        self.save_payload_pruned_height(height).await?;
+        Ok(())
+    }
+
+    /// Delete VID data for all blocks up to and including `height`, keeping leaves and headers.
+    ///
+    /// VID shares are always cleared. Unless `retain_vid_common` is set, the VID common data is
+    /// deleted as well.
+    pub(super) async fn delete_vid_batch(
+        &mut self,
+        height: u64,
+        retain_vid_common: bool,
+    ) -> anyhow::Result<()> {
+        if retain_vid_common {
+            self.execute(
+                query("UPDATE vid SET share = NULL WHERE height <= $1 AND share IS NOT NULL")
+                    .bind(height as i64),
+            )
+            .await?;
+        } else {
+            self.execute(query("DELETE FROM vid WHERE height <= $1").bind(height as i64))
+                .await?;
+        }
+        self.execute(query("DELETE FROM vid_share WHERE height <= $1").bind(height as i64))
+            .await?;
+        self.save_vid_pruned_height(height).await?;
+        Ok(())
+    }
+
+    /// Delete all data at or above `height`.
+    ///
+    /// Every other table referencing a block references its header, so deleting the headers
+    /// cascades to the rest of the data at those heights.
+    pub(super) async fn delete_from(&mut self, height: u64) -> anyhow::Result<()> {
+        self.execute(query("DELETE FROM header WHERE height >= $1").bind(height as i64))
+            .await?;
+        Ok(())
+    }
+
+    /// Record the height of the latest block whose payload has been pruned.
+    pub(super) async fn save_payload_pruned_height(&mut self, height: u64) -> anyhow::Result<()> {
+        self.upsert(
+            "pruned_payload_height",
+            ["id", "last_height"],
+            ["id"],
+            [(1i32, height as i64)],
+        )
+        .await
+    }
+
+    /// Record the height of the latest block whose VID data has been pruned.
+    pub(super) async fn save_vid_pruned_height(&mut self, height: u64) -> anyhow::Result<()> {
+        self.upsert(
+            "pruned_vid_height",
+            ["id", "last_height"],
+            ["id"],
+            [(1i32, height as i64)],
+        )
+        .await
+    }
 }
 
 impl<Types> UpdateAvailabilityStorage<Types> for Transaction<Write>
@@ -395,45 +521,96 @@ where
     Header<Types>: QueryableHeader<Types>,
 {
     async fn insert_leaf(&mut self, leaf: LeafQueryData<Types>) -> anyhow::Result<()> {
-        // While we don't necessarily have the full block for this leaf yet, we can initialize the
+        self.insert_leaves([leaf]).await
+    }
+
+    async fn insert_leaves<I>(&mut self, leaves: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = LeafQueryData<Types>> + Send,
+        I::IntoIter: Send,
+    {
+        // A single statement cannot upsert the same row twice, so deduplicate the batch by height,
+        // keeping the last leaf at each height, as if they were inserted one at a time.
+        let mut batch = BTreeMap::<u64, LeafQueryData<Types>>::new();
+        for leaf in leaves {
+            if let Some(prev) = batch.get(&leaf.height()) {
+                Reorg::check(prev.hash(), &leaf)?;
+            }
+            batch.insert(leaf.height(), leaf);
+        }
+        let leaves = batch;
+        let (Some(first), Some(last)) = (leaves.keys().next(), leaves.keys().next_back()) else {
+            return Ok(());
+        };
+
+        // Never overwrite a different leaf already stored at the same height.
+        let existing = query_as::<(i64, String)>(
+            "SELECT height, hash FROM leaf WHERE height >= $1 AND height <= $2",
+        )
+        .bind(*first as i64)
+        .bind(*last as i64)
+        .fetch_all(self.as_mut())
+        .await?;
+        for (height, hash) in existing {
+            let Some(leaf) = leaves.get(&(height as u64)) else {
+                continue;
+            };
+            if hash != leaf.hash().to_string() {
+                let old_hash: LeafHash<Types> = hash.parse().context("malformed leaf hash")?;
+                Reorg::check(old_hash, leaf)?;
+            }
+        }
+
+        // While we don't necessarily have the full block for each leaf yet, we can initialize the
         // header table with block metadata taken from the leaf.
-        let header_json = serde_json::to_value(leaf.leaf().block_header())
-            .context("failed to serialize header")?;
-        self.upsert(
-            "header",
-            ["height", "hash", "payload_hash", "data", "timestamp"],
-            ["height"],
-            [(
-                leaf.height() as i64,
+        let mut headers = vec![];
+        let mut payloads = vec![];
+        let mut leaf_rows = vec![];
+        for (height, leaf) in &leaves {
+            let header_json = serde_json::to_value(leaf.leaf().block_header())
+                .context("failed to serialize header")?;
+            headers.push((
+                *height as i64,
                 leaf.block_hash().to_string(),
                 leaf.leaf().block_header().payload_commitment().to_string(),
                 header_json,
                 leaf.leaf().block_header().timestamp() as i64,
-            )],
+            ));
+
+            // Similarly, we can initialize the payload table with a null payload, which can help us
+            // distinguish between blocks that haven't been produced yet and blocks we haven't
+            // received yet when answering queries.
+            payloads.push((*height as i64,));
+
+            // Finally, we insert the leaf itself, which references the header row we created.
+            // Serialize the full leaf and QC to JSON for easy storage.
+            let leaf_json =
+                serde_json::to_value(leaf.leaf()).context("failed to serialize leaf")?;
+            let qc_json = serde_json::to_value(leaf.qc()).context("failed to serialize QC")?;
+            leaf_rows.push((
+                *height as i64,
+                leaf.hash().to_string(),
+                leaf.block_hash().to_string(),
+                leaf.leaf().view_number().u64() as i64,
+                leaf_json,
+                qc_json,
+            ));
+        }
+
+        self.upsert(
+            "header",
+            ["height", "hash", "payload_hash", "data", "timestamp"],
+            ["height"],
+            headers,
         )
         .await?;
-
-        // Similarly, we can initialize the payload table with a null payload, which can help us
-        // distinguish between blocks that haven't been produced yet and blocks we haven't received
-        // yet when answering queries.
-        self.upsert("payload", ["height"], ["height"], [(leaf.height() as i64,)])
+        self.upsert("payload", ["height"], ["height"], payloads)
             .await?;
-
-        // Finally, we insert the leaf itself, which references the header row we created.
-        // Serialize the full leaf and QC to JSON for easy storage.
-        let leaf_json = serde_json::to_value(leaf.leaf()).context("failed to serialize leaf")?;
-        let qc_json = serde_json::to_value(leaf.qc()).context("failed to serialize QC")?;
         self.upsert(
             "leaf",
-            ["height", "hash", "block_hash", "leaf", "qc"],
+            ["height", "hash", "block_hash", "view_number", "leaf", "qc"],
             ["height"],
-            [(
-                leaf.height() as i64,
-                leaf.hash().to_string(),
-                leaf.block_hash().to_string(),
-                leaf_json,
-                qc_json,
-            )],
+            leaf_rows,
         )
         .await?;
 
@@ -441,10 +618,51 @@ where
     }
 
     async fn insert_block(&mut self, block: BlockQueryData<Types>) -> anyhow::Result<()> {
+        // Never restore a payload which was deliberately deleted.
+        if self.is_deleted(block.height()).await? {
+            tracing::info!(height = block.height(), "not inserting deleted block");
+            return Ok(());
+        }
+
         // The header and payload tables should already have been initialized when we inserted the
         // corresponding leaf. All we have to do is add the payload itself and its size.
         let payload = block.payload.encode();
 
+        // Check the block against whatever we already have at this height. Re-inserting the same
+        // block is a no-op, but a conflicting block means a fork or corrupt data, and must not
+        // overwrite what is stored. The header hash commits to the payload commitment, so checking
+        // it also checks that the payload commitments match.
+        let existing = query_as::<(String, Option<Vec<u8>>)>(
+            "SELECT h.hash, p.data
+               FROM header AS h
+               JOIN payload AS p ON p.height = h.height
+              WHERE h.height = $1
+              LIMIT 1",
+        )
+        .bind(block.height() as i64)
+        .fetch_optional(self.as_mut())
+        .await?;
+        if let Some((hash, data)) = existing {
+            if hash != block.hash().to_string() {
+                return Err(InconsistentData {
+                    height: block.height(),
+                    reason: format!("block {} does not match stored header {hash}", block.hash()),
+                }
+                .into());
+            }
+            if let Some(data) = data {
+                if data != payload.as_ref() {
+                    return Err(InconsistentData {
+                        height: block.height(),
+                        reason: "payload does not match stored payload".into(),
+                    }
+                    .into());
+                }
+                // The block is already stored.
+                return Ok(());
+            }
+        }
+
         self.upsert(
             "payload",
             ["height", "data", "size", "num_transactions"],
@@ -483,6 +701,14 @@ where
         common: VidCommonQueryData<Types>,
         share: Option<VidShare>,
     ) -> anyhow::Result<()> {
+        if self.is_deleted(common.height()).await? {
+            tracing::info!(
+                height = common.height(),
+                "not inserting VID data for deleted block"
+            );
+            return Ok(());
+        }
+
         let common_data =
             bincode::serialize(common.common()).context("failed to serialize VID common data")?;
         if let Some(share) = share {
@@ -507,6 +733,70 @@ where
             .await
         }
     }
+
+    async fn insert_vid_share(
+        &mut self,
+        height: u64,
+        index: usize,
+        share: VidShare,
+    ) -> anyhow::Result<()> {
+        if self.is_deleted(height).await? {
+            tracing::info!(height, index, "not inserting VID share for deleted block");
+            return Ok(());
+        }
+
+        let share_data = bincode::serialize(&share).context("failed to serialize VID share")?;
+        self.upsert(
+            "vid_share",
+            ["height", "node_index", "data"],
+            ["height", "node_index"],
+            [(height as i64, index as i64, share_data)],
+        )
+        .await
+    }
+
+    async fn purge_from(&mut self, height: u64) -> anyhow::Result<()> {
+        self.delete_from(height).await
+    }
+
+    async fn reject_payload(&mut self, height: u64) -> anyhow::Result<()> {
+        // A rejected payload was never stored, so all that is left to do is to record it like a
+        // deleted block, which prevents it from being inserted or fetched again.
+        self.execute(
+            query("INSERT INTO deleted_block (height) VALUES ($1) ON CONFLICT DO NOTHING")
+                .bind(height as i64),
+        )
+        .await?;
+        tracing::warn!(height, "rejected payload");
+        Ok(())
+    }
+}
+
+impl<Types> DeleteBlockStorage<Types> for Transaction<Write>
+where
+    Types: NodeType,
+{
+    async fn delete_block(&mut self, id: BlockId<Types>) -> anyhow::Result<u64> {
+        let height = self.load_header::<Types>(id).await?.block_number();
+
+        // Keep the payload row with its data cleared, as the pruner does, so that payload metadata
+        // remains available. Everything derived from the payload contents goes.
+        self.execute(query("UPDATE payload SET data = NULL WHERE height = $1").bind(height as i64))
+            .await?;
+        self.execute(query("DELETE FROM transactions WHERE block_height = $1").bind(height as i64))
+            .await?;
+        self.execute(query("DELETE FROM vid WHERE height = $1").bind(height as i64))
+            .await?;
+        self.execute(query("DELETE FROM vid_share WHERE height = $1").bind(height as i64))
+            .await?;
+        self.execute(
+            query("INSERT INTO deleted_block (height) VALUES ($1) ON CONFLICT DO NOTHING")
+                .bind(height as i64),
+        )
+        .await?;
+        tracing::warn!(height, "deleted block");
+        Ok(height)
+    }
 }
 
 #[async_trait]
@@ -680,8 +970,37 @@ impl<Types: NodeType, State: MerklizedState<Types, ARITY>, const ARITY: usize>
             }
         }
 
+        let paths = nodes
+            .iter()
+            .map(|(node, _, _)| node.path.clone())
+            .collect::<Vec<_>>();
         Node::upsert(name, nodes.into_iter().map(|(n, _, _)| n), self).await?;
 
+        // If we only retain a limited window of history, delete the versions of these nodes which
+        // were replaced before the start of that window. They are not needed to serve any snapshot
+        // which is still available.
+        if let Some(retention) = self.state_retention {
+            let height = MerklizedStateHeightStorage::get_last_state_height(self).await? as u64;
+            let cutoff = height.saturating_sub(retention) as i64;
+
+            let mut query = queries::QueryBuilder::default();
+            let paths = paths
+                .into_iter()
+                .map(|path| query.bind(path))
+                .collect::<QueryResult<Vec<_>>>()?;
+            let cutoff = query.bind(cutoff)?;
+            let sql = format!(
+                "DELETE FROM {name} AS n
+                  WHERE path IN ({}) AND created < {cutoff}
+                    AND EXISTS (
+                        SELECT 1 FROM {name} AS m
+                         WHERE m.path = n.path AND m.created > n.created AND m.created <= {cutoff}
+                    )",
+                paths.join(",")
+            );
+            query.query(&sql).execute(self.as_mut()).await?;
+        }
+
         Ok(())
     }
 }
@@ -698,6 +1017,48 @@ impl<Mode: TransactionMode> PrunedHeightStorage for Transaction<Mode> {
         };
         Ok(Some(height as u64))
     }
+
+    async fn load_payload_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        let Some((height,)) = query_as::<(i64,)>(
+            "SELECT last_height FROM pruned_payload_height ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(self.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(height as u64))
+    }
+
+    async fn load_vid_pruned_height(&mut self) -> anyhow::Result<Option<u64>> {
+        let Some((height,)) = query_as::<(i64,)>(
+            "SELECT last_height FROM pruned_vid_height ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(self.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(height as u64))
+    }
+
+    async fn load_deleted_heights(&mut self) -> anyhow::Result<Vec<u64>> {
+        let heights = query_as::<(i64,)>("SELECT height FROM deleted_block ORDER BY height")
+            .fetch_all(self.as_mut())
+            .await?;
+        Ok(heights.into_iter().map(|(h,)| h as u64).collect())
+    }
+}
+
+impl<Mode: TransactionMode> Transaction<Mode> {
+    /// Has the block at `height` been deleted with [`DeleteBlockStorage::delete_block`]?
+    pub(super) async fn is_deleted(&mut self, height: u64) -> anyhow::Result<bool> {
+        let deleted = query_as::<(i64,)>("SELECT height FROM deleted_block WHERE height = $1")
+            .bind(height as i64)
+            .fetch_optional(self.as_mut())
+            .await?;
+        Ok(deleted.is_some())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -707,6 +1068,13 @@ pub(super) struct PoolMetrics {
     commits: Box<dyn Counter>,
     reverts: Box<dyn Counter>,
     drops: Box<dyn Counter>,
+    pool_size: Box<dyn Gauge>,
+    idle_connections: Box<dyn Gauge>,
+    connection_waiters: Box<dyn Gauge>,
+    max_connections: Box<dyn Gauge>,
+    min_connections: Box<dyn Gauge>,
+    acquire_timeout: Box<dyn Gauge>,
+    idle_timeout: Box<dyn Gauge>,
 }
 
 impl PoolMetrics {
@@ -718,6 +1086,56 @@ impl PoolMetrics {
             commits: metrics.create_counter("committed_transactions".into(), None),
             reverts: metrics.create_counter("reverted_transactions".into(), None),
             drops: metrics.create_counter("dropped_transactions".into(), None),
+            pool_size: metrics.create_gauge("pool_size".into(), None),
+            idle_connections: metrics.create_gauge("idle_connections".into(), None),
+            connection_waiters: metrics.create_gauge("connection_waiters".into(), None),
+            max_connections: metrics.create_gauge("max_connections".into(), None),
+            min_connections: metrics.create_gauge("min_connections".into(), None),
+            acquire_timeout: metrics.create_gauge("acquire_timeout".into(), Some("ms".into())),
+            idle_timeout: metrics.create_gauge("idle_connection_timeout".into(), Some("s".into())),
         }
     }
+
+    /// Record the configuration of `pool`, so that it can be compared against the usage metrics.
+    ///
+    /// An idle timeout of 0 means idle connections are never closed.
+    pub(super) fn record_options(&self, pool: &Pool<Db>) {
+        let options = pool.options();
+        self.max_connections
+            .set(options.get_max_connections() as usize);
+        self.min_connections
+            .set(options.get_min_connections() as usize);
+        self.acquire_timeout
+            .set(options.get_acquire_timeout().as_millis() as usize);
+        self.idle_timeout.set(
+            options
+                .get_idle_timeout()
+                .map_or(0, |timeout| timeout.as_secs() as usize),
+        );
+        self.update_pool(pool);
+    }
+
+    /// Record the current number of open and idle connections in `pool`.
+    fn update_pool(&self, pool: &Pool<Db>) {
+        self.pool_size.set(pool.size() as usize);
+        self.idle_connections.set(pool.num_idle());
+    }
+
+    /// Count a task as waiting for a connection from `pool` until the returned guard is dropped.
+    fn wait_for_connection(&self, pool: &Pool<Db>) -> ConnectionWaiter {
+        self.update_pool(pool);
+        self.connection_waiters.update(1);
+        ConnectionWaiter(self.connection_waiters.clone())
+    }
+}
+
+/// A task waiting to acquire a connection from the pool.
+///
+/// This decrements the waiter count when dropped, even if the waiting future is cancelled.
+struct ConnectionWaiter(Box<dyn Gauge>);
+
+impl Drop for ConnectionWaiter {
+    fn drop(&mut self) {
+        self.0.update(-1);
+    }
 }
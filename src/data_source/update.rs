@@ -13,25 +13,27 @@
 //! A generic algorithm for updating a HotShot Query Service data source with new data.
 use crate::{
     availability::{
-        BlockInfo, BlockQueryData, LeafQueryData, QueryablePayload, UpdateAvailabilityData,
-        VidCommonQueryData,
+        genesis_vid_disperse, BlockInfo, BlockQueryData, LeafQueryData, QueryablePayload,
+        UpdateAvailabilityData, VidCommonQueryData,
     },
-    Leaf, Payload, VidShare,
+    data_source::storage::InvalidData,
+    types::HeightIndexed,
+    Header, Leaf, Payload, VidShare,
 };
-use anyhow::{ensure, Context};
+use anyhow::{bail, ensure, Context};
 use async_trait::async_trait;
 use futures::future::Future;
 use hotshot::types::{Event, EventType};
 use hotshot_types::event::LeafInfo;
-use hotshot_types::{
-    traits::{
-        block_contents::{BlockHeader, BlockPayload, EncodeBytes, GENESIS_VID_NUM_STORAGE_NODES},
-        node_implementation::{ConsensusTime, NodeType},
-    },
-    vid::vid_scheme,
+use hotshot_types::traits::{
+    block_contents::{BlockHeader, BlockPayload, EncodeBytes, GENESIS_VID_NUM_STORAGE_NODES},
+    node_implementation::{ConsensusTime, NodeType},
 };
+use hotshot_types::vid::{vid_scheme, VidSchemeType};
 use jf_vid::VidScheme;
-use std::iter::once;
+use serde::{Deserialize, Serialize};
+use std::{iter::once, sync::Arc, time::Instant};
+use tokio::sync::watch;
 
 /// An extension trait for types which implement the update trait for each API module.
 ///
@@ -57,12 +59,206 @@ pub trait UpdateDataSource<Types: NodeType>: UpdateAvailabilityData<Types> {
     /// If you want to update the data source with an untrusted event, for example one received from
     /// a peer over the network, you must authenticate it first.
     ///
+    /// One inconsistency is always detected: if `event` decides a leaf at a height where a different
+    /// leaf is already stored, inserting it fails with [`Reorg`](super::storage::Reorg) instead of
+    /// overwriting the stored leaf, and the update fails at that height without storing any of the
+    /// event. The divergent suffix can then be removed with
+    /// [`purge_from`](super::storage::UpdateAvailabilityStorage::purge_from). On the other hand,
+    /// events which are delivered more than once or out of order are harmless: re-inserting a leaf
+    /// which is already stored is a no-op.
+    ///
+    /// Each successful update reports its latency via
+    /// [`record_update_latency`](UpdateAvailabilityData::record_update_latency), which data sources
+    /// with status metrics record in the `update_latency` histogram. HotShot events do not carry
+    /// the time at which they were emitted or received, so this is measured from the time this
+    /// function is called until the decided blocks are committed to storage. It captures only the
+    /// processing time within the query service, not any delay between HotShot emitting the event
+    /// and the caller passing it on.
+    ///
     /// # Returns
     ///
     /// If all provided data is successfully inserted into the database, returns `Ok(())`. If any
     /// error occurred, the error is logged, and the return value is the height of the first leaf
     /// which failed to be inserted.
-    async fn update(&self, event: &Event<Types>) -> Result<(), u64>;
+    async fn update(&self, event: &Event<Types>) -> Result<(), u64> {
+        self.update_with_summary(event).await.map(|_| ())
+    }
+
+    /// Update query state based on a new consensus event, and report what was stored.
+    ///
+    /// This is the same as [`update`](Self::update), except that on success it returns an
+    /// [`UpdateSummary`] describing which leaves were inserted and which of them are missing a
+    /// payload or VID data. Missing data will eventually be fetched by data sources which support
+    /// fetching, but callers running outside of such a data source can use the summary to find out
+    /// which heights need to be backfilled from a provider.
+    async fn update_with_summary(&self, event: &Event<Types>) -> Result<UpdateSummary, u64> {
+        self.update_with_handler(event, &LogMissingData).await
+    }
+
+    /// Update query state based on a new consensus event, reporting missing data to `handler`.
+    ///
+    /// This is the same as [`update_with_summary`](Self::update_with_summary), except that once
+    /// the decided blocks have been stored, `handler` is called for each block whose payload or VID
+    /// data was not available at decide. This allows the caller to schedule a fetch of the missing
+    /// data right away, for example when HotShot delivers a decide before the payload has been
+    /// reconstructed locally. The other update methods use [`LogMissingData`], which just logs.
+    async fn update_with_handler(
+        &self,
+        event: &Event<Types>,
+        handler: &dyn MissingDataHandler,
+    ) -> Result<UpdateSummary, u64>;
+
+    /// Update query state based on a sequence of consensus events, atomically.
+    ///
+    /// This is useful for processes which buffer several events before applying them, for example
+    /// while catching up. All of the blocks decided by `events` are stored together using
+    /// [`append_chain`](UpdateAvailabilityData::append_chain), so whether the update is atomic
+    /// depends on the data source. [`FetchingDataSource`](super::FetchingDataSource), and thus all
+    /// of the data sources provided by this crate, stores the chain in a single [`Transaction`], so
+    /// the events are either all applied or not applied at all. A data source which does not
+    /// override `append_chain` appends the blocks one at a time, so if storing one of them fails,
+    /// the blocks before it remain stored.
+    ///
+    /// The same authentication caveats apply as for [`update`](Self::update).
+    ///
+    /// # Returns
+    ///
+    /// If all provided data is successfully inserted into the database, returns `Ok(())`. If any
+    /// event contains an inconsistent leaf, nothing is inserted, and the return value is the height
+    /// of the inconsistent leaf. If an error occurs while inserting, the error is logged, and the
+    /// return value is the height of the first leaf in `events`, even if some of the leaves were
+    /// stored by a data source which does not store the chain atomically.
+    async fn update_with_events(&self, events: &[Event<Types>]) -> Result<(), u64>;
+
+    /// Update query state based on a new consensus event, handling missing VID data as configured.
+    ///
+    /// This is the same as [`update_with_summary`](Self::update_with_summary), except that any
+    /// decided block (other than genesis) whose VID data is not available is handled according to
+    /// `on_missing_vid`. This is useful for nodes which must guarantee VID completeness, and would
+    /// rather halt than leave a gap in their VID store to be filled in later.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `on_missing_vid` is [`MissingVidPolicy::Error`] and VID data is missing for any
+    /// block decided by `event`, in which case nothing is inserted. Also fails if `event` contains
+    /// an inconsistent leaf, or if the data cannot be inserted, just like
+    /// [`update_with_summary`](Self::update_with_summary).
+    async fn update_with_vid_policy(
+        &self,
+        event: &Event<Types>,
+        on_missing_vid: MissingVidPolicy,
+    ) -> anyhow::Result<UpdateSummary>;
+}
+
+/// A kind of data which can be missing from a decided block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MissingData {
+    /// The block payload.
+    Payload,
+    /// The VID common data and this node's VID share.
+    Vid,
+}
+
+/// A callback for data which was not available when a block was decided.
+///
+/// See [`UpdateDataSource::update_with_handler`]. This is implemented for any function or closure
+/// taking the height of the block and the kind of missing data.
+pub trait MissingDataHandler: Send + Sync {
+    fn handle_missing(&self, height: u64, kind: MissingData);
+}
+
+impl<F> MissingDataHandler for F
+where
+    F: Fn(u64, MissingData) + Send + Sync,
+{
+    fn handle_missing(&self, height: u64, kind: MissingData) {
+        self(height, kind)
+    }
+}
+
+/// The default [`MissingDataHandler`], which just logs the missing data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogMissingData;
+
+impl MissingDataHandler for LogMissingData {
+    fn handle_missing(&self, height: u64, kind: MissingData) {
+        match kind {
+            MissingData::Payload => tracing::info!(height, "block not available at decide"),
+            MissingData::Vid => tracing::info!(height, "VID not available at decide"),
+        }
+    }
+}
+
+/// A policy check applied to block payloads before they are stored.
+///
+/// See [`with_payload_validator`](super::fetching::Builder::with_payload_validator). This is
+/// implemented for any function or closure taking the header and payload of the block.
+pub trait PayloadValidator<Types: NodeType>: Send + Sync {
+    fn validate(&self, header: &Header<Types>, payload: &Payload<Types>) -> anyhow::Result<()>;
+}
+
+impl<Types, F> PayloadValidator<Types> for F
+where
+    Types: NodeType,
+    F: Fn(&Header<Types>, &Payload<Types>) -> anyhow::Result<()> + Send + Sync,
+{
+    fn validate(&self, header: &Header<Types>, payload: &Payload<Types>) -> anyhow::Result<()> {
+        self(header, payload)
+    }
+}
+
+/// Drop the payloads in `chain` which `ds` rejects, returning the heights of the rejected blocks.
+fn reject_invalid_payloads<Types, D>(ds: &D, chain: &mut [BlockInfo<Types>]) -> Vec<u64>
+where
+    Types: NodeType,
+    D: UpdateAvailabilityData<Types> + ?Sized,
+{
+    let mut rejected = vec![];
+    for info in chain {
+        let Some(block) = &info.block else {
+            continue;
+        };
+        if let Err(err) = ds.validate_payload(block.header(), block.payload()) {
+            let height = info.height();
+            tracing::warn!(height, "rejected invalid payload: {err:#}");
+            info.block = None;
+            rejected.push(height);
+        }
+    }
+    rejected
+}
+
+/// Tell `ds` about payloads rejected by [`reject_invalid_payloads`], once their leaves are stored.
+///
+/// Failing to record a rejection does not fail the update: the leaves are already stored, and if
+/// the rejected payload is fetched later, it will be rejected (and recorded) again at that point.
+async fn record_rejected_payloads<Types, D>(ds: &D, heights: &[u64])
+where
+    Types: NodeType,
+    D: UpdateAvailabilityData<Types> + ?Sized,
+{
+    if heights.is_empty() {
+        return;
+    }
+    if let Err(err) = ds.reject_payloads(heights.to_vec()).await {
+        tracing::warn!(?heights, "failed to record rejected payloads: {err:#}");
+    }
+}
+
+/// How to handle a decided block whose VID data is not available.
+///
+/// HotShot does not guarantee that this node receives its VID share for every decided block. By
+/// default, such blocks are stored anyway, and data sources which support fetching will later
+/// retrieve the missing VID data from a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingVidPolicy {
+    /// Store the block without VID data.
+    #[default]
+    Store,
+    /// Store the block without VID data, and log a warning.
+    Warn,
+    /// Fail the update without storing anything.
+    Error,
 }
 
 #[async_trait]
@@ -71,106 +267,345 @@ where
     T: UpdateAvailabilityData<Types> + Send + Sync,
     Payload<Types>: QueryablePayload<Types>,
 {
-    async fn update(&self, event: &Event<Types>) -> Result<(), u64> {
-        if let EventType::Decide { leaf_chain, qc, .. } = &event.event {
-            // `qc` justifies the first (most recent) leaf...
-            let qcs = once((**qc).clone())
-                // ...and each leaf in the chain justifies the subsequent leaf (its parent) through
-                // `leaf.justify_qc`.
-                .chain(leaf_chain.iter().map(|leaf| leaf.leaf.justify_qc()))
-                // Put the QCs in chronological order.
-                .rev()
-                // The oldest QC is the `justify_qc` of the oldest leaf, which does not justify any
-                // leaf in the new chain, so we don't need it.
-                .skip(1);
-            for (
-                qc,
-                LeafInfo {
-                    leaf, vid_share, ..
-                },
-            ) in qcs.zip(leaf_chain.iter().rev())
-            {
-                let height = leaf.block_header().block_number();
-                let leaf_data = match LeafQueryData::new(leaf.clone(), qc.clone()) {
-                    Ok(leaf) => leaf,
-                    Err(err) => {
-                        tracing::error!(
-                            height,
-                            ?leaf,
-                            ?qc,
-                            "inconsistent leaf; cannot append leaf information: {err:#}"
-                        );
-                        return Err(leaf.block_header().block_number());
-                    }
-                };
-
-                let block_data = leaf
-                    .block_payload()
-                    .map(|payload| BlockQueryData::new(leaf.block_header().clone(), payload));
-                if block_data.is_none() {
-                    tracing::info!(height, "block not available at decide");
-                }
+    async fn update_with_handler(
+        &self,
+        event: &Event<Types>,
+        handler: &dyn MissingDataHandler,
+    ) -> Result<UpdateSummary, u64> {
+        let start = Instant::now();
+        let (mut chain, inconsistent) = decided_blocks(event, self.genesis_vid_num_storage_nodes());
+        let mut summary = UpdateSummary::new(&chain);
+        summary.rejected_payloads = reject_invalid_payloads(self, &mut chain);
+        if let Some(first) = chain.first() {
+            let height = first.height();
+            let missing_vid = missing_vid_at_decide(&chain).len();
+            if let Err(err) = self.append_chain(chain).await {
+                tracing::error!(height, "failed to append leaf information: {err:#}");
+                return Err(height);
+            }
+            self.record_update_latency(start.elapsed());
+            self.record_missing_vid_at_decide(missing_vid);
+            record_rejected_payloads(self, &summary.rejected_payloads).await;
+        }
+        summary.report_missing(handler);
+        if let Some(height) = inconsistent {
+            return Err(height);
+        }
+        Ok(summary)
+    }
 
-                let (vid_common, vid_share) = if let Some(vid_share) = vid_share {
-                    (
-                        Some(VidCommonQueryData::new(
-                            leaf.block_header().clone(),
-                            vid_share.common.clone(),
-                        )),
-                        Some(vid_share.share.clone()),
-                    )
-                } else if leaf.view_number().u64() == 0 {
-                    // HotShot does not run VID in consensus for the genesis block. In this case,
-                    // the block payload is guaranteed to always be empty, so VID isn't really
-                    // necessary. But for consistency, we will still store the VID dispersal data,
-                    // computing it ourselves based on the well-known genesis VID commitment.
-                    match genesis_vid(leaf) {
-                        Ok((common, share)) => (Some(common), Some(share)),
-                        Err(err) => {
-                            tracing::warn!("failed to compute genesis VID: {err:#}");
-                            (None, None)
-                        }
-                    }
-                } else {
-                    (None, None)
-                };
-                if vid_common.is_none() {
-                    tracing::info!(height, "VID not available at decide");
-                }
+    async fn update_with_events(&self, events: &[Event<Types>]) -> Result<(), u64> {
+        let start = Instant::now();
+        let mut chain = vec![];
+        for event in events {
+            let (blocks, inconsistent) =
+                decided_blocks(event, self.genesis_vid_num_storage_nodes());
+            if let Some(height) = inconsistent {
+                // Don't store anything if any of the events is inconsistent.
+                return Err(height);
+            }
+            chain.extend(blocks);
+        }
 
-                if let Err(err) = self
-                    .append(BlockInfo::new(leaf_data, block_data, vid_common, vid_share))
-                    .await
-                {
-                    tracing::error!(height, "failed to append leaf information: {err:#}");
-                    return Err(leaf.block_header().block_number());
+        let Some(first) = chain.first() else {
+            return Ok(());
+        };
+        let height = first.height();
+        let summary = UpdateSummary::new(&chain);
+        let rejected = reject_invalid_payloads(self, &mut chain);
+        let missing_vid = missing_vid_at_decide(&chain).len();
+        if let Err(err) = self.append_chain(chain).await {
+            tracing::error!(height, "failed to append leaf information: {err:#}");
+            return Err(height);
+        }
+        self.record_update_latency(start.elapsed());
+        self.record_missing_vid_at_decide(missing_vid);
+        record_rejected_payloads(self, &rejected).await;
+        summary.report_missing(&LogMissingData);
+        Ok(())
+    }
+
+    async fn update_with_vid_policy(
+        &self,
+        event: &Event<Types>,
+        on_missing_vid: MissingVidPolicy,
+    ) -> anyhow::Result<UpdateSummary> {
+        let start = Instant::now();
+        let (mut chain, inconsistent) = decided_blocks(event, self.genesis_vid_num_storage_nodes());
+        let missing_vid = missing_vid_at_decide(&chain);
+        if !missing_vid.is_empty() {
+            match on_missing_vid {
+                MissingVidPolicy::Store => {}
+                MissingVidPolicy::Warn => {
+                    tracing::warn!(?missing_vid, "VID not available at decide");
+                }
+                MissingVidPolicy::Error => {
+                    bail!("VID not available at decide for blocks {missing_vid:?}");
                 }
             }
         }
-        Ok(())
+
+        let mut summary = UpdateSummary::new(&chain);
+        summary.rejected_payloads = reject_invalid_payloads(self, &mut chain);
+        if let Some(first) = chain.first() {
+            let height = first.height();
+            self.append_chain(chain).await.context(format!(
+                "failed to append leaf information at height {height}"
+            ))?;
+            self.record_update_latency(start.elapsed());
+            self.record_missing_vid_at_decide(missing_vid.len());
+            record_rejected_payloads(self, &summary.rejected_payloads).await;
+        }
+        summary.report_missing(&LogMissingData);
+        if let Some(height) = inconsistent {
+            bail!("inconsistent leaf at height {height}");
+        }
+        Ok(summary)
+    }
+}
+
+/// The heights of the decided blocks in `chain` whose VID data is not available.
+///
+/// HotShot does not run VID for the genesis block, so it is not missing VID data even if we failed
+/// to compute it ourselves.
+fn missing_vid_at_decide<Types: NodeType>(chain: &[BlockInfo<Types>]) -> Vec<u64> {
+    chain
+        .iter()
+        .filter(|info| info.vid_common.is_none() && info.leaf.leaf().view_number().u64() != 0)
+        .map(|info| info.height())
+        .collect()
+}
+
+/// A report of the data stored by [`UpdateDataSource::update_with_summary`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Heights of the leaves which were inserted.
+    pub inserted_leaves: Vec<u64>,
+    /// Heights of inserted leaves whose block payload was not available at decide.
+    pub missing_payloads: Vec<u64>,
+    /// Heights of inserted leaves whose VID data was not available at decide.
+    pub missing_vid: Vec<u64>,
+    /// Heights of inserted leaves whose block payload was rejected by
+    /// [`validate_payload`](UpdateAvailabilityData::validate_payload), and so was not stored.
+    pub rejected_payloads: Vec<u64>,
+}
+
+impl UpdateSummary {
+    fn new<Types: NodeType>(chain: &[BlockInfo<Types>]) -> Self {
+        let mut summary = Self::default();
+        for info in chain {
+            let height = info.height();
+            summary.inserted_leaves.push(height);
+            if info.block.is_none() {
+                summary.missing_payloads.push(height);
+            }
+            if info.vid_common.is_none() {
+                summary.missing_vid.push(height);
+            }
+        }
+        summary
+    }
+
+    fn report_missing(&self, handler: &dyn MissingDataHandler) {
+        for height in &self.missing_payloads {
+            handler.handle_missing(*height, MissingData::Payload);
+        }
+        for height in &self.missing_vid {
+            handler.handle_missing(*height, MissingData::Vid);
+        }
+    }
+}
+
+/// Extract information about each block decided by `event`, in chronological order.
+///
+/// If `event` contains an inconsistent leaf, only the blocks preceding that leaf are returned,
+/// along with the height of the inconsistent leaf.
+///
+/// `genesis_vid_num_storage_nodes` is the number of storage nodes to use when computing VID data
+/// for the genesis block, if it is known.
+fn decided_blocks<Types: NodeType>(
+    event: &Event<Types>,
+    genesis_vid_num_storage_nodes: Option<usize>,
+) -> (Vec<BlockInfo<Types>>, Option<u64>)
+where
+    Payload<Types>: QueryablePayload<Types>,
+{
+    let EventType::Decide { leaf_chain, qc, .. } = &event.event else {
+        return (vec![], None);
+    };
+    // `qc` justifies the first (most recent) leaf...
+    let qcs = once((**qc).clone())
+        // ...and each leaf in the chain justifies the subsequent leaf (its parent) through
+        // `leaf.justify_qc`.
+        .chain(leaf_chain.iter().map(|leaf| leaf.leaf.justify_qc()))
+        // Put the QCs in chronological order.
+        .rev()
+        // The oldest QC is the `justify_qc` of the oldest leaf, which does not justify any
+        // leaf in the new chain, so we don't need it.
+        .skip(1);
+
+    // Collect information about each block in the chain, stopping at the first leaf which is
+    // inconsistent.
+    let mut chain = vec![];
+    for (
+        qc,
+        LeafInfo {
+            leaf, vid_share, ..
+        },
+    ) in qcs.zip(leaf_chain.iter().rev())
+    {
+        let height = leaf.block_header().block_number();
+        let leaf_data = match LeafQueryData::new(leaf.clone(), qc.clone()) {
+            Ok(leaf) => leaf,
+            Err(err) => {
+                tracing::error!(
+                    height,
+                    ?leaf,
+                    ?qc,
+                    "inconsistent leaf; cannot append leaf information: {err:#}"
+                );
+                return (chain, Some(height));
+            }
+        };
+
+        let block_data = leaf
+            .block_payload()
+            .map(|payload| BlockQueryData::new(leaf.block_header().clone(), payload));
+
+        let (vid_common, vid_share) = if let Some(vid_share) = vid_share {
+            (
+                Some(VidCommonQueryData::new(
+                    leaf.block_header().clone(),
+                    vid_share.common.clone(),
+                )),
+                Some(vid_share.share.clone()),
+            )
+        } else if leaf.view_number().u64() == 0 {
+            // HotShot does not run VID in consensus for the genesis block. In this case,
+            // the block payload is guaranteed to always be empty, so VID isn't really
+            // necessary. But for consistency, we will still store the VID dispersal data,
+            // computing it ourselves based on the well-known genesis VID commitment.
+            match genesis_vid(leaf, genesis_vid_num_storage_nodes) {
+                Ok((common, share)) => (Some(common), Some(share)),
+                Err(err) => {
+                    tracing::warn!("failed to compute genesis VID: {err:#}");
+                    (None, None)
+                }
+            }
+        } else {
+            (None, None)
+        };
+
+        chain.push(BlockInfo::new(leaf_data, block_data, vid_common, vid_share));
     }
+
+    (chain, None)
 }
 
 fn genesis_vid<Types: NodeType>(
     leaf: &Leaf<Types>,
+    num_storage_nodes: Option<usize>,
 ) -> anyhow::Result<(VidCommonQueryData<Types>, VidShare)> {
+    let num_storage_nodes = num_storage_nodes.unwrap_or_else(|| {
+        tracing::warn!(
+            GENESIS_VID_NUM_STORAGE_NODES,
+            "number of VID storage nodes not configured, computing genesis VID with the default"
+        );
+        GENESIS_VID_NUM_STORAGE_NODES
+    });
     let payload = Payload::<Types>::empty().0;
-    let bytes = payload.encode();
-    let mut disperse = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES)
-        .disperse(bytes)
-        .context("unable to compute VID dispersal for genesis block")?;
+    let disperse = genesis_vid_disperse(num_storage_nodes, &payload.encode())?;
     ensure!(
         disperse.commit == leaf.block_header().payload_commitment(),
-        "computed VID commit {} for genesis block does not match header commit {}",
+        "computed VID commit {} for genesis block with {num_storage_nodes} storage nodes does not \
+         match header commit {}",
         disperse.commit,
         leaf.block_header().payload_commitment()
     );
     Ok((
-        VidCommonQueryData::new(leaf.block_header().clone(), disperse.common),
-        disperse.shares.remove(0),
+        VidCommonQueryData::new(leaf.block_header().clone(), disperse.common.clone()),
+        disperse.share.clone(),
     ))
 }
 
+/// Check that the objects in `info` are consistent with each other.
+///
+/// This checks that the leaf is certified by its own QC and, if the block is present, that it
+/// matches the leaf and that its payload matches the payload commitment in the header. Recomputing
+/// the payload commitment requires the number of VID storage nodes, which is taken from the VID
+/// common data, or for the genesis block, from `genesis_vid_num_storage_nodes`. A non-genesis block
+/// without VID common data cannot be verified, and is rejected.
+pub(crate) fn verify_block_info<Types: NodeType>(
+    info: &BlockInfo<Types>,
+    genesis_vid_num_storage_nodes: Option<usize>,
+) -> Result<(), InvalidData>
+where
+    Payload<Types>: QueryablePayload<Types>,
+{
+    verify_block_parts(
+        &info.leaf,
+        info.block.as_ref(),
+        info.vid_common.as_ref(),
+        genesis_vid_num_storage_nodes,
+    )
+}
+
+/// Check that a leaf and, if present, its block are consistent with each other.
+///
+/// This performs the same checks as [`verify_block_info`], on borrowed objects.
+pub(crate) fn verify_block_parts<Types: NodeType>(
+    leaf: &LeafQueryData<Types>,
+    block: Option<&BlockQueryData<Types>>,
+    vid_common: Option<&VidCommonQueryData<Types>>,
+    genesis_vid_num_storage_nodes: Option<usize>,
+) -> Result<(), InvalidData>
+where
+    Payload<Types>: QueryablePayload<Types>,
+{
+    let height = leaf.height();
+    let invalid = |reason: String| InvalidData { height, reason };
+
+    if leaf.qc().data.leaf_commit != leaf.hash() {
+        return Err(invalid(format!(
+            "QC references leaf {}, but leaf is {}",
+            leaf.qc().data.leaf_commit,
+            leaf.hash()
+        )));
+    }
+
+    let Some(block) = block else {
+        return Ok(());
+    };
+    if block.hash() != leaf.block_hash() {
+        return Err(invalid(format!(
+            "block {} does not match leaf header {}",
+            block.hash(),
+            leaf.block_hash()
+        )));
+    }
+    let num_storage_nodes = match vid_common {
+        Some(common) => VidSchemeType::get_num_storage_nodes(common.common()) as usize,
+        None if height == 0 => {
+            genesis_vid_num_storage_nodes.unwrap_or(GENESIS_VID_NUM_STORAGE_NODES)
+        }
+        None => {
+            return Err(invalid(
+                "cannot verify payload without VID common data".into(),
+            ))
+        }
+    };
+    let commit = vid_scheme(num_storage_nodes)
+        .commit_only(block.payload().encode())
+        .map_err(|err| invalid(format!("unable to compute VID commitment: {err}")))?;
+    if commit != block.payload_hash() {
+        return Err(invalid(format!(
+            "payload commitment {commit} does not match header commitment {}",
+            block.payload_hash()
+        )));
+    }
+    Ok(())
+}
+
 /// A data source with an atomic transaction-based synchronization interface.
 ///
 /// Changes are made to a versioned data source through a [`Transaction`]. Any changes made in a
@@ -207,6 +642,19 @@ pub trait VersionedDataSource: Send + Sync {
     ///
     /// Read-only transactions do not need to be committed, and reverting has no effect.
     fn read(&self) -> impl Future<Output = anyhow::Result<Self::ReadOnly<'_>>> + Send;
+
+    /// Start a read-only transaction which observes at least the changes up to `marker`.
+    ///
+    /// `marker` should be the result of [`commit_with_version`](Transaction::commit_with_version)
+    /// on a transaction from this same data source. This waits until all changes up to and
+    /// including that commit have been applied, and then behaves exactly like
+    /// [`read`](Self::read). This makes it possible to read back the results of a write, even if
+    /// the read is served by a different connection than the write was, without racing the
+    /// commit.
+    fn read_at_least(
+        &self,
+        marker: CommitMarker,
+    ) -> impl Future<Output = anyhow::Result<Self::ReadOnly<'_>>> + Send;
 }
 
 /// A unit of atomicity for updating a shared data sourec.
@@ -217,6 +665,341 @@ pub trait VersionedDataSource: Send + Sync {
 /// rolled back ([revert](Self::revert)) so that they are never written back to storage and are no
 /// longer reflected even through the data source object which was used to make the changes.
 pub trait Transaction: Send + Sync {
-    fn commit(self) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Commit this transaction, returning a marker identifying the resulting version of storage.
+    ///
+    /// The returned marker can be passed to
+    /// [`read_at_least`](VersionedDataSource::read_at_least) to obtain a read-only transaction
+    /// which is guaranteed to reflect the changes made in this transaction.
+    fn commit_with_version(self) -> impl Future<Output = anyhow::Result<CommitMarker>> + Send;
+
+    fn commit(self) -> impl Future<Output = anyhow::Result<()>> + Send
+    where
+        Self: Sized,
+    {
+        async move {
+            self.commit_with_version().await?;
+            Ok(())
+        }
+    }
+
     fn revert(self) -> impl Future + Send;
 }
+
+/// A point in the commit history of a [`VersionedDataSource`].
+///
+/// Markers from the same data source are totally ordered: a transaction which commits later
+/// receives a greater marker. Markers are only meaningful to the data source which issued them,
+/// and to clones of it sharing the same storage.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct CommitMarker(u64);
+
+impl CommitMarker {
+    /// The number of commits up to and including the one identified by this marker.
+    pub fn version(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Bookkeeping for the [`CommitMarker`]s issued by a storage implementation.
+#[derive(Clone, Debug)]
+pub(crate) struct CommitTracker(Arc<watch::Sender<u64>>);
+
+impl Default for CommitTracker {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(0).0))
+    }
+}
+
+impl CommitTracker {
+    /// Record a commit, returning its marker.
+    ///
+    /// This must only be called once the changes made by the commit are visible to new readers.
+    pub(crate) fn commit(&self) -> CommitMarker {
+        let mut version = 0;
+        self.0.send_modify(|current| {
+            *current += 1;
+            version = *current;
+        });
+        CommitMarker(version)
+    }
+
+    /// The marker of the latest recorded commit.
+    pub(crate) fn current(&self) -> CommitMarker {
+        CommitMarker(*self.0.borrow())
+    }
+
+    /// Wait until the commit identified by `marker` has been recorded.
+    pub(crate) async fn wait(&self, marker: CommitMarker) {
+        let mut rx = self.0.subscribe();
+        // The sender is never dropped while `self` is alive, so this cannot fail.
+        rx.wait_for(|version| *version >= marker.0).await.ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{
+        consensus::{MockDataSource, MockNetwork},
+        mocks::{MockPayload, MockTypes},
+        setup_test,
+    };
+    use futures::{future, stream::StreamExt};
+    use hotshot_example_types::{
+        node_types::TestVersions,
+        state_types::{TestInstanceState, TestValidatedState},
+    };
+    use std::sync::Mutex;
+
+    /// A data source which only validates payloads.
+    struct Validating<V>(V);
+
+    impl<V: PayloadValidator<MockTypes>> UpdateAvailabilityData<MockTypes> for Validating<V> {
+        async fn append(&self, _: BlockInfo<MockTypes>) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn validate_payload(
+            &self,
+            header: &Header<MockTypes>,
+            payload: &Payload<MockTypes>,
+        ) -> anyhow::Result<()> {
+            self.0.validate(header, payload)
+        }
+    }
+
+    /// A data source which just records the heights of the blocks appended to it.
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<u64>>);
+
+    impl UpdateAvailabilityData<MockTypes> for Recording {
+        async fn append(&self, info: BlockInfo<MockTypes>) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(info.height());
+            Ok(())
+        }
+    }
+
+    /// A data source which records blocks like [`Recording`], but fails to append a given height.
+    ///
+    /// This uses the default, non-atomic implementation of
+    /// [`append_chain`](UpdateAvailabilityData::append_chain).
+    struct FailingAt(u64, Recording);
+
+    impl UpdateAvailabilityData<MockTypes> for FailingAt {
+        async fn append(&self, info: BlockInfo<MockTypes>) -> anyhow::Result<()> {
+            ensure!(info.height() != self.0, "failed to append {}", self.0);
+            self.1.append(info).await
+        }
+    }
+
+    /// Collect the first `n` decide events from a mock consensus network.
+    async fn decide_events(n: usize) -> Vec<Event<MockTypes>> {
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        let events = network.handle().event_stream();
+        network.start().await;
+        events
+            .filter(|event| future::ready(matches!(event.event, EventType::Decide { .. })))
+            .take(n)
+            .collect()
+            .await
+    }
+
+    /// The heights of the leaves decided by `event`, in chronological order.
+    fn decided_heights(event: &Event<MockTypes>) -> Vec<u64> {
+        let EventType::Decide { leaf_chain, .. } = &event.event else {
+            panic!("not a decide event: {event:?}");
+        };
+        leaf_chain
+            .iter()
+            .rev()
+            .map(|info| info.leaf.block_header().block_number())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_update_with_events() {
+        setup_test();
+
+        let mut events = decide_events(3).await;
+
+        // Make the second event inconsistent, by replacing its QC with the QC from the first
+        // event, which certifies a different leaf.
+        let EventType::Decide { qc, .. } = &events[0].event else {
+            unreachable!();
+        };
+        let qc = qc.clone();
+        let EventType::Decide { qc: bad_qc, .. } = &mut events[1].event else {
+            unreachable!();
+        };
+        *bad_qc = qc;
+        // The QC of a decide justifies the most recent leaf, which is thus the inconsistent one.
+        let inconsistent = *decided_heights(&events[1]).last().unwrap();
+
+        // None of the events are applied, not even the ones before the inconsistent one.
+        let ds = Recording::default();
+        assert_eq!(ds.update_with_events(&events).await, Err(inconsistent));
+        assert_eq!(*ds.0.lock().unwrap(), Vec::<u64>::new());
+
+        // Without the inconsistent event, all the other blocks are stored, in order.
+        events.remove(1);
+        ds.update_with_events(&events).await.unwrap();
+        let expected = events.iter().flat_map(decided_heights).collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(*ds.0.lock().unwrap(), expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_update_with_events_not_atomic() {
+        setup_test();
+
+        let events = decide_events(3).await;
+        let heights = events.iter().flat_map(decided_heights).collect::<Vec<_>>();
+        assert!(heights.len() >= 2, "{heights:?}");
+
+        // A data source which appends blocks one at a time keeps the blocks stored before the
+        // failure, even though the update as a whole fails.
+        let fail_at = heights.len() / 2;
+        let ds = FailingAt(heights[fail_at], Recording::default());
+        assert_eq!(ds.update_with_events(&events).await, Err(heights[0]));
+        assert_eq!(*ds.1 .0.lock().unwrap(), heights[..fail_at]);
+    }
+
+    /// Replace the leaf chain of a decide `event` with just its most recent leaf, modified by `f`.
+    fn with_latest_leaf(
+        event: &Event<MockTypes>,
+        f: impl FnOnce(&mut LeafInfo<MockTypes>),
+    ) -> Event<MockTypes> {
+        let mut event = event.clone();
+        let EventType::Decide { leaf_chain, .. } = &mut event.event else {
+            panic!("not a decide event: {event:?}");
+        };
+        let mut info = leaf_chain[0].clone();
+        f(&mut info);
+        *leaf_chain = Arc::new(vec![info]);
+        event
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_update_summary() {
+        setup_test();
+
+        let event = decide_events(1).await.remove(0);
+        let height = *decided_heights(&event).last().unwrap();
+
+        // A block missing both its payload and its VID data is reported as missing both.
+        let event_without_data = with_latest_leaf(&event, |info| {
+            info.leaf.unfill_block_payload();
+            info.vid_share = None;
+        });
+        let ds = Recording::default();
+        let summary = ds.update_with_summary(&event_without_data).await.unwrap();
+        assert_eq!(
+            summary,
+            UpdateSummary {
+                inserted_leaves: vec![height],
+                missing_payloads: vec![height],
+                missing_vid: vec![height],
+                rejected_payloads: vec![],
+            }
+        );
+
+        // A block with its payload but not its VID data is only reported as missing VID.
+        let event_without_vid = with_latest_leaf(&event, |info| {
+            assert!(info.leaf.block_payload().is_some());
+            info.vid_share = None;
+        });
+        let summary = ds.update_with_summary(&event_without_vid).await.unwrap();
+        assert_eq!(
+            summary,
+            UpdateSummary {
+                inserted_leaves: vec![height],
+                missing_payloads: vec![],
+                missing_vid: vec![height],
+                rejected_payloads: vec![],
+            }
+        );
+        assert_eq!(*ds.0.lock().unwrap(), [height, height]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_genesis_vid_num_storage_nodes() {
+        setup_test();
+
+        let leaf = Leaf::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+
+        // With the number of storage nodes used to build the genesis header, or with the default
+        // when it is not configured, we get VID data matching the header.
+        let (common, _) = genesis_vid(&leaf, Some(GENESIS_VID_NUM_STORAGE_NODES)).unwrap();
+        assert_eq!(
+            common.payload_hash(),
+            leaf.block_header().payload_commitment()
+        );
+        let (default, _) = genesis_vid(&leaf, None).unwrap();
+        assert_eq!(common, default);
+
+        // With a different number of storage nodes, the commitment does not match.
+        genesis_vid(&leaf, Some(GENESIS_VID_NUM_STORAGE_NODES + 1)).unwrap_err();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reject_invalid_payloads() {
+        setup_test();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let block = BlockQueryData::<MockTypes>::genesis(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let info = BlockInfo::new(leaf, Some(block), None, None);
+
+        // Payloads which the validator accepts are kept.
+        let ds = Validating(|_: &Header<MockTypes>, _: &MockPayload| Ok(()));
+        let mut chain = vec![info.clone()];
+        assert_eq!(reject_invalid_payloads(&ds, &mut chain), Vec::<u64>::new());
+        assert_eq!(chain[0].block, info.block);
+
+        // Rejected payloads are dropped, but the leaf is kept.
+        let ds = Validating(|_: &Header<MockTypes>, payload: &MockPayload| {
+            ensure!(!payload.transactions.is_empty(), "empty payload");
+            Ok(())
+        });
+        let mut chain = vec![info.clone()];
+        assert_eq!(reject_invalid_payloads(&ds, &mut chain), [0]);
+        assert_eq!(chain[0].leaf, info.leaf);
+        assert_eq!(chain[0].block, None);
+    }
+
+    #[test]
+    fn test_report_missing() {
+        let summary = UpdateSummary {
+            inserted_leaves: vec![1, 2, 3],
+            missing_payloads: vec![1, 3],
+            missing_vid: vec![2],
+            ..Default::default()
+        };
+
+        let missing = std::sync::Mutex::new(vec![]);
+        summary.report_missing(&|height: u64, kind: MissingData| {
+            missing.lock().unwrap().push((height, kind))
+        });
+        assert_eq!(
+            missing.into_inner().unwrap(),
+            [
+                (1, MissingData::Payload),
+                (3, MissingData::Payload),
+                (2, MissingData::Vid)
+            ]
+        );
+    }
+}
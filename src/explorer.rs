@@ -46,6 +46,7 @@ pub enum Error {
     GetTransactionDetail(GetTransactionDetailError),
     GetTransactionSummaries(GetTransactionSummariesError),
     GetExplorerSummary(GetExplorerSummaryError),
+    GetBlockStatistics(GetBlockStatisticsError),
     GetSearchResults(GetSearchResultsError),
 }
 
@@ -57,6 +58,7 @@ impl Error {
             Error::GetTransactionDetail(e) => e.status(),
             Error::GetTransactionSummaries(e) => e.status(),
             Error::GetExplorerSummary(e) => e.status(),
+            Error::GetBlockStatistics(e) => e.status(),
             Error::GetSearchResults(e) => e.status(),
         }
     }
@@ -70,6 +72,7 @@ impl Display for Error {
             Error::GetTransactionDetail(e) => e.fmt(f),
             Error::GetTransactionSummaries(e) => e.fmt(f),
             Error::GetExplorerSummary(e) => e.fmt(f),
+            Error::GetBlockStatistics(e) => e.fmt(f),
             Error::GetSearchResults(e) => e.fmt(f),
         }
     }
@@ -83,6 +86,7 @@ impl std::error::Error for Error {
             Error::GetTransactionDetail(e) => Some(e),
             Error::GetTransactionSummaries(e) => Some(e),
             Error::GetExplorerSummary(e) => Some(e),
+            Error::GetBlockStatistics(e) => Some(e),
             Error::GetSearchResults(e) => Some(e),
         }
     }
@@ -128,6 +132,30 @@ where
     }
 }
 
+/// [BlockSummaryPageResponse] is a struct that represents the response from the
+/// `get_block_summary_page` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct BlockSummaryPageResponse<Types: NodeType>
+where
+    Header<Types>: ExplorerHeader<Types>,
+{
+    pub block_summaries: Vec<BlockSummary<Types>>,
+    pub total_count: u64,
+}
+
+impl<Types: NodeType> From<BlockSummaryPage<Types>> for BlockSummaryPageResponse<Types>
+where
+    Header<Types>: ExplorerHeader<Types>,
+{
+    fn from(page: BlockSummaryPage<Types>) -> Self {
+        Self {
+            block_summaries: page.block_summaries,
+            total_count: page.total_count,
+        }
+    }
+}
+
 /// [TransactionDetailResponse] is a struct that represents the response from the
 /// `get_transaction_detail` endpoint.
 #[derive(Debug, Serialize, Deserialize)]
@@ -190,6 +218,19 @@ where
     }
 }
 
+/// [BlockStatisticsResponse] is a struct that represents the response from the
+/// `get_block_statistics` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockStatisticsResponse {
+    pub block_statistics: BlockStatistics,
+}
+
+impl From<BlockStatistics> for BlockStatisticsResponse {
+    fn from(block_statistics: BlockStatistics) -> Self {
+        Self { block_statistics }
+    }
+}
+
 /// [SearchResultResponse] is a struct that represents the response from the
 /// `get_search_result` endpoint.
 #[derive(Debug, Serialize, Deserialize)]
@@ -212,6 +253,28 @@ where
     }
 }
 
+/// [SearchMatchResponse] is a struct that represents the response from the
+/// `get_search_match` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SearchMatchResponse<Types: NodeType>
+where
+    Header<Types>: ExplorerHeader<Types>,
+    Transaction<Types>: ExplorerTransaction,
+{
+    pub search_match: SearchMatch<Types>,
+}
+
+impl<Types: NodeType> From<SearchMatch<Types>> for SearchMatchResponse<Types>
+where
+    Header<Types>: ExplorerHeader<Types>,
+    Transaction<Types>: ExplorerTransaction,
+{
+    fn from(search_match: SearchMatch<Types>) -> Self {
+        Self { search_match }
+    }
+}
+
 fn validate_limit(
     limit: Result<usize, tide_disco::RequestError>,
 ) -> Result<NonZeroUsize, InvalidLimit> {
@@ -297,6 +360,24 @@ where
             }
             .boxed()
         })?
+        .get("get_block_summary_page", move |req, state| {
+            async move {
+                let limit = validate_limit(req.integer_param("limit"))
+                    .map_err(GetBlockSummariesError::InvalidLimit)
+                    .map_err(Error::GetBlockSummaries)?;
+                let offset = req
+                    .integer_param("offset")
+                    .map_err(|_| GetBlockSummariesError::InvalidLimit(InvalidLimit {}))
+                    .map_err(Error::GetBlockSummaries)?;
+
+                state
+                    .get_block_summary_page(GetBlockSummaryPageRequest { offset, limit })
+                    .await
+                    .map(BlockSummaryPageResponse::from)
+                    .map_err(Error::GetBlockSummaries)
+            }
+            .boxed()
+        })?
         .get("get_transaction_detail", move |req, state| {
             async move {
                 state
@@ -370,23 +451,59 @@ where
             }
             .boxed()
         })?
+        .get("get_block_statistics", move |req, state| {
+            async move {
+                let window = validate_limit(req.integer_param("window"))
+                    .map_err(GetBlockStatisticsError::InvalidLimit)
+                    .map_err(Error::GetBlockStatistics)?;
+
+                state
+                    .get_block_statistics(GetBlockStatisticsRequest { window })
+                    .await
+                    .map(BlockStatisticsResponse::from)
+                    .map_err(Error::GetBlockStatistics)
+            }
+            .boxed()
+        })?
         .get("get_search_result", move |req, state| {
             async move {
                 let query = req
-                    .tagged_base64_param("query")
+                    .string_param("query")
                     .map_err(|err| {
                         tracing::error!("query param error: {}", err);
-                        GetSearchResultsError::InvalidQuery(errors::BadQuery {})
+                        errors::BadQuery {}
                     })
+                    .and_then(|query| query.parse::<SearchQuery>())
+                    .map_err(GetSearchResultsError::InvalidQuery)
                     .map_err(Error::GetSearchResults)?;
 
                 state
-                    .get_search_results(query.clone())
+                    .get_search_results(query)
                     .await
                     .map(SearchResultResponse::from)
                     .map_err(Error::GetSearchResults)
             }
             .boxed()
+        })?
+        .get("get_search_match", move |req, state| {
+            async move {
+                let query = req
+                    .string_param("query")
+                    .map_err(|err| {
+                        tracing::error!("query param error: {}", err);
+                        errors::BadQuery {}
+                    })
+                    .and_then(|query| query.parse::<SearchQuery>())
+                    .map_err(GetSearchResultsError::InvalidQuery)
+                    .map_err(Error::GetSearchResults)?;
+
+                state
+                    .get_search_match(query)
+                    .await
+                    .map(SearchMatchResponse::from)
+                    .map_err(Error::GetSearchResults)
+            }
+            .boxed()
         })?;
     Ok(api)
 }
@@ -506,6 +623,60 @@ mod test {
                     >= num_blocks - 1
             );
         }
+        {
+            // Page through the latest block summaries.
+            let first_page: BlockSummaryPageResponse<MockTypes> =
+                client.get("blocks/page/0/5").send().await.unwrap();
+            assert!(first_page.total_count >= num_blocks);
+            assert_eq!(
+                first_page.block_summaries.len(),
+                min(first_page.total_count as usize, 5)
+            );
+            assert_eq!(
+                first_page.block_summaries[0].height,
+                first_page.total_count - 1
+            );
+
+            let second_page: BlockSummaryPageResponse<MockTypes> =
+                client.get("blocks/page/5/5").send().await.unwrap();
+            if let Some(first) = second_page.block_summaries.first() {
+                assert_eq!(first.height, second_page.total_count - 6);
+            }
+
+            // Paging past the first block yields an empty page, not an error.
+            let empty_page: BlockSummaryPageResponse<MockTypes> = client
+                .get(format!("blocks/page/{}/5", first_page.total_count + 10).as_str())
+                .send()
+                .await
+                .unwrap();
+            assert!(empty_page.block_summaries.is_empty());
+            assert!(empty_page.total_count >= first_page.total_count);
+        }
+
+        {
+            // Statistics over the latest blocks cover at most the requested
+            // window, and every block has a known size and transaction count.
+            let stats: BlockStatisticsResponse = client.get("stats/5").send().await.unwrap();
+            let stats = stats.block_statistics;
+            assert!(stats.num_blocks > 0);
+            assert!(stats.num_blocks <= 5);
+            assert!(stats.block_transactions.mean.is_some());
+            assert!(stats.block_transactions.median.is_some());
+            assert!(stats.block_size.max.is_some());
+
+            // The window is bounded like the other limits.
+            client
+                .get::<BlockStatisticsResponse>("stats/0")
+                .send()
+                .await
+                .unwrap_err();
+            client
+                .get::<BlockStatisticsResponse>("stats/101")
+                .send()
+                .await
+                .unwrap_err();
+        }
+
         let get_search_response: SearchResultResponse<MockTypes> = client
             .get(format!("search/{}", latest_block.hash).as_str())
             .send()
@@ -514,6 +685,58 @@ mod test {
 
         assert!(!get_search_response.search_results.blocks.is_empty());
 
+        // The same block can be found by its height.
+        let get_search_response: SearchResultResponse<MockTypes> = client
+            .get(format!("search/{}", latest_block.height).as_str())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(get_search_response.search_results.blocks.len(), 1);
+        assert_eq!(
+            get_search_response.search_results.blocks[0].hash,
+            latest_block.hash
+        );
+
+        // Resolving a query to a single match stops at the first kind of
+        // entity it identifies.
+        for query in [
+            latest_block.height.to_string(),
+            latest_block.hash.to_string(),
+            SearchQuery::Hash(latest_block.hash.into()).to_string(),
+        ] {
+            let search_match: SearchMatchResponse<MockTypes> = client
+                .get(format!("search/match/{query}").as_str())
+                .send()
+                .await
+                .unwrap();
+            match search_match.search_match {
+                SearchMatch::Block(block) => assert_eq!(block.hash, latest_block.hash),
+                other => panic!("expected block match for {query}, got {other:?}"),
+            }
+        }
+        let search_match: SearchMatchResponse<MockTypes> = client
+            .get(format!("search/match/{}", SearchQuery::Hash([0xff; 32])).as_str())
+            .send()
+            .await
+            .unwrap();
+        assert!(matches!(search_match.search_match, SearchMatch::NotFound));
+        client
+            .get::<SearchMatchResponse<MockTypes>>("search/match/not-a-hash")
+            .send()
+            .await
+            .unwrap_err();
+
+        // An untagged hash matches a block, but not a leaf or a transaction.
+        let block_hash: [u8; 32] = latest_block.hash.into();
+        let get_search_response: SearchResultResponse<MockTypes> = client
+            .get(format!("search/{}", SearchQuery::Hash(block_hash)).as_str())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(get_search_response.search_results.blocks.len(), 1);
+        assert!(get_search_response.search_results.leaves.is_empty());
+        assert!(get_search_response.search_results.transactions.is_empty());
+
         if num_transactions > 0 {
             let last_transaction = latest_transactions.first().unwrap();
             let transaction_detail_response: TransactionDetailResponse<MockTypes> = client
@@ -564,6 +787,20 @@ mod test {
                 last_transaction.time
             );
 
+            // The transaction's hash resolves to the transaction itself.
+            let search_match: SearchMatchResponse<MockTypes> = client
+                .get(format!("search/match/{}", last_transaction.hash).as_str())
+                .send()
+                .await
+                .unwrap();
+            match search_match.search_match {
+                SearchMatch::Transaction(txn) => assert_eq!(txn.hash, last_transaction.hash),
+                SearchMatch::Ambiguous(txns) => {
+                    assert!(txns.iter().all(|txn| txn.hash == last_transaction.hash))
+                }
+                other => panic!("expected transaction match, got {other:?}"),
+            }
+
             // Transactions Summaries - No Filter
             let n_txns = num_txns_per_block();
 
@@ -12,11 +12,12 @@
 
 use super::{
     query_data::{
-        BlockDetail, BlockIdentifier, BlockSummary, ExplorerSummary, GetBlockDetailError,
-        GetBlockSummariesError, GetBlockSummariesRequest, GetExplorerSummaryError,
-        GetSearchResultsError, GetTransactionDetailError, GetTransactionSummariesError,
-        GetTransactionSummariesRequest, SearchResult, TransactionDetailResponse,
-        TransactionIdentifier, TransactionSummary,
+        BlockDetail, BlockIdentifier, BlockStatistics, BlockSummary, BlockSummaryPage,
+        ExplorerSummary, GetBlockDetailError, GetBlockStatisticsError, GetBlockStatisticsRequest,
+        GetBlockSummariesError, GetBlockSummariesRequest, GetBlockSummaryPageRequest,
+        GetExplorerSummaryError, GetSearchResultsError, GetTransactionDetailError,
+        GetTransactionSummariesError, GetTransactionSummariesRequest, SearchMatch, SearchQuery,
+        SearchResult, TransactionDetailResponse, TransactionIdentifier, TransactionSummary,
     },
     traits::{ExplorerHeader, ExplorerTransaction},
 };
@@ -26,7 +27,6 @@ use crate::{
 };
 use async_trait::async_trait;
 use hotshot_types::traits::node_implementation::NodeType;
-use tagged_base64::TaggedBase64;
 
 /// An interface for querying Data and Statistics from the HotShot Blockchain.
 ///
@@ -61,6 +61,15 @@ where
         request: GetBlockSummariesRequest<Types>,
     ) -> Result<Vec<BlockSummary<Types>>, GetBlockSummariesError>;
 
+    /// `get_block_summary_page` is a method that retrieves a page of block
+    /// summaries from the blockchain, counting back from the latest block,
+    /// together with the total number of blocks.  The page is generated from
+    /// the given [GetBlockSummaryPageRequest].
+    async fn get_block_summary_page(
+        &self,
+        request: GetBlockSummaryPageRequest,
+    ) -> Result<BlockSummaryPage<Types>, GetBlockSummariesError>;
+
     /// `get_transaction_detail` is a method that retrieves the details of a
     /// specific transaction from the blockchain.  The transaction is identified
     /// by the given [TransactionIdentifier].
@@ -83,11 +92,27 @@ where
     async fn get_explorer_summary(&self)
         -> Result<ExplorerSummary<Types>, GetExplorerSummaryError>;
 
+    /// `get_block_statistics` is a method that retrieves rolling statistics
+    /// over the latest blocks in the blockchain.  The size of the window is
+    /// given by the [GetBlockStatisticsRequest].
+    async fn get_block_statistics(
+        &self,
+        request: GetBlockStatisticsRequest,
+    ) -> Result<BlockStatistics, GetBlockStatisticsError>;
+
     /// `get_search_results` is a method that retrieves the results of a search
     /// query against the blockchain.  The results are generated from the given
-    /// query string.
+    /// [SearchQuery].
     async fn get_search_results(
         &self,
-        query: TaggedBase64,
+        query: SearchQuery,
     ) -> Result<SearchResult<Types>, GetSearchResultsError>;
+
+    /// `get_search_match` is a method that resolves a search query to the
+    /// first entity it identifies.  Unlike `get_search_results`, it stops
+    /// looking as soon as one kind of entity matches the [SearchQuery].
+    async fn get_search_match(
+        &self,
+        query: SearchQuery,
+    ) -> Result<SearchMatch<Types>, GetSearchResultsError>;
 }
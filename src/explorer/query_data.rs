@@ -25,7 +25,9 @@ use serde::{Deserialize, Serialize};
 use std::{
     fmt::{Debug, Display},
     num::{NonZeroUsize, TryFromIntError},
+    str::FromStr,
 };
+use tagged_base64::TaggedBase64;
 use tide_disco::StatusCode;
 use time::format_description::well_known::Rfc3339;
 
@@ -415,6 +417,32 @@ where
 #[derive(Debug, PartialEq, Eq)]
 pub struct GetBlockSummariesRequest<Types: NodeType>(pub BlockRange<Types>);
 
+/// GetBlockSummaryPageRequest is a struct that represents an incoming request
+/// for a page of Block Summaries.  Pages count backwards from the latest
+/// block: an `offset` of `0` starts with the latest block, and the page
+/// contains up to `limit` blocks descending from there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetBlockSummaryPageRequest {
+    pub offset: usize,
+    pub limit: NonZeroUsize,
+}
+
+/// [BlockSummaryPage] is a single page of [BlockSummary] entries, along with
+/// the total number of blocks in the chain, so that a client can tell how many
+/// pages there are.
+///
+/// If the requested `offset` is beyond the latest block, the page is simply
+/// empty.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct BlockSummaryPage<Types: NodeType>
+where
+    Header<Types>: ExplorerHeader<Types>,
+{
+    pub block_summaries: Vec<BlockSummary<Types>>,
+    pub total_count: u64,
+}
+
 /// [TransactionSummaryFilter] represents the various filters that can be
 /// applied when retrieving a list of [TransactionSummary] entries.
 #[derive(Debug, Deserialize, Serialize)]
@@ -474,6 +502,39 @@ pub struct ExplorerHistograms {
     pub block_heights: Vec<u64>,
 }
 
+/// GetBlockStatisticsRequest is a struct that represents an incoming request
+/// for statistics over the latest `window` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetBlockStatisticsRequest {
+    pub window: NonZeroUsize,
+}
+
+/// [MetricStatistics] summarizes the values of a single per-block metric over
+/// a window of blocks.  Each field is `None` if no block in the window has a
+/// known value for the metric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricStatistics {
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub max: Option<u64>,
+}
+
+/// [BlockStatistics] provides rolling statistics over the latest blocks in the
+/// chain.  The statistics are computed by the storage layer, so that a client
+/// does not need to fetch every block in the window and aggregate them itself.
+///
+/// `num_blocks` is the number of blocks actually in the window, which is less
+/// than the requested window if the chain is shorter.  The `block_time` of a
+/// block is the number of seconds since the block before it, and the
+/// `block_size` is the size of its payload in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockStatistics {
+    pub num_blocks: u64,
+    pub block_time: MetricStatistics,
+    pub block_size: MetricStatistics,
+    pub block_transactions: MetricStatistics,
+}
+
 /// [ExplorerSummary] is a struct that represents an at-a-glance snapshot of
 /// the Block Chain.  It contains some helpful information that can be used
 /// to display a simple health check of the Block Chain.
@@ -497,9 +558,70 @@ where
     pub histograms: ExplorerHistograms,
 }
 
+/// [SearchQuery] represents the parsed input of a search query against the
+/// chain.
+///
+/// A search query can be given in any of the forms a user is likely to paste
+/// into a search box, so that the caller does not need to know in advance what
+/// kind of entity it identifies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchQuery {
+    /// A decimal block height.
+    Height(u64),
+    /// A tagged base 64 hash.  The tag determines what kind of entity it can
+    /// match.
+    Tagged(TaggedBase64),
+    /// A `0x`-prefixed hex hash.  Without a tag, this may match a block, a
+    /// leaf, or a transaction.
+    Hash([u8; 32]),
+}
+
+impl FromStr for SearchQuery {
+    type Err = BadQuery;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(height) = s.parse() {
+            return Ok(Self::Height(height));
+        }
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(BadQuery {});
+            }
+            let mut hash = [0; 32];
+            for (i, byte) in hash.iter_mut().enumerate() {
+                // The input is all ASCII, so slicing it at any index is valid.
+                *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| BadQuery {})?;
+            }
+            return Ok(Self::Hash(hash));
+        }
+        TaggedBase64::parse(s)
+            .map(Self::Tagged)
+            .map_err(|_| BadQuery {})
+    }
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchQuery::Height(height) => write!(f, "{height}"),
+            SearchQuery::Tagged(tb64) => write!(f, "{tb64}"),
+            SearchQuery::Hash(hash) => {
+                write!(f, "0x")?;
+                for byte in hash {
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 /// [SearchResult] is a struct that represents the results of executing a
-/// search query against the chain.  It contains a list of blocks and
+/// search query against the chain.  It contains a list of blocks, leaves, and
 /// transactions that match the search query.
+///
+/// A query which could identify more than one kind of entity, such as an
+/// untagged hash, yields every match, each in the list for its kind.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(bound = "")]
 pub struct SearchResult<Types: NodeType>
@@ -508,9 +630,38 @@ where
     Transaction<Types>: ExplorerTransaction,
 {
     pub blocks: Vec<BlockSummary<Types>>,
+    /// Summaries of the blocks whose leaves match the query.
+    #[serde(default)]
+    pub leaves: Vec<BlockSummary<Types>>,
     pub transactions: Vec<TransactionSummary<Types>>,
 }
 
+/// [SearchMatch] is the result of resolving a search query to the first entity
+/// it identifies.
+///
+/// A query which could identify more than one kind of entity, such as an
+/// untagged hash, is tried as a block hash, then as a leaf hash, then as a
+/// transaction hash, and resolves to the first kind that matches.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "", tag = "kind", content = "match", rename_all = "snake_case")]
+pub enum SearchMatch<Types: NodeType>
+where
+    Header<Types>: ExplorerHeader<Types>,
+    Transaction<Types>: ExplorerTransaction,
+{
+    /// The block with the given height or hash.
+    Block(BlockSummary<Types>),
+    /// The summary of the block whose leaf has the given hash.
+    Leaf(BlockSummary<Types>),
+    /// The transaction with the given hash.
+    Transaction(TransactionSummary<Types>),
+    /// A transaction hash which was included in more than one block, with one
+    /// summary for each inclusion.
+    Ambiguous(Vec<TransactionSummary<Types>>),
+    /// Nothing matches the query.
+    NotFound,
+}
+
 /// [GetBlockDetailError] represents an error that has occurred in response to
 /// the `get_block_detail` request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -841,6 +992,62 @@ impl From<GetTransactionSummariesError> for GetExplorerSummaryError {
     }
 }
 
+/// [GetBlockStatisticsError] represents an error that has occurred in response
+/// to the [GetBlockStatisticsRequest] request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GetBlockStatisticsError {
+    Unimplemented(Unimplemented),
+    InvalidLimit(InvalidLimit),
+    QueryError(QueryError),
+}
+
+impl GetBlockStatisticsError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            GetBlockStatisticsError::Unimplemented(err) => err.status(),
+            GetBlockStatisticsError::InvalidLimit(err) => err.status(),
+            GetBlockStatisticsError::QueryError(err) => err.status(),
+        }
+    }
+}
+
+impl Display for GetBlockStatisticsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetBlockStatisticsError::Unimplemented(err) => write!(f, "{err}"),
+            GetBlockStatisticsError::InvalidLimit(err) => write!(f, "{err}"),
+            GetBlockStatisticsError::QueryError(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl ExplorerAPIError for GetBlockStatisticsError {
+    fn code(&self) -> &str {
+        match self {
+            GetBlockStatisticsError::Unimplemented(err) => err.code(),
+            GetBlockStatisticsError::InvalidLimit(err) => err.code(),
+            GetBlockStatisticsError::QueryError(err) => err.code(),
+        }
+    }
+}
+
+impl std::error::Error for GetBlockStatisticsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GetBlockStatisticsError::Unimplemented(err) => Some(err),
+            GetBlockStatisticsError::InvalidLimit(err) => Some(err),
+            GetBlockStatisticsError::QueryError(err) => Some(err),
+        }
+    }
+}
+
+impl From<crate::QueryError> for GetBlockStatisticsError {
+    fn from(value: crate::QueryError) -> Self {
+        GetBlockStatisticsError::QueryError(QueryError { error: value })
+    }
+}
+
 /// [GetSearchResultsError] represents an error that has occurred in response
 /// to the `get_search_results` request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -896,3 +1103,44 @@ impl From<crate::QueryError> for GetSearchResultsError {
         GetSearchResultsError::QueryError(QueryError { error: value })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BadQuery, SearchQuery};
+    use tagged_base64::TaggedBase64;
+
+    #[test]
+    fn test_parse_search_query() {
+        assert_eq!("42".parse::<SearchQuery>(), Ok(SearchQuery::Height(42)));
+
+        let mut hash = [0u8; 32];
+        hash[0] = 0xab;
+        hash[31] = 0x01;
+        let hex = SearchQuery::Hash(hash).to_string();
+        assert_eq!(
+            hex,
+            "0xab00000000000000000000000000000000000000000000000000000000000001"
+        );
+        assert_eq!(hex.parse::<SearchQuery>(), Ok(SearchQuery::Hash(hash)));
+        assert_eq!(
+            hex.to_uppercase()
+                .replacen("0X", "0x", 1)
+                .parse::<SearchQuery>(),
+            Ok(SearchQuery::Hash(hash))
+        );
+
+        let tagged = TaggedBase64::new("BLOCK", &hash).unwrap();
+        assert_eq!(
+            tagged.to_string().parse::<SearchQuery>(),
+            Ok(SearchQuery::Tagged(tagged))
+        );
+
+        // Hex of the wrong length, or with non-hex digits, is rejected.
+        assert_eq!("0xab".parse::<SearchQuery>(), Err(BadQuery {}));
+        assert_eq!(
+            format!("0x+{}", &hex[3..]).parse::<SearchQuery>(),
+            Err(BadQuery {})
+        );
+        assert_eq!("not a hash".parse::<SearchQuery>(), Err(BadQuery {}));
+    }
+}
@@ -21,15 +21,17 @@
 //! implementations of [`Provider`] for various data availability sources.
 //!
 
+use crate::QueryError;
 use async_lock::Mutex;
 use async_lock::Semaphore;
 use backoff::{backoff::Backoff, ExponentialBackoff};
 use derivative::Derivative;
+use hotshot_types::traits::metrics::Counter;
 use std::{
     collections::{hash_map::Entry, BTreeSet, HashMap},
     fmt::Debug,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{spawn, time::sleep};
 
@@ -52,8 +54,16 @@ pub use request::Request;
 /// instance of each distinct callback which was registered. Callbacks will run in the order
 /// determined by `Ord`.
 #[trait_variant::make(Callback: Send)]
-pub trait LocalCallback<T>: Debug + Ord {
+pub trait LocalCallback<T>: Debug + Ord + Sized {
     async fn run(self, response: T);
+
+    /// Handle the failure of the request, after the fetcher has given up on it.
+    ///
+    /// The fetcher only gives up if it was configured with a [timeout](Fetcher::with_timeout). The
+    /// default implementation does nothing.
+    async fn fail(self, err: QueryError) {
+        let _ = err;
+    }
 }
 
 /// Management of concurrent requests to fetch resources.
@@ -64,6 +74,8 @@ pub struct Fetcher<T, C> {
     in_progress: Arc<Mutex<HashMap<T, BTreeSet<C>>>>,
     backoff: ExponentialBackoff,
     permit: Arc<Semaphore>,
+    timeout: Option<Duration>,
+    coalesced: Option<Arc<dyn Counter>>,
 }
 
 impl<T, C> Fetcher<T, C> {
@@ -72,8 +84,24 @@ impl<T, C> Fetcher<T, C> {
             in_progress: Default::default(),
             permit,
             backoff,
+            timeout: None,
+            coalesced: None,
         }
     }
+
+    /// Give up on fetches which are still failing after `timeout`.
+    ///
+    /// By default, fetches are retried indefinitely.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Count requests which join a fetch already in progress for the same resource.
+    pub fn with_coalesced_counter(mut self, counter: Box<dyn Counter>) -> Self {
+        self.coalesced = Some(counter.into());
+        self
+    }
 }
 
 impl<T, C> Fetcher<T, C> {
@@ -94,7 +122,10 @@ impl<T, C> Fetcher<T, C> {
     ///
     /// The spawned task will continue trying to fetch the object until it succeeds, so it is the
     /// caller's responsibility only to use this method for resources which are known to exist and
-    /// be fetchable by `provider`.
+    /// be fetchable by `provider`. If a [timeout](Self::with_timeout) is set and the object still
+    /// has not been fetched when it expires, every registered callback is
+    /// [failed](LocalCallback::fail) with the same error instead, and the resource is no longer
+    /// considered in progress, so that a later call to this method starts a fresh fetch.
     pub fn spawn_fetch<Types>(
         &self,
         req: T,
@@ -107,6 +138,8 @@ impl<T, C> Fetcher<T, C> {
         let in_progress = self.in_progress.clone();
         let permit = self.permit.clone();
         let mut backoff = self.backoff.clone();
+        let timeout = self.timeout;
+        let coalesced = self.coalesced.clone();
 
         spawn(async move {
             tracing::info!("spawned active fetch for {req:?}");
@@ -121,6 +154,9 @@ impl<T, C> Fetcher<T, C> {
                         // task to execute upon completion.
                         e.get_mut().extend(callbacks);
                         tracing::info!(?req, callbacks = ?e.get(), "resource is already being fetched");
+                        if let Some(coalesced) = &coalesced {
+                            coalesced.add(1);
+                        }
                         return;
                     }
                     Entry::Vacant(e) => {
@@ -133,12 +169,13 @@ impl<T, C> Fetcher<T, C> {
 
             // Now we are responsible for fetching the object, reach out to the provider.
             backoff.reset();
+            let start = Instant::now();
             let mut delay = backoff.next_backoff().unwrap_or(Duration::from_secs(1));
             let res = loop {
                 // Acquire a permit from the semaphore to rate limit the number of concurrent fetch requests
                 let permit = permit.acquire().await;
                 if let Some(res) = provider.fetch(req).await {
-                    break res;
+                    break Ok(res);
                 }
 
                 // We only fetch objects which are known to exist, so we should eventually succeed
@@ -152,6 +189,17 @@ impl<T, C> Fetcher<T, C> {
                 // fetching, we require manual intervention only when active fetches are
                 // accumulating because a peer which _should_ have the resource isn't providing it.
                 // In this case, we would require manual intervention on the peer anyways.
+                //
+                // Still, an application may prefer that requests fail rather than wait on such a
+                // peer, so it can opt into giving up after a timeout. We then stop tracking the
+                // fetch, so the next request for the same resource (for example, from the proactive
+                // scanner) will start a new one.
+                if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                    tracing::warn!("failed to fetch {req:?}, giving up");
+                    break Err(QueryError::Error {
+                        message: format!("failed to fetch {req:?} after repeated attempts"),
+                    });
+                }
                 tracing::warn!("failed to fetch {req:?}, will retry in {delay:?}");
                 drop(permit);
                 sleep(delay).await;
@@ -161,7 +209,8 @@ impl<T, C> Fetcher<T, C> {
                 }
             };
 
-            // Done fetching, remove our lock on the object and execute all callbacks.
+            // Done fetching, remove our lock on the object and execute all callbacks. If we failed,
+            // this also evicts the object, so that a later request will fetch it again.
             //
             // We will keep this lock the whole time we are running the callbacks. We can't release
             // it earlier because we can't allow another task to register a callback after we have
@@ -176,8 +225,109 @@ impl<T, C> Fetcher<T, C> {
             let mut in_progress = in_progress.lock().await;
             let callbacks = in_progress.remove(&req).unwrap_or_default();
             for callback in callbacks {
-                callback.run(res.clone()).await;
+                match &res {
+                    Ok(res) => callback.run(res.clone()).await,
+                    Err(err) => callback.fail(err.clone()).await,
+                }
             }
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        availability::LeafQueryData,
+        fetching::request::LeafRequest,
+        metrics::PrometheusMetrics,
+        testing::{mocks::MockTypes, setup_test},
+    };
+    use async_trait::async_trait;
+    use backoff::ExponentialBackoffBuilder;
+    use hotshot_example_types::{
+        node_types::TestVersions,
+        state_types::{TestInstanceState, TestValidatedState},
+    };
+    use hotshot_types::traits::metrics::Metrics;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A provider which counts requests and takes a while to respond to each one.
+    #[derive(Clone, Debug)]
+    struct SlowProvider {
+        leaf: LeafQueryData<MockTypes>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Provider<MockTypes, LeafRequest> for SlowProvider {
+        async fn fetch(&self, _req: LeafRequest) -> Option<LeafQueryData<MockTypes>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            sleep(Duration::from_millis(500)).await;
+            Some(self.leaf.clone())
+        }
+    }
+
+    /// A callback which records that it ran.
+    #[derive(Derivative)]
+    #[derivative(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct RecordCallback {
+        id: usize,
+        #[derivative(
+            Debug = "ignore",
+            PartialEq = "ignore",
+            PartialOrd = "ignore",
+            Ord = "ignore"
+        )]
+        ran: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Callback<LeafQueryData<MockTypes>> for RecordCallback {
+        async fn run(self, _leaf: LeafQueryData<MockTypes>) {
+            self.ran.lock().await.push(self.id);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_fetches_coalesced() {
+        setup_test();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let provider = SlowProvider {
+            leaf,
+            requests: Default::default(),
+        };
+        let metrics = PrometheusMetrics::default();
+        let fetcher = Fetcher::<LeafRequest, RecordCallback>::new(
+            Arc::new(Semaphore::new(10)),
+            ExponentialBackoffBuilder::default().build(),
+        )
+        .with_coalesced_counter(metrics.create_counter("coalesced".into(), None));
+
+        // Request the same object many times concurrently.
+        let n = 100;
+        let ran = Arc::new(Mutex::new(vec![]));
+        for id in 0..n {
+            fetcher.spawn_fetch(
+                LeafRequest::from(0),
+                provider.clone(),
+                [RecordCallback {
+                    id,
+                    ran: ran.clone(),
+                }],
+            );
+        }
+
+        // Every requester is notified, but the provider is only asked once.
+        while ran.lock().await.len() < n {
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(*ran.lock().await, (0..n).collect::<Vec<_>>());
+        assert_eq!(provider.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(metrics.get_counter("coalesced").unwrap().get(), n - 1);
+    }
+}
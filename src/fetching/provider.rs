@@ -43,7 +43,7 @@ mod any;
 mod query_service;
 mod testing;
 
-pub use any::AnyProvider;
+pub use any::{AnyProvider, RetryPolicy};
 pub use query_service::QueryServiceProvider;
 #[cfg(any(test, feature = "testing"))]
 pub use testing::TestProvider;
@@ -21,7 +21,8 @@ use async_trait::async_trait;
 use derivative::Derivative;
 use hotshot_types::traits::node_implementation::NodeType;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Blanket trait combining [`Debug`] and [`Provider`].
 ///
@@ -47,6 +48,99 @@ type PayloadProvider<Types> = Arc<dyn DebugProvider<Types, PayloadRequest>>;
 type LeafProvider<Types> = Arc<dyn DebugProvider<Types, LeafRequest>>;
 type VidCommonProvider<Types> = Arc<dyn DebugProvider<Types, VidCommonRequest>>;
 
+/// Policy for temporarily skipping providers which have recently failed.
+///
+/// After a provider fails a request, [`AnyProvider`] stops sending it requests for a cooldown period,
+/// which grows exponentially with each consecutive failure, up to a maximum. A successful request
+/// resets the provider's cooldown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    initial_cooldown: Duration,
+    max_cooldown: Duration,
+    multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_cooldown: Duration::from_secs(1),
+            // 5 minutes
+            max_cooldown: Duration::from_secs(300),
+            multiplier: 2.,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Set the cooldown after the first failure.
+    pub fn with_initial_cooldown(mut self, cooldown: Duration) -> Self {
+        self.initial_cooldown = cooldown;
+        self
+    }
+
+    /// Set the maximum cooldown, regardless of the number of consecutive failures.
+    pub fn with_max_cooldown(mut self, cooldown: Duration) -> Self {
+        self.max_cooldown = cooldown;
+        self
+    }
+
+    /// Set the factor by which the cooldown grows with each consecutive failure.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// The cooldown for a provider after `failures` consecutive failures.
+    pub fn cooldown(&self, failures: u32) -> Duration {
+        let Some(exp) = failures.checked_sub(1) else {
+            return Duration::ZERO;
+        };
+        let secs = self.initial_cooldown.as_secs_f64() * self.multiplier.powi(exp as i32);
+        Duration::try_from_secs_f64(secs)
+            .unwrap_or(self.max_cooldown)
+            .min(self.max_cooldown)
+    }
+}
+
+#[derive(Debug, Default)]
+struct PeerHealth {
+    /// The number of consecutive failed requests.
+    failures: u32,
+    /// The earliest time at which to send this provider another request.
+    retry_at: Option<Instant>,
+}
+
+/// A sub-provider, together with its recent health.
+///
+/// The health is shared between all the request types a single provider serves, since a peer which
+/// is unreachable for one kind of request is likely unreachable for all of them.
+#[derive(Derivative)]
+#[derivative(Clone(bound = "P: Clone"), Debug(bound = "P: Debug"))]
+struct Peer<P> {
+    provider: P,
+    health: Arc<Mutex<PeerHealth>>,
+}
+
+impl<P> Peer<P> {
+    /// If this provider is cooling down after recent failures, the time until it may be retried.
+    fn cooldown_remaining(&self) -> Option<Duration> {
+        let health = self.health.lock().unwrap();
+        health.retry_at?.checked_duration_since(Instant::now())
+    }
+
+    fn record_success(&self) {
+        *self.health.lock().unwrap() = PeerHealth::default();
+    }
+
+    fn record_failure(&self, policy: &RetryPolicy) -> Duration {
+        let mut health = self.health.lock().unwrap();
+        health.failures = health.failures.saturating_add(1);
+        let cooldown = policy.cooldown(health.failures);
+        health.retry_at = Some(Instant::now() + cooldown);
+        cooldown
+    }
+}
+
 /// Adaptor combining multiple data availability providers.
 ///
 /// This provider adaptor implements the [`Provider`](super::Provider) protocol by fetching
@@ -61,6 +155,10 @@ type VidCommonProvider<Types> = Arc<dyn DebugProvider<Types, VidCommonRequest>>;
 /// provides blocks and one which only provides leaves into a provider which provides both, and thus
 /// can be used as a provider for the availability API module.
 ///
+/// Providers are tried in the order they were added. A provider which fails a request is skipped
+/// for a cooldown period, so that an unreachable peer does not slow down every fetch. The cooldown
+/// is configured by a [`RetryPolicy`] (see [`with_retry_policy`](Self::with_retry_policy)).
+///
 /// # Examples
 ///
 /// Fetching from multiple query services, for resiliency.
@@ -85,15 +183,32 @@ type VidCommonProvider<Types> = Arc<dyn DebugProvider<Types, VidCommonRequest>>;
 /// # Ok(())
 /// # }
 /// ```
+///
+/// Backing off from unreliable providers more aggressively.
+///
+/// ```
+/// # use hotshot_types::traits::node_implementation::NodeType;
+/// # use std::time::Duration;
+/// # fn doc<Types: NodeType>() {
+/// use hotshot_query_service::fetching::provider::{AnyProvider, RetryPolicy};
+///
+/// let provider = AnyProvider::<Types>::default().with_retry_policy(
+///     RetryPolicy::default()
+///         .with_initial_cooldown(Duration::from_secs(10))
+///         .with_max_cooldown(Duration::from_secs(600)),
+/// );
+/// # }
+/// ```
 #[derive(Derivative)]
 #[derivative(Clone(bound = ""), Debug(bound = ""), Default(bound = ""))]
 pub struct AnyProvider<Types>
 where
     Types: NodeType,
 {
-    payload_providers: Vec<PayloadProvider<Types>>,
-    leaf_providers: Vec<LeafProvider<Types>>,
-    vid_common_providers: Vec<VidCommonProvider<Types>>,
+    payload_providers: Vec<Peer<PayloadProvider<Types>>>,
+    leaf_providers: Vec<Peer<LeafProvider<Types>>>,
+    vid_common_providers: Vec<Peer<VidCommonProvider<Types>>>,
+    retry_policy: RetryPolicy,
 }
 
 #[async_trait]
@@ -102,7 +217,7 @@ where
     Types: NodeType,
 {
     async fn fetch(&self, req: PayloadRequest) -> Option<Payload<Types>> {
-        any_fetch(&self.payload_providers, req).await
+        any_fetch(&self.payload_providers, &self.retry_policy, req).await
     }
 }
 
@@ -112,7 +227,7 @@ where
     Types: NodeType,
 {
     async fn fetch(&self, req: LeafRequest) -> Option<LeafQueryData<Types>> {
-        any_fetch(&self.leaf_providers, req).await
+        any_fetch(&self.leaf_providers, &self.retry_policy, req).await
     }
 }
 
@@ -122,7 +237,7 @@ where
     Types: NodeType,
 {
     async fn fetch(&self, req: VidCommonRequest) -> Option<VidCommon> {
-        any_fetch(&self.vid_common_providers, req).await
+        any_fetch(&self.vid_common_providers, &self.retry_policy, req).await
     }
 }
 
@@ -136,9 +251,16 @@ where
         P: AvailabilityProvider<Types> + Debug + 'static,
     {
         let provider = Arc::new(provider);
-        self.payload_providers.push(provider.clone());
-        self.leaf_providers.push(provider.clone());
-        self.vid_common_providers.push(provider);
+        let health = Arc::<Mutex<PeerHealth>>::default();
+        self.payload_providers.push(Peer {
+            provider: provider.clone(),
+            health: health.clone(),
+        });
+        self.leaf_providers.push(Peer {
+            provider: provider.clone(),
+            health: health.clone(),
+        });
+        self.vid_common_providers.push(Peer { provider, health });
         self
     }
 
@@ -147,7 +269,10 @@ where
     where
         P: Provider<Types, PayloadRequest> + Debug + 'static,
     {
-        self.payload_providers.push(Arc::new(provider));
+        self.payload_providers.push(Peer {
+            provider: Arc::new(provider),
+            health: Default::default(),
+        });
         self
     }
 
@@ -156,7 +281,10 @@ where
     where
         P: Provider<Types, LeafRequest> + Debug + 'static,
     {
-        self.leaf_providers.push(Arc::new(provider));
+        self.leaf_providers.push(Peer {
+            provider: Arc::new(provider),
+            health: Default::default(),
+        });
         self
     }
 
@@ -165,12 +293,25 @@ where
     where
         P: Provider<Types, VidCommonRequest> + Debug + 'static,
     {
-        self.vid_common_providers.push(Arc::new(provider));
+        self.vid_common_providers.push(Peer {
+            provider: Arc::new(provider),
+            health: Default::default(),
+        });
+        self
+    }
+
+    /// Set the policy for skipping providers which have recently failed.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
         self
     }
 }
 
-async fn any_fetch<Types, P, T>(providers: &[Arc<P>], req: T) -> Option<T::Response>
+async fn any_fetch<Types, P, T>(
+    providers: &[Peer<Arc<P>>],
+    policy: &RetryPolicy,
+    req: T,
+) -> Option<T::Response>
 where
     Types: NodeType,
     P: Provider<Types, T> + Debug + ?Sized,
@@ -182,13 +323,30 @@ where
     // fetcher: low latency, and no undue burden on the other providers. However, a more complicated
     // strategy where we slowly ramp up the parallelism as more and more requests fail may provide
     // better worst-case latency.
+    //
+    // Providers which have failed recently are skipped until their cooldown elapses, so that a dead
+    // provider near the front of the list does not add latency to every request.
     for (i, p) in providers.iter().enumerate() {
-        match p.fetch(req).await {
-            Some(obj) => return Some(obj),
+        if let Some(remaining) = p.cooldown_remaining() {
+            tracing::debug!(
+                "skipping provider {i}/{} for {req:?}, cooling down for {remaining:?}: {:?}",
+                providers.len(),
+                p.provider
+            );
+            continue;
+        }
+        match p.provider.fetch(req).await {
+            Some(obj) => {
+                p.record_success();
+                return Some(obj);
+            }
             None => {
+                let cooldown = p.record_failure(policy);
                 tracing::warn!(
-                    "failed to fetch {req:?} from provider {i}/{}: {p:?}",
-                    providers.len()
+                    "failed to fetch {req:?} from provider {i}/{}, skipping it for {cooldown:?}: \
+                     {:?}",
+                    providers.len(),
+                    p.provider
                 );
                 continue;
             }
@@ -205,18 +363,25 @@ mod test {
     use crate::{
         availability::{define_api, AvailabilityDataSource, UpdateAvailabilityData},
         data_source::storage::sql::testing::TmpDb,
-        fetching::provider::{NoFetching, QueryServiceProvider},
+        fetching::provider::{
+            NoFetching, Provider as ProviderTrait, QueryServiceProvider, TestProvider,
+        },
         task::BackgroundTask,
         testing::{
             consensus::{MockDataSource, MockNetwork},
             mocks::{MockBase, MockTypes},
-            setup_test,
+            setup_test, sleep,
         },
         types::HeightIndexed,
         ApiState, Error,
     };
     use futures::stream::StreamExt;
+    use hotshot_example_types::{
+        node_types::TestVersions,
+        state_types::{TestInstanceState, TestValidatedState},
+    };
     use portpicker::pick_unused_port;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use tide_disco::App;
     use vbs::version::StaticVersionType;
 
@@ -286,4 +451,93 @@ mod test {
         assert_eq!(payload.block_hash(), test_payload.block_hash());
         assert_eq!(payload.hash(), test_payload.payload_hash());
     }
+
+    /// A leaf provider which counts the requests it receives.
+    #[derive(Clone, Debug, Default)]
+    struct CountingProvider {
+        leaf: Option<LeafQueryData<MockTypes>>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl CountingProvider {
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl ProviderTrait<MockTypes, LeafRequest> for CountingProvider {
+        async fn fetch(&self, _req: LeafRequest) -> Option<LeafQueryData<MockTypes>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            self.leaf.clone()
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_cooldown() {
+        let policy = RetryPolicy::default()
+            .with_initial_cooldown(Duration::from_secs(1))
+            .with_max_cooldown(Duration::from_secs(10))
+            .with_multiplier(3.);
+        assert_eq!(policy.cooldown(0), Duration::ZERO);
+        assert_eq!(policy.cooldown(1), Duration::from_secs(1));
+        assert_eq!(policy.cooldown(2), Duration::from_secs(3));
+        assert_eq!(policy.cooldown(3), Duration::from_secs(9));
+        assert_eq!(policy.cooldown(4), Duration::from_secs(10));
+        assert_eq!(policy.cooldown(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_skip_failing_provider() {
+        setup_test();
+
+        let leaf = LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+            &TestValidatedState::default(),
+            &TestInstanceState::default(),
+        )
+        .await;
+        let live = CountingProvider {
+            leaf: Some(leaf.clone()),
+            ..Default::default()
+        };
+        let flaky = TestProvider::new(live.clone());
+        let backup = CountingProvider {
+            leaf: Some(leaf.clone()),
+            ..Default::default()
+        };
+        let provider = Provider::default()
+            .with_retry_policy(RetryPolicy::default().with_initial_cooldown(Duration::from_secs(1)))
+            .with_leaf_provider(flaky.clone())
+            .with_leaf_provider(backup.clone());
+        let fetch = || ProviderTrait::<MockTypes, _>::fetch(&provider, LeafRequest::from(0));
+
+        // While the first provider is healthy, it serves all requests.
+        assert_eq!(fetch().await.unwrap(), leaf);
+        assert_eq!((live.requests(), backup.requests()), (1, 0));
+
+        // When it fails, we fall back to the next provider.
+        flaky.fail();
+        assert_eq!(fetch().await.unwrap(), leaf);
+        assert_eq!((live.requests(), backup.requests()), (1, 1));
+        assert_eq!(
+            provider.leaf_providers[0].health.lock().unwrap().failures,
+            1
+        );
+
+        // Even after it recovers, the failed provider is skipped until its cooldown elapses.
+        flaky.unfail();
+        assert_eq!(fetch().await.unwrap(), leaf);
+        assert_eq!((live.requests(), backup.requests()), (1, 2));
+
+        // Once the cooldown has elapsed, the provider is tried again, and a successful request
+        // resets its health.
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(fetch().await.unwrap(), leaf);
+        assert_eq!((live.requests(), backup.requests()), (2, 2));
+        assert_eq!(
+            provider.leaf_providers[0].health.lock().unwrap().failures,
+            0
+        );
+        assert_eq!(provider.leaf_providers[0].cooldown_remaining(), None);
+    }
 }
@@ -15,35 +15,129 @@ use super::Provider;
 use crate::{
     availability::{LeafQueryData, PayloadQueryData, VidCommonQueryData},
     fetching::request::{LeafRequest, PayloadRequest, VidCommonRequest},
+    types::HeightIndexed,
     Error, Payload, VidCommon,
 };
 use async_trait::async_trait;
+use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
 use futures::try_join;
 use hotshot_types::{
-    traits::{node_implementation::NodeType, EncodeBytes},
+    traits::{
+        metrics::{Counter, Metrics, NoMetrics},
+        node_implementation::NodeType,
+        EncodeBytes,
+    },
     vid::{vid_scheme, VidSchemeType},
 };
 use jf_vid::VidScheme;
+use serde::de::DeserializeOwned;
+use std::{sync::Arc, time::Duration};
 use surf_disco::{Client, Url};
+use tide_disco::{Error as _, StatusCode};
+use tokio::time::sleep;
 use vbs::version::StaticVersionType;
 
 /// Data availability provider backed by another instance of this query service.
 ///
 /// This fetcher implements the [`Provider`] interface by querying the REST API provided by another
 /// instance of this query service to try and retrieve missing objects.
+///
+/// Requests which fail transiently, because the peer could not be reached, timed out, or responded
+/// with 502, 503, or 504, are retried with exponential backoff and jitter, up to
+/// [`with_max_attempts`](Self::with_max_attempts) attempts in total. Other failures, such as a 404
+/// when the peer does not have the requested object, fail immediately, since retrying will not
+/// help. The number of retries is counted in the `query_service_provider_retries` metric, if
+/// [metrics](Self::with_metrics) are configured.
 #[derive(Clone, Debug)]
 pub struct QueryServiceProvider<Ver: StaticVersionType> {
     client: Client<Error, Ver>,
+    max_attempts: usize,
+    base_delay: Duration,
+    retries: Arc<dyn Counter>,
 }
 
 impl<Ver: StaticVersionType> QueryServiceProvider<Ver> {
     pub fn new(url: Url, _: Ver) -> Self {
         Self {
             client: Client::new(url),
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            retries: NoMetrics.create_counter(RETRIES_METRIC.into(), None).into(),
+        }
+    }
+
+    /// Set the maximum number of attempts for each request, including the first.
+    ///
+    /// Setting this to 1 disables retries. The default is 3.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `attempts` is zero.
+    pub fn with_max_attempts(mut self, attempts: usize) -> Self {
+        assert!(attempts > 0, "max attempts must be positive");
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Set the delay before the first retry of a failed request.
+    ///
+    /// The delay doubles with each subsequent retry, and each delay is randomized by up to 50% in
+    /// either direction, so that many requests failing at once do not all retry at once. The
+    /// default is 100ms.
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Report the number of retries performed in `metrics`.
+    pub fn with_metrics(mut self, metrics: &(impl Metrics + ?Sized)) -> Self {
+        self.retries = metrics.create_counter(RETRIES_METRIC.into(), None).into();
+        self
+    }
+
+    /// Send a GET request to the peer, retrying transient failures.
+    async fn get<T: DeserializeOwned>(&self, route: &str) -> Result<T, Error> {
+        let mut backoff = ExponentialBackoffBuilder::new()
+            .with_initial_interval(self.base_delay)
+            .with_multiplier(2.)
+            .with_randomization_factor(0.5)
+            .with_max_elapsed_time(None)
+            .build();
+        let mut attempt = 1;
+        loop {
+            match self.client.get::<T>(route).send().await {
+                Ok(res) => return Ok(res),
+                Err(err) if attempt < self.max_attempts && is_retriable(&err) => {
+                    let delay = backoff.next_backoff().unwrap_or(self.base_delay);
+                    tracing::info!(route, attempt, ?delay, "retrying failed request: {err}");
+                    self.retries.add(1);
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
         }
     }
 }
 
+/// The name of the metric counting retried requests.
+const RETRIES_METRIC: &str = "query_service_provider_retries";
+
+/// Whether a failed request might succeed if retried.
+///
+/// The client reports failures to reach the peer at all, such as a reset connection, as internal
+/// server errors, so those are retried along with gateway errors and timeouts.
+fn is_retriable(err: &Error) -> bool {
+    matches!(
+        err.status(),
+        StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+            | StatusCode::REQUEST_TIMEOUT
+    )
+}
+
 #[async_trait]
 impl<Types, Ver: StaticVersionType> Provider<Types, PayloadRequest> for QueryServiceProvider<Ver>
 where
@@ -54,15 +148,11 @@ where
         // commitment, to ensure the payload we received is consistent with the commitment we
         // requested.
         let res = try_join!(
-            self.client
-                .get::<PayloadQueryData<Types>>(&format!("availability/payload/hash/{}", req.0))
-                .send(),
-            self.client
-                .get::<VidCommonQueryData<Types>>(&format!(
-                    "availability/vid/common/payload-hash/{}",
-                    req.0
-                ))
-                .send()
+            self.get::<PayloadQueryData<Types>>(&format!("availability/payload/hash/{}", req.0)),
+            self.get::<VidCommonQueryData<Types>>(&format!(
+                "availability/vid/common/payload-hash/{}",
+                req.0
+            ))
         );
         match res {
             Ok((payload, common)) => {
@@ -99,12 +189,21 @@ where
 {
     async fn fetch(&self, req: LeafRequest) -> Option<LeafQueryData<Types>> {
         match self
-            .client
             .get::<LeafQueryData<Types>>(&format!("availability/leaf/{}", usize::from(req)))
-            .send()
             .await
         {
             Ok(mut leaf) => {
+                // Make sure the peer gave us the leaf we asked for, since we will store it at the
+                // requested height.
+                if leaf.height() != usize::from(req) as u64 {
+                    tracing::error!(
+                        ?req,
+                        height = leaf.height(),
+                        "received leaf at wrong height"
+                    );
+                    return None;
+                }
+
                 // TODO we should also download a chain of QCs justifying the inclusion of `leaf` in
                 // the chain at the requested height. However, HotShot currently lacks a good light
                 // client API to verify this chain, so for now we just trust the other server.
@@ -134,12 +233,10 @@ where
 {
     async fn fetch(&self, req: VidCommonRequest) -> Option<VidCommon> {
         match self
-            .client
             .get::<VidCommonQueryData<Types>>(&format!(
                 "availability/vid/common/payload-hash/{}",
                 req.0
             ))
-            .send()
             .await
         {
             Ok(res) if VidSchemeType::is_consistent(&req.0, &res.common).is_ok() => {
@@ -179,26 +276,35 @@ mod test {
             AvailabilityProvider, FetchingDataSource, Transaction, VersionedDataSource,
         },
         fetching::provider::{NoFetching, Provider as ProviderTrait, TestProvider},
+        metrics::PrometheusMetrics,
         node::{data_source::NodeDataSource, SyncStatus},
+        status::HasMetrics,
         task::BackgroundTask,
         testing::{
             consensus::{MockDataSource, MockNetwork},
-            mocks::{mock_transaction, MockBase, MockTypes},
+            mocks::{mock_transaction, MockBase, MockPayload, MockTypes},
             setup_test, sleep,
         },
         types::HeightIndexed,
-        ApiState, VidCommitment,
+        ApiState, Header, QueryError, VidCommitment,
     };
     use committable::Committable;
     use futures::{
-        future::{join, FutureExt},
+        future::{join, join_all, FutureExt},
         stream::StreamExt,
     };
     use generic_array::GenericArray;
+    use hotshot_example_types::node_types::TestVersions;
+    use hotshot_types::traits::block_contents::BlockHeader;
     use portpicker::pick_unused_port;
     use rand::RngCore;
-    use std::{future::IntoFuture, time::Duration};
+    use std::{
+        future::IntoFuture,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
     use tide_disco::{error::ServerError, App};
+    use tokio::{spawn, time::timeout};
 
     type Provider = TestProvider<QueryServiceProvider<MockBase>>;
 
@@ -451,6 +557,388 @@ mod test {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_unavailable() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus, and which considers
+        // block 1 permanently unavailable.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let data_source = builder(&db, &provider)
+            .await
+            .with_unavailable_heights(1..2)
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus and wait for a few blocks.
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+
+        // Tell the node about the block height.
+        data_source
+            .append(leaves.last().cloned().unwrap().into())
+            .await
+            .unwrap();
+
+        // A request for the unavailable block does not trigger a fetch.
+        let fetch = data_source.get_block(1).await;
+        sleep(Duration::from_secs(1)).await;
+        fetch.try_resolve().unwrap_err();
+
+        // Other blocks are still fetched on request.
+        let block = data_source.get_block(2).await.await;
+        assert_eq!(block, network.data_source().get_block(2).await.await);
+
+        // Once the height is made available again, a new request triggers a fetch.
+        data_source.clear_unavailable();
+        let block = data_source.get_block(1).await.await;
+        assert_eq!(block, network.data_source().get_block(1).await.await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_rejected_payload() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus, and which rejects the
+        // payload of block 1, counting how many times it is asked to check it.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let checked = Arc::new(AtomicUsize::new(0));
+        let validator = {
+            let checked = checked.clone();
+            move |header: &Header<MockTypes>, _: &MockPayload| {
+                if header.block_number() == 1 {
+                    checked.fetch_add(1, Ordering::SeqCst);
+                    anyhow::bail!("payload rejected by test");
+                }
+                Ok(())
+            }
+        };
+        let data_source = builder(&db, &provider)
+            .await
+            .with_payload_validator(validator.clone())
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus and wait for a few blocks.
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+
+        // Tell the node about the leaf at height 1 and the block height, but not the payloads.
+        data_source.append(leaves[0].clone().into()).await.unwrap();
+        data_source
+            .append(leaves.last().cloned().unwrap().into())
+            .await
+            .unwrap();
+
+        // A request for block 1 fetches the payload, which is rejected. Once the rejection is
+        // recorded, requests for the block fail instead of fetching the payload again.
+        data_source.get_block(1).await;
+        let err = loop {
+            if let Fetch::Failed(err) = data_source.get_block(1).await {
+                break err;
+            }
+            sleep(Duration::from_millis(100)).await;
+        };
+        assert!(matches!(err, QueryError::Deleted), "{err:?}");
+        assert_eq!(checked.load(Ordering::SeqCst), 1);
+
+        // Other blocks are still fetched and stored.
+        let block = data_source.get_block(2).await.await;
+        assert_eq!(block, network.data_source().get_block(2).await.await);
+
+        // The rejection is persisted, so the payload is not fetched again after a restart.
+        drop(data_source);
+        let data_source = builder(&db, &provider)
+            .await
+            .with_payload_validator(validator)
+            .build()
+            .await
+            .unwrap();
+        let err = data_source
+            .get_block(1)
+            .await
+            .try_with_timeout(Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::Deleted), "{err:?}");
+        assert_eq!(checked.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_coalesced() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let data_source = builder(&db, &provider)
+            .await
+            .disable_aggregator()
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus and wait for a few blocks.
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+
+        // Give the node the leaf at height 1, so that only the payload is missing, and a later
+        // leaf so it learns the block height. Insert them directly, so we don't trigger any fetches
+        // yet.
+        let mut tx = data_source.write().await.unwrap();
+        tx.insert_leaf(leaves[0].clone()).await.unwrap();
+        tx.insert_leaf(leaves.last().cloned().unwrap())
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Request the missing block many times concurrently, while the provider is blocked, so
+        // that every request is made while the first fetch is still in progress.
+        provider.block().await;
+        let n = 100;
+        let fetches = join_all((0..n).map(|_| data_source.get_block(1))).await;
+        assert!(fetches.iter().all(|fetch| fetch.is_pending()));
+
+        // Every request resolves, but the provider is only asked once.
+        sleep(Duration::from_secs(1)).await;
+        provider.unblock().await;
+        let expected = network.data_source().get_block(1).await.await;
+        for block in join_all(fetches.into_iter().map(IntoFuture::into_future)).await {
+            assert_eq!(block, expected);
+        }
+        assert_eq!(provider.requests(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_coalesced_failure() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus, and which gives up on
+        // a fetch after a single failed attempt.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let data_source = builder(&db, &provider)
+            .await
+            .disable_aggregator()
+            .with_fetch_timeout(Duration::ZERO)
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus and wait for a few blocks.
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+
+        // Give the node the leaf at height 1, so that only the payload is missing.
+        let mut tx = data_source.write().await.unwrap();
+        tx.insert_leaf(leaves[0].clone()).await.unwrap();
+        tx.insert_leaf(leaves.last().cloned().unwrap())
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Request the missing block many times concurrently, and then make the one fetch fail.
+        provider.block().await;
+        provider.fail();
+        let n = 100;
+        let fetches = join_all((0..n).map(|_| data_source.get_block(1))).await;
+        sleep(Duration::from_secs(1)).await;
+        provider.unblock().await;
+
+        // Every request fails with the same error, and the provider was only asked once.
+        let errs = join_all(
+            fetches
+                .into_iter()
+                .map(|fetch| fetch.try_with_timeout(Duration::from_secs(10))),
+        )
+        .await
+        .into_iter()
+        .map(|res| res.unwrap_err())
+        .collect::<Vec<_>>();
+        assert!(matches!(errs[0], QueryError::Error { .. }), "{:?}", errs[0]);
+        for err in &errs {
+            assert_eq!(err.to_string(), errs[0].to_string());
+        }
+        assert_eq!(provider.requests(), 1);
+
+        // The failed fetch is no longer in progress, so a later request fetches the block again.
+        provider.unfail();
+        let block = data_source
+            .get_block(1)
+            .await
+            .with_timeout(Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(block, network.data_source().get_block(1).await.await);
+        assert_eq!(provider.requests(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_after_failed_fetch() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus, and which gives up on
+        // a fetch after a single failed attempt.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let data_source = builder(&db, &provider)
+            .await
+            .disable_aggregator()
+            .with_fetch_timeout(Duration::ZERO)
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus and wait for a few blocks.
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+
+        // Give the node the leaf at height 1, so that only the payload is missing.
+        let mut tx = data_source.write().await.unwrap();
+        tx.insert_leaf(leaves[0].clone()).await.unwrap();
+        tx.insert_leaf(leaves.last().cloned().unwrap())
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // Start streaming blocks while the provider is failing, and wait for the fetch of the
+        // missing block to be abandoned.
+        provider.fail();
+        let mut blocks = data_source.subscribe_blocks(1).await;
+        let next = spawn(async move { blocks.next().await });
+        data_source
+            .get_block(1)
+            .await
+            .try_with_timeout(Duration::from_secs(10))
+            .await
+            .unwrap_err();
+
+        // The stream does not end or fail, but keeps waiting for the block.
+        sleep(Duration::from_secs(1)).await;
+        assert!(!next.is_finished());
+
+        // Once a later request fetches the block, the stream gets it.
+        provider.unfail();
+        let expected = network.data_source().get_block(1).await.await;
+        let block = data_source
+            .get_block(1)
+            .await
+            .with_timeout(Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(block, expected);
+        let streamed = timeout(Duration::from_secs(10), next)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(streamed, expected);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_fetch_block_and_leaf_concurrently() {
         setup_test();
@@ -867,6 +1355,18 @@ mod test {
             }
             .boxed()
         })
+        .unwrap()
+        .get("get_leaf", move |_, _| {
+            async move {
+                // No matter what data we are asked for, always respond with the genesis leaf.
+                Ok(LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+                    &Default::default(),
+                    &Default::default(),
+                )
+                .await)
+            }
+            .boxed()
+        })
         .unwrap();
 
         let mut app = App::<(), ServerError>::with_state(());
@@ -902,6 +1402,100 @@ mod test {
             ProviderTrait::<MockTypes, _>::fetch(&provider, VidCommonRequest(random_vid_commit()))
                 .await;
         assert_eq!(res, None);
+
+        // Query for a leaf at a non-zero height, the server will respond with the genesis leaf, and
+        // we should detect the error.
+        let res = ProviderTrait::<MockTypes, _>::fetch(&provider, LeafRequest::from(1)).await;
+        assert_eq!(res, None);
+    }
+
+    async fn flaky_server(port: u16, failures: usize) {
+        let mut api = load_api::<(), ServerError, MockBase>(
+            None::<std::path::PathBuf>,
+            include_str!("../../../api/availability.toml"),
+            vec![],
+        )
+        .unwrap();
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        api.get("get_leaf", move |_, _| {
+            let requests = requests.clone();
+            async move {
+                // Fail the first `failures` requests with a transient error.
+                if requests.fetch_add(1, Ordering::SeqCst) < failures {
+                    return Err(ServerError::catch_all(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "try again later".into(),
+                    ));
+                }
+                Ok(LeafQueryData::<MockTypes>::genesis::<TestVersions>(
+                    &Default::default(),
+                    &Default::default(),
+                )
+                .await)
+            }
+            .boxed()
+        })
+        .unwrap()
+        .get("get_vid_common", move |_, _| {
+            async move {
+                Err::<VidCommonQueryData<MockTypes>, _>(ServerError::catch_all(
+                    StatusCode::NOT_FOUND,
+                    "missing".into(),
+                ))
+            }
+            .boxed()
+        })
+        .unwrap();
+
+        let mut app = App::<(), ServerError>::with_state(());
+        app.register_module("availability", api).unwrap();
+        app.serve(format!("0.0.0.0:{port}"), MockBase::instance())
+            .await
+            .ok();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_transient_errors() {
+        setup_test();
+
+        let port = pick_unused_port().unwrap();
+        let _server = BackgroundTask::spawn("flaky server", flaky_server(port, 5));
+
+        let metrics = PrometheusMetrics::default();
+        let provider = QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        )
+        .with_max_attempts(3)
+        .with_base_delay(Duration::from_millis(10))
+        .with_metrics(&metrics);
+        provider.client.connect(None).await;
+        let retries = || {
+            metrics
+                .get_counter("query_service_provider_retries")
+                .unwrap()
+                .get()
+        };
+
+        // The request fails once all attempts have failed transiently.
+        let res = ProviderTrait::<MockTypes, _>::fetch(&provider, LeafRequest::from(0)).await;
+        assert_eq!(res, None);
+        assert_eq!(retries(), 2);
+
+        // The server recovers during the retries of the next request.
+        let leaf = ProviderTrait::<MockTypes, _>::fetch(&provider, LeafRequest::from(0))
+            .await
+            .unwrap();
+        assert_eq!(leaf.height(), 0);
+        assert_eq!(retries(), 4);
+
+        // A missing object is not retried.
+        let res =
+            ProviderTrait::<MockTypes, _>::fetch(&provider, VidCommonRequest(random_vid_commit()))
+                .await;
+        assert_eq!(res, None);
+        assert_eq!(retries(), 4);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -995,6 +1589,28 @@ mod test {
             sleep(Duration::from_secs(1)).await;
         }
 
+        // Once the pruner run completes, its metrics account for everything it deleted.
+        let metrics = data_source.metrics().get_subgroup(["pruner"]).unwrap();
+        let oldest_retained_height = metrics.get_gauge("oldest_retained_height").unwrap();
+        while oldest_retained_height.get() <= last_leaf.height() as usize {
+            tracing::info!("waiting for pruner metrics");
+            sleep(Duration::from_secs(1)).await;
+        }
+        assert_eq!(
+            metrics.get_counter("pruned_blocks_total").unwrap().get(),
+            last_leaf.height() as usize + 1
+        );
+
+        // The data source knows the earliest height it can still serve.
+        assert_eq!(
+            data_source.earliest_height().await,
+            last_leaf.height() as usize + 1
+        );
+        assert_eq!(
+            data_source.earliest_block_height().await,
+            last_leaf.height() as usize + 1
+        );
+
         // Now close the data source and restart it with archive recovery.
         data_source = db
             .config()
@@ -1017,6 +1633,7 @@ mod test {
             .await
             .unwrap();
         assert_eq!(pruned_height, None);
+        assert_eq!(data_source.earliest_height().await, 0);
 
         // The node has pruned all of it's data including the latest block, so it's forgotten the
         // block height. We need to give it another leaf with some height so it will be willing to
@@ -1251,6 +1868,65 @@ mod test {
         test_fetch_storage_failure_retry_helper(FailureType::Commit).await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_append_chain_storage_failure() {
+        setup_test();
+
+        // Run consensus just long enough to get a short chain of complete blocks.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+        network.start().await;
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+        let blocks = network.data_source().subscribe_blocks(1).await;
+        let blocks = blocks.take(3).collect::<Vec<_>>().await;
+        let vid = network.data_source().subscribe_vid_common(1).await;
+        let vid = vid.take(3).collect::<Vec<_>>().await;
+        let chain = leaves
+            .into_iter()
+            .zip(blocks)
+            .zip(vid)
+            .map(|((leaf, block), common)| BlockInfo::new(leaf, Some(block), Some(common), None))
+            .collect::<Vec<_>>();
+
+        // Start a separate data source, whose storage we can break.
+        let db = TmpDb::init().await;
+        let storage = FailStorage::from(SqlStorage::connect(db.config()).await.unwrap());
+        let data_source = FetchingDataSource::builder(storage, NoFetching)
+            .disable_proactive_fetching()
+            .disable_aggregator()
+            .with_max_retry_interval(Duration::from_millis(100))
+            .with_retry_timeout(Duration::from_secs(1))
+            .build()
+            .await
+            .unwrap();
+
+        // If storage keeps failing, appending the chain fails once we give up retrying, and none of
+        // the chain is stored.
+        data_source.as_ref().fail_writes(FailableAction::Any).await;
+        data_source.append_chain(chain.clone()).await.unwrap_err();
+        data_source.as_ref().pass().await;
+        {
+            let mut tx = data_source.read().await.unwrap();
+            for info in &chain {
+                tx.get_leaf((info.height() as usize).into())
+                    .await
+                    .unwrap_err();
+            }
+        }
+
+        // Once storage recovers, the whole chain is stored.
+        data_source.append_chain(chain.clone()).await.unwrap();
+        let mut tx = data_source.read().await.unwrap();
+        for info in chain {
+            let height = info.height() as usize;
+            assert_eq!(info.leaf, tx.get_leaf(height.into()).await.unwrap());
+            assert_eq!(
+                info.block.unwrap(),
+                tx.get_block(height.into()).await.unwrap()
+            );
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_fetch_on_decide() {
         setup_test();
@@ -1315,6 +1991,86 @@ mod test {
         assert_eq!(vid.block_hash(), leaf.block_hash());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_backfill() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start a web server that the non-consensus node can use to fetch blocks.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "availability",
+            define_api(&Default::default(), MockBase::instance()).unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{port}"), MockBase::instance()),
+        );
+
+        // Start a data source which is not receiving events from consensus, and which only fetches
+        // missing data via backfill.
+        let db = TmpDb::init().await;
+        let provider = Provider::new(QueryServiceProvider::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+            MockBase::instance(),
+        ));
+        let data_source = builder(&db, &provider)
+            .await
+            .with_backfill(Duration::from_millis(100), 2)
+            .with_backfill_concurrency(2)
+            .build()
+            .await
+            .unwrap();
+
+        // Start consensus.
+        network.start().await;
+
+        // Wait until a few blocks have been decided.
+        let leaves = network.data_source().subscribe_leaves(1).await;
+        let leaves = leaves.take(3).collect::<Vec<_>>().await;
+        let last_leaf = leaves.last().unwrap();
+
+        // Give the node the latest leaf, so that it knows all the earlier objects are missing.
+        data_source.append(last_leaf.clone().into()).await.unwrap();
+
+        // Wait for the gaps to be filled, without making any requests for the missing data.
+        loop {
+            let sync_status = data_source.sync_status().await.unwrap();
+
+            // VID shares are unique to a node and will never be fetched from a peer. Ignore missing
+            // VID shares in the `is_fully_synced` check.
+            if (SyncStatus {
+                missing_vid_shares: 0,
+                ..sync_status
+            })
+            .is_fully_synced()
+            {
+                break;
+            }
+            tracing::info!(?sync_status, "waiting for backfill");
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        // Read the backfilled data directly from storage, so that this does not trigger a fetch.
+        let mut tx = data_source.read().await.unwrap();
+        for leaf in &leaves {
+            let id = BlockId::<MockTypes>::from(leaf.height() as usize);
+            assert_eq!(tx.get_block(id).await.unwrap().hash(), leaf.block_hash());
+        }
+        drop(tx);
+
+        // Backfill accounts for the objects it found and filled.
+        let metrics = data_source.metrics().get_subgroup(["backfill"]).unwrap();
+        let found = metrics.get_counter("found").unwrap().get();
+        let filled = metrics.get_counter("filled").unwrap().get();
+        assert!(filled > 0, "{filled}");
+        assert!(filled <= found, "{filled} > {found}");
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_fetch_begin_failure() {
         setup_test();
@@ -21,7 +21,7 @@ use hotshot_types::traits::node_implementation::NodeType;
 use std::sync::Arc;
 use std::{
     fmt::Debug,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 use tokio::sync::broadcast;
 
@@ -35,6 +35,7 @@ pub struct TestProvider<P> {
     inner: Arc<P>,
     unblock: Arc<RwLock<Option<broadcast::Sender<()>>>>,
     fail: Arc<AtomicBool>,
+    requests: Arc<AtomicUsize>,
 }
 
 impl<P> TestProvider<P> {
@@ -43,6 +44,7 @@ impl<P> TestProvider<P> {
             inner: Arc::new(inner),
             unblock: Default::default(),
             fail: Arc::new(AtomicBool::new(false)),
+            requests: Default::default(),
         }
     }
 
@@ -73,7 +75,8 @@ impl<P> TestProvider<P> {
     /// Cause subsequent requests to fail.
     ///
     /// All requests to the provider after this function is called will fail, until
-    /// [`unfail`](Self::unfail) is called.
+    /// [`unfail`](Self::unfail) is called. This includes requests which were
+    /// [blocked](Self::block) before this function was called, but are unblocked after.
     pub fn fail(&self) {
         self.fail.store(true, Ordering::SeqCst);
     }
@@ -82,6 +85,11 @@ impl<P> TestProvider<P> {
     pub fn unfail(&self) {
         self.fail.store(false, Ordering::SeqCst);
     }
+
+    /// The number of fetch requests made to this provider so far.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
 }
 
 #[async_trait]
@@ -92,10 +100,7 @@ where
     P: Provider<Types, T> + Sync,
 {
     async fn fetch(&self, req: T) -> Option<T::Response> {
-        // Fail the request if the user has called `fail`.
-        if self.fail.load(Ordering::SeqCst) {
-            return None;
-        }
+        self.requests.fetch_add(1, Ordering::SeqCst);
 
         // Block the request if the user has called `block`.
         let handle = self
@@ -110,6 +115,11 @@ where
             tracing::info!("request for {req:?} unblocked");
         }
 
+        // Fail the request if the user has called `fail`.
+        if self.fail.load(Ordering::SeqCst) {
+            return None;
+        }
+
         // Do the request.
         self.inner.fetch(req).await
     }
@@ -268,8 +268,9 @@
 //! #   NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart,
 //! # };
 //! # use hotshot_query_service::status::{HasMetrics, StatusDataSource};
+//! # use futures::stream::BoxStream;
 //! # use hotshot_query_service::testing::mocks::MockTypes as AppTypes;
-//! # use std::ops::RangeBounds;
+//! # use std::ops::{Bound, RangeBounds};
 //! # type AppQueryData = ();
 //! // Our AppState takes an underlying data source `D` which already implements the relevant
 //! // traits, and adds some state for use with other modules.
@@ -326,6 +327,8 @@
 //! #   where
 //! #       ID: Into<BlockId<AppTypes>> + Send + Sync { todo!() }
 //! #   async fn get_transaction(&self, hash: TransactionHash<AppTypes>) -> Fetch<TransactionQueryData<AppTypes>> { todo!() }
+//! #   async fn get_leaf_range_rev(&self, start: Bound<usize>, end: usize) -> BoxStream<'static, Fetch<LeafQueryData<AppTypes>>> { todo!() }
+//! #   async fn get_block_range_rev(&self, start: Bound<usize>, end: usize) -> BoxStream<'static, Fetch<BlockQueryData<AppTypes>>> { todo!() }
 //! #   async fn get_leaf_range<R>(&self, range: R) -> Self::LeafRange<R>
 //! #   where
 //! #       R: RangeBounds<usize> + Send { todo!() }
@@ -480,6 +483,47 @@ pub enum QueryError {
     ///
     /// In most cases a missing resource can be recovered from DA.
     Missing,
+    /// The requested block payload existed, but has been intentionally discarded by the pruner.
+    ///
+    /// Unlike a [`Missing`](Self::Missing) resource, a pruned payload will not be recovered from
+    /// DA, so this is reported to HTTP clients as `410 Gone` rather than `404 Not Found`. The
+    /// corresponding leaf and header remain available.
+    PayloadPruned,
+    /// The requested VID data existed, but has been intentionally discarded by the pruner.
+    ///
+    /// A [`PrunePolicy`](data_source::storage::pruning::PrunePolicy) may prune VID data
+    /// independently of payloads, so the payload of the same block may still be available. Like a
+    /// [`PayloadPruned`](Self::PayloadPruned) resource, this is reported to HTTP clients as
+    /// `410 Gone`.
+    VidPruned,
+    /// The requested block payload or VID data existed, but has been deleted on demand.
+    ///
+    /// Like a [`PayloadPruned`](Self::PayloadPruned) resource, a deleted payload will not be
+    /// recovered from DA, and is reported to HTTP clients as `410 Gone`. The corresponding leaf and
+    /// header remain available. See
+    /// [`DeleteBlockStorage`](crate::data_source::storage::DeleteBlockStorage).
+    Deleted,
+    /// The requested snapshot of merklized state is older than the configured history retention.
+    ///
+    /// Nodes which are only needed to serve such old snapshots are discarded as the state is
+    /// updated, so like a [`PayloadPruned`](Self::PayloadPruned) resource, this is reported to HTTP
+    /// clients as `410 Gone`.
+    StatePruned,
+    /// A storage query took too long and was cancelled.
+    ///
+    /// This is a transient condition, reported to HTTP clients as `504 Gateway Timeout`. The same
+    /// request may succeed if retried.
+    Timeout,
+    /// No storage connection became available within the configured acquire timeout.
+    ///
+    /// This indicates that the node is overloaded, and is reported to HTTP clients as
+    /// `503 Service Unavailable`, so that load can be shed rather than piling up waiting requests.
+    PoolExhausted,
+    /// Storage has been failing persistently, and requests are being rejected until it recovers.
+    ///
+    /// This is reported to HTTP clients as `503 Service Unavailable`. See
+    /// [`with_circuit_breaker`](data_source::fetching::Builder::with_circuit_breaker).
+    Unavailable,
     /// There was an error while trying to fetch the requested resource.
     #[snafu(display("Failed to fetch requested resource: {message}"))]
     #[snafu(context(suffix(ErrorSnafu)))]
@@ -490,11 +534,32 @@ impl QueryError {
     pub fn status(&self) -> StatusCode {
         match self {
             Self::NotFound | Self::Missing => StatusCode::NOT_FOUND,
+            Self::PayloadPruned | Self::VidPruned | Self::Deleted | Self::StatePruned => {
+                StatusCode::GONE
+            }
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::PoolExhausted | Self::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
             Self::Error { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+impl From<anyhow::Error> for QueryError {
+    /// Convert a storage error into a [`QueryError`].
+    ///
+    /// If the error is itself a [`QueryError`] (for example, [`PoolExhausted`](Self::PoolExhausted)
+    /// when opening a transaction), it is preserved. Otherwise it becomes a generic
+    /// [`Error`](Self::Error).
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<Self>() {
+            Some(err) => err.clone(),
+            None => Self::Error {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
 pub type QueryResult<T> = Result<T, QueryError>;
 
 #[derive(Default)]
@@ -598,7 +663,7 @@ mod test {
             TransactionQueryData, UpdateAvailabilityData, VidCommonMetadata, VidCommonQueryData,
         },
         metrics::PrometheusMetrics,
-        node::{NodeDataSource, SyncStatus, TimeWindowQueryData, WindowStart},
+        node::{Gap, Gaps, NodeDataSource, SyncGaps, SyncStatus, TimeWindowQueryData, WindowStart},
         status::{HasMetrics, StatusDataSource},
         testing::{
             consensus::MockDataSource,
@@ -608,10 +673,10 @@ mod test {
     use async_lock::RwLock;
     use async_trait::async_trait;
     use atomic_store::{load_store::BincodeLoadStore, AtomicStore, AtomicStoreLoader, RollingLog};
-    use futures::future::FutureExt;
+    use futures::{future::FutureExt, stream::BoxStream};
     use hotshot_types::simple_certificate::QuorumCertificate;
     use portpicker::pick_unused_port;
-    use std::ops::RangeBounds;
+    use std::ops::{Bound, RangeBounds};
     use std::time::Duration;
     use surf_disco::Client;
     use tempfile::TempDir;
@@ -727,6 +792,20 @@ mod test {
         {
             self.hotshot_qs.get_vid_common_metadata_range(range).await
         }
+        async fn get_leaf_range_rev(
+            &self,
+            start: Bound<usize>,
+            end: usize,
+        ) -> BoxStream<'static, Fetch<LeafQueryData<MockTypes>>> {
+            self.hotshot_qs.get_leaf_range_rev(start, end).await
+        }
+        async fn get_block_range_rev(
+            &self,
+            start: Bound<usize>,
+            end: usize,
+        ) -> BoxStream<'static, Fetch<BlockQueryData<MockTypes>>> {
+            self.hotshot_qs.get_block_range_rev(start, end).await
+        }
         async fn get_transaction(
             &self,
             hash: TransactionHash<MockTypes>,
@@ -899,7 +978,18 @@ mod test {
                 missing_leaves: 0,
                 missing_vid_common: 1,
                 missing_vid_shares: 1,
-                pruned_height: None
+                pruned_height: None,
+                latest_stored_height: Some(0),
+                latest_view: None,
+                fully_synced: false,
+                lowest_stored_height: Some(0),
+                gaps: SyncGaps {
+                    vid: Gaps {
+                        ranges: vec![Gap { start: 0, end: 1 }],
+                        has_more: false,
+                    },
+                    ..Default::default()
+                },
             }
         );
         assert_eq!(
@@ -15,11 +15,28 @@
 //! The state API provides an interface for serving queries against arbitrarily old snapshots of the state.
 //! This allows a full Merkle tree to be reconstructed from storage.
 //! If any parent state is missing then the partial snapshot can not be queried.
+//!
+//! # Multiple trees
+//!
+//! A single data source can store any number of trees, each identified by its own
+//! [`MerklizedState`] type. Each tree keeps its nodes in its own table, named by
+//! [`MerklizedState::state_type`], and records its root in its own header field, named by
+//! [`MerklizedState::header_state_commitment_field`], so trees with different keys, entries or
+//! arities do not interfere with each other. The application is responsible for the migrations
+//! creating these tables and header columns.
+//!
+//! To serve several trees, call [`define_api`] once for each tree and register each resulting
+//! module under a distinct name, which then addresses the tree in request URLs (e.g.
+//! `/fee-state/...` and `/block-state/...`). Updates are dispatched to the right table by calling
+//! [`UpdateStateData::insert_merkle_nodes`] with the corresponding state type.
+//!
+//! Note that all trees share a single [last state height](MerklizedStateHeightPersistence), so an
+//! application should insert the nodes of every tree for a given block before advancing it.
 use std::{fmt::Display, path::PathBuf};
 
 use derive_more::From;
 use futures::FutureExt;
-use hotshot_types::traits::node_implementation::NodeType;
+use hotshot_types::traits::{metrics::Metrics, node_implementation::NodeType};
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::fmt::Debug;
@@ -27,7 +44,10 @@ use tagged_base64::TaggedBase64;
 use tide_disco::{api::ApiError, method::ReadState, Api, RequestError, StatusCode};
 use vbs::version::StaticVersionType;
 
-use crate::{api::load_api, QueryError};
+use crate::{
+    api::{load_api, RequestMetrics},
+    QueryError,
+};
 
 pub(crate) mod data_source;
 pub use data_source::*;
@@ -41,8 +61,33 @@ pub struct Options {
     /// These optional files may contain route definitions for application-specific routes that have
     /// been added as extensions to the basic status API.
     pub extensions: Vec<toml::Value>,
+
+    /// Metrics registry in which to record the duration of each request.
+    ///
+    /// If set, every request handler in this module records its latency in a `request_duration`
+    /// histogram, labeled by endpoint, in the `merklized_state` subgroup of this registry. This is
+    /// disabled by default, since it adds a time series for every endpoint. To export these
+    /// histograms along with the other status metrics, use the registry of the data source, e.g.
+    /// `Some(Box::new(data_source.metrics().clone()))`.
+    pub request_metrics: Option<Box<dyn Metrics>>,
+
+    /// The maximum number of changed entries returned in a single page of a state diff.
+    ///
+    /// Defaults to [`DEFAULT_MAX_DIFF_LEN`].
+    pub max_diff_len: Option<usize>,
+
+    /// The maximum number of keys which can be looked up in a single batch request.
+    ///
+    /// Defaults to [`DEFAULT_MAX_PATH_BATCH_LEN`].
+    pub max_path_batch_len: Option<usize>,
 }
 
+/// The default maximum number of changed entries returned in a single page of a state diff.
+pub const DEFAULT_MAX_DIFF_LEN: usize = 1000;
+
+/// The default maximum number of keys which can be looked up in a single batch request.
+pub const DEFAULT_MAX_PATH_BATCH_LEN: usize = 100;
+
 #[derive(Clone, Debug, From, Snafu, Deserialize, Serialize)]
 pub enum Error {
     Request {
@@ -88,30 +133,123 @@ where
         include_str!("../api/state.toml"),
         options.extensions.clone(),
     )?;
+    let request_metrics = RequestMetrics::<<State as ReadState>::State, Error>::new(
+        options.request_metrics.as_deref(),
+        "merklized_state",
+    );
+    let max_diff_len = options.max_diff_len.unwrap_or(DEFAULT_MAX_DIFF_LEN);
+    let max_path_batch_len = options
+        .max_path_batch_len
+        .unwrap_or(DEFAULT_MAX_PATH_BATCH_LEN);
 
     api.with_version("0.0.1".parse().unwrap())
-        .get("get_path", move |req, state| {
-            async move {
-                // Determine the snapshot type based on request parameters, either index or commit
-                let snapshot = if let Some(height) = req.opt_integer_param("height")? {
-                    Snapshot::Index(height)
-                } else {
-                    Snapshot::Commit(req.blob_param("commit")?)
-                };
-
-                let key = req.string_param("key")?;
-                let key = key.parse::<M::Key>().map_err(|_| Error::Custom {
-                    message: "failed to parse Key param".to_string(),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                })?;
-
-                state.get_path(snapshot, key).await.context(QuerySnafu)
-            }
-            .boxed()
-        })?
-        .get("get_height", move |_, state| {
-            async move { state.get_last_state_height().await.context(QuerySnafu) }.boxed()
-        })?;
+        .get(
+            "get_path",
+            request_metrics.instrument("get_path", move |req, state| {
+                async move {
+                    // Determine the snapshot type based on request parameters, either index or commit
+                    let snapshot = if let Some(height) = req.opt_integer_param("height")? {
+                        Snapshot::Index(height)
+                    } else {
+                        Snapshot::Commit(req.blob_param("commit")?)
+                    };
+
+                    let key = req.string_param("key")?;
+                    let key = key.parse::<M::Key>().map_err(|_| Error::Custom {
+                        message: "failed to parse Key param".to_string(),
+                        status: StatusCode::INTERNAL_SERVER_ERROR,
+                    })?;
+
+                    state.get_path(snapshot, key).await.context(QuerySnafu)
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "get_path_at_height",
+            request_metrics.instrument("get_path_at_height", move |req, state| {
+                async move {
+                    let height = req.integer_param("height")?;
+                    let key = req.string_param("key")?;
+                    let key = key.parse::<M::Key>().map_err(|_| Error::Custom {
+                        message: "failed to parse Key param".to_string(),
+                        status: StatusCode::INTERNAL_SERVER_ERROR,
+                    })?;
+
+                    let snapshot = state
+                        .snapshot_for_height(height)
+                        .await
+                        .context(QuerySnafu)?;
+                    state.get_path(snapshot, key).await.context(QuerySnafu)
+                }
+                .boxed()
+            }),
+        )?
+        .at(
+            "get_path_batch",
+            request_metrics.with_state::<State>().instrument(
+                "get_path_batch",
+                move |req, state| {
+                    async move {
+                        let snapshot = if let Some(height) = req.opt_integer_param("height")? {
+                            Snapshot::Index(height)
+                        } else {
+                            Snapshot::Commit(req.blob_param("commit")?)
+                        };
+                        let keys = req.body_auto::<Vec<M::Key>, Ver>(Ver::instance())?;
+                        if keys.len() > max_path_batch_len {
+                            return Err(Error::Custom {
+                                message: format!(
+                                    "too many keys in batch ({}, maximum is {max_path_batch_len})",
+                                    keys.len()
+                                ),
+                                status: StatusCode::BAD_REQUEST,
+                            });
+                        }
+
+                        state
+                            .read(|state| {
+                                async move { state.get_path_batch(snapshot, &keys).await }.boxed()
+                            })
+                            .await
+                            .context(QuerySnafu)
+                    }
+                    .boxed()
+                },
+            ),
+        )?
+        .get(
+            "get_diff",
+            request_metrics.instrument("get_diff", move |req, state| {
+                async move {
+                    let from = req.integer_param("from")?;
+                    let to = req.integer_param("to")?;
+                    let offset = req.opt_integer_param("offset")?.unwrap_or(0);
+                    if from > to {
+                        return Err(Error::Custom {
+                            message: format!("snapshot {from} is later than snapshot {to}"),
+                            status: StatusCode::BAD_REQUEST,
+                        });
+                    }
+                    state
+                        .get_diff(
+                            Snapshot::Index(from),
+                            Snapshot::Index(to),
+                            offset,
+                            max_diff_len,
+                        )
+                        .await
+                        .context(QuerySnafu)
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "get_height",
+            request_metrics.instrument("get_height", move |_, state| {
+                async move { state.get_last_state_height().await.context(QuerySnafu) }.boxed()
+            }),
+        )?;
 
     Ok(api)
 }
@@ -26,7 +26,7 @@ use jf_merkle_tree::{
     prelude::MerkleProof, DigestAlgorithm, Element, ForgetableMerkleTreeScheme, Index,
     MerkleCommitment, NodeValue, ToTraversalPath,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::fmt::Display;
 use std::{fmt::Debug, str::FromStr};
 use tagged_base64::TaggedBase64;
@@ -48,6 +48,47 @@ where
         snapshot: Snapshot<Types, State, ARITY>,
         key: State::Key,
     ) -> QueryResult<MerkleProof<State::Entry, State::Key, State::T, ARITY>>;
+
+    /// Get the membership paths for several leaves at once.
+    ///
+    /// All of the returned proofs are relative to the same root commitment, even if the state is
+    /// updated concurrently with this query.
+    async fn get_path_batch(
+        &self,
+        snapshot: Snapshot<Types, State, ARITY>,
+        keys: &[State::Key],
+    ) -> QueryResult<Vec<MerkleProof<State::Entry, State::Key, State::T, ARITY>>>;
+
+    /// Resolve a block height to the snapshot of the state as of that block.
+    ///
+    /// The result identifies the snapshot by its root commitment. If the block at `height` has been
+    /// pruned, this fails with [`QueryError::Missing`](crate::QueryError::Missing).
+    async fn snapshot_for_height(&self, height: u64) -> QueryResult<Snapshot<Types, State, ARITY>>;
+
+    /// Get the entries which changed between two snapshots.
+    ///
+    /// The result includes every key whose entry was inserted, updated, or removed after `from`,
+    /// up to and including `to`, along with its entry as of `to` ([`None`] if it was removed). The
+    /// keys are returned in a fixed order, so a large diff can be loaded in pages: this returns at
+    /// most `limit` keys, starting from the `offset`th one.
+    async fn get_diff(
+        &self,
+        from: Snapshot<Types, State, ARITY>,
+        to: Snapshot<Types, State, ARITY>,
+        offset: usize,
+        limit: usize,
+    ) -> QueryResult<StateDiff<State::Key, State::Entry>>;
+}
+
+/// The changes to a merklized state between two snapshots.
+///
+/// See [`MerklizedStateDataSource::get_diff`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDiff<Key, Entry> {
+    /// Each changed key, with its new entry, or [`None`] if the entry was removed.
+    pub changes: Vec<(Key, Option<Entry>)>,
+    /// The offset of the next page of changes, or [`None`] if this is the last page.
+    pub next: Option<usize>,
 }
 
 /// This trait defines methods for updating the storage with the merkle tree state.
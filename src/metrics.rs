@@ -16,10 +16,11 @@ use hotshot_types::traits::metrics;
 use itertools::Itertools;
 use prometheus::{
     core::{AtomicU64, GenericCounter, GenericCounterVec, GenericGauge, GenericGaugeVec},
+    proto::MetricType,
     Encoder, HistogramVec, Opts, Registry, TextEncoder,
 };
 use snafu::Snafu;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, RwLock};
 
 #[derive(Debug, Snafu)]
@@ -42,6 +43,11 @@ impl From<prometheus::Error> for MetricsError {
     }
 }
 
+/// The values of a collection of metrics at a point in time, keyed by fully qualified name.
+///
+/// See [`PrometheusMetrics::snapshot`].
+pub type MetricsSnapshot = BTreeMap<String, f64>;
+
 /// A Prometheus-based implementation of a [Metrics](metrics::Metrics) registry.
 ///
 /// [PrometheusMetrics] provides a collection of metrics including [Counter], [Gauge], and
@@ -131,6 +137,64 @@ impl PrometheusMetrics {
         Ok(curr)
     }
 
+    /// Take a snapshot of the current values of all metrics.
+    ///
+    /// Like the Prometheus export, the snapshot includes every metric in the tree of related groups,
+    /// keyed by its fully qualified name. A metric with labels is keyed by its name followed by the
+    /// labels, like `name{label="value"}`. A histogram contributes its sample count and sum, under
+    /// the keys `name_count` and `name_sum`.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot_of(|_| true)
+    }
+
+    /// Take a snapshot of the current values of the metrics whose fully qualified names satisfy
+    /// `filter`.
+    ///
+    /// See [`snapshot`](Self::snapshot) for the format of the snapshot.
+    pub fn snapshot_of(&self, filter: impl Fn(&str) -> bool) -> MetricsSnapshot {
+        let mut snapshot = MetricsSnapshot::new();
+        for family in self.metrics.gather() {
+            let name = family.get_name();
+            if !filter(name) {
+                continue;
+            }
+            for metric in family.get_metric() {
+                let labels = metric.get_label();
+                let labels = if labels.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "{{{}}}",
+                        labels
+                            .iter()
+                            .map(|label| format!("{}=\"{}\"", label.get_name(), label.get_value()))
+                            .join(",")
+                    )
+                };
+                match family.get_field_type() {
+                    MetricType::COUNTER => {
+                        snapshot
+                            .insert(format!("{name}{labels}"), metric.get_counter().get_value());
+                    }
+                    MetricType::GAUGE => {
+                        snapshot.insert(format!("{name}{labels}"), metric.get_gauge().get_value());
+                    }
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        snapshot.insert(
+                            format!("{name}_count{labels}"),
+                            histogram.get_sample_count() as f64,
+                        );
+                        snapshot.insert(format!("{name}_sum{labels}"), histogram.get_sample_sum());
+                    }
+                    // We never register any other kinds of metrics.
+                    _ => {}
+                }
+            }
+        }
+        snapshot
+    }
+
     fn get_metric<M: Clone>(
         &self,
         metrics: &Arc<RwLock<HashMap<String, M>>>,
@@ -500,6 +564,41 @@ mod test {
         assert!(lines.contains(&"histogram_sum 42"));
         assert!(lines.contains(&"histogram_count 2"));
         assert!(lines.contains(&"text 1"));
+
+        // The export is in the Prometheus text exposition format, with metadata for each metric and
+        // the full set of series for histograms.
+        assert!(lines.contains(&"# HELP counter counter"), "{lines:?}");
+        assert!(lines.contains(&"# TYPE counter counter"), "{lines:?}");
+        assert!(lines.contains(&"# TYPE gauge gauge"), "{lines:?}");
+        assert!(lines.contains(&"# TYPE histogram histogram"), "{lines:?}");
+        assert!(
+            lines.contains(&"histogram_bucket{le=\"10\"} 0"),
+            "{lines:?}"
+        );
+        assert!(
+            lines.contains(&"histogram_bucket{le=\"+Inf\"} 2"),
+            "{lines:?}"
+        );
+
+        // Take a snapshot.
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["counter"], 42f64);
+        assert_eq!(snapshot["gauge"], 100f64);
+        assert_eq!(snapshot["histogram_count"], 2f64);
+        assert_eq!(snapshot["histogram_sum"], 42f64);
+        assert_eq!(snapshot["text"], 1f64);
+
+        // Take a snapshot of only some metrics.
+        let snapshot = metrics.snapshot_of(|name| name == "counter" || name == "histogram");
+        assert_eq!(
+            snapshot,
+            [
+                ("counter".to_string(), 42f64),
+                ("histogram_count".to_string(), 2f64),
+                ("histogram_sum".to_string(), 42f64),
+            ]
+            .into()
+        );
     }
 
     #[test]
@@ -20,12 +20,15 @@
 //! fully synced with the entire history of the chain. However, the node will _eventually_ sync and
 //! return the expected counts.
 
-use crate::{api::load_api, QueryError};
+use crate::{
+    api::{load_api, RequestMetrics},
+    NotFoundSnafu, QueryError,
+};
 use derive_more::From;
 use futures::FutureExt;
-use hotshot_types::traits::node_implementation::NodeType;
+use hotshot_types::traits::{metrics::Metrics, node_implementation::NodeType};
 use serde::{Deserialize, Serialize};
-use snafu::{ResultExt, Snafu};
+use snafu::{OptionExt, ResultExt, Snafu};
 use std::{fmt::Display, ops::Bound, path::PathBuf};
 use tide_disco::{api::ApiError, method::ReadState, Api, RequestError, StatusCode};
 use vbs::version::StaticVersionType;
@@ -47,6 +50,15 @@ pub struct Options {
 
     /// The maximum number of headers which can be loaded in a single `header/window` query.
     pub window_limit: usize,
+
+    /// Metrics registry in which to record the duration of each request.
+    ///
+    /// If set, every request handler in this module records its latency in a `request_duration`
+    /// histogram, labeled by endpoint, in the `node` subgroup of this registry. This is disabled by
+    /// default, since it adds a time series for every endpoint. To export these histograms along
+    /// with the other status metrics, use the registry of the data source, e.g.
+    /// `Some(Box::new(data_source.metrics().clone()))`.
+    pub request_metrics: Option<Box<dyn Metrics>>,
 }
 
 impl Default for Options {
@@ -55,6 +67,7 @@ impl Default for Options {
             api_path: None,
             extensions: vec![],
             window_limit: 500,
+            request_metrics: None,
         }
     }
 }
@@ -123,79 +136,113 @@ where
         options.extensions.clone(),
     )?;
     let window_limit = options.window_limit;
+    let request_metrics = RequestMetrics::<<State as ReadState>::State, Error>::new(
+        options.request_metrics.as_deref(),
+        "node",
+    );
     api.with_version("0.0.1".parse().unwrap())
-        .get("block_height", |_req, state| {
-            async move { state.block_height().await.context(QuerySnafu) }.boxed()
-        })?
-        .get("count_transactions", |req, state| {
-            async move {
-                let from: Bound<usize> = match req.opt_integer_param("from")? {
-                    Some(from) => Bound::Included(from),
-                    None => Bound::Unbounded,
-                };
-                let to = match req.opt_integer_param("to")? {
-                    Some(to) => Bound::Included(to),
-                    None => Bound::Unbounded,
-                };
-                Ok(state.count_transactions_in_range((from, to)).await?)
-            }
-            .boxed()
-        })?
-        .get("payload_size", |req, state| {
-            async move {
-                let from: Bound<usize> = match req.opt_integer_param("from")? {
-                    Some(from) => Bound::Included(from),
-                    None => Bound::Unbounded,
-                };
-                let to = match req.opt_integer_param("to")? {
-                    Some(to) => Bound::Included(to),
-                    None => Bound::Unbounded,
-                };
-                Ok(state.payload_size_in_range((from, to)).await?)
-            }
-            .boxed()
-        })?
-        .get("get_vid_share", |req, state| {
-            async move {
-                let id = if let Some(height) = req.opt_integer_param("height")? {
-                    BlockId::Number(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    BlockId::Hash(hash)
-                } else {
-                    BlockId::PayloadHash(req.blob_param("payload-hash")?)
-                };
-                state.vid_share(id).await.context(QueryVidSnafu {
-                    block: id.to_string(),
-                })
-            }
-            .boxed()
-        })?
-        .get("sync_status", |_req, state| {
-            async move { state.sync_status().await.context(QuerySnafu) }.boxed()
-        })?
-        .get("get_header_window", move |req, state| {
-            async move {
-                let start = if let Some(height) = req.opt_integer_param("height")? {
-                    WindowStart::Height(height)
-                } else if let Some(hash) = req.opt_blob_param("hash")? {
-                    WindowStart::Hash(hash)
-                } else {
-                    WindowStart::Time(req.integer_param("start")?)
-                };
-                let end = req.integer_param("end")?;
-                state
-                    .get_header_window(start, end, window_limit)
-                    .await
-                    .context(QueryWindowSnafu {
-                        start: format!("{start:?}"),
-                        end,
+        .get(
+            "block_height",
+            request_metrics.instrument("block_height", |_req, state| {
+                async move { state.block_height().await.context(QuerySnafu) }.boxed()
+            }),
+        )?
+        .get(
+            "count_transactions",
+            request_metrics.instrument("count_transactions", |req, state| {
+                async move {
+                    let from: Bound<usize> = match req.opt_integer_param("from")? {
+                        Some(from) => Bound::Included(from),
+                        None => Bound::Unbounded,
+                    };
+                    let to = match req.opt_integer_param("to")? {
+                        Some(to) => Bound::Included(to),
+                        None => Bound::Unbounded,
+                    };
+                    Ok(state.count_transactions_in_range((from, to)).await?)
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "payload_size",
+            request_metrics.instrument("payload_size", |req, state| {
+                async move {
+                    let from: Bound<usize> = match req.opt_integer_param("from")? {
+                        Some(from) => Bound::Included(from),
+                        None => Bound::Unbounded,
+                    };
+                    let to = match req.opt_integer_param("to")? {
+                        Some(to) => Bound::Included(to),
+                        None => Bound::Unbounded,
+                    };
+                    Ok(state.payload_size_in_range((from, to)).await?)
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "get_vid_share",
+            request_metrics.instrument("get_vid_share", |req, state| {
+                async move {
+                    let id = if let Some(height) = req.opt_integer_param("height")? {
+                        BlockId::Number(height)
+                    } else if let Some(hash) = req.opt_blob_param("hash")? {
+                        BlockId::Hash(hash)
+                    } else if let Some(hash) = req.opt_blob_param("payload-hash")? {
+                        BlockId::PayloadHash(hash)
+                    } else {
+                        // The `latest` alias, resolved to the most recent block at the time of the
+                        // request.
+                        let height = state.block_height().await.context(QuerySnafu)?;
+                        let latest = height
+                            .checked_sub(1)
+                            .context(NotFoundSnafu)
+                            .context(QueryVidSnafu { block: "latest" })?;
+                        BlockId::Number(latest)
+                    };
+                    state.vid_share(id).await.context(QueryVidSnafu {
+                        block: id.to_string(),
                     })
-            }
-            .boxed()
-        })?
-        .get("get_limits", move |_req, _state| {
-            async move { Ok(Limits { window_limit }) }.boxed()
-        })?;
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "sync_status",
+            request_metrics.instrument("sync_status", |_req, state| {
+                async move { state.sync_status().await.context(QuerySnafu) }.boxed()
+            }),
+        )?
+        .get(
+            "get_header_window",
+            request_metrics.instrument("get_header_window", move |req, state| {
+                async move {
+                    let start = if let Some(height) = req.opt_integer_param("height")? {
+                        WindowStart::Height(height)
+                    } else if let Some(hash) = req.opt_blob_param("hash")? {
+                        WindowStart::Hash(hash)
+                    } else {
+                        WindowStart::Time(req.integer_param("start")?)
+                    };
+                    let end = req.integer_param("end")?;
+                    state
+                        .get_header_window(start, end, window_limit)
+                        .await
+                        .context(QueryWindowSnafu {
+                            start: format!("{start:?}"),
+                            end,
+                        })
+                }
+                .boxed()
+            }),
+        )?
+        .get(
+            "get_limits",
+            request_metrics.instrument("get_limits", move |_req, _state| {
+                async move { Ok(Limits { window_limit }) }.boxed()
+            }),
+        )?;
     Ok(api)
 }
 
@@ -204,6 +251,7 @@ mod test {
     use super::*;
     use crate::{
         data_source::ExtensibleDataSource,
+        metrics::PrometheusMetrics,
         task::BackgroundTask,
         testing::{
             consensus::{MockDataSource, MockNetwork, MockSqlDataSource},
@@ -405,6 +453,12 @@ mod test {
         assert_eq!(sync_status.missing_blocks, 0);
         assert_eq!(sync_status.missing_leaves, 0);
 
+        // The status should also report our position relative to consensus.
+        let block_height = client.get::<usize>("block-height").send().await.unwrap();
+        assert!(sync_status.latest_stored_height.unwrap() < block_height);
+        assert!(sync_status.latest_view.is_some(), "{sync_status:?}");
+        assert_eq!(sync_status.fully_synced, sync_status.is_fully_synced());
+
         network.shut_down().await;
     }
 
@@ -531,6 +585,32 @@ mod test {
                 .unwrap()
         );
 
+        // Empty range, even one extending past the end of the chain
+        assert_eq!(
+            0,
+            client
+                .get::<usize>(&format!(
+                    "node/transactions/count/{}/{}",
+                    tx_heights[1] + 100,
+                    tx_heights[0]
+                ))
+                .send()
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            0,
+            client
+                .get::<usize>(&format!(
+                    "node/payloads/size/{}/{}",
+                    tx_heights[1] + 100,
+                    tx_heights[0]
+                ))
+                .send()
+                .await
+                .unwrap()
+        );
+
         // All transactions
         assert_eq!(
             2,
@@ -620,4 +700,55 @@ mod test {
         let sync_status: SyncStatus = client.get("sync-status").send().await.unwrap();
         assert!(sync_status.is_fully_synced(), "{sync_status:?}");
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_request_metrics() {
+        setup_test();
+
+        let dir = TempDir::with_prefix("test_node_request_metrics").unwrap();
+        let data_source = MockDataSource::create(dir.path(), Default::default())
+            .await
+            .unwrap();
+        let metrics = PrometheusMetrics::default();
+
+        let api = define_api::<RwLock<MockDataSource>, MockTypes, MockBase>(
+            &Options {
+                request_metrics: Some(Box::new(metrics.clone())),
+                ..Default::default()
+            },
+            MockBase::instance(),
+        )
+        .unwrap();
+        let mut app = App::<_, Error>::with_state(RwLock::new(data_source));
+        app.register_module("node", api).unwrap();
+
+        let port = pick_unused_port().unwrap();
+        let _server = BackgroundTask::spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{}/node", port).parse().unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        for _ in 0..3 {
+            client.get::<u64>("block-height").send().await.unwrap();
+        }
+        client
+            .get::<SyncStatus>("sync-status")
+            .send()
+            .await
+            .unwrap();
+
+        // Each endpoint records its own latency distribution.
+        let durations = metrics
+            .get_subgroup(["node"])
+            .unwrap()
+            .get_histogram_family("request_duration")
+            .unwrap();
+        assert_eq!(durations.get(&["block_height"]).sample_count(), 3);
+        assert_eq!(durations.get(&["sync_status"]).sample_count(), 1);
+        assert_eq!(durations.get(&["payload_size"]).sample_count(), 0);
+    }
 }
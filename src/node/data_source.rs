@@ -69,7 +69,11 @@ pub trait NodeDataSource<Types: NodeType> {
         limit: usize,
     ) -> QueryResult<TimeWindowQueryData<Header<Types>>>;
 
-    /// Search the database for missing objects and generate a report.
+    /// Report how far behind consensus this node's storage is.
+    ///
+    /// The report includes the height of the latest stored leaf, the latest view seen by consensus
+    /// (if known), the number of objects missing from the stored range of the chain, and whether
+    /// the node is fully synced.
     async fn sync_status(&self) -> QueryResult<SyncStatus>;
 
     async fn count_transactions(&self) -> QueryResult<usize> {
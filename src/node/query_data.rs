@@ -13,31 +13,127 @@
 use crate::types::HeightIndexed;
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
+use std::{cmp::min, iter, ops::Range};
 
 pub use crate::availability::{BlockHash, BlockId};
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+/// Progress of a node in syncing with the latest state of the blockchain.
+///
+/// The storage layer is responsible for the counts of missing objects, the pruned height and the
+/// lowest stored height. The remaining fields, which describe the node's position relative to
+/// consensus and the ranges of missing data, are filled in by the data source.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct SyncStatus {
     pub missing_blocks: usize,
     pub missing_leaves: usize,
     pub missing_vid_common: usize,
     pub missing_vid_shares: usize,
     pub pruned_height: Option<usize>,
+    /// The height of the latest leaf in storage, if there is one.
+    #[serde(default)]
+    pub latest_stored_height: Option<usize>,
+    /// The latest view reached by consensus, as observed by this node, if known.
+    #[serde(default)]
+    pub latest_view: Option<u64>,
+    /// Whether no data is missing from the stored range of the chain.
+    #[serde(default)]
+    pub fully_synced: bool,
+    /// The height of the earliest leaf in storage, if there is one.
+    #[serde(default)]
+    pub lowest_stored_height: Option<usize>,
+    /// Ranges of heights missing from storage, for each type of object.
+    #[serde(default)]
+    pub gaps: SyncGaps,
+}
+
+/// Ranges of heights missing from storage, for each type of object.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct SyncGaps {
+    pub leaves: Gaps,
+    pub blocks: Gaps,
+    pub vid: Gaps,
+}
+
+/// A bounded list of ranges of missing heights.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Gaps {
+    /// Missing ranges, in ascending order.
+    pub ranges: Vec<Gap>,
+    /// Whether there are more missing ranges after the ones in `ranges`, which were omitted to
+    /// bound the size of the response.
+    pub has_more: bool,
+}
+
+impl Gaps {
+    /// Collect the gaps in `range` given the heights which are present, in ascending order.
+    ///
+    /// At most `max_gaps` gaps are collected. Heights outside of `range` are ignored.
+    pub fn from_present(
+        present: impl IntoIterator<Item = usize>,
+        range: Range<usize>,
+        max_gaps: usize,
+    ) -> Self {
+        let mut gaps = Self::default();
+        let mut next = range.start;
+        // Treat the end of the range as present, so that we detect a gap at the end.
+        let present = present
+            .into_iter()
+            .filter(|height| *height >= range.start)
+            .chain(iter::once(range.end));
+        for height in present {
+            let height = min(height, range.end);
+            if height > next && !gaps.push(next..height, max_gaps) {
+                break;
+            }
+            if height >= range.end {
+                break;
+            }
+            next = height + 1;
+        }
+        gaps
+    }
+
+    /// Add a gap to the list, unless the list already has `max_gaps` gaps.
+    ///
+    /// Returns `false`, and sets [`has_more`](Self::has_more), if the gap was omitted.
+    pub fn push(&mut self, range: Range<usize>, max_gaps: usize) -> bool {
+        if self.ranges.len() >= max_gaps {
+            self.has_more = true;
+            return false;
+        }
+        self.ranges.push(Gap {
+            start: range.start,
+            end: range.end,
+        });
+        true
+    }
+}
+
+/// A range of missing heights, from `start` (inclusive) to `end` (exclusive).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Gap {
+    pub start: usize,
+    pub end: usize,
 }
 
 impl SyncStatus {
     pub fn fully_synced() -> Self {
         Self {
-            missing_blocks: 0,
-            missing_leaves: 0,
-            missing_vid_common: 0,
-            missing_vid_shares: 0,
-            pruned_height: None,
+            fully_synced: true,
+            ..Default::default()
         }
     }
 
+    /// Whether there is no data missing and none has been pruned.
+    ///
+    /// This only considers the counts of missing objects, not the position of the node relative to
+    /// consensus, so it can be used to check a status which has not been completely filled in.
     pub fn is_fully_synced(&self) -> bool {
-        *self == Self::fully_synced()
+        self.missing_blocks == 0
+            && self.missing_leaves == 0
+            && self.missing_vid_common == 0
+            && self.missing_vid_shares == 0
+            && self.pruned_height.is_none()
     }
 }
 
@@ -66,3 +162,52 @@ impl<T: HeightIndexed> TimeWindowQueryData<T> {
 pub struct Limits {
     pub window_limit: usize,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gaps(ranges: &[(usize, usize)], has_more: bool) -> Gaps {
+        Gaps {
+            ranges: ranges
+                .iter()
+                .map(|&(start, end)| Gap { start, end })
+                .collect(),
+            has_more,
+        }
+    }
+
+    #[test]
+    fn test_gaps_from_present() {
+        // Nothing missing.
+        assert_eq!(Gaps::from_present(0..5, 0..5, 10), gaps(&[], false));
+        // Nothing present.
+        assert_eq!(Gaps::from_present([], 0..5, 10), gaps(&[(0, 5)], false));
+        // Empty range.
+        assert_eq!(Gaps::from_present([], 3..3, 10), gaps(&[], false));
+
+        // Gaps at the start, middle, and end of the range.
+        let present = [2, 3, 5, 8];
+        assert_eq!(
+            Gaps::from_present(present, 0..10, 10),
+            gaps(&[(0, 2), (4, 5), (6, 8), (9, 10)], false)
+        );
+
+        // Heights outside the range are ignored.
+        assert_eq!(
+            Gaps::from_present(present, 3..8, 10),
+            gaps(&[(4, 5), (6, 8)], false)
+        );
+
+        // The number of gaps is bounded.
+        assert_eq!(
+            Gaps::from_present(present, 0..10, 2),
+            gaps(&[(0, 2), (4, 5)], true)
+        );
+        assert_eq!(
+            Gaps::from_present(present, 0..10, 4),
+            gaps(&[(0, 2), (4, 5), (6, 8), (9, 10)], false)
+        );
+        assert_eq!(Gaps::from_present([], 0..5, 0), gaps(&[], true));
+    }
+}
@@ -25,13 +25,15 @@
 
 use crate::api::load_api;
 use derive_more::From;
-use futures::FutureExt;
+use futures::{stream, FutureExt, StreamExt, TryFutureExt};
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::time::Duration;
 use tide_disco::{api::ApiError, method::ReadState, Api, RequestError, StatusCode};
+use tokio::time::sleep;
 use vbs::version::StaticVersionType;
 
 pub(crate) mod data_source;
@@ -47,12 +49,41 @@ pub struct Options {
     /// These optional files may contain route definitions for application-specific routes that have
     /// been added as extensions to the basic status API.
     pub extensions: Vec<toml::Value>,
+
+    /// The maximum time since the last decide for the node to be considered healthy.
+    ///
+    /// If set, the `healthcheck` endpoint reports the node as unhealthy when no block has been
+    /// decided for longer than this, for example because the task feeding consensus events to the
+    /// data source has died. By default, only the reachability of storage is checked.
+    pub max_decide_staleness: Option<Duration>,
+
+    /// The maximum number of decided blocks not yet stored for the node to be considered ready.
+    ///
+    /// If set, the `readiness` endpoint reports the node as not ready when storage falls further
+    /// than this behind consensus. By default, only the reachability of storage is checked.
+    pub max_sync_lag: Option<u64>,
+
+    /// How often the `stream_metrics` endpoint sends a snapshot of the metrics.
+    ///
+    /// Defaults to [`DEFAULT_METRICS_STREAM_INTERVAL`].
+    pub metrics_stream_interval: Option<Duration>,
 }
 
+/// The default interval between snapshots sent by the `stream_metrics` endpoint.
+pub const DEFAULT_METRICS_STREAM_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone, Debug, From, Snafu, Deserialize, Serialize)]
 pub enum Error {
-    Request { source: RequestError },
-    Internal { reason: String },
+    Request {
+        source: RequestError,
+    },
+    Internal {
+        reason: String,
+    },
+    #[snafu(display("node is not ready: {readiness:?}"))]
+    NotReady {
+        readiness: Readiness,
+    },
 }
 
 impl Error {
@@ -60,6 +91,7 @@ impl Error {
         match self {
             Self::Request { .. } => StatusCode::BAD_REQUEST,
             Self::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotReady { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
@@ -83,7 +115,20 @@ where
         include_str!("../api/status.toml"),
         options.extensions.clone(),
     )?;
+    let max_decide_staleness = options.max_decide_staleness;
+    let max_sync_lag = options.max_sync_lag;
+    let metrics_stream_interval = options
+        .metrics_stream_interval
+        .unwrap_or(DEFAULT_METRICS_STREAM_INTERVAL);
     api.with_version("0.0.1".parse().unwrap())
+        .with_health_check(move |state| {
+            async move {
+                state
+                    .read(|state| state.health(max_decide_staleness).boxed())
+                    .await
+            }
+            .boxed()
+        })
         .get("block_height", |_, state| {
             async { state.block_height().await.map_err(internal) }.boxed()
         })?
@@ -99,8 +144,50 @@ where
             }
             .boxed()
         })?
+        .at("liveness", |_, _| async { Ok("alive") }.boxed())?
+        .at("readiness", move |_, state| {
+            async move {
+                let readiness = state
+                    .read(|state| state.readiness(max_sync_lag).boxed())
+                    .await;
+                if readiness.ready {
+                    Ok(readiness)
+                } else {
+                    Err(Error::NotReady { readiness })
+                }
+            }
+            .boxed()
+        })?
         .metrics("metrics", |_, state| {
             async { Ok(Cow::Borrowed(state.metrics())) }.boxed()
+        })?
+        .stream("stream_metrics", move |req, state| {
+            async move {
+                // If no names are given, stream all metrics.
+                let names = req
+                    .opt_string_param("names")?
+                    .map(|names| names.split(',').map(String::from).collect::<Vec<_>>());
+                let metrics = state
+                    .read(|state| async move { state.metrics().clone() }.boxed())
+                    .await;
+                Ok(stream::unfold(true, move |first| {
+                    let metrics = metrics.clone();
+                    let names = names.clone();
+                    async move {
+                        if !first {
+                            sleep(metrics_stream_interval).await;
+                        }
+                        let snapshot = metrics.snapshot_of(|name| {
+                            names
+                                .as_ref()
+                                .map_or(true, |names| names.iter().any(|n| n == name))
+                        });
+                        Some((Ok(snapshot), false))
+                    }
+                }))
+            }
+            .try_flatten_stream()
+            .boxed()
         })?;
     Ok(api)
 }
@@ -110,16 +197,19 @@ mod test {
     use super::*;
     use crate::{
         data_source::ExtensibleDataSource,
+        metrics::{MetricsSnapshot, PrometheusMetrics},
         task::BackgroundTask,
         testing::{
             consensus::{MockDataSource, MockNetwork},
             mocks::MockBase,
             setup_test, sleep,
         },
-        ApiState, Error,
+        ApiState, Error, QueryError, QueryResult,
     };
     use async_lock::RwLock;
-    use futures::FutureExt;
+    use async_trait::async_trait;
+    use futures::{FutureExt, StreamExt};
+    use hotshot_types::traits::metrics::Metrics;
     use portpicker::pick_unused_port;
     use reqwest::redirect::Policy;
     use std::str::FromStr;
@@ -266,4 +356,247 @@ mod test {
         // Ensure we can still access the built-in functionality.
         assert_eq!(client.get::<u64>("block-height").send().await.unwrap(), 0);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_metrics() {
+        setup_test();
+
+        let dir = TempDir::with_prefix("test_stream_metrics").unwrap();
+        let data_source = MockDataSource::create(dir.path(), Default::default())
+            .await
+            .unwrap();
+
+        // Register some metrics of our own, so we know what values to expect.
+        let metrics = data_source.populate_metrics();
+        let gauge = metrics.create_gauge("gauge".into(), None);
+        let counter = metrics.create_counter("counter".into(), None);
+        gauge.set(42);
+
+        let mut app = App::<_, Error>::with_state(RwLock::new(data_source));
+        app.register_module(
+            "status",
+            define_api(
+                &Options {
+                    metrics_stream_interval: Some(Duration::from_millis(100)),
+                    ..Default::default()
+                },
+                MockBase::instance(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let port = pick_unused_port().unwrap();
+        let _server = BackgroundTask::spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{}/status", port).parse().unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        // Subscribe to just the metrics we registered.
+        let mut snapshots = client
+            .socket("stream/metrics/consensus_gauge,consensus_counter")
+            .subscribe::<MetricsSnapshot>()
+            .await
+            .unwrap();
+        assert_eq!(
+            snapshots.next().await.unwrap().unwrap(),
+            [
+                ("consensus_counter".to_string(), 0f64),
+                ("consensus_gauge".to_string(), 42f64),
+            ]
+            .into()
+        );
+
+        // Changes are reflected in later snapshots.
+        counter.add(1);
+        loop {
+            let snapshot = snapshots.next().await.unwrap().unwrap();
+            if snapshot["consensus_counter"] == 1f64 {
+                assert_eq!(snapshot["consensus_gauge"], 42f64);
+                break;
+            }
+            tracing::info!("waiting for counter to update");
+        }
+
+        // Without a list of names, every metric is included.
+        let snapshot = client
+            .socket("stream/metrics")
+            .subscribe::<MetricsSnapshot>()
+            .await
+            .unwrap()
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot["consensus_gauge"], 42f64);
+        assert_eq!(snapshot["consensus_counter"], 1f64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_healthcheck() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "status",
+            define_api(
+                &Options {
+                    max_decide_staleness: Some(Duration::from_secs(60)),
+                    ..Default::default()
+                },
+                MockBase::instance(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+        let url = format!("http://localhost:{port}/status/healthcheck");
+        let client = reqwest::Client::new();
+
+        // Until the network starts, nothing has been decided, so the node is stale. We can't use
+        // `client.connect` here, since it waits for the health check to pass.
+        let res = loop {
+            match client.get(&url).send().await {
+                Ok(res) => break res,
+                Err(err) => {
+                    tracing::info!("waiting for server to start: {err}");
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        };
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let health: Health = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+        assert_eq!(health.status, HealthStatus::Unhealthy);
+
+        // Once blocks are being decided, the node becomes healthy.
+        network.start().await;
+        loop {
+            let res = client.get(&url).send().await.unwrap();
+            if res.status() == StatusCode::OK {
+                let health: Health = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+                assert_eq!(health.status, HealthStatus::Available);
+                assert!(health.last_decide_age_secs.unwrap() <= 60, "{health:?}");
+                break;
+            }
+            tracing::info!("waiting for node to become healthy");
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        network.shut_down().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_liveness_and_readiness() {
+        setup_test();
+
+        // Create the consensus network.
+        let mut network = MockNetwork::<MockDataSource>::init().await;
+
+        // Start the web server.
+        let port = pick_unused_port().unwrap();
+        let mut app = App::<_, Error>::with_state(ApiState::from(network.data_source()));
+        app.register_module(
+            "status",
+            define_api(
+                &Options {
+                    max_sync_lag: Some(100),
+                    ..Default::default()
+                },
+                MockBase::instance(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        network.spawn(
+            "server",
+            app.serve(format!("0.0.0.0:{}", port), MockBase::instance()),
+        );
+        let client = Client::<Error, MockBase>::new(
+            format!("http://localhost:{port}/status").parse().unwrap(),
+        );
+        assert!(client.connect(Some(Duration::from_secs(60))).await);
+
+        assert_eq!(
+            client.get::<String>("liveness").send().await.unwrap(),
+            "alive"
+        );
+        network.start().await;
+        let readiness = client.get::<Readiness>("readiness").send().await.unwrap();
+        assert!(readiness.ready, "{readiness:?}");
+        assert_eq!(readiness.reason, None);
+        assert!(readiness.sync_lag.unwrap() <= 100, "{readiness:?}");
+
+        network.shut_down().await;
+    }
+
+    /// A status data source with a fixed block height, or unreachable storage.
+    struct FixedStatus {
+        metrics: PrometheusMetrics,
+        block_height: Option<usize>,
+    }
+
+    impl HasMetrics for FixedStatus {
+        fn metrics(&self) -> &PrometheusMetrics {
+            &self.metrics
+        }
+    }
+
+    #[async_trait]
+    impl StatusDataSource for FixedStatus {
+        async fn block_height(&self) -> QueryResult<usize> {
+            self.block_height.ok_or_else(|| QueryError::Error {
+                message: "storage unreachable".into(),
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_readiness_reasons() {
+        setup_test();
+
+        let metrics = PrometheusMetrics::default();
+        metrics
+            .subgroup("consensus".into())
+            .create_gauge("last_synced_block_height".into(), None)
+            .set(10);
+
+        // Unreachable storage.
+        let status = FixedStatus {
+            metrics: metrics.clone(),
+            block_height: None,
+        };
+        let readiness = status.readiness(None).await;
+        assert!(!readiness.ready);
+        assert_eq!(readiness.reason, Some(NotReadyReason::DbUnreachable));
+        assert_eq!(
+            super::Error::NotReady { readiness }.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        // Storage behind consensus.
+        let status = FixedStatus {
+            metrics,
+            block_height: Some(4),
+        };
+        assert_eq!(status.sync_lag().await.unwrap(), 6);
+        let readiness = status.readiness(Some(5)).await;
+        assert!(!readiness.ready);
+        assert_eq!(readiness.reason, Some(NotReadyReason::SyncLagExceeded));
+        assert_eq!(readiness.sync_lag, Some(6));
+        assert!(status.readiness(Some(6)).await.ready);
+        assert!(status.readiness(None).await.ready);
+    }
 }
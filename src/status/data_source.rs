@@ -18,6 +18,9 @@ use crate::{
 use async_trait::async_trait;
 use chrono::Utc;
 use hotshot_types::traits::metrics::Metrics;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tide_disco::{healthcheck::HealthCheck, StatusCode};
 
 pub trait HasMetrics {
     fn metrics(&self) -> &PrometheusMetrics;
@@ -58,6 +61,121 @@ pub trait StatusDataSource: HasMetrics {
         // By definition, a successful view is any which committed a block.
         Ok(self.block_height().await? as f64 / total_views)
     }
+
+    /// Check whether this data source is able to serve requests.
+    ///
+    /// The data source is healthy if its storage is reachable, which is checked by loading the
+    /// block height, and if `max_decide_age` is given, if the last decide happened at most that long
+    /// ago. Note that a node which has not yet decided anything since starting up counts as stale.
+    async fn health(&self, max_decide_age: Option<Duration>) -> Health {
+        let last_decide_age_secs = self.elapsed_time_since_last_decide().await.ok();
+        let mut status = HealthStatus::Available;
+        if let Err(err) = self.block_height().await {
+            tracing::warn!("health check failed to reach storage: {err:#}");
+            status = HealthStatus::Unhealthy;
+        }
+        if let Some(max_age) = max_decide_age {
+            if !last_decide_age_secs.is_some_and(|age| age <= max_age.as_secs()) {
+                tracing::warn!(
+                    ?last_decide_age_secs,
+                    ?max_age,
+                    "health check found stale decide"
+                );
+                status = HealthStatus::Unhealthy;
+            }
+        }
+        Health {
+            status,
+            last_decide_age_secs,
+        }
+    }
+
+    /// The number of blocks decided by consensus which have not yet been stored.
+    async fn sync_lag(&self) -> QueryResult<u64> {
+        let decided_height = self
+            .consensus_metrics()?
+            .get_gauge("last_synced_block_height")
+            .map_err(metrics_err)?
+            .get();
+        Ok(decided_height.saturating_sub(self.block_height().await?) as u64)
+    }
+
+    /// Check whether this data source is ready to serve requests.
+    ///
+    /// The data source is ready if its storage is reachable, which is checked by loading the block
+    /// height, and if `max_sync_lag` is given, if it has stored all but at most that many of the
+    /// blocks decided by consensus.
+    async fn readiness(&self, max_sync_lag: Option<u64>) -> Readiness {
+        if let Err(err) = self.block_height().await {
+            tracing::warn!("readiness check failed to reach storage: {err:#}");
+            return Readiness {
+                ready: false,
+                reason: Some(NotReadyReason::DbUnreachable),
+                sync_lag: None,
+            };
+        }
+        let sync_lag = match self.sync_lag().await {
+            Ok(lag) => Some(lag),
+            Err(err) => {
+                tracing::warn!("readiness check failed to compute sync lag: {err:#}");
+                None
+            }
+        };
+        let reason = match (max_sync_lag, sync_lag) {
+            (Some(max), Some(lag)) if lag > max => Some(NotReadyReason::SyncLagExceeded),
+            _ => None,
+        };
+        Readiness {
+            ready: reason.is_none(),
+            reason,
+            sync_lag,
+        }
+    }
+}
+
+/// Whether a data source is able to serve requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Available,
+    Unhealthy,
+}
+
+/// The result of a [health check](StatusDataSource::health).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: HealthStatus,
+    /// The time in seconds since the last decide, if known.
+    pub last_decide_age_secs: Option<u64>,
+}
+
+impl HealthCheck for Health {
+    fn status(&self) -> StatusCode {
+        match self.status {
+            HealthStatus::Available => StatusCode::OK,
+            HealthStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// The reason a data source is not ready to serve requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotReadyReason {
+    /// Storage could not be reached.
+    DbUnreachable,
+    /// Storage is too far behind consensus.
+    SyncLagExceeded,
+}
+
+/// The result of a [readiness check](StatusDataSource::readiness).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Why the data source is not ready, if it isn't.
+    pub reason: Option<NotReadyReason>,
+    /// The number of decided blocks not yet stored, if known.
+    pub sync_lag: Option<u64>,
 }
 
 pub trait UpdateStatusData {
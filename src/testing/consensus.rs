@@ -63,6 +63,7 @@ pub struct MockNetwork<D: DataSourceLifeCycle> {
     tasks: Vec<BackgroundTask>,
     nodes: Vec<MockNode<D>>,
     pub_keys: Vec<BLSPubKey>,
+    membership: MockMembership,
 }
 
 // MockNetwork can be used with any DataSourceLifeCycle, but it's nice to have a default with a
@@ -224,6 +225,7 @@ impl<D: DataSourceLifeCycle + UpdateStatusData> MockNetwork<D> {
         let mut network = Self {
             nodes,
             pub_keys,
+            membership: non_da_membership,
             tasks: Default::default(),
         };
         D::setup(&mut network).await;
@@ -248,6 +250,11 @@ impl<D: DataSourceLifeCycle> MockNetwork<D> {
         self.pub_keys[i]
     }
 
+    /// The quorum membership of the network, which can be used to verify QCs.
+    pub fn membership(&self) -> &MockMembership {
+        &self.membership
+    }
+
     pub fn data_source_index(&self, i: usize) -> D {
         self.nodes[i].data_source.clone()
     }
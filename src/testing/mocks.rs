@@ -13,7 +13,10 @@
 use crate::explorer::traits::{ExplorerHeader, ExplorerTransaction};
 use crate::merklized_state::MerklizedState;
 use crate::{
-    availability::{QueryableHeader, QueryablePayload},
+    availability::{
+        NamespacedPayload, QueryableHeader, QueryablePayload, VerifiableNamespacedPayload,
+        VerifiablePayload,
+    },
     types::HeightIndexed,
 };
 use hotshot::traits::{
@@ -119,6 +122,42 @@ impl<Types: NodeType> QueryablePayload<Types> for MockPayload {
     }
 }
 
+impl<Types: NodeType> NamespacedPayload<Types> for MockPayload {
+    type Namespace = u64;
+
+    fn transaction_namespace(
+        &self,
+        _meta: &Self::Metadata,
+        index: &Self::TransactionIndex,
+    ) -> Option<Self::Namespace> {
+        Some(self.transactions.get(*index)?.namespace_id())
+    }
+}
+
+impl<Types: NodeType> VerifiablePayload<Types> for MockPayload {
+    fn verify_inclusion(
+        _header: &Types::BlockHeader,
+        _transaction: &Self::Transaction,
+        _proof: &Self::InclusionProof,
+    ) -> bool {
+        // The mock payload uses a trivial inclusion proof, as for a trusted query service, so there
+        // is nothing to check.
+        true
+    }
+}
+
+impl<Types: NodeType> VerifiableNamespacedPayload<Types> for MockPayload {
+    fn verify_namespace_inclusion(
+        header: &Types::BlockHeader,
+        namespace: &Self::Namespace,
+        transaction: &Self::Transaction,
+        proof: &Self::InclusionProof,
+    ) -> bool {
+        transaction.namespace_id() == *namespace
+            && <Self as VerifiablePayload<Types>>::verify_inclusion(header, transaction, proof)
+    }
+}
+
 #[derive(
     Copy, Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
 )]
@@ -206,3 +245,38 @@ impl MerklizedState<MockTypes, 8> for MockMerkleTree {
         Ok(())
     }
 }
+
+/// A second mock tree, with a different arity, for testing multiple trees in the same database.
+pub type MockMerkleTree4 = UniversalMerkleTree<usize, Sha3Digest, usize, 4, Sha3Node>;
+
+impl MerklizedState<MockTypes, 4> for MockMerkleTree4 {
+    type Key = usize;
+    type Entry = usize;
+    type T = Sha3Node;
+    type Commit = Self::Commitment;
+    type Digest = Sha3Digest;
+
+    fn state_type() -> &'static str {
+        "test_tree_4"
+    }
+
+    fn header_state_commitment_field() -> &'static str {
+        "test_merkle_tree_4_root"
+    }
+
+    fn tree_height() -> usize {
+        12
+    }
+
+    fn insert_path(
+        &mut self,
+        key: Self::Key,
+        proof: &MerkleProof<Self::Entry, Self::Key, Self::T, 4>,
+    ) -> anyhow::Result<()> {
+        match proof.elem() {
+            Some(elem) => self.remember(key, elem, proof)?,
+            None => self.non_membership_remember(key, proof)?,
+        }
+        Ok(())
+    }
+}